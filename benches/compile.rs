@@ -0,0 +1,219 @@
+//! Compile-time benchmarks.
+//!
+//! Measure the time spent in the main stages of the code generator on a fixed corpus: the
+//! functions in `benches/corpus`, and the WebAssembly modules in `wasmtests`. Run them with `cargo
+//! bench`. Criterion keeps the results of the previous run in `target/criterion`, and reports the
+//! changes against them.
+//!
+//! Each stage is measured in isolation. The functions are brought to the state the stage expects
+//! before the measurement starts, so a regression in one pass doesn't show up in the numbers of the
+//! passes after it. The verifier is disabled, as it would otherwise dominate the timings.
+//!
+//! The `.wat` modules are converted to binary with `wat2wasm`. The WebAssembly benchmarks are
+//! skipped when it isn't installed.
+
+#[macro_use]
+extern crate criterion;
+extern crate cretonne;
+extern crate cton_reader;
+extern crate cton_wasm;
+extern crate tempdir;
+
+use cretonne::Context;
+use cretonne::ir::Function;
+use cretonne::isa::{self, TargetIsa};
+use cretonne::settings::{self, Configurable};
+use criterion::{Bencher, Criterion};
+use cton_reader::parse_functions;
+use cton_wasm::{translate_module, DummyEnvironment};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempdir::TempDir;
+
+/// The settings shared by all the benchmarks.
+fn flags() -> settings::Flags {
+    let mut flag_builder = settings::builder();
+    flag_builder.enable("is_64bit").unwrap();
+    flag_builder.set("enable_verifier", "false").unwrap();
+    settings::Flags::new(&flag_builder)
+}
+
+/// The target of all the benchmarks.
+fn isa() -> Box<TargetIsa> {
+    let isa_builder = isa::lookup("intel").expect("the intel ISA is required for benchmarking");
+    isa_builder.finish(flags())
+}
+
+/// Get the files in `dir` with the extension `ext`, sorted by name.
+fn files(dir: &str, ext: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some(OsStr::new(ext)))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Get the name of a benchmark input from its file name.
+fn name(path: &Path) -> String {
+    path.file_stem().unwrap().to_string_lossy().into_owned()
+}
+
+fn read(path: &Path) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    fs::File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut buffer))
+        .unwrap();
+    buffer
+}
+
+/// Convert the `.wat` file at `path` to binary, or return `None` if `wat2wasm` isn't installed.
+fn wat2wasm(path: &Path, dir: &Path) -> Option<Vec<u8>> {
+    let out = dir.join("module.wasm");
+    match Command::new("wat2wasm").arg(path).arg("-o").arg(&out).status() {
+        Ok(status) => {
+            assert!(status.success(), "wat2wasm failed on {}", path.display());
+            Some(read(&out))
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => panic!("running wat2wasm: {}", e),
+    }
+}
+
+/// The functions of the corpus, grouped by the file they come from.
+///
+/// The functions of the WebAssembly modules are included after translation.
+fn corpus(modules: &[(String, Vec<u8>)]) -> Vec<(String, Vec<Function>)> {
+    let mut corpus = Vec::new();
+    for path in files("benches/corpus", "cton") {
+        let text = String::from_utf8(read(&path)).unwrap();
+        let funcs = parse_functions(&text).unwrap_or_else(|e| {
+            panic!("{}: {}", path.display(), e)
+        });
+        corpus.push((name(&path), funcs));
+    }
+    for module in modules {
+        let mut environ = DummyEnvironment::with_flags(flags());
+        translate_module(&module.1, &mut environ).unwrap();
+        corpus.push((format!("wasm-{}", module.0), environ.info.function_bodies));
+    }
+    corpus
+}
+
+/// Benchmark one stage on a group of functions.
+///
+/// The `setup` function brings a function to the state the stage expects, and isn't measured.
+fn stage<S, R>(c: &mut Criterion, stage: &str, group: &(String, Vec<Function>), setup: S, run: R)
+where
+    S: Fn(&mut Context, &TargetIsa) + 'static,
+    R: Fn(&mut Context, &TargetIsa) + 'static,
+{
+    let funcs = group.1.clone();
+    let isa = isa();
+    c.bench_function(&format!("{}/{}", stage, group.0), move |b: &mut Bencher| {
+        b.iter_with_setup(
+            || {
+                let mut contexts: Vec<Context> =
+                    funcs.iter().cloned().map(Context::for_function).collect();
+                for ctx in &mut contexts {
+                    setup(ctx, &*isa);
+                }
+                contexts
+            },
+            |mut contexts| {
+                for ctx in &mut contexts {
+                    run(ctx, &*isa);
+                }
+                contexts
+            },
+        )
+    });
+}
+
+fn translation(c: &mut Criterion, modules: &[(String, Vec<u8>)]) {
+    for module in modules {
+        let data = module.1.clone();
+        c.bench_function(&format!("translate/{}", module.0), move |b: &mut Bencher| {
+            b.iter(|| {
+                let mut environ = DummyEnvironment::with_flags(flags());
+                translate_module(&data, &mut environ).unwrap();
+                environ
+            })
+        });
+    }
+}
+
+fn legalization(c: &mut Criterion, group: &(String, Vec<Function>)) {
+    stage(
+        c,
+        "legalize",
+        group,
+        |ctx, _| ctx.compute_cfg(),
+        |ctx, isa| ctx.legalize(isa).unwrap(),
+    );
+}
+
+fn register_allocation(c: &mut Criterion, group: &(String, Vec<Function>)) {
+    stage(
+        c,
+        "regalloc",
+        group,
+        |ctx, isa| {
+            ctx.compute_cfg();
+            ctx.legalize(isa).unwrap();
+            ctx.flowgraph();
+            ctx.eliminate_unreachable_code(isa).unwrap();
+        },
+        |ctx, isa| ctx.regalloc(isa).unwrap(),
+    );
+}
+
+fn emission(c: &mut Criterion, group: &(String, Vec<Function>)) {
+    stage(
+        c,
+        "emit",
+        group,
+        |ctx, isa| {
+            ctx.compute_cfg();
+            ctx.legalize(isa).unwrap();
+            ctx.flowgraph();
+            ctx.eliminate_unreachable_code(isa).unwrap();
+            ctx.regalloc(isa).unwrap();
+            ctx.prologue_epilogue(isa).unwrap();
+        },
+        |ctx, isa| {
+            let code_size = ctx.relax_branches(isa).unwrap();
+            let mut code = vec![0; code_size as usize];
+            ctx.emit_to_slice(&mut code, isa).unwrap();
+        },
+    );
+}
+
+fn compile_benches(c: &mut Criterion) {
+    let tmp_dir = TempDir::new("cretonne-bench").unwrap();
+    let mut modules = Vec::new();
+    for path in files("wasmtests", "wat") {
+        match wat2wasm(&path, tmp_dir.path()) {
+            Some(data) => modules.push((name(&path), data)),
+            None => {
+                println!("wat2wasm not found; skipping the WebAssembly benchmarks");
+                break;
+            }
+        }
+    }
+
+    translation(c, &modules);
+    for group in &corpus(&modules) {
+        legalization(c, group);
+        register_allocation(c, group);
+        emission(c, group);
+    }
+}
+
+criterion_group!(benches, compile_benches);
+criterion_main!(benches);
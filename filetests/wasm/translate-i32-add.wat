@@ -0,0 +1,11 @@
+;; Test the translation of WebAssembly to Cretonne IL.
+;; test wasm
+;; isa intel
+
+(module
+  (func $add (param i32 i32) (result i32)
+    (i32.add (get_local 0) (get_local 1))))
+
+;; check: function u0:0(i32, i32
+;; check: iadd
+;; check: return
@@ -0,0 +1,29 @@
+;; Floating point arithmetic, NaNs, and conversions.
+(module
+  (func (export "f32.add") (param f32 f32) (result f32) (f32.add (get_local 0) (get_local 1)))
+  (func (export "f32.min") (param f32 f32) (result f32) (f32.min (get_local 0) (get_local 1)))
+  (func (export "f32.copysign") (param f32 f32) (result f32)
+    (f32.copysign (get_local 0) (get_local 1)))
+  (func (export "f64.div") (param f64 f64) (result f64) (f64.div (get_local 0) (get_local 1)))
+  (func (export "f64.sqrt") (param f64) (result f64) (f64.sqrt (get_local 0)))
+  (func (export "f64.nearest") (param f64) (result f64) (f64.nearest (get_local 0)))
+  (func (export "i32.trunc_s/f32") (param f32) (result i32) (i32.trunc_s/f32 (get_local 0)))
+)
+
+(assert_return (invoke "f32.add" (f32.const 0x1p-149) (f32.const 0x1p-149)) (f32.const 0x1p-148))
+(assert_return_canonical_nan (invoke "f32.add" (f32.const inf) (f32.const -inf)))
+(assert_return_arithmetic_nan (invoke "f32.add" (f32.const nan:0x200000) (f32.const 1)))
+(assert_return (invoke "f32.min" (f32.const -0) (f32.const 0)) (f32.const -0))
+(assert_return (invoke "f32.min" (f32.const 0) (f32.const -0)) (f32.const -0))
+(assert_return_canonical_nan (invoke "f32.min" (f32.const nan) (f32.const 1)))
+(assert_return (invoke "f32.copysign" (f32.const 1) (f32.const -nan)) (f32.const -1))
+
+(assert_return (invoke "f64.div" (f64.const 1) (f64.const 3)) (f64.const 0x1.5555555555555p-2))
+(assert_return (invoke "f64.div" (f64.const -1) (f64.const 0)) (f64.const -inf))
+(assert_return (invoke "f64.sqrt" (f64.const 2)) (f64.const 0x1.6a09e667f3bcdp+0))
+(assert_return (invoke "f64.nearest" (f64.const 2.5)) (f64.const 2))
+(assert_return (invoke "f64.nearest" (f64.const -3.5)) (f64.const -4))
+
+(assert_return (invoke "i32.trunc_s/f32" (f32.const -0x1p31)) (i32.const 0x80000000))
+(assert_trap (invoke "i32.trunc_s/f32" (f32.const 0x1p31)) "integer overflow")
+(assert_trap (invoke "i32.trunc_s/f32" (f32.const nan)) "invalid conversion to integer")
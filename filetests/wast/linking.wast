@@ -0,0 +1,48 @@
+;; Tables, indirect calls, and imports between modules.
+(module $M
+  (type $ii (func (param i32) (result i32)))
+  (global (export "g") i32 (i32.const 42))
+  (table (export "tab") 4 anyfunc)
+  (elem (i32.const 0) $inc $dec $zero)
+  (func $inc (export "inc") (type $ii) (i32.add (get_local 0) (i32.const 1)))
+  (func $dec (type $ii) (i32.sub (get_local 0) (i32.const 1)))
+  (func $zero (result i32) (i32.const 0))
+  (func (export "call") (param i32 i32) (result i32)
+    (call_indirect (type $ii) (get_local 1) (get_local 0)))
+)
+
+(assert_return (invoke "call" (i32.const 0) (i32.const 5)) (i32.const 6))
+(assert_return (invoke "call" (i32.const 1) (i32.const 5)) (i32.const 4))
+(assert_trap (invoke "call" (i32.const 2) (i32.const 5)) "indirect call type mismatch")
+(assert_trap (invoke "call" (i32.const 3) (i32.const 5)) "uninitialized element")
+(assert_trap (invoke "call" (i32.const 4) (i32.const 5)) "undefined element")
+(register "M" $M)
+
+(module
+  (import "M" "inc" (func $inc (param i32) (result i32)))
+  (import "M" "g" (global $g i32))
+  (import "M" "tab" (table 4 anyfunc))
+  (import "spectest" "print_i32" (func $print (param i32)))
+  (elem (i32.const 3) $twice)
+  (func $twice (param i32) (result i32) (i32.mul (get_local 0) (i32.const 2)))
+  (func (export "inc_g") (result i32)
+    (call $print (get_global $g))
+    (call $inc (get_global $g)))
+)
+
+(assert_return (invoke "inc_g") (i32.const 43))
+(assert_return (invoke $M "call" (i32.const 3) (i32.const 5)) (i32.const 10))
+(assert_return (get $M "g") (i32.const 42))
+
+(assert_unlinkable (module (import "M" "missing" (func))) "unknown import")
+(assert_unlinkable (module (import "M" "inc" (func (param i64)))) "incompatible import type")
+(assert_unlinkable (module (import "M" "tab" (table 5 anyfunc))) "incompatible import type")
+
+(module binary "\00asm" "\01\00\00\00")
+
+(module
+  (func $loop (call $loop))
+  (func (export "runaway") (call $loop))
+)
+(assert_exhaustion (invoke "runaway") "call stack exhausted")
+(assert_trap (module (func $start unreachable) (start $start)) "unreachable")
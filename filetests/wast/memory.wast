@@ -0,0 +1,40 @@
+;; Linear memory accesses, bounds checks, and growing.
+(module
+  (memory 1 3)
+  (data (i32.const 0) "abcdefgh")
+  (data (i32.const 0xfff8) "\01\02\03\04\05\06\07\08")
+  (func (export "load8_u") (param i32) (result i32) (i32.load8_u (get_local 0)))
+  (func (export "load") (param i32) (result i64) (i64.load (get_local 0)))
+  (func (export "load_offset") (param i32) (result i32)
+    (i32.load offset=0x10000 (get_local 0)))
+  (func (export "store") (param i32 i32) (i32.store (get_local 0) (get_local 1)))
+  (func (export "size") (result i32) (current_memory))
+  (func (export "grow") (param i32) (result i32) (grow_memory (get_local 0)))
+)
+
+(assert_return (invoke "load8_u" (i32.const 0)) (i32.const 97))
+(assert_return (invoke "load8_u" (i32.const 7)) (i32.const 104))
+(assert_return (invoke "load" (i32.const 0xfff8)) (i64.const 0x0807060504030201))
+(assert_trap (invoke "load" (i32.const 0xfff9)) "out of bounds memory access")
+(assert_trap (invoke "load8_u" (i32.const 0x10000)) "out of bounds memory access")
+(assert_trap (invoke "load8_u" (i32.const -1)) "out of bounds memory access")
+(assert_trap (invoke "load_offset" (i32.const 0)) "out of bounds memory access")
+
+(invoke "store" (i32.const 8) (i32.const 0x64636261))
+(assert_return (invoke "load8_u" (i32.const 9)) (i32.const 98))
+
+(assert_return (invoke "size") (i32.const 1))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "size") (i32.const 2))
+(assert_return (invoke "load8_u" (i32.const 0x10000)) (i32.const 0))
+(assert_return (invoke "load_offset" (i32.const 0)) (i32.const 0))
+(assert_return (invoke "load" (i32.const 0xfff8)) (i64.const 0x0807060504030201))
+(assert_return (invoke "grow" (i32.const 2)) (i32.const -1))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 2))
+(assert_return (invoke "grow" (i32.const 0)) (i32.const 3))
+(assert_trap (invoke "load8_u" (i32.const 0x30000)) "out of bounds memory access")
+
+(assert_unlinkable
+  (module (memory 1) (data (i32.const 0x10000) "a"))
+  "data segment does not fit"
+)
@@ -0,0 +1,20 @@
+//! Fuzz the `.cton` test file parser.
+//!
+//! The parser must report malformed input as an error. Seed the corpus with the `filetests`
+//! directory to get interesting inputs quickly.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cton_reader;
+
+use std::str;
+
+fuzz_target!(|data: &[u8]| {
+    // The parser reads text, so other bytes can't reach it.
+    if let Ok(text) = str::from_utf8(data) {
+        let _ = cton_reader::parse_test(text);
+        // `cton-util fmt` keeps parsing after errors, which takes different paths.
+        let _ = cton_reader::parse_test_with_recovery(text);
+    }
+});
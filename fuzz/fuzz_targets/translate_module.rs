@@ -0,0 +1,21 @@
+//! Fuzz the WebAssembly translator.
+//!
+//! The translator expects a valid module, so the input is validated first, the same way an
+//! embedder would. A valid module may still use features the translator doesn't support, which it
+//! reports as an error, but it must not panic.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cton_wasm;
+extern crate wasmparser;
+
+use cton_wasm::{translate_module, DummyEnvironment};
+
+fuzz_target!(|data: &[u8]| {
+    if !wasmparser::validate(data) {
+        return;
+    }
+    let mut environ = DummyEnvironment::default();
+    let _ = translate_module(data, &mut environ);
+});
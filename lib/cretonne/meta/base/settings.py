@@ -27,6 +27,21 @@ enable_verifier = BoolSetting(
         """,
         default=True)
 
+verifier_level = EnumSetting(
+        """
+        How thoroughly the enabled verifier checks the code:
+
+        - full: Run all the checks, including the expensive ones that compute
+          the dominator tree and check dominance, liveness, CPU flags, value
+          locations, and encodings.
+        - basic: Only run the cheap structural checks of the function: EBB
+          and instruction integrity, entity references, and type checking.
+
+        The basic checks still catch many bugs in embedders and front ends
+        without the cost of the exhaustive passes on every function.
+        """,
+        'full', 'basic')
+
 is_64bit = BoolSetting("Enable 64-bit code generation")
 
 is_pic = BoolSetting("Enable Position-Independent Code generation")
@@ -324,6 +324,13 @@ def gen_opcodes(groups, fmt):
                     i.format.name, i.name)
     fmt.line()
 
+    # Generate a private table of all opcodes in numerical order.
+    with fmt.indented(
+            'const OPCODES: [Opcode; {}] = ['.format(len(instrs)), '];'):
+        for i in instrs:
+            fmt.format('Opcode::{},', i.camel_name)
+    fmt.line()
+
     # Generate a private opcode_name function.
     with fmt.indented('fn opcode_name(opc: Opcode) -> &\'static str {', '}'):
         m = srcgen.Match('opc')
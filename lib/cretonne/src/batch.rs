@@ -0,0 +1,139 @@
+//! Parallel compilation of many functions.
+//!
+//! Compiling a function only depends on the function itself and the target ISA, so a batch of
+//! functions can be compiled in parallel. A `BatchCompiler` distributes the functions over a
+//! number of worker threads, each with its own `Context`, and returns the results in the same
+//! order as the input, regardless of which worker compiled which function.
+
+use binemit::CompiledFunction;
+use context::Context;
+use ir::Function;
+use isa::TargetIsa;
+use result::CodegenError;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// The result of compiling one function in a batch.
+pub type BatchResult = Result<CompiledFunction, CodegenError>;
+
+/// Compiles batches of functions on a pool of worker threads.
+pub struct BatchCompiler {
+    isa: Arc<TargetIsa>,
+    num_threads: usize,
+}
+
+impl BatchCompiler {
+    /// Create a batch compiler for `isa` that uses `num_threads` worker threads.
+    ///
+    /// At least one worker thread is always used.
+    pub fn new(isa: Arc<TargetIsa>, num_threads: usize) -> Self {
+        Self {
+            isa,
+            num_threads: if num_threads == 0 { 1 } else { num_threads },
+        }
+    }
+
+    /// Get the target ISA.
+    pub fn isa(&self) -> &TargetIsa {
+        &*self.isa
+    }
+
+    /// Compile `funcs` and emit their machine code.
+    ///
+    /// The result for `funcs[i]` is at index `i` of the returned vector.
+    pub fn compile<I>(&self, funcs: I) -> Vec<BatchResult>
+    where
+        I: IntoIterator<Item = Function>,
+    {
+        let queue: Vec<(usize, Function)> = funcs.into_iter().enumerate().collect();
+        let count = queue.len();
+        let queue = Arc::new(Mutex::new(queue.into_iter()));
+        let (tx, rx) = mpsc::channel();
+
+        let workers: Vec<_> = (0..self.num_threads.min(count))
+            .map(|_| {
+                let queue = queue.clone();
+                let tx = tx.clone();
+                let isa = self.isa.clone();
+                thread::spawn(move || {
+                    let mut ctx = Context::new();
+                    loop {
+                        // Don't hold the lock while compiling.
+                        let next = queue.lock().unwrap().next();
+                        let (index, func) = match next {
+                            Some(work) => work,
+                            None => break,
+                        };
+                        ctx.clear();
+                        ctx.func = func;
+                        let result = ctx.compile(&*isa).map(|code_size| {
+                            CompiledFunction::emit(&ctx, code_size, &*isa)
+                        });
+                        if tx.send((index, result)).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+
+        let mut results: Vec<Option<BatchResult>> = (0..count).map(|_| None).collect();
+        for (index, result) in rx {
+            results[index] = Some(result);
+        }
+        for worker in workers {
+            worker.join().expect("compilation worker panicked");
+        }
+        results
+            .into_iter()
+            .map(|r| r.expect("function was not compiled"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{AbiParam, CallConv, ExternalName, InstBuilder, Signature, types};
+    use isa;
+    use settings;
+
+    fn make(c: i64) -> Function {
+        let mut sig = Signature::new(CallConv::Native);
+        sig.returns.push(AbiParam::new(types::I32));
+        let mut func = Function::with_name_signature(ExternalName::user(0, c as u32), sig);
+        let ebb = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb);
+            let v = pos.ins().iconst(types::I32, c);
+            pos.ins().return_(&[v]);
+        }
+        func
+    }
+
+    #[test]
+    fn ordering() {
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(
+            &settings::builder(),
+        ));
+        let batch = BatchCompiler::new(isa.into(), 4);
+        let consts = [1, 1000, 7, 100_000, 0, 3, 12_345_678, 9];
+
+        let results = batch.compile(consts.iter().map(|&c| make(c)));
+        assert_eq!(results.len(), consts.len());
+        for (&c, result) in consts.iter().zip(results) {
+            let mut ctx = Context::for_function(make(c));
+            let size = ctx.compile(batch.isa()).unwrap();
+            assert_eq!(
+                result.unwrap(),
+                CompiledFunction::emit(&ctx, size, batch.isa())
+            );
+        }
+
+        assert!(batch.compile(Vec::new()).is_empty());
+    }
+}
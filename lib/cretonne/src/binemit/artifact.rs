@@ -0,0 +1,329 @@
+//! Serializable compiled functions.
+//!
+//! A `CompiledFunction` holds the machine code of a function along with the relocations that
+//! must be applied before it can run. It can be serialized to a stable binary format and read back
+//! later, so an embedder can cache native code on disk and link it again without recompiling.
+//!
+//! The serialized form records the ISA name and the complete settings used to compile the code.
+//! Deserializing with a `TargetIsa` that has different settings fails with
+//! `ArtifactError::SettingsMismatch`, so stale code is never linked by accident.
+//!
+//! The code generator doesn't produce trap tables or stack maps yet, so those are not part of
+//! the format. They will be added in a future version.
+
+use binemit::{Addend, CodeOffset, Reloc, RelocSink};
+use bytestream::{ReadError, Reader, Writer};
+use cache::isa_fingerprint;
+use entity::EntityRef;
+use ir::{ExternalName, JumpTable};
+use isa::TargetIsa;
+use std::fmt;
+use Context;
+
+/// Magic bytes at the start of a serialized `CompiledFunction`.
+const MAGIC: &[u8; 8] = b"CTONFUNC";
+
+/// Version of the serialized format. Bump this whenever the format changes.
+pub const ARTIFACT_VERSION: u32 = 1;
+
+/// The target of a relocation in a compiled function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RelocTarget {
+    /// An EBB at the given offset in the same function.
+    Ebb(CodeOffset),
+    /// An external symbol.
+    External(ExternalName),
+    /// A jump table in the same function.
+    JumpTable(JumpTable),
+}
+
+/// A relocation in a compiled function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArtifactReloc {
+    /// Offset of the relocated field from the start of the function.
+    pub offset: CodeOffset,
+    /// The kind of relocation.
+    pub reloc: Reloc,
+    /// What the relocation refers to.
+    pub target: RelocTarget,
+    /// Addend to add to the target address.
+    pub addend: Addend,
+}
+
+/// The machine code and relocations of a compiled function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompiledFunction {
+    /// The machine code.
+    pub code: Vec<u8>,
+    /// Relocations to apply to `code`.
+    pub relocs: Vec<ArtifactReloc>,
+}
+
+/// A `RelocSink` that records all relocations as a list of fixups to apply later.
+pub struct RelocRecorder {
+    /// The recorded relocations in the order they were emitted.
+    pub relocs: Vec<ArtifactReloc>,
+}
+
+impl RelocRecorder {
+    /// Create an empty recorder.
+    pub fn new() -> Self {
+        Self { relocs: Vec::new() }
+    }
+}
+
+impl RelocSink for RelocRecorder {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+        self.relocs.push(ArtifactReloc {
+            offset,
+            reloc,
+            target: RelocTarget::Ebb(ebb_offset),
+            addend: 0,
+        });
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        self.relocs.push(ArtifactReloc {
+            offset,
+            reloc,
+            target: RelocTarget::External(name.clone()),
+            addend,
+        });
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+        self.relocs.push(ArtifactReloc {
+            offset,
+            reloc,
+            target: RelocTarget::JumpTable(jt),
+            addend: 0,
+        });
+    }
+}
+
+/// An error encountered while reading a serialized `CompiledFunction`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ArtifactError {
+    /// The data doesn't start with the expected magic bytes.
+    BadMagic,
+    /// The data was written by an incompatible version of the format.
+    VersionMismatch(u32),
+    /// The code was compiled for a different ISA or with different settings.
+    SettingsMismatch,
+    /// The data ended unexpectedly.
+    Truncated,
+    /// The data contains an invalid value.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for ArtifactError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArtifactError::BadMagic => f.write_str("not a compiled function"),
+            ArtifactError::VersionMismatch(v) => {
+                write!(
+                    f,
+                    "compiled function format version {}, expected {}",
+                    v,
+                    ARTIFACT_VERSION
+                )
+            }
+            ArtifactError::SettingsMismatch => {
+                f.write_str("compiled function was compiled with different ISA settings")
+            }
+            ArtifactError::Truncated => f.write_str("compiled function is truncated"),
+            ArtifactError::Corrupt(what) => write!(f, "compiled function has invalid {}", what),
+        }
+    }
+}
+
+impl From<ReadError> for ArtifactError {
+    fn from(e: ReadError) -> Self {
+        match e {
+            ReadError::Truncated => ArtifactError::Truncated,
+            ReadError::Corrupt(what) => ArtifactError::Corrupt(what),
+        }
+    }
+}
+
+impl CompiledFunction {
+    /// Emit the function compiled in `ctx` and collect its relocations.
+    ///
+    /// The code size must be the one returned by `Context::compile`.
+    pub fn emit(ctx: &Context, code_size: CodeOffset, isa: &TargetIsa) -> Self {
+        let mut code = vec![0; code_size as usize];
+        let mut recorder = RelocRecorder::new();
+        ctx.emit_to_memory(code.as_mut_ptr(), &mut recorder, isa);
+        Self {
+            code,
+            relocs: recorder.relocs,
+        }
+    }
+
+    /// Serialize this function. `isa` must be the ISA the function was compiled with.
+    pub fn serialize(&self, isa: &TargetIsa) -> Vec<u8> {
+        let mut w = Writer(Vec::with_capacity(self.code.len() + 64));
+        w.0.extend_from_slice(MAGIC);
+        w.u32(ARTIFACT_VERSION);
+        w.bytes(isa_fingerprint(isa).as_bytes());
+        w.bytes(&self.code);
+        w.u32(self.relocs.len() as u32);
+        for r in &self.relocs {
+            w.u32(r.offset);
+            w.u8(reloc_code(r.reloc));
+            w.u64(r.addend as u64);
+            match r.target {
+                RelocTarget::Ebb(offset) => {
+                    w.u8(0);
+                    w.u32(offset);
+                }
+                RelocTarget::External(ref name) => {
+                    w.u8(1);
+                    w.name(name);
+                }
+                RelocTarget::JumpTable(jt) => {
+                    w.u8(2);
+                    w.u32(jt.index() as u32);
+                }
+            }
+        }
+        w.0
+    }
+
+    /// Deserialize a function that will be linked for `isa`.
+    ///
+    /// Fails if the function was serialized for a different ISA or with different settings.
+    pub fn deserialize(data: &[u8], isa: &TargetIsa) -> Result<Self, ArtifactError> {
+        let mut r = Reader(data);
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(ArtifactError::BadMagic);
+        }
+        let version = r.u32()?;
+        if version != ARTIFACT_VERSION {
+            return Err(ArtifactError::VersionMismatch(version));
+        }
+        if r.bytes()? != isa_fingerprint(isa).as_bytes() {
+            return Err(ArtifactError::SettingsMismatch);
+        }
+        let code = r.bytes()?.to_vec();
+        let count = r.u32()?;
+        let mut relocs = Vec::new();
+        for _ in 0..count {
+            let offset = r.u32()?;
+            let reloc = reloc_from_code(r.u8()?)?;
+            let addend = r.u64()? as Addend;
+            let target = match r.u8()? {
+                0 => RelocTarget::Ebb(r.u32()?),
+                1 => RelocTarget::External(r.name()?),
+                2 => RelocTarget::JumpTable(JumpTable::new(r.u32()? as usize)),
+                _ => return Err(ArtifactError::Corrupt("relocation target")),
+            };
+            if offset as usize > code.len() {
+                return Err(ArtifactError::Corrupt("relocation offset"));
+            }
+            relocs.push(ArtifactReloc {
+                offset,
+                reloc,
+                target,
+                addend,
+            });
+        }
+        if !r.is_empty() {
+            return Err(ArtifactError::Corrupt("trailing data"));
+        }
+        Ok(Self { code, relocs })
+    }
+}
+
+/// The serialized encodings of relocation kinds. Never reorder these.
+const RELOCS: [Reloc; 8] = [
+    Reloc::IntelPCRel4,
+    Reloc::IntelAbs4,
+    Reloc::IntelAbs8,
+    Reloc::IntelGOTPCRel4,
+    Reloc::IntelPLTRel4,
+    Reloc::Arm32Call,
+    Reloc::Arm64Call,
+    Reloc::RiscvCall,
+];
+
+fn reloc_code(reloc: Reloc) -> u8 {
+    RELOCS.iter().position(|&r| r == reloc).expect(
+        "unknown relocation",
+    ) as u8
+}
+
+fn reloc_from_code(code: u8) -> Result<Reloc, ArtifactError> {
+    RELOCS.get(code as usize).cloned().ok_or(
+        ArtifactError::Corrupt("relocation kind"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::LibCall;
+    use isa;
+    use settings::{self, Configurable};
+
+    fn isa(opt_level: &str) -> Box<TargetIsa> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", opt_level).unwrap();
+        isa::lookup("intel").unwrap().finish(
+            settings::Flags::new(&flags),
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let func = CompiledFunction {
+            code: vec![0x90, 0xe8, 0, 0, 0, 0, 0xc3],
+            relocs: vec![
+                ArtifactReloc {
+                    offset: 2,
+                    reloc: Reloc::IntelPCRel4,
+                    target: RelocTarget::External(ExternalName::testcase("foo")),
+                    addend: -4,
+                },
+                ArtifactReloc {
+                    offset: 3,
+                    reloc: Reloc::IntelAbs8,
+                    target: RelocTarget::External(ExternalName::LibCall(LibCall::FloorF64)),
+                    addend: 0,
+                },
+                ArtifactReloc {
+                    offset: 4,
+                    reloc: Reloc::IntelAbs4,
+                    target: RelocTarget::JumpTable(JumpTable::new(1)),
+                    addend: 0,
+                },
+            ],
+        };
+        let default = isa("default");
+        let bytes = func.serialize(&*default);
+        assert_eq!(CompiledFunction::deserialize(&bytes, &*default), Ok(func));
+
+        assert_eq!(
+            CompiledFunction::deserialize(&bytes, &*isa("best")),
+            Err(ArtifactError::SettingsMismatch)
+        );
+        assert_eq!(
+            CompiledFunction::deserialize(&bytes[0..bytes.len() - 1], &*default),
+            Err(ArtifactError::Truncated)
+        );
+        assert_eq!(
+            CompiledFunction::deserialize(b"CTONFUNC\x02\0\0\0", &*default),
+            Err(ArtifactError::VersionMismatch(2))
+        );
+        assert_eq!(
+            CompiledFunction::deserialize(b"not a function", &*default),
+            Err(ArtifactError::BadMagic)
+        );
+    }
+}
@@ -21,9 +21,12 @@ use std::ptr::write_unaligned;
 /// A `CodeSink` that writes binary machine code directly into memory.
 ///
 /// A `MemoryCodeSink` object should be used when emitting a Cretonne IL function into executable
-/// memory. It writes machine code directly to a raw pointer without any bounds checking, so make
-/// sure to allocate enough memory for the whole function. The number of bytes required is returned
-/// by the `Context::compile()` function.
+/// memory. A sink created with `new()` writes machine code directly to a raw pointer without any
+/// bounds checking, so make sure to allocate enough memory for the whole function. The number of
+/// bytes required is returned by the `Context::compile()` function.
+///
+/// A sink created with `with_slice()` never writes past the end of its slice. Machine code that
+/// doesn't fit is dropped, and `overflowed()` reports it after emission.
 ///
 /// Any relocations in the function are forwarded to the `RelocSink` trait object.
 ///
@@ -32,6 +35,7 @@ use std::ptr::write_unaligned;
 pub struct MemoryCodeSink<'a> {
     data: *mut u8,
     offset: isize,
+    limit: isize,
     relocs: &'a mut RelocSink,
 }
 
@@ -41,9 +45,31 @@ impl<'a> MemoryCodeSink<'a> {
         MemoryCodeSink {
             data,
             offset: 0,
+            limit: isize::max_value(),
             relocs,
         }
     }
+
+    /// Create a new memory code sink that writes a function to `mem` with bounds checking.
+    pub fn with_slice(mem: &'a mut [u8], relocs: &'a mut RelocSink) -> MemoryCodeSink<'a> {
+        MemoryCodeSink {
+            data: mem.as_mut_ptr(),
+            offset: 0,
+            limit: mem.len() as isize,
+            relocs,
+        }
+    }
+
+    /// Did the emitted code exceed the end of the memory given to `with_slice()`?
+    pub fn overflowed(&self) -> bool {
+        self.offset > self.limit
+    }
+
+    /// Can `n` more bytes be written?
+    #[inline]
+    fn fits(&self, n: isize) -> bool {
+        self.offset + n <= self.limit
+    }
 }
 
 /// A trait for receiving relocations for code that is emitted directly into memory.
@@ -64,29 +90,37 @@ impl<'a> CodeSink for MemoryCodeSink<'a> {
     }
 
     fn put1(&mut self, x: u8) {
-        unsafe {
-            write_unaligned(self.data.offset(self.offset), x);
+        if self.fits(1) {
+            unsafe {
+                write_unaligned(self.data.offset(self.offset), x);
+            }
         }
         self.offset += 1;
     }
 
     fn put2(&mut self, x: u16) {
-        unsafe {
-            write_unaligned(self.data.offset(self.offset) as *mut u16, x);
+        if self.fits(2) {
+            unsafe {
+                write_unaligned(self.data.offset(self.offset) as *mut u16, x);
+            }
         }
         self.offset += 2;
     }
 
     fn put4(&mut self, x: u32) {
-        unsafe {
-            write_unaligned(self.data.offset(self.offset) as *mut u32, x);
+        if self.fits(4) {
+            unsafe {
+                write_unaligned(self.data.offset(self.offset) as *mut u32, x);
+            }
         }
         self.offset += 4;
     }
 
     fn put8(&mut self, x: u64) {
-        unsafe {
-            write_unaligned(self.data.offset(self.offset) as *mut u64, x);
+        if self.fits(8) {
+            unsafe {
+                write_unaligned(self.data.offset(self.offset) as *mut u64, x);
+            }
         }
         self.offset += 8;
     }
@@ -106,3 +140,53 @@ impl<'a> CodeSink for MemoryCodeSink<'a> {
         self.relocs.reloc_jt(ofs, rel, jt);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use Context;
+    use binemit::CompiledFunction;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{AbiParam, ExternalName, ExtFuncData, InstBuilder, types};
+    use isa;
+    use result::CodegenError;
+    use settings;
+
+    #[test]
+    fn emit_to_slice() {
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(
+            &settings::builder(),
+        ));
+        let mut ctx = Context::new();
+        ctx.func.signature.returns.push(AbiParam::new(types::I32));
+        let sig = ctx.func.signature.clone();
+        let sigref = ctx.func.import_signature(sig);
+        let fref = ctx.func.import_function(ExtFuncData {
+            name: ExternalName::testcase("callee"),
+            signature: sigref,
+        });
+        let ebb = ctx.func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut ctx.func);
+            pos.insert_ebb(ebb);
+            let call = pos.ins().call(fref, &[]);
+            let v = pos.func.dfg.first_result(call);
+            pos.ins().return_(&[v]);
+        }
+        let size = ctx.compile(&*isa).unwrap() as usize;
+        let expected = CompiledFunction::emit(&ctx, size as u32, &*isa);
+
+        let mut mem = vec![0xaa; size + 4];
+        let relocs = ctx.emit_to_slice(&mut mem[0..size], &*isa).unwrap();
+        assert_eq!(&mem[0..size], &expected.code[..]);
+        assert_eq!(relocs, expected.relocs);
+        assert_eq!(relocs.len(), 1);
+
+        // Emitting into a slice that is too small fails without writing past its end.
+        let mut mem = vec![0xaa; size + 4];
+        assert_eq!(
+            ctx.emit_to_slice(&mut mem[0..size - 1], &*isa),
+            Err(CodegenError::CodeTooLarge)
+        );
+        assert!(mem[size - 1..].iter().all(|&b| b == 0xaa));
+    }
+}
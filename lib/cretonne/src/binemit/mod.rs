@@ -3,10 +3,13 @@
 //! The `binemit` module contains code for translating Cretonne's intermediate representation into
 //! binary machine code.
 
+mod artifact;
 mod relaxation;
 mod memorysink;
 
 pub use regalloc::RegDiversions;
+pub use self::artifact::{ArtifactError, ArtifactReloc, CompiledFunction, RelocRecorder,
+                         RelocTarget, ARTIFACT_VERSION};
 pub use self::relaxation::relax_branches;
 pub use self::memorysink::{MemoryCodeSink, RelocSink};
 
@@ -23,7 +26,7 @@ pub type CodeOffset = u32;
 pub type Addend = i64;
 
 /// Relocation kinds for every ISA
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Reloc {
     /// Intel PC-relative 4-byte
     IntelPCRel4,
@@ -32,12 +32,16 @@ use cursor::{Cursor, FuncCursor};
 use ir::{Function, InstructionData, Opcode};
 use isa::{TargetIsa, EncInfo};
 use iterators::IteratorExtras;
-use result::CtonError;
+use result::{CodegenError, CodegenResult};
+use verifier;
 
 /// Relax branches and compute the final layout of EBB headers in `func`.
 ///
 /// Fill in the `func.offsets` table so the function is ready for binary emission.
-pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> Result<CodeOffset, CtonError> {
+///
+/// Fails with `CodegenError::Unsupported` when a branch is out of range for all of its encodings,
+/// since longer branches can't be synthesized yet.
+pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> Result<CodeOffset, CodegenError> {
     let encinfo = isa.encoding_info();
 
     // Clear all offsets so we can recognize EBBs that haven't been visited yet.
@@ -45,7 +49,7 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> Result<CodeOffset
     func.offsets.resize(func.dfg.num_ebbs());
 
     // Start by inserting fall through instructions.
-    fallthroughs(func);
+    fallthroughs(func)?;
 
     let mut offset = 0;
 
@@ -81,7 +85,7 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> Result<CodeOffset
                             // Relax it unless the destination offset has not been computed yet.
                             if dest_offset != 0 || Some(dest) == cur.func.layout.entry_block() {
                                 offset +=
-                                    relax_branch(&mut cur, offset, dest_offset, &encinfo, isa);
+                                    relax_branch(&mut cur, offset, dest_offset, &encinfo, isa)?;
                                 continue;
                             }
                         }
@@ -98,9 +102,17 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> Result<CodeOffset
 
 /// Convert `jump` instructions to `fallthrough` instructions where possible and verify that any
 /// existing `fallthrough` instructions are correct.
-fn fallthroughs(func: &mut Function) {
+fn fallthroughs(func: &mut Function) -> CodegenResult {
     for (ebb, succ) in func.layout.ebbs().adjacent_pairs() {
-        let term = func.layout.last_inst(ebb).expect("EBB has no terminator.");
+        let term = match func.layout.last_inst(ebb) {
+            Some(term) => term,
+            None => {
+                return Err(CodegenError::Verifier(verifier::Error {
+                    location: ebb.into(),
+                    message: String::from("EBB has no terminator"),
+                }))
+            }
+        };
         if let InstructionData::Jump {
             ref mut opcode,
             destination,
@@ -124,6 +136,7 @@ fn fallthroughs(func: &mut Function) {
             }
         }
     }
+    Ok(())
 }
 
 /// Relax the branch instruction at `pos` so it can cover the range `offset - dest_offset`.
@@ -136,9 +149,9 @@ fn relax_branch(
     dest_offset: CodeOffset,
     encinfo: &EncInfo,
     isa: &TargetIsa,
-) -> CodeOffset {
+) -> Result<CodeOffset, CodegenError> {
     let inst = cur.current_inst().unwrap();
-    dbg!(
+    trace!(
         "Relaxing [{}] {} for {:#x}-{:#x} range",
         encinfo.display(cur.func.encodings[inst]),
         cur.func.dfg.display_inst(inst, isa),
@@ -147,14 +160,26 @@ fn relax_branch(
     );
 
     // Pick the first encoding that can handle the branch range.
-    let dfg = &cur.func.dfg;
-    let ctrl_type = dfg.ctrl_typevar(inst);
-    if let Some(enc) = isa.legal_encodings(dfg, &dfg[inst], ctrl_type).find(
-        |&enc| {
-            let range = encinfo.branch_range(enc).expect("Branch with no range");
+    let mut found = None;
+    {
+        let dfg = &cur.func.dfg;
+        let ctrl_type = dfg.ctrl_typevar(inst);
+        for enc in isa.legal_encodings(dfg, &dfg[inst], ctrl_type) {
+            let range = match encinfo.branch_range(enc) {
+                Some(range) => range,
+                None => {
+                    return Err(CodegenError::Unencodable {
+                        inst,
+                        message: format!(
+                            "branch encoding [{}] has no range for {}",
+                            encinfo.display(enc),
+                            dfg.display_inst(inst, isa)
+                        ),
+                    })
+                }
+            };
             if !range.contains(offset, dest_offset) {
-                dbg!("  trying [{}]: out of range", encinfo.display(enc));
-                false
+                trace!("  trying [{}]: out of range", encinfo.display(enc));
             } else if encinfo.operand_constraints(enc) !=
                        encinfo.operand_constraints(cur.func.encodings[inst])
             {
@@ -163,17 +188,17 @@ fn relax_branch(
                 // which the existing operands don't satisfy. We can't check for
                 // validity directly because we don't have a RegDiversions active so
                 // we don't know which registers are actually in use.
-                dbg!("  trying [{}]: constraints differ", encinfo.display(enc));
-                false
+                trace!("  trying [{}]: constraints differ", encinfo.display(enc));
             } else {
-                dbg!("  trying [{}]: OK", encinfo.display(enc));
-                true
+                trace!("  trying [{}]: OK", encinfo.display(enc));
+                found = Some(enc);
+                break;
             }
-        },
-    )
-    {
+        }
+    }
+    if let Some(enc) = found {
         cur.func.encodings[inst] = enc;
-        return encinfo.bytes(enc);
+        return Ok(encinfo.bytes(enc));
     }
 
     // Note: On some RISC ISAs, conditional branches have shorter range than unconditional
@@ -194,6 +219,59 @@ fn relax_branch(
     // predecessor could contain kill points for some values that are live in this EBB, and
     // diversions are not automatically cancelled when the live range of a value ends.
 
-    // This assumes solution 2. above:
-    panic!("No branch in range for {:#x}-{:#x}", offset, dest_offset);
+    // This assumes solution 2. above, which isn't implemented yet.
+    Err(CodegenError::Unsupported(format!(
+        "no {} branch in range for {:#x}-{:#x}: {}",
+        isa.name(),
+        offset,
+        dest_offset,
+        cur.func.dfg.display_inst(inst, isa)
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use Context;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{AbiParam, CallConv, ExternalName, Function, InstBuilder, Signature, types};
+    use isa;
+    use result::CodegenError;
+    use settings;
+
+    #[test]
+    fn branch_out_of_range() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(
+            &settings::builder(),
+        ));
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.returns.push(AbiParam::new(types::I32));
+        let mut ctx = Context::for_function(
+            Function::with_name_signature(ExternalName::testcase("f"), sig),
+        );
+        {
+            let ebb0 = ctx.func.dfg.make_ebb();
+            let ebb1 = ctx.func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut ctx.func);
+            pos.insert_ebb(ebb0);
+            let arg = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            pos.ins().brz(arg, ebb1, &[]);
+
+            // RISC-V conditional branches only reach 4 KB.
+            let mut v = arg;
+            for _ in 0..2000 {
+                v = pos.ins().iadd_imm(v, 1);
+            }
+            pos.ins().return_(&[v]);
+            pos.insert_ebb(ebb1);
+            pos.ins().return_(&[arg]);
+        }
+
+        match ctx.compile(&*isa) {
+            Err(CodegenError::Unsupported(ref message)) => {
+                assert!(message.starts_with("no riscv branch in range"), "{}", message);
+            }
+            result => panic!("Unexpected {:?}", result),
+        }
+    }
 }
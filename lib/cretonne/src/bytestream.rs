@@ -0,0 +1,172 @@
+//! Little-endian byte streams for the binary formats.
+//!
+//! These are shared by the compiled function format in `binemit` and the IL format in
+//! `serialize`.
+
+use ir::{ExternalName, LibCall};
+use std::str;
+
+/// An error encountered while reading a byte stream.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReadError {
+    /// The stream ended unexpectedly.
+    Truncated,
+    /// The stream contains an invalid value. The string describes what was being read.
+    Corrupt(&'static str),
+}
+
+/// Result type for reading a byte stream.
+pub type ReadResult<T> = Result<T, ReadError>;
+
+/// Little-endian writer.
+pub struct Writer(pub Vec<u8>);
+
+impl Writer {
+    /// Create a writer with an empty buffer.
+    pub fn new() -> Self {
+        Writer(Vec::new())
+    }
+
+    /// Write a byte.
+    pub fn u8(&mut self, x: u8) {
+        self.0.push(x);
+    }
+
+    /// Write a 16-bit integer.
+    pub fn u16(&mut self, x: u16) {
+        self.0.push(x as u8);
+        self.0.push((x >> 8) as u8);
+    }
+
+    /// Write a 32-bit integer.
+    pub fn u32(&mut self, x: u32) {
+        self.u16(x as u16);
+        self.u16((x >> 16) as u16);
+    }
+
+    /// Write a 64-bit integer.
+    pub fn u64(&mut self, x: u64) {
+        self.u32(x as u32);
+        self.u32((x >> 32) as u32);
+    }
+
+    /// Write a length-prefixed byte string.
+    pub fn bytes(&mut self, b: &[u8]) {
+        self.u32(b.len() as u32);
+        self.0.extend_from_slice(b);
+    }
+
+    /// Write an external name.
+    pub fn name(&mut self, name: &ExternalName) {
+        match *name {
+            ExternalName::User { namespace, index } => {
+                self.u8(0);
+                self.u32(namespace);
+                self.u32(index);
+            }
+            ExternalName::TestCase { length, ascii } => {
+                self.u8(1);
+                self.bytes(&ascii[0..length as usize]);
+            }
+            ExternalName::LibCall(lc) => {
+                // Library calls are stored by name so the encoding doesn't depend on the order
+                // of the `LibCall` variants.
+                self.u8(2);
+                self.bytes(lc.to_string().as_bytes());
+            }
+        }
+    }
+}
+
+/// Little-endian reader.
+pub struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Is the whole stream consumed?
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Read `n` raw bytes.
+    pub fn take(&mut self, n: usize) -> ReadResult<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(ReadError::Truncated);
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    /// Read a byte.
+    pub fn u8(&mut self) -> ReadResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Read a 16-bit integer.
+    pub fn u16(&mut self) -> ReadResult<u16> {
+        let b = self.take(2)?;
+        Ok(u16::from(b[0]) | u16::from(b[1]) << 8)
+    }
+
+    /// Read a 32-bit integer.
+    pub fn u32(&mut self) -> ReadResult<u32> {
+        let lo = self.u16()?;
+        let hi = self.u16()?;
+        Ok(u32::from(lo) | u32::from(hi) << 16)
+    }
+
+    /// Read a 64-bit integer.
+    pub fn u64(&mut self) -> ReadResult<u64> {
+        let lo = self.u32()?;
+        let hi = self.u32()?;
+        Ok(u64::from(lo) | u64::from(hi) << 32)
+    }
+
+    /// Read a length-prefixed byte string.
+    pub fn bytes(&mut self) -> ReadResult<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    /// Read an external name.
+    pub fn name(&mut self) -> ReadResult<ExternalName> {
+        Ok(match self.u8()? {
+            0 => ExternalName::user(self.u32()?, self.u32()?),
+            1 => ExternalName::testcase(self.bytes()?),
+            2 => {
+                str::from_utf8(self.bytes()?)
+                    .ok()
+                    .and_then(|s| s.parse::<LibCall>().ok())
+                    .map(ExternalName::LibCall)
+                    .ok_or(ReadError::Corrupt("library call name"))?
+            }
+            _ => return Err(ReadError::Corrupt("external name")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut w = Writer::new();
+        w.u8(1);
+        w.u16(0x1234);
+        w.u32(0xdead_beef);
+        w.u64(0x0123_4567_89ab_cdef);
+        w.name(&ExternalName::testcase("foo"));
+        w.name(&ExternalName::LibCall(LibCall::CeilF32));
+
+        let mut r = Reader(&w.0);
+        assert_eq!(r.u8(), Ok(1));
+        assert_eq!(r.u16(), Ok(0x1234));
+        assert_eq!(r.u32(), Ok(0xdead_beef));
+        assert_eq!(r.u64(), Ok(0x0123_4567_89ab_cdef));
+        assert_eq!(r.name(), Ok(ExternalName::testcase("foo")));
+        assert_eq!(r.name(), Ok(ExternalName::LibCall(LibCall::CeilF32)));
+        assert!(r.is_empty());
+        assert_eq!(r.u8(), Err(ReadError::Truncated));
+    }
+}
@@ -0,0 +1,257 @@
+//! Compilation cache.
+//!
+//! Embedders that compile the same functions over and over, for example in repeated builds of a
+//! project, can install a `CompileCache` in a `Context`. After legalization, `Context::compile`
+//! computes a `CacheKey` from the legal-form IL and the ISA settings and looks it up in the cache.
+//! On a hit, the fully compiled function is restored from the cache, skipping register allocation,
+//! prologue insertion and branch relaxation. On a miss, the compiled function is stored in the
+//! cache once compilation finishes.
+//!
+//! Cached values are opaque byte strings, so a cache can easily be kept on disk. Keys include the
+//! version of Cretonne, so upgrading the compiler invalidates the cache. Corrupt values are
+//! treated as misses.
+
+use binemit::CodeOffset;
+use bytestream::{Reader, Writer};
+use ir::Function;
+use isa::TargetIsa;
+use serialize::{deserialize_function_into, serialize_function};
+use std::collections::HashMap;
+use VERSION;
+
+/// Get a string identifying an ISA and all of its settings, along with the version of the
+/// compiler.
+///
+/// Code compiled by the same version of Cretonne for ISAs with the same fingerprint is
+/// interchangeable.
+pub fn isa_fingerprint(isa: &TargetIsa) -> String {
+    format!("cretonne {}\n{}\n{}", VERSION, isa.name(), isa)
+}
+
+/// The key identifying a compiled function in a `CompileCache`.
+///
+/// The key contains the complete legal-form IL of the function and the fingerprint of the ISA,
+/// so two keys compare equal only if the functions would compile to the same code. The 64-bit
+/// `hash()` is stable across runs and can be used to index a persistent cache.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    data: Vec<u8>,
+    hash: u64,
+}
+
+impl CacheKey {
+    /// Compute the key of `func` compiled for `isa`.
+    pub fn new(func: &Function, isa: &TargetIsa) -> Self {
+        let mut w = Writer::new();
+        w.bytes(isa_fingerprint(isa).as_bytes());
+        w.bytes(&serialize_function(func));
+        let hash = fnv1a(&w.0);
+        Self { data: w.0, hash }
+    }
+
+    /// Get the stable hash of this key.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Get the full contents of this key.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// The 64-bit FNV-1a hash of `data`.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A cache of compiled functions consulted by `Context::compile`.
+pub trait CompileCache {
+    /// Look up the value stored for `key`.
+    fn get(&mut self, key: &CacheKey) -> Option<Vec<u8>>;
+
+    /// Store `value` for `key`.
+    fn insert(&mut self, key: CacheKey, value: Vec<u8>);
+}
+
+/// A `CompileCache` that keeps everything in memory.
+pub struct MemoryCache {
+    map: HashMap<CacheKey, Vec<u8>>,
+}
+
+impl MemoryCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self { map: HashMap::new() }
+    }
+
+    /// Get the number of cached functions.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Is the cache empty?
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl CompileCache for MemoryCache {
+    fn get(&mut self, key: &CacheKey) -> Option<Vec<u8>> {
+        self.map.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: CacheKey, value: Vec<u8>) {
+        self.map.insert(key, value);
+    }
+}
+
+/// Encode a compiled function and its code size as a cache value.
+pub fn encode_value(func: &Function, code_size: CodeOffset) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.u32(code_size);
+    w.bytes(&serialize_function(func));
+    w.0
+}
+
+/// Decode a cache value produced by `encode_value` into `func`, and return the code size.
+///
+/// The compiled function replaces `func`, reusing its memory. `func` must be the function `key` was
+/// computed from: if the value is corrupt, `func` is restored from the key and `None` is
+/// returned.
+pub fn decode_value(key: &CacheKey, value: &[u8], func: &mut Function) -> Option<CodeOffset> {
+    let code_size = decode_value_into(value, func);
+    if code_size.is_none() {
+        let mut r = Reader(&key.data);
+        let restored = r.bytes().and_then(|_| r.bytes()).ok().and_then(|il| {
+            deserialize_function_into(il, func).ok()
+        });
+        debug_assert!(restored.is_some(), "Cache key doesn't contain a function");
+    }
+    code_size
+}
+
+/// Decode a cache value into `func`, which may be left partially decoded if the value is
+/// corrupt.
+fn decode_value_into(value: &[u8], func: &mut Function) -> Option<CodeOffset> {
+    let mut r = Reader(value);
+    let code_size = r.u32().ok()?;
+    deserialize_function_into(r.bytes().ok()?, func).ok()?;
+    if r.is_empty() {
+        Some(code_size)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Context;
+    use binemit::CompiledFunction;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{AbiParam, CallConv, ExternalName, InstBuilder, Signature, types};
+    use isa;
+    use settings;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// A cache that counts hits.
+    struct Counting {
+        inner: MemoryCache,
+        hits: Rc<Cell<usize>>,
+    }
+
+    impl CompileCache for Counting {
+        fn get(&mut self, key: &CacheKey) -> Option<Vec<u8>> {
+            let value = self.inner.get(key);
+            if value.is_some() {
+                self.hits.set(self.hits.get() + 1);
+            }
+            value
+        }
+
+        fn insert(&mut self, key: CacheKey, value: Vec<u8>) {
+            self.inner.insert(key, value)
+        }
+    }
+
+    fn build(func: &mut Function, c: i64) {
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.returns.push(AbiParam::new(types::I32));
+        func.name = ExternalName::testcase("f");
+        func.signature = sig;
+        let ebb = func.dfg.make_ebb();
+        let mut pos = FuncCursor::new(func);
+        pos.insert_ebb(ebb);
+        let arg = pos.func.dfg.append_ebb_param(ebb, types::I32);
+        let sum = pos.ins().iadd_imm(arg, c);
+        pos.ins().return_(&[sum]);
+    }
+
+    #[test]
+    fn compile() {
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(
+            &settings::builder(),
+        ));
+        let hits = Rc::new(Cell::new(0));
+        let mut ctx = Context::new();
+        ctx.set_cache(Box::new(Counting {
+            inner: MemoryCache::new(),
+            hits: hits.clone(),
+        }));
+
+        build(&mut ctx.func, 5);
+        let size = ctx.compile(&*isa).unwrap();
+        let code = CompiledFunction::emit(&ctx, size, &*isa);
+        let compiled = ctx.func.to_string();
+
+        ctx.clear();
+        build(&mut ctx.func, 5);
+        assert_eq!(ctx.compile(&*isa), Ok(size));
+        assert_eq!(ctx.func.to_string(), compiled);
+        assert_eq!(CompiledFunction::emit(&ctx, size, &*isa), code);
+
+        ctx.clear();
+        build(&mut ctx.func, 6);
+        ctx.compile(&*isa).unwrap();
+
+        assert_eq!(hits.get(), 1);
+    }
+
+    #[test]
+    fn keys() {
+        let flags = settings::builder();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&flags));
+        let mut f1 = Function::new();
+        build(&mut f1, 1);
+        let mut f2 = Function::new();
+        build(&mut f2, 1);
+        assert_eq!(CacheKey::new(&f1, &*isa), CacheKey::new(&f2, &*isa));
+        assert_eq!(CacheKey::new(&f1, &*isa).hash(), CacheKey::new(&f2, &*isa).hash());
+
+        let mut flags = settings::builder();
+        settings::Configurable::set(&mut flags, "opt_level", "best").unwrap();
+        let best = isa::lookup("intel").unwrap().finish(settings::Flags::new(&flags));
+        assert!(CacheKey::new(&f1, &*isa) != CacheKey::new(&f1, &*best));
+
+        let key = CacheKey::new(&f1, &*isa);
+        let legalized = f1.to_string();
+        let mut func = Function::new();
+        assert_eq!(decode_value(&key, &encode_value(&f1, 7), &mut func), Some(7));
+        assert_eq!(func.to_string(), legalized);
+
+        // A corrupt value leaves the function as it was, even after decoding another function.
+        let mut f3 = Function::new();
+        build(&mut f3, 3);
+        let mut value = encode_value(&f3, 7);
+        value.push(0);
+        assert_eq!(decode_value(&key, &value, &mut func), None);
+        assert_eq!(func.to_string(), legalized);
+        assert_eq!(decode_value(&key, b"garbage", &mut func), None);
+        assert_eq!(func.to_string(), legalized);
+    }
+}
@@ -1,35 +1,128 @@
 //! The `CFGPrinter` utility.
+//!
+//! The CFG is printed in graphviz dot format, or as plain text. Optionally, the CFG can be
+//! annotated with the dominator tree, the loop nesting, and the number of live values in each EBB,
+//! which helps when looking into register pressure problems.
 
+use std::cmp;
+use std::collections::HashSet;
 use std::fmt::{Result, Write, Display, Formatter};
 
+use dominator_tree::{DominatorTree, DominatorTreePreorder};
+use entity::EntityMap;
 use flowgraph::ControlFlowGraph;
-use ir::Function;
+use ir::{Ebb, Function, ProgramOrder, Value};
 use ir::instructions::BranchInfo;
+use loop_analysis::{Loop, LoopAnalysis};
 
 /// A utility for pretty-printing the CFG of a `Function`.
 pub struct CFGPrinter<'a> {
     func: &'a Function,
     cfg: ControlFlowGraph,
+    domtree: DominatorTree,
+    show_domtree: bool,
+    loops: Option<LoopAnalysis>,
+    live: Option<EntityMap<Ebb, LiveCounts>>,
+}
+
+/// The number of live values in an EBB.
+#[derive(Clone, Copy, Default)]
+struct LiveCounts {
+    /// Values live into the EBB, excluding its parameters.
+    live_in: usize,
+    /// The largest number of values live at once in the EBB.
+    max_live: usize,
 }
 
 /// A utility for pretty-printing the CFG of a `Function`.
 impl<'a> CFGPrinter<'a> {
     /// Create a new CFGPrinter.
     pub fn new(func: &'a Function) -> CFGPrinter<'a> {
+        let cfg = ControlFlowGraph::with_function(func);
+        let domtree = DominatorTree::with_function(func, &cfg);
         CFGPrinter {
             func,
-            cfg: ControlFlowGraph::with_function(func),
+            cfg,
+            domtree,
+            show_domtree: false,
+            loops: None,
+            live: None,
         }
     }
 
+    /// Also print the dominator tree as dashed edges from each EBB's immediate dominator.
+    pub fn show_domtree(&mut self) -> &mut Self {
+        self.show_domtree = true;
+        self
+    }
+
+    /// Also print the loop nesting as nested clusters labelled with the loop headers and depths.
+    pub fn show_loops(&mut self) -> &mut Self {
+        let mut loops = LoopAnalysis::new();
+        loops.compute(self.func, &self.cfg, &self.domtree);
+        self.loops = Some(loops);
+        self
+    }
+
+    /// Also print the number of values live into each EBB, and the largest number of values live
+    /// at once inside it.
+    ///
+    /// The counts are computed from the SSA form, so they don't depend on the target ISA. They
+    /// approximate the register pressure before spilling.
+    pub fn show_live_values(&mut self) -> &mut Self {
+        self.live = Some(live_counts(self.func, &self.domtree));
+        self
+    }
+
     /// Write the CFG for this function to `w`.
     pub fn write(&self, w: &mut Write) -> Result {
         self.header(w)?;
         self.ebb_nodes(w)?;
         self.cfg_connections(w)?;
+        if self.show_domtree {
+            self.domtree_connections(w)?;
+        }
+        if let Some(ref loops) = self.loops {
+            self.loop_clusters(w, loops)?;
+        }
         writeln!(w, "}}")
     }
 
+    /// Write the CFG for this function to `w` as text.
+    ///
+    /// Each EBB is listed with its successors. The dominator tree is written as an indented tree
+    /// of EBBs, and each loop is written with its header, its depth, and all the EBBs in it,
+    /// including the EBBs of nested loops.
+    pub fn write_text(&self, w: &mut Write) -> Result {
+        writeln!(w, "function {}:", self.func.name)?;
+        for ebb in &self.func.layout {
+            write!(w, "    {}", ebb)?;
+            if let Some(ref live) = self.live {
+                write!(
+                    w,
+                    " [live-in: {}, max live: {}]",
+                    live[ebb].live_in,
+                    live[ebb].max_live
+                )?;
+            }
+            let mut succs = self.cfg.succ_iter(ebb).peekable();
+            if succs.peek().is_some() {
+                write!(w, " ->")?;
+                for succ in succs {
+                    write!(w, " {}", succ)?;
+                }
+            }
+            writeln!(w)?;
+        }
+        if self.show_domtree {
+            self.domtree_text(w)?;
+        }
+        if let Some(ref loops) = self.loops {
+            self.loops_text(w, loops)?;
+        }
+        Ok(())
+    }
+
     fn header(&self, w: &mut Write) -> Result {
         writeln!(w, "digraph \"{}\" {{", self.func.name)?;
         if let Some(entry) = self.func.layout.entry_block() {
@@ -41,6 +134,14 @@ impl<'a> CFGPrinter<'a> {
     fn ebb_nodes(&self, w: &mut Write) -> Result {
         for ebb in &self.func.layout {
             write!(w, "    {} [shape=record, label=\"{{{}", ebb, ebb)?;
+            if let Some(ref live) = self.live {
+                write!(
+                    w,
+                    " | live-in: {}, max live: {}",
+                    live[ebb].live_in,
+                    live[ebb].max_live
+                )?;
+            }
             // Add all outgoing branch instructions to the label.
             for inst in self.func.layout.ebb_insts(ebb) {
                 let idata = &self.func.dfg[inst];
@@ -67,6 +168,185 @@ impl<'a> CFGPrinter<'a> {
         }
         Ok(())
     }
+
+    fn domtree_connections(&self, w: &mut Write) -> Result {
+        for ebb in &self.func.layout {
+            if let Some(idom) = self.domtree.idom(ebb) {
+                let parent = self.func.layout.inst_ebb(idom).expect("idom not in layout");
+                writeln!(
+                    w,
+                    "    {} -> {} [style=dashed, color=blue, constraint=false]",
+                    parent,
+                    ebb
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn loop_clusters(&self, w: &mut Write, loops: &LoopAnalysis) -> Result {
+        for lp in loops.loops() {
+            if loops.loop_parent(lp).is_none() {
+                self.loop_cluster(w, loops, lp, 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn loop_cluster(
+        &self,
+        w: &mut Write,
+        loops: &LoopAnalysis,
+        lp: Loop,
+        depth: usize,
+    ) -> Result {
+        let indent = "    ".repeat(depth);
+        writeln!(w, "{}subgraph cluster_{} {{", indent, lp)?;
+        writeln!(
+            w,
+            "{}    label=\"{} ({}, depth {})\";",
+            indent,
+            lp,
+            loops.loop_header(lp),
+            depth
+        )?;
+        for ebb in &self.func.layout {
+            if loops.innermost_loop(ebb) == Some(lp) {
+                writeln!(w, "{}    {};", indent, ebb)?;
+            }
+        }
+        for child in loops.loops() {
+            if loops.loop_parent(child) == Some(lp) {
+                self.loop_cluster(w, loops, child, depth + 1)?;
+            }
+        }
+        writeln!(w, "{}}}", indent)
+    }
+
+    fn domtree_text(&self, w: &mut Write) -> Result {
+        writeln!(w, "dominator tree:")?;
+        let mut preorder = DominatorTreePreorder::new();
+        preorder.compute(&self.domtree, &self.func.layout);
+        // Walk the tree depth first, with the depth of each EBB on the stack.
+        let mut stack = Vec::new();
+        if let Some(entry) = self.func.layout.entry_block() {
+            stack.push((entry, 1));
+        }
+        while let Some((ebb, depth)) = stack.pop() {
+            writeln!(w, "{}{}", "    ".repeat(depth), ebb)?;
+            let mut children: Vec<Ebb> = preorder.children(ebb).collect();
+            children.sort_by(|&a, &b| self.func.layout.cmp(a, b));
+            stack.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+        }
+        let unreachable: Vec<String> = self.func
+            .layout
+            .ebbs()
+            .filter(|&ebb| !self.domtree.is_reachable(ebb))
+            .map(|ebb| ebb.to_string())
+            .collect();
+        if !unreachable.is_empty() {
+            writeln!(w, "    unreachable: {}", unreachable.join(" "))?;
+        }
+        Ok(())
+    }
+
+    fn loops_text(&self, w: &mut Write, loops: &LoopAnalysis) -> Result {
+        if loops.loops().next().is_none() {
+            return writeln!(w, "loops: none");
+        }
+        writeln!(w, "loops:")?;
+        for lp in loops.loops() {
+            if loops.loop_parent(lp).is_none() {
+                self.loop_text(w, loops, lp, 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn loop_text(&self, w: &mut Write, loops: &LoopAnalysis, lp: Loop, depth: usize) -> Result {
+        write!(
+            w,
+            "{}{}: header {}, depth {}, ebbs",
+            "    ".repeat(depth),
+            lp,
+            loops.loop_header(lp),
+            depth
+        )?;
+        for ebb in &self.func.layout {
+            if loops.is_in_loop(ebb, lp) {
+                write!(w, " {}", ebb)?;
+            }
+        }
+        writeln!(w)?;
+        for child in loops.loops() {
+            if loops.loop_parent(child) == Some(lp) {
+                self.loop_text(w, loops, child, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Count the live values in each EBB of `func`.
+fn live_counts(func: &Function, domtree: &DominatorTree) -> EntityMap<Ebb, LiveCounts> {
+    // Iterate the backwards data flow problem to a fixed point. The live-in sets only grow, so
+    // comparing their sizes detects changes.
+    let mut live_ins = EntityMap::<Ebb, HashSet<Value>>::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &ebb in domtree.cfg_postorder() {
+            let (live_in, _) = scan_ebb(func, ebb, &live_ins);
+            if live_in.len() != live_ins[ebb].len() {
+                live_ins[ebb] = live_in;
+                changed = true;
+            }
+        }
+    }
+
+    let mut counts = EntityMap::new();
+    for ebb in &func.layout {
+        let (live_in, max_live) = scan_ebb(func, ebb, &live_ins);
+        counts[ebb] = LiveCounts {
+            live_in: live_in.len(),
+            max_live,
+        };
+    }
+    counts
+}
+
+/// Compute the values live into `ebb` from the live-in sets of its successors, along with the
+/// largest number of values live before an instruction in `ebb`.
+fn scan_ebb(
+    func: &Function,
+    ebb: Ebb,
+    live_ins: &EntityMap<Ebb, HashSet<Value>>,
+) -> (HashSet<Value>, usize) {
+    let mut live = HashSet::new();
+    let mut max_live = 0;
+    for inst in func.layout.ebb_insts(ebb).rev() {
+        // Branches can appear in the middle of an EBB, so successors are live-out at the branch.
+        match func.dfg[inst].analyze_branch(&func.dfg.value_lists) {
+            BranchInfo::SingleDest(dest, _) => live.extend(&live_ins[dest]),
+            BranchInfo::Table(table) => {
+                for (_, dest) in func.jump_tables[table].entries() {
+                    live.extend(&live_ins[dest]);
+                }
+            }
+            BranchInfo::NotABranch => {}
+        }
+        for result in func.dfg.inst_results(inst) {
+            live.remove(result);
+        }
+        for &arg in func.dfg.inst_args(inst) {
+            live.insert(func.dfg.resolve_aliases(arg));
+        }
+        max_live = cmp::max(max_live, live.len());
+    }
+    for param in func.dfg.ebb_params(ebb) {
+        live.remove(param);
+    }
+    (live, max_live)
 }
 
 impl<'a> Display for CFGPrinter<'a> {
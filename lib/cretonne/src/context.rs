@@ -9,22 +9,28 @@
 //! contexts concurrently. Typically, you would have one context per compilation thread and only a
 //! single ISA instance.
 
-use binemit::{CodeOffset, relax_branches, MemoryCodeSink, RelocSink};
+use binemit::{ArtifactReloc, CodeOffset, relax_branches, MemoryCodeSink, RelocRecorder, RelocSink};
+use cache::{self, CacheKey, CompileCache};
 use dominator_tree::DominatorTree;
 use flowgraph::ControlFlowGraph;
 use ir::Function;
 use loop_analysis::LoopAnalysis;
 use isa::TargetIsa;
-use legalize_function;
+use legalizer::{check_encodings, legalize_function};
+use observer::{CompileObserver, Event, FunctionSize, Pass};
 use regalloc;
-use result::{CtonError, CtonResult};
-use settings::{FlagsOrIsa, OptLevel};
+use result::{CodegenError, CodegenResult};
+use settings::{FlagsOrIsa, OptLevel, VerifierLevel};
 use unreachable_code::eliminate_unreachable_code;
 use verifier;
 use simple_gvn::do_simple_gvn;
+use source_map::SourceMap;
+use stats::{Stage, Statistics};
 use licm::do_licm;
 use preopt::do_preopt;
+use std::time::Instant;
 use timing;
+use value_label::{value_labels_ranges, ValueLabelsRanges};
 
 /// Persistent data structures and compilation pipeline.
 pub struct Context {
@@ -42,6 +48,15 @@ pub struct Context {
 
     /// Loop analysis of `func`.
     pub loop_analysis: LoopAnalysis,
+
+    /// Cache of compiled functions consulted by `compile`.
+    cache: Option<Box<CompileCache>>,
+
+    /// IR statistics recorded by `compile`, if enabled.
+    stats: Option<Statistics>,
+
+    /// Receiver of the events of `compile`.
+    observer: Option<Box<CompileObserver>>,
 }
 
 impl Context {
@@ -64,12 +79,117 @@ impl Context {
             domtree: DominatorTree::new(),
             regalloc: regalloc::Context::new(),
             loop_analysis: LoopAnalysis::new(),
+            cache: None,
+            stats: None,
+            observer: None,
+        }
+    }
+
+    /// Install a cache of compiled functions to be consulted by `compile`.
+    ///
+    /// The cache is kept when the context is cleared.
+    pub fn set_cache(&mut self, cache: Box<CompileCache>) {
+        self.cache = Some(cache);
+    }
+
+    /// Remove the installed cache of compiled functions, if any, and return it.
+    pub fn take_cache(&mut self) -> Option<Box<CompileCache>> {
+        self.cache.take()
+    }
+
+    /// Install an observer to receive the events of `compile`.
+    ///
+    /// The observer is kept when the context is cleared.
+    pub fn set_observer(&mut self, observer: Box<CompileObserver>) {
+        self.observer = Some(observer);
+    }
+
+    /// Remove the installed observer, if any, and return it.
+    pub fn take_observer(&mut self) -> Option<Box<CompileObserver>> {
+        self.observer.take()
+    }
+
+    /// Report `event` to the observer, if any.
+    fn notify(&mut self, event: &Event) {
+        if let Some(ref mut observer) = self.observer {
+            observer.event(event);
+        }
+    }
+
+    /// Run `pass`, reporting its start and end to the observer, if any.
+    fn observe<T, F>(&mut self, pass: Pass, run: F) -> Result<T, CodegenError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, CodegenError>,
+    {
+        if self.observer.is_none() {
+            return run(self);
+        }
+        self.notify(&Event::PassStarted(pass));
+        let started = Instant::now();
+        let result = run(self);
+        self.notify(&Event::PassFinished {
+            pass,
+            duration: started.elapsed(),
+        });
+        result
+    }
+
+    /// Start recording IR statistics in `compile`.
+    ///
+    /// The statistics are accumulated over all the functions compiled with this context, and they
+    /// are kept when the context is cleared.
+    pub fn enable_stats(&mut self) {
+        if self.stats.is_none() {
+            self.stats = Some(Statistics::new());
+        }
+    }
+
+    /// Get the IR statistics recorded so far, if enabled.
+    pub fn stats(&self) -> Option<&Statistics> {
+        self.stats.as_ref()
+    }
+
+    /// Stop recording IR statistics, and return the statistics recorded so far.
+    pub fn take_stats(&mut self) -> Option<Statistics> {
+        self.stats.take()
+    }
+
+    /// Add the statistics of the function at `stage`, if enabled.
+    fn record_stats(&mut self, stage: Stage) {
+        if let Some(ref mut stats) = self.stats {
+            stats.record(stage, &self.func);
+        }
+    }
+
+    /// Add the statistics of the compiled function, if enabled.
+    fn record_final_stats(&mut self, code_size: CodeOffset) {
+        if let Some(ref mut stats) = self.stats {
+            stats.record(Stage::Final, &self.func);
+            stats.record_code_size(code_size);
         }
     }
 
     /// Clear all data structures in this context.
+    ///
+    /// The data structures retain their allocated memory, so compiling the next function doesn't
+    /// need to allocate it again. To benefit from this, build the next function directly in
+    /// `self.func` instead of assigning a new `Function`.
     pub fn clear(&mut self) {
         self.func.clear();
+        self.clear_analyses();
+    }
+
+    /// Prepare this context for compiling an existing Function.
+    ///
+    /// This is the reusing counterpart of `for_function`: The previous function is dropped, but the
+    /// other data structures retain their allocated memory.
+    pub fn reset(&mut self, func: Function) {
+        self.func = func;
+        self.clear_analyses();
+    }
+
+    /// Clear everything except the function.
+    fn clear_analyses(&mut self) {
         self.cfg.clear();
         self.domtree.clear();
         self.regalloc.clear();
@@ -82,25 +202,145 @@ impl Context {
     /// represented by `isa`. This does not include the final step of emitting machine code into a
     /// code sink.
     ///
-    /// Returns the size of the function's code.
-    pub fn compile(&mut self, isa: &TargetIsa) -> Result<CodeOffset, CtonError> {
+    /// If a cache is installed with `set_cache`, the legalized function is looked up in the cache,
+    /// and the remaining passes are skipped on a hit.
+    ///
+    /// If an observer is installed with `set_observer`, the start and end of the compilation and of
+    /// each pass are reported to it.
+    ///
+    /// Returns the size of the function's code. A function that can't be compiled for `isa`, for
+    /// example because it uses an instruction the ISA can't encode or needs more registers than
+    /// are available, results in an error rather than a panic. The context can still be `reset`
+    /// for the next function.
+    pub fn compile(&mut self, isa: &TargetIsa) -> Result<CodeOffset, CodegenError> {
         let _tt = timing::compile();
-        self.verify_if(isa)?;
+        if self.observer.is_none() {
+            return self.run_passes(isa);
+        }
+
+        let started = Instant::now();
+        if let Some(ref mut observer) = self.observer {
+            observer.event(&Event::FunctionStarted {
+                name: &self.func.name,
+                size: FunctionSize::of(&self.func),
+            });
+        }
+        let result = self.run_passes(isa);
+        if let Some(ref mut observer) = self.observer {
+            observer.event(&match result {
+                Ok(code_size) => Event::FunctionFinished {
+                    name: &self.func.name,
+                    size: FunctionSize::of(&self.func),
+                    code_size,
+                    duration: started.elapsed(),
+                },
+                Err(ref error) => Event::FunctionFailed {
+                    name: &self.func.name,
+                    error,
+                },
+            });
+        }
+        result
+    }
+
+    /// Run all the passes of `compile`.
+    fn run_passes(&mut self, isa: &TargetIsa) -> Result<CodeOffset, CodegenError> {
+        self.observe(Pass::Verifier, |ctx| ctx.verify_if(isa))?;
+        self.record_stats(Stage::Input);
+
+        self.observe(Pass::Flowgraph, |ctx| {
+            ctx.compute_cfg();
+            Ok(())
+        })?;
+        self.observe(Pass::Preopt, |ctx| ctx.preopt(isa))?;
+        self.observe(Pass::Legalize, |ctx| {
+            ctx.legalize(isa)?;
+            ctx.verify_encodings_if(isa)?;
+            check_encodings(&ctx.func, isa)
+        })?;
+        self.record_stats(Stage::Legalized);
+
+        let key = match self.cache {
+            Some(_) => Some(CacheKey::new(&self.func, isa)),
+            None => None,
+        };
+        if let Some(ref key) = key {
+            if let Some(code_size) = self.load_cached(key, isa)? {
+                return Ok(code_size);
+            }
+        }
 
-        self.compute_cfg();
-        self.preopt(isa)?;
-        self.legalize(isa)?;
         if isa.flags().opt_level() == OptLevel::Best {
-            self.compute_domtree();
-            self.compute_loop_analysis();
-            self.licm(isa)?;
-            self.simple_gvn(isa)?;
+            self.observe(Pass::Domtree, |ctx| {
+                ctx.compute_domtree();
+                Ok(())
+            })?;
+            self.observe(Pass::LoopAnalysis, |ctx| {
+                ctx.compute_loop_analysis();
+                Ok(())
+            })?;
+            self.observe(Pass::Licm, |ctx| ctx.licm(isa))?;
+            self.observe(Pass::SimpleGvn, |ctx| ctx.simple_gvn(isa))?;
+        }
+        self.observe(Pass::Domtree, |ctx| {
+            ctx.compute_domtree();
+            Ok(())
+        })?;
+        self.observe(Pass::UnreachableCode, |ctx| ctx.eliminate_unreachable_code(isa))?;
+        self.record_stats(Stage::Optimized);
+        self.observe(Pass::Regalloc, |ctx| ctx.regalloc(isa))?;
+        self.record_stats(Stage::Allocated);
+        self.observe(Pass::PrologueEpilogue, |ctx| ctx.prologue_epilogue(isa))?;
+        let code_size = self.observe(Pass::RelaxBranches, |ctx| ctx.relax_branches(isa))?;
+        self.record_final_stats(code_size);
+
+        if let Some(key) = key {
+            let value = cache::encode_value(&self.func, code_size);
+            if let Some(ref mut cache) = self.cache {
+                cache.insert(key, value);
+            }
+        }
+        Ok(code_size)
+    }
+
+    /// Replace the function with its compiled form from the cache, if `key` is present.
+    ///
+    /// Returns the code size on a hit.
+    fn load_cached(
+        &mut self,
+        key: &CacheKey,
+        isa: &TargetIsa,
+    ) -> Result<Option<CodeOffset>, CodegenError> {
+        let value = match self.cache {
+            Some(ref mut cache) => cache.get(key),
+            None => None,
+        };
+        match value.and_then(|v| cache::decode_value(key, &v, &mut self.func)) {
+            Some(code_size) => {
+                self.notify(&Event::CacheHit);
+                self.loop_analysis.clear();
+                self.flowgraph();
+                self.verify_if(isa)?;
+                self.verify_locations_if(isa)?;
+                self.record_final_stats(code_size);
+                Ok(Some(code_size))
+            }
+            None => Ok(None),
         }
-        self.compute_domtree();
-        self.eliminate_unreachable_code(isa)?;
-        self.regalloc(isa)?;
-        self.prologue_epilogue(isa)?;
-        self.relax_branches(isa)
+    }
+
+    /// Compute the code ranges where the labelled values of the compiled function are available.
+    ///
+    /// See the `value_label` module.
+    pub fn value_labels_ranges(&self, isa: &TargetIsa) -> ValueLabelsRanges {
+        value_labels_ranges(&self.func, isa)
+    }
+
+    /// Compute the source locations of the machine code of the compiled function.
+    ///
+    /// See the `source_map` module.
+    pub fn source_map(&self, isa: &TargetIsa) -> SourceMap {
+        SourceMap::new(&self.func, isa)
     }
 
     /// Emit machine code directly into raw memory.
@@ -114,6 +354,32 @@ impl Context {
         isa.emit_function(&self.func, &mut MemoryCodeSink::new(mem, relocs));
     }
 
+    /// Emit machine code into `mem` with bounds checking.
+    ///
+    /// This is useful for emitting straight into executable or shared memory. Nothing is written
+    /// outside `mem`, and if the function's code doesn't fit, `CodegenError::CodeTooLarge` is
+    /// returned.
+    ///
+    /// Returns the list of relocations that must be applied to the code before it can run.
+    pub fn emit_to_slice(
+        &self,
+        mem: &mut [u8],
+        isa: &TargetIsa,
+    ) -> Result<Vec<ArtifactReloc>, CodegenError> {
+        let _tt = timing::binemit();
+        let mut recorder = RelocRecorder::new();
+        let overflowed = {
+            let mut sink = MemoryCodeSink::with_slice(mem, &mut recorder);
+            isa.emit_function(&self.func, &mut sink);
+            sink.overflowed()
+        };
+        if overflowed {
+            Err(CodegenError::CodeTooLarge)
+        } else {
+            Ok(recorder.relocs)
+        }
+    }
+
     /// Run the verifier on the function.
     ///
     /// Also check that the dominator tree and control flow graph are consistent with the function.
@@ -122,7 +388,7 @@ impl Context {
     }
 
     /// Run the verifier only if the `enable_verifier` setting is true.
-    pub fn verify_if<'a, FOI: Into<FlagsOrIsa<'a>>>(&self, fisa: FOI) -> CtonResult {
+    pub fn verify_if<'a, FOI: Into<FlagsOrIsa<'a>>>(&self, fisa: FOI) -> CodegenResult {
         let fisa = fisa.into();
         if fisa.flags.enable_verifier() {
             self.verify(fisa).map_err(Into::into)
@@ -131,14 +397,30 @@ impl Context {
         }
     }
 
+    /// Run the encodings verifier on the legalized function.
+    pub fn verify_encodings(&self, isa: &TargetIsa) -> verifier::Result {
+        verifier::verify_encodings(isa, &self.func)
+    }
+
+    /// Run the encodings verifier only if the `enable_verifier` setting is true and the
+    /// `verifier_level` is full.
+    pub fn verify_encodings_if(&self, isa: &TargetIsa) -> CodegenResult {
+        if isa.flags().enable_verifier() && isa.flags().verifier_level() == VerifierLevel::Full {
+            self.verify_encodings(isa).map_err(Into::into)
+        } else {
+            Ok(())
+        }
+    }
+
     /// Run the locations verifier on the function.
     pub fn verify_locations<'a>(&self, isa: &TargetIsa) -> verifier::Result {
         verifier::verify_locations(isa, &self.func, None)
     }
 
-    /// Run the locations verifier only if the `enable_verifier` setting is true.
-    pub fn verify_locations_if<'a>(&self, isa: &TargetIsa) -> CtonResult {
-        if isa.flags().enable_verifier() {
+    /// Run the locations verifier only if the `enable_verifier` setting is true and the
+    /// `verifier_level` is full.
+    pub fn verify_locations_if<'a>(&self, isa: &TargetIsa) -> CodegenResult {
+        if isa.flags().enable_verifier() && isa.flags().verifier_level() == VerifierLevel::Full {
             self.verify_locations(isa).map_err(Into::into)
         } else {
             Ok(())
@@ -146,19 +428,19 @@ impl Context {
     }
 
     /// Perform pre-legalization rewrites on the function.
-    pub fn preopt(&mut self, isa: &TargetIsa) -> CtonResult {
+    pub fn preopt(&mut self, isa: &TargetIsa) -> CodegenResult {
         do_preopt(&mut self.func);
         self.verify_if(isa)?;
         Ok(())
     }
 
     /// Run the legalizer for `isa` on the function.
-    pub fn legalize(&mut self, isa: &TargetIsa) -> CtonResult {
+    pub fn legalize(&mut self, isa: &TargetIsa) -> CodegenResult {
         // Legalization invalidates the domtree and loop_analysis by mutating the CFG.
         // TODO: Avoid doing this when legalization doesn't actually mutate the CFG.
         self.domtree.clear();
         self.loop_analysis.clear();
-        legalize_function(&mut self.func, &mut self.cfg, isa);
+        legalize_function(&mut self.func, &mut self.cfg, isa)?;
         self.verify_if(isa)
     }
 
@@ -188,13 +470,13 @@ impl Context {
     }
 
     /// Perform simple GVN on the function.
-    pub fn simple_gvn<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CtonResult {
+    pub fn simple_gvn<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult {
         do_simple_gvn(&mut self.func, &mut self.cfg, &mut self.domtree);
         self.verify_if(fisa)
     }
 
     /// Perform LICM on the function.
-    pub fn licm<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CtonResult {
+    pub fn licm<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult {
         do_licm(
             &mut self.func,
             &mut self.cfg,
@@ -205,16 +487,16 @@ impl Context {
     }
 
     /// Perform unreachable code elimination.
-    pub fn eliminate_unreachable_code<'a, FOI>(&mut self, fisa: FOI) -> CtonResult
+    pub fn eliminate_unreachable_code<'a, FOI>(&mut self, fisa: FOI) -> CodegenResult
     where
         FOI: Into<FlagsOrIsa<'a>>,
     {
-        eliminate_unreachable_code(&mut self.func, &mut self.cfg, &self.domtree);
+        eliminate_unreachable_code(&mut self.func, &mut self.cfg, &mut self.domtree);
         self.verify_if(fisa)
     }
 
     /// Run the register allocator.
-    pub fn regalloc(&mut self, isa: &TargetIsa) -> CtonResult {
+    pub fn regalloc(&mut self, isa: &TargetIsa) -> CodegenResult {
         self.regalloc.run(
             isa,
             &mut self.func,
@@ -224,7 +506,7 @@ impl Context {
     }
 
     /// Insert prologue and epilogues after computing the stack frame layout.
-    pub fn prologue_epilogue(&mut self, isa: &TargetIsa) -> CtonResult {
+    pub fn prologue_epilogue(&mut self, isa: &TargetIsa) -> CodegenResult {
         isa.prologue_epilogue(&mut self.func)?;
         self.verify_if(isa)?;
         self.verify_locations_if(isa)?;
@@ -232,7 +514,7 @@ impl Context {
     }
 
     /// Run the branch relaxation pass and return the final code size.
-    pub fn relax_branches(&mut self, isa: &TargetIsa) -> Result<CodeOffset, CtonError> {
+    pub fn relax_branches(&mut self, isa: &TargetIsa) -> Result<CodeOffset, CodegenError> {
         let code_size = relax_branches(&mut self.func, isa)?;
         self.verify_if(isa)?;
         self.verify_locations_if(isa)?;
@@ -0,0 +1,300 @@
+//! Structural diffs of functions.
+//!
+//! Compare two versions of a function, typically before and after a compiler pass, and print only
+//! the changes. The EBBs of the two versions are aligned by their entity numbers, which passes
+//! preserve, and the instructions in matching EBBs are compared line by line. The changes are
+//! printed per EBB, with the EBB header for context:
+//!
+//! ```text
+//!  ebb0(v0: i32):
+//! -    v1 = imul_imm v0, 2
+//! +    v1 = ishl_imm v0, 1
+//! ```
+//!
+//! The preamble is compared like an EBB whose header is the `function` line. Instructions are
+//! compared as text, ignoring indentation, so an instruction that only got an encoding or a
+//! different value number shows up as changed.
+
+use ir::Function;
+use isa::TargetIsa;
+use std::fmt::{Result, Error, Write};
+use std::result;
+
+/// Write the differences between `old` and `new` to `w`.
+///
+/// Nothing is written if the two functions print the same.
+pub fn write_function_diff(
+    w: &mut Write,
+    old: &Function,
+    new: &Function,
+    isa: Option<&TargetIsa>,
+) -> Result {
+    let old_text = old.display(isa).to_string();
+    let new_text = new.display(isa).to_string();
+    let old = sections(&old_text);
+    let new = sections(&new_text);
+
+    let old_keys: Vec<&str> = old.iter().map(|s| s.key).collect();
+    let new_keys: Vec<&str> = new.iter().map(|s| s.key).collect();
+    let mut any = false;
+    for edit in diff(&old_keys, &new_keys) {
+        any = match edit {
+            Edit::Same(i, j) => write_section_diff(w, &old[i], &new[j], any)?,
+            Edit::Removed(i) => write_section(w, &old[i], '-', any)?,
+            Edit::Added(j) => write_section(w, &new[j], '+', any)?,
+        } || any;
+    }
+    Ok(())
+}
+
+/// A section of a function's text: The preamble or an EBB.
+struct Section<'a> {
+    /// The entity that starts the section, `function` for the preamble.
+    key: &'a str,
+    /// The `function` line or the EBB header.
+    header: &'a str,
+    /// The preamble declarations or the instructions, without indentation.
+    body: Vec<&'a str>,
+}
+
+/// Split the text of a function into sections.
+fn sections(text: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line == "}" {
+            continue;
+        }
+        match section_key(line) {
+            Some(key) => {
+                sections.push(Section {
+                    key,
+                    header: line,
+                    body: Vec::new(),
+                })
+            }
+            None => {
+                if let Some(section) = sections.last_mut() {
+                    section.body.push(line);
+                }
+            }
+        }
+    }
+    sections
+}
+
+/// Get the key of the section started by `line`, if it is a `function` line or an EBB header.
+fn section_key(line: &str) -> Option<&str> {
+    if line.starts_with("function ") {
+        return Some("function");
+    }
+    if !line.starts_with("ebb") {
+        return None;
+    }
+    let digits = line[3..].find(|c: char| !c.is_digit(10)).unwrap_or(
+        line.len() - 3,
+    );
+    let rest = &line[3 + digits..];
+    if digits > 0 && (rest.starts_with('(') || rest.starts_with(':')) {
+        Some(&line[0..3 + digits])
+    } else {
+        None
+    }
+}
+
+/// Write the changes between two versions of a section.
+///
+/// Returns true if anything was written. `separate` requests a blank line before the section.
+fn write_section_diff(
+    w: &mut Write,
+    old: &Section,
+    new: &Section,
+    separate: bool,
+) -> result::Result<bool, Error> {
+    if old.header == new.header && old.body == new.body {
+        return Ok(false);
+    }
+    if separate {
+        writeln!(w)?;
+    }
+    if old.header == new.header {
+        writeln!(w, " {}", new.header)?;
+    } else {
+        writeln!(w, "-{}", old.header)?;
+        writeln!(w, "+{}", new.header)?;
+    }
+    for edit in diff(&old.body, &new.body) {
+        match edit {
+            Edit::Same(..) => {}
+            Edit::Removed(i) => writeln!(w, "-    {}", old.body[i])?,
+            Edit::Added(j) => writeln!(w, "+    {}", new.body[j])?,
+        }
+    }
+    Ok(true)
+}
+
+/// Write a whole section that exists in only one version, with every line prefixed by `sign`.
+fn write_section(
+    w: &mut Write,
+    section: &Section,
+    sign: char,
+    separate: bool,
+) -> result::Result<bool, Error> {
+    if separate {
+        writeln!(w)?;
+    }
+    writeln!(w, "{}{}", sign, section.header)?;
+    for line in &section.body {
+        writeln!(w, "{}    {}", sign, line)?;
+    }
+    Ok(true)
+}
+
+/// An edit operation turning one sequence into another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+    /// `old[i]` and `new[j]` are the same.
+    Same(usize, usize),
+    /// `old[i]` was removed.
+    Removed(usize),
+    /// `new[j]` was added.
+    Added(usize),
+}
+
+/// Compute the edits that turn `old` into `new`, keeping the longest common subsequence.
+fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    // The common prefix and suffix are always kept, and skipping them makes the quadratic part
+    // small for the typical pass that only changes a few instructions.
+    let prefix = old.iter().zip(new).take_while(|&(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|&(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    // `lcs[i * width + j]` is the length of the longest common subsequence of `old_mid[i..]` and
+    // `new_mid[j..]`.
+    let width = new_mid.len() + 1;
+    let mut lcs = vec![0u32; (old_mid.len() + 1) * width];
+    for i in (0..old_mid.len()).rev() {
+        for j in (0..new_mid.len()).rev() {
+            lcs[i * width + j] = if old_mid[i] == new_mid[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Same(i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < old_mid.len() || j < new_mid.len() {
+        if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+            edits.push(Edit::Same(prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if j == new_mid.len() ||
+                   (i < old_mid.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+        {
+            edits.push(Edit::Removed(prefix + i));
+            i += 1;
+        } else {
+            edits.push(Edit::Added(prefix + j));
+            j += 1;
+        }
+    }
+    let old_end = old.len() - suffix;
+    let new_end = new.len() - suffix;
+    edits.extend((0..suffix).map(|k| Edit::Same(old_end + k, new_end + k)));
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{ExternalName, InstBuilder};
+    use ir::types;
+
+    #[test]
+    fn edits() {
+        assert_eq!(diff::<u32>(&[], &[]), []);
+        assert_eq!(
+            diff(&[1, 2, 3, 4], &[1, 5, 3, 4, 6]),
+            [
+                Edit::Same(0, 0),
+                Edit::Removed(1),
+                Edit::Added(1),
+                Edit::Same(2, 2),
+                Edit::Same(3, 3),
+                Edit::Added(4),
+            ]
+        );
+        assert_eq!(
+            diff(&[1, 2, 3], &[2, 3, 1]),
+            [
+                Edit::Removed(0),
+                Edit::Same(1, 0),
+                Edit::Same(2, 1),
+                Edit::Added(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(section_key("function %foo() native {"), Some("function"));
+        assert_eq!(section_key("ebb12(v1: i32):"), Some("ebb12"));
+        assert_eq!(section_key("ebb3:"), Some("ebb3"));
+        assert_eq!(section_key("ebb:"), None);
+        assert_eq!(section_key("v1 = iconst.i32 1"), None);
+    }
+
+    #[test]
+    fn functions() {
+        let mut old = Function::new();
+        old.name = ExternalName::testcase("foo");
+        let (ebb0, arg) = {
+            let ebb0 = old.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut old);
+            pos.insert_ebb(ebb0);
+            let arg = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            let v = pos.ins().imul_imm(arg, 2);
+            pos.ins().return_(&[v]);
+            (ebb0, arg)
+        };
+
+        let mut s = String::new();
+        write_function_diff(&mut s, &old, &old, None).unwrap();
+        assert_eq!(s, "");
+
+        let mut new = old.clone();
+        {
+            let ebb1 = new.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut new).at_first_inst(ebb0);
+            let v = pos.ins().ishl_imm(arg, 1);
+            pos.ins().jump(ebb1, &[v]);
+            pos.remove_inst();
+            pos.remove_inst();
+            pos.insert_ebb(ebb1);
+            let param = pos.func.dfg.append_ebb_param(ebb1, types::I32);
+            pos.ins().return_(&[param]);
+        }
+        write_function_diff(&mut s, &old, &new, None).unwrap();
+        assert_eq!(
+            s,
+            concat!(
+                " ebb0(v0: i32):\n",
+                "-    v1 = imul_imm v0, 2\n",
+                "-    return v1\n",
+                "+    v2 = ishl_imm v0, 1\n",
+                "+    jump ebb1(v2)\n",
+                "\n",
+                "+ebb1(v3: i32):\n",
+                "+    return v3\n",
+            )
+        );
+    }
+}
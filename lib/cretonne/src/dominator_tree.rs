@@ -46,7 +46,8 @@ pub struct DominatorTree {
     // Scratch memory used by `compute_postorder()`.
     stack: Vec<Ebb>,
 
-    valid: bool,
+    // The layout generation the tree is up to date with, or `None` if it hasn't been computed.
+    generation: Option<u64>,
 }
 
 /// Methods for querying the dominator tree.
@@ -216,7 +217,7 @@ impl DominatorTree {
             nodes: EntityMap::new(),
             postorder: Vec::new(),
             stack: Vec::new(),
-            valid: false,
+            generation: None,
         }
     }
 
@@ -230,10 +231,10 @@ impl DominatorTree {
     /// Reset and compute a CFG post-order and dominator tree.
     pub fn compute(&mut self, func: &Function, cfg: &ControlFlowGraph) {
         let _tt = timing::domtree();
-        debug_assert!(cfg.is_valid());
+        cfg.assert_current(func);
         self.compute_postorder(func);
         self.compute_domtree(func, cfg);
-        self.valid = true;
+        self.generation = Some(func.layout.generation());
     }
 
     /// Clear the data structures used to represent the dominator tree. This will leave the tree in
@@ -242,7 +243,7 @@ impl DominatorTree {
         self.nodes.clear();
         self.postorder.clear();
         debug_assert!(self.stack.is_empty());
-        self.valid = false;
+        self.generation = None;
     }
 
     /// Check if the dominator tree is in a valid state.
@@ -251,7 +252,20 @@ impl DominatorTree {
     /// `compute()` method has been called since the last `clear()`. It does not check that the
     /// dominator tree is consistent with the CFG.
     pub fn is_valid(&self) -> bool {
-        self.valid
+        self.generation.is_some()
+    }
+
+    /// Assert that the dominator tree has been computed for the current EBB layout of `func`.
+    ///
+    /// See `ControlFlowGraph::assert_current()`.
+    pub fn assert_current(&self, func: &Function) {
+        debug_assert!(self.is_valid(), "the dominator tree hasn't been computed");
+        debug_assert!(
+            self.generation == Some(func.layout.generation()),
+            "the dominator tree is stale: EBBs were inserted, removed, or split in {} since it \
+             was computed",
+            func.name
+        );
     }
 
     /// Reset all internal data structures and compute a post-order of the control flow graph.
@@ -441,8 +455,16 @@ impl DominatorTree {
     ///
     /// `old_ebb` is the `Ebb` before splitting, and `new_ebb` is the `Ebb` which now contains
     /// the second half of `old_ebb`. `split_jump_inst` is the terminator jump instruction of
-    /// `old_ebb` that points to `new_ebb`.
-    pub fn recompute_split_ebb(&mut self, old_ebb: Ebb, new_ebb: Ebb, split_jump_inst: Inst) {
+    /// `old_ebb` that points to `new_ebb`. Afterwards, the dominator tree is considered up to date
+    /// with the layout of `func`.
+    pub fn recompute_split_ebb(
+        &mut self,
+        func: &Function,
+        old_ebb: Ebb,
+        new_ebb: Ebb,
+        split_jump_inst: Inst,
+    ) {
+        self.generation = Some(func.layout.generation());
         if !self.is_reachable(old_ebb) {
             // old_ebb is unreachable, it stays so and new_ebb is unreachable too
             self.nodes[new_ebb] = Default::default();
@@ -460,7 +482,16 @@ impl DominatorTree {
             rpo_number: new_ebb_rpo,
             idom: Some(split_jump_inst).into(),
         };
+    }
 
+    /// Update the dominator tree after removing the unreachable `ebb` from the layout of `func`.
+    ///
+    /// Unreachable EBBs don't dominate anything, so this only records that the dominator tree is
+    /// still up to date with the layout.
+    pub fn remove_unreachable_ebb(&mut self, func: &Function, ebb: Ebb) {
+        debug_assert!(!self.is_reachable(ebb), "{} is reachable", ebb);
+        debug_assert!(!func.layout.is_ebb_inserted(ebb), "{} is still in the layout", ebb);
+        self.generation = Some(func.layout.generation());
     }
 
     // Insert new_ebb just after ebb in the RPO. This function checks
@@ -908,29 +939,52 @@ mod test {
         cur.goto_bottom(ebb0);
         let middle_jump_inst = cur.ins().jump(ebb1, &[]);
 
-        dt.recompute_split_ebb(ebb0, ebb1, middle_jump_inst);
+        dt.recompute_split_ebb(cur.func, ebb0, ebb1, middle_jump_inst);
 
         let ebb2 = cur.func.dfg.make_ebb();
         cur.func.layout.split_ebb(ebb2, inst3);
         cur.goto_bottom(ebb1);
         let middle_jump_inst = cur.ins().jump(ebb2, &[]);
-        dt.recompute_split_ebb(ebb1, ebb2, middle_jump_inst);
+        dt.recompute_split_ebb(cur.func, ebb1, ebb2, middle_jump_inst);
 
         let ebb3 = cur.func.dfg.make_ebb();
         cur.func.layout.split_ebb(ebb3, inst4);
         cur.goto_bottom(ebb2);
         let middle_jump_inst = cur.ins().jump(ebb3, &[]);
-        dt.recompute_split_ebb(ebb2, ebb3, middle_jump_inst);
+        dt.recompute_split_ebb(cur.func, ebb2, ebb3, middle_jump_inst);
 
         let ebb4 = cur.func.dfg.make_ebb();
         cur.func.layout.split_ebb(ebb4, inst5);
         cur.goto_bottom(ebb3);
         let middle_jump_inst = cur.ins().jump(ebb4, &[]);
-        dt.recompute_split_ebb(ebb3, ebb4, middle_jump_inst);
+        dt.recompute_split_ebb(cur.func, ebb3, ebb4, middle_jump_inst);
 
         cfg.compute(cur.func);
 
         let flags = settings::Flags::new(&settings::builder());
         verify_context(cur.func, &cfg, &dt, &flags).unwrap();
+        dt.assert_current(cur.func);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "dominator tree is stale")]
+    fn stale() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_ebb(ebb0);
+        cur.ins().return_(&[]);
+
+        let mut cfg = ControlFlowGraph::with_function(cur.func);
+        let dt = DominatorTree::with_function(cur.func, &cfg);
+
+        // The CFG is updated, but the dominator tree isn't.
+        cur.insert_ebb(ebb1);
+        cur.ins().return_(&[]);
+        cfg.recompute_ebb(cur.func, ebb1);
+        cfg.assert_current(cur.func);
+        dt.assert_current(cur.func);
     }
 }
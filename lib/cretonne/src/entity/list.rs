@@ -55,6 +55,9 @@ use std::mem;
 /// as small as possible. This means that shrinking a list may cause the excess capacity to shrink
 /// if a smaller power-of-two size becomes available.
 ///
+/// The smallest blocks have room for a single element, which is the common case for instruction
+/// results and for the arguments of branches and calls.
+///
 /// Both growing and shrinking a list may cause it to be reallocated in the pool vector.
 ///
 /// The index stored in an `EntityList` points to part 2, the list elements. The value 0 is
@@ -98,25 +101,27 @@ pub struct ListPool<T: EntityRef> {
     free: Vec<usize>,
 }
 
-/// Lists are allocated in sizes that are powers of two, starting from 4.
-/// Each power of two is assigned a size class number, so the size is `4 << SizeClass`.
+/// Lists are allocated in sizes that are powers of two, starting from 2.
+/// Each power of two is assigned a size class number, so the size is `2 << SizeClass`.
+///
+/// A block of size 2 is also the smallest that can hold a free list entry.
 type SizeClass = u8;
 
 /// Get the size of a given size class. The size includes the length field, so the maximum list
 /// length is one less than the class size.
 fn sclass_size(sclass: SizeClass) -> usize {
-    4 << sclass
+    2 << sclass
 }
 
 /// Get the size class to use for a given list length.
 /// This always leaves room for the length element in addition to the list elements.
 fn sclass_for_length(len: usize) -> SizeClass {
-    30 - (len as u32 | 3).leading_zeros() as SizeClass
+    31 - (len as u32 | 1).leading_zeros() as SizeClass
 }
 
 /// Is `len` the minimum length in its size class?
 fn is_sclass_min_length(len: usize) -> bool {
-    len > 3 && len.is_power_of_two()
+    len > 1 && len.is_power_of_two()
 }
 
 impl<T: EntityRef> ListPool<T> {
@@ -479,23 +484,29 @@ impl<T: EntityRef> EntityList<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::{sclass_size, sclass_for_length};
+    use super::{sclass_size, sclass_for_length, is_sclass_min_length};
     use ir::Inst;
     use entity::EntityRef;
 
     #[test]
     fn size_classes() {
-        assert_eq!(sclass_size(0), 4);
+        assert_eq!(sclass_size(0), 2);
         assert_eq!(sclass_for_length(0), 0);
         assert_eq!(sclass_for_length(1), 0);
-        assert_eq!(sclass_for_length(2), 0);
-        assert_eq!(sclass_for_length(3), 0);
-        assert_eq!(sclass_for_length(4), 1);
-        assert_eq!(sclass_for_length(7), 1);
-        assert_eq!(sclass_for_length(8), 2);
-        assert_eq!(sclass_size(1), 8);
+        assert_eq!(sclass_for_length(2), 1);
+        assert_eq!(sclass_for_length(3), 1);
+        assert_eq!(sclass_for_length(4), 2);
+        assert_eq!(sclass_for_length(7), 2);
+        assert_eq!(sclass_for_length(8), 3);
+        assert_eq!(sclass_size(1), 4);
         for l in 0..300 {
             assert!(sclass_size(sclass_for_length(l)) >= l + 1);
+            // The size class is the smallest one that fits.
+            assert!(l < 2 || sclass_size(sclass_for_length(l) - 1) < l + 1);
+            assert_eq!(
+                is_sclass_min_length(l),
+                l > 0 && sclass_for_length(l) != sclass_for_length(l - 1)
+            );
         }
     }
 
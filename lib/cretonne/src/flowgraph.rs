@@ -62,7 +62,8 @@ pub struct ControlFlowGraph {
     data: EntityMap<Ebb, CFGNode>,
     pred_forest: bforest::MapForest<Inst, Ebb, ()>,
     succ_forest: bforest::SetForest<Ebb, ()>,
-    valid: bool,
+    // The layout generation the CFG is up to date with, or `None` if it hasn't been computed.
+    generation: Option<u64>,
 }
 
 impl ControlFlowGraph {
@@ -70,7 +71,7 @@ impl ControlFlowGraph {
     pub fn new() -> Self {
         Self {
             data: EntityMap::new(),
-            valid: false,
+            generation: None,
             pred_forest: bforest::MapForest::new(),
             succ_forest: bforest::SetForest::new(),
         }
@@ -81,7 +82,7 @@ impl ControlFlowGraph {
         self.data.clear();
         self.pred_forest.clear();
         self.succ_forest.clear();
-        self.valid = false;
+        self.generation = None;
     }
 
     /// Allocate and compute the control flow graph for `func`.
@@ -103,7 +104,7 @@ impl ControlFlowGraph {
             self.compute_ebb(func, ebb);
         }
 
-        self.valid = true;
+        self.generation = Some(func.layout.generation());
     }
 
     fn compute_ebb(&mut self, func: &Function, ebb: Ebb) {
@@ -142,10 +143,14 @@ impl ControlFlowGraph {
     /// from `ebb` while leaving edges to `ebb` intact. Its functionality a subset of that of the
     /// more expensive `compute`, and should be used when we know we don't need to recompute the CFG
     /// from scratch, but rather that our changes have been restricted to specific EBBs.
+    ///
+    /// EBBs that were inserted or removed must be recomputed too. Afterwards, the CFG is considered
+    /// up to date with the layout of `func`.
     pub fn recompute_ebb(&mut self, func: &Function, ebb: Ebb) {
         debug_assert!(self.is_valid());
         self.invalidate_ebb_successors(ebb);
         self.compute_ebb(func, ebb);
+        self.generation = Some(func.layout.generation());
     }
 
     fn add_edge(&mut self, from: BasicBlock, to: Ebb) {
@@ -179,7 +184,23 @@ impl ControlFlowGraph {
     /// `compute()` method has been called since the last `clear()`. It does not check that the
     /// CFG is consistent with the function.
     pub fn is_valid(&self) -> bool {
-        self.valid
+        self.generation.is_some()
+    }
+
+    /// Assert that the CFG has been computed for the current EBB layout of `func`.
+    ///
+    /// Passes that use the CFG call this first, so a CFG that wasn't updated after inserting,
+    /// removing, or splitting EBBs is caught before it is used. The check is only performed in
+    /// debug builds, and it can't see branches that were retargeted without changing the EBB
+    /// layout. See `Layout::generation()`.
+    pub fn assert_current(&self, func: &Function) {
+        debug_assert!(self.is_valid(), "the control flow graph hasn't been computed");
+        debug_assert!(
+            self.generation == Some(func.layout.generation()),
+            "the control flow graph is stale: EBBs were inserted, removed, or split in {} since \
+             it was computed",
+            func.name
+        );
     }
 }
 
@@ -312,4 +333,43 @@ mod tests {
             assert_eq!(ebb2_successors.collect::<Vec<_>>(), []);
         }
     }
+
+    #[test]
+    fn current() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        let mut cfg = ControlFlowGraph::with_function(&func);
+        cfg.assert_current(&func);
+
+        // Recomputing the new EBB brings the CFG up to date.
+        func.layout.append_ebb(ebb1);
+        cfg.recompute_ebb(&func, ebb1);
+        cfg.assert_current(&func);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "control flow graph is stale")]
+    fn stale() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        let cfg = ControlFlowGraph::with_function(&func);
+        func.layout.append_ebb(ebb1);
+        cfg.assert_current(&func);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "control flow graph is stale")]
+    fn other_function() {
+        let func = Function::new();
+        let cfg = ControlFlowGraph::with_function(&func);
+
+        // Another fresh function must not look like the one the CFG was computed for.
+        cfg.assert_current(&Function::new());
+    }
 }
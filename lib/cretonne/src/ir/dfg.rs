@@ -190,6 +190,19 @@ impl DataFlowGraph {
         }
     }
 
+    /// Get the value that `v` is a direct alias of, or `None` if `v` is not an alias.
+    ///
+    /// Unlike `resolve_aliases()`, this only follows a single alias, so the result may be an
+    /// alias itself.
+    pub fn value_alias_original(&self, v: Value) -> Option<Value> {
+        match self.values[v] {
+            ValueData::Alias { original, .. } if original != Value::reserved_value() => {
+                Some(original)
+            }
+            _ => None,
+        }
+    }
+
     /// Resolve value aliases.
     ///
     /// Find the original SSA value that `value` aliases.
@@ -156,6 +156,14 @@ impl Heap {
     }
 }
 
+/// A reference to a value label.
+///
+/// Value labels identify source-level variables in debug information. See
+/// `Function::value_labels`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueLabel(u32);
+entity_impl!(ValueLabel, "label");
+
 /// A reference to any of the entities defined in this module.
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum AnyEntity {
@@ -18,7 +18,7 @@ use std::str::FromStr;
 ///
 /// A signature can optionally include ISA-specific ABI information which specifies exactly how
 /// arguments and return values are passed.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Signature {
     /// The arguments passed to the function.
     pub params: Vec<AbiParam>,
@@ -122,7 +122,7 @@ impl fmt::Display for Signature {
 ///
 /// This describes the value type being passed to or from a function along with flags that affect
 /// how the argument is passed.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct AbiParam {
     /// Type of the argument value.
     pub value_type: Type,
@@ -224,7 +224,7 @@ impl fmt::Display for AbiParam {
 ///
 /// On some architectures, small integer function arguments are extended to the width of a
 /// general-purpose register.
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
 pub enum ArgumentExtension {
     /// No extension, high bits are indeterminate.
     None,
@@ -241,7 +241,7 @@ pub enum ArgumentExtension {
 /// frame pointers and callee-saved registers.
 ///
 /// The argument purpose is used to indicate any special meaning of an argument or return value.
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
 pub enum ArgumentPurpose {
     /// A normal user program value passed to or from a function.
     Normal,
@@ -340,7 +340,7 @@ impl fmt::Display for ExtFuncData {
 /// and how stack frames are managed. Since all of these details depend on both the instruction set
 /// architecture and possibly the operating system, a function's calling convention is only fully
 /// determined by a `(TargetIsa, CallConv)` tuple.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CallConv {
     /// The C calling convention.
     ///
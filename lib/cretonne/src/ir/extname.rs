@@ -21,7 +21,7 @@ const TESTCASE_NAME_LENGTH: usize = 16;
 /// External names can also serve as a primitive testing and debugging tool.
 /// In particular, many `.cton` test files use function names to identify
 /// functions.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ExternalName {
     /// A name in a user-defined symbol table. Cretonne does not interpret
     /// these numbers in any way.
@@ -7,12 +7,13 @@ use binemit::CodeOffset;
 use entity::{PrimaryMap, EntityMap};
 use ir;
 use ir::{ExternalName, CallConv, Signature, DataFlowGraph, Layout};
-use ir::{InstEncodings, ValueLocations, JumpTables, StackSlots, EbbOffsets, SourceLocs};
+use ir::{InstEncodings, ValueLocations, JumpTables, StackSlots, EbbOffsets, SourceLocs,
+         ValueLabelAssignments, ValueLabelListPool};
 use ir::{Ebb, JumpTableData, JumpTable, StackSlotData, StackSlot, SigRef, ExtFuncData, FuncRef,
-         GlobalVarData, GlobalVar, HeapData, Heap};
+         GlobalVarData, GlobalVar, HeapData, Heap, Value, ValueLabel, ValueLabelData};
 use isa::{TargetIsa, EncInfo};
 use std::fmt;
-use write::write_function;
+use write::{write_function, write_function_with_options, WriteOptions};
 
 /// A function.
 ///
@@ -63,6 +64,18 @@ pub struct Function {
     /// Track the original source location for each instruction. The source locations are not
     /// interpreted by Cretonne, only preserved.
     pub srclocs: SourceLocs,
+
+    /// Value labels declared for the source-level variables of the function.
+    pub value_labels: PrimaryMap<ValueLabel, ValueLabelData>,
+
+    /// The value labels attached to each value.
+    ///
+    /// Like source locations, value labels are not interpreted by Cretonne. They are not included
+    /// in the textual IL format. Use `value_labels_of()` to get the labels of a value.
+    pub labelled_values: ValueLabelAssignments,
+
+    /// Memory pool of the label lists in `labelled_values`.
+    pub value_label_lists: ValueLabelListPool,
 }
 
 impl Function {
@@ -81,12 +94,26 @@ impl Function {
             locations: EntityMap::new(),
             offsets: EntityMap::new(),
             srclocs: EntityMap::new(),
+            value_labels: PrimaryMap::new(),
+            labelled_values: EntityMap::new(),
+            value_label_lists: ValueLabelListPool::new(),
         }
     }
 
     /// Clear all data structures in this function.
+    ///
+    /// The function becomes an empty, anonymous function with a native calling convention, but it
+    /// retains the memory allocated for its tables so it can be reused for another function.
     pub fn clear(&mut self) {
+        self.name = ExternalName::default();
         self.signature.clear(ir::CallConv::Native);
+        self.clear_body();
+    }
+
+    /// Clear everything in this function except its name and signature.
+    ///
+    /// Like `clear()`, this retains the allocated memory, so a new body can be built in its place.
+    pub fn clear_body(&mut self) {
         self.stack_slots.clear();
         self.global_vars.clear();
         self.heaps.clear();
@@ -97,6 +124,9 @@ impl Function {
         self.locations.clear();
         self.offsets.clear();
         self.srclocs.clear();
+        self.value_labels.clear();
+        self.labelled_values.clear();
+        self.value_label_lists.clear();
     }
 
     /// Create a new empty, anonymous function with a native calling convention.
@@ -135,6 +165,24 @@ impl Function {
         self.global_vars.push(data)
     }
 
+    /// Declares a value label for a source-level variable.
+    pub fn create_value_label(&mut self, data: ValueLabelData) -> ValueLabel {
+        self.value_labels.push(data)
+    }
+
+    /// Attaches `label` to `value`, recording that the value holds the labelled variable.
+    pub fn label_value(&mut self, value: Value, label: ValueLabel) {
+        let labels = &mut self.labelled_values[value];
+        if !labels.as_slice(&self.value_label_lists).contains(&label) {
+            labels.push(label, &mut self.value_label_lists);
+        }
+    }
+
+    /// Get the value labels attached to `value`.
+    pub fn value_labels_of(&self, value: Value) -> &[ValueLabel] {
+        self.labelled_values[value].as_slice(&self.value_label_lists)
+    }
+
     /// Declares a heap accessible to the function.
     pub fn create_heap(&mut self, data: HeapData) -> Heap {
         self.heaps.push(data)
@@ -142,7 +190,30 @@ impl Function {
 
     /// Return an object that can display this function with correct ISA-specific annotations.
     pub fn display<'a, I: Into<Option<&'a TargetIsa>>>(&'a self, isa: I) -> DisplayFunction<'a> {
-        DisplayFunction(self, isa.into())
+        DisplayFunction(self, isa.into(), WriteOptions::default())
+    }
+
+    /// Return an object that displays this function as text that reads back with the same entity
+    /// numbers. See `write::write_function_stable()`.
+    pub fn display_stable<'a, I: Into<Option<&'a TargetIsa>>>(
+        &'a self,
+        isa: I,
+    ) -> DisplayFunction<'a> {
+        let options = WriteOptions {
+            stable: true,
+            ..WriteOptions::default()
+        };
+        DisplayFunction(self, isa.into(), options)
+    }
+
+    /// Return an object that displays this function with the annotations selected by `options`.
+    /// See `write::write_function_with_options()`.
+    pub fn display_with_options<'a, I: Into<Option<&'a TargetIsa>>>(
+        &'a self,
+        isa: I,
+        options: WriteOptions,
+    ) -> DisplayFunction<'a> {
+        DisplayFunction(self, isa.into(), options)
     }
 
     /// Find a presumed unique special-purpose function parameter value.
@@ -179,11 +250,13 @@ impl Function {
 }
 
 /// Wrapper type capable of displaying a `Function` with correct ISA annotations.
-pub struct DisplayFunction<'a>(&'a Function, Option<&'a TargetIsa>);
+///
+/// The last field holds the options passed to `write_function_with_options()`.
+pub struct DisplayFunction<'a>(&'a Function, Option<&'a TargetIsa>, WriteOptions);
 
 impl<'a> fmt::Display for DisplayFunction<'a> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write_function(fmt, self.0, self.1)
+        write_function_with_options(fmt, self.0, self.1, &self.2)
     }
 }
 
@@ -32,6 +32,7 @@ pub type ValueListPool = entity::ListPool<Value>;
 // - The `pub enum InstructionFormat` enum with all the instruction formats.
 // - The `pub enum Opcode` definition with all known opcodes,
 // - The `const OPCODE_FORMAT: [InstructionFormat; N]` table.
+// - The `const OPCODES: [Opcode; N]` table of all opcodes in numerical order.
 // - The private `fn opcode_name(Opcode) -> &'static str` function, and
 // - The hash table `const OPCODE_HASH_TABLE: [Opcode; N]`.
 //
@@ -60,6 +61,14 @@ impl Opcode {
     pub fn constraints(self) -> OpcodeConstraints {
         OPCODE_CONSTRAINTS[self as usize - 1]
     }
+
+    /// Get the opcode whose numerical value is `number`, as returned by `opcode as u8`.
+    pub fn from_number(number: u8) -> Option<Opcode> {
+        match number {
+            0 => None,
+            n => OPCODES.get(n as usize - 1).cloned(),
+        }
+    }
 }
 
 // This trait really belongs in lib/reader where it is used by the `.cton` file parser, but since
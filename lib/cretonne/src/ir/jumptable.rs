@@ -64,6 +64,14 @@ impl JumpTableData {
         self.table.push(dest.into())
     }
 
+    /// Append a missing table entry.
+    ///
+    /// The `br_table` instruction will fall through if given the index of a missing entry.
+    pub fn push_hole(&mut self) {
+        self.holes += 1;
+        self.table.push(None.into())
+    }
+
     /// Clear a table entry.
     ///
     /// The `br_table` instruction will fall through if given an index corresponding to a cleared
@@ -121,9 +129,11 @@ impl<'a> Iterator for Entries<'a> {
 
 impl Display for JumpTableData {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
-        match self.table.first().and_then(|e| e.expand()) {
-            None => write!(fmt, "jump_table 0")?,
-            Some(first) => write!(fmt, "jump_table {}", first)?,
+        // An empty table has no entries at all, while `0` is a missing entry.
+        match self.table.first().map(|e| e.expand()) {
+            None => write!(fmt, "jump_table")?,
+            Some(None) => write!(fmt, "jump_table 0")?,
+            Some(Some(first)) => write!(fmt, "jump_table {}", first)?,
         }
 
         for dest in self.table.iter().skip(1).map(|e| e.expand()) {
@@ -149,12 +159,25 @@ mod tests {
         assert_eq!(jt.get_entry(0), None);
         assert_eq!(jt.get_entry(10), None);
 
-        assert_eq!(jt.to_string(), "jump_table 0");
+        assert_eq!(jt.to_string(), "jump_table");
 
         let v: Vec<(usize, Ebb)> = jt.entries().collect();
         assert_eq!(v, []);
     }
 
+    #[test]
+    fn holes() {
+        let mut jt = JumpTableData::new();
+        jt.push_hole();
+        assert_eq!(jt.len(), 1);
+        assert_eq!(jt.to_string(), "jump_table 0");
+
+        jt.push_entry(Ebb::new(3));
+        jt.push_hole();
+        assert_eq!(jt.to_string(), "jump_table 0, ebb3, 0");
+        assert_eq!(jt.get_entry(2), None);
+    }
+
     #[test]
     fn insert() {
         let e1 = Ebb::new(1);
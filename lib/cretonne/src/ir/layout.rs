@@ -9,8 +9,18 @@ use ir::progpoint::{ProgramOrder, ExpandedProgramPoint};
 use packed_option::PackedOption;
 use std::cmp;
 use std::iter::{Iterator, IntoIterator};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use timing;
 
+// Source of layout generations. All layouts draw from the same counter, so a generation is never
+// reused, not even by a different function.
+static NEXT_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+// Get a fresh layout generation.
+fn next_generation() -> u64 {
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed) as u64
+}
+
 /// The `Layout` struct determines the layout of EBBs and instructions in a function. It does not
 /// contain definitions of instructions or EBBs, but depends on `Inst` and `Ebb` entity references
 /// being defined elsewhere.
@@ -39,6 +49,9 @@ pub struct Layout {
 
     // Last EBB in the layout order, or `None` when no EBBs have been laid out.
     last_ebb: Option<Ebb>,
+
+    // Renewed whenever EBBs are inserted, removed, or split. See `generation()`.
+    generation: u64,
 }
 
 impl Layout {
@@ -49,6 +62,7 @@ impl Layout {
             insts: EntityMap::new(),
             first_ebb: None,
             last_ebb: None,
+            generation: next_generation(),
         }
     }
 
@@ -58,6 +72,24 @@ impl Layout {
         self.insts.clear();
         self.first_ebb = None;
         self.last_ebb = None;
+        // Analyses of the old function must not look current for the next one.
+        self.generation = next_generation();
+    }
+
+    /// Get the generation of the EBB layout.
+    ///
+    /// The generation changes whenever an EBB is inserted, removed, or split. The analyses that
+    /// depend on the EBBs of a function, like the control flow graph and the dominator tree,
+    /// remember the generation they were computed for, so they can detect that they are stale.
+    /// Generations are unique across all layouts, so an analysis of one function doesn't look
+    /// current for a new function either.
+    ///
+    /// Instructions can be inserted and removed without changing the generation, and branch
+    /// destinations live in the data flow graph, so retargeting a branch isn't detected. A pass
+    /// that changes the destinations of branches must update the CFG itself with
+    /// `ControlFlowGraph::recompute_ebb()` and recompute the analyses derived from it.
+    pub fn generation(&self) -> u64 {
+        self.generation
     }
 }
 
@@ -313,7 +345,7 @@ impl Layout {
                 next_inst = self.insts[inst].next.expand();
             }
         }
-        dbg!("Renumbered {} program points", seq / MAJOR_STRIDE);
+        debug!("Renumbered {} program points", seq / MAJOR_STRIDE);
     }
 }
 
@@ -351,6 +383,7 @@ impl Layout {
         }
         self.last_ebb = Some(ebb);
         self.assign_ebb_seq(ebb);
+        self.generation = next_generation();
     }
 
     /// Insert `ebb` in the layout before the existing EBB `before`.
@@ -375,6 +408,7 @@ impl Layout {
             Some(a) => self.ebbs[a].next = ebb.into(),
         }
         self.assign_ebb_seq(ebb);
+        self.generation = next_generation();
     }
 
     /// Insert `ebb` in the layout *after* the existing EBB `after`.
@@ -399,6 +433,7 @@ impl Layout {
             Some(b) => self.ebbs[b].prev = ebb.into(),
         }
         self.assign_ebb_seq(ebb);
+        self.generation = next_generation();
     }
 
     /// Remove `ebb` from the layout.
@@ -425,6 +460,7 @@ impl Layout {
             None => self.last_ebb = prev.expand(),
             Some(n) => self.ebbs[n].prev = prev,
         }
+        self.generation = next_generation();
     }
 
     /// Return an iterator over all EBBs in layout order.
@@ -684,6 +720,7 @@ impl Layout {
         }
 
         self.assign_ebb_seq(new_ebb);
+        self.generation = next_generation();
     }
 }
 
@@ -948,6 +985,41 @@ mod tests {
         verify(&mut layout, &[(e1, &[]), (e0, &[]), (e2, &[])]);
     }
 
+    #[test]
+    fn generation() {
+        let mut layout = Layout::new();
+        let e0 = Ebb::new(0);
+        let e1 = Ebb::new(1);
+        let i0 = Inst::new(0);
+        let i1 = Inst::new(1);
+
+        let mut gen = layout.generation();
+        layout.append_ebb(e0);
+        assert!(layout.generation() != gen);
+
+        // Instructions don't change the generation.
+        gen = layout.generation();
+        layout.append_inst(i0, e0);
+        layout.insert_inst(i1, i0);
+        layout.remove_inst(i1);
+        assert_eq!(layout.generation(), gen);
+
+        layout.split_ebb(e1, i0);
+        assert!(layout.generation() != gen);
+
+        gen = layout.generation();
+        layout.remove_inst(i0);
+        layout.remove_ebb(e1);
+        assert!(layout.generation() != gen);
+
+        gen = layout.generation();
+        layout.clear();
+        assert!(layout.generation() != gen);
+
+        // A new layout doesn't reuse the generation of another.
+        assert!(Layout::new().generation() != layout.generation());
+    }
+
     #[test]
     fn append_inst() {
         let mut layout = Layout::new();
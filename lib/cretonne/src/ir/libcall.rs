@@ -12,7 +12,7 @@ use std::str::FromStr;
 /// convention in the embedding VM's runtime library.
 ///
 /// This list is likely to grow over time.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum LibCall {
     /// ceil.f32
     CeilF32,
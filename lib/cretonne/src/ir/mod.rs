@@ -20,11 +20,13 @@ mod memflags;
 mod progpoint;
 mod sourceloc;
 mod trapcode;
+mod valuelabel;
 mod valueloc;
 
 pub use ir::builder::{InstBuilder, InstBuilderBase, InstInserterBase, InsertBuilder};
 pub use ir::dfg::{DataFlowGraph, ValueDef};
-pub use ir::entities::{Ebb, Inst, Value, StackSlot, GlobalVar, JumpTable, FuncRef, SigRef, Heap};
+pub use ir::entities::{Ebb, Inst, Value, StackSlot, GlobalVar, JumpTable, FuncRef, SigRef, Heap,
+                       ValueLabel};
 pub use ir::extfunc::{Signature, CallConv, AbiParam, ArgumentExtension, ArgumentPurpose,
                       ExtFuncData};
 pub use ir::extname::ExternalName;
@@ -41,6 +43,7 @@ pub use ir::sourceloc::SourceLoc;
 pub use ir::stackslot::{StackSlots, StackSlotKind, StackSlotData};
 pub use ir::trapcode::TrapCode;
 pub use ir::types::Type;
+pub use ir::valuelabel::{ValueLabelData, ValueLabelList, ValueLabelListPool};
 pub use ir::valueloc::{ValueLoc, ArgumentLoc};
 
 use binemit;
@@ -61,3 +64,6 @@ pub type EbbOffsets = EntityMap<Ebb, binemit::CodeOffset>;
 
 /// Source locations for instructions.
 pub type SourceLocs = EntityMap<Inst, SourceLoc>;
+
+/// Value labels attached to values.
+pub type ValueLabelAssignments = EntityMap<Value, ValueLabelList>;
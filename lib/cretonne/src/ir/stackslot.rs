@@ -112,6 +112,11 @@ pub struct StackSlotData {
     /// For `OutgoingArg` stack slots, the offset is relative to the current function's stack
     /// pointer immediately before the call.
     pub offset: Option<StackOffset>,
+
+    /// Required alignment of the stack slot in bytes, a power of two.
+    ///
+    /// When this is `None`, the slot is aligned according to its size.
+    pub align: Option<StackSize>,
 }
 
 impl StackSlotData {
@@ -121,23 +126,33 @@ impl StackSlotData {
             kind,
             size,
             offset: None,
+            align: None,
         }
     }
 
     /// Get the alignment in bytes of this stack slot given the stack pointer alignment.
+    ///
+    /// This is at least the required alignment, unless that is larger than `max_align`.
     pub fn alignment(&self, max_align: StackSize) -> StackSize {
         debug_assert!(max_align.is_power_of_two());
         // We want to find the largest power of two that divides both `self.size` and `max_align`.
         // That is the same as isolating the rightmost bit in `x`.
         let x = self.size | max_align;
         // C.f. Hacker's delight.
-        x & x.wrapping_neg()
+        let natural = x & x.wrapping_neg();
+        match self.align {
+            Some(align) => cmp::max(natural, cmp::min(align, max_align)),
+            None => natural,
+        }
     }
 }
 
 impl fmt::Display for StackSlotData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{} {}", self.kind, self.size)?;
+        if let Some(align) = self.align {
+            write!(f, ", align({})", align)?;
+        }
         if let Some(offset) = self.offset {
             write!(f, ", offset {}", offset)?;
         }
@@ -372,6 +387,13 @@ mod tests {
         assert_eq!(slot2.alignment(8), 8);
         assert_eq!(slot2.alignment(16), 8);
         assert_eq!(slot2.alignment(32), 8);
+
+        let mut slot3 = StackSlotData::new(StackSlotKind::ExplicitSlot, 12);
+        slot3.align = Some(8);
+
+        assert_eq!(slot3.alignment(4), 4);
+        assert_eq!(slot3.alignment(16), 8);
+        assert_eq!(slot3.to_string(), "explicit_slot 12, align(8)");
     }
 
     #[test]
@@ -0,0 +1,37 @@
+//! Value labels.
+//!
+//! Frontends can declare value labels for the source-level variables of a function and attach them
+//! to the SSA values that hold the variables. Cretonne doesn't interpret the labels, but it keeps
+//! them attached through compilation so the locations of the variables in the generated code can
+//! be reported as debug information. See the `value_label` module.
+
+use entity;
+use ir::{SourceLoc, ValueLabel};
+
+/// The value labels attached to a single value.
+///
+/// Most values carry no labels, and the rest usually carry one, so the lists are allocated from
+/// `Function::value_label_lists` rather than the heap, keeping the per-value overhead at 4 bytes.
+pub type ValueLabelList = entity::EntityList<ValueLabel>;
+
+/// Memory pool for holding value label lists. See `ValueLabelList`.
+pub type ValueLabelListPool = entity::ListPool<ValueLabel>;
+
+/// Contents of a value label.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValueLabelData {
+    /// The name of the source-level variable.
+    pub name: String,
+    /// The source location where the variable is declared.
+    pub srcloc: SourceLoc,
+}
+
+impl ValueLabelData {
+    /// Create a value label for the variable `name` declared at `srcloc`.
+    pub fn new(name: &str, srcloc: SourceLoc) -> Self {
+        Self {
+            name: name.to_string(),
+            srcloc,
+        }
+    }
+}
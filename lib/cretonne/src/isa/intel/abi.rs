@@ -169,7 +169,7 @@ pub fn callee_saved_registers(flags: &shared_settings::Flags) -> &'static [RU] {
     }
 }
 
-pub fn prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> result::CtonResult {
+pub fn prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> result::CodegenResult {
     match func.signature.call_conv {
         ir::CallConv::Native => native_prologue_epilogue(func, isa),
         ir::CallConv::SpiderWASM => spiderwasm_prologue_epilogue(func, isa),
@@ -179,7 +179,7 @@ pub fn prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> result::Ct
 pub fn spiderwasm_prologue_epilogue(
     func: &mut ir::Function,
     isa: &TargetIsa,
-) -> result::CtonResult {
+) -> result::CodegenResult {
     // Spiderwasm on 32-bit x86 always aligns its stack pointer to 16 bytes.
     let stack_align = 16;
     let word_size = if isa.flags().is_64bit() { 8 } else { 4 };
@@ -194,7 +194,7 @@ pub fn spiderwasm_prologue_epilogue(
 }
 
 /// Insert a System V-compatible prologue and epilogue.
-pub fn native_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> result::CtonResult {
+pub fn native_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> result::CodegenResult {
     // The original 32-bit x86 ELF ABI had a 4-byte aligned stack pointer, but
     // newer versions use a 16-byte aligned stack pointer.
     let stack_align = 16;
@@ -218,6 +218,7 @@ pub fn native_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> res
         kind: ir::StackSlotKind::IncomingArg,
         size: csr_stack_size as u32,
         offset: Some(-csr_stack_size),
+        align: None,
     });
 
     let total_stack_size = layout_stack(&mut func.stack_slots, stack_align)? as i32;
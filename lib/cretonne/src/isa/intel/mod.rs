@@ -112,7 +112,7 @@ impl TargetIsa for Isa {
         emit_function(func, binemit::emit_inst, sink)
     }
 
-    fn prologue_epilogue(&self, func: &mut ir::Function) -> result::CtonResult {
+    fn prologue_epilogue(&self, func: &mut ir::Function) -> result::CodegenResult {
         let _tt = timing::prologue_epilogue();
         abi::prologue_epilogue(func, self)
     }
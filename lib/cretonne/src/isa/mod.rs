@@ -150,7 +150,9 @@ pub type Legalize = fn(ir::Inst,
 
 /// Methods that are specialized to a target ISA. Implies a Display trait that shows the
 /// shared flags, as well as any isa-specific flags.
-pub trait TargetIsa: fmt::Display {
+///
+/// ISA instances are immutable, so they can be shared between compilation threads.
+pub trait TargetIsa: fmt::Display + Send + Sync {
     /// Get the name of this ISA.
     fn name(&self) -> &'static str;
 
@@ -239,7 +241,7 @@ pub trait TargetIsa: fmt::Display {
     /// Compute the stack layout and insert prologue and epilogue code into `func`.
     ///
     /// Return an error if the stack frame is too large.
-    fn prologue_epilogue(&self, func: &mut ir::Function) -> result::CtonResult {
+    fn prologue_epilogue(&self, func: &mut ir::Function) -> result::CodegenResult {
         let _tt = timing::prologue_epilogue();
         // This default implementation is unlikely to be good enough.
         use stack_layout::layout_stack;
@@ -0,0 +1,263 @@
+//! JSON export of Cretonne IL.
+//!
+//! The JSON representation of a function is intended for visualization and analysis tools written
+//! in other languages, so they can consume Cretonne IL without parsing the text format. It is an
+//! export format only; there is no way to read it back into an `ir::Function`.
+//!
+//! A function is written as a JSON object with these members:
+//!
+//! - `name` and `signature`: The function name and signature, as in the text format.
+//! - `stack_slots`, `global_vars`, `heaps`, `signatures`, `functions`, and `jump_tables`: The
+//!   entities declared in the function preamble. Each entity is an object with its `name` and its
+//!   declaration as `text`.
+//! - `ebbs`: The EBBs in layout order. Each EBB is an object with its `name`, its `params` values,
+//!   and its `insts`.
+//!
+//! Values are objects with a `name`, a `type`, and a `location`. Instructions are objects with
+//! these members:
+//!
+//! - `name`: The instruction entity, like `inst12`.
+//! - `opcode`: The opcode name.
+//! - `args`: The value arguments, with aliases resolved.
+//! - `results`: The result values.
+//! - `destinations`: The EBB or jump table a branch goes to.
+//! - `encoding`: The encoding recipe and bits, like `Op1rr#01`.
+//! - `srcloc`: The source location as a number.
+//! - `text`: The whole instruction in the text format, including any immediate operands.
+//!
+//! Missing information is `null`, for example the location of a value that hasn't been assigned
+//! one, or the encoding when no ISA is given.
+
+use ir::instructions::BranchInfo;
+use ir::{Ebb, Function, Inst, Value, ValueLoc};
+use isa::{RegInfo, TargetIsa};
+use std::fmt::{Display, Result, Write};
+
+/// Write `func` to `w` as a JSON object.
+///
+/// The locations and encodings are only written when `isa` is given.
+pub fn write_function(w: &mut Write, func: &Function, isa: Option<&TargetIsa>) -> Result {
+    let regs = isa.map(TargetIsa::register_info);
+    let regs = regs.as_ref();
+
+    w.write_str("{\"name\":")?;
+    write_string(w, &func.name)?;
+    w.write_str(",\"signature\":")?;
+    write_string(w, &func.signature.display(regs))?;
+
+    write_entities(w, "stack_slots", func.stack_slots.keys().map(|ss| {
+        (ss, &func.stack_slots[ss])
+    }))?;
+    write_entities(w, "global_vars", func.global_vars.keys().map(|gv| {
+        (gv, &func.global_vars[gv])
+    }))?;
+    write_entities(w, "heaps", func.heaps.keys().map(|heap| (heap, &func.heaps[heap])))?;
+    write_entities(w, "signatures", func.dfg.signatures.keys().map(|sig| {
+        (sig, func.dfg.signatures[sig].display(regs))
+    }))?;
+    write_entities(w, "functions", func.dfg.ext_funcs.keys().map(|fn_| {
+        (fn_, &func.dfg.ext_funcs[fn_])
+    }))?;
+    write_entities(w, "jump_tables", func.jump_tables.keys().map(|jt| {
+        (jt, &func.jump_tables[jt])
+    }))?;
+
+    w.write_str(",\"ebbs\":[")?;
+    for (idx, ebb) in func.layout.ebbs().enumerate() {
+        if idx != 0 {
+            w.write_char(',')?;
+        }
+        write_ebb(w, func, isa, regs, ebb)?;
+    }
+    w.write_str("]}")
+}
+
+/// Write `s` as a JSON string, escaping the characters that need it.
+fn write_string<T: Display + ?Sized>(w: &mut Write, s: &T) -> Result {
+    w.write_char('"')?;
+    for c in s.to_string().chars() {
+        match c {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\t' => w.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char('"')
+}
+
+/// Write a `,"key":[...]` member listing preamble entities and their declarations.
+fn write_entities<I, K, V>(w: &mut Write, key: &str, entities: I) -> Result
+where
+    I: Iterator<Item = (K, V)>,
+    K: Display,
+    V: Display,
+{
+    write!(w, ",\"{}\":[", key)?;
+    for (idx, (entity, data)) in entities.enumerate() {
+        if idx != 0 {
+            w.write_char(',')?;
+        }
+        w.write_str("{\"name\":")?;
+        write_string(w, &entity)?;
+        w.write_str(",\"text\":")?;
+        write_string(w, &data)?;
+        w.write_char('}')?;
+    }
+    w.write_char(']')
+}
+
+/// Write a list of values.
+fn write_values(
+    w: &mut Write,
+    func: &Function,
+    regs: Option<&RegInfo>,
+    values: &[Value],
+) -> Result {
+    w.write_char('[')?;
+    for (idx, &value) in values.iter().enumerate() {
+        if idx != 0 {
+            w.write_char(',')?;
+        }
+        let value = func.dfg.resolve_aliases(value);
+        w.write_str("{\"name\":")?;
+        write_string(w, &value)?;
+        w.write_str(",\"type\":")?;
+        write_string(w, &func.dfg.value_type(value))?;
+        w.write_str(",\"location\":")?;
+        match func.locations.get(value).cloned().unwrap_or_default() {
+            ValueLoc::Unassigned => w.write_str("null")?,
+            loc => write_string(w, &loc.display(regs))?,
+        }
+        w.write_char('}')?;
+    }
+    w.write_char(']')
+}
+
+fn write_ebb(
+    w: &mut Write,
+    func: &Function,
+    isa: Option<&TargetIsa>,
+    regs: Option<&RegInfo>,
+    ebb: Ebb,
+) -> Result {
+    w.write_str("{\"name\":")?;
+    write_string(w, &ebb)?;
+    w.write_str(",\"params\":")?;
+    write_values(w, func, regs, func.dfg.ebb_params(ebb))?;
+    w.write_str(",\"insts\":[")?;
+    for (idx, inst) in func.layout.ebb_insts(ebb).enumerate() {
+        if idx != 0 {
+            w.write_char(',')?;
+        }
+        write_instruction(w, func, isa, regs, inst)?;
+    }
+    w.write_str("]}")
+}
+
+fn write_instruction(
+    w: &mut Write,
+    func: &Function,
+    isa: Option<&TargetIsa>,
+    regs: Option<&RegInfo>,
+    inst: Inst,
+) -> Result {
+    let data = &func.dfg[inst];
+    w.write_str("{\"name\":")?;
+    write_string(w, &inst)?;
+    w.write_str(",\"opcode\":")?;
+    write_string(w, &data.opcode())?;
+    w.write_str(",\"args\":")?;
+    write_values(w, func, regs, func.dfg.inst_args(inst))?;
+    w.write_str(",\"results\":")?;
+    write_values(w, func, regs, func.dfg.inst_results(inst))?;
+
+    w.write_str(",\"destinations\":[")?;
+    match data.analyze_branch(&func.dfg.value_lists) {
+        BranchInfo::SingleDest(dest, _) => write_string(w, &dest)?,
+        BranchInfo::Table(table) => write_string(w, &table)?,
+        BranchInfo::NotABranch => {}
+    }
+
+    w.write_str("],\"encoding\":")?;
+    let enc = func.encodings.get(inst).cloned().unwrap_or_default();
+    match isa {
+        Some(isa) if enc.is_legal() => write_string(w, &isa.encoding_info().display(enc))?,
+        _ => w.write_str("null")?,
+    }
+
+    w.write_str(",\"srcloc\":")?;
+    let srcloc = func.srclocs.get(inst).cloned().unwrap_or_default();
+    if srcloc.is_default() {
+        w.write_str("null")?;
+    } else {
+        write!(w, "{}", srcloc.bits())?;
+    }
+
+    w.write_str(",\"text\":")?;
+    write_string(w, &func.dfg.display_inst(inst, isa))?;
+    w.write_char('}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{ExternalName, InstBuilder, StackSlotData, StackSlotKind};
+    use ir::types;
+
+    #[test]
+    fn strings() {
+        let mut s = String::new();
+        write_string(&mut s, "a \"b\"\\\n\u{1}").unwrap();
+        assert_eq!(s, "\"a \\\"b\\\"\\\\\\n\\u0001\"");
+    }
+
+    #[test]
+    fn function() {
+        let mut func = Function::new();
+        func.name = ExternalName::testcase("foo");
+        func.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 4));
+        {
+            let ebb0 = func.dfg.make_ebb();
+            let ebb1 = func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let arg = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            pos.ins().brz(arg, ebb1, &[]);
+            pos.insert_ebb(ebb1);
+            let sum = pos.ins().iadd_imm(arg, 1);
+            pos.ins().return_(&[sum]);
+        }
+
+        let mut s = String::new();
+        write_function(&mut s, &func, None).unwrap();
+        assert_eq!(
+            s,
+            concat!(
+                "{\"name\":\"%foo\",\"signature\":\"() native\",",
+                "\"stack_slots\":[{\"name\":\"ss0\",\"text\":\"explicit_slot 4\"}],",
+                "\"global_vars\":[],\"heaps\":[],\"signatures\":[],\"functions\":[],",
+                "\"jump_tables\":[],\"ebbs\":[",
+                "{\"name\":\"ebb0\",\"params\":[",
+                "{\"name\":\"v0\",\"type\":\"i32\",\"location\":null}],\"insts\":[",
+                "{\"name\":\"inst0\",\"opcode\":\"brz\",\"args\":[",
+                "{\"name\":\"v0\",\"type\":\"i32\",\"location\":null}],\"results\":[],",
+                "\"destinations\":[\"ebb1\"],\"encoding\":null,\"srcloc\":null,",
+                "\"text\":\"brz.i32 v0, ebb1\"}]},",
+                "{\"name\":\"ebb1\",\"params\":[],\"insts\":[",
+                "{\"name\":\"inst1\",\"opcode\":\"iadd_imm\",\"args\":[",
+                "{\"name\":\"v0\",\"type\":\"i32\",\"location\":null}],\"results\":[",
+                "{\"name\":\"v1\",\"type\":\"i32\",\"location\":null}],",
+                "\"destinations\":[],\"encoding\":null,\"srcloc\":null,",
+                "\"text\":\"v1 = iadd_imm.i32 v0, 1\"},",
+                "{\"name\":\"inst2\",\"opcode\":\"return\",\"args\":[",
+                "{\"name\":\"v1\",\"type\":\"i32\",\"location\":null}],\"results\":[],",
+                "\"destinations\":[],\"encoding\":null,\"srcloc\":null,",
+                "\"text\":\"return v1\"}]}]}"
+            )
+        );
+    }
+}
@@ -1,13 +1,27 @@
 //! Legalization of heaps.
 //!
 //! This module exports the `expand_heap_addr` function which transforms a `heap_addr`
-//! instruction into code that depends on the kind of heap referenced.
+//! instruction into code that depends on the kind of heap referenced, and the `check_heap_addr`
+//! function which rejects the kinds of heaps that can't be expanded yet.
 
 use cursor::{Cursor, FuncCursor};
 use flowgraph::ControlFlowGraph;
 use ir::{self, InstBuilder, MemFlags};
 use ir::condcodes::IntCC;
 use isa::TargetIsa;
+use result::{CodegenError, CodegenResult};
+
+/// Check that the `heap_addr` instruction `inst` can be expanded.
+pub fn check_heap_addr(inst: ir::Inst, func: &ir::Function) -> CodegenResult {
+    if let ir::InstructionData::HeapAddr { heap, .. } = func.dfg[inst] {
+        if let ir::HeapBase::ReservedReg = func.heaps[heap].base {
+            return Err(CodegenError::Unsupported(
+                format!("{} is based in a reserved register", heap),
+            ));
+        }
+    }
+    Ok(())
+}
 
 /// Expand a `heap_addr` instruction according to the definition of the heap.
 pub fn expand_heap_addr(
@@ -172,7 +186,7 @@ fn offset_addr(
 
     // Add the heap base address base
     match pos.func.heaps[heap].base {
-        ir::HeapBase::ReservedReg => unimplemented!(),
+        ir::HeapBase::ReservedReg => panic!("{} was not rejected by check_heap_addr", heap),
         ir::HeapBase::GlobalVar(base_gv) => {
             let base_addr = pos.ins().global_addr(addr_ty, base_gv);
             let base = pos.ins().load(addr_ty, MemFlags::new(), base_addr, 0);
@@ -2,9 +2,10 @@
 
 use ir;
 use ir::InstBuilder;
+use isa::TargetIsa;
 
 /// Try to expand `inst` as a library call, returning true is successful.
-pub fn expand_as_libcall(inst: ir::Inst, func: &mut ir::Function) -> bool {
+pub fn expand_as_libcall(inst: ir::Inst, func: &mut ir::Function, isa: &TargetIsa) -> bool {
     // Does the opcode/ctrl_type combo even have a well-known runtime library name.
     let libcall =
         match ir::LibCall::for_inst(func.dfg[inst].opcode(), func.dfg.ctrl_typevar(inst)) {
@@ -12,7 +13,8 @@ pub fn expand_as_libcall(inst: ir::Inst, func: &mut ir::Function) -> bool {
             None => return false,
         };
 
-    let funcref = find_funcref(libcall, func).unwrap_or_else(|| make_funcref(libcall, inst, func));
+    let funcref = find_funcref(libcall, func)
+        .unwrap_or_else(|| make_funcref(libcall, inst, func, isa));
 
     // Now we convert `inst` to a call. First save the arguments.
     let mut args = Vec::new();
@@ -20,8 +22,6 @@ pub fn expand_as_libcall(inst: ir::Inst, func: &mut ir::Function) -> bool {
     // The replace builder will preserve the instruction result values.
     func.dfg.replace(inst).call(funcref, &args);
 
-    // TODO: ask the ISA to legalize the signature.
-
     true
 }
 
@@ -43,8 +43,16 @@ fn find_funcref(libcall: ir::LibCall, func: &ir::Function) -> Option<ir::FuncRef
 }
 
 /// Create a funcref for `libcall` with a signature matching `inst`.
-fn make_funcref(libcall: ir::LibCall, inst: ir::Inst, func: &mut ir::Function) -> ir::FuncRef {
-    // Start with a native calling convention. We'll give the ISA a chance to change it.
+///
+/// The signatures were legalized before the instructions, so the new signature is legalized here.
+/// Without argument locations, the call's arguments and results can't be assigned registers.
+fn make_funcref(
+    libcall: ir::LibCall,
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    isa: &TargetIsa,
+) -> ir::FuncRef {
+    // Start with a native calling convention. The ISA may change it when legalizing.
     let mut sig = ir::Signature::new(ir::CallConv::Native);
     for &v in func.dfg.inst_args(inst) {
         sig.params.push(ir::AbiParam::new(func.dfg.value_type(v)));
@@ -52,6 +60,8 @@ fn make_funcref(libcall: ir::LibCall, inst: ir::Inst, func: &mut ir::Function) -
     for &v in func.dfg.inst_results(inst) {
         sig.returns.push(ir::AbiParam::new(func.dfg.value_type(v)));
     }
+    isa.legalize_signature(&mut sig, false);
+    sig.compute_argument_bytes();
     let sigref = func.import_signature(sig);
 
     func.import_function(ir::ExtFuncData {
@@ -18,6 +18,8 @@ use flowgraph::ControlFlowGraph;
 use ir::{self, InstBuilder};
 use isa::TargetIsa;
 use bitset::BitSet;
+use entity::EntitySet;
+use result::{CodegenError, CodegenResult};
 use timing;
 
 mod boundary;
@@ -27,7 +29,7 @@ mod libcall;
 mod split;
 
 use self::globalvar::expand_global_addr;
-use self::heap::expand_heap_addr;
+use self::heap::{check_heap_addr, expand_heap_addr};
 use self::libcall::expand_as_libcall;
 
 /// Legalize `func` for `isa`.
@@ -35,9 +37,18 @@ use self::libcall::expand_as_libcall;
 /// - Transform any instructions that don't have a legal representation in `isa`.
 /// - Fill out `func.encodings`.
 ///
-pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, isa: &TargetIsa) {
+/// Instructions that can't be legalized are left without an encoding. Use `check_encodings()` to
+/// find out if the function can be compiled.
+///
+/// Fails with `CodegenError::Unsupported` when the function uses a feature the legalizer doesn't
+/// implement. The function is left partially legalized in that case.
+pub fn legalize_function(
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    isa: &TargetIsa,
+) -> CodegenResult {
     let _tt = timing::legalize();
-    debug_assert!(cfg.is_valid());
+    cfg.assert_current(func);
 
     boundary::legalize_signatures(func, isa);
 
@@ -72,6 +83,10 @@ pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, is
                 split::simplify_branch_arguments(&mut pos.func.dfg, inst);
             }
 
+            if opcode == ir::Opcode::HeapAddr {
+                check_heap_addr(inst, pos.func)?;
+            }
+
             match isa.encode(
                 &pos.func.dfg,
                 &pos.func.dfg[inst],
@@ -93,7 +108,7 @@ pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, is
 
                     // We don't have any pattern expansion for this instruction either.
                     // Try converting it to a library call as a last resort.
-                    if expand_as_libcall(inst, pos.func) {
+                    if expand_as_libcall(inst, pos.func, isa) {
                         pos.set_position(prev_pos);
                         continue;
                     }
@@ -104,6 +119,55 @@ pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, is
             prev_pos = pos.position();
         }
     }
+    Ok(())
+}
+
+/// Check that code can be generated for the legalized `func`.
+///
+/// Dead instructions without side effects don't need an encoding since they don't generate any
+/// code, and the legalizer leaves some of them behind. Any other instruction without an encoding
+/// is one that the legalizer couldn't handle, which is reported as `CodegenError::Unencodable`.
+pub fn check_encodings(func: &ir::Function, isa: &TargetIsa) -> CodegenResult {
+    // Values used by the instructions that generate code.
+    let mut live = EntitySet::new();
+    live.resize(func.dfg.num_values());
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if func.encodings[inst].is_legal() {
+                for &arg in func.dfg.inst_args(inst) {
+                    live.insert(func.dfg.resolve_aliases(arg));
+                }
+            }
+        }
+    }
+
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if func.encodings[inst].is_legal() {
+                continue;
+            }
+            let opcode = func.dfg[inst].opcode();
+            let needs_encoding = if opcode == ir::Opcode::Fallthrough {
+                false
+            } else {
+                opcode.is_branch() || opcode.is_call() || opcode.is_return() ||
+                    opcode.can_store() || opcode.can_trap() ||
+                    opcode.other_side_effects() ||
+                    func.dfg.inst_results(inst).iter().any(|&v| live.contains(v))
+            };
+            if needs_encoding {
+                return Err(CodegenError::Unencodable {
+                    inst,
+                    message: format!(
+                        "no {} encoding for {}",
+                        isa.name(),
+                        func.dfg.display_inst(inst, isa)
+                    ),
+                });
+            }
+        }
+    }
+    Ok(())
 }
 
 // Include legalization patterns that were generated by `gen_legalizer.py` from the `XForms` in
@@ -303,3 +367,86 @@ pub fn expand_stack_check(
         ir::TrapCode::StackOverflow,
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use Context;
+    use cursor::{Cursor, FuncCursor};
+    use ir::immediates::Ieee64;
+    use ir::{AbiParam, CallConv, ExternalName, Function, HeapBase, HeapData, HeapStyle,
+             InstBuilder, Signature, types};
+    use isa;
+    use result::CodegenError;
+    use settings::{self, Configurable};
+
+    /// Get an Intel ISA without the verifier, which would otherwise catch some of the errors
+    /// first.
+    fn intel(is_64bit: bool) -> Box<isa::TargetIsa> {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("enable_verifier", "false").unwrap();
+        if is_64bit {
+            flag_builder.enable("is_64bit").unwrap();
+        }
+        isa::lookup("intel").unwrap().finish(
+            settings::Flags::new(&flag_builder),
+        )
+    }
+
+    #[test]
+    fn unencodable() {
+        // 32-bit Intel has no way of materializing an `f64` constant yet.
+        let isa = intel(false);
+        let mut sig = Signature::new(CallConv::Native);
+        sig.returns.push(AbiParam::new(types::F64));
+        let mut ctx = Context::for_function(
+            Function::with_name_signature(ExternalName::testcase("f"), sig),
+        );
+        {
+            let ebb = ctx.func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut ctx.func);
+            pos.insert_ebb(ebb);
+            let v = pos.ins().f64const(Ieee64::pow2(1));
+            pos.ins().return_(&[v]);
+        }
+
+        match ctx.compile(&*isa) {
+            Err(CodegenError::Unencodable { inst, ref message }) => {
+                assert!(!ctx.func.encodings[inst].is_legal());
+                assert!(message.starts_with("no intel encoding for "), "{}", message);
+            }
+            result => panic!("Unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reserved_reg_heap() {
+        let isa = intel(true);
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.returns.push(AbiParam::new(types::I64));
+        let mut ctx = Context::for_function(
+            Function::with_name_signature(ExternalName::testcase("f"), sig),
+        );
+        let heap = ctx.func.create_heap(HeapData {
+            base: HeapBase::ReservedReg,
+            min_size: 0.into(),
+            guard_size: 0x8000_0000.into(),
+            style: HeapStyle::Static { bound: 0x1_0000_0000.into() },
+        });
+        {
+            let ebb = ctx.func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut ctx.func);
+            pos.insert_ebb(ebb);
+            let arg = pos.func.dfg.append_ebb_param(ebb, types::I32);
+            let addr = pos.ins().heap_addr(types::I64, heap, arg, 4);
+            pos.ins().return_(&[addr]);
+        }
+
+        assert_eq!(
+            ctx.compile(&*isa),
+            Err(CodegenError::Unsupported(
+                String::from("heap0 is based in a reserved register"),
+            ))
+        );
+    }
+}
@@ -4,37 +4,50 @@
         trivial_numeric_casts,
         unused_extern_crates)]
 
+#[macro_use]
+extern crate log;
+
 pub use context::Context;
-pub use legalizer::legalize_function;
+pub use legalizer::{check_encodings, legalize_function};
 pub use verifier::verify_function;
-pub use write::write_function;
+pub use write::{write_function, write_function_stable, write_function_with_options,
+                write_function_annotated, Annotator, WriteOptions};
 
 /// Version number of the cretonne crate.
 pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-#[macro_use]
 pub mod dbg;
 #[macro_use]
 pub mod entity;
 
+pub mod batch;
 pub mod bforest;
 pub mod binemit;
+pub mod cache;
 pub mod cfg_printer;
 pub mod cursor;
+pub mod diff;
 pub mod dominator_tree;
 pub mod flowgraph;
 pub mod ir;
 pub mod isa;
+pub mod json;
 pub mod loop_analysis;
+pub mod observer;
 pub mod packed_option;
 pub mod print_errors;
 pub mod result;
+pub mod serialize;
 pub mod settings;
+pub mod source_map;
+pub mod stats;
 pub mod timing;
+pub mod value_label;
 pub mod verifier;
 
 mod abi;
 mod bitset;
+mod bytestream;
 mod constant_hash;
 mod context;
 mod divconst_magic_numbers;
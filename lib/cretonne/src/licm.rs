@@ -19,9 +19,9 @@ pub fn do_licm(
     loop_analysis: &mut LoopAnalysis,
 ) {
     let _tt = timing::licm();
-    debug_assert!(cfg.is_valid());
-    debug_assert!(domtree.is_valid());
-    debug_assert!(loop_analysis.is_valid());
+    cfg.assert_current(func);
+    domtree.assert_current(func);
+    loop_analysis.assert_current(func);
 
     for lp in loop_analysis.loops() {
         // For each loop that we want to optimize we determine the set of loop-invariant
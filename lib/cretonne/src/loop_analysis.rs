@@ -21,7 +21,8 @@ entity_impl!(Loop, "loop");
 pub struct LoopAnalysis {
     loops: PrimaryMap<Loop, LoopData>,
     ebb_loop_map: EntityMap<Ebb, PackedOption<Loop>>,
-    valid: bool,
+    // The layout generation the analysis is up to date with, or `None` if it hasn't been computed.
+    generation: Option<u64>,
 }
 
 struct LoopData {
@@ -45,7 +46,7 @@ impl LoopAnalysis {
     /// a function.
     pub fn new() -> Self {
         Self {
-            valid: false,
+            generation: None,
             loops: PrimaryMap::new(),
             ebb_loop_map: EntityMap::new(),
         }
@@ -69,6 +70,11 @@ impl LoopAnalysis {
         self.loops[lp].parent.expand()
     }
 
+    /// Get the innermost loop containing `ebb`, if any.
+    pub fn innermost_loop(&self, ebb: Ebb) -> Option<Loop> {
+        self.ebb_loop_map[ebb].expand()
+    }
+
     /// Determine if an Ebb belongs to a loop by running a finger along the loop tree.
     ///
     /// Returns `true` if `ebb` is in loop `lp`.
@@ -100,12 +106,14 @@ impl LoopAnalysis {
     /// Detects the loops in a function. Needs the control flow graph and the dominator tree.
     pub fn compute(&mut self, func: &Function, cfg: &ControlFlowGraph, domtree: &DominatorTree) {
         let _tt = timing::loop_analysis();
+        cfg.assert_current(func);
+        domtree.assert_current(func);
         self.loops.clear();
         self.ebb_loop_map.clear();
         self.ebb_loop_map.resize(func.dfg.num_ebbs());
         self.find_loop_headers(cfg, domtree, &func.layout);
         self.discover_loop_blocks(cfg, domtree, &func.layout);
-        self.valid = true;
+        self.generation = Some(func.layout.generation());
     }
 
     /// Check if the loop analysis is in a valid state.
@@ -114,7 +122,20 @@ impl LoopAnalysis {
     /// `compute()` method has been called since the last `clear()`. It does not check that the
     /// loop analysis is consistent with the CFG.
     pub fn is_valid(&self) -> bool {
-        self.valid
+        self.generation.is_some()
+    }
+
+    /// Assert that the loop analysis has been computed for the current EBB layout of `func`.
+    ///
+    /// See `ControlFlowGraph::assert_current()`.
+    pub fn assert_current(&self, func: &Function) {
+        debug_assert!(self.is_valid(), "the loop analysis hasn't been computed");
+        debug_assert!(
+            self.generation == Some(func.layout.generation()),
+            "the loop analysis is stale: EBBs were inserted, removed, or split in {} since it \
+             was computed",
+            func.name
+        );
     }
 
     /// Clear all the data structures contanted in the loop analysis. This will leave the
@@ -123,7 +144,7 @@ impl LoopAnalysis {
     pub fn clear(&mut self) {
         self.loops.clear();
         self.ebb_loop_map.clear();
-        self.valid = false;
+        self.generation = None;
     }
 
     // Traverses the CFG in reverse postorder and create a loop object for every EBB having a
@@ -0,0 +1,262 @@
+//! Compilation events.
+//!
+//! Embedders that want to follow the progress of the compiler, for example to feed it into their
+//! own telemetry, can install a `CompileObserver` in a `Context` with `Context::set_observer`.
+//! `Context::compile` then reports the start and the end of every function and of every pass it
+//! runs, along with the size of the compiled code and the number of spills, as structured
+//! `Event`s. The WebAssembly translator reports the function bodies of a module in the same way.
+//!
+//! Events are delivered synchronously on the compiling thread, so an observer should be cheap.
+
+use binemit::CodeOffset;
+use ir::{ExternalName, Function, Opcode};
+use result::CodegenError;
+use std::fmt;
+use std::time::Duration;
+
+/// A pass run by `Context::compile` or by the WebAssembly translator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    /// Verification of the input function.
+    Verifier,
+    /// Computing the control flow graph.
+    Flowgraph,
+    /// Pre-legalization rewriting.
+    Preopt,
+    /// Legalization.
+    Legalize,
+    /// Computing the dominator tree.
+    Domtree,
+    /// Loop analysis.
+    LoopAnalysis,
+    /// Loop invariant code motion.
+    Licm,
+    /// Global value numbering.
+    SimpleGvn,
+    /// Removal of unreachable blocks.
+    UnreachableCode,
+    /// Register allocation.
+    Regalloc,
+    /// Prologue and epilogue insertion.
+    PrologueEpilogue,
+    /// Branch relaxation, which also computes the code size.
+    RelaxBranches,
+    /// Translation of a WebAssembly module.
+    WasmTranslateModule,
+}
+
+impl Pass {
+    /// Get the name of this pass, as used in reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Verifier => "verifier",
+            Pass::Flowgraph => "flowgraph",
+            Pass::Preopt => "preopt",
+            Pass::Legalize => "legalize",
+            Pass::Domtree => "domtree",
+            Pass::LoopAnalysis => "loop_analysis",
+            Pass::Licm => "licm",
+            Pass::SimpleGvn => "simple_gvn",
+            Pass::UnreachableCode => "unreachable_code",
+            Pass::Regalloc => "regalloc",
+            Pass::PrologueEpilogue => "prologue_epilogue",
+            Pass::RelaxBranches => "relax_branches",
+            Pass::WasmTranslateModule => "wasm_translate_module",
+        }
+    }
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The size of a function.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FunctionSize {
+    /// Number of EBBs in the layout.
+    pub ebbs: usize,
+    /// Number of instructions in the layout.
+    pub insts: usize,
+    /// Number of `spill` instructions.
+    pub spills: usize,
+    /// Number of `fill` instructions.
+    pub fills: usize,
+}
+
+impl FunctionSize {
+    /// Measure `func`.
+    pub fn of(func: &Function) -> Self {
+        let mut size = Self::default();
+        for ebb in func.layout.ebbs() {
+            size.ebbs += 1;
+            for inst in func.layout.ebb_insts(ebb) {
+                size.insts += 1;
+                match func.dfg[inst].opcode() {
+                    Opcode::Spill => size.spills += 1,
+                    Opcode::Fill => size.fills += 1,
+                    _ => {}
+                }
+            }
+        }
+        size
+    }
+}
+
+/// An event reported to a `CompileObserver`.
+#[derive(Clone, Copy, Debug)]
+pub enum Event<'a> {
+    /// `Context::compile` started compiling the function `name`.
+    FunctionStarted {
+        /// The name of the function.
+        name: &'a ExternalName,
+        /// The size of the input function.
+        size: FunctionSize,
+    },
+
+    /// A pass started.
+    PassStarted(Pass),
+
+    /// A pass finished, successfully or not, after running for `duration`.
+    PassFinished {
+        /// The pass that finished.
+        pass: Pass,
+        /// The time spent in the pass, including verification.
+        duration: Duration,
+    },
+
+    /// The function was found in the compilation cache, and the remaining passes are skipped.
+    CacheHit,
+
+    /// `Context::compile` finished compiling the function `name`.
+    FunctionFinished {
+        /// The name of the function.
+        name: &'a ExternalName,
+        /// The size of the compiled function. The spills and fills are the ones inserted by the
+        /// register allocator.
+        size: FunctionSize,
+        /// The size of the machine code in bytes.
+        code_size: CodeOffset,
+        /// The time spent compiling the function.
+        duration: Duration,
+    },
+
+    /// `Context::compile` failed to compile the function `name`.
+    FunctionFailed {
+        /// The name of the function.
+        name: &'a ExternalName,
+        /// The reason.
+        error: &'a CodegenError,
+    },
+
+    /// The WebAssembly translator handed the body of a function to the module environment.
+    ///
+    /// The duration covers whatever the environment does with the body, which is usually the
+    /// translation to Cretonne IL.
+    WasmFunctionBody {
+        /// The index of the body in the code section.
+        index: usize,
+        /// The size of the body in bytes.
+        body_size: usize,
+        /// The time spent by the environment.
+        duration: Duration,
+    },
+}
+
+/// A receiver of compilation events.
+pub trait CompileObserver {
+    /// Handle `event`.
+    fn event(&mut self, event: &Event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Context;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{AbiParam, CallConv, InstBuilder, Signature, types};
+    use isa;
+    use settings;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// An observer that records the events as text.
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl CompileObserver for Recorder {
+        fn event(&mut self, event: &Event) {
+            let text = match *event {
+                Event::FunctionStarted { name, size } => format!("start {} {}", name, size.insts),
+                Event::PassStarted(pass) => format!("+{}", pass),
+                Event::PassFinished { pass, .. } => format!("-{}", pass),
+                Event::CacheHit => String::from("hit"),
+                Event::FunctionFinished { name, size, code_size, .. } => {
+                    format!("finish {} {} {}", name, size.spills, code_size)
+                }
+                Event::FunctionFailed { name, error } => format!("fail {} {}", name, error),
+                Event::WasmFunctionBody { index, .. } => format!("body {}", index),
+            };
+            self.0.borrow_mut().push(text);
+        }
+    }
+
+    #[test]
+    fn compile() {
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(
+            &settings::builder(),
+        ));
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut ctx = Context::new();
+        ctx.set_observer(Box::new(Recorder(events.clone())));
+
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.returns.push(AbiParam::new(types::I32));
+        ctx.func.name = ExternalName::testcase("f");
+        ctx.func.signature = sig;
+        let ebb = ctx.func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut ctx.func);
+            pos.insert_ebb(ebb);
+            let arg = pos.func.dfg.append_ebb_param(ebb, types::I32);
+            let sum = pos.ins().iadd_imm(arg, 1);
+            pos.ins().return_(&[sum]);
+        }
+        let code_size = ctx.compile(&*isa).unwrap();
+
+        let events = events.borrow();
+        assert_eq!(events[0], "start %f 2");
+        assert_eq!(events[1], "+verifier");
+        assert_eq!(events[2], "-verifier");
+        assert!(events.contains(&String::from("+regalloc")));
+        assert_eq!(events[events.len() - 2], "-relax_branches");
+        assert_eq!(events[events.len() - 1], format!("finish %f 0 {}", code_size));
+        assert_eq!(
+            events.iter().filter(|e| e.starts_with('+')).count(),
+            events.iter().filter(|e| e.starts_with('-')).count()
+        );
+    }
+
+    #[test]
+    fn function_size() {
+        let mut func = Function::new();
+        assert_eq!(FunctionSize::of(&func), FunctionSize::default());
+        let ebb = func.dfg.make_ebb();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb);
+        let v = pos.ins().iconst(types::I32, 1);
+        let v = pos.ins().spill(v);
+        pos.ins().fill(v);
+        pos.ins().return_(&[]);
+        assert_eq!(
+            FunctionSize::of(&func),
+            FunctionSize {
+                ebbs: 1,
+                insts: 4,
+                spills: 1,
+                fills: 1,
+            }
+        );
+    }
+}
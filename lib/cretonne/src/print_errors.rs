@@ -2,7 +2,7 @@
 
 use ir;
 use verifier;
-use result::CtonError;
+use result::CodegenError;
 use isa::TargetIsa;
 use std::fmt::Write;
 
@@ -24,10 +24,18 @@ pub fn pretty_verifier_error(
 }
 
 /// Pretty-print a Cretonne error.
-pub fn pretty_error(func: &ir::Function, isa: Option<&TargetIsa>, err: CtonError) -> String {
-    if let CtonError::Verifier(e) = err {
-        pretty_verifier_error(func, isa, e)
-    } else {
-        err.to_string()
+///
+/// Errors that refer to an instruction are followed by the function.
+pub fn pretty_error(func: &ir::Function, isa: Option<&TargetIsa>, err: CodegenError) -> String {
+    match err {
+        CodegenError::Verifier(e) => pretty_verifier_error(func, isa, e),
+        CodegenError::Unencodable { inst, .. } |
+        CodegenError::RegisterPressure { inst, .. } => {
+            let mut msg = err.to_string();
+            write!(msg, "\n{}: {}\n\n", inst, func.dfg.display_inst(inst, isa)).unwrap();
+            write!(msg, "{}", func.display(isa)).unwrap();
+            msg
+        }
+        _ => err.to_string(),
     }
 }
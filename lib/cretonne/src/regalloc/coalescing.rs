@@ -114,7 +114,8 @@ impl Coalescing {
         virtregs: &mut VirtRegs,
     ) {
         let _tt = timing::ra_cssa();
-        dbg!("Coalescing for:\n{}", func.display(isa));
+        liveness.assert_current(func);
+        debug!("Coalescing for:\n{}", func.display(isa));
         self.preorder.compute(domtree, &func.layout);
         let mut context = Context {
             isa,
@@ -179,7 +180,7 @@ impl<'a> Context<'a> {
                 continue;
             }
 
-            dbg!(
+            trace!(
                 " - checking {} params at back-edge {}: {}",
                 num_params,
                 pred_ebb,
@@ -220,7 +221,7 @@ impl<'a> Context<'a> {
                 if Some(def_ebb) == self.func.layout.entry_block() &&
                     self.func.signature.params[def_num].location.is_stack()
                 {
-                    dbg!("-> isolating function stack parameter {}", arg);
+                    trace!("-> isolating function stack parameter {}", arg);
                     let new_arg = self.isolate_arg(pred_ebb, pred_inst, argnum, arg);
                     self.virtregs.union(param, new_arg);
                     continue;
@@ -287,7 +288,7 @@ impl<'a> Context<'a> {
         let inst = pos.built_inst();
         self.liveness.move_def_locally(param, inst);
 
-        dbg!(
+        trace!(
             "-> inserted {}, following {}({}: {})",
             pos.display_inst(inst),
             ebb,
@@ -342,6 +343,7 @@ impl<'a> Context<'a> {
         pred_val: Value,
     ) -> Value {
         let mut pos = EncCursor::new(self.func, self.isa).at_inst(pred_inst);
+        pos.use_srcloc(pred_inst);
         let copy = pos.ins().copy(pred_val);
         let inst = pos.built_inst();
 
@@ -364,7 +366,7 @@ impl<'a> Context<'a> {
 
         pos.func.dfg.inst_variable_args_mut(pred_inst)[argnum] = copy;
 
-        dbg!(
+        trace!(
             "-> inserted {}, before {}: {}",
             pos.display_inst(inst),
             pred_ebb,
@@ -380,7 +382,7 @@ impl<'a> Context<'a> {
     /// closure of the relation formed by EBB parameter-argument pairs found by `union_find_ebb()`.
     fn finish_union_find(&mut self) {
         self.virtregs.finish_union_find(None);
-        dbg!("After union-find phase:{}", self.virtregs);
+        debug!("After union-find phase:{}", self.virtregs);
     }
 }
 
@@ -411,7 +413,7 @@ impl<'a> Context<'a> {
     fn check_vreg(&mut self, vreg: VirtReg) -> bool {
         // Order the values according to the dominator pre-order of their definition.
         let values = self.virtregs.sort_values(vreg, self.func, self.preorder);
-        dbg!("Checking {} = {}", vreg, DisplayList(values));
+        trace!("Checking {} = {}", vreg, DisplayList(values));
 
         // Now push the values in order to the dominator forest.
         // This gives us the closest dominating value def for each of the values.
@@ -435,7 +437,7 @@ impl<'a> Context<'a> {
             let ctx = self.liveness.context(&self.func.layout);
             if self.liveness[parent.value].overlaps_def(node.def, node.ebb, ctx) {
                 // The two values are interfering, so they can't be in the same virtual register.
-                dbg!("-> interference: {} overlaps def of {}", parent, value);
+                trace!("-> interference: {} overlaps def of {}", parent, value);
                 return false;
             }
         }
@@ -459,7 +461,7 @@ impl<'a> Context<'a> {
             self.cfg,
             self.preorder,
         );
-        dbg!(
+        trace!(
             "Synthesizing {} from {} branches and params {}",
             vreg,
             self.vcopies.branches.len(),
@@ -547,7 +549,7 @@ impl<'a> Context<'a> {
         }
 
         let vreg = self.virtregs.unify(self.values);
-        dbg!("-> merged into {} = {}", vreg, DisplayList(self.values));
+        trace!("-> merged into {} = {}", vreg, DisplayList(self.values));
         true
     }
 
@@ -573,7 +575,7 @@ impl<'a> Context<'a> {
         // registers and the filtered virtual copies.
         let v0 = self.virtregs.congruence_class(&param);
         let v1 = self.virtregs.congruence_class(&arg);
-        dbg!(
+        trace!(
             " - set 0: {}\n - set 1: {}",
             DisplayList(v0),
             DisplayList(v1)
@@ -626,7 +628,7 @@ impl<'a> Context<'a> {
                 if node.set_id != parent.set_id &&
                     self.liveness[parent.value].reaches_use(inst, node.ebb, ctx)
                 {
-                    dbg!(
+                    trace!(
                         " - interference: {} overlaps vcopy at {}:{}",
                         parent,
                         node.ebb,
@@ -651,7 +653,7 @@ impl<'a> Context<'a> {
                 self.liveness[parent.value].overlaps_def(node.def, node.ebb, ctx)
             {
                 // The two values are interfering.
-                dbg!(" - interference: {} overlaps def of {}", parent, node.value);
+                trace!(" - interference: {} overlaps def of {}", parent, node.value);
                 return false;
             }
         }
@@ -56,6 +56,7 @@ use regalloc::live_value_tracker::{LiveValue, LiveValueTracker};
 use regalloc::liveness::Liveness;
 use regalloc::liverange::{LiveRange, LiveRangeContext};
 use regalloc::solver::{Solver, SolverError};
+use result::{CodegenError, CodegenResult};
 use std::mem;
 use timing;
 
@@ -123,9 +124,10 @@ impl Coloring {
         domtree: &DominatorTree,
         liveness: &mut Liveness,
         tracker: &mut LiveValueTracker,
-    ) {
+    ) -> CodegenResult {
         let _tt = timing::ra_coloring();
-        dbg!("Coloring for:\n{}", func.display(isa));
+        liveness.assert_current(func);
+        debug!("Coloring for:\n{}", func.display(isa));
         let mut ctx = Context {
             usable_regs: isa.allocatable_registers(func),
             cur: EncCursor::new(func, isa),
@@ -142,7 +144,7 @@ impl Coloring {
 
 impl<'a> Context<'a> {
     /// Run the coloring algorithm.
-    fn run(&mut self, tracker: &mut LiveValueTracker) {
+    fn run(&mut self, tracker: &mut LiveValueTracker) -> CodegenResult {
         self.cur.func.locations.resize(
             self.cur.func.dfg.num_values(),
         );
@@ -150,13 +152,14 @@ impl<'a> Context<'a> {
         // Visit blocks in reverse post-order. We need to ensure that at least one predecessor has
         // been visited before each EBB. That guarantees that the EBB arguments have been colored.
         for &ebb in self.domtree.cfg_postorder().iter().rev() {
-            self.visit_ebb(ebb, tracker);
+            self.visit_ebb(ebb, tracker)?;
         }
+        Ok(())
     }
 
     /// Visit `ebb`, assuming that the immediate dominator has already been visited.
-    fn visit_ebb(&mut self, ebb: Ebb, tracker: &mut LiveValueTracker) {
-        dbg!("Coloring {}:", ebb);
+    fn visit_ebb(&mut self, ebb: Ebb, tracker: &mut LiveValueTracker) -> CodegenResult {
+        debug!("Coloring {}:", ebb);
         let mut regs = self.visit_ebb_header(ebb, tracker);
         tracker.drop_dead_params();
         self.divert.clear();
@@ -167,7 +170,7 @@ impl<'a> Context<'a> {
             self.cur.use_srcloc(inst);
             let enc = self.cur.func.encodings[inst];
             if let Some(constraints) = self.encinfo.operand_constraints(enc) {
-                if self.visit_inst(inst, constraints, tracker, &mut regs) {
+                if self.visit_inst(inst, constraints, tracker, &mut regs)? {
                     self.replace_global_defines(inst, tracker);
                     // Restore cursor location after `replace_global_defines` moves it.
                     // We want to revisit the copy instructions it inserted.
@@ -180,6 +183,7 @@ impl<'a> Context<'a> {
             }
             tracker.drop_dead(inst);
         }
+        Ok(())
     }
 
     /// Visit the `ebb` header.
@@ -216,7 +220,7 @@ impl<'a> Context<'a> {
         let mut regs = AvailableRegs::new(&self.usable_regs);
 
         for lv in live.iter().filter(|lv| !lv.is_dead) {
-            dbg!(
+            trace!(
                 "Live-in: {}:{} in {}",
                 lv.value,
                 lv.affinity.display(&self.reginfo),
@@ -287,15 +291,16 @@ impl<'a> Context<'a> {
     /// Update `regs` to reflect the allocated registers after `inst`, including removing any dead
     /// or killed values from the set.
     ///
-    /// Returns true when the global values defined by `inst` must be replaced by local values.
+    /// Returns true when the global values defined by `inst` must be replaced by local values, or
+    /// an error when there are not enough registers for `inst`.
     fn visit_inst(
         &mut self,
         inst: Inst,
         constraints: &RecipeConstraints,
         tracker: &mut LiveValueTracker,
         regs: &mut AvailableRegs,
-    ) -> bool {
-        dbg!(
+    ) -> Result<bool, CodegenError> {
+        trace!(
             "Coloring {}\n    from {}",
             self.cur.display_inst(inst),
             regs.input.display(&self.reginfo),
@@ -363,7 +368,7 @@ impl<'a> Context<'a> {
             if let Affinity::Reg(rci) = lv.affinity {
                 let rc = self.reginfo.rc(rci);
                 let reg = self.divert.reg(lv.value, &self.cur.func.locations);
-                dbg!(
+                trace!(
                     "    kill {} in {} ({} {})",
                     lv.value,
                     self.reginfo.display_regunit(reg),
@@ -383,7 +388,7 @@ impl<'a> Context<'a> {
         }
 
         // This aligns with the "    from" line at the top of the function.
-        dbg!("    glob {}", regs.global.display(&self.reginfo));
+        trace!("    glob {}", regs.global.display(&self.reginfo));
 
         // This flag is set when the solver failed to find a solution for the global defines that
         // doesn't interfere with `regs.global`. We need to rewrite all of `inst`s global defines
@@ -421,10 +426,18 @@ impl<'a> Context<'a> {
 
         // Finally, we've fully programmed the constraint solver.
         // We expect a quick solution in most cases.
-        let output_regs = self.solver.quick_solve(&regs.global).unwrap_or_else(|_| {
-            dbg!("quick_solve failed for {}", self.solver);
-            self.iterate_solution(throughs, &regs.global, &mut replace_global_defines)
-        });
+        let output_regs = match self.solver.quick_solve(&regs.global) {
+            Ok(output_regs) => output_regs,
+            Err(_) => {
+                trace!("quick_solve failed for {}", self.solver);
+                self.iterate_solution(
+                    inst,
+                    throughs,
+                    &regs.global,
+                    &mut replace_global_defines,
+                )?
+            }
+        };
 
 
         // The solution and/or fixed input constraints may require us to shuffle the set of live
@@ -458,7 +471,7 @@ impl<'a> Context<'a> {
         regs.input = output_regs;
         for lv in defs {
             let loc = self.cur.func.locations[lv.value];
-            dbg!(
+            trace!(
                 "    color {} -> {}{}",
                 lv.value,
                 loc.display(&self.reginfo),
@@ -489,7 +502,7 @@ impl<'a> Context<'a> {
 
         self.forget_diverted(kills);
 
-        replace_global_defines
+        Ok(replace_global_defines)
     }
 
     /// Program the input-side constraints for `inst` into the constraint solver.
@@ -710,7 +723,7 @@ impl<'a> Context<'a> {
                 ConstraintKind::FixedTied(reg) => {
                     self.add_fixed_output(lv.value, op.regclass, reg, throughs);
                     if !lv.is_local && !global_regs.is_avail(op.regclass, reg) {
-                        dbg!(
+                        trace!(
                             "Fixed output {} in {}:{} is not available in global regs",
                             lv.value,
                             op.regclass,
@@ -749,7 +762,7 @@ impl<'a> Context<'a> {
                     let rc = self.reginfo.rc(rci);
                     self.add_fixed_output(lv.value, rc, reg, throughs);
                     if !lv.is_local && !global_regs.is_avail(rc, reg) {
-                        dbg!(
+                        trace!(
                             "ABI output {} in {}:{} is not available in global regs",
                             lv.value,
                             rc,
@@ -826,7 +839,7 @@ impl<'a> Context<'a> {
                         // We need to make sure that fixed output register is compatible with the
                         // global register set.
                         if !lv.is_local && !global_regs.is_avail(op.regclass, reg) {
-                            dbg!(
+                            trace!(
                                 "Tied output {} in {}:{} is not available in global regs",
                                 lv.value,
                                 op.regclass,
@@ -843,26 +856,36 @@ impl<'a> Context<'a> {
     /// Try harder to find a solution to the constraint problem since `quick_solve()` failed.
     ///
     /// We may need to move more registers around before a solution is possible. Use an iterative
-    /// algorithm that adds one more variable until a solution can be found.
+    /// algorithm that adds one more variable until a solution can be found. Fail when there are no
+    /// more live-through values to move out of the way.
     fn iterate_solution(
         &mut self,
+        inst: Inst,
         throughs: &[LiveValue],
         global_regs: &AllocatableSet,
         replace_global_defines: &mut bool,
-    ) -> AllocatableSet {
+    ) -> Result<AllocatableSet, CodegenError> {
         // Make sure `try_add_var()` below doesn't create a variable with too loose constraints.
         self.program_complete_input_constraints();
 
         loop {
             match self.solver.real_solve(global_regs) {
-                Ok(regs) => return regs,
+                Ok(regs) => return Ok(regs),
                 Err(SolverError::Divert(rc)) => {
                     // Do we have any live-through `rc` registers that are not already variables?
-                    let added = self.try_add_var(rc, throughs);
-                    debug_assert!(added, "Ran out of registers in {}", rc);
+                    if !self.try_add_var(rc, throughs) {
+                        return Err(CodegenError::RegisterPressure {
+                            inst,
+                            message: format!(
+                                "ran out of {} registers for {}",
+                                rc,
+                                self.cur.display_inst(inst)
+                            ),
+                        });
+                    }
                 }
                 Err(SolverError::Global(value)) => {
-                    dbg!("Not enough global registers for {}, trying as local", value);
+                    trace!("Not enough global registers for {}, trying as local", value);
                     // We'll clear the `is_global` flag on all solver variables and instead make a
                     // note to replace all global defines with local defines followed by a copy.
                     *replace_global_defines = true;
@@ -875,7 +898,7 @@ impl<'a> Context<'a> {
 
     /// Try to add an `rc` variable to the solver from the `throughs` set.
     fn try_add_var(&mut self, rc: RegClass, throughs: &[LiveValue]) -> bool {
-        dbg!("Trying to add a {} reg from {} values", rc, throughs.len());
+        trace!("Trying to add a {} reg from {} values", rc, throughs.len());
 
         for lv in throughs {
             if let Affinity::Reg(rci) = lv.affinity {
@@ -1003,7 +1026,7 @@ impl<'a> Context<'a> {
     /// the constraints on the instruction operands.
     ///
     fn replace_global_defines(&mut self, inst: Inst, tracker: &mut LiveValueTracker) {
-        dbg!("Replacing global defs on {}", self.cur.display_inst(inst));
+        trace!("Replacing global defs on {}", self.cur.display_inst(inst));
 
         // We'll insert copies *after `inst`. Our caller will move the cursor back.
         self.cur.next_inst();
@@ -1051,14 +1074,14 @@ impl<'a> Context<'a> {
             lv.endpoint = copy;
             lv.is_local = true;
 
-            dbg!(
+            trace!(
                 "  + {} with {} in {}",
                 self.cur.display_inst(copy),
                 local,
                 loc.display(&self.reginfo)
             );
         }
-        dbg!("Done: {}", self.cur.display_inst(inst));
+        trace!("Done: {}", self.cur.display_inst(inst));
     }
 
     /// Process kills on a ghost instruction.
@@ -15,7 +15,8 @@ use regalloc::liveness::Liveness;
 use regalloc::reload::Reload;
 use regalloc::spilling::Spilling;
 use regalloc::virtregs::VirtRegs;
-use result::CtonResult;
+use result::CodegenResult;
+use settings::VerifierLevel;
 use timing;
 use topo_order::TopoOrder;
 use verifier::{verify_context, verify_liveness, verify_cssa, verify_locations};
@@ -72,9 +73,14 @@ impl Context {
         func: &mut Function,
         cfg: &ControlFlowGraph,
         domtree: &mut DominatorTree,
-    ) -> CtonResult {
+    ) -> CodegenResult {
         let _tt = timing::regalloc();
-        debug_assert!(domtree.is_valid());
+        cfg.assert_current(func);
+        domtree.assert_current(func);
+
+        // The liveness, CSSA, and locations verifiers are only run at the full verifier level.
+        let verify = isa.flags().enable_verifier();
+        let verify_all = verify && isa.flags().verifier_level() == VerifierLevel::Full;
 
         // `Liveness` and `Coloring` are self-clearing.
         self.virtregs.clear();
@@ -86,7 +92,7 @@ impl Context {
         // Pass: Liveness analysis.
         self.liveness.compute(isa, func, cfg);
 
-        if isa.flags().enable_verifier() {
+        if verify_all {
             verify_liveness(isa, func, cfg, &self.liveness)?;
         }
 
@@ -100,8 +106,10 @@ impl Context {
             &mut self.virtregs,
         );
 
-        if isa.flags().enable_verifier() {
+        if verify {
             verify_context(func, cfg, domtree, isa)?;
+        }
+        if verify_all {
             verify_liveness(isa, func, cfg, &self.liveness)?;
             verify_cssa(func, cfg, domtree, &self.liveness, &self.virtregs)?;
         }
@@ -116,10 +124,12 @@ impl Context {
             &self.virtregs,
             &mut self.topo,
             &mut self.tracker,
-        );
+        )?;
 
-        if isa.flags().enable_verifier() {
+        if verify {
             verify_context(func, cfg, domtree, isa)?;
+        }
+        if verify_all {
             verify_liveness(isa, func, cfg, &self.liveness)?;
             verify_cssa(func, cfg, domtree, &self.liveness, &self.virtregs)?;
         }
@@ -134,8 +144,10 @@ impl Context {
             &mut self.tracker,
         );
 
-        if isa.flags().enable_verifier() {
+        if verify {
             verify_context(func, cfg, domtree, isa)?;
+        }
+        if verify_all {
             verify_liveness(isa, func, cfg, &self.liveness)?;
             verify_cssa(func, cfg, domtree, &self.liveness, &self.virtregs)?;
         }
@@ -147,10 +159,12 @@ impl Context {
             domtree,
             &mut self.liveness,
             &mut self.tracker,
-        );
+        )?;
 
-        if isa.flags().enable_verifier() {
+        if verify {
             verify_context(func, cfg, domtree, isa)?;
+        }
+        if verify_all {
             verify_liveness(isa, func, cfg, &self.liveness)?;
             verify_locations(isa, func, Some(&self.liveness))?;
             verify_cssa(func, cfg, domtree, &self.liveness, &self.virtregs)?;
@@ -294,6 +294,10 @@ pub struct Liveness {
     /// This vector is always empty, except for inside that function.
     /// It lives here to avoid repeated allocation of scratch memory.
     worklist: Vec<Ebb>,
+
+    /// The layout generation the live ranges are up to date with, or `None` if they haven't been
+    /// computed.
+    generation: Option<u64>,
 }
 
 impl Liveness {
@@ -306,6 +310,7 @@ impl Liveness {
             ranges: LiveRangeSet::new(),
             forest: LiveRangeForest::new(),
             worklist: Vec::new(),
+            generation: None,
         }
     }
 
@@ -319,6 +324,25 @@ impl Liveness {
         self.ranges.clear();
         self.forest.clear();
         self.worklist.clear();
+        self.generation = None;
+    }
+
+    /// Assert that the live ranges have been computed for the current EBB layout of `func`.
+    ///
+    /// The register allocator passes keep the live ranges up to date as they insert instructions,
+    /// but inserting, removing, or splitting EBBs invalidates them. See
+    /// `ControlFlowGraph::assert_current()`.
+    pub fn assert_current(&self, func: &Function) {
+        debug_assert!(
+            self.generation.is_some(),
+            "the liveness analysis hasn't been computed"
+        );
+        debug_assert!(
+            self.generation == Some(func.layout.generation()),
+            "the liveness analysis is stale: EBBs were inserted, removed, or split in {} since \
+             it was computed",
+            func.name
+        );
     }
 
     /// Get the live range for `value`, if it exists.
@@ -382,7 +406,9 @@ impl Liveness {
     /// This clears out any existing analysis stored in this data structure.
     pub fn compute(&mut self, isa: &TargetIsa, func: &mut Function, cfg: &ControlFlowGraph) {
         let _tt = timing::ra_liveness();
+        cfg.assert_current(func);
         self.ranges.clear();
+        self.generation = Some(func.layout.generation());
 
         // Get ISA data structures used for computing live range affinities.
         let enc_info = isa.encoding_info();
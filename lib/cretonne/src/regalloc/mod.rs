@@ -18,6 +18,7 @@ mod reload;
 mod solver;
 mod spilling;
 
+pub use self::affinity::Affinity;
 pub use self::allocatable_set::AllocatableSet;
 pub use self::context::Context;
 pub use self::diversion::RegDiversions;
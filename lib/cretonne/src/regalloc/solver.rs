@@ -549,7 +549,7 @@ impl Solver {
     /// In either case, `to` will not be available for variables on the input side of the
     /// instruction.
     pub fn reassign_in(&mut self, value: Value, rc: RegClass, from: RegUnit, to: RegUnit) {
-        dbg!(
+        trace!(
             "reassign_in({}:{}, {} -> {})",
             value,
             rc,
@@ -562,7 +562,7 @@ impl Solver {
             // added as a variable previously. A fixed constraint beats a variable, so convert it.
             if let Some(idx) = self.vars.iter().position(|v| v.value == value) {
                 let v = self.vars.remove(idx);
-                dbg!("-> converting variable {} to a fixed constraint", v);
+                trace!("-> converting variable {} to a fixed constraint", v);
                 // The spiller is responsible for ensuring that all constraints on the uses of a
                 // value are compatible.
                 debug_assert!(
@@ -595,7 +595,7 @@ impl Solver {
     /// This function can only be used before calling `inputs_done()`. Afterwards, more input-side
     /// variables can be added by calling `add_killed_var()` and `add_through_var()`
     pub fn add_var(&mut self, value: Value, constraint: RegClass, from: RegUnit) {
-        dbg!(
+        trace!(
             "add_var({}:{}, from={})",
             value,
             constraint,
@@ -610,7 +610,7 @@ impl Solver {
     ///
     /// This function should be called after `inputs_done()` only. Use `add_var()` before.
     pub fn add_killed_var(&mut self, value: Value, constraint: RegClass, from: RegUnit) {
-        dbg!(
+        trace!(
             "add_killed_var({}:{}, from={})",
             value,
             constraint,
@@ -625,7 +625,7 @@ impl Solver {
     ///
     /// This function should be called after `inputs_done()` only. Use `add_var()` before.
     pub fn add_through_var(&mut self, value: Value, constraint: RegClass, from: RegUnit) {
-        dbg!(
+        trace!(
             "add_through_var({}:{}, from={})",
             value,
             constraint,
@@ -652,7 +652,7 @@ impl Solver {
             if let Some(v) = self.vars.iter_mut().find(|v| v.value == value) {
                 // We have an existing variable entry for `value`. Combine the constraints.
                 if let Some(rc) = v.constraint.intersect(constraint) {
-                    dbg!("-> combining constraint with {} yields {}", v, rc);
+                    trace!("-> combining constraint with {} yields {}", v, rc);
                     v.constraint = rc;
                     return;
                 } else {
@@ -664,7 +664,7 @@ impl Solver {
 
             // No variable, then it must be a fixed reassignment.
             if let Some(a) = self.assignments.get(value) {
-                dbg!("-> already fixed assignment {}", a);
+                trace!("-> already fixed assignment {}", a);
                 debug_assert!(
                     constraint.contains(a.to),
                     "Incompatible constraints for {}",
@@ -673,12 +673,12 @@ impl Solver {
                 return;
             }
 
-            dbg!("{}", self);
+            trace!("{}", self);
             panic!("Wrong from register for {}", value);
         }
 
         let new_var = Variable::new_live(value, constraint, from, live_through);
-        dbg!("-> new var: {}", new_var);
+        trace!("-> new var: {}", new_var);
 
         self.regs_in.free(constraint, from);
         if self.inputs_done && live_through {
@@ -794,7 +794,7 @@ impl Solver {
         if is_global {
             let mut new_var = Variable::new_live(value, rc, reg, true);
             new_var.is_global = true;
-            dbg!("add_tied_input: new tied-global var: {}", new_var);
+            trace!("add_tied_input: new tied-global var: {}", new_var);
             self.vars.push(new_var);
             self.regs_in.free(rc, reg);
         } else {
@@ -923,7 +923,7 @@ impl Solver {
             )
         });
 
-        dbg!("real_solve for {}", self);
+        trace!("real_solve for {}", self);
         self.find_solution(global_regs)
     }
 
@@ -1010,7 +1010,7 @@ impl Solver {
         ));
 
         if !(self.moves.is_empty()) {
-            dbg!("collect_moves: {}", DisplayList(&self.moves));
+            trace!("collect_moves: {}", DisplayList(&self.moves));
         }
     }
 
@@ -1053,7 +1053,7 @@ impl Solver {
                 if let Some((rc, reg)) = m.from_reg() {
                     avail.free(rc, reg);
                 }
-                dbg!("move #{}: {}", i, m);
+                trace!("move #{}: {}", i, m);
                 i += 1;
                 continue;
             }
@@ -1087,7 +1087,7 @@ impl Solver {
             let m = self.moves[i].clone();
             let toprc = m.rc().toprc();
             if let Some(reg) = avail.iter(toprc).next() {
-                dbg!(
+                trace!(
                     "breaking cycle at {} with available {} register {}",
                     m,
                     toprc,
@@ -1118,7 +1118,7 @@ impl Solver {
             // a last resort.
             let slot = num_spill_slots;
             num_spill_slots += 1;
-            dbg!("breaking cycle at {} with slot {}", m, slot);
+            trace!("breaking cycle at {} with slot {}", m, slot);
             let old_to_reg = self.moves[i].change_to_spill(slot);
             self.fills.push(Move::Fill {
                 value: m.value(),
@@ -25,6 +25,7 @@ use regalloc::live_value_tracker::{LiveValue, LiveValueTracker};
 use regalloc::liveness::Liveness;
 use regalloc::pressure::Pressure;
 use regalloc::virtregs::VirtRegs;
+use result::{CodegenError, CodegenResult};
 use std::fmt;
 use timing;
 use topo_order::TopoOrder;
@@ -87,9 +88,10 @@ impl Spilling {
         virtregs: &VirtRegs,
         topo: &mut TopoOrder,
         tracker: &mut LiveValueTracker,
-    ) {
+    ) -> CodegenResult {
         let _tt = timing::ra_spilling();
-        dbg!("Spilling for:\n{}", func.display(isa));
+        liveness.assert_current(func);
+        debug!("Spilling for:\n{}", func.display(isa));
         let reginfo = isa.register_info();
         let usable_regs = isa.allocatable_registers(func);
         let mut ctx = Context {
@@ -109,15 +111,16 @@ impl Spilling {
 }
 
 impl<'a> Context<'a> {
-    fn run(&mut self, tracker: &mut LiveValueTracker) {
+    fn run(&mut self, tracker: &mut LiveValueTracker) -> CodegenResult {
         self.topo.reset(self.cur.func.layout.ebbs());
         while let Some(ebb) = self.topo.next(&self.cur.func.layout, self.domtree) {
-            self.visit_ebb(ebb, tracker);
+            self.visit_ebb(ebb, tracker)?;
         }
+        Ok(())
     }
 
-    fn visit_ebb(&mut self, ebb: Ebb, tracker: &mut LiveValueTracker) {
-        dbg!("Spilling {}:", ebb);
+    fn visit_ebb(&mut self, ebb: Ebb, tracker: &mut LiveValueTracker) -> CodegenResult {
+        debug!("Spilling {}:", ebb);
         self.cur.goto_top(ebb);
         self.visit_ebb_header(ebb, tracker);
         tracker.drop_dead_params();
@@ -129,7 +132,7 @@ impl<'a> Context<'a> {
                     self.cur.func.encodings[inst],
                 )
             {
-                self.visit_inst(inst, ebb, constraints, tracker);
+                self.visit_inst(inst, ebb, constraints, tracker)?;
             } else {
                 let (_throughs, kills) = tracker.process_ghost(inst);
                 self.free_regs(kills);
@@ -137,6 +140,7 @@ impl<'a> Context<'a> {
             tracker.drop_dead(inst);
             self.process_spills(tracker);
         }
+        Ok(())
     }
 
     // Take all live registers in `regs` from the pressure set.
@@ -198,10 +202,10 @@ impl<'a> Context<'a> {
             if let Affinity::Reg(rci) = lv.affinity {
                 let rc = self.reginfo.rc(rci);
                 'try_take: while let Err(mask) = self.pressure.take_transient(rc) {
-                    dbg!("Need {} reg for EBB param {}", rc, lv.value);
+                    trace!("Need {} reg for EBB param {}", rc, lv.value);
                     match self.spill_candidate(mask, liveins) {
                         Some(cand) => {
-                            dbg!(
+                            trace!(
                                 "Spilling live-in {} to make room for {} EBB param {}",
                                 cand,
                                 rc,
@@ -213,7 +217,7 @@ impl<'a> Context<'a> {
                             // We can't spill any of the live-in registers, so we have to spill an
                             // EBB argument. Since the current spill metric would consider all the
                             // EBB arguments equal, just spill the present register.
-                            dbg!("Spilling {} EBB argument {}", rc, lv.value);
+                            trace!("Spilling {} EBB argument {}", rc, lv.value);
 
                             // Since `spill_reg` will free a register, add the current one here.
                             self.pressure.take(rc);
@@ -236,8 +240,8 @@ impl<'a> Context<'a> {
         ebb: Ebb,
         constraints: &RecipeConstraints,
         tracker: &mut LiveValueTracker,
-    ) {
-        dbg!("Inst {}, {}", self.cur.display_inst(inst), self.pressure);
+    ) -> CodegenResult {
+        trace!("Inst {}, {}", self.cur.display_inst(inst), self.pressure);
         debug_assert_eq!(self.cur.current_inst(), Some(inst));
         debug_assert_eq!(self.cur.current_ebb(), Some(ebb));
 
@@ -245,14 +249,14 @@ impl<'a> Context<'a> {
         debug_assert!(self.reg_uses.is_empty());
         self.collect_reg_uses(inst, ebb, constraints);
 
-        // Calls usually have fixed register uses.
+        // Calls and returns usually have fixed register uses.
         let call_sig = self.cur.func.dfg.call_signature(inst);
-        if let Some(sig) = call_sig {
-            self.collect_abi_reg_uses(inst, sig);
+        if call_sig.is_some() || self.cur.func.dfg[inst].opcode().is_return() {
+            self.collect_abi_reg_uses(inst, call_sig);
         }
 
         if !self.reg_uses.is_empty() {
-            self.process_reg_uses(inst, tracker);
+            self.process_reg_uses(inst, tracker)?;
         }
 
         // Update the live value tracker with this instruction.
@@ -279,15 +283,18 @@ impl<'a> Context<'a> {
             if op.kind != ConstraintKind::Stack {
                 // Add register def to pressure, spill if needed.
                 while let Err(mask) = self.pressure.take_transient(op.regclass) {
-                    dbg!("Need {} reg from {} throughs", op.regclass, throughs.len());
+                    trace!("Need {} reg from {} throughs", op.regclass, throughs.len());
                     match self.spill_candidate(mask, throughs) {
                         Some(cand) => self.spill_reg(cand),
                         None => {
-                            panic!(
-                                "Ran out of {} registers for {}",
-                                op.regclass,
-                                self.cur.display_inst(inst)
-                            )
+                            return Err(CodegenError::RegisterPressure {
+                                inst,
+                                message: format!(
+                                    "ran out of {} registers for {}",
+                                    op.regclass,
+                                    self.cur.display_inst(inst)
+                                ),
+                            })
                         }
                     }
                 }
@@ -299,6 +306,7 @@ impl<'a> Context<'a> {
         // Exclude dead defs. Includes call return values.
         // This won't cause spilling.
         self.take_live_regs(defs);
+        Ok(())
     }
 
     // Collect register uses that are noteworthy in one of the following ways:
@@ -335,26 +343,25 @@ impl<'a> Context<'a> {
 
             // Only collect the interesting register uses.
             if reguse.fixed || reguse.tied || reguse.spilled {
-                dbg!("  reguse: {}", reguse);
+                trace!("  reguse: {}", reguse);
                 self.reg_uses.push(reguse);
             }
         }
     }
 
-    // Collect register uses from the ABI input constraints.
-    fn collect_abi_reg_uses(&mut self, inst: Inst, sig: SigRef) {
+    // Collect register uses from the ABI input constraints of a call with signature `sig`, or of a
+    // return if `sig` is `None`.
+    fn collect_abi_reg_uses(&mut self, inst: Inst, sig: Option<SigRef>) {
         let fixed_args = self.cur.func.dfg[inst]
             .opcode()
             .constraints()
             .fixed_value_arguments();
         let args = self.cur.func.dfg.inst_variable_args(inst);
-        for (idx, (abi, &arg)) in
-            self.cur.func.dfg.signatures[sig]
-                .params
-                .iter()
-                .zip(args)
-                .enumerate()
-        {
+        let abi_params = match sig {
+            Some(sig) => &self.cur.func.dfg.signatures[sig].params,
+            None => &self.cur.func.signature.returns,
+        };
+        for (idx, (abi, &arg)) in abi_params.iter().zip(args).enumerate() {
             if abi.location.is_reg() {
                 let (rci, spilled) = match self.liveness[arg].affinity {
                     Affinity::Reg(rci) => (rci, false),
@@ -377,14 +384,18 @@ impl<'a> Context<'a> {
     // Process multiple register uses to resolve potential conflicts.
     //
     // Look for multiple uses of the same value in `self.reg_uses` and insert copies as necessary.
-    // Trigger spilling if any of the temporaries cause the register pressure to become too high.
+    // Trigger spilling if any of the temporaries cause the register pressure to become too high,
+    // and fail if there is nothing left to spill.
     //
     // Leave `self.reg_uses` empty.
-    fn process_reg_uses(&mut self, inst: Inst, tracker: &LiveValueTracker) {
+    fn process_reg_uses(&mut self, inst: Inst, tracker: &LiveValueTracker) -> CodegenResult {
         // We're looking for multiple uses of the same value, so start by sorting by value. The
         // secondary `opidx` key makes it possible to use an unstable (non-allocating) sort.
         self.reg_uses.sort_unstable_by_key(|u| (u.value, u.opidx));
 
+        // Copies inherit the source location of the instruction using them.
+        self.cur.use_srcloc(inst);
+
         for i in 0..self.reg_uses.len() {
             let ru = self.reg_uses[i];
 
@@ -412,7 +423,7 @@ impl<'a> Context<'a> {
             if need_copy || ru.spilled {
                 let rc = self.reginfo.rc(ru.rci);
                 while let Err(mask) = self.pressure.take_transient(rc) {
-                    dbg!("Copy of {} reg causes spill", rc);
+                    trace!("Copy of {} reg causes spill", rc);
                     // Spill a live register that is *not* used by the current instruction.
                     // Spilling a use wouldn't help.
                     //
@@ -433,18 +444,23 @@ impl<'a> Context<'a> {
                     } {
                         Some(cand) => self.spill_reg(cand),
                         None => {
-                            panic!(
-                                "Ran out of {} registers when inserting copy before {}",
-                                rc,
-                                self.cur.display_inst(inst)
-                            )
+                            self.reg_uses.clear();
+                            return Err(CodegenError::RegisterPressure {
+                                inst,
+                                message: format!(
+                                    "ran out of {} registers when inserting copy before {}",
+                                    rc,
+                                    self.cur.display_inst(inst)
+                                ),
+                            });
                         }
                     }
                 }
             }
         }
         self.pressure.reset_transient();
-        self.reg_uses.clear()
+        self.reg_uses.clear();
+        Ok(())
     }
 
     // Find a spill candidate from `candidates` whose top-level register class is in `mask`.
@@ -497,7 +513,7 @@ impl<'a> Context<'a> {
             let rc = self.reginfo.rc(rci);
             self.pressure.free(rc);
             self.spills.push(value);
-            dbg!("Spilled {}:{} -> {}", value, rc, self.pressure);
+            trace!("Spilled {}:{} -> {}", value, rc, self.pressure);
         } else {
             panic!("Cannot spill {} that was already on the stack", value);
         }
@@ -1,5 +1,6 @@
 //! Result and error types representing the outcome of compiling a function.
 
+use ir::Inst;
 use verifier;
 use std::error::Error as StdError;
 use std::fmt;
@@ -8,7 +9,7 @@ use std::fmt;
 ///
 /// When Cretonne fails to compile a function, it will return one of these error codes.
 #[derive(Debug, PartialEq, Eq)]
-pub enum CtonError {
+pub enum CodegenError {
     /// The input is invalid.
     ///
     /// This error code is used by a WebAssembly translator when it encounters invalid WebAssembly
@@ -34,43 +35,89 @@ pub enum CtonError {
     /// Different target ISAs may impose a limit on the size of a compiled function. If that limit
     /// is exceeded, compilation fails.
     CodeTooLarge,
+
+    /// An instruction can't be encoded for the target ISA.
+    ///
+    /// The legalizer found neither an encoding nor a legal expansion for the instruction, and it
+    /// can't be turned into a library call either.
+    Unencodable {
+        /// The instruction.
+        inst: Inst,
+        /// A description of the problem.
+        message: String,
+    },
+
+    /// The register allocator ran out of registers.
+    ///
+    /// This happens when an instruction needs more registers of a class at once than the target
+    /// ISA has, for example because of conflicting fixed register constraints.
+    RegisterPressure {
+        /// The instruction that couldn't be allocated.
+        inst: Inst,
+        /// A description of the problem.
+        message: String,
+    },
+
+    /// The function uses a feature that isn't supported by the code generator yet.
+    Unsupported(String),
 }
 
 /// A Cretonne compilation result.
-pub type CtonResult = Result<(), CtonError>;
+pub type CodegenResult = Result<(), CodegenError>;
+
+/// The old name of `CodegenError`.
+#[deprecated(note = "renamed to `CodegenError`")]
+pub type CtonError = CodegenError;
+
+/// The old name of `CodegenResult`.
+#[deprecated(note = "renamed to `CodegenResult`")]
+pub type CtonResult = CodegenResult;
 
-impl fmt::Display for CtonError {
+impl fmt::Display for CodegenError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            CtonError::Verifier(ref e) => write!(f, "Verifier error: {}", e),
-            CtonError::InvalidInput |
-            CtonError::ImplLimitExceeded |
-            CtonError::CodeTooLarge => f.write_str(self.description()),
+            CodegenError::Verifier(ref e) => write!(f, "Verifier error: {}", e),
+            CodegenError::Unencodable { ref message, .. } => {
+                write!(f, "Unencodable instruction: {}", message)
+            }
+            CodegenError::RegisterPressure { ref message, .. } => {
+                write!(f, "Register allocation failed: {}", message)
+            }
+            CodegenError::Unsupported(ref feature) => write!(f, "Unsupported: {}", feature),
+            CodegenError::InvalidInput |
+            CodegenError::ImplLimitExceeded |
+            CodegenError::CodeTooLarge => f.write_str(self.description()),
         }
     }
 }
 
-impl StdError for CtonError {
+impl StdError for CodegenError {
     fn description(&self) -> &str {
         match *self {
-            CtonError::InvalidInput => "Invalid input code",
-            CtonError::Verifier(ref e) => &e.message,
-            CtonError::ImplLimitExceeded => "Implementation limit exceeded",
-            CtonError::CodeTooLarge => "Code for function is too large",
+            CodegenError::InvalidInput => "Invalid input code",
+            CodegenError::Verifier(ref e) => &e.message,
+            CodegenError::ImplLimitExceeded => "Implementation limit exceeded",
+            CodegenError::CodeTooLarge => "Code for function is too large",
+            CodegenError::Unencodable { .. } => "Unencodable instruction",
+            CodegenError::RegisterPressure { .. } => "Register allocation failed",
+            CodegenError::Unsupported(_) => "Unsupported feature",
         }
     }
     fn cause(&self) -> Option<&StdError> {
         match *self {
-            CtonError::Verifier(ref e) => Some(e),
-            CtonError::InvalidInput |
-            CtonError::ImplLimitExceeded |
-            CtonError::CodeTooLarge => None,
+            CodegenError::Verifier(ref e) => Some(e),
+            CodegenError::InvalidInput |
+            CodegenError::ImplLimitExceeded |
+            CodegenError::CodeTooLarge |
+            CodegenError::Unencodable { .. } |
+            CodegenError::RegisterPressure { .. } |
+            CodegenError::Unsupported(_) => None,
         }
     }
 }
 
-impl From<verifier::Error> for CtonError {
-    fn from(e: verifier::Error) -> CtonError {
-        CtonError::Verifier(e)
+impl From<verifier::Error> for CodegenError {
+    fn from(e: verifier::Error) -> CodegenError {
+        CodegenError::Verifier(e)
     }
 }
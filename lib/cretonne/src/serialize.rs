@@ -0,0 +1,1315 @@
+//! Binary serialization of Cretonne IL.
+//!
+//! The binary format is a compact alternative to the textual IL format, intended for caching IL
+//! or passing it between processes. It preserves everything that is needed to reconstruct an
+//! identical `ir::Function`: entity numbers, instructions, value aliases, the layout, encodings,
+//! value locations, EBB offsets, source locations and value labels.
+//!
+//! The format is versioned with `FORMAT_VERSION`. Functions serialized with a different version
+//! are rejected by `deserialize_function`. Unlike the text format, the binary format doesn't
+//! preserve comments or the names of entities that don't appear in the IL.
+
+use bytestream::{ReadError, ReadResult, Reader, Writer};
+use entity::EntityRef;
+use ir::condcodes::{FloatCC, IntCC};
+use ir::immediates::{Ieee32, Ieee64, Imm64, Offset32, Uimm32};
+use ir::instructions::InstructionFormat;
+use ir::{self, AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, CallConv, Ebb,
+         ExtFuncData, Function, GlobalVarData, HeapBase, HeapData, HeapStyle, Inst,
+         InstructionData, JumpTableData, MemFlags, Opcode, Signature, SourceLoc, StackSlotData,
+         StackSlotKind, TrapCode, Type, Value, ValueLabel, ValueLabelData, ValueList, ValueLoc,
+         types};
+use isa::Encoding;
+use std::fmt;
+
+/// Magic bytes at the start of a serialized function.
+const MAGIC: &[u8; 8] = b"CTONIL\0\0";
+
+/// Version of the binary IL format. Bump this whenever the format changes.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// An error encountered while deserializing a function.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// The data doesn't start with the expected magic bytes.
+    BadMagic,
+    /// The data was written by an incompatible version of the format.
+    VersionMismatch(u32),
+    /// The data ended unexpectedly.
+    Truncated,
+    /// The data contains an invalid value. The string describes what was being read.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeserializeError::BadMagic => f.write_str("not a binary IL function"),
+            DeserializeError::VersionMismatch(v) => {
+                write!(f, "binary IL version {}, expected {}", v, FORMAT_VERSION)
+            }
+            DeserializeError::Truncated => f.write_str("binary IL is truncated"),
+            DeserializeError::Corrupt(what) => write!(f, "binary IL has invalid {}", what),
+        }
+    }
+}
+
+impl From<ReadError> for DeserializeError {
+    fn from(e: ReadError) -> Self {
+        match e {
+            ReadError::Truncated => DeserializeError::Truncated,
+            ReadError::Corrupt(what) => DeserializeError::Corrupt(what),
+        }
+    }
+}
+
+/// Serialize `func` to the binary IL format.
+pub fn serialize_function(func: &Function) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.0.extend_from_slice(MAGIC);
+    w.u32(FORMAT_VERSION);
+    w.name(&func.name);
+    write_signature(&mut w, &func.signature);
+
+    // Preamble entities.
+    w.u32(func.dfg.signatures.len() as u32);
+    for sig in func.dfg.signatures.keys() {
+        write_signature(&mut w, &func.dfg.signatures[sig]);
+    }
+    w.u32(func.dfg.ext_funcs.len() as u32);
+    for fref in func.dfg.ext_funcs.keys() {
+        let ext = &func.dfg.ext_funcs[fref];
+        w.name(&ext.name);
+        entity(&mut w, ext.signature);
+    }
+    w.u32(func.stack_slots.keys().count() as u32);
+    for ss in func.stack_slots.keys() {
+        let slot = &func.stack_slots[ss];
+        w.u8(encode(&STACK_SLOT_KINDS, slot.kind));
+        w.u32(slot.size);
+        write_option(&mut w, slot.offset.map(|x| x as u32));
+        write_option(&mut w, slot.align);
+    }
+    write_option(&mut w, func.stack_slots.frame_size);
+    w.u32(func.global_vars.len() as u32);
+    for gv in func.global_vars.keys() {
+        match func.global_vars[gv] {
+            GlobalVarData::VmCtx { offset } => {
+                w.u8(0);
+                w.u32(offset32_bits(offset));
+            }
+            GlobalVarData::Deref { base, offset } => {
+                w.u8(1);
+                entity(&mut w, base);
+                w.u32(offset32_bits(offset));
+            }
+            GlobalVarData::Sym { ref name } => {
+                w.u8(2);
+                w.name(name);
+            }
+        }
+    }
+    w.u32(func.heaps.len() as u32);
+    for heap in func.heaps.keys() {
+        let data = &func.heaps[heap];
+        match data.base {
+            HeapBase::ReservedReg => w.u8(0),
+            HeapBase::GlobalVar(gv) => {
+                w.u8(1);
+                entity(&mut w, gv);
+            }
+        }
+        w.u64(imm64_bits(data.min_size));
+        w.u64(imm64_bits(data.guard_size));
+        match data.style {
+            HeapStyle::Dynamic { bound_gv } => {
+                w.u8(0);
+                entity(&mut w, bound_gv);
+            }
+            HeapStyle::Static { bound } => {
+                w.u8(1);
+                w.u64(imm64_bits(bound));
+            }
+        }
+    }
+
+    // EBBs and their parameters. The number of values comes first so the reader can reserve
+    // value numbers before they are defined.
+    w.u32(func.dfg.num_values() as u32);
+    w.u32(func.dfg.num_ebbs() as u32);
+    for i in 0..func.dfg.num_ebbs() {
+        let params = func.dfg.ebb_params(Ebb::new(i));
+        w.u32(params.len() as u32);
+        for &v in params {
+            entity(&mut w, v);
+            write_type(&mut w, func.dfg.value_type(v));
+        }
+    }
+
+    w.u32(func.jump_tables.len() as u32);
+    for jt in func.jump_tables.keys() {
+        let table = &func.jump_tables[jt];
+        w.u32(table.len() as u32);
+        for idx in 0..table.len() {
+            // EBB numbers are biased by 1 so that 0 can represent an empty slot.
+            w.u32(table.get_entry(idx).map_or(0, |ebb| ebb.index() as u32 + 1));
+        }
+    }
+
+    // Instructions in numerical order, with their results.
+    w.u32(func.dfg.num_insts() as u32);
+    for i in 0..func.dfg.num_insts() {
+        let inst = Inst::new(i);
+        write_inst(&mut w, func, inst);
+        write_type(&mut w, func.dfg.ctrl_typevar(inst));
+        let results = func.dfg.inst_results(inst);
+        w.u32(results.len() as u32);
+        for &v in results {
+            entity(&mut w, v);
+        }
+    }
+
+    // Value aliases. Invalid values that were only created to pad the value numbering have the
+    // `VOID` type and are left out.
+    let aliases: Vec<Value> = (0..func.dfg.num_values())
+        .map(Value::new)
+        .filter(|&v| {
+            func.dfg.value_type(v) != types::VOID && func.dfg.resolve_aliases(v) != v
+        })
+        .collect();
+    w.u32(aliases.len() as u32);
+    for v in aliases {
+        entity(&mut w, v);
+        entity(&mut w, func.dfg.resolve_aliases(v));
+    }
+
+    // Layout.
+    w.u32(func.layout.ebbs().count() as u32);
+    for ebb in func.layout.ebbs() {
+        entity(&mut w, ebb);
+        w.u32(func.layout.ebb_insts(ebb).count() as u32);
+        for inst in func.layout.ebb_insts(ebb) {
+            entity(&mut w, inst);
+        }
+    }
+
+    // Sparse side tables.
+    let encodings: Vec<Inst> = func.encodings
+        .keys()
+        .filter(|&inst| func.encodings[inst].is_legal())
+        .collect();
+    w.u32(encodings.len() as u32);
+    for inst in encodings {
+        entity(&mut w, inst);
+        w.u16(func.encodings[inst].recipe() as u16);
+        w.u16(func.encodings[inst].bits());
+    }
+    let locations: Vec<Value> = func.locations
+        .keys()
+        .filter(|&v| func.locations[v] != ValueLoc::Unassigned)
+        .collect();
+    w.u32(locations.len() as u32);
+    for v in locations {
+        entity(&mut w, v);
+        match func.locations[v] {
+            ValueLoc::Unassigned => unreachable!(),
+            ValueLoc::Reg(ru) => {
+                w.u8(0);
+                w.u16(ru);
+            }
+            ValueLoc::Stack(ss) => {
+                w.u8(1);
+                entity(&mut w, ss);
+            }
+        }
+    }
+    let offsets: Vec<Ebb> = func.offsets
+        .keys()
+        .filter(|&ebb| func.offsets[ebb] != 0)
+        .collect();
+    w.u32(offsets.len() as u32);
+    for ebb in offsets {
+        entity(&mut w, ebb);
+        w.u32(func.offsets[ebb]);
+    }
+    let srclocs: Vec<Inst> = func.srclocs
+        .keys()
+        .filter(|&inst| !func.srclocs[inst].is_default())
+        .collect();
+    w.u32(srclocs.len() as u32);
+    for inst in srclocs {
+        entity(&mut w, inst);
+        w.u32(func.srclocs[inst].bits());
+    }
+    w.u32(func.value_labels.len() as u32);
+    for label in func.value_labels.keys() {
+        let data = &func.value_labels[label];
+        w.bytes(data.name.as_bytes());
+        w.u32(data.srcloc.bits());
+    }
+    let labelled: Vec<Value> = func.labelled_values
+        .keys()
+        .filter(|&v| !func.value_labels_of(v).is_empty())
+        .collect();
+    w.u32(labelled.len() as u32);
+    for v in labelled {
+        let labels = func.value_labels_of(v);
+        entity(&mut w, v);
+        w.u32(labels.len() as u32);
+        for &label in labels {
+            entity(&mut w, label);
+        }
+    }
+
+    w.0
+}
+
+/// Deserialize a function from the binary IL format.
+pub fn deserialize_function(data: &[u8]) -> Result<Function, DeserializeError> {
+    let mut func = Function::new();
+    deserialize_function_into(data, &mut func)?;
+    Ok(func)
+}
+
+/// Deserialize a function from the binary IL format into `func`, replacing its contents.
+///
+/// This reuses the memory allocated by `func`. When the data is invalid, `func` may be left
+/// partially decoded.
+pub fn deserialize_function_into(data: &[u8], func: &mut Function) -> Result<(), DeserializeError> {
+    let mut r = Reader(data);
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err(DeserializeError::BadMagic);
+    }
+    let version = r.u32()?;
+    if version != FORMAT_VERSION {
+        return Err(DeserializeError::VersionMismatch(version));
+    }
+    func.name = r.name()?;
+    func.signature = read_signature(&mut r)?;
+    func.clear_body();
+    Decoder {
+        r,
+        func,
+        defined: Vec::new(),
+    }.read_body()?;
+    Ok(())
+}
+
+/// State for decoding the body of a function.
+struct Decoder<'a, 'f> {
+    r: Reader<'a>,
+    func: &'f mut Function,
+    /// Values that have been defined so far.
+    defined: Vec<bool>,
+}
+
+impl<'a, 'f> Decoder<'a, 'f> {
+    fn read_body(&mut self) -> ReadResult<()> {
+        for _ in 0..self.r.u32()? {
+            let sig = read_signature(&mut self.r)?;
+            self.func.dfg.signatures.push(sig);
+        }
+        for _ in 0..self.r.u32()? {
+            let name = self.r.name()?;
+            let signature = self.entity(self.func.dfg.signatures.len(), "signature")?;
+            self.func.dfg.ext_funcs.push(ExtFuncData { name, signature });
+        }
+        for _ in 0..self.r.u32()? {
+            let kind = decode(&STACK_SLOT_KINDS, self.r.u8()?, "stack slot kind")?;
+            let mut slot = StackSlotData::new(kind, self.r.u32()?);
+            slot.offset = read_option(&mut self.r)?.map(|x| x as i32);
+            slot.align = read_option(&mut self.r)?;
+            self.func.stack_slots.push(slot);
+        }
+        self.func.stack_slots.frame_size = read_option(&mut self.r)?;
+        let num_gvs = self.r.u32()? as usize;
+        for _ in 0..num_gvs {
+            let gv = match self.r.u8()? {
+                0 => GlobalVarData::VmCtx { offset: Offset32::new(self.r.u32()? as i32) },
+                1 => {
+                    GlobalVarData::Deref {
+                        base: self.entity(num_gvs, "global variable")?,
+                        offset: Offset32::new(self.r.u32()? as i32),
+                    }
+                }
+                2 => GlobalVarData::Sym { name: self.r.name()? },
+                _ => return Err(ReadError::Corrupt("global variable")),
+            };
+            self.func.global_vars.push(gv);
+        }
+        for _ in 0..self.r.u32()? {
+            let base = match self.r.u8()? {
+                0 => HeapBase::ReservedReg,
+                1 => HeapBase::GlobalVar(self.entity(num_gvs, "global variable")?),
+                _ => return Err(ReadError::Corrupt("heap base")),
+            };
+            let min_size = Imm64::new(self.r.u64()? as i64);
+            let guard_size = Imm64::new(self.r.u64()? as i64);
+            let style = match self.r.u8()? {
+                0 => HeapStyle::Dynamic { bound_gv: self.entity(num_gvs, "global variable")? },
+                1 => HeapStyle::Static { bound: Imm64::new(self.r.u64()? as i64) },
+                _ => return Err(ReadError::Corrupt("heap style")),
+            };
+            self.func.heaps.push(HeapData {
+                base,
+                min_size,
+                guard_size,
+                style,
+            });
+        }
+
+        // Reserve all value numbers, then define EBB parameters.
+        let num_values = self.r.u32()? as usize;
+        if num_values > self.r.0.len() {
+            // Every value is mentioned at least once, so this can't be right.
+            return Err(ReadError::Truncated);
+        }
+        for _ in 0..num_values {
+            self.func.dfg.make_invalid_value_for_parser();
+        }
+        self.defined = vec![false; num_values];
+        for _ in 0..self.r.u32()? {
+            let ebb = self.func.dfg.make_ebb();
+            for _ in 0..self.r.u32()? {
+                let v = self.define_value()?;
+                let ty = read_type(&mut self.r)?;
+                self.func.dfg.append_ebb_param_for_parser(ebb, ty, v);
+            }
+        }
+
+        for _ in 0..self.r.u32()? {
+            let len = self.r.u32()? as usize;
+            if len > self.r.0.len() {
+                return Err(ReadError::Truncated);
+            }
+            let mut table = JumpTableData::with_capacity(len);
+            for idx in 0..len {
+                match self.r.u32()? as usize {
+                    0 => {
+                        table.push_entry(Ebb::new(0));
+                        table.clear_entry(idx);
+                    }
+                    dest if dest <= self.func.dfg.num_ebbs() => {
+                        table.push_entry(Ebb::new(dest - 1))
+                    }
+                    _ => return Err(ReadError::Corrupt("jump table entry")),
+                }
+            }
+            self.func.jump_tables.push(table);
+        }
+
+        for _ in 0..self.r.u32()? {
+            let data = self.read_inst()?;
+            let ctrl_typevar = read_type(&mut self.r)?;
+            let mut results = Vec::new();
+            for _ in 0..self.r.u32()? {
+                results.push(self.define_value()?);
+            }
+            let inst = self.func.dfg.make_inst(data);
+            if self.func.dfg.make_inst_results_for_parser(inst, ctrl_typevar, &results) !=
+                results.len()
+            {
+                return Err(ReadError::Corrupt("instruction results"));
+            }
+        }
+
+        for _ in 0..self.r.u32()? {
+            let dest = self.define_value()?;
+            let src = self.value()?;
+            if self.func.dfg.value_type(src) == types::VOID {
+                return Err(ReadError::Corrupt("value alias"));
+            }
+            self.func.dfg.make_value_alias_for_parser(src, dest);
+        }
+
+        let num_insts = self.func.dfg.num_insts();
+        for _ in 0..self.r.u32()? {
+            let ebb = self.entity(self.func.dfg.num_ebbs(), "EBB")?;
+            if self.func.layout.is_ebb_inserted(ebb) {
+                return Err(ReadError::Corrupt("layout"));
+            }
+            self.func.layout.append_ebb(ebb);
+            for _ in 0..self.r.u32()? {
+                let inst = self.entity(num_insts, "instruction")?;
+                if self.func.layout.inst_ebb(inst).is_some() {
+                    return Err(ReadError::Corrupt("layout"));
+                }
+                self.func.layout.append_inst(inst, ebb);
+            }
+        }
+
+        for _ in 0..self.r.u32()? {
+            let inst: Inst = self.entity(num_insts, "instruction")?;
+            self.func.encodings[inst] = Encoding::new(self.r.u16()?, self.r.u16()?);
+        }
+        for _ in 0..self.r.u32()? {
+            let v = self.value()?;
+            self.func.locations[v] = match self.r.u8()? {
+                0 => ValueLoc::Reg(self.r.u16()?),
+                1 => ValueLoc::Stack(self.stack_slot()?),
+                _ => return Err(ReadError::Corrupt("value location")),
+            };
+        }
+        for _ in 0..self.r.u32()? {
+            let ebb: Ebb = self.entity(self.func.dfg.num_ebbs(), "EBB")?;
+            self.func.offsets[ebb] = self.r.u32()?;
+        }
+        for _ in 0..self.r.u32()? {
+            let inst: Inst = self.entity(num_insts, "instruction")?;
+            self.func.srclocs[inst] = SourceLoc::new(self.r.u32()?);
+        }
+        for _ in 0..self.r.u32()? {
+            let name = String::from_utf8(self.r.bytes()?.to_vec()).map_err(|_| {
+                ReadError::Corrupt("value label name")
+            })?;
+            let srcloc = SourceLoc::new(self.r.u32()?);
+            self.func.create_value_label(ValueLabelData { name, srcloc });
+        }
+        for _ in 0..self.r.u32()? {
+            let v = self.value()?;
+            for _ in 0..self.r.u32()? {
+                let label: ValueLabel = self.entity(self.func.value_labels.len(), "value label")?;
+                self.func.label_value(v, label);
+            }
+        }
+
+        if !self.r.is_empty() {
+            return Err(ReadError::Corrupt("trailing data"));
+        }
+        Ok(())
+    }
+
+    /// Read a reference to an entity, checking that it is one of `count` existing entities.
+    fn entity<E: EntityRef>(&mut self, count: usize, what: &'static str) -> ReadResult<E> {
+        let index = self.r.u32()? as usize;
+        if index < count {
+            Ok(E::new(index))
+        } else {
+            Err(ReadError::Corrupt(what))
+        }
+    }
+
+    fn value(&mut self) -> ReadResult<Value> {
+        let count = self.defined.len();
+        self.entity(count, "value")
+    }
+
+    /// Read a value that is being defined, and check that it isn't defined twice.
+    fn define_value(&mut self) -> ReadResult<Value> {
+        let v = self.value()?;
+        if self.defined[v.index()] {
+            return Err(ReadError::Corrupt("value definition"));
+        }
+        self.defined[v.index()] = true;
+        Ok(v)
+    }
+
+    fn values(&mut self) -> ReadResult<ValueList> {
+        let mut args = Vec::new();
+        for _ in 0..self.r.u32()? {
+            args.push(self.value()?);
+        }
+        let mut list = ValueList::new();
+        list.extend(args, &mut self.func.dfg.value_lists);
+        Ok(list)
+    }
+
+    fn ebb(&mut self) -> ReadResult<Ebb> {
+        let count = self.func.dfg.num_ebbs();
+        self.entity(count, "EBB")
+    }
+
+    fn stack_slot(&mut self) -> ReadResult<ir::StackSlot> {
+        let count = self.func.stack_slots.keys().count();
+        self.entity(count, "stack slot")
+    }
+
+    fn func_ref(&mut self) -> ReadResult<ir::FuncRef> {
+        let count = self.func.dfg.ext_funcs.len();
+        self.entity(count, "function reference")
+    }
+
+    fn intcc(&mut self) -> ReadResult<IntCC> {
+        decode(&INTCCS, self.r.u8()?, "integer condition code")
+    }
+
+    fn floatcc(&mut self) -> ReadResult<FloatCC> {
+        decode(&FLOATCCS, self.r.u8()?, "float condition code")
+    }
+
+    fn trapcode(&mut self) -> ReadResult<TrapCode> {
+        match self.r.u8()? {
+            0xff => Ok(TrapCode::User(self.r.u16()?)),
+            code => decode(&TRAPCODES, code, "trap code"),
+        }
+    }
+
+    fn memflags(&mut self) -> ReadResult<MemFlags> {
+        let bits = self.r.u8()?;
+        let mut flags = MemFlags::new();
+        if bits & 1 != 0 {
+            flags.set_notrap();
+        }
+        if bits & 2 != 0 {
+            flags.set_aligned();
+        }
+        Ok(flags)
+    }
+
+    fn imm64(&mut self) -> ReadResult<Imm64> {
+        Ok(Imm64::new(self.r.u64()? as i64))
+    }
+
+    fn offset32(&mut self) -> ReadResult<Offset32> {
+        Ok(Offset32::new(self.r.u32()? as i32))
+    }
+
+    /// Read the instruction data written by `write_inst`.
+    fn read_inst(&mut self) -> ReadResult<InstructionData> {
+        let opcode = Opcode::from_number(self.r.u8()?).ok_or(
+            ReadError::Corrupt("opcode"),
+        )?;
+        Ok(match opcode.format() {
+            InstructionFormat::Unary => InstructionData::Unary {
+                opcode,
+                arg: self.value()?,
+            },
+            InstructionFormat::UnaryImm => InstructionData::UnaryImm {
+                opcode,
+                imm: self.imm64()?,
+            },
+            InstructionFormat::UnaryIeee32 => InstructionData::UnaryIeee32 {
+                opcode,
+                imm: Ieee32::with_bits(self.r.u32()?),
+            },
+            InstructionFormat::UnaryIeee64 => InstructionData::UnaryIeee64 {
+                opcode,
+                imm: Ieee64::with_bits(self.r.u64()?),
+            },
+            InstructionFormat::UnaryBool => InstructionData::UnaryBool {
+                opcode,
+                imm: self.r.u8()? != 0,
+            },
+            InstructionFormat::UnaryGlobalVar => {
+                let count = self.func.global_vars.len();
+                InstructionData::UnaryGlobalVar {
+                    opcode,
+                    global_var: self.entity(count, "global variable")?,
+                }
+            }
+            InstructionFormat::Binary => InstructionData::Binary {
+                opcode,
+                args: [self.value()?, self.value()?],
+            },
+            InstructionFormat::BinaryImm => InstructionData::BinaryImm {
+                opcode,
+                arg: self.value()?,
+                imm: self.imm64()?,
+            },
+            InstructionFormat::Ternary => InstructionData::Ternary {
+                opcode,
+                args: [self.value()?, self.value()?, self.value()?],
+            },
+            InstructionFormat::MultiAry => InstructionData::MultiAry {
+                opcode,
+                args: self.values()?,
+            },
+            InstructionFormat::NullAry => InstructionData::NullAry { opcode },
+            InstructionFormat::InsertLane => InstructionData::InsertLane {
+                opcode,
+                args: [self.value()?, self.value()?],
+                lane: self.r.u8()?,
+            },
+            InstructionFormat::ExtractLane => InstructionData::ExtractLane {
+                opcode,
+                arg: self.value()?,
+                lane: self.r.u8()?,
+            },
+            InstructionFormat::IntCompare => InstructionData::IntCompare {
+                opcode,
+                cond: self.intcc()?,
+                args: [self.value()?, self.value()?],
+            },
+            InstructionFormat::IntCompareImm => InstructionData::IntCompareImm {
+                opcode,
+                cond: self.intcc()?,
+                arg: self.value()?,
+                imm: self.imm64()?,
+            },
+            InstructionFormat::IntCond => InstructionData::IntCond {
+                opcode,
+                cond: self.intcc()?,
+                arg: self.value()?,
+            },
+            InstructionFormat::FloatCompare => InstructionData::FloatCompare {
+                opcode,
+                cond: self.floatcc()?,
+                args: [self.value()?, self.value()?],
+            },
+            InstructionFormat::FloatCond => InstructionData::FloatCond {
+                opcode,
+                cond: self.floatcc()?,
+                arg: self.value()?,
+            },
+            InstructionFormat::IntSelect => InstructionData::IntSelect {
+                opcode,
+                cond: self.intcc()?,
+                args: [self.value()?, self.value()?, self.value()?],
+            },
+            InstructionFormat::Jump => InstructionData::Jump {
+                opcode,
+                destination: self.ebb()?,
+                args: self.values()?,
+            },
+            InstructionFormat::Branch => InstructionData::Branch {
+                opcode,
+                destination: self.ebb()?,
+                args: self.values()?,
+            },
+            InstructionFormat::BranchInt => InstructionData::BranchInt {
+                opcode,
+                cond: self.intcc()?,
+                destination: self.ebb()?,
+                args: self.values()?,
+            },
+            InstructionFormat::BranchFloat => InstructionData::BranchFloat {
+                opcode,
+                cond: self.floatcc()?,
+                destination: self.ebb()?,
+                args: self.values()?,
+            },
+            InstructionFormat::BranchIcmp => InstructionData::BranchIcmp {
+                opcode,
+                cond: self.intcc()?,
+                destination: self.ebb()?,
+                args: self.values()?,
+            },
+            InstructionFormat::BranchTable => {
+                let count = self.func.jump_tables.len();
+                InstructionData::BranchTable {
+                    opcode,
+                    arg: self.value()?,
+                    table: self.entity(count, "jump table")?,
+                }
+            }
+            InstructionFormat::Call => InstructionData::Call {
+                opcode,
+                func_ref: self.func_ref()?,
+                args: self.values()?,
+            },
+            InstructionFormat::IndirectCall => {
+                let count = self.func.dfg.signatures.len();
+                InstructionData::IndirectCall {
+                    opcode,
+                    sig_ref: self.entity(count, "signature")?,
+                    args: self.values()?,
+                }
+            }
+            InstructionFormat::FuncAddr => InstructionData::FuncAddr {
+                opcode,
+                func_ref: self.func_ref()?,
+            },
+            InstructionFormat::Load => InstructionData::Load {
+                opcode,
+                flags: self.memflags()?,
+                arg: self.value()?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::Store => InstructionData::Store {
+                opcode,
+                flags: self.memflags()?,
+                args: [self.value()?, self.value()?],
+                offset: self.offset32()?,
+            },
+            InstructionFormat::StackLoad => InstructionData::StackLoad {
+                opcode,
+                stack_slot: self.stack_slot()?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::StackStore => InstructionData::StackStore {
+                opcode,
+                arg: self.value()?,
+                stack_slot: self.stack_slot()?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::HeapAddr => {
+                let count = self.func.heaps.len();
+                InstructionData::HeapAddr {
+                    opcode,
+                    heap: self.entity(count, "heap")?,
+                    arg: self.value()?,
+                    imm: Uimm32::from(self.r.u32()?),
+                }
+            }
+            InstructionFormat::RegMove => InstructionData::RegMove {
+                opcode,
+                arg: self.value()?,
+                src: self.r.u16()?,
+                dst: self.r.u16()?,
+            },
+            InstructionFormat::CopySpecial => InstructionData::CopySpecial {
+                opcode,
+                src: self.r.u16()?,
+                dst: self.r.u16()?,
+            },
+            InstructionFormat::RegSpill => InstructionData::RegSpill {
+                opcode,
+                arg: self.value()?,
+                src: self.r.u16()?,
+                dst: self.stack_slot()?,
+            },
+            InstructionFormat::RegFill => InstructionData::RegFill {
+                opcode,
+                arg: self.value()?,
+                src: self.stack_slot()?,
+                dst: self.r.u16()?,
+            },
+            InstructionFormat::Trap => InstructionData::Trap {
+                opcode,
+                code: self.trapcode()?,
+            },
+            InstructionFormat::CondTrap => InstructionData::CondTrap {
+                opcode,
+                arg: self.value()?,
+                code: self.trapcode()?,
+            },
+            InstructionFormat::IntCondTrap => InstructionData::IntCondTrap {
+                opcode,
+                cond: self.intcc()?,
+                arg: self.value()?,
+                code: self.trapcode()?,
+            },
+            InstructionFormat::FloatCondTrap => InstructionData::FloatCondTrap {
+                opcode,
+                cond: self.floatcc()?,
+                arg: self.value()?,
+                code: self.trapcode()?,
+            },
+        })
+    }
+}
+
+/// Write the opcode and operands of `inst`.
+///
+/// The fields of each format are written in the order `read_inst` reads them.
+fn write_inst(w: &mut Writer, func: &Function, inst: Inst) {
+    use ir::InstructionData::*;
+
+    let pool = &func.dfg.value_lists;
+    let data = &func.dfg[inst];
+    w.u8(data.opcode() as u8);
+    match *data {
+        Unary { arg, .. } => entity(w, arg),
+        UnaryImm { imm, .. } => w.u64(imm64_bits(imm)),
+        UnaryIeee32 { imm, .. } => w.u32(imm.bits()),
+        UnaryIeee64 { imm, .. } => w.u64(imm.bits()),
+        UnaryBool { imm, .. } => w.u8(imm as u8),
+        UnaryGlobalVar { global_var, .. } => entity(w, global_var),
+        Binary { args, .. } => {
+            entity(w, args[0]);
+            entity(w, args[1]);
+        }
+        BinaryImm { arg, imm, .. } => {
+            entity(w, arg);
+            w.u64(imm64_bits(imm));
+        }
+        Ternary { args, .. } => {
+            entity(w, args[0]);
+            entity(w, args[1]);
+            entity(w, args[2]);
+        }
+        MultiAry { ref args, .. } => write_values(w, args.as_slice(pool)),
+        NullAry { .. } => {}
+        InsertLane { args, lane, .. } => {
+            entity(w, args[0]);
+            entity(w, args[1]);
+            w.u8(lane);
+        }
+        ExtractLane { arg, lane, .. } => {
+            entity(w, arg);
+            w.u8(lane);
+        }
+        IntCompare { cond, args, .. } => {
+            w.u8(encode(&INTCCS, cond));
+            entity(w, args[0]);
+            entity(w, args[1]);
+        }
+        IntCompareImm { cond, arg, imm, .. } => {
+            w.u8(encode(&INTCCS, cond));
+            entity(w, arg);
+            w.u64(imm64_bits(imm));
+        }
+        IntCond { cond, arg, .. } => {
+            w.u8(encode(&INTCCS, cond));
+            entity(w, arg);
+        }
+        FloatCompare { cond, args, .. } => {
+            w.u8(encode(&FLOATCCS, cond));
+            entity(w, args[0]);
+            entity(w, args[1]);
+        }
+        FloatCond { cond, arg, .. } => {
+            w.u8(encode(&FLOATCCS, cond));
+            entity(w, arg);
+        }
+        IntSelect { cond, args, .. } => {
+            w.u8(encode(&INTCCS, cond));
+            entity(w, args[0]);
+            entity(w, args[1]);
+            entity(w, args[2]);
+        }
+        Jump {
+            destination,
+            ref args,
+            ..
+        } |
+        Branch {
+            destination,
+            ref args,
+            ..
+        } => {
+            entity(w, destination);
+            write_values(w, args.as_slice(pool));
+        }
+        BranchInt {
+            cond,
+            destination,
+            ref args,
+            ..
+        } |
+        BranchIcmp {
+            cond,
+            destination,
+            ref args,
+            ..
+        } => {
+            w.u8(encode(&INTCCS, cond));
+            entity(w, destination);
+            write_values(w, args.as_slice(pool));
+        }
+        BranchFloat {
+            cond,
+            destination,
+            ref args,
+            ..
+        } => {
+            w.u8(encode(&FLOATCCS, cond));
+            entity(w, destination);
+            write_values(w, args.as_slice(pool));
+        }
+        BranchTable { arg, table, .. } => {
+            entity(w, arg);
+            entity(w, table);
+        }
+        Call {
+            func_ref, ref args, ..
+        } => {
+            entity(w, func_ref);
+            write_values(w, args.as_slice(pool));
+        }
+        IndirectCall {
+            sig_ref, ref args, ..
+        } => {
+            entity(w, sig_ref);
+            write_values(w, args.as_slice(pool));
+        }
+        FuncAddr { func_ref, .. } => entity(w, func_ref),
+        Load {
+            flags, arg, offset, ..
+        } => {
+            write_memflags(w, flags);
+            entity(w, arg);
+            w.u32(offset32_bits(offset));
+        }
+        Store {
+            flags,
+            args,
+            offset,
+            ..
+        } => {
+            write_memflags(w, flags);
+            entity(w, args[0]);
+            entity(w, args[1]);
+            w.u32(offset32_bits(offset));
+        }
+        StackLoad {
+            stack_slot, offset, ..
+        } => {
+            entity(w, stack_slot);
+            w.u32(offset32_bits(offset));
+        }
+        StackStore {
+            arg,
+            stack_slot,
+            offset,
+            ..
+        } => {
+            entity(w, arg);
+            entity(w, stack_slot);
+            w.u32(offset32_bits(offset));
+        }
+        HeapAddr { heap, arg, imm, .. } => {
+            entity(w, heap);
+            entity(w, arg);
+            w.u32(imm.into());
+        }
+        RegMove { arg, src, dst, .. } => {
+            entity(w, arg);
+            w.u16(src);
+            w.u16(dst);
+        }
+        CopySpecial { src, dst, .. } => {
+            w.u16(src);
+            w.u16(dst);
+        }
+        RegSpill { arg, src, dst, .. } => {
+            entity(w, arg);
+            w.u16(src);
+            entity(w, dst);
+        }
+        RegFill { arg, src, dst, .. } => {
+            entity(w, arg);
+            entity(w, src);
+            w.u16(dst);
+        }
+        Trap { code, .. } => write_trapcode(w, code),
+        CondTrap { arg, code, .. } => {
+            entity(w, arg);
+            write_trapcode(w, code);
+        }
+        IntCondTrap { cond, arg, code, .. } => {
+            w.u8(encode(&INTCCS, cond));
+            entity(w, arg);
+            write_trapcode(w, code);
+        }
+        FloatCondTrap { cond, arg, code, .. } => {
+            w.u8(encode(&FLOATCCS, cond));
+            entity(w, arg);
+            write_trapcode(w, code);
+        }
+    }
+}
+
+fn entity<E: EntityRef>(w: &mut Writer, e: E) {
+    w.u32(e.index() as u32);
+}
+
+fn imm64_bits(x: Imm64) -> u64 {
+    let x: i64 = x.into();
+    x as u64
+}
+
+fn offset32_bits(x: Offset32) -> u32 {
+    let x: i32 = x.into();
+    x as u32
+}
+
+fn write_values(w: &mut Writer, values: &[Value]) {
+    w.u32(values.len() as u32);
+    for &v in values {
+        entity(w, v);
+    }
+}
+
+fn write_option(w: &mut Writer, x: Option<u32>) {
+    match x {
+        None => w.u8(0),
+        Some(x) => {
+            w.u8(1);
+            w.u32(x);
+        }
+    }
+}
+
+fn read_option(r: &mut Reader) -> ReadResult<Option<u32>> {
+    match r.u8()? {
+        0 => Ok(None),
+        1 => Ok(Some(r.u32()?)),
+        _ => Err(ReadError::Corrupt("option")),
+    }
+}
+
+fn write_memflags(w: &mut Writer, flags: MemFlags) {
+    w.u8(flags.notrap() as u8 | (flags.aligned() as u8) << 1);
+}
+
+fn write_trapcode(w: &mut Writer, code: TrapCode) {
+    match code {
+        TrapCode::User(x) => {
+            w.u8(0xff);
+            w.u16(x);
+        }
+        code => w.u8(encode(&TRAPCODES, code)),
+    }
+}
+
+/// Scalar lane types in the order of their encoding.
+const LANE_TYPES: [Type; 11] = [
+    types::B1,
+    types::B8,
+    types::B16,
+    types::B32,
+    types::B64,
+    types::I8,
+    types::I16,
+    types::I32,
+    types::I64,
+    types::F32,
+    types::F64,
+];
+
+fn write_type(w: &mut Writer, ty: Type) {
+    w.u8(ty.index() as u8);
+}
+
+fn read_type(r: &mut Reader) -> ReadResult<Type> {
+    let bits = r.u8()?;
+    let ty = match bits {
+        0 => Some(types::VOID),
+        1 => Some(types::IFLAGS),
+        2 => Some(types::FFLAGS),
+        _ if bits >= LANE_TYPES[0].index() as u8 => {
+            let lane = usize::from(bits & 0x0f);
+            let log2_lanes = (bits - LANE_TYPES[0].index() as u8) >> 4;
+            LANE_TYPES.get(lane).and_then(|t| t.by(1 << log2_lanes))
+        }
+        _ => None,
+    };
+    match ty {
+        Some(ty) if ty.index() == usize::from(bits) => Ok(ty),
+        _ => Err(ReadError::Corrupt("type")),
+    }
+}
+
+fn write_signature(w: &mut Writer, sig: &Signature) {
+    w.u8(encode(&CALL_CONVS, sig.call_conv));
+    write_option(w, sig.argument_bytes);
+    for params in &[&sig.params, &sig.returns] {
+        w.u32(params.len() as u32);
+        for param in params.iter() {
+            write_type(w, param.value_type);
+            w.u8(encode(&PURPOSES, param.purpose));
+            w.u8(encode(&EXTENSIONS, param.extension));
+            match param.location {
+                ArgumentLoc::Unassigned => w.u8(0),
+                ArgumentLoc::Reg(ru) => {
+                    w.u8(1);
+                    w.u16(ru);
+                }
+                ArgumentLoc::Stack(offset) => {
+                    w.u8(2);
+                    w.u32(offset as u32);
+                }
+            }
+        }
+    }
+}
+
+fn read_signature(r: &mut Reader) -> ReadResult<Signature> {
+    let mut sig = Signature::new(decode(&CALL_CONVS, r.u8()?, "calling convention")?);
+    sig.argument_bytes = read_option(r)?;
+    for i in 0..2 {
+        for _ in 0..r.u32()? {
+            let mut param = AbiParam::new(read_type(r)?);
+            param.purpose = decode(&PURPOSES, r.u8()?, "argument purpose")?;
+            param.extension = decode(&EXTENSIONS, r.u8()?, "argument extension")?;
+            param.location = match r.u8()? {
+                0 => ArgumentLoc::Unassigned,
+                1 => ArgumentLoc::Reg(r.u16()?),
+                2 => ArgumentLoc::Stack(r.u32()? as i32),
+                _ => return Err(ReadError::Corrupt("argument location")),
+            };
+            if i == 0 {
+                sig.params.push(param);
+            } else {
+                sig.returns.push(param);
+            }
+        }
+    }
+    Ok(sig)
+}
+
+/// Get the encoding of `x`, which is its index in `table`.
+fn encode<T: PartialEq>(table: &[T], x: T) -> u8 {
+    table.iter().position(|t| *t == x).expect(
+        "missing from encoding table",
+    ) as u8
+}
+
+fn decode<T: Copy>(table: &[T], code: u8, what: &'static str) -> ReadResult<T> {
+    table.get(code as usize).cloned().ok_or(
+        ReadError::Corrupt(what),
+    )
+}
+
+// Encoding tables for enums. Never reorder these; only append new variants.
+
+const CALL_CONVS: [CallConv; 2] = [CallConv::Native, CallConv::SpiderWASM];
+
+const PURPOSES: [ArgumentPurpose; 7] = [
+    ArgumentPurpose::Normal,
+    ArgumentPurpose::StructReturn,
+    ArgumentPurpose::Link,
+    ArgumentPurpose::FramePointer,
+    ArgumentPurpose::CalleeSaved,
+    ArgumentPurpose::VMContext,
+    ArgumentPurpose::SignatureId,
+];
+
+const EXTENSIONS: [ArgumentExtension; 3] = [
+    ArgumentExtension::None,
+    ArgumentExtension::Uext,
+    ArgumentExtension::Sext,
+];
+
+const STACK_SLOT_KINDS: [StackSlotKind; 5] = [
+    StackSlotKind::SpillSlot,
+    StackSlotKind::ExplicitSlot,
+    StackSlotKind::IncomingArg,
+    StackSlotKind::OutgoingArg,
+    StackSlotKind::EmergencySlot,
+];
+
+const INTCCS: [IntCC; 10] = [
+    IntCC::Equal,
+    IntCC::NotEqual,
+    IntCC::SignedLessThan,
+    IntCC::SignedGreaterThanOrEqual,
+    IntCC::SignedGreaterThan,
+    IntCC::SignedLessThanOrEqual,
+    IntCC::UnsignedLessThan,
+    IntCC::UnsignedGreaterThanOrEqual,
+    IntCC::UnsignedGreaterThan,
+    IntCC::UnsignedLessThanOrEqual,
+];
+
+const FLOATCCS: [FloatCC; 14] = [
+    FloatCC::Ordered,
+    FloatCC::Unordered,
+    FloatCC::Equal,
+    FloatCC::NotEqual,
+    FloatCC::OrderedNotEqual,
+    FloatCC::UnorderedOrEqual,
+    FloatCC::LessThan,
+    FloatCC::LessThanOrEqual,
+    FloatCC::GreaterThan,
+    FloatCC::GreaterThanOrEqual,
+    FloatCC::UnorderedOrLessThan,
+    FloatCC::UnorderedOrLessThanOrEqual,
+    FloatCC::UnorderedOrGreaterThan,
+    FloatCC::UnorderedOrGreaterThanOrEqual,
+];
+
+const TRAPCODES: [TrapCode; 8] = [
+    TrapCode::StackOverflow,
+    TrapCode::HeapOutOfBounds,
+    TrapCode::OutOfBounds,
+    TrapCode::IndirectCallToNull,
+    TrapCode::BadSignature,
+    TrapCode::IntegerOverflow,
+    TrapCode::IntegerDivisionByZero,
+    TrapCode::BadConversionToInteger,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{ExternalName, InstBuilder, StackSlotKind};
+    use std::string::ToString;
+
+    fn sample() -> Function {
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.returns.push(AbiParam::new(types::I32));
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig.clone());
+        let ss = func.stack_slots.push(
+            StackSlotData::new(StackSlotKind::ExplicitSlot, 4),
+        );
+        let sigref = func.import_signature(sig);
+        let fref = func.import_function(ExtFuncData {
+            name: ExternalName::user(0, 1),
+            signature: sigref,
+        });
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let mut jt = JumpTableData::new();
+        jt.set_entry(1, ebb1);
+        let jt = func.jump_tables.push(jt);
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let arg = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            let c = pos.ins().iconst(types::I32, -7);
+            let sum = pos.ins().iadd(arg, c);
+            pos.ins().stack_store(sum, ss, 0);
+            let call = pos.ins().call(fref, &[sum]);
+            let res = pos.func.dfg.first_result(call);
+            let f = pos.ins().f64const(Ieee64::with_float(1.5));
+            pos.ins().fcmp(FloatCC::UnorderedOrLessThan, f, f);
+            pos.ins().br_table(res, jt);
+            pos.ins().brnz(res, ebb1, &[res]);
+            pos.ins().trap(TrapCode::User(3));
+
+            pos.insert_ebb(ebb1);
+            let param = pos.func.dfg.append_ebb_param(ebb1, types::I32);
+            pos.ins().return_(&[param]);
+            pos.func.srclocs[call] = SourceLoc::new(42);
+        }
+        // Create an alias.
+        let v = func.dfg.append_ebb_param(ebb1, types::I32);
+        func.dfg.remove_ebb_param(v);
+        let first = func.dfg.ebb_params(ebb0)[0];
+        func.dfg.change_to_alias(v, first);
+        let label = func.create_value_label(ValueLabelData::new("x", SourceLoc::new(7)));
+        func.label_value(first, label);
+        func
+    }
+
+    #[test]
+    fn round_trip() {
+        let func = sample();
+        let bytes = serialize_function(&func);
+        let copy = deserialize_function(&bytes).unwrap();
+        assert_eq!(copy.to_string(), func.to_string());
+        assert_eq!(copy.srclocs[Inst::new(3)], SourceLoc::new(42));
+        assert_eq!(copy.value_labels[ValueLabel::new(0)].name, "x");
+        assert_eq!(copy.value_labels_of(Value::new(0)), [ValueLabel::new(0)]);
+        assert_eq!(serialize_function(&copy), bytes);
+    }
+
+    #[test]
+    fn errors() {
+        let bytes = serialize_function(&sample());
+        assert_eq!(
+            deserialize_function(&bytes[0..bytes.len() - 1]).unwrap_err(),
+            DeserializeError::Truncated
+        );
+        assert_eq!(
+            deserialize_function(b"CTONIL\0\0\x09\0\0\0").unwrap_err(),
+            DeserializeError::VersionMismatch(9)
+        );
+        assert_eq!(
+            deserialize_function(b"function").unwrap_err(),
+            DeserializeError::BadMagic
+        );
+        // Truncating or corrupting the data at any point must not panic.
+        for len in 0..bytes.len() {
+            assert!(deserialize_function(&bytes[0..len]).is_err());
+            let mut corrupt = bytes.clone();
+            corrupt[len] ^= 0x55;
+            let _ = deserialize_function(&corrupt);
+        }
+    }
+
+    #[test]
+    fn types() {
+        for &ty in &[types::VOID, types::IFLAGS, types::I32, types::F64X2, types::B8X16] {
+            let mut w = Writer::new();
+            write_type(&mut w, ty);
+            assert_eq!(read_type(&mut Reader(&w.0)), Ok(ty));
+        }
+        assert!(read_type(&mut Reader(&[3])).is_err());
+        assert!(read_type(&mut Reader(&[0x7b])).is_err());
+    }
+}
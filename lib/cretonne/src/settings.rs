@@ -357,6 +357,7 @@ mod tests {
             "[shared]\n\
                     opt_level = \"default\"\n\
                     enable_verifier = true\n\
+                    verifier_level = \"full\"\n\
                     is_64bit = false\n\
                     is_pic = false\n\
                     return_at_end = false\n\
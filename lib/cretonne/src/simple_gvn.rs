@@ -18,8 +18,8 @@ fn trivially_unsafe_for_gvn(opcode: Opcode) -> bool {
 ///
 pub fn do_simple_gvn(func: &mut Function, cfg: &mut ControlFlowGraph, domtree: &mut DominatorTree) {
     let _tt = timing::gvn();
-    debug_assert!(cfg.is_valid());
-    debug_assert!(domtree.is_valid());
+    cfg.assert_current(func);
+    domtree.assert_current(func);
 
     let mut visible_values: ScopedHashMap<(InstructionData, Type), Inst> = ScopedHashMap::new();
     let mut scope_stack: Vec<Inst> = Vec::new();
@@ -0,0 +1,142 @@
+//! Source maps.
+//!
+//! Frontends attach source locations to the instructions they generate, such as the offsets of
+//! the WebAssembly instructions the IL was translated from, and the passes carry them over to the
+//! instructions they insert. After compilation, a `SourceMap` maps the machine code of the
+//! function back to those source locations, so a profiler can symbolize its samples, and a
+//! WebAssembly runtime can report the offset of the instruction that trapped.
+//!
+//! The map is computed from the final code layout, so it describes exactly the code emitted by
+//! `Context::emit_to_memory`.
+
+use binemit::CodeOffset;
+use ir::{Function, SourceLoc};
+use isa::TargetIsa;
+
+/// A range of machine code generated for a single source location.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceLocRange {
+    /// The source location.
+    pub srcloc: SourceLoc,
+    /// Offset of the first byte of the range.
+    pub start: CodeOffset,
+    /// Offset of the end of the range, exclusive.
+    pub end: CodeOffset,
+}
+
+/// The source locations of the machine code of a compiled function.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// Non-empty ranges sorted by offset, without overlaps. Adjacent instructions with the same
+    /// source location share a range, and code without a source location isn't covered.
+    ranges: Vec<SourceLocRange>,
+}
+
+impl SourceMap {
+    /// Compute the source map of `func`.
+    ///
+    /// The function must have been compiled for `isa`, so that the code layout is known.
+    pub fn new(func: &Function, isa: &TargetIsa) -> Self {
+        let encinfo = isa.encoding_info();
+        let mut ranges: Vec<SourceLocRange> = Vec::new();
+        for ebb in func.layout.ebbs() {
+            for (offset, inst, size) in func.inst_offsets(ebb, &encinfo) {
+                let srcloc = func.srclocs.get(inst).cloned().unwrap_or_default();
+                if size == 0 || srcloc.is_default() {
+                    continue;
+                }
+                if let Some(last) = ranges.last_mut() {
+                    if last.srcloc == srcloc && last.end == offset {
+                        last.end = offset + size;
+                        continue;
+                    }
+                }
+                ranges.push(SourceLocRange {
+                    srcloc,
+                    start: offset,
+                    end: offset + size,
+                });
+            }
+        }
+        Self { ranges }
+    }
+
+    /// Get the ranges of the map, sorted by offset.
+    pub fn ranges(&self) -> &[SourceLocRange] {
+        &self.ranges
+    }
+
+    /// Get the source location of the code at `offset`, if it has one.
+    ///
+    /// Any offset within an instruction finds the source location of the instruction. To
+    /// symbolize the return address of a call, look up the offset before it.
+    pub fn lookup(&self, offset: CodeOffset) -> Option<SourceLoc> {
+        let index = match self.ranges.binary_search_by_key(&offset, |range| range.start) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let range = &self.ranges[index];
+        if offset < range.end {
+            Some(range.srcloc)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Context;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{AbiParam, CallConv, ExternalName, InstBuilder, Signature, types};
+    use isa;
+    use settings;
+
+    #[test]
+    fn map() {
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(
+            &settings::builder(),
+        ));
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.returns.push(AbiParam::new(types::I32));
+        let mut ctx = Context::for_function(
+            Function::with_name_signature(ExternalName::testcase("f"), sig),
+        );
+        {
+            let ebb = ctx.func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut ctx.func);
+            pos.insert_ebb(ebb);
+            let arg = pos.func.dfg.append_ebb_param(ebb, types::I32);
+            pos.set_srcloc(SourceLoc::new(10));
+            let sum = pos.ins().iadd_imm(arg, 3);
+            pos.set_srcloc(SourceLoc::new(20));
+            let prod = pos.ins().imul(sum, arg);
+            let prod = pos.ins().imul(prod, prod);
+            pos.set_srcloc(SourceLoc::default());
+            pos.ins().return_(&[prod]);
+        }
+        let code_size = ctx.compile(&*isa).unwrap();
+
+        let map = ctx.source_map(&*isa);
+        let ranges = map.ranges();
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[0].srcloc, SourceLoc::new(10));
+        assert_eq!(ranges[1].srcloc, SourceLoc::new(20));
+        assert!(ranges[0].start < ranges[0].end);
+        assert!(ranges[0].end <= ranges[1].start);
+        assert!(ranges[1].end <= code_size);
+
+        assert_eq!(map.lookup(ranges[0].start), Some(SourceLoc::new(10)));
+        assert_eq!(map.lookup(ranges[0].end - 1), Some(SourceLoc::new(10)));
+        assert_eq!(map.lookup(ranges[1].start), Some(SourceLoc::new(20)));
+        assert_eq!(map.lookup(ranges[1].end - 1), Some(SourceLoc::new(20)));
+        assert_eq!(map.lookup(ranges[1].end), None);
+        assert_eq!(map.lookup(code_size), None);
+        if ranges[0].start > 0 {
+            assert_eq!(map.lookup(0), None);
+        }
+    }
+}
@@ -2,7 +2,7 @@
 
 use ir::StackSlots;
 use ir::stackslot::{StackSize, StackOffset, StackSlotKind};
-use result::CtonError;
+use result::CodegenError;
 use std::cmp::{min, max};
 
 /// Compute the stack frame layout.
@@ -14,8 +14,12 @@ use std::cmp::{min, max};
 ///
 /// Returns the total stack frame size which is also saved in `frame.frame_size`.
 ///
-/// If the stack frame is too big, returns an `ImplLimitExceeded` error.
-pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> Result<StackSize, CtonError> {
+/// If the stack frame is too big, or a slot requires a larger alignment than `alignment`, returns
+/// an `ImplLimitExceeded` error.
+pub fn layout_stack(
+    frame: &mut StackSlots,
+    alignment: StackSize,
+) -> Result<StackSize, CodegenError> {
     // Each object and the whole stack frame must fit in 2 GB such that any relative offset within
     // the frame fits in a `StackOffset`.
     let max_size = StackOffset::max_value() as StackSize;
@@ -43,7 +47,7 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> Result<Stac
         let slot = &frame[ss];
 
         if slot.size > max_size {
-            return Err(CtonError::ImplLimitExceeded);
+            return Err(CodegenError::ImplLimitExceeded);
         }
 
         match slot.kind {
@@ -54,12 +58,17 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> Result<Stac
                 let offset = slot.offset
                     .unwrap()
                     .checked_add(slot.size as StackOffset)
-                    .ok_or(CtonError::ImplLimitExceeded)?;
+                    .ok_or(CodegenError::ImplLimitExceeded)?;
                 outgoing_max = max(outgoing_max, offset);
             }
             StackSlotKind::SpillSlot |
             StackSlotKind::ExplicitSlot |
             StackSlotKind::EmergencySlot => {
+                // Offsets are relative to the stack pointer, so no slot can be aligned more than
+                // the stack pointer itself.
+                if slot.align.map_or(false, |align| align > alignment) {
+                    return Err(CodegenError::ImplLimitExceeded);
+                }
                 // Determine the smallest alignment of any explicit or spill slot.
                 min_align = slot.alignment(min_align);
             }
@@ -89,7 +98,7 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> Result<Stac
             }
 
             offset = offset.checked_sub(slot.size as StackOffset).ok_or(
-                CtonError::ImplLimitExceeded,
+                CodegenError::ImplLimitExceeded,
             )?;
 
             // Aligning the negative offset can never cause overflow. We're only clearing bits.
@@ -103,7 +112,7 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> Result<Stac
 
     // Finally, make room for the outgoing arguments.
     offset = offset.checked_sub(outgoing_max).ok_or(
-        CtonError::ImplLimitExceeded,
+        CodegenError::ImplLimitExceeded,
     )?;
     offset &= -(alignment as StackOffset);
 
@@ -118,7 +127,7 @@ mod tests {
     use ir::types;
     use super::layout_stack;
     use ir::stackslot::StackOffset;
-    use result::CtonError;
+    use result::CodegenError;
 
     #[test]
     fn layout() {
@@ -192,7 +201,7 @@ mod tests {
 
         // Also test that an unsupported offset is rejected.
         sss.get_outgoing_arg(types::I8, StackOffset::max_value() - 1);
-        assert_eq!(layout_stack(sss, 1), Err(CtonError::ImplLimitExceeded));
+        assert_eq!(layout_stack(sss, 1), Err(CodegenError::ImplLimitExceeded));
     }
 
     #[test]
@@ -212,4 +221,22 @@ mod tests {
         assert_eq!(sss[ss1].offset, Some(-8));
         assert_eq!(sss[ss2].offset, Some(-12));
     }
+
+    #[test]
+    fn required_alignment() {
+        let sss = &mut StackSlots::new();
+
+        let ss0 = sss.make_spill_slot(types::I32);
+        let mut data = StackSlotData::new(StackSlotKind::ExplicitSlot, 12);
+        data.align = Some(8);
+        let ss1 = sss.push(data.clone());
+
+        assert_eq!(layout_stack(sss, 16), Ok(16));
+        assert_eq!(sss[ss0].offset, Some(-4));
+        assert_eq!(sss[ss1].offset, Some(-16));
+
+        data.align = Some(32);
+        sss.push(data);
+        assert_eq!(layout_stack(sss, 16), Err(CodegenError::ImplLimitExceeded));
+    }
 }
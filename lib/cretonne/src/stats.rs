@@ -0,0 +1,292 @@
+//! IR statistics.
+//!
+//! The statistics count the EBBs, instructions, values, and spills of the functions compiled by a
+//! `Context`, along with the size of their code. They are recorded at several stages of the
+//! compilation pipeline and accumulated over all the compiled functions, which shows how each
+//! group of passes changes the code, and which instructions the code size comes from.
+//!
+//! Collecting statistics is enabled with `Context::enable_stats`.
+
+use binemit::CodeOffset;
+use ir::{Function, Opcode};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A stage of the compilation pipeline where statistics are recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// The input function, before any passes have run.
+    Input,
+    /// After legalization.
+    Legalized,
+    /// After the optimization passes and the removal of unreachable code.
+    Optimized,
+    /// After register allocation.
+    Allocated,
+    /// The final function, after prologue/epilogue insertion and branch relaxation.
+    Final,
+}
+
+/// The number of stages.
+const NUM_STAGES: usize = 5;
+
+/// All the stages in pipeline order.
+pub const STAGES: [Stage; NUM_STAGES] = [
+    Stage::Input,
+    Stage::Legalized,
+    Stage::Optimized,
+    Stage::Allocated,
+    Stage::Final,
+];
+
+impl Stage {
+    /// Get the name of this stage, as used in reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Input => "input",
+            Stage::Legalized => "legalized",
+            Stage::Optimized => "optimized",
+            Stage::Allocated => "allocated",
+            Stage::Final => "final",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Statistics of the functions that reached one stage.
+#[derive(Clone, Debug, Default)]
+pub struct StageStats {
+    /// Number of functions.
+    pub functions: u64,
+    /// Number of EBBs in the layout.
+    pub ebbs: u64,
+    /// Number of instructions in the layout.
+    pub insts: u64,
+    /// Number of values defined by the EBB parameters and instructions in the layout.
+    pub values: u64,
+    /// Number of `spill` instructions.
+    pub spills: u64,
+    /// Number of `fill` instructions.
+    pub fills: u64,
+    /// Size of the machine code in bytes. This is only known at the final stage.
+    pub code_bytes: u64,
+    /// Number of instructions with each opcode.
+    pub opcodes: HashMap<Opcode, u64>,
+}
+
+impl StageStats {
+    /// Add the statistics of `func`.
+    pub fn add_function(&mut self, func: &Function) {
+        self.functions += 1;
+        for ebb in func.layout.ebbs() {
+            self.ebbs += 1;
+            self.values += func.dfg.num_ebb_params(ebb) as u64;
+            for inst in func.layout.ebb_insts(ebb) {
+                let opcode = func.dfg[inst].opcode();
+                self.insts += 1;
+                self.values += func.dfg.inst_results(inst).len() as u64;
+                match opcode {
+                    Opcode::Spill => self.spills += 1,
+                    Opcode::Fill => self.fills += 1,
+                    _ => {}
+                }
+                *self.opcodes.entry(opcode).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// Get the number of instructions with `opcode`.
+    pub fn opcode_count(&self, opcode: Opcode) -> u64 {
+        self.opcodes.get(&opcode).cloned().unwrap_or(0)
+    }
+}
+
+/// Statistics accumulated over the compiled functions, per stage.
+#[derive(Clone, Debug, Default)]
+pub struct Statistics {
+    stages: [StageStats; NUM_STAGES],
+}
+
+impl Statistics {
+    /// Create empty statistics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Clear all the statistics.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Add the statistics of `func` at `stage`.
+    pub fn record(&mut self, stage: Stage, func: &Function) {
+        self.stages[stage as usize].add_function(func);
+    }
+
+    /// Add the size of the machine code of a compiled function.
+    pub fn record_code_size(&mut self, code_size: CodeOffset) {
+        self.stages[Stage::Final as usize].code_bytes += u64::from(code_size);
+    }
+
+    /// Get the statistics of `stage`.
+    pub fn stage(&self, stage: Stage) -> &StageStats {
+        &self.stages[stage as usize]
+    }
+}
+
+/// Print a table of the counts per stage, followed by a table of the instructions per opcode.
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}",
+            "stage",
+            "functions",
+            "ebbs",
+            "insts",
+            "values",
+            "spills",
+            "fills",
+            "bytes"
+        )?;
+        for &stage in &STAGES {
+            let s = self.stage(stage);
+            writeln!(
+                f,
+                "{:<12}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}",
+                stage.name(),
+                s.functions,
+                s.ebbs,
+                s.insts,
+                s.values,
+                s.spills,
+                s.fills,
+                s.code_bytes
+            )?;
+        }
+
+        let mut opcodes: Vec<Opcode> = self.stages
+            .iter()
+            .flat_map(|s| s.opcodes.keys().cloned())
+            .collect();
+        opcodes.sort_by_key(|opcode| opcode.to_string());
+        opcodes.dedup();
+        if opcodes.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(f)?;
+        write!(f, "{:<22}", "opcode")?;
+        for &stage in &STAGES {
+            write!(f, "{:>10}", stage.name())?;
+        }
+        writeln!(f)?;
+        for opcode in opcodes {
+            write!(f, "{:<22}", opcode.to_string())?;
+            for &stage in &STAGES {
+                write!(f, "{:>10}", self.stage(stage).opcode_count(opcode))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Context;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{AbiParam, CallConv, ExternalName, InstBuilder, Signature, types};
+    use isa;
+    use settings::{self, Configurable};
+
+    #[test]
+    fn counts() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let v0 = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            let v1 = pos.ins().iadd(v0, v0);
+            let v2 = pos.ins().spill(v1);
+            pos.ins().jump(ebb1, &[]);
+            pos.insert_ebb(ebb1);
+            let v3 = pos.ins().fill(v2);
+            pos.ins().iadd(v3, v3);
+            pos.ins().return_(&[]);
+        }
+
+        let mut stats = Statistics::new();
+        stats.record(Stage::Input, &func);
+        stats.record(Stage::Input, &func);
+        stats.record(Stage::Final, &func);
+        stats.record_code_size(20);
+
+        let input = stats.stage(Stage::Input);
+        assert_eq!(input.functions, 2);
+        assert_eq!(input.ebbs, 4);
+        assert_eq!(input.insts, 12);
+        assert_eq!(input.values, 10);
+        assert_eq!(input.spills, 2);
+        assert_eq!(input.fills, 2);
+        assert_eq!(input.code_bytes, 0);
+        assert_eq!(input.opcode_count(Opcode::Iadd), 4);
+        assert_eq!(input.opcode_count(Opcode::Imul), 0);
+        assert_eq!(stats.stage(Stage::Legalized).functions, 0);
+        assert_eq!(stats.stage(Stage::Final).code_bytes, 20);
+
+        let text = stats.to_string();
+        assert!(text.contains(
+            "input                2         4        12        10         2         2         0",
+        ));
+        assert!(text.contains(
+            "iadd                           4         0         0         0         2",
+        ));
+
+        stats.clear();
+        assert_eq!(stats.stage(Stage::Input).functions, 0);
+    }
+
+    #[test]
+    fn compile() {
+        let mut flag_builder = settings::builder();
+        flag_builder.enable("is_64bit").unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&flag_builder));
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(types::I64));
+        let mut ctx = Context::for_function(
+            Function::with_name_signature(ExternalName::testcase("f"), sig),
+        );
+        {
+            let ebb0 = ctx.func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut ctx.func);
+            pos.insert_ebb(ebb0);
+            let v0 = pos.func.dfg.append_ebb_param(ebb0, types::I64);
+            let v1 = pos.ins().iadd_imm(v0, 1);
+            pos.ins().return_(&[v1]);
+        }
+
+        let func = ctx.func.clone();
+        ctx.compile(&*isa).unwrap();
+        assert!(ctx.stats().is_none());
+
+        ctx.enable_stats();
+        ctx.reset(func);
+        let code_size = ctx.compile(&*isa).unwrap();
+        let stats = ctx.take_stats().unwrap();
+        for &stage in &STAGES {
+            assert_eq!(stats.stage(stage).functions, 1);
+        }
+        assert_eq!(stats.stage(Stage::Final).code_bytes, u64::from(code_size));
+        assert!(ctx.stats().is_none());
+    }
+}
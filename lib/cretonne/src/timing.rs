@@ -50,6 +50,7 @@ define_passes!{
     verify_liveness: "Verify live ranges",
     verify_locations: "Verify value locations",
     verify_flags: "Verify CPU flags",
+    verify_encodings: "Verify instruction encodings",
 
     compile: "Compilation passes",
     flowgraph: "Control flow graph",
@@ -133,6 +134,21 @@ mod details {
         pass: [PassTime; NUM_PASSES],
     }
 
+    impl PassTimes {
+        /// Get the description, the total time, and the self time of each pass that has run.
+        pub fn passes(&self) -> Vec<(&'static str, Duration, Duration)> {
+            self.pass
+                .iter()
+                .zip(&DESCRIPTIONS)
+                .filter(|&(time, _)| time.total != Duration::default())
+                .map(|(time, &desc)| {
+                    let self_time = time.total.checked_sub(time.child).unwrap_or_default();
+                    (desc, time.total, self_time)
+                })
+                .collect()
+        }
+    }
+
     impl fmt::Display for PassTimes {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             writeln!(f, "======== ========  ==================================")?;
@@ -173,7 +189,7 @@ mod details {
     /// This function is called by the publicly exposed pass functions.
     pub(super) fn start_pass(pass: Pass) -> TimingToken {
         let prev = CURRENT_PASS.with(|p| p.replace(pass));
-        dbg!("timing: Starting {}, (during {})", pass, prev);
+        trace!("timing: Starting {}, (during {})", pass, prev);
         TimingToken {
             start: Instant::now(),
             pass,
@@ -185,7 +201,7 @@ mod details {
     impl Drop for TimingToken {
         fn drop(&mut self) {
             let duration = self.start.elapsed();
-            dbg!("timing: Ending {}", self.pass);
+            trace!("timing: Ending {}", self.pass);
             let old_cur = CURRENT_PASS.with(|p| p.replace(self.prev));
             debug_assert_eq!(self.pass, old_cur, "Timing tokens dropped out of order");
             PASS_TIME.with(|rc| {
@@ -224,4 +240,21 @@ mod test {
         assert_eq!(Pass::NoPass.to_string(), "<no pass>");
         assert_eq!(Pass::regalloc.to_string(), "Register allocation");
     }
+
+    #[test]
+    fn passes() {
+        take_current();
+        {
+            let _tt = regalloc();
+            let _tt = ra_coloring();
+        }
+        let times = take_current();
+        let passes = times.passes();
+        assert_eq!(passes.len(), 2);
+        assert_eq!(passes[0].0, "Register allocation");
+        assert!(passes[0].1 >= passes[0].2);
+        assert_eq!(passes[1].0, "RA coloring");
+        assert_eq!(passes[1].1, passes[1].2);
+        assert!(take_current().passes().is_empty());
+    }
 }
@@ -15,31 +15,32 @@ use timing;
 pub fn eliminate_unreachable_code(
     func: &mut ir::Function,
     cfg: &mut ControlFlowGraph,
-    domtree: &DominatorTree,
+    domtree: &mut DominatorTree,
 ) {
     let _tt = timing::unreachable_code();
+    cfg.assert_current(func);
+    domtree.assert_current(func);
     let mut pos = FuncCursor::new(func);
     while let Some(ebb) = pos.next_ebb() {
         if domtree.is_reachable(ebb) {
             continue;
         }
 
-        dbg!("Eliminating unreachable {}", ebb);
+        debug!("Eliminating unreachable {}", ebb);
         // Move the cursor out of the way and make sure the next lop iteration goes to the right
         // EBB.
         pos.prev_ebb();
 
         // Remove all instructions from `ebb`.
         while let Some(inst) = pos.func.layout.first_inst(ebb) {
-            dbg!(" - {}", pos.func.dfg.display_inst(inst, None));
+            trace!(" - {}", pos.func.dfg.display_inst(inst, None));
             pos.func.layout.remove_inst(inst);
         }
 
-        // Once the EBB is completely empty, we can update the CFG which removes it from any
-        // predecessor lists.
-        cfg.recompute_ebb(pos.func, ebb);
-
-        // Finally, remove the EBB from the layout.
+        // Once the EBB is completely empty, we can remove it from the layout and update the
+        // CFG, which removes it from any predecessor lists.
         pos.func.layout.remove_ebb(ebb);
+        cfg.recompute_ebb(pos.func, ebb);
+        domtree.remove_unreachable_ebb(pos.func, ebb);
     }
 }
@@ -0,0 +1,244 @@
+//! Value label ranges.
+//!
+//! Frontends attach value labels to the SSA values holding source-level variables, see
+//! `Function::label_value`. After compilation, the functions in this module map each label to the
+//! ranges of machine code where the variable is available, along with its location in each range.
+//! This is the raw data needed to describe variable locations in debug information.
+//!
+//! Labels follow the values created from labelled values by the register allocator, such as
+//! copies, spills and fills, and a range is split wherever a value is temporarily diverted to
+//! another register or stack slot.
+//!
+//! The ranges are computed from the code layout, so a value is considered available from its
+//! definition to its last use in layout order.
+
+use binemit::CodeOffset;
+use ir::{Function, InstructionData, Opcode, Value, ValueLabel, ValueLoc};
+use isa::TargetIsa;
+use std::collections::HashMap;
+
+/// A range of machine code where a labelled variable is found in a single location.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValueLocRange {
+    /// The location of the variable.
+    pub loc: ValueLoc,
+    /// Offset of the first instruction where the variable is available.
+    pub start: CodeOffset,
+    /// Offset of the end of the range, exclusive.
+    pub end: CodeOffset,
+}
+
+/// The ranges of each value label, sorted by start offset.
+pub type ValueLabelsRanges = HashMap<ValueLabel, Vec<ValueLocRange>>;
+
+/// The code range and diverted locations of a labelled value.
+struct ValueRange {
+    start: CodeOffset,
+    end: CodeOffset,
+    /// Location changes within the range, as `(offset, new location)` in code order.
+    diversions: Vec<(CodeOffset, ValueLoc)>,
+}
+
+/// Compute the ranges of machine code where each value label is available.
+///
+/// The function must have been compiled for `isa`, so that the code layout is known.
+pub fn value_labels_ranges(func: &Function, isa: &TargetIsa) -> ValueLabelsRanges {
+    let mut ranges = ValueLabelsRanges::new();
+    if func.value_labels.is_empty() {
+        return ranges;
+    }
+    let encinfo = isa.encoding_info();
+
+    // Attach labels to the values they ended up on: resolve aliases, and follow the copies of
+    // labelled values.
+    let mut labels: HashMap<Value, Vec<ValueLabel>> = HashMap::new();
+    for value in func.labelled_values.keys() {
+        let attached = func.value_labels_of(value);
+        if !attached.is_empty() {
+            labels
+                .entry(func.dfg.resolve_aliases(value))
+                .or_insert_with(Vec::new)
+                .extend_from_slice(attached);
+        }
+    }
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg[inst].opcode() {
+                Opcode::Copy | Opcode::Spill | Opcode::Fill => {}
+                _ => continue,
+            }
+            let arg = func.dfg.resolve_aliases(func.dfg.inst_args(inst)[0]);
+            let inherited = match labels.get(&arg) {
+                Some(inherited) => inherited.clone(),
+                None => continue,
+            };
+            let result = func.dfg.first_result(inst);
+            labels.entry(result).or_insert_with(Vec::new).extend(
+                inherited,
+            );
+        }
+    }
+
+    // Find the code range of each labelled value.
+    let mut values: HashMap<Value, ValueRange> = HashMap::new();
+    for ebb in func.layout.ebbs() {
+        for &param in func.dfg.ebb_params(ebb) {
+            if labels.contains_key(&param) {
+                let start = func.offsets[ebb];
+                values.insert(
+                    param,
+                    ValueRange {
+                        start,
+                        end: start,
+                        diversions: Vec::new(),
+                    },
+                );
+            }
+        }
+        for (offset, inst, size) in func.inst_offsets(ebb, &encinfo) {
+            for &arg in func.dfg.inst_args(inst) {
+                if let Some(range) = values.get_mut(&func.dfg.resolve_aliases(arg)) {
+                    range.end = offset + size;
+                }
+            }
+            match func.dfg[inst] {
+                InstructionData::RegMove { arg, dst, .. } => {
+                    divert(&mut values, func, arg, offset + size, ValueLoc::Reg(dst));
+                }
+                InstructionData::RegSpill { arg, dst, .. } => {
+                    divert(&mut values, func, arg, offset + size, ValueLoc::Stack(dst));
+                }
+                InstructionData::RegFill { arg, dst, .. } => {
+                    divert(&mut values, func, arg, offset + size, ValueLoc::Reg(dst));
+                }
+                _ => {}
+            }
+            for &result in func.dfg.inst_results(inst) {
+                if labels.contains_key(&result) {
+                    values.insert(
+                        result,
+                        ValueRange {
+                            start: offset + size,
+                            end: offset + size,
+                            diversions: Vec::new(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    // Split the ranges at the diversions.
+    for (value, range) in values {
+        if range.start >= range.end {
+            continue;
+        }
+        let mut pieces = Vec::new();
+        let mut start = range.start;
+        let mut loc = func.locations[value];
+        for (offset, new_loc) in range.diversions {
+            if offset > start {
+                pieces.push(ValueLocRange {
+                    loc,
+                    start,
+                    end: offset,
+                });
+            }
+            start = offset;
+            loc = new_loc;
+        }
+        if range.end > start {
+            pieces.push(ValueLocRange {
+                loc,
+                start,
+                end: range.end,
+            });
+        }
+        for &label in &labels[&value] {
+            ranges.entry(label).or_insert_with(Vec::new).extend(
+                pieces
+                    .iter()
+                    .filter(|piece| piece.loc.is_assigned())
+                    .cloned(),
+            );
+        }
+    }
+    for label_ranges in ranges.values_mut() {
+        label_ranges.sort_by_key(|range| (range.start, range.end));
+    }
+    ranges
+}
+
+/// Record that the value `arg` moves to `loc` at `offset`, if it is labelled.
+///
+/// Diversions are local to an EBB, and the register allocator moves a diverted value back to its
+/// assigned location before leaving the EBB, so that appears as another diversion.
+fn divert(
+    values: &mut HashMap<Value, ValueRange>,
+    func: &Function,
+    arg: Value,
+    offset: CodeOffset,
+    loc: ValueLoc,
+) {
+    if let Some(range) = values.get_mut(&func.dfg.resolve_aliases(arg)) {
+        range.diversions.push((offset, loc));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Context;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{AbiParam, CallConv, ExternalName, InstBuilder, Signature, SourceLoc, ValueLabelData,
+             types};
+    use isa;
+    use settings;
+
+    #[test]
+    fn ranges() {
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(
+            &settings::builder(),
+        ));
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.returns.push(AbiParam::new(types::I32));
+        let mut ctx = Context::for_function(
+            Function::with_name_signature(ExternalName::testcase("f"), sig),
+        );
+        let x = ctx.func.create_value_label(
+            ValueLabelData::new("x", SourceLoc::new(1)),
+        );
+        let y = ctx.func.create_value_label(
+            ValueLabelData::new("y", SourceLoc::new(2)),
+        );
+        let unused = ctx.func.create_value_label(
+            ValueLabelData::new("unused", SourceLoc::new(3)),
+        );
+        {
+            let ebb = ctx.func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut ctx.func);
+            pos.insert_ebb(ebb);
+            let arg = pos.func.dfg.append_ebb_param(ebb, types::I32);
+            pos.func.label_value(arg, x);
+            let sum = pos.ins().iadd_imm(arg, 3);
+            pos.func.label_value(sum, y);
+            let prod = pos.ins().imul(sum, arg);
+            pos.ins().return_(&[prod]);
+        }
+        let code_size = ctx.compile(&*isa).unwrap();
+
+        let ranges = value_labels_ranges(&ctx.func, &*isa);
+        for label in &[x, y] {
+            let label_ranges = &ranges[label];
+            assert!(!label_ranges.is_empty());
+            for range in label_ranges {
+                assert!(range.start < range.end);
+                assert!(range.end <= code_size);
+                assert!(range.loc.is_assigned());
+            }
+        }
+        assert!(ranges[&x][0].start <= ranges[&y][0].start);
+        assert!(!ranges.contains_key(&unused));
+    }
+}
@@ -0,0 +1,95 @@
+//! Verify instruction encodings after legalization.
+
+use ir;
+use ir::instructions::Opcode;
+use isa;
+use verifier::Result;
+use timing;
+
+/// Verify that every instruction in the legalized `func` can be emitted for `isa`.
+///
+/// The legalizer leaves an instruction without an encoding when the ISA can't encode it and there
+/// is no legalization pattern for it either. Binary emission silently skips instructions without
+/// an encoding, so this would produce wrong code instead of an error.
+///
+/// Only ghost instructions that don't need any machine code may remain unencoded: the
+/// `fallthrough` instruction, and the instructions that split and concatenate values into parts
+/// that fit in registers. The legal encodings themselves are checked by `verify_function`.
+pub fn verify_encodings(isa: &isa::TargetIsa, func: &ir::Function) -> Result {
+    let _tt = timing::verify_encodings();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if func.encodings[inst].is_legal() {
+                continue;
+            }
+            match func.dfg[inst].opcode() {
+                Opcode::Fallthrough | Opcode::Isplit | Opcode::Iconcat | Opcode::Vsplit |
+                Opcode::Vconcat => {}
+                opcode => {
+                    return match isa.encode(
+                        &func.dfg,
+                        &func.dfg[inst],
+                        func.dfg.ctrl_typevar(inst),
+                    ) {
+                        Ok(enc) => {
+                            err!(
+                                inst,
+                                "{} has no encoding (e.g., {})",
+                                opcode,
+                                isa.encoding_info().display(enc)
+                            )
+                        }
+                        Err(_) => err!(inst, "{} can't be encoded for {}", opcode, isa.name()),
+                    };
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::verify_encodings;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{types, AbiParam, Function, InstBuilder};
+    use isa;
+    use settings::{self, Configurable};
+
+    #[test]
+    fn unencoded() {
+        let mut b = settings::builder();
+        b.set("is_64bit", "1").unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&b));
+
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.returns.push(AbiParam::new(types::I32));
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_param(ebb0, types::I32);
+        let v1 = {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let v1 = pos.ins().iadd(v0, v0);
+            pos.ins().return_(&[v1]);
+            v1
+        };
+        let add = func.dfg.value_def(v1).unwrap_inst();
+        let ret = func.layout.last_inst(ebb0).unwrap();
+
+        let encode = |func: &mut Function, inst| {
+            func.encodings[inst] = isa.encode(
+                &func.dfg,
+                &func.dfg[inst],
+                func.dfg.ctrl_typevar(inst),
+            ).unwrap();
+        };
+        encode(&mut func, ret);
+        let err = verify_encodings(&*isa, &func).unwrap_err();
+        assert_eq!(err.location, add.into());
+        assert!(err.message.starts_with("iadd has no encoding"));
+
+        encode(&mut func, add);
+        assert_eq!(verify_encodings(&*isa, &func), Ok(()));
+    }
+}
@@ -19,8 +19,9 @@ use timing;
 /// We verify the following conditions:
 ///
 /// - At most one flags value can be live at a time.
-/// - A flags value can not be live across an instruction that clobbers the flags.
-///
+/// - A flags value can not be live across an instruction that clobbers the flags. Calls always
+///   clobber the flags, other instructions only when their encoding does.
+/// - A flags value can't be passed as an EBB argument, since it can't be copied.
 ///
 pub fn verify_flags(
     func: &ir::Function,
@@ -82,6 +83,12 @@ impl<'a> FlagsVerifier<'a> {
 
     /// Check flags usage in `ebb` and return the live-in flags value, if any.
     fn visit_ebb(&self, ebb: ir::Ebb) -> result::Result<Option<ir::Value>, Error> {
+        for &param in self.func.dfg.ebb_params(ebb) {
+            if self.func.dfg.value_type(param).is_flags() {
+                return err!(ebb, "CPU flags {} can't be an EBB parameter", param);
+            }
+        }
+
         // The single currently live flags value.
         let mut live_val = None;
 
@@ -98,6 +105,11 @@ impl<'a> FlagsVerifier<'a> {
                     }
                 }
 
+                // The callee can change the CPU flags.
+                if self.func.dfg[inst].opcode().is_call() && live_val.is_some() {
+                    return err!(inst, "call clobbers live CPU flags in {}", live);
+                }
+
                 // Does the instruction have an encoding that clobbers the CPU flags?
                 if self.encinfo
                     .as_ref()
@@ -1,8 +1,11 @@
 //! Verify value locations.
 
+use entity::EntityMap;
+use flowgraph::ControlFlowGraph;
 use ir;
+use ir::entities::AnyEntity;
 use isa;
-use regalloc::RegDiversions;
+use regalloc::{Affinity, RegDiversions};
 use regalloc::liveness::Liveness;
 use verifier::Result;
 use timing;
@@ -11,13 +14,15 @@ use timing;
 ///
 /// After register allocation, every value must be assigned to a location - either a register or a
 /// stack slot. These locations must be compatible with the constraints described by the
-/// instruction encoding recipes.
+/// instruction encoding recipes. The arguments passed to an EBB by a branch must be in the same
+/// locations as the EBB parameters.
 ///
 /// Values can be temporarily diverted to a different location by using the `regmove`, `regspill`,
 /// and `regfill` instructions, but only inside an EBB.
 ///
 /// If a liveness analysis is provided, it is used to verify that there are no active register
-/// diversions across control flow edges.
+/// diversions across control flow edges, and that no two live values are in overlapping registers
+/// at any program point.
 pub fn verify_locations(
     isa: &isa::TargetIsa,
     func: &ir::Function,
@@ -32,9 +37,23 @@ pub fn verify_locations(
         liveness,
     };
     verifier.check_constraints()?;
+    verifier.check_interference()?;
     Ok(())
 }
 
+/// Verify the value locations of the register allocated `func`, including the checks that need a
+/// liveness analysis.
+///
+/// The liveness analysis is computed from scratch on a copy of `func`, so this is meant for
+/// testing functions that weren't allocated by the register allocator.
+pub fn verify_allocation(isa: &isa::TargetIsa, func: &ir::Function) -> Result {
+    let mut func = func.clone();
+    let cfg = ControlFlowGraph::with_function(&func);
+    let mut liveness = Liveness::new();
+    liveness.compute(isa, &mut func, &cfg);
+    verify_locations(isa, &func, Some(&liveness))
+}
+
 struct LocationVerifier<'a> {
     isa: &'a isa::TargetIsa,
     func: &'a ir::Function,
@@ -53,11 +72,13 @@ impl<'a> LocationVerifier<'a> {
             // Diversions are reset at the top of each EBB. No diversions can exist across control
             // flow edges.
             divert.clear();
+            self.check_ebb_params(ebb)?;
             for inst in self.func.layout.ebb_insts(ebb) {
                 let enc = self.func.encodings[inst];
 
                 if enc.is_legal() {
-                    self.check_enc_constraints(inst, enc, &divert)?
+                    self.check_enc_constraints(inst, enc, &divert)?;
+                    self.check_results(inst)?;
                 } else {
                     self.check_ghost_results(inst)?;
                 }
@@ -71,8 +92,11 @@ impl<'a> LocationVerifier<'a> {
                     self.check_return_abi(inst, &divert)?;
                 }
 
-                if opcode.is_branch() && !divert.is_empty() {
-                    self.check_cfg_edges(inst, &divert)?;
+                if opcode.is_branch() {
+                    self.check_branch_args(inst, &divert)?;
+                    if !divert.is_empty() {
+                        self.check_cfg_edges(inst, &divert)?;
+                    }
                 }
 
                 self.update_diversions(inst, &mut divert)?;
@@ -105,6 +129,26 @@ impl<'a> LocationVerifier<'a> {
         )
     }
 
+    /// Check that the parameters of `ebb` are assigned a location.
+    fn check_ebb_params(&self, ebb: ir::Ebb) -> Result {
+        for &param in self.func.dfg.ebb_params(ebb) {
+            if !self.func.locations[param].is_assigned() {
+                return err!(ebb, "parameter {} has no location", param);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the result values produced by an encoded instruction are assigned a location.
+    fn check_results(&self, inst: ir::Inst) -> Result {
+        for &res in self.func.dfg.inst_results(inst) {
+            if !self.func.locations[res].is_assigned() {
+                return err!(inst, "result {} has no location", res);
+            }
+        }
+        Ok(())
+    }
+
     /// Check that the result values produced by a ghost instruction are not assigned a value
     /// location.
     fn check_ghost_results(&self, inst: ir::Inst) -> Result {
@@ -262,6 +306,29 @@ impl<'a> LocationVerifier<'a> {
         Ok(())
     }
 
+    /// Check that the arguments of a branch are in the same locations as the parameters of its
+    /// destination EBB.
+    fn check_branch_args(&self, inst: ir::Inst, divert: &RegDiversions) -> Result {
+        let dfg = &self.func.dfg;
+        if let ir::instructions::BranchInfo::SingleDest(ebb, args) = dfg.analyze_branch(inst) {
+            for (&arg, &param) in args.iter().zip(dfg.ebb_params(ebb)) {
+                let arg_loc = divert.get(arg, &self.func.locations);
+                let param_loc = self.func.locations[param];
+                if arg_loc != param_loc {
+                    return err!(
+                        inst,
+                        "{} in {} is passed to {} in {}",
+                        arg,
+                        arg_loc.display(&self.reginfo),
+                        param,
+                        param_loc.display(&self.reginfo)
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// We have active diversions before a branch. Make sure none of the diverted values are live
     /// on the outgoing CFG edges.
     fn check_cfg_edges(&self, inst: ir::Inst, divert: &RegDiversions) -> Result {
@@ -283,7 +350,10 @@ impl<'a> LocationVerifier<'a> {
             }
             SingleDest(ebb, _) => {
                 for d in divert.all() {
-                    let lr = &liveness[d.value];
+                    let lr = match liveness.get(d.value) {
+                        Some(lr) => lr,
+                        None => continue,
+                    };
                     if lr.is_livein(ebb, liveness.context(&self.func.layout)) {
                         return err!(
                             inst,
@@ -297,7 +367,10 @@ impl<'a> LocationVerifier<'a> {
             }
             Table(jt) => {
                 for d in divert.all() {
-                    let lr = &liveness[d.value];
+                    let lr = match liveness.get(d.value) {
+                        Some(lr) => lr,
+                        None => continue,
+                    };
                     for (_, ebb) in self.func.jump_tables[jt].entries() {
                         if lr.is_livein(ebb, liveness.context(&self.func.layout)) {
                             return err!(
@@ -315,4 +388,119 @@ impl<'a> LocationVerifier<'a> {
 
         Ok(())
     }
+
+    /// Check that no two live values are in overlapping registers at any program point.
+    ///
+    /// Whenever two live ranges overlap, one of the values is defined inside the live range of the
+    /// other, so it is enough to check each value where it is defined or moved to a new register
+    /// against the values that are live there.
+    fn check_interference(&self) -> Result {
+        let liveness = match self.liveness {
+            Some(l) => l,
+            None => return Ok(()),
+        };
+        let dfg = &self.func.dfg;
+        let layout = &self.func.layout;
+        let ctx = liveness.context(layout);
+
+        // Collect the values that are live in to each EBB.
+        let mut liveins = EntityMap::<ir::Ebb, Vec<ir::Value>>::new();
+        for ebb in layout.ebbs() {
+            let results = layout.ebb_insts(ebb).flat_map(|inst| dfg.inst_results(inst));
+            for &value in dfg.ebb_params(ebb).iter().chain(results) {
+                if let Some(lr) = liveness.get(value) {
+                    for (livein, _) in lr.liveins(ctx) {
+                        liveins[livein].push(value);
+                    }
+                }
+            }
+        }
+
+        let mut divert = RegDiversions::new();
+        let mut live = Vec::new();
+        for ebb in layout.ebbs() {
+            divert.clear();
+            live.clear();
+            if let Some(values) = liveins.get(ebb) {
+                live.extend_from_slice(values);
+            }
+            // Values without a live range are never live, so only the values with a live range
+            // are tracked.
+            for &param in dfg.ebb_params(ebb) {
+                if liveness.get(param).is_some() {
+                    self.check_overlap(ebb, param, &live, &divert)?;
+                    live.push(param);
+                }
+            }
+            live.retain(|&value| liveness.get(value).map_or(false, |lr| !lr.is_dead()));
+
+            for inst in layout.ebb_insts(ebb) {
+                // The results can reuse the registers of the values killed by the instruction.
+                live.retain(|&value| {
+                    liveness.get(value).map_or(false, |lr| !lr.killed_at(inst, ebb, ctx))
+                });
+
+                divert.apply(&dfg[inst]);
+                match dfg[inst] {
+                    ir::InstructionData::RegMove { arg, .. } |
+                    ir::InstructionData::RegFill { arg, .. } => {
+                        self.check_overlap(inst, arg, &live, &divert)?;
+                    }
+                    _ => {}
+                }
+
+                for &res in dfg.inst_results(inst) {
+                    if liveness.get(res).is_some() {
+                        self.check_overlap(inst, res, &live, &divert)?;
+                        live.push(res);
+                    }
+                }
+                live.retain(|&value| liveness.get(value).map_or(false, |lr| !lr.is_dead()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the register of `value`, if any, doesn't overlap the register of any of the
+    /// `live` values.
+    fn check_overlap<E: Into<AnyEntity>>(
+        &self,
+        loc: E,
+        value: ir::Value,
+        live: &[ir::Value],
+        divert: &RegDiversions,
+    ) -> Result {
+        let reg = match divert.get(value, &self.func.locations) {
+            ir::ValueLoc::Reg(reg) => reg,
+            _ => return Ok(()),
+        };
+        let rc = self.regclass(value);
+        for &other in live {
+            if other == value {
+                continue;
+            }
+            if let ir::ValueLoc::Reg(other_reg) = divert.get(other, &self.func.locations) {
+                if isa::regs_overlap(rc, reg, self.regclass(other), other_reg) {
+                    return err!(
+                        loc,
+                        "{} in {} overlaps the live value {} in {}",
+                        value,
+                        self.reginfo.display_regunit(reg),
+                        other,
+                        self.reginfo.display_regunit(other_reg)
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the register class of `value`, which determines the width of its register.
+    fn regclass(&self, value: ir::Value) -> isa::RegClass {
+        match self.liveness.and_then(|l| l.get(value)).map(|lr| lr.affinity) {
+            Some(Affinity::Reg(rci)) => self.reginfo.rc(rci),
+            _ => self.isa.regclass_for_abi_type(self.func.dfg.value_type(value)),
+        }
+    }
 }
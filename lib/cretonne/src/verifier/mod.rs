@@ -1,4 +1,10 @@
 //! A verifier for ensuring that functions are well formed.
+//!
+//! The checks are split in two tiers selected by the `verifier_level` setting. The basic level
+//! only runs the cheap structural checks. The checks marked *(full)* below need the dominator tree
+//! or other analyses, so they only run at the full level, along with the checks of instruction
+//! encodings and CPU flags.
+//!
 //! It verifies:
 //!
 //! EBB integrity
@@ -20,9 +26,10 @@
 //!
 //! - Values must be defined by an instruction that exists and that is inserted in
 //!   an EBB, or be an argument of an existing EBB.
-//! - Values used by an instruction must dominate the instruction.
+//! - Values used by an instruction must strictly dominate the instruction. This includes the
+//!   arguments a branch passes to its destination EBB. *(full)*
 //!
-//! Control flow graph and dominator tree integrity:
+//! Control flow graph and dominator tree integrity *(full)*:
 //!
 //! - All predecessors in the CFG must be branches to the EBB.
 //! - All branches to an EBB must be present in the CFG.
@@ -34,15 +41,19 @@
 //!   For polymorphic opcodes, determine the controlling type variable first.
 //! - Branches and jumps must pass arguments to destination EBBs that match the
 //!   expected types exactly. The number of arguments must match.
-//! - All EBBs in a jump_table must take no arguments.
+//! - All EBBs in a jump table must take no arguments, even if no instruction uses the table.
 //! - Function calls are type checked against their signature.
 //! - The entry block must take arguments that match the signature of the current
 //!   function.
 //! - All return instructions must have return value operands matching the current
 //!   function signature.
 //!
-//! Global variables
+//! Preamble entities
 //!
+//! - Global variables, heaps, and jump tables may only refer to declared entities: The base of a
+//!   `deref` global variable, the base and bound global variables of a heap, and the EBBs in a
+//!   jump table must all exist.
+//! - Jump table entries can't branch to the entry block.
 //! - Detect cycles in deref(base) declarations.
 //!
 //! TODO:
@@ -62,12 +73,13 @@ use flowgraph::ControlFlowGraph;
 use ir::entities::AnyEntity;
 use ir::instructions::{InstructionFormat, BranchInfo, ResolvedConstraint, CallInfo};
 use ir::{types, Function, ValueDef, Ebb, Inst, SigRef, FuncRef, ValueList, JumpTable, StackSlot,
-         StackSlotKind, GlobalVar, Value, Type, Opcode, ValueLoc, ArgumentLoc};
+         StackSlotKind, GlobalVar, Value, Type, Opcode, ValueLoc, ArgumentLoc,
+         ExpandedProgramPoint};
 use ir;
 use isa::TargetIsa;
 use iterators::IteratorExtras;
 use self::flags::verify_flags;
-use settings::{Flags, FlagsOrIsa};
+use settings::{Flags, FlagsOrIsa, VerifierLevel};
 use std::cmp::Ordering;
 use std::collections::BTreeSet;
 use std::error as std_error;
@@ -76,8 +88,9 @@ use std::result;
 use timing;
 
 pub use self::cssa::verify_cssa;
+pub use self::encodings::verify_encodings;
 pub use self::liveness::verify_liveness;
-pub use self::locations::verify_locations;
+pub use self::locations::{verify_locations, verify_allocation};
 
 // Create an `Err` variant of `Result<X>` from a location and `format!` arguments.
 macro_rules! err {
@@ -97,6 +110,7 @@ macro_rules! err {
 }
 
 mod cssa;
+mod encodings;
 mod flags;
 mod liveness;
 mod locations;
@@ -141,11 +155,13 @@ pub fn verify_context<'a, FOI: Into<FlagsOrIsa<'a>>>(
 ) -> Result {
     let _tt = timing::verifier();
     let verifier = Verifier::new(func, fisa.into());
-    if cfg.is_valid() {
-        verifier.cfg_integrity(cfg)?;
-    }
-    if domtree.is_valid() {
-        verifier.domtree_integrity(domtree)?;
+    if verifier.full {
+        if cfg.is_valid() {
+            verifier.cfg_integrity(cfg)?;
+        }
+        if domtree.is_valid() {
+            verifier.domtree_integrity(domtree)?;
+        }
     }
     verifier.run()
 }
@@ -156,25 +172,41 @@ struct Verifier<'a> {
     expected_domtree: DominatorTree,
     flags: &'a Flags,
     isa: Option<&'a TargetIsa>,
+    // Run the expensive checks too? The expected CFG and dominator tree are left empty otherwise.
+    full: bool,
 }
 
 impl<'a> Verifier<'a> {
     pub fn new(func: &'a Function, fisa: FlagsOrIsa<'a>) -> Verifier<'a> {
-        let expected_cfg = ControlFlowGraph::with_function(func);
-        let expected_domtree = DominatorTree::with_function(func, &expected_cfg);
+        let full = fisa.flags.verifier_level() == VerifierLevel::Full;
+        let (expected_cfg, expected_domtree) = if full {
+            let cfg = ControlFlowGraph::with_function(func);
+            let domtree = DominatorTree::with_function(func, &cfg);
+            (cfg, domtree)
+        } else {
+            (ControlFlowGraph::new(), DominatorTree::new())
+        };
         Verifier {
             func,
             expected_cfg,
             expected_domtree,
             flags: fisa.flags,
             isa: fisa.isa,
+            full,
         }
     }
 
-    // Check for cycles in the global variable declarations.
+    // Check the references and cycles in the global variable declarations.
     fn verify_global_vars(&self) -> Result {
-        let mut seen = SparseSet::new();
+        for gv in self.func.global_vars.keys() {
+            if let ir::GlobalVarData::Deref { base, .. } = self.func.global_vars[gv] {
+                if !self.func.global_vars.is_valid(base) {
+                    return err!(gv, "invalid base global variable {}", base);
+                }
+            }
+        }
 
+        let mut seen = SparseSet::new();
         for gv in self.func.global_vars.keys() {
             seen.clear();
             seen.insert(gv);
@@ -192,6 +224,51 @@ impl<'a> Verifier<'a> {
         Ok(())
     }
 
+    // Check that the heap declarations refer to existing global variables.
+    fn verify_heaps(&self) -> Result {
+        for heap in self.func.heaps.keys() {
+            let data = &self.func.heaps[heap];
+            if let ir::HeapBase::GlobalVar(base) = data.base {
+                if !self.func.global_vars.is_valid(base) {
+                    return err!(heap, "invalid base global variable {}", base);
+                }
+            }
+            if let ir::HeapStyle::Dynamic { bound_gv } = data.style {
+                if !self.func.global_vars.is_valid(bound_gv) {
+                    return err!(heap, "invalid bound global variable {}", bound_gv);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Check that the jump tables only branch to EBBs that can be reached without arguments.
+    //
+    // This also covers the jump tables that aren't used by any `br_table` instruction.
+    fn verify_jump_tables(&self) -> Result {
+        let entry_block = self.func.layout.entry_block();
+        for jt in self.func.jump_tables.keys() {
+            for (_, ebb) in self.func.jump_tables[jt].entries() {
+                if !self.func.dfg.ebb_is_valid(ebb) || !self.func.layout.is_ebb_inserted(ebb) {
+                    return err!(jt, "invalid ebb reference {}", ebb);
+                }
+                if Some(ebb) == entry_block {
+                    return err!(jt, "invalid reference to entry ebb {}", ebb);
+                }
+                let arg_count = self.func.dfg.num_ebb_params(ebb);
+                if arg_count != 0 {
+                    return err!(
+                        jt,
+                        "entries take no arguments, but {} has {} parameters",
+                        ebb,
+                        arg_count
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn ebb_integrity(&self, ebb: Ebb, inst: Inst) -> Result {
 
         let is_terminator = self.func.dfg[inst].opcode().is_terminator();
@@ -267,6 +344,9 @@ impl<'a> Verifier<'a> {
 
         for &arg in self.func.dfg.inst_args(inst) {
             self.verify_value(inst, arg)?;
+            if self.full {
+                self.verify_dominance(inst, arg)?;
+            }
 
             // All used values must be attached to something.
             let original = self.func.dfg.resolve_aliases(arg);
@@ -446,8 +526,6 @@ impl<'a> Verifier<'a> {
         if !dfg.value_is_valid(v) {
             return err!(loc_inst, "invalid value reference {}", v);
         }
-        let loc_ebb = self.func.layout.pp_ebb(loc_inst);
-        let is_reachable = self.expected_domtree.is_reachable(loc_ebb);
 
         // SSA form
         match dfg.value_def(v) {
@@ -470,16 +548,6 @@ impl<'a> Verifier<'a> {
                         def_inst
                     );
                 }
-                // Defining instruction dominates the instruction that uses the value.
-                if is_reachable &&
-                    !self.expected_domtree.dominates(
-                        def_inst,
-                        loc_inst,
-                        &self.func.layout,
-                    )
-                {
-                    return err!(loc_inst, "uses value from non-dominating {}", def_inst);
-                }
             }
             ValueDef::Param(ebb, _) => {
                 // Value is defined by an existing EBB.
@@ -495,21 +563,50 @@ impl<'a> Verifier<'a> {
                         ebb
                     );
                 }
-                // The defining EBB dominates the instruction using this value.
-                if is_reachable &&
-                    !self.expected_domtree.dominates(
-                        ebb,
-                        loc_inst,
-                        &self.func.layout,
-                    )
-                {
-                    return err!(loc_inst, "uses value arg from non-dominating {}", ebb);
-                }
             }
         }
         Ok(())
     }
 
+    /// Check that the definition of `v` strictly dominates its use as an argument of `loc_inst`.
+    ///
+    /// The dominator tree is computed from the current CFG, so this also catches uses that were
+    /// invalidated by changes to the control flow. The arguments a branch passes to its
+    /// destination EBB are used at the branch.
+    fn verify_dominance(&self, loc_inst: Inst, v: Value) -> Result {
+        // Uses in unreachable code don't need to be dominated.
+        let loc_ebb = self.func.layout.pp_ebb(loc_inst);
+        if !self.expected_domtree.is_reachable(loc_ebb) {
+            return Ok(());
+        }
+
+        let def = match self.func.dfg.value_def(v) {
+            ValueDef::Result(def_inst, _) => {
+                if def_inst == loc_inst {
+                    return err!(loc_inst, "uses its own result {}", v);
+                }
+                ExpandedProgramPoint::from(def_inst)
+            }
+            ValueDef::Param(ebb, _) => ExpandedProgramPoint::from(ebb),
+        };
+        if self.expected_domtree.dominates(def, loc_inst, &self.func.layout) {
+            return Ok(());
+        }
+
+        match self.func.dfg.analyze_branch(loc_inst) {
+            BranchInfo::SingleDest(dest, args) if args.contains(&v) => {
+                err!(
+                    loc_inst,
+                    "passes {} to {}, but it is defined by non-dominating {}",
+                    v,
+                    dest,
+                    def
+                )
+            }
+            _ => err!(loc_inst, "uses {} defined by non-dominating {}", v, def),
+        }
+    }
+
     fn domtree_integrity(&self, domtree: &DominatorTree) -> Result {
         // We consider two `DominatorTree`s to be equal if they return the same immediate
         // dominator for each EBB. Therefore the current domtree is valid if it matches the freshly
@@ -700,19 +797,8 @@ impl<'a> Verifier<'a> {
                 });
                 self.typecheck_variable_args_iterator(inst, iter)?;
             }
-            BranchInfo::Table(table) => {
-                for (_, ebb) in self.func.jump_tables[table].entries() {
-                    let arg_count = self.func.dfg.num_ebb_params(ebb);
-                    if arg_count != 0 {
-                        return err!(
-                            inst,
-                            "takes no arguments, but had target {} with {} arguments",
-                            ebb,
-                            arg_count
-                        );
-                    }
-                }
-            }
+            // The jump table entries are checked by `verify_jump_tables`.
+            BranchInfo::Table(_) |
             BranchInfo::NotABranch => {}
         }
 
@@ -1096,13 +1182,20 @@ impl<'a> Verifier<'a> {
 
     pub fn run(&self) -> Result {
         self.verify_global_vars()?;
+        self.verify_heaps()?;
+        self.verify_jump_tables()?;
         self.typecheck_entry_block_params()?;
         for ebb in self.func.layout.ebbs() {
+            if self.func.layout.first_inst(ebb).is_none() {
+                return err!(ebb, "block does not end in a terminator instruction!");
+            }
             for inst in self.func.layout.ebb_insts(ebb) {
                 self.ebb_integrity(ebb, inst)?;
                 self.instruction_integrity(inst)?;
                 self.typecheck(inst)?;
-                self.verify_encoding(inst)?;
+                if self.full {
+                    self.verify_encoding(inst)?;
+                }
             }
         }
 
@@ -1110,7 +1203,9 @@ impl<'a> Verifier<'a> {
             self.verify_return_at_end()?;
         }
 
-        verify_flags(self.func, &self.expected_cfg, self.isa)?;
+        if self.full {
+            verify_flags(self.func, &self.expected_cfg, self.isa)?;
+        }
 
         Ok(())
     }
@@ -1119,7 +1214,7 @@ impl<'a> Verifier<'a> {
 #[cfg(test)]
 mod tests {
     use super::{Verifier, Error};
-    use ir::Function;
+    use ir::{Function, GlobalVar, GlobalVarData, HeapBase, HeapData, HeapStyle, JumpTableData};
     use ir::instructions::{InstructionData, Opcode};
     use entity::EntityList;
     use settings;
@@ -1145,6 +1240,16 @@ mod tests {
         assert_eq!(verifier.run(), Ok(()));
     }
 
+    #[test]
+    fn empty_ebb() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        let flags = &settings::Flags::new(&settings::builder());
+        let verifier = Verifier::new(&func, flags.into());
+        assert_err_with_msg!(verifier.run(), "terminator");
+    }
+
     #[test]
     fn bad_instruction_format() {
         let mut func = Function::new();
@@ -1167,4 +1272,51 @@ mod tests {
         let verifier = Verifier::new(&func, flags.into());
         assert_err_with_msg!(verifier.run(), "instruction format");
     }
+
+    #[test]
+    fn undeclared_preamble_references() {
+        let flags = &settings::Flags::new(&settings::builder());
+        let gv9 = GlobalVar::with_number(9).unwrap();
+
+        let mut func = Function::new();
+        func.create_global_var(GlobalVarData::Deref {
+            base: gv9,
+            offset: 0.into(),
+        });
+        assert_err_with_msg!(
+            Verifier::new(&func, flags.into()).run(),
+            "invalid base global variable gv9"
+        );
+
+        let mut func = Function::new();
+        func.create_heap(HeapData {
+            base: HeapBase::ReservedReg,
+            min_size: 0.into(),
+            guard_size: 0.into(),
+            style: HeapStyle::Dynamic { bound_gv: gv9 },
+        });
+        assert_err_with_msg!(
+            Verifier::new(&func, flags.into()).run(),
+            "invalid bound global variable gv9"
+        );
+
+        // A jump table can't refer to an EBB that isn't in the layout, or to the entry block.
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let mut jt = JumpTableData::new();
+        jt.push_entry(ebb1);
+        let jt0 = func.create_jump_table(jt);
+        assert_err_with_msg!(
+            Verifier::new(&func, flags.into()).run(),
+            "invalid ebb reference ebb1"
+        );
+
+        func.layout.append_ebb(ebb0);
+        func.jump_tables[jt0].set_entry(0, ebb0);
+        assert_err_with_msg!(
+            Verifier::new(&func, flags.into()).run(),
+            "invalid reference to entry ebb ebb0"
+        );
+    }
 }
@@ -3,29 +3,154 @@
 //! The `write` module provides the `write_function` function which converts an IL `Function` to an
 //! equivalent textual representation. This textual representation can be read back by the
 //! `cretonne-reader` crate.
+//!
+//! The `write_function_stable` function writes text which reads back with the same entity numbers
+//! as the original function, so textual diffs between compilation stages are meaningful. The
+//! annotations written for each instruction can be chosen with `write_function_with_options`.
+//!
+//! Tools that rewrite text files can interleave their own comments with the function text by
+//! passing an `Annotator` to `write_function_annotated`.
 
-use ir::{Function, DataFlowGraph, Ebb, Inst, Value, ValueDef, Type, SigRef};
-use isa::{TargetIsa, RegInfo};
+use entity::EntitySet;
+use ir::{Function, DataFlowGraph, Ebb, Inst, Value, ValueDef, ValueLoc, Type, SigRef};
+use ir::entities::AnyEntity;
+use isa::{Encoding, TargetIsa, RegInfo};
 use std::fmt::{self, Result, Error, Write};
 use std::result;
 use packed_option::ReservedValue;
 
+/// Options controlling the text written by `write_function_with_options`.
+///
+/// The default options write all annotations, like `write_function`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Write the `[enc]` encoding annotations of instructions. When they are suppressed but value
+    /// locations are written, the encoding is written as `-`.
+    pub encodings: bool,
+    /// Write the register locations of values.
+    pub reg_locations: bool,
+    /// Write the stack slot locations of values.
+    pub stack_locations: bool,
+    /// Write text that reads back with the same entity numbers. See `write_function_stable`.
+    pub stable: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            encodings: true,
+            reg_locations: true,
+            stack_locations: true,
+            stable: false,
+        }
+    }
+}
+
+impl WriteOptions {
+    // Should the value location `loc` be written?
+    fn show_location(&self, loc: ValueLoc) -> bool {
+        match loc {
+            ValueLoc::Unassigned => false,
+            ValueLoc::Reg(_) => self.reg_locations,
+            ValueLoc::Stack(_) => self.stack_locations,
+        }
+    }
+}
+
+/// Extra text to interleave with the text written by `write_function_annotated`.
+///
+/// The methods write nothing by default.
+pub trait Annotator {
+    /// Write complete lines to appear before the line defining `entity`.
+    fn write_leading(&mut self, _w: &mut Write, _entity: AnyEntity) -> Result {
+        Ok(())
+    }
+
+    /// Write text to appear at the end of the line defining `entity`, before the newline.
+    ///
+    /// The line defining `AnyEntity::Function` is the line with the function name.
+    fn write_trailing(&mut self, _w: &mut Write, _entity: AnyEntity) -> Result {
+        Ok(())
+    }
+
+    /// Write complete lines to appear after the last EBB, before the closing brace.
+    fn write_body_end(&mut self, _w: &mut Write) -> Result {
+        Ok(())
+    }
+
+    /// Should the preamble declaration of `entity` be written?
+    ///
+    /// All declarations are written by default. A reader that keeps the entity numbers of its
+    /// input fills the gaps in the numbering with placeholder declarations, which can be hidden
+    /// with this.
+    fn show_declaration(&mut self, _entity: AnyEntity) -> bool {
+        true
+    }
+}
+
+/// The unit annotator writes nothing.
+impl Annotator for () {}
+
 /// Write `func` to `w` as equivalent text.
 /// Use `isa` to emit ISA-dependent annotations.
 pub fn write_function(w: &mut Write, func: &Function, isa: Option<&TargetIsa>) -> Result {
+    write_function_with_options(w, func, isa, &WriteOptions::default())
+}
+
+/// Write `func` to `w` as text that reads back with the same entity numbers and ordering.
+///
+/// The reader keeps the numbers of the values, EBBs, stack slots, jump tables, and other entities
+/// that appear in the text, and EBBs are laid out in text order. `write_function` writes a value
+/// alias before every instruction using it, resolved to the final aliased value, which the reader
+/// rejects as a redefinition when the alias is used twice. This function writes every alias once,
+/// before its first use, and keeps the intermediate values of alias chains.
+///
+/// Values and EBBs that don't appear in the function at all are not written, so the reader may
+/// allocate fewer entities than `func` has.
+pub fn write_function_stable(w: &mut Write, func: &Function, isa: Option<&TargetIsa>) -> Result {
+    let options = WriteOptions {
+        stable: true,
+        ..WriteOptions::default()
+    };
+    write_function_with_options(w, func, isa, &options)
+}
+
+/// Write `func` to `w` as equivalent text, with the annotations selected by `options`.
+pub fn write_function_with_options(
+    w: &mut Write,
+    func: &Function,
+    isa: Option<&TargetIsa>,
+    options: &WriteOptions,
+) -> Result {
+    write_function_annotated(w, func, isa, options, &mut ())
+}
+
+/// Write `func` to `w` like `write_function_with_options`, interleaved with the text written by
+/// `annotator`.
+pub fn write_function_annotated(
+    w: &mut Write,
+    func: &Function,
+    isa: Option<&TargetIsa>,
+    options: &WriteOptions,
+    annotator: &mut Annotator,
+) -> Result {
     let regs = isa.map(TargetIsa::register_info);
     let regs = regs.as_ref();
 
     write_spec(w, func, regs)?;
-    writeln!(w, " {{")?;
-    let mut any = write_preamble(w, func, regs)?;
+    write!(w, " {{")?;
+    annotator.write_trailing(w, AnyEntity::Function)?;
+    writeln!(w, "")?;
+    let mut any = write_preamble(w, func, regs, annotator)?;
+    let mut aliases = EntitySet::new();
     for ebb in &func.layout {
         if any {
             writeln!(w, "")?;
         }
-        write_ebb(w, func, isa, ebb)?;
+        write_ebb(w, func, isa, options, annotator, ebb, &mut aliases)?;
         any = true;
     }
+    annotator.write_body_end(w)?;
     writeln!(w, "}}")
 }
 
@@ -43,62 +168,81 @@ fn write_preamble(
     w: &mut Write,
     func: &Function,
     regs: Option<&RegInfo>,
+    annotator: &mut Annotator,
 ) -> result::Result<bool, Error> {
     let mut any = false;
 
     for ss in func.stack_slots.keys() {
-        any = true;
-        writeln!(w, "    {} = {}", ss, func.stack_slots[ss])?;
+        any = write_definition(w, annotator, ss.into(), &func.stack_slots[ss])? || any;
     }
 
     for gv in func.global_vars.keys() {
-        any = true;
-        writeln!(w, "    {} = {}", gv, func.global_vars[gv])?;
+        any = write_definition(w, annotator, gv.into(), &func.global_vars[gv])? || any;
     }
 
     for heap in func.heaps.keys() {
-        any = true;
-        writeln!(w, "    {} = {}", heap, func.heaps[heap])?;
+        any = write_definition(w, annotator, heap.into(), &func.heaps[heap])? || any;
     }
 
     // Write out all signatures before functions since function declarations can refer to
     // signatures.
     for sig in func.dfg.signatures.keys() {
-        any = true;
-        writeln!(
+        any = write_definition(
             w,
-            "    {} = {}",
-            sig,
-            func.dfg.signatures[sig].display(regs)
-        )?;
+            annotator,
+            sig.into(),
+            &func.dfg.signatures[sig].display(regs),
+        )? || any;
     }
 
     for fnref in func.dfg.ext_funcs.keys() {
-        any = true;
         let ext_func = &func.dfg.ext_funcs[fnref];
         if ext_func.signature != SigRef::reserved_value() {
-            writeln!(w, "    {} = {}", fnref, ext_func)?;
+            any = write_definition(w, annotator, fnref.into(), ext_func)? || any;
         }
     }
 
     for jt in func.jump_tables.keys() {
-        any = true;
-        writeln!(w, "    {} = {}", jt, func.jump_tables[jt])?;
+        any = write_definition(w, annotator, jt.into(), &func.jump_tables[jt])? || any;
     }
 
     Ok(any)
 }
 
+// Write the preamble declaration of `entity` as `entity = def`, unless the annotator hides it.
+// Returns true if the declaration was written.
+fn write_definition(
+    w: &mut Write,
+    annotator: &mut Annotator,
+    entity: AnyEntity,
+    def: &fmt::Display,
+) -> result::Result<bool, Error> {
+    if !annotator.show_declaration(entity) {
+        return Ok(false);
+    }
+    annotator.write_leading(w, entity)?;
+    write!(w, "    {} = {}", entity, def)?;
+    annotator.write_trailing(w, entity)?;
+    writeln!(w, "")?;
+    Ok(true)
+}
+
 // ====--------------------------------------------------------------------------------------====//
 //
 // Basic blocks
 //
 // ====--------------------------------------------------------------------------------------====//
 
-pub fn write_arg(w: &mut Write, func: &Function, regs: Option<&RegInfo>, arg: Value) -> Result {
+pub fn write_arg(
+    w: &mut Write,
+    func: &Function,
+    regs: Option<&RegInfo>,
+    options: &WriteOptions,
+    arg: Value,
+) -> Result {
     write!(w, "{}: {}", arg, func.dfg.value_type(arg))?;
     let loc = func.locations[arg];
-    if loc.is_assigned() {
+    if options.show_location(loc) {
         write!(w, " [{}]", loc.display(regs))?
     }
 
@@ -109,6 +253,8 @@ pub fn write_ebb_header(
     w: &mut Write,
     func: &Function,
     isa: Option<&TargetIsa>,
+    options: &WriteOptions,
+    annotator: &mut Annotator,
     ebb: Ebb,
     indent: usize,
 ) -> Result {
@@ -120,6 +266,7 @@ pub fn write_ebb_header(
     //
 
     // The `indent` is the instruction indentation. EBB headers are 4 spaces out from that.
+    annotator.write_leading(w, ebb.into())?;
     write!(w, "{1:0$}{2}", indent - 4, "", ebb)?;
 
     let regs = isa.map(TargetIsa::register_info);
@@ -127,36 +274,63 @@ pub fn write_ebb_header(
 
     let mut args = func.dfg.ebb_params(ebb).iter().cloned();
     match args.next() {
-        None => return writeln!(w, ":"),
+        None => write!(w, ":")?,
         Some(arg) => {
             write!(w, "(")?;
-            write_arg(w, func, regs, arg)?;
+            write_arg(w, func, regs, options, arg)?;
+            // Remaining arguments.
+            for arg in args {
+                write!(w, ", ")?;
+                write_arg(w, func, regs, options, arg)?;
+            }
+            write!(w, "):")?;
         }
     }
-    // Remaining arguments.
-    for arg in args {
-        write!(w, ", ")?;
-        write_arg(w, func, regs, arg)?;
-    }
-    writeln!(w, "):")
+    annotator.write_trailing(w, ebb.into())?;
+    writeln!(w, "")
 }
 
-pub fn write_ebb(w: &mut Write, func: &Function, isa: Option<&TargetIsa>, ebb: Ebb) -> Result {
-    // Indent all instructions if any encodings are present.
-    let indent = if func.encodings.is_empty() && func.srclocs.is_empty() {
+// Get the indentation of instructions in `func`.
+fn inst_indent(func: &Function, options: &WriteOptions) -> usize {
+    // Indent all instructions if any encoding annotations are present.
+    let annotated = options.encodings || options.reg_locations || options.stack_locations;
+    let encoded = !func.encodings.is_empty() || !func.locations.is_empty();
+    if (!encoded || !annotated) && func.srclocs.is_empty() {
         4
     } else {
         36
-    };
+    }
+}
 
-    write_ebb_header(w, func, isa, ebb, indent)?;
+// Write `ebb`. When writing stable text, `aliases` records the value aliases written so far.
+fn write_ebb(
+    w: &mut Write,
+    func: &Function,
+    isa: Option<&TargetIsa>,
+    options: &WriteOptions,
+    annotator: &mut Annotator,
+    ebb: Ebb,
+    aliases: &mut EntitySet<Value>,
+) -> Result {
+    let indent = inst_indent(func, options);
+    write_ebb_header(w, func, isa, options, annotator, ebb, indent)?;
     for inst in func.layout.ebb_insts(ebb) {
-        write_instruction(w, func, isa, inst, indent)?;
+        annotator.write_leading(w, inst.into())?;
+        // Value aliases come out on lines before the instruction using them.
+        if options.stable {
+            for &arg in func.dfg.inst_args(inst) {
+                write_alias_chain(w, func, arg, indent, aliases)?;
+            }
+        } else {
+            write_value_aliases(w, func, inst, indent)?;
+        }
+        write_instruction(w, func, isa, options, inst, indent)?;
+        annotator.write_trailing(w, inst.into())?;
+        writeln!(w, "")?;
     }
     Ok(())
 }
 
-
 // ====--------------------------------------------------------------------------------------====//
 //
 // Instructions
@@ -208,16 +382,34 @@ fn write_value_aliases(w: &mut Write, func: &Function, inst: Inst, indent: usize
     Ok(())
 }
 
+// Write out the aliases leading from `value` to the value it resolves to, unless they are in
+// `aliases` already. The aliases closest to the resolved value are written first, so every alias
+// refers to a value that is already defined.
+fn write_alias_chain(
+    w: &mut Write,
+    func: &Function,
+    value: Value,
+    indent: usize,
+    aliases: &mut EntitySet<Value>,
+) -> Result {
+    if let Some(original) = func.dfg.value_alias_original(value) {
+        if aliases.insert(value) {
+            write_alias_chain(w, func, original, indent, aliases)?;
+            writeln!(w, "{1:0$}{2} -> {3}", indent, "", value, original)?;
+        }
+    }
+    Ok(())
+}
+
+// Write `inst` without the final newline.
 fn write_instruction(
     w: &mut Write,
     func: &Function,
     isa: Option<&TargetIsa>,
+    options: &WriteOptions,
     inst: Inst,
     indent: usize,
 ) -> Result {
-    // Value aliases come out on lines before the instruction using them.
-    write_value_aliases(w, func, inst, indent)?;
-
     // Prefix containing source location, encoding, and value locations.
     let mut s = String::with_capacity(16);
 
@@ -227,19 +419,42 @@ fn write_instruction(
         write!(s, "{} ", srcloc)?;
     }
 
-    // Write out encoding info.
-    if let Some(enc) = func.encodings.get(inst).cloned() {
+    // Write out encoding info. Instructions without an encoding may still have value locations.
+    let enc = func.encodings.get(inst).cloned().or_else(|| {
+        let located = func.dfg.inst_results(inst).iter().any(|&r| {
+            func.locations.get(r).map_or(false, |loc| loc.is_assigned())
+        });
+        if isa.is_some() && located {
+            Some(Encoding::default())
+        } else {
+            None
+        }
+    });
+    if let Some(enc) = enc {
         if let Some(isa) = isa {
-            write!(s, "[{}", isa.encoding_info().display(enc))?;
-            // Write value locations, if we have them.
-            if !func.locations.is_empty() {
-                let regs = isa.register_info();
-                for &r in func.dfg.inst_results(inst) {
-                    write!(s, ",{}", func.locations[r].display(&regs))?
+            // Write value locations, if we have them. Suppressed locations are written as `-`.
+            let show_locations = !func.locations.is_empty() &&
+                (options.reg_locations || options.stack_locations);
+            if options.encodings || show_locations {
+                if options.encodings {
+                    write!(s, "[{}", isa.encoding_info().display(enc))?;
+                } else {
+                    write!(s, "[-")?;
+                }
+                if show_locations {
+                    let regs = isa.register_info();
+                    for &r in func.dfg.inst_results(inst) {
+                        let loc = func.locations[r];
+                        if options.show_location(loc) {
+                            write!(s, ",{}", loc.display(&regs))?
+                        } else {
+                            write!(s, ",-")?
+                        }
+                    }
                 }
+                write!(s, "] ")?;
             }
-            write!(s, "] ")?;
-        } else {
+        } else if options.encodings {
             write!(s, "[{}] ", enc)?;
         }
     }
@@ -269,8 +484,7 @@ fn write_instruction(
         None => write!(w, "{}", opcode)?,
     }
 
-    write_operands(w, &func.dfg, isa, inst)?;
-    writeln!(w, "")
+    write_operands(w, &func.dfg, isa, inst)
 }
 
 /// Write the operands of `inst` to `w` with a prepended space.
@@ -464,8 +678,13 @@ impl<'a> fmt::Display for DisplayValues<'a> {
 
 #[cfg(test)]
 mod tests {
-    use ir::{Function, ExternalName, StackSlotData, StackSlotKind};
+    use super::*;
+    use Context;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{AbiParam, ExternalName, InstBuilder, StackSlotData, StackSlotKind};
     use ir::types;
+    use isa;
+    use settings;
 
     #[test]
     fn basic() {
@@ -500,4 +719,68 @@ mod tests {
             "function %foo() native {\n    ss0 = explicit_slot 4\n\nebb0(v0: i8, v1: f32x4):\n}\n"
         );
     }
+
+    #[test]
+    fn options() {
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(
+            &settings::builder(),
+        ));
+        let mut ctx = Context::new();
+        ctx.func.signature.params.push(AbiParam::new(types::I32));
+        let ss0 = ctx.func.create_stack_slot(
+            StackSlotData::new(StackSlotKind::SpillSlot, 4),
+        );
+        let (arg, sum) = {
+            let ebb = ctx.func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut ctx.func);
+            pos.insert_ebb(ebb);
+            let arg = pos.func.dfg.append_ebb_param(ebb, types::I32);
+            let sum = pos.ins().iadd(arg, arg);
+            pos.ins().return_(&[]);
+            (arg, sum)
+        };
+        ctx.compile(&*isa).unwrap();
+        ctx.func.locations[arg] = ValueLoc::Stack(ss0);
+        let reg = ctx.func.locations[sum].display(&isa.register_info()).to_string();
+
+        let write = |options: WriteOptions| {
+            let mut text = String::new();
+            write_function_with_options(&mut text, &ctx.func, Some(&*isa), &options).unwrap();
+            text
+        };
+        let all = write(WriteOptions::default());
+        assert!(all.contains("(v0: i32 [ss0]"));
+        assert!(all.contains(&format!("[Op1rr#01,{}]", reg)));
+
+        let no_encodings = write(WriteOptions {
+            encodings: false,
+            ..WriteOptions::default()
+        });
+        assert!(no_encodings.contains(&format!("[-,{}]", reg)));
+
+        let no_regs = write(WriteOptions {
+            reg_locations: false,
+            ..WriteOptions::default()
+        });
+        assert!(no_regs.contains("(v0: i32 [ss0]"));
+        assert!(no_regs.contains("[Op1rr#01,-]"));
+
+        let no_stack = write(WriteOptions {
+            stack_locations: false,
+            ..WriteOptions::default()
+        });
+        assert!(no_stack.contains("(v0: i32, "));
+        assert!(no_stack.contains(&format!("[Op1rr#01,{}]", reg)));
+
+        let none = write(WriteOptions {
+            encodings: false,
+            reg_locations: false,
+            stack_locations: false,
+            stable: false,
+        });
+        // The signature keeps its ABI argument locations.
+        let body = &none[none.find('\n').unwrap()..];
+        assert!(!body.contains('['));
+        assert!(none.contains("\n    v1 = iadd v2, v2\n"));
+    }
 }
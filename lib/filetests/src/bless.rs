@@ -0,0 +1,237 @@
+//! Blessing: rewriting the filecheck directives of failing tests.
+//!
+//! When a test's output changes intentionally, updating the filecheck directives by hand can be
+//! tedious. In bless mode, the directives of a function that fails its filecheck are replaced by
+//! directives matching the actual output exactly, a `check:` for the first line followed by
+//! `nextln:` for the rest. The new directives are inserted where the first old one was, and the
+//! test file is rewritten when all its tests have run.
+//!
+//! The blessed directives match the output literally, so value numbers and the like should be
+//! replaced by filecheck variables by hand afterwards where that matters.
+
+use cton_reader::Comment;
+use match_directive::match_directive;
+use subtest::Result;
+
+/// The filecheck directives replaced when blessing.
+const DIRECTIVES: &[&str] = &[
+    "check:",
+    "sameln:",
+    "nextln:",
+    "unordered:",
+    "not:",
+    "regex:",
+];
+
+/// Pending changes to the source of a test file.
+pub struct Bless<'a> {
+    source: &'a str,
+    edits: Vec<Edit>,
+}
+
+/// Replace `source[start..end]` with `text`.
+struct Edit {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+impl<'a> Bless<'a> {
+    /// Create an empty set of changes to `source`.
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            edits: Vec::new(),
+        }
+    }
+
+    /// Replace the filecheck directives in `comments` with directives matching `output`.
+    ///
+    /// The comments must be slices of the source. The directives in the preamble can't be blessed
+    /// because they apply to all the functions in the file.
+    pub fn update(
+        &mut self,
+        preamble_comments: &[Comment],
+        comments: &[Comment],
+        output: &str,
+    ) -> Result<()> {
+        if preamble_comments.iter().any(|c| is_directive(c.text)) {
+            return Err("can't bless filecheck directives in the preamble".to_string());
+        }
+
+        let mut new_text = Some(expected_directives(output));
+        for comment in comments.iter().filter(|c| is_directive(c.text)) {
+            let offset = (comment.text.as_ptr() as usize)
+                .wrapping_sub(self.source.as_ptr() as usize);
+            if offset > self.source.len() {
+                return Err("can't bless a comment that isn't in the test file".to_string());
+            }
+            let line_start = self.source[0..offset].rfind('\n').map_or(0, |i| i + 1);
+            let indent = &self.source[line_start..offset];
+            let mut end = offset + comment.text.len();
+
+            // Remove whole lines, but only the comment after code.
+            let start = if indent.trim().is_empty() {
+                if self.source[end..].starts_with('\n') {
+                    end += 1;
+                }
+                line_start
+            } else {
+                offset
+            };
+            let text = match new_text.take() {
+                Some(lines) if start == line_start => {
+                    lines.iter().map(|l| format!("{}{}\n", indent, l)).collect()
+                }
+                Some(lines) => lines.join("\n"),
+                None => String::new(),
+            };
+            self.edit(Edit { start, end, text })?;
+        }
+        Ok(())
+    }
+
+    /// Add an edit, unless it has been made already.
+    fn edit(&mut self, edit: Edit) -> Result<()> {
+        match self.edits.iter().find(|e| e.start == edit.start) {
+            Some(e) if e.text == edit.text => return Ok(()),
+            Some(_) => {
+                return Err(
+                    "can't bless a function whose output differs between tests".to_string(),
+                )
+            }
+            None => {}
+        }
+        self.edits.push(edit);
+        Ok(())
+    }
+
+    /// Apply the changes to the source.
+    ///
+    /// Returns the new source and a description of the changes in a diff-like format, or `None`
+    /// if there are no changes.
+    pub fn apply(&self) -> Option<(String, String)> {
+        if self.edits.is_empty() {
+            return None;
+        }
+        let mut edits = self.edits.iter().collect::<Vec<_>>();
+        edits.sort_by_key(|e| e.start);
+        let mut source = String::new();
+        let mut diff = String::new();
+        let mut pos = 0;
+        for edit in edits {
+            source.push_str(&self.source[pos..edit.start]);
+            source.push_str(&edit.text);
+            pos = edit.end;
+
+            let line = self.source[0..edit.start].lines().count() + 1;
+            diff.push_str(&format!("@@ line {} @@\n", line));
+            for l in self.source[edit.start..edit.end].lines() {
+                diff.push_str(&format!("-{}\n", l));
+            }
+            for l in edit.text.lines() {
+                diff.push_str(&format!("+{}\n", l));
+            }
+        }
+        source.push_str(&self.source[pos..]);
+        Some((source, diff))
+    }
+}
+
+/// Is `comment` a filecheck directive?
+fn is_directive(comment: &str) -> bool {
+    DIRECTIVES.iter().any(
+        |d| match_directive(comment, d).is_some(),
+    )
+}
+
+/// Get the directive comments that match `output` exactly.
+fn expected_directives(output: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut directive = "check:";
+    for line in output.lines().map(str::trim) {
+        if line.is_empty() {
+            // `nextln:` can't match an empty line, so skip to the next non-empty one.
+            directive = "check:";
+            continue;
+        }
+        lines.push(format!("; {} {}", directive, line.replace('$', "$$")));
+        directive = "nextln:";
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cretonne::ir::entities::AnyEntity;
+
+    fn comment(source: &'static str, text: &str) -> Comment<'static> {
+        let start = source.find(text).unwrap();
+        Comment {
+            entity: AnyEntity::Function,
+            text: &source[start..start + text.len()],
+        }
+    }
+
+    #[test]
+    fn bless() {
+        let source = concat!(
+            "function %f() {\n",
+            "ebb0:\n",
+            "    ; check: old\n",
+            "    ; not: gone\n",
+            "    return ; keep\n",
+            "}\n",
+            "; nextln: after\n",
+        );
+        let comments = [
+            comment(source, "; check: old"),
+            comment(source, "; not: gone"),
+            comment(source, "; keep"),
+            comment(source, "; nextln: after"),
+        ];
+        let mut bless = Bless::new(source);
+        bless.update(&[], &comments, "ebb0:\n  v1 = $x\n\nreturn\n").unwrap();
+        // Blessing the same output again is fine.
+        bless.update(&[], &comments, "ebb0:\n  v1 = $x\n\nreturn\n").unwrap();
+        let (new, diff) = bless.apply().unwrap();
+        assert_eq!(
+            new,
+            concat!(
+                "function %f() {\n",
+                "ebb0:\n",
+                "    ; check: ebb0:\n",
+                "    ; nextln: v1 = $$x\n",
+                "    ; check: return\n",
+                "    return ; keep\n",
+                "}\n",
+            )
+        );
+        assert_eq!(
+            diff,
+            concat!(
+                "@@ line 3 @@\n",
+                "-    ; check: old\n",
+                "+    ; check: ebb0:\n",
+                "+    ; nextln: v1 = $$x\n",
+                "+    ; check: return\n",
+                "@@ line 4 @@\n",
+                "-    ; not: gone\n",
+                "@@ line 7 @@\n",
+                "-; nextln: after\n",
+            )
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        let source = "function %f() {}\n; check: old\n";
+        let comments = [comment(source, "; check: old")];
+        let mut bless = Bless::new(source);
+        bless.update(&[], &comments, "a").unwrap();
+        assert!(bless.update(&[], &comments, "b").is_err());
+        assert!(bless.update(&comments, &[], "a").is_err());
+        assert!(Bless::new(source).apply().is_none());
+    }
+}
@@ -0,0 +1,182 @@
+//! Caching of test results between runs.
+//!
+//! A test that passed doesn't need to run again until either the test file or the compiler
+//! changes. The cache records the content hash of each passed test file along with its duration,
+//! and the whole cache is tied to a build id identifying the test executable and the options that
+//! can change the outcome of a test. Tests whose file hash is in the cache are skipped as passed,
+//! and any change to the executable or those options discards the cache.
+//!
+//! `cton-util test` uses the cache unless it is given `--no-cache`. The `cargo test` harness always
+//! runs every test.
+//!
+//! The cache is a small text file next to the executable, so `cargo clean` removes it. The first
+//! line is `build` followed by the build id, and each following line has a content hash, a
+//! duration in nanoseconds, and a path, separated by spaces.
+//!
+//! The paths of the tests that didn't pass in the last run are also kept next to the executable,
+//! one per line, so they can be run again on their own. Unlike the cache, this list survives
+//! rebuilds, since fixing the failures usually means changing the compiler.
+
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Cached results of the tests that passed in earlier runs.
+pub struct ResultCache {
+    // The file the cache is stored in.
+    path: PathBuf,
+
+    // The build id of the running executable, combined with the run options.
+    build_id: u64,
+
+    // Content hash and duration of passed tests, by path.
+    entries: HashMap<PathBuf, (u64, Duration)>,
+}
+
+impl ResultCache {
+    /// Load the cache for the running executable and the run options identified by `options`.
+    ///
+    /// Returns `None` if the executable can't be identified. A missing or unreadable cache file,
+    /// or one written by another build or with other options, yields an empty cache.
+    pub fn load(options: u64) -> Option<Self> {
+        let exe = env::current_exe().ok()?;
+        let build_id = build_id(&exe, options)?;
+        let mut cache = Self {
+            path: exe.with_extension("testcache"),
+            build_id,
+            entries: HashMap::new(),
+        };
+        if let Ok(text) = read_to_string(&cache.path) {
+            cache.parse(&text);
+        }
+        Some(cache)
+    }
+
+    /// Parse the contents of a cache file, ignoring it unless it is for this build.
+    fn parse(&mut self, text: &str) {
+        let mut lines = text.lines();
+        let header = format!("build {:016x}", self.build_id);
+        if lines.next() != Some(header.as_str()) {
+            return;
+        }
+        for line in lines {
+            let mut fields = line.splitn(3, ' ');
+            let hash = fields.next().and_then(|h| u64::from_str_radix(h, 16).ok());
+            let nanos = fields.next().and_then(|n| n.parse::<u64>().ok());
+            if let (Some(hash), Some(nanos), Some(path)) = (hash, nanos, fields.next()) {
+                let dur = Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32);
+                self.entries.insert(PathBuf::from(path), (hash, dur));
+            }
+        }
+    }
+
+    /// Get the duration of the passed run of the test at `path` with content hash `hash`.
+    pub fn lookup(&self, path: &Path, hash: u64) -> Option<Duration> {
+        match self.entries.get(path) {
+            Some(&(h, dur)) if h == hash => Some(dur),
+            _ => None,
+        }
+    }
+
+    /// Record that the test at `path` with content hash `hash` passed in `dur`.
+    pub fn insert(&mut self, path: &Path, hash: u64, dur: Duration) {
+        self.entries.insert(path.to_path_buf(), (hash, dur));
+    }
+
+    /// Forget the test at `path`, which didn't pass.
+    pub fn remove(&mut self, path: &Path) {
+        self.entries.remove(path);
+    }
+
+    /// Write the cache back to its file.
+    pub fn save(&self) -> io::Result<()> {
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|&(path, _)| path);
+        let mut text = format!("build {:016x}\n", self.build_id);
+        for (path, &(hash, dur)) in entries {
+            let nanos = dur.as_secs() * 1_000_000_000 + u64::from(dur.subsec_nanos());
+            text.push_str(&format!("{:016x} {} {}\n", hash, nanos, path.display()));
+        }
+        fs::File::create(&self.path).and_then(|mut file| file.write_all(text.as_bytes()))
+    }
+}
+
+/// Get the file listing the tests that didn't pass in the last run.
+fn failed_list_path() -> io::Result<PathBuf> {
+    Ok(env::current_exe()?.with_extension("failed"))
+}
+
+/// Load the paths of the tests that didn't pass in the last run.
+pub fn load_failed() -> io::Result<HashSet<PathBuf>> {
+    let text = read_to_string(&failed_list_path()?)?;
+    Ok(text.lines().map(PathBuf::from).collect())
+}
+
+/// Record `paths` as the tests that didn't pass.
+pub fn save_failed(paths: &[PathBuf]) -> io::Result<()> {
+    let mut text = String::new();
+    for path in paths {
+        text.push_str(&format!("{}\n", path.display()));
+    }
+    fs::File::create(failed_list_path()?).and_then(|mut file| file.write_all(text.as_bytes()))
+}
+
+/// Compute the content hash of the test file at `path`.
+pub fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut data = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut data)?;
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+/// Identify the build of the executable at `exe` by its size and modification time, and the run
+/// options by their hash `options`.
+///
+/// Any rebuild of the compiler relinks the test executable, which changes both.
+fn build_id(exe: &Path, options: u64) -> Option<u64> {
+    let metadata = fs::metadata(exe).ok()?;
+    let mut hasher = DefaultHasher::new();
+    options.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    metadata.modified().ok()?.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Read an entire file into a string.
+fn read_to_string(path: &Path) -> io::Result<String> {
+    let mut text = String::new();
+    fs::File::open(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let mut cache = ResultCache {
+            path: PathBuf::from("test.testcache"),
+            build_id: 0x1234,
+            entries: HashMap::new(),
+        };
+        cache.parse("build 0000000000001234\n00000000000000ff 1500000000 a dir/b.cton\nbad\n");
+        assert_eq!(
+            cache.lookup(Path::new("a dir/b.cton"), 0xff),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(cache.lookup(Path::new("a dir/b.cton"), 0xfe), None);
+        assert_eq!(cache.entries.len(), 1);
+
+        // A cache written by another build is ignored.
+        cache.entries.clear();
+        cache.parse("build 0000000000001235\n00000000000000ff 1500000000 a dir/b.cton\n");
+        assert!(cache.entries.is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+//! Filecheck variables captured across the functions in a test file.
+//!
+//! A filecheck variable defined by a directive like `check: $(v=$V) = iadd` is normally only
+//! visible to the later directives for the same function. The `Captures` collected from the
+//! functions already tested in a file are passed to filecheck as predefined variables, so the
+//! directives for a function can refer to a value name or stack slot captured in an earlier one.
+//!
+//! Filecheck doesn't report the values it bound, so they are recovered by matching the
+//! directives again once the check has passed. The `check:`, `sameln:`, and `nextln:` directives
+//! are matched in order, each after the previous match, and `unordered:` directives after the last
+//! ordered match. Variables defined by `not:` directives are never recorded.
+
+use filecheck::{Value, VariableMap};
+use regex::{escape, Regex};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Variables captured by the filecheck directives of earlier functions.
+#[derive(Default)]
+pub struct Captures {
+    vars: HashMap<String, String>,
+}
+
+impl VariableMap for Captures {
+    fn lookup(&self, varname: &str) -> Option<Value> {
+        self.vars.get(varname).map(
+            |text| Value::Text(Cow::Borrowed(text.as_str())),
+        )
+    }
+}
+
+impl Captures {
+    /// Record the variables defined by `directives` when they matched `text`.
+    ///
+    /// The directives are comment lines, as passed to `CheckerBuilder::directive`. Lines that
+    /// aren't directives are ignored.
+    pub fn record<'d, I>(&mut self, directives: I, text: &str)
+    where
+        I: IntoIterator<Item = &'d str>,
+    {
+        let directive_re = Regex::new(
+            r"\b(check|sameln|nextln|unordered|not|regex):\s*(.*)$",
+        ).unwrap();
+        // Variables defined by `regex:` directives, and the ones captured so far in this function.
+        let mut regexes: HashMap<String, String> = HashMap::new();
+        let mut locals: HashMap<String, String> = HashMap::new();
+        let mut pos = 0;
+
+        for line in directives {
+            let caps = match directive_re.captures(line) {
+                Some(caps) => caps,
+                None => continue,
+            };
+            let (kind, pattern) = (&caps[1], caps[2].trim());
+            if kind == "regex" {
+                if let Some(eq) = pattern.find('=') {
+                    let (name, regex) = (pattern[0..eq].trim(), &pattern[eq + 1..]);
+                    regexes.insert(name.to_string(), regex.to_string());
+                }
+                continue;
+            }
+            if kind == "not" {
+                continue;
+            }
+            let (regex, defs) = {
+                let lookup = |name: &str| if let Some(re) = regexes.get(name) {
+                    Some(re.clone())
+                } else {
+                    locals.get(name).or_else(|| self.vars.get(name)).map(
+                        |text| escape(text),
+                    )
+                };
+                match to_regex(pattern, &lookup) {
+                    Some(found) => found,
+                    None => continue,
+                }
+            };
+            let caps = match regex.captures(&text[pos..]) {
+                Some(caps) => caps,
+                None => continue,
+            };
+            for name in defs {
+                if let Some(m) = caps.name(&name) {
+                    locals.insert(name, m.as_str().to_string());
+                }
+            }
+            if kind != "unordered" {
+                pos += caps.get(0).map_or(0, |m| m.end());
+            }
+        }
+        self.vars.extend(locals);
+    }
+}
+
+/// Convert a filecheck pattern into a regex with a named group for each variable it defines.
+///
+/// Variable references are replaced by the regexes returned by `lookup`, which returns `None` for
+/// variables that aren't known. Returns the regex and the names of the defined variables, or
+/// `None` if the pattern can't be converted.
+fn to_regex<F>(pattern: &str, lookup: &F) -> Option<(Regex, Vec<String>)>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut out = String::new();
+    let mut defs = Vec::new();
+    let mut literal = String::new();
+    let mut rest = pattern;
+
+    while let Some(dollar) = rest.find('$') {
+        literal.push_str(&rest[0..dollar]);
+        rest = &rest[dollar + 1..];
+        if rest.starts_with('$') {
+            literal.push('$');
+            rest = &rest[1..];
+            continue;
+        }
+        push_literal(&mut out, &literal);
+        literal.clear();
+
+        if rest.starts_with('(') {
+            // `$(name)` or `$(name=regex)`, where the regex may contain balanced parentheses.
+            let mut depth = 0;
+            let close = rest.char_indices().find(|&(_, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })?
+                .0;
+            let inner = &rest[1..close];
+            rest = &rest[close + 1..];
+            match inner.find('=') {
+                Some(eq) => {
+                    let name = inner[0..eq].trim();
+                    let body = expand_regex(&inner[eq + 1..], &defs, lookup)?;
+                    if defs.iter().any(|d| d == name) {
+                        return None;
+                    }
+                    out.push_str(&format!("(?P<{}>{})", name, body));
+                    defs.push(name.to_string());
+                }
+                None => out.push_str(&reference(inner.trim(), &defs, lookup)?),
+            }
+        } else {
+            let len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+            if len == 0 {
+                return None;
+            }
+            out.push_str(&reference(&rest[0..len], &defs, lookup)?);
+            rest = &rest[len..];
+        }
+    }
+    literal.push_str(rest);
+    push_literal(&mut out, &literal);
+    Regex::new(&out).ok().map(|re| (re, defs))
+}
+
+/// Get the regex for a reference to the variable `name`.
+fn reference<F>(name: &str, defs: &[String], lookup: &F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if defs.iter().any(|d| d == name) {
+        // A back-reference to a variable defined earlier in the same pattern. The regex crate
+        // doesn't support those, but the pattern has already matched, so anything will do.
+        Some(".*?".to_string())
+    } else {
+        lookup(name).map(|re| format!("(?:{})", re))
+    }
+}
+
+/// Expand the variable references in the regex of a variable definition.
+fn expand_regex<F>(regex: &str, defs: &[String], lookup: &F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut out = String::new();
+    let mut rest = regex;
+    while let Some(dollar) = rest.find('$') {
+        out.push_str(&rest[0..dollar]);
+        rest = &rest[dollar + 1..];
+        let len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+        if len == 0 {
+            // A `$` anchor.
+            out.push('$');
+            continue;
+        }
+        out.push_str(&reference(&rest[0..len], defs, lookup)?);
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+/// Append literal text to a regex, matching any run of whitespace with any other.
+fn push_literal(out: &mut String, literal: &str) {
+    let mut space = false;
+    for c in literal.chars() {
+        if !c.is_whitespace() {
+            out.push_str(&escape(&c.to_string()));
+        } else if !space {
+            out.push_str(r"\s+");
+        }
+        space = c.is_whitespace();
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(captures: &Captures, name: &str) -> Option<String> {
+        match captures.lookup(name) {
+            Some(Value::Text(text)) => Some(text.into_owned()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn record() {
+        let mut captures = Captures::default();
+        captures.record(
+            vec![
+                "; regex: V=v\\d+",
+                "; check: $(sum=$V) = iadd",
+                "; not: $(bad=$V) = isub",
+                "; check: return $sum",
+                "; check: $(slot=ss\\d+) = spill_slot 4",
+                "not a directive",
+            ],
+            "v1 = iconst.i32 1\nv7 = iadd v1, v1\nreturn v7\nss3 = spill_slot 4\n",
+        );
+        assert_eq!(lookup(&captures, "sum"), Some("v7".to_string()));
+        assert_eq!(lookup(&captures, "slot"), Some("ss3".to_string()));
+        assert_eq!(lookup(&captures, "bad"), None);
+
+        // A later function can refer to the captured variables in its own definitions.
+        captures.record(
+            vec!["; check: $(copy=v\\d+) = copy $sum"],
+            "v2 = copy v3\nv9 = copy v7\n",
+        );
+        assert_eq!(lookup(&captures, "copy"), Some("v9".to_string()));
+    }
+
+    #[test]
+    fn patterns() {
+        let none = |_: &str| None;
+        let (re, defs) = to_regex("$(x=[a-z]+)  $$ $(y=($x|q)) ($x)", &none).unwrap();
+        assert_eq!(defs, ["x", "y"]);
+        assert_eq!(re.as_str(), r"(?P<x>[a-z]+)\s+\$\s+(?P<y>(.*?|q))\s+\(.*?\)");
+        assert!(to_regex("$unknown", &none).is_none());
+        assert!(to_regex("$(x=a) $(x=b)", &none).is_none());
+    }
+}
@@ -4,22 +4,30 @@
 //! concurrently.
 
 use cretonne::timing;
+use file_per_thread_logger;
 use std::panic::catch_unwind;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Sender, Receiver};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use num_cpus;
-use {TestResult, runone};
+use {TestResult, LOG_FILENAME_PREFIX, runone};
+use runone::Expect;
 
-// Request sent to worker threads contains jobid and path.
-struct Request(usize, PathBuf);
+// Request sent to worker threads contains jobid, path, and ISA configuration.
+struct Request(usize, PathBuf, Option<usize>);
 
 /// Reply from worker thread,
 pub enum Reply {
-    Starting { jobid: usize, thread_num: usize },
+    Starting {
+        jobid: usize,
+        thread_num: usize,
+        timeout: Option<usize>,
+        expect: Expect,
+    },
     Done { jobid: usize, result: TestResult },
+    Cancelled { jobid: usize },
     Tick,
 }
 
@@ -34,28 +42,77 @@ pub struct ConcurrentRunner {
     // Workers have their own `Sender`.
     reply_rx: Receiver<Reply>,
 
-    handles: Vec<thread::JoinHandle<timing::PassTimes>>,
+    // Shared ends of the channels, for spawning replacement workers.
+    request_mutex: Arc<Mutex<Receiver<Request>>>,
+    reply_tx: Sender<Reply>,
+
+    // Run tests in bless mode.
+    bless: bool,
+
+    // Set when the queued jobs should be cancelled instead of run.
+    cancelled: Arc<AtomicBool>,
+
+    // Worker threads indexed by thread number. Abandoned workers are `None`.
+    handles: Vec<Option<thread::JoinHandle<timing::PassTimes>>>,
+
+    // Per-worker flags telling a worker to exit instead of taking another job, indexed like
+    // `handles`.
+    abandoned: Vec<Arc<AtomicBool>>,
 }
 
 impl ConcurrentRunner {
-    /// Create a new `ConcurrentRunner` with threads spun up.
-    pub fn new() -> Self {
+    /// Create a new `ConcurrentRunner` with `num_threads` worker threads spun up.
+    ///
+    /// The workers run the tests in bless mode if `bless` is set.
+    pub fn new(num_threads: usize, bless: bool) -> Self {
         let (request_tx, request_rx) = channel();
         let request_mutex = Arc::new(Mutex::new(request_rx));
         let (reply_tx, reply_rx) = channel();
 
-        heartbeat_thread(reply_tx.clone());
+        let cancelled = Arc::new(AtomicBool::new(false));
 
-        let handles = (0..num_cpus::get())
-            .map(|num| {
-                worker_thread(num, request_mutex.clone(), reply_tx.clone())
-            })
-            .collect();
+        heartbeat_thread(reply_tx.clone());
 
-        Self {
+        let mut runner = Self {
             request_tx: Some(request_tx),
             reply_rx,
-            handles,
+            request_mutex,
+            reply_tx,
+            bless,
+            cancelled,
+            handles: Vec::new(),
+            abandoned: Vec::new(),
+        };
+        for _ in 0..num_threads {
+            runner.spawn_worker();
+        }
+        runner
+    }
+
+    /// Spawn a new worker thread numbered after the existing ones.
+    fn spawn_worker(&mut self) {
+        let abandoned = Arc::new(AtomicBool::new(false));
+        let handle = worker_thread(
+            self.handles.len(),
+            self.request_mutex.clone(),
+            self.reply_tx.clone(),
+            self.bless,
+            self.cancelled.clone(),
+            abandoned.clone(),
+        );
+        self.handles.push(Some(handle));
+        self.abandoned.push(abandoned);
+    }
+
+    /// Give up on the worker thread `thread_num`, which is stuck running a test that timed out.
+    ///
+    /// Threads can't be killed, so the worker is left running but won't be joined, and its pass
+    /// timings are lost. It is flagged so that it exits instead of taking another job if the test
+    /// ever finishes, keeping the pool at its original size. A new worker is spawned in its place.
+    pub fn abandon(&mut self, thread_num: usize) {
+        if self.handles[thread_num].take().is_some() {
+            self.abandoned[thread_num].store(true, Ordering::Relaxed);
+            self.spawn_worker();
         }
     }
 
@@ -64,11 +121,23 @@ impl ConcurrentRunner {
         self.request_tx = None;
     }
 
+    /// Cancel the queued jobs. The workers reply `Cancelled` to them instead of running them.
+    ///
+    /// The jobs that are already running are allowed to finish.
+    pub fn cancel(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
     /// Join all the worker threads.
     /// Transfer pass timings from the worker threads to the current thread.
     pub fn join(&mut self) {
         assert!(self.request_tx.is_none(), "must shutdown before join");
         for h in self.handles.drain(..) {
+            // Abandoned workers may never finish.
+            let h = match h {
+                Some(h) => h,
+                None => continue,
+            };
             match h.join() {
                 Ok(t) => timing::add_to_current(t),
                 Err(e) => println!("worker panicked: {:?}", e),
@@ -77,11 +146,11 @@ impl ConcurrentRunner {
     }
 
     /// Add a new job to the queues.
-    pub fn put(&mut self, jobid: usize, path: &Path) {
+    pub fn put(&mut self, jobid: usize, path: &Path, config: Option<usize>) {
         self.request_tx
             .as_ref()
             .expect("cannot push after shutdown")
-            .send(Request(jobid, path.to_owned()))
+            .send(Request(jobid, path.to_owned(), config))
             .expect("all the worker threads are gone");
     }
 
@@ -112,35 +181,67 @@ fn worker_thread(
     thread_num: usize,
     requests: Arc<Mutex<Receiver<Request>>>,
     replies: Sender<Reply>,
+    bless: bool,
+    cancelled: Arc<AtomicBool>,
+    abandoned: Arc<AtomicBool>,
 ) -> thread::JoinHandle<timing::PassTimes> {
     thread::Builder::new()
         .name(format!("worker #{}", thread_num))
         .spawn(move || {
+            file_per_thread_logger::initialize(LOG_FILENAME_PREFIX);
             loop {
+                // An abandoned worker that came unstuck must not compete with its replacement.
+                if abandoned.load(Ordering::Relaxed) {
+                    break;
+                }
+
                 // Lock the mutex only long enough to extract a request.
-                let Request(jobid, path) = match requests.lock().unwrap().recv() {
+                let Request(jobid, path, config) = match requests.lock().unwrap().recv() {
                     Err(..) => break, // TX end shut down. exit thread.
                     Ok(req) => req,
                 };
 
-                // Tell them we're starting this job.
-                // The receiver should always be present for this as long as we have jobs.
-                replies.send(Reply::Starting { jobid, thread_num }).unwrap();
-
-                let result = catch_unwind(|| runone::run(path.as_path())).unwrap_or_else(|e| {
-                    // The test panicked, leaving us a `Box<Any>`.
-                    // Panics are usually strings.
-                    if let Some(msg) = e.downcast_ref::<String>() {
-                        Err(format!("panicked in worker #{}: {}", thread_num, msg))
-                    } else if let Some(msg) = e.downcast_ref::<&'static str>() {
-                        Err(format!("panicked in worker #{}: {}", thread_num, msg))
-                    } else {
-                        Err(format!("panicked in worker #{}", thread_num))
-                    }
-                });
+                if cancelled.load(Ordering::Relaxed) {
+                    replies.send(Reply::Cancelled { jobid }).unwrap();
+                    continue;
+                }
+
+                // Tell them we're starting this job, how long it may take, and whether it should
+                // pass. The receiver should always be present for this as long as we have jobs.
+                let header = runone::header(path.as_path());
+                replies
+                    .send(Reply::Starting {
+                        jobid,
+                        thread_num,
+                        timeout: header.timeout,
+                        expect: header.expect,
+                    })
+                    .unwrap();
+                if header.expect == Expect::Skip {
+                    replies
+                        .send(Reply::Done {
+                            jobid,
+                            result: Ok(Duration::default()),
+                        })
+                        .unwrap();
+                    continue;
+                }
+
+                let result = catch_unwind(|| runone::run(path.as_path(), config, bless))
+                    .unwrap_or_else(|e| {
+                        // The test panicked, leaving us a `Box<Any>`.
+                        // Panics are usually strings.
+                        if let Some(msg) = e.downcast_ref::<String>() {
+                            Err(format!("panicked in worker #{}: {}", thread_num, msg))
+                        } else if let Some(msg) = e.downcast_ref::<&'static str>() {
+                            Err(format!("panicked in worker #{}: {}", thread_num, msg))
+                        } else {
+                            Err(format!("panicked in worker #{}", thread_num))
+                        }
+                    });
 
                 if let Err(ref msg) = result {
-                    dbg!("FAIL: {}", msg);
+                    debug!("FAIL: {}", msg);
                 }
 
                 replies.send(Reply::Done { jobid, result }).unwrap();
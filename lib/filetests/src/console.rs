@@ -0,0 +1,128 @@
+//! Output of the test runner.
+//!
+//! When stdout is a terminal, results are printed in color, and the last line shows the progress
+//! of the run with an estimate of the remaining time. The progress line is rewritten in place, and
+//! it is erased before printing anything else. Otherwise, the output is plain text with no progress
+//! line, as expected by scripts and log files.
+
+use atty;
+use std::io::Write;
+use std::time;
+use term::{self, color, StdoutTerminal};
+
+/// The kind of a line of output, which decides its color.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Style {
+    /// A passed test.
+    Pass,
+    /// A failure.
+    Fail,
+    /// A skipped test or an expected failure.
+    Skip,
+    /// A warning about a slow or stalled test.
+    Slow,
+}
+
+/// Where the test runner prints its output.
+pub struct Console {
+    // The terminal, if stdout is one that supports color.
+    terminal: Option<Box<StdoutTerminal>>,
+
+    // When the run started, for estimating the remaining time.
+    started: time::Instant,
+
+    // The progress line currently displayed.
+    status: Option<String>,
+}
+
+impl Console {
+    /// Create a console that prints to stdout, with color if it is a terminal.
+    pub fn new() -> Self {
+        let terminal = if atty::is(atty::Stream::Stdout) {
+            term::stdout().and_then(|t| if t.supports_color() { Some(t) } else { None })
+        } else {
+            None
+        };
+        Self {
+            terminal,
+            started: time::Instant::now(),
+            status: None,
+        }
+    }
+
+    /// Print a line of output in the given style.
+    pub fn println(&mut self, style: Style, text: &str) {
+        let terminal = match self.terminal {
+            Some(ref mut terminal) => terminal,
+            None => {
+                println!("{}", text);
+                return;
+            }
+        };
+        clear_line(&mut **terminal);
+        let _ = terminal.fg(match style {
+            Style::Pass => color::GREEN,
+            Style::Fail => color::BRIGHT_RED,
+            Style::Skip => color::YELLOW,
+            Style::Slow => color::MAGENTA,
+        });
+        let _ = write!(terminal, "{}", text);
+        let _ = terminal.reset();
+        let _ = writeln!(terminal);
+        if let Some(ref status) = self.status {
+            let _ = write!(terminal, "{}", status);
+        }
+        let _ = terminal.flush();
+    }
+
+    /// Show that `done` of `total` tests have finished.
+    ///
+    /// This does nothing unless stdout is a terminal.
+    pub fn progress(&mut self, done: usize, total: usize) {
+        let terminal = match self.terminal {
+            Some(ref mut terminal) => terminal,
+            None => return,
+        };
+        let status = format!("[{}/{}]{}", done, total, eta(self.started.elapsed(), done, total));
+        clear_line(&mut **terminal);
+        let _ = write!(terminal, "{}", status);
+        let _ = terminal.flush();
+        self.status = Some(status);
+    }
+
+    /// Erase the progress line, if any.
+    pub fn clear_progress(&mut self) {
+        if let Some(ref mut terminal) = self.terminal {
+            if self.status.take().is_some() {
+                clear_line(&mut **terminal);
+                let _ = terminal.flush();
+            }
+        }
+    }
+}
+
+/// Erase the current line of the terminal and move the cursor to the start of it.
+fn clear_line(terminal: &mut StdoutTerminal) {
+    if terminal.carriage_return().and_then(|_| terminal.delete_line()).is_err() {
+        // Fall back to overwriting the line with spaces when the terminal can't delete it.
+        let _ = write!(terminal, "\r{:79}\r", "");
+    }
+}
+
+/// Estimate the remaining time, given the time taken to finish `done` of `total` tests.
+fn eta(elapsed: time::Duration, done: usize, total: usize) -> String {
+    if done == 0 || done >= total {
+        return String::new();
+    }
+    let millis = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_nanos() / 1_000_000);
+    let secs = millis * (total - done) as u64 / done as u64 / 1000;
+    format!(" ETA {}:{:02}", secs / 60, secs % 60)
+}
+
+#[test]
+fn test_eta() {
+    assert_eq!(eta(time::Duration::from_secs(10), 0, 10), "");
+    assert_eq!(eta(time::Duration::from_secs(10), 10, 10), "");
+    assert_eq!(eta(time::Duration::from_secs(10), 1, 10), " ETA 1:30");
+    assert_eq!(eta(time::Duration::from_millis(2500), 5, 6), " ETA 0:00");
+}
@@ -0,0 +1,313 @@
+//! Differential testing of the native code against the interpreter.
+//!
+//! The `cton-util differential` command generates random functions like `cton-util generate`,
+//! compiles them for the host machine with `cton_simplejit`, and calls each of them with random
+//! arguments, both natively and in `cton_interpreter`. The interpreter is simple enough to trust,
+//! so different results mean that the function was miscompiled by an optimization or a backend.
+//!
+//! The native results are stored to memory by a trampoline that calls the function with constant
+//! arguments, so any signature can be called.
+
+use cretonne::Context;
+use cretonne::cursor::{Cursor, FuncCursor};
+use cretonne::ir::{types, AbiParam, CallConv, ExtFuncData, ExternalName, Function, InstBuilder,
+                   MemFlags, Signature, Type, Value};
+use cton_interpreter::{DataValue, Interpreter};
+use cton_simplejit::SimpleJIT;
+use generate::{generate, Shape, FLOATS, INTEGERS};
+use mutate::catch;
+use random::Rng;
+use std::cmp;
+use std::mem;
+use std::panic;
+use std::time;
+use test_run::constant;
+use TestResult;
+
+/// The number of functions generated together. Each of them may call the ones after it.
+const GROUP: usize = 8;
+
+/// The maximum number of instructions the interpreter executes for a call. The generated
+/// functions always terminate, so this is only a safety net.
+const FUEL: u64 = 10_000_000;
+
+/// Generate `count` random functions with `shape`, using the random sequence given by `seed`, and
+/// check that the native code computes the same results as the interpreter for `inputs` random
+/// arguments each.
+pub fn run(shape: &Shape, count: usize, inputs: usize, seed: u64, verbose: bool) -> TestResult {
+    let started = time::Instant::now();
+    let mut rng = Rng::new(seed);
+    let mut generated = 0;
+    let mut failures = 0;
+
+    // The panics are reported with the functions that caused them.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let mut result = Ok(());
+    while generated < count && result.is_ok() {
+        let group = generate(shape, cmp::min(GROUP, count - generated), &mut rng);
+        generated += group.len();
+        result = run_group(&group, inputs, &mut rng, verbose).map(|n| failures += n);
+    }
+    panic::set_hook(hook);
+    result?;
+
+    println!(
+        "{} functions run with {} inputs each, {} failed (seed {})",
+        generated,
+        inputs,
+        failures,
+        seed
+    );
+    if failures == 0 {
+        Ok(started.elapsed())
+    } else {
+        Err(format!(
+            "{} functions computed different results natively",
+            failures
+        ))
+    }
+}
+
+/// A call of a generated function with random arguments.
+struct Call<'a> {
+    func: &'a Function,
+    args: Vec<DataValue>,
+    /// The results computed by the interpreter.
+    expected: Vec<DataValue>,
+    /// The trampoline making the call natively.
+    trampoline: ExternalName,
+}
+
+/// Run the functions in `group`, which may call each other, and return the number of failures.
+fn run_group(
+    group: &[Function],
+    inputs: usize,
+    rng: &mut Rng,
+    verbose: bool,
+) -> Result<usize, String> {
+    let mut failed = vec![false; group.len()];
+    let mut interpreter = Interpreter::new();
+    for func in group {
+        interpreter.add_function(func);
+    }
+
+    let mut jit = SimpleJIT::new().map_err(|e| e.to_string())?;
+    let pointer_type = if jit.isa().flags().is_64bit() {
+        types::I64
+    } else {
+        types::I32
+    };
+    for (i, func) in group.iter().enumerate() {
+        if verbose {
+            println!("{}", func.display(None));
+        }
+        let mut ctx = Context::for_function(func.clone());
+        if let Some(msg) = failure(catch(|| jit.compile_function(func.name.clone(), &mut ctx))) {
+            report(&mut failed[i], func, &format!("compiling {} {}", func.name, msg));
+        }
+    }
+    if failed.iter().any(|&f| f) {
+        // The other functions may call the ones that failed to compile.
+        return Ok(group.len());
+    }
+
+    let mut calls = Vec::new();
+    for (i, func) in group.iter().enumerate() {
+        for input in 0..inputs {
+            let args: Vec<DataValue> = func.signature
+                .params
+                .iter()
+                .map(|param| argument(param.value_type, rng))
+                .collect();
+            interpreter.set_fuel(Some(FUEL));
+            let expected = match interpreter.call(&func.name, &args) {
+                Ok(results) => results,
+                Err(e) => {
+                    let msg = format!("{}: the interpreter failed: {}", call_text(func, &args), e);
+                    report(&mut failed[i], func, &msg);
+                    continue;
+                }
+            };
+
+            let name = ExternalName::testcase(format!("call{}_{}", i, input));
+            let tramp = trampoline(name.clone(), func, &args, pointer_type);
+            let mut ctx = Context::for_function(tramp);
+            if let Some(msg) = failure(catch(|| jit.compile_function(name.clone(), &mut ctx))) {
+                let msg = format!("compiling the trampoline of {} {}", call_text(func, &args), msg);
+                report(&mut failed[i], func, &msg);
+                continue;
+            }
+            calls.push(Call {
+                func,
+                args,
+                expected,
+                trampoline: name,
+            });
+        }
+    }
+    jit.finalize().map_err(|e| e.to_string())?;
+
+    for call in &calls {
+        let i = group.iter().position(|f| f.name == call.func.name).unwrap();
+        let code = jit.get_function(&call.trampoline).expect("trampoline was compiled");
+        let trampoline: extern "C" fn(*mut u64) = unsafe { mem::transmute(code) };
+        let mut slots = vec![0u64; cmp::max(call.expected.len(), 1)];
+        trampoline(slots.as_mut_ptr());
+        let results: Vec<DataValue> = call.expected
+            .iter()
+            .zip(&slots)
+            .map(|(expected, &bits)| decode(expected.ty(), bits))
+            .collect();
+        if results.len() != call.expected.len() ||
+            results.iter().zip(&call.expected).any(|(&a, &b)| !same(a, b))
+        {
+            let msg = format!(
+                "{}: the native code returned {}, but the interpreter returned {}",
+                call_text(call.func, &call.args),
+                list(&results),
+                list(&call.expected)
+            );
+            report(&mut failed[i], call.func, &msg);
+        }
+    }
+
+    Ok(failed.iter().filter(|&&f| f).count())
+}
+
+/// Describe the failure of a compilation, if it failed.
+fn failure<E: ToString>(result: Result<Result<(), E>, String>) -> Option<String> {
+    match result {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(format!("failed: {}", e.to_string())),
+        Err(msg) => Some(format!("panicked: {}", msg)),
+    }
+}
+
+/// Print a failure of `func`. The function is printed after its first failure.
+fn report(failed: &mut bool, func: &Function, msg: &str) {
+    println!("{}", msg);
+    if !*failed {
+        *failed = true;
+        println!("{}", func.display(None));
+    }
+}
+
+fn list(values: &[DataValue]) -> String {
+    let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+    values.join(", ")
+}
+
+fn call_text(func: &Function, args: &[DataValue]) -> String {
+    format!("{}({})", func.name, list(args))
+}
+
+/// Pick a random argument of type `ty`, favoring the edge cases.
+fn argument(ty: Type, rng: &mut Rng) -> DataValue {
+    let edge = rng.below(2) == 0;
+    if ty.is_int() {
+        let x = if edge {
+            *rng.pick(&INTEGERS).unwrap()
+        } else {
+            rng.next_u64() as i64
+        };
+        DataValue::int(ty, x)
+    } else if ty.is_bool() {
+        DataValue::Bool(ty, edge)
+    } else if ty == types::F32 {
+        let x = if edge {
+            *rng.pick(&FLOATS).unwrap() as f32
+        } else {
+            f32::from_bits(rng.next_u64() as u32)
+        };
+        DataValue::F32(x)
+    } else {
+        let x = if edge {
+            *rng.pick(&FLOATS).unwrap()
+        } else {
+            f64::from_bits(rng.next_u64())
+        };
+        DataValue::F64(x)
+    }
+}
+
+/// Decode a result of type `ty` stored by a trampoline.
+pub fn decode(ty: Type, bits: u64) -> DataValue {
+    if ty.is_int() {
+        DataValue::int(ty, bits as i64)
+    } else if ty.is_bool() {
+        DataValue::Bool(ty, bits as u32 != 0)
+    } else if ty == types::F32 {
+        DataValue::F32(f32::from_bits(bits as u32))
+    } else {
+        DataValue::F64(f64::from_bits(bits))
+    }
+}
+
+/// Are `a` and `b` the same result?
+///
+/// Floats must have the same bits, so zeros of different signs differ, but all NaNs are the same:
+/// the payloads of computed NaNs differ between the hosts.
+fn same(a: DataValue, b: DataValue) -> bool {
+    match (a, b) {
+        (DataValue::F32(a), DataValue::F32(b)) => {
+            (a.is_nan() && b.is_nan()) || a.to_bits() == b.to_bits()
+        }
+        (DataValue::F64(a), DataValue::F64(b)) => {
+            (a.is_nan() && b.is_nan()) || a.to_bits() == b.to_bits()
+        }
+        _ => a == b,
+    }
+}
+
+/// Create a function named `name` that calls `func` with `args`, and stores the results in the
+/// consecutive 8-byte slots pointed to by its parameter.
+pub fn trampoline(
+    name: ExternalName,
+    func: &Function,
+    args: &[DataValue],
+    pointer_type: Type,
+) -> Function {
+    let mut sig = Signature::new(CallConv::Native);
+    sig.params.push(AbiParam::new(pointer_type));
+    let mut tramp = Function::with_name_signature(name, sig);
+    let sigref = tramp.import_signature(func.signature.clone());
+    let callee = tramp.import_function(ExtFuncData {
+        name: func.name.clone(),
+        signature: sigref,
+    });
+
+    let ebb = tramp.dfg.make_ebb();
+    let slots = tramp.dfg.append_ebb_param(ebb, pointer_type);
+    let mut pos = FuncCursor::new(&mut tramp);
+    pos.insert_ebb(ebb);
+    let args: Vec<Value> = args.iter().map(|&arg| constant(&mut pos, arg)).collect();
+    let call = pos.ins().call(callee, &args);
+    let results = pos.func.dfg.inst_results(call).to_vec();
+    for (i, result) in results.into_iter().enumerate() {
+        let ty = pos.func.dfg.value_type(result);
+        let value = if ty.is_bool() {
+            pos.ins().bint(types::I32, result)
+        } else if ty.is_int() && ty.bits() < 32 {
+            pos.ins().uextend(types::I32, result)
+        } else {
+            result
+        };
+        pos.ins().store(MemFlags::new(), value, slots, 8 * i as i32);
+    }
+    pos.ins().return_(&[]);
+    tramp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_results() {
+        assert!(same(DataValue::F32(::std::f32::NAN), DataValue::F32(-::std::f32::NAN)));
+        assert!(!same(DataValue::F64(0.0), DataValue::F64(-0.0)));
+        assert!(same(DataValue::int(types::I8, -1), decode(types::I8, 0xff)));
+        assert!(same(DataValue::Bool(types::B1, true), decode(types::B1, 1)));
+    }
+}
@@ -0,0 +1,309 @@
+//! Running a function from the command line.
+//!
+//! The `cton-util run` command compiles a file for the host machine, calls one of its functions
+//! with arguments given on the command line, and prints the results. It reads the file and detects
+//! its format, and this module takes over from there:
+//!
+//! - The functions of a `.cton` file are compiled with `cton_simplejit`. Calls to the functions
+//!   `%print_i32`, `%print_i64`, `%print_f32`, and `%print_f64` go to host functions that print
+//!   their argument. The called function must only have normal parameters.
+//! - A WebAssembly module is instantiated by the runtime of the `wast` test command. It may
+//!   import the linear memory, table, globals, and print functions of the `spectest` module, which
+//!   print their arguments like the reference interpreter. The start function runs first.
+//!
+//! Without an explicit function name, the function or export named `main` is called. A `.cton`
+//! file with a single function calls that function, and a WebAssembly module with a start
+//! function but no `main` export is only instantiated.
+//!
+//! Every call runs in a child process, so a trap is reported instead of killing the tool.
+//!
+//! The `cton-util interpret` command evaluates a function of a `.cton` file with the IL
+//! interpreter instead, which doesn't need a native backend. The function is selected in the same
+//! way, and it may call the other functions of the file.
+
+use cretonne::Context;
+use cretonne::ir::{types, ArgumentPurpose, ExternalName, Function, Type};
+use cton_interpreter::{DataValue, Interpreter};
+use cton_reader::parse_functions;
+use cton_simplejit::SimpleJIT;
+use differential::{decode, trampoline};
+use run_directive::parse_constant;
+use std::cmp;
+use std::mem;
+use wast::{Action, Value};
+use wast_runtime::{call_in_child, print_function, Region, Runtime, PRINT_FUNCTIONS};
+
+/// The name of the function called by default.
+const MAIN: &str = "main";
+
+/// Print the results of a call.
+pub fn print_results(results: Vec<DataValue>) {
+    for result in results {
+        println!("{}", show(result));
+    }
+}
+
+/// Evaluate the function `invoke` of the `.cton` functions in `text` with the interpreter.
+///
+/// The interpreter executes at most `fuel` instructions, if given.
+pub fn interpret_cton(
+    text: &str,
+    invoke: Option<&str>,
+    args: &[String],
+    fuel: Option<u64>,
+) -> Result<Vec<DataValue>, String> {
+    let funcs = parse_functions(text).map_err(|e| e.to_string())?;
+    let func = select(&funcs, invoke)?;
+    let param_types: Vec<Type> = func.signature.params.iter().map(|p| p.value_type).collect();
+    let args = arguments(args, &param_types)?;
+
+    let mut interpreter = Interpreter::new();
+    for func in &funcs {
+        interpreter.add_function(func);
+    }
+    interpreter.set_fuel(fuel);
+    interpreter.call(&func.name, &args).map_err(
+        |e| e.to_string(),
+    )
+}
+
+/// Call the function `invoke` of the `.cton` functions in `text`.
+pub fn run_cton(
+    text: &str,
+    invoke: Option<&str>,
+    args: &[String],
+) -> Result<Vec<DataValue>, String> {
+    let funcs = parse_functions(text).map_err(|e| e.to_string())?;
+    let func = select(&funcs, invoke)?;
+    if let Some(param) = func.signature.params.iter().find(|p| {
+        p.purpose != ArgumentPurpose::Normal
+    })
+    {
+        return Err(format!("{} has a {} parameter, which can't be passed", func.name, param));
+    }
+    let param_types: Vec<Type> = func.signature.params.iter().map(|p| p.value_type).collect();
+    let args = arguments(args, &param_types)?;
+
+    let mut jit = SimpleJIT::new().map_err(|e| e.to_string())?;
+    for &(name, _) in &PRINT_FUNCTIONS {
+        jit.symbol(ExternalName::testcase(name), print_function(name));
+    }
+    for func in &funcs {
+        let mut ctx = Context::for_function(func.clone());
+        jit.compile_function(func.name.clone(), &mut ctx).map_err(
+            |e| {
+                format!("compiling {}: {}", func.name, e)
+            },
+        )?;
+    }
+    let pointer_type = if jit.isa().flags().is_64bit() {
+        types::I64
+    } else {
+        types::I32
+    };
+    let name = ExternalName::testcase("cton_util_run");
+    let mut ctx = Context::for_function(trampoline(name.clone(), func, &args, pointer_type));
+    jit.compile_function(name.clone(), &mut ctx).map_err(|e| {
+        format!("compiling the trampoline: {}", e)
+    })?;
+    jit.finalize().map_err(|e| e.to_string())?;
+
+    let code = jit.get_function(&name).expect("trampoline was compiled");
+    let trampoline: extern "C" fn(*mut u64) = unsafe { mem::transmute(code) };
+    let returns = &func.signature.returns;
+    let slots = Region::words(cmp::max(returns.len(), 1) * 8 / mem::size_of::<usize>())?;
+    let slots = slots.ptr as *mut u64;
+    call_in_child(|| trampoline(slots)).map_err(|e| e.to_string())?;
+    Ok(
+        returns
+            .iter()
+            .enumerate()
+            .map(|(i, ret)| {
+                decode(ret.value_type, unsafe { *slots.offset(i as isize) })
+            })
+            .collect(),
+    )
+}
+
+/// Select the function named `invoke` in `funcs`, or the default function.
+fn select<'a>(funcs: &'a [Function], invoke: Option<&str>) -> Result<&'a Function, String> {
+    match invoke {
+        Some(name) => find(funcs, name.trim_left_matches('%')),
+        None if funcs.len() == 1 => Ok(&funcs[0]),
+        None => find(funcs, MAIN),
+    }
+}
+
+fn find<'a>(funcs: &'a [Function], name: &str) -> Result<&'a Function, String> {
+    let name = ExternalName::testcase(name);
+    funcs.iter().find(|func| func.name == name).ok_or_else(|| {
+        let names: Vec<String> = funcs.iter().map(|func| func.name.to_string()).collect();
+        format!("no function {}, choose one of {}", name, names.join(", "))
+    })
+}
+
+/// Instantiate the WebAssembly module in `data`, and call its export `invoke`.
+pub fn run_wasm(
+    data: &[u8],
+    invoke: Option<&str>,
+    args: &[String],
+) -> Result<Vec<DataValue>, String> {
+    let mut runtime = Runtime::with_printing()?;
+    runtime.instantiate(data, None).map_err(|e| e.to_string())?;
+    let exports = runtime.exported_functions();
+    let field = match invoke {
+        Some(name) => name,
+        None if exports.iter().any(|name| name == MAIN) => MAIN,
+        None if args.is_empty() && runtime.has_start() => return Ok(Vec::new()),
+        None => {
+            return Err(format!(
+                "no {} export, choose one of {}",
+                MAIN,
+                exports.join(", ")
+            ))
+        }
+    };
+
+    let sig = runtime.export_signature(field)?;
+    let param_types: Vec<Type> = sig.params.iter().map(|p| p.value_type).collect();
+    let args = arguments(args, &param_types)?
+        .into_iter()
+        .map(|arg| match arg {
+            DataValue::Int(ty, x) if ty == types::I32 => Value::I32(x as u32),
+            DataValue::Int(_, x) => Value::I64(x),
+            DataValue::F32(x) => Value::F32(x.to_bits()),
+            DataValue::F64(x) => Value::F64(x.to_bits()),
+            DataValue::Bool(..) => panic!("WebAssembly has no booleans"),
+        })
+        .collect();
+    let action = Action::Invoke {
+        module: None,
+        field: field.to_string(),
+        args,
+    };
+    let results = runtime.perform(&action).map_err(|e| e.to_string())?;
+    Ok(
+        results
+            .into_iter()
+            .map(|result| match result {
+                Value::I32(x) => DataValue::int(types::I32, i64::from(x as i32)),
+                Value::I64(x) => DataValue::int(types::I64, x as i64),
+                Value::F32(x) => DataValue::F32(f32::from_bits(x)),
+                Value::F64(x) => DataValue::F64(f64::from_bits(x)),
+            })
+            .collect(),
+    )
+}
+
+/// Parse the command line arguments `args` of a function with parameters of `types`.
+///
+/// Floating point numbers may be written in decimal as well as in the hexadecimal notation of the
+/// IL.
+fn arguments(args: &[String], types: &[Type]) -> Result<Vec<DataValue>, String> {
+    if args.len() != types.len() {
+        return Err(format!(
+            "expected {} arguments, got {}",
+            types.len(),
+            args.len()
+        ));
+    }
+    args.iter()
+        .zip(types)
+        .map(|(arg, &ty)| if ty == types::F32 {
+            arg.parse().map(DataValue::F32).or_else(
+                |_| parse_constant(arg, ty),
+            )
+        } else if ty == types::F64 {
+            arg.parse().map(DataValue::F64).or_else(
+                |_| parse_constant(arg, ty),
+            )
+        } else {
+            parse_constant(arg, ty)
+        })
+        .collect()
+}
+
+/// Format a result with its type.
+fn show(value: DataValue) -> String {
+    match value {
+        DataValue::F32(x) => format!("{} : f32", x),
+        DataValue::F64(x) => format!("{} : f64", x),
+        _ => format!("{} : {}", value, value.ty()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_arguments() {
+        let args: Vec<String> = ["-1", "0x10", "1.5", "0x1.8p1", "true"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let types = [types::I32, types::I64, types::F32, types::F64, types::B1];
+        assert_eq!(
+            arguments(&args, &types).unwrap(),
+            [
+                DataValue::int(types::I32, -1),
+                DataValue::int(types::I64, 16),
+                DataValue::F32(1.5),
+                DataValue::F64(3.0),
+                DataValue::bool(true),
+            ]
+        );
+        assert!(arguments(&args[0..1], &types).is_err());
+        assert_eq!(show(DataValue::int(types::I8, -2)), "-2 : i8");
+        assert_eq!(show(DataValue::F64(0.5)), "0.5 : f64");
+    }
+
+    #[test]
+    fn cton() {
+        let text = "function %square(i32) -> i32 {
+                    ebb0(v0: i32):
+                        v1 = imul v0, v0
+                        return v1
+                    }";
+        assert_eq!(
+            run_cton(text, None, &["-7".to_string()]).unwrap(),
+            [DataValue::int(types::I32, 49)]
+        );
+        assert!(run_cton(text, Some("%cube"), &[]).is_err());
+    }
+
+    #[test]
+    fn interpret() {
+        let text = "function %main(i32) -> i32 {
+                        fn0 = function %double(i32) -> i32
+                    ebb0(v0: i32):
+                        v1 = call fn0(v0)
+                        v2 = iadd_imm v1, 1
+                        return v2
+                    }
+
+                    function %double(i32) -> i32 {
+                    ebb0(v0: i32):
+                        v1 = iadd v0, v0
+                        return v1
+                    }
+
+                    function %spin() {
+                    ebb0:
+                        jump ebb0
+                    }";
+        let args = ["20".to_string()];
+        assert_eq!(
+            interpret_cton(text, None, &args, None).unwrap(),
+            [DataValue::int(types::I32, 41)]
+        );
+        assert_eq!(
+            interpret_cton(text, Some("double"), &args, None).unwrap(),
+            [DataValue::int(types::I32, 40)]
+        );
+        assert_eq!(
+            interpret_cton(text, Some("%spin"), &[], Some(100)).unwrap_err(),
+            "out of fuel"
+        );
+    }
+}
@@ -0,0 +1,737 @@
+//! Random function generation for stress testing the code generator.
+//!
+//! The `cton-util generate` command generates random functions that pass the verifier, and
+//! compiles them for the target ISAs. The test files exercise the cases somebody thought of, and
+//! random functions reach the combinations of types, instructions, and control flow that nobody
+//! did. The code generator may reject a function by exceeding an implementation limit, but it must
+//! not panic, and the verifier must accept its output.
+//!
+//! The generated functions are also well-behaved programs, so they can be executed: they don't
+//! trap, their loops run a few iterations, and they only call the functions generated after them.
+
+use cretonne::cursor::{Cursor, FuncCursor};
+use cretonne::ir::condcodes::{FloatCC, IntCC};
+use cretonne::ir::immediates::{Ieee32, Ieee64, Imm64};
+use cretonne::ir::{types, AbiParam, CallConv, Ebb, ExtFuncData, ExternalName, FuncRef, Function,
+                   InstBuilder, JumpTableData, Opcode, Signature, Type, Value};
+use cretonne::isa::TargetIsa;
+use cretonne::result::CodegenError;
+use cretonne::{settings, verify_function, Context};
+use mutate::catch;
+use random::Rng;
+use std::cmp;
+use std::panic;
+use std::time;
+use TestResult;
+
+/// The types that can be generated.
+const TYPES: [Type; 7] = [
+    types::I8,
+    types::I16,
+    types::I32,
+    types::I64,
+    types::F32,
+    types::F64,
+    types::B1,
+];
+
+/// The number of functions generated together. Each of them may call the ones after it.
+const GROUP: usize = 8;
+
+/// Immediates and integer constants, before they are truncated to the width of their type.
+pub const INTEGERS: [i64; 9] = [0, 1, -1, 7, 31, 32, 63, 64, i64::min_value()];
+
+/// Floating point constants, including the special values.
+pub const FLOATS: [f64; 8] = [
+    0.0,
+    -0.0,
+    1.0,
+    -1.5,
+    0.1,
+    1e30,
+    ::std::f64::INFINITY,
+    ::std::f64::NAN,
+];
+
+const INT_BINARY: [Opcode; 11] = [
+    Opcode::Iadd,
+    Opcode::Isub,
+    Opcode::Imul,
+    Opcode::Band,
+    Opcode::Bor,
+    Opcode::Bxor,
+    Opcode::Ishl,
+    Opcode::Ushr,
+    Opcode::Sshr,
+    Opcode::Rotl,
+    Opcode::Rotr,
+];
+
+const INT_BINARY_IMM: [Opcode; 9] = [
+    Opcode::IaddImm,
+    Opcode::ImulImm,
+    Opcode::IrsubImm,
+    Opcode::BandImm,
+    Opcode::BorImm,
+    Opcode::BxorImm,
+    Opcode::IshlImm,
+    Opcode::UshrImm,
+    Opcode::SshrImm,
+];
+
+/// Intel can't encode `bnot` yet, so it isn't used.
+const INT_UNARY: [Opcode; 3] = [Opcode::Clz, Opcode::Ctz, Opcode::Popcnt];
+
+const FLOAT_BINARY: [Opcode; 7] = [
+    Opcode::Fadd,
+    Opcode::Fsub,
+    Opcode::Fmul,
+    Opcode::Fdiv,
+    Opcode::Fmin,
+    Opcode::Fmax,
+    Opcode::Fcopysign,
+];
+
+const FLOAT_UNARY: [Opcode; 7] = [
+    Opcode::Fneg,
+    Opcode::Fabs,
+    Opcode::Sqrt,
+    Opcode::Ceil,
+    Opcode::Floor,
+    Opcode::Trunc,
+    Opcode::Nearest,
+];
+
+const INT_CONDS: [IntCC; 10] = [
+    IntCC::Equal,
+    IntCC::NotEqual,
+    IntCC::SignedLessThan,
+    IntCC::SignedGreaterThanOrEqual,
+    IntCC::SignedGreaterThan,
+    IntCC::SignedLessThanOrEqual,
+    IntCC::UnsignedLessThan,
+    IntCC::UnsignedGreaterThanOrEqual,
+    IntCC::UnsignedGreaterThan,
+    IntCC::UnsignedLessThanOrEqual,
+];
+
+const FLOAT_CONDS: [FloatCC; 14] = [
+    FloatCC::Ordered,
+    FloatCC::Unordered,
+    FloatCC::Equal,
+    FloatCC::NotEqual,
+    FloatCC::OrderedNotEqual,
+    FloatCC::UnorderedOrEqual,
+    FloatCC::LessThan,
+    FloatCC::LessThanOrEqual,
+    FloatCC::GreaterThan,
+    FloatCC::GreaterThanOrEqual,
+    FloatCC::UnorderedOrLessThan,
+    FloatCC::UnorderedOrLessThanOrEqual,
+    FloatCC::UnorderedOrGreaterThan,
+    FloatCC::UnorderedOrGreaterThanOrEqual,
+];
+
+/// The shape of the generated functions.
+#[derive(Clone, Debug)]
+pub struct Shape {
+    /// The types of the parameters, results, and computed values. A type that is listed twice is
+    /// used twice as often.
+    pub types: Vec<Type>,
+    /// The maximum number of parameters of a function.
+    pub params: usize,
+    /// The maximum number of results of a function. Intel only returns two values of each
+    /// register class, in registers.
+    pub returns: usize,
+    /// The maximum number of statements in a sequence of code.
+    pub statements: usize,
+    /// The maximum nesting of conditionals, switches, and loops.
+    pub depth: usize,
+    /// The percentage of statements that are conditionals, switches, or loops.
+    pub branches: usize,
+    /// The percentage of statements that call another function.
+    pub calls: usize,
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Self {
+            types: vec![types::I32, types::I64, types::F32, types::F64, types::B1],
+            params: 4,
+            returns: 2,
+            statements: 8,
+            depth: 3,
+            branches: 15,
+            calls: 5,
+        }
+    }
+}
+
+impl Shape {
+    /// Set `types` from a comma separated list like `i32,i64,f64`.
+    pub fn set_types(&mut self, list: &str) -> Result<(), String> {
+        let mut types = Vec::new();
+        for name in list.split(',').map(str::trim) {
+            match TYPES.iter().find(|ty| ty.to_string() == name) {
+                Some(&ty) => types.push(ty),
+                None => return Err(format!("can't generate values of type '{}'", name)),
+            }
+        }
+        self.types = types;
+        Ok(())
+    }
+}
+
+/// Generate `count` random functions with `shape`, using the random sequence given by `seed`, and
+/// compile each of them for `isas`.
+pub fn run(
+    shape: &Shape,
+    isas: &[Box<TargetIsa>],
+    count: usize,
+    seed: u64,
+    verbose: bool,
+) -> TestResult {
+    let started = time::Instant::now();
+    let mut rng = Rng::new(seed);
+    let flags = settings::Flags::new(&settings::builder());
+    let mut generated = 0;
+    let mut failures = 0;
+
+    // The panics are reported with the functions that caused them.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    while generated < count {
+        let group = generate(shape, cmp::min(GROUP, count - generated), &mut rng);
+        generated += group.len();
+        for func in &group {
+            if verbose {
+                println!("{}", func.display(None));
+            }
+            let mut failed = Vec::new();
+            if let Err(e) = verify_function(func, &flags) {
+                failed.push(format!("generated an invalid function: {}", e));
+            }
+            if failed.is_empty() {
+                for isa in isas {
+                    let result = catch(|| Context::for_function(func.clone()).compile(&**isa));
+                    let msg = match result {
+                        Ok(Ok(_)) |
+                        Ok(Err(CodegenError::ImplLimitExceeded)) |
+                        Ok(Err(CodegenError::CodeTooLarge)) => continue,
+                        Ok(Err(e)) => e.to_string(),
+                        Err(msg) => format!("panicked: {}", msg),
+                    };
+                    failed.push(format!("compiling {} for {}: {}", func.name, isa.name(), msg));
+                }
+            }
+            if !failed.is_empty() {
+                failures += 1;
+                for msg in &failed {
+                    println!("{}", msg);
+                }
+                println!("{}", func.display(None));
+            }
+        }
+    }
+    panic::set_hook(hook);
+
+    println!(
+        "{} functions compiled for {} ISAs, {} failed (seed {})",
+        generated,
+        isas.len(),
+        failures,
+        seed
+    );
+    if failures == 0 {
+        Ok(started.elapsed())
+    } else {
+        Err(format!("{} functions failed to compile", failures))
+    }
+}
+
+/// Generate `count` random functions with `shape`, named `%f0`, `%f1`, and so on.
+///
+/// Each function may call the functions that follow it, so the last one doesn't call any.
+pub fn generate(shape: &Shape, count: usize, rng: &mut Rng) -> Vec<Function> {
+    let names: Vec<ExternalName> = (0..count)
+        .map(|i| ExternalName::testcase(format!("f{}", i)))
+        .collect();
+    let signatures: Vec<Signature> = (0..count).map(|_| signature(shape, rng)).collect();
+
+    let mut functions = Vec::with_capacity(count);
+    for i in 0..count {
+        let mut func = Function::with_name_signature(names[i].clone(), signatures[i].clone());
+        let callees = (i + 1..count)
+            .map(|callee| {
+                let signature = func.import_signature(signatures[callee].clone());
+                func.import_function(ExtFuncData {
+                    name: names[callee].clone(),
+                    signature,
+                })
+            })
+            .collect();
+        let entry = func.dfg.make_ebb();
+        let mut builder = Builder {
+            func,
+            shape,
+            rng,
+            ebb: entry,
+            values: Vec::new(),
+            callees,
+        };
+        builder.body(entry);
+        functions.push(builder.func);
+    }
+    functions
+}
+
+/// Generate a random signature.
+fn signature(shape: &Shape, rng: &mut Rng) -> Signature {
+    let mut sig = Signature::new(CallConv::Native);
+    for _ in 0..rng.below(shape.params + 1) {
+        sig.params.push(AbiParam::new(*rng.pick(&shape.types).unwrap()));
+    }
+    for _ in 0..rng.below(shape.returns + 1) {
+        sig.returns.push(AbiParam::new(*rng.pick(&shape.types).unwrap()));
+    }
+    sig
+}
+
+/// Builder of the body of a single function.
+///
+/// The code is generated from a random structured program, so it is valid SSA without any
+/// analysis: a value can be used in the rest of the sequence that defines it, including the nested
+/// sequences, and values flow out of conditionals and loops as EBB parameters.
+struct Builder<'a> {
+    func: Function,
+    shape: &'a Shape,
+    rng: &'a mut Rng,
+    /// The EBB being filled. It is always the last one in the layout.
+    ebb: Ebb,
+    /// The values that can be used at the end of `ebb`.
+    values: Vec<Value>,
+    /// The functions that can be called.
+    callees: Vec<FuncRef>,
+}
+
+impl<'a> Builder<'a> {
+    fn body(&mut self, entry: Ebb) {
+        let params: Vec<Type> = self.func
+            .signature
+            .params
+            .iter()
+            .map(|param| param.value_type)
+            .collect();
+        for ty in params {
+            self.func.dfg.append_ebb_param(entry, ty);
+        }
+        self.switch_to(entry);
+        self.sequence(0);
+        self.return_();
+    }
+
+    /// Get a cursor appending to the current EBB.
+    fn cursor(&mut self) -> FuncCursor {
+        FuncCursor::new(&mut self.func).at_bottom(self.ebb)
+    }
+
+    /// Start filling `ebb`, and make its parameters available.
+    fn switch_to(&mut self, ebb: Ebb) {
+        self.func.layout.append_ebb(ebb);
+        self.ebb = ebb;
+        self.values.extend_from_slice(self.func.dfg.ebb_params(ebb));
+    }
+
+    /// Create an EBB with a few random parameters.
+    fn join_ebb(&mut self) -> Ebb {
+        let ebb = self.func.dfg.make_ebb();
+        for _ in 0..self.rng.below(3) {
+            let ty = self.pick_type();
+            self.func.dfg.append_ebb_param(ebb, ty);
+        }
+        ebb
+    }
+
+    /// Pick random values of `types`.
+    fn operands(&mut self, types: &[Type]) -> Vec<Value> {
+        types.iter().map(|&ty| self.operand(ty)).collect()
+    }
+
+    fn param_types(&self, ebb: Ebb) -> Vec<Type> {
+        self.func
+            .dfg
+            .ebb_params(ebb)
+            .iter()
+            .map(|&param| self.func.dfg.value_type(param))
+            .collect()
+    }
+
+    /// Jump to `ebb` with random arguments.
+    fn jump(&mut self, ebb: Ebb) {
+        let types = self.param_types(ebb);
+        let args = self.operands(&types);
+        self.cursor().ins().jump(ebb, &args);
+    }
+
+    fn return_(&mut self) {
+        let types: Vec<Type> = self.func
+            .signature
+            .returns
+            .iter()
+            .map(|ret| ret.value_type)
+            .collect();
+        let args = self.operands(&types);
+        self.cursor().ins().return_(&args);
+    }
+
+    fn sequence(&mut self, depth: usize) {
+        for _ in 0..1 + self.rng.below(self.shape.statements) {
+            let roll = self.rng.below(100);
+            if roll < self.shape.branches && depth < self.shape.depth {
+                match self.rng.below(3) {
+                    0 => self.conditional(depth + 1),
+                    1 => self.switch(depth + 1),
+                    _ => self.repeat(depth + 1),
+                }
+            } else if roll < self.shape.branches + self.shape.calls && !self.callees.is_empty() {
+                self.call();
+            } else {
+                let ty = self.pick_type();
+                let value = self.compute(ty);
+                self.values.push(value);
+            }
+        }
+    }
+
+    /// Generate an if-then-else. The then branch may return early.
+    fn conditional(&mut self, depth: usize) {
+        let cond = self.operand(types::B1);
+        let else_ebb = self.func.dfg.make_ebb();
+        let join = self.join_ebb();
+        if self.rng.below(2) == 0 {
+            self.cursor().ins().brz(cond, else_ebb, &[]);
+        } else {
+            self.cursor().ins().brnz(cond, else_ebb, &[]);
+        }
+
+        let scope = self.values.len();
+        self.sequence(depth);
+        if self.rng.below(8) == 0 {
+            self.return_();
+        } else {
+            self.jump(join);
+        }
+        self.values.truncate(scope);
+
+        self.switch_to(else_ebb);
+        self.sequence(depth);
+        self.jump(join);
+        self.values.truncate(scope);
+
+        self.switch_to(join);
+    }
+
+    /// Generate a switch through a jump table, with the default case inline.
+    fn switch(&mut self, depth: usize) {
+        let ty = self.pick_type_where(Type::is_int);
+        let index = self.operand(ty);
+        let index = self.cursor().ins().band_imm(index, 3);
+        let cases: Vec<Ebb> = (0..1 + self.rng.below(3))
+            .map(|_| self.func.dfg.make_ebb())
+            .collect();
+        let mut table = JumpTableData::new();
+        for &case in &cases {
+            table.push_entry(case);
+        }
+        let table = self.func.create_jump_table(table);
+        let join = self.join_ebb();
+        self.cursor().ins().br_table(index, table);
+
+        let scope = self.values.len();
+        self.sequence(depth);
+        self.jump(join);
+        self.values.truncate(scope);
+        for case in cases {
+            self.switch_to(case);
+            self.sequence(depth);
+            self.jump(join);
+            self.values.truncate(scope);
+        }
+
+        self.switch_to(join);
+    }
+
+    /// Generate a loop that runs one to three times, and carries a few values around.
+    ///
+    /// The code following the loop is in the same EBB as the back edge, so the values computed in
+    /// the loop remain available.
+    fn repeat(&mut self, depth: usize) {
+        let trips = 1 + self.rng.below(3) as i64;
+        let count = self.cursor().ins().iconst(types::I32, trips);
+        let header = self.join_ebb();
+        let carried = self.param_types(header);
+        let counter = self.func.dfg.append_ebb_param(header, types::I32);
+        let mut args = self.operands(&carried);
+        args.push(count);
+        self.cursor().ins().jump(header, &args);
+        self.switch_to(header);
+        self.sequence(depth);
+
+        let next = self.cursor().ins().iadd_imm(counter, -1);
+        let mut args = self.operands(&carried);
+        args.push(next);
+        self.cursor().ins().brnz(next, header, &args);
+    }
+
+    fn call(&mut self) {
+        let callee = *self.rng.pick(&self.callees).unwrap();
+        let sig = self.func.dfg.ext_funcs[callee].signature;
+        let types: Vec<Type> = self.func.dfg.signatures[sig]
+            .params
+            .iter()
+            .map(|param| param.value_type)
+            .collect();
+        let args = self.operands(&types);
+        let inst = self.cursor().ins().call(callee, &args);
+        let results = self.func.dfg.inst_results(inst).to_vec();
+        self.values.extend(results);
+    }
+
+    fn pick_type(&mut self) -> Type {
+        *self.rng.pick(&self.shape.types).unwrap()
+    }
+
+    /// Pick one of the types satisfying `pred`, or `i32` if there are none.
+    fn pick_type_where(&mut self, pred: fn(Type) -> bool) -> Type {
+        let types: Vec<Type> = self.shape
+            .types
+            .iter()
+            .cloned()
+            .filter(|&ty| pred(ty))
+            .collect();
+        self.rng.pick(&types).cloned().unwrap_or(types::I32)
+    }
+
+    /// Pick an available value of type `ty`, or sometimes a new constant.
+    fn operand(&mut self, ty: Type) -> Value {
+        let candidates: Vec<Value> = self.values
+            .iter()
+            .cloned()
+            .filter(|&v| self.func.dfg.value_type(v) == ty)
+            .collect();
+        match self.rng.pick(&candidates) {
+            Some(&value) if self.rng.below(8) != 0 => value,
+            _ => self.constant(ty),
+        }
+    }
+
+    /// An immediate that fits in `ty`.
+    fn immediate(&mut self, ty: Type) -> Imm64 {
+        let x = if self.rng.below(2) == 0 {
+            INTEGERS[self.rng.below(INTEGERS.len())]
+        } else {
+            self.rng.next_u64() as i64
+        };
+        let unused = 64 - ty.bits();
+        Imm64::new((x << unused) >> unused)
+    }
+
+    fn constant(&mut self, ty: Type) -> Value {
+        if ty.is_int() {
+            let imm = self.immediate(ty);
+            self.cursor().ins().iconst(ty, imm)
+        } else if ty.is_bool() {
+            // Intel can't encode `bconst`, so compare an integer constant instead.
+            let x = self.constant(types::I32);
+            self.cursor().ins().icmp_imm(IntCC::NotEqual, x, 0)
+        } else {
+            let x = FLOATS[self.rng.below(FLOATS.len())];
+            if ty == types::F32 {
+                self.cursor().ins().f32const(Ieee32::with_float(x as f32))
+            } else {
+                self.cursor().ins().f64const(Ieee64::with_float(x))
+            }
+        }
+    }
+
+    /// Compute a new value of type `ty` with a random instruction.
+    fn compute(&mut self, ty: Type) -> Value {
+        if ty.is_int() {
+            self.compute_int(ty)
+        } else if ty.is_bool() {
+            self.compute_bool(ty)
+        } else {
+            self.compute_float(ty)
+        }
+    }
+
+    fn compute_int(&mut self, ty: Type) -> Value {
+        match self.rng.below(8) {
+            0 => self.constant(ty),
+            1 | 2 => {
+                let opcode = *self.rng.pick(&INT_BINARY).unwrap();
+                self.binary(opcode, ty)
+            }
+            3 => {
+                let opcode = *self.rng.pick(&INT_BINARY_IMM).unwrap();
+                let x = self.operand(ty);
+                let imm = self.immediate(ty);
+                let mut pos = self.cursor();
+                let (inst, dfg) = pos.ins().BinaryImm(opcode, ty, imm, x);
+                dfg.first_result(inst)
+            }
+            4 => {
+                let opcode = *self.rng.pick(&INT_UNARY).unwrap();
+                self.unary(opcode, ty)
+            }
+            5 => {
+                // Divide by an odd number, so the division doesn't trap.
+                let x = self.operand(ty);
+                let y = self.operand(ty);
+                let y = self.cursor().ins().bor_imm(y, 1);
+                if self.rng.below(2) == 0 {
+                    self.cursor().ins().udiv(x, y)
+                } else {
+                    self.cursor().ins().urem(x, y)
+                }
+            }
+            6 => self.select(ty),
+            _ => {
+                let from = self.pick_type();
+                let x = self.operand(from);
+                if from.is_bool() {
+                    self.cursor().ins().bint(ty, x)
+                } else if from.is_float() && from.bits() == ty.bits() {
+                    self.cursor().ins().bitcast(ty, x)
+                } else if from.is_int() && from.bits() > ty.bits() {
+                    self.cursor().ins().ireduce(ty, x)
+                } else if from.is_int() && from.bits() < ty.bits() {
+                    if self.rng.below(2) == 0 {
+                        self.cursor().ins().uextend(ty, x)
+                    } else {
+                        self.cursor().ins().sextend(ty, x)
+                    }
+                } else {
+                    // Floats can't be converted to integers without the risk of a trap.
+                    self.binary(Opcode::Iadd, ty)
+                }
+            }
+        }
+    }
+
+    fn compute_float(&mut self, ty: Type) -> Value {
+        match self.rng.below(6) {
+            0 => self.constant(ty),
+            1 | 2 => {
+                let opcode = *self.rng.pick(&FLOAT_BINARY).unwrap();
+                self.binary(opcode, ty)
+            }
+            3 => {
+                let opcode = *self.rng.pick(&FLOAT_UNARY).unwrap();
+                self.unary(opcode, ty)
+            }
+            4 => self.select(ty),
+            _ => {
+                let from = self.pick_type();
+                let x = self.operand(from);
+                if from.is_int() && from.bits() == ty.bits() && self.rng.below(2) == 0 {
+                    self.cursor().ins().bitcast(ty, x)
+                } else if from.is_int() && from.bits() >= 32 {
+                    if self.rng.below(2) == 0 {
+                        self.cursor().ins().fcvt_from_sint(ty, x)
+                    } else {
+                        self.cursor().ins().fcvt_from_uint(ty, x)
+                    }
+                } else if from.is_float() && from.bits() < ty.bits() {
+                    self.cursor().ins().fpromote(ty, x)
+                } else if from.is_float() && from.bits() > ty.bits() {
+                    self.cursor().ins().fdemote(ty, x)
+                } else {
+                    self.binary(Opcode::Fadd, ty)
+                }
+            }
+        }
+    }
+
+    fn compute_bool(&mut self, ty: Type) -> Value {
+        match self.rng.below(4) {
+            0 => self.constant(ty),
+            1 => {
+                let cond = *self.rng.pick(&INT_CONDS).unwrap();
+                let arg_ty = self.pick_type_where(Type::is_int);
+                let x = self.operand(arg_ty);
+                if self.rng.below(2) == 0 {
+                    let y = self.operand(arg_ty);
+                    self.cursor().ins().icmp(cond, x, y)
+                } else {
+                    let imm = self.immediate(arg_ty);
+                    self.cursor().ins().icmp_imm(cond, x, imm)
+                }
+            }
+            2 => {
+                let cond = *self.rng.pick(&FLOAT_CONDS).unwrap();
+                let arg_ty = self.pick_type_where(Type::is_float);
+                let arg_ty = if arg_ty.is_float() { arg_ty } else { types::F64 };
+                let x = self.operand(arg_ty);
+                let y = self.operand(arg_ty);
+                self.cursor().ins().fcmp(cond, x, y)
+            }
+            _ => {
+                let opcode = *self.rng.pick(&[Opcode::Band, Opcode::Bor, Opcode::Bxor]).unwrap();
+                self.binary(opcode, ty)
+            }
+        }
+    }
+
+    fn unary(&mut self, opcode: Opcode, ty: Type) -> Value {
+        let x = self.operand(ty);
+        let mut pos = self.cursor();
+        let (inst, dfg) = pos.ins().Unary(opcode, ty, x);
+        dfg.first_result(inst)
+    }
+
+    fn binary(&mut self, opcode: Opcode, ty: Type) -> Value {
+        let x = self.operand(ty);
+        let y = self.operand(ty);
+        let mut pos = self.cursor();
+        let (inst, dfg) = pos.ins().Binary(opcode, ty, x, y);
+        dfg.first_result(inst)
+    }
+
+    fn select(&mut self, ty: Type) -> Value {
+        let cond = self.operand(types::B1);
+        let x = self.operand(ty);
+        let y = self.operand(ty);
+        self.cursor().ins().select(cond, x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_functions_verify() {
+        let flags = settings::Flags::new(&settings::builder());
+        let mut shape = Shape::default();
+        shape.set_types("i8,i16,i32,i64,f32,f64,b1").unwrap();
+        for seed in 0..20 {
+            for func in generate(&shape, GROUP, &mut Rng::new(seed)) {
+                if let Err(e) = verify_function(&func, &flags) {
+                    panic!("{}\n{}", e, func.display(None));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn types() {
+        let mut shape = Shape::default();
+        shape.set_types("i32, f64,i32").unwrap();
+        assert_eq!(shape.types, [types::I32, types::F64, types::I32]);
+        assert_eq!(
+            shape.set_types("i32,i128"),
+            Err("can't generate values of type 'i128'".to_string())
+        );
+    }
+}
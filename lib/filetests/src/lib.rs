@@ -3,49 +3,181 @@
 //! This crate contains the main test driver as well as implementations of the
 //! available filetest commands.
 
-#[macro_use(dbg)]
+extern crate atty;
+extern crate capstone;
 extern crate cretonne;
+extern crate cton_interpreter;
 extern crate cton_reader;
+extern crate cton_simplejit;
+extern crate cton_wasm;
+extern crate file_per_thread_logger;
 extern crate filecheck;
+extern crate libc;
+#[macro_use]
+extern crate log;
 extern crate num_cpus;
+extern crate regex;
+extern crate tempdir;
+extern crate term;
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::path::Path;
 use std::time;
 use cton_reader::TestCommand;
-use runner::TestRunner;
+use cache::ResultCache;
+use cretonne::isa::TargetIsa;
+use runner::{random_seed, TestRunner};
 
+pub use generate::Shape;
+pub use test_wasm::wat2wasm;
+
+/// The prefix of the names of the files the compiler traces are written to, one file per thread.
+///
+/// Tracing is enabled with the `RUST_LOG` environment variable, whose directives select the log
+/// level of each module: `RUST_LOG=cretonne::regalloc=trace` traces the register allocator.
+pub const LOG_FILENAME_PREFIX: &str = "cretonne.dbg.";
+
+mod bless;
+mod cache;
+mod captures;
 mod concurrent;
+mod console;
+mod differential;
+mod execute;
 mod runner;
 mod runone;
+mod watch;
 mod subtest;
 mod match_directive;
+mod generate;
+mod mutate;
+mod random;
+mod report;
+mod run_directive;
 
 mod test_binemit;
 mod test_cat;
 mod test_compile;
+mod test_disasm;
 mod test_domtree;
+mod test_interpret;
 mod test_legalizer;
 mod test_licm;
 mod test_preopt;
 mod test_print_cfg;
 mod test_regalloc;
+mod test_run;
 mod test_simple_gvn;
 mod test_verifier;
+mod test_wasm;
+mod test_wast;
+mod wast;
+mod wast_runtime;
 
 /// The result of running the test in a file.
 type TestResult = Result<time::Duration, String>;
 
+/// Options for running the file tests.
+#[derive(Clone, Default)]
+pub struct Options {
+    /// Print the duration of each test, not just the failures.
+    pub verbose: bool,
+    /// The number of worker threads, or one per CPU if `None`. With a single job, the tests are
+    /// run in order on the current thread instead.
+    pub jobs: Option<usize>,
+    /// Only run the test files whose path matches this pattern. See `TestRunner::set_filter` for
+    /// the syntax.
+    pub filter: Option<String>,
+    /// Only run the test files that use this test command, like `legalizer` or `binemit`.
+    pub command: Option<String>,
+    /// Fail tests that run for more than this many seconds, 10 by default, unless their header
+    /// has a `timeout:` directive.
+    pub timeout: Option<usize>,
+    /// Also write the results to this file as JSON, or as JUnit XML if the file name ends in
+    /// `.xml`.
+    pub report: Option<String>,
+    /// Also write the duration of each test to this CSV file.
+    pub timings: Option<String>,
+    /// Report the tests that run for more than this many seconds as slow, instead of the ones
+    /// that take much longer than the rest.
+    pub slow_threshold: Option<f64>,
+    /// Rewrite the filecheck directives of failing tests to match their actual output.
+    pub bless: bool,
+    /// Run the tests in a random order, to catch tests that depend on each other.
+    pub shuffle: bool,
+    /// The seed for a random order, to reproduce an earlier run. This implies `shuffle`.
+    pub seed: Option<u64>,
+    /// Run all the tests, instead of skipping the ones that passed in an earlier run of the same
+    /// executable with the same test command and timeout, and haven't changed since.
+    pub no_cache: bool,
+    /// Stop running tests after the first failure.
+    pub fail_fast: bool,
+    /// Only run the tests that failed in the last run, or weren't run because of `fail_fast`.
+    pub failed: bool,
+}
+
+impl Options {
+    /// Hash the options that can change the outcome of a test, so results cached with other
+    /// options aren't reused.
+    ///
+    /// The filter and the number of jobs only select and schedule whole test files, so they don't
+    /// change the cached results.
+    fn cache_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.command.hash(&mut hasher);
+        self.timeout.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
 /// Main entry point for `cton-util test`.
 ///
 /// Take a list of filenames which can be either `.cton` files or directories.
 ///
 /// Files are interpreted as test cases and executed immediately.
 ///
-/// Directories are scanned recursively for test cases ending in `.cton`. These test cases are
-/// executed on background threads.
+/// Directories are scanned recursively for test cases ending in `.cton`, `.wat`, or `.wasm`.
+/// These test cases are executed concurrently as directed by `options`.
 ///
-pub fn run(verbose: bool, files: Vec<String>) -> TestResult {
-    let mut runner = TestRunner::new(verbose);
+pub fn run(options: &Options, files: Vec<String>) -> TestResult {
+    let mut runner = TestRunner::new(options.verbose);
+    if let Some(ref pattern) = options.filter {
+        runner.set_filter(pattern.clone());
+    }
+    if let Some(ref command) = options.command {
+        runner.set_command(command.clone());
+    }
+    if let Some(secs) = options.timeout {
+        runner.set_timeout(secs);
+    }
+    if let Some(ref path) = options.report {
+        runner.set_report(path.into());
+    }
+    if let Some(ref path) = options.timings {
+        runner.set_timings(path.into());
+    }
+    if let Some(secs) = options.slow_threshold {
+        runner.set_slow_threshold(time::Duration::from_millis((secs * 1000.0) as u64));
+    }
+    if options.bless {
+        runner.set_bless();
+    }
+    if options.shuffle || options.seed.is_some() {
+        runner.set_shuffle(options.seed.unwrap_or_else(random_seed));
+    }
+    if options.fail_fast {
+        runner.set_fail_fast();
+    }
+    if options.failed {
+        // Without a list of failures, there is nothing to rerun.
+        runner.set_failed(cache::load_failed().unwrap_or_default());
+    }
+    if !options.no_cache {
+        if let Some(cache) = ResultCache::load(options.cache_key()) {
+            runner.set_cache(cache);
+        }
+    }
 
     for path in files.iter().map(Path::new) {
         if path.is_file() {
@@ -55,10 +187,108 @@ pub fn run(verbose: bool, files: Vec<String>) -> TestResult {
         }
     }
 
-    runner.start_threads();
+    let jobs = options.jobs.unwrap_or_else(num_cpus::get);
+    if jobs > 1 {
+        runner.start_threads(jobs);
+    }
     runner.run()
 }
 
+/// Entry point for `cton-util test --watch`.
+///
+/// Run the tests like `run`, and then keep watching the test files and directories for changes.
+/// Test files are run again as soon as they are modified or added. When the program itself is
+/// rebuilt, it restarts to run the whole suite with the new code.
+///
+/// Reports, timings, blessing, and rerunning the failed tests are not supported in watch mode, so
+/// those options are ignored.
+///
+/// This function only returns if the restart fails.
+pub fn watch(options: &Options, files: Vec<String>) -> TestResult {
+    let options = Options {
+        report: None,
+        timings: None,
+        bless: false,
+        failed: false,
+        ..options.clone()
+    };
+    watch::watch(&files, |paths| run(&options, paths))
+}
+
+/// Entry point for `cton-util mutate`.
+///
+/// Mutate the functions in the `.cton` files and directories `files`, and check that the verifier
+/// and the passes don't panic on the mutants. See the `mutate` module for details.
+///
+/// The random mutations are determined by `seed`, or by a random seed if it is `None`. The seed is
+/// printed, so a failing run can be reproduced.
+pub fn mutate(files: Vec<String>, mutants: usize, seed: Option<u64>, verbose: bool) -> TestResult {
+    mutate::run(&files, mutants, seed.unwrap_or_else(random_seed), verbose)
+}
+
+/// Entry point for `cton-util generate`.
+///
+/// Generate `count` random functions with `shape`, and check that they compile for each of `isas`
+/// without panicking. See the `generate` module for details.
+///
+/// The functions are determined by `seed`, or by a random seed if it is `None`. The seed is
+/// printed, so a failing run can be reproduced.
+pub fn generate(
+    shape: &Shape,
+    isas: &[Box<TargetIsa>],
+    count: usize,
+    seed: Option<u64>,
+    verbose: bool,
+) -> TestResult {
+    generate::run(shape, isas, count, seed.unwrap_or_else(random_seed), verbose)
+}
+
+/// Entry point for `cton-util differential`.
+///
+/// Generate `count` random functions with `shape`, compile them for the host machine, and check
+/// that they compute the same results as the interpreter for `inputs` random arguments each. See
+/// the `differential` module for details.
+///
+/// The functions and arguments are determined by `seed`, or by a random seed if it is `None`.
+pub fn differential(
+    shape: &Shape,
+    count: usize,
+    inputs: usize,
+    seed: Option<u64>,
+    verbose: bool,
+) -> TestResult {
+    differential::run(shape, count, inputs, seed.unwrap_or_else(random_seed), verbose)
+}
+
+/// Entry point for `cton-util run` on Cretonne IL.
+///
+/// Compile the `.cton` functions in `text` for the host machine, call the function `invoke` with
+/// the arguments `args`, and print the results. See the `execute` module for details.
+pub fn execute_cton(text: &str, invoke: Option<&str>, args: &[String]) -> Result<(), String> {
+    execute::run_cton(text, invoke, args).map(execute::print_results)
+}
+
+/// Entry point for `cton-util run` on a WebAssembly module.
+///
+/// Instantiate the binary module `data`, call its export `invoke` with the arguments `args`, and
+/// print the results. See the `execute` module for details.
+pub fn execute_wasm(data: &[u8], invoke: Option<&str>, args: &[String]) -> Result<(), String> {
+    execute::run_wasm(data, invoke, args).map(execute::print_results)
+}
+
+/// Entry point for `cton-util interpret`.
+///
+/// Evaluate the function `invoke` of the `.cton` functions in `text` with the IL interpreter on
+/// the arguments `args`, executing at most `fuel` instructions, and print the results.
+pub fn interpret(
+    text: &str,
+    invoke: Option<&str>,
+    args: &[String],
+    fuel: Option<u64>,
+) -> Result<(), String> {
+    execute::interpret_cton(text, invoke, args, fuel).map(execute::print_results)
+}
+
 /// Create a new subcommand trait object to match `parsed.command`.
 ///
 /// This function knows how to create all of the possible `test <foo>` commands that can appear in
@@ -68,14 +298,19 @@ fn new_subtest(parsed: &TestCommand) -> subtest::Result<Box<subtest::SubTest>> {
         "binemit" => test_binemit::subtest(parsed),
         "cat" => test_cat::subtest(parsed),
         "compile" => test_compile::subtest(parsed),
+        "disasm" => test_disasm::subtest(parsed),
         "domtree" => test_domtree::subtest(parsed),
+        "interpret" => test_interpret::subtest(parsed),
         "legalizer" => test_legalizer::subtest(parsed),
         "licm" => test_licm::subtest(parsed),
         "preopt" => test_preopt::subtest(parsed),
         "print-cfg" => test_print_cfg::subtest(parsed),
         "regalloc" => test_regalloc::subtest(parsed),
+        "run" => test_run::subtest(parsed),
         "simple-gvn" => test_simple_gvn::subtest(parsed),
         "verifier" => test_verifier::subtest(parsed),
+        "wasm" => test_wasm::subtest(parsed),
+        "wast" => test_wast::subtest(parsed),
         _ => Err(format!("unknown test command '{}'", parsed.command)),
     }
 }
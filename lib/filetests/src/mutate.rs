@@ -0,0 +1,328 @@
+//! Mutation fuzzing of the verifier and the passes.
+//!
+//! The `cton-util mutate` command takes the functions in a corpus of test files that pass the
+//! verifier, applies a few small random mutations to each of them, and verifies the result again.
+//! Mutants that the verifier rejects are discarded. The ones it accepts are compiled, and the
+//! passes must handle them gracefully: they may report an error, but they must not panic. A panic
+//! means that a pass assumes more about its input than the verifier guarantees.
+//!
+//! The verifier itself must not panic on any mutant either.
+
+use cretonne::Context;
+use cretonne::ir::immediates::Imm64;
+use cretonne::ir::{Ebb, Function, Inst, InstructionData, Value};
+use cretonne::result::CodegenResult;
+use cretonne::settings::FlagsOrIsa;
+use cretonne::verify_function;
+use cton_reader::{parse_test, IsaSpec};
+use random::Rng;
+use std::any::Any;
+use std::fs;
+use std::io::{self, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time;
+use TestResult;
+
+/// Immediates that tend to expose unchecked assumptions: zero divisors, out of range shift
+/// amounts, and overflowing extremes.
+const IMMEDIATES: [i64; 7] = [0, 1, -1, 63, 64, i64::min_value(), i64::max_value()];
+
+/// The maximum number of mutations applied to a single mutant.
+const MAX_MUTATIONS: usize = 3;
+
+/// Mutate each valid function in the test files `paths` `mutants` times, using the random
+/// sequence given by `seed`.
+///
+/// Directories are scanned recursively for `.cton` files. Files that don't parse are skipped,
+/// and so are functions that fail to verify or that make a pass panic before being mutated.
+pub fn run(paths: &[String], mutants: usize, seed: u64, verbose: bool) -> TestResult {
+    let started = time::Instant::now();
+    let mut files = Vec::new();
+    for path in paths {
+        collect_files(Path::new(path), &mut files).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    files.sort();
+
+    let mut mutator = Mutator {
+        rng: Rng::new(seed),
+        mutants,
+        verbose,
+        functions: 0,
+        tried: 0,
+        accepted: 0,
+        failures: 0,
+    };
+
+    // The panics are reported with the mutants that caused them.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result: Result<(), String> = files
+        .iter()
+        .map(|file| mutator.mutate_file(file))
+        .collect();
+    panic::set_hook(hook);
+    result?;
+
+    println!(
+        "{} mutants of {} functions, {} accepted by the verifier, {} failed (seed {})",
+        mutator.tried,
+        mutator.functions,
+        mutator.accepted,
+        mutator.failures,
+        seed
+    );
+    if mutator.failures == 0 {
+        Ok(started.elapsed())
+    } else {
+        Err(format!(
+            "{} mutants made the verifier or a pass panic",
+            mutator.failures
+        ))
+    }
+}
+
+/// Add the `.cton` files in `path` to `files`.
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_files(&entry?.path(), files)?;
+        }
+    } else if path.extension().map_or(false, |ext| ext == "cton") {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+struct Mutator {
+    rng: Rng,
+    mutants: usize,
+    verbose: bool,
+    /// Number of functions that were mutated.
+    functions: usize,
+    /// Number of mutants that were verified.
+    tried: usize,
+    /// Number of mutants accepted by the verifier.
+    accepted: usize,
+    /// Number of mutants that caused a panic.
+    failures: usize,
+}
+
+impl Mutator {
+    fn mutate_file(&mut self, path: &Path) -> Result<(), String> {
+        let mut text = String::new();
+        fs::File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        // Some test files test the parser errors, so they aren't part of the corpus.
+        let testfile = match parse_test(&text) {
+            Ok(testfile) => testfile,
+            Err(_) => return Ok(()),
+        };
+        let fisa = match testfile.isa_spec {
+            IsaSpec::None(ref flags) => FlagsOrIsa::from(flags),
+            IsaSpec::Some(ref isas) => FlagsOrIsa::from(&*isas[0]),
+        };
+        for (func, _) in testfile.functions {
+            self.mutate_function(path, &func, fisa);
+        }
+        Ok(())
+    }
+
+    fn mutate_function(&mut self, path: &Path, func: &Function, fisa: FlagsOrIsa) {
+        // Only start from functions the passes can handle, so the panics are caused by the
+        // mutations.
+        if verify_function(func, fisa).is_err() || catch(|| compile(func, fisa)).is_err() {
+            if self.verbose {
+                println!("{}: skipping {}", path.display(), func.name);
+            }
+            return;
+        }
+        self.functions += 1;
+
+        for _ in 0..self.mutants {
+            let mut mutant = func.clone();
+            let mut changes = Vec::new();
+            for _ in 0..1 + self.rng.below(MAX_MUTATIONS) {
+                if let Some(change) = mutate(&mut mutant, &mut self.rng) {
+                    changes.push(change);
+                }
+            }
+            if changes.is_empty() {
+                continue;
+            }
+
+            self.tried += 1;
+            let failure = match catch(|| verify_function(&mutant, fisa)) {
+                Err(msg) => Some(("the verifier", msg)),
+                Ok(Err(_)) => None,
+                Ok(Ok(())) => {
+                    self.accepted += 1;
+                    catch(|| compile(&mutant, fisa)).err().map(
+                        |msg| ("compilation", msg),
+                    )
+                }
+            };
+            if let Some((stage, msg)) = failure {
+                self.failures += 1;
+                println!(
+                    "{}: {} panicked on a mutant of {}: {}",
+                    path.display(),
+                    stage,
+                    func.name,
+                    msg
+                );
+                for change in &changes {
+                    println!("    {}", change);
+                }
+                println!("{}", mutant.display(fisa.isa));
+            }
+        }
+    }
+}
+
+/// Run `func` through the passes: the whole compilation pipeline when there is an ISA, or the
+/// ISA-independent optimizations otherwise.
+///
+/// Errors are a graceful way for the passes to reject a function, so they are not failures.
+fn compile(func: &Function, fisa: FlagsOrIsa) -> CodegenResult {
+    let mut ctx = Context::for_function(func.clone());
+    match fisa.isa {
+        Some(isa) => ctx.compile(isa).map(|_| ()),
+        None => {
+            ctx.flowgraph();
+            ctx.simple_gvn(fisa)?;
+            ctx.compute_loop_analysis();
+            ctx.licm(fisa)?;
+            // LICM may have inserted loop pre-headers.
+            ctx.flowgraph();
+            ctx.eliminate_unreachable_code(fisa)
+        }
+    }
+}
+
+/// Call `f`, and return the message of the panic if it panics.
+pub fn catch<F: FnOnce() -> R, R>(f: F) -> Result<R, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|e| panic_message(&*e))
+}
+
+/// Get the message of a panic. Panics are usually strings.
+fn panic_message(payload: &(Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else if let Some(msg) = payload.downcast_ref::<&'static str>() {
+        msg.to_string()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Apply a random mutation to a random instruction in `func`, and describe it.
+///
+/// Returns `None` without changing `func` if the chosen mutation doesn't apply to the chosen
+/// instruction.
+fn mutate(func: &mut Function, rng: &mut Rng) -> Option<String> {
+    let insts: Vec<Inst> = func.layout
+        .ebbs()
+        .flat_map(|ebb| func.layout.ebb_insts(ebb))
+        .collect();
+    let inst = *rng.pick(&insts)?;
+    match rng.below(6) {
+        0 => {
+            let num_args = func.dfg.inst_args(inst).len();
+            if num_args == 0 {
+                return None;
+            }
+            let arg = rng.below(num_args);
+            let value = *rng.pick(&layout_values(func))?;
+            func.dfg.inst_args_mut(inst)[arg] = value;
+            Some(format!("replaced argument {} of {} with {}", arg, inst, value))
+        }
+        1 => {
+            let next = func.layout.next_inst(inst)?;
+            func.layout.remove_inst(next);
+            func.layout.insert_inst(next, inst);
+            Some(format!("swapped {} and {}", inst, next))
+        }
+        2 => {
+            func.layout.remove_inst(inst);
+            Some(format!("removed {}", inst))
+        }
+        3 => {
+            let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+            let ebb = *rng.pick(&ebbs)?;
+            *func.dfg[inst].branch_destination_mut()? = ebb;
+            Some(format!("retargeted {} to {}", inst, ebb))
+        }
+        4 => {
+            // This drops one of the values passed to a branch destination or a callee. Dropping a
+            // fixed operand would make an instruction that the IR can't represent.
+            if func.dfg.inst_variable_args(inst).is_empty() {
+                return None;
+            }
+            let mut args = func.dfg[inst].take_value_list()?;
+            let len = args.len(&func.dfg.value_lists);
+            args.remove(len - 1, &mut func.dfg.value_lists);
+            func.dfg[inst].put_value_list(args);
+            Some(format!("dropped the last argument of {}", inst))
+        }
+        _ => {
+            let value = IMMEDIATES[rng.below(IMMEDIATES.len())];
+            match func.dfg[inst] {
+                InstructionData::UnaryImm { ref mut imm, .. } |
+                InstructionData::BinaryImm { ref mut imm, .. } => *imm = Imm64::new(value),
+                _ => return None,
+            }
+            Some(format!("changed the immediate of {} to {}", inst, value))
+        }
+    }
+}
+
+/// Get the values defined in the layout of `func`: EBB parameters and instruction results.
+fn layout_values(func: &Function) -> Vec<Value> {
+    let mut values = Vec::new();
+    for ebb in func.layout.ebbs() {
+        values.extend_from_slice(func.dfg.ebb_params(ebb));
+        for inst in func.layout.ebb_insts(ebb) {
+            values.extend_from_slice(func.dfg.inst_results(inst));
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cretonne::settings;
+    use cton_reader::parse_functions;
+
+    #[test]
+    fn mutations() {
+        let func = parse_functions(
+            "function %f(i32) -> i32 {
+             ebb0(v0: i32):
+                 v1 = iconst.i32 3
+                 v2 = udiv v0, v1
+                 brz v2, ebb1(v0)
+                 return v2
+             ebb1(v3: i32):
+                 return v3
+             }",
+        ).unwrap()
+            .remove(0);
+        let flags = settings::Flags::new(&settings::builder());
+        let mut rng = Rng::new(1);
+        let mut mutated = 0;
+        for _ in 0..200 {
+            let mut mutant = func.clone();
+            if mutate(&mut mutant, &mut rng).is_some() {
+                mutated += 1;
+                // Whatever the verifier says, compiling what it accepts must not panic.
+                if verify_function(&mutant, &flags).is_ok() {
+                    let _ = compile(&mutant, (&flags).into());
+                }
+            }
+        }
+        assert!(mutated > 100);
+    }
+}
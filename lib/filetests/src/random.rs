@@ -0,0 +1,41 @@
+//! A small pseudo-random number generator.
+//!
+//! Shuffling the tests and mutating functions only need a reproducible sequence of numbers from a
+//! seed, not a good generator, so this is a xorshift64* generator instead of a dependency.
+
+/// A xorshift64* generator.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator producing the sequence determined by `seed`.
+    pub fn new(seed: u64) -> Self {
+        // Xorshift gets stuck at zero, so avoid that state.
+        let state = seed ^ 0x9e37_79b9_7f4a_7c15;
+        Self { state: if state == 0 { 1 } else { state } }
+    }
+
+    /// Get the next random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Get a random number in the range `0..n`, which must not be empty.
+    pub fn below(&mut self, n: usize) -> usize {
+        debug_assert!(n > 0, "empty range");
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Pick a random element of `items`, if there are any.
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            None
+        } else {
+            Some(&items[self.below(items.len())])
+        }
+    }
+}
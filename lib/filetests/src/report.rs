@@ -0,0 +1,296 @@
+//! Machine-readable test reports.
+//!
+//! Besides printing failures as they happen, the test runner can write the results of a whole run
+//! to a file for CI dashboards. Two formats are supported:
+//!
+//! - JSON: An object with the number of `tests`, `failures`, and `skipped` tests, the total
+//!   `duration` in seconds, and a `results` array. Each result is an object with the test `path`,
+//!   its `status`, its `duration` in seconds, and the `error` message of a failed test. The status
+//!   is one of `"pass"`, `"fail"`, `"skip"`, or `"xfail"` for an expected failure. The duration of
+//!   a failed test and the error of a passed test are `null`.
+//! - JUnit XML: A single `<testsuite>` with a `<testcase>` per test file. Failed tests have a
+//!   `<failure>` element with the error message. Skipped tests and expected failures have a
+//!   `<skipped>` element.
+//!
+//! The durations of the tests can also be written to a separate CSV file with a row for each test:
+//! its path, its status as in the JSON report, and its duration in seconds, which is empty for a
+//! failed test. Collecting these files across commits shows how the speed of the compiler changes.
+
+use std::fmt::{Result, Write};
+use std::path::Path;
+use std::time::Duration;
+use TestResult;
+use runone::Expect;
+
+/// The format of a test report.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReportFormat {
+    /// A JSON object.
+    Json,
+    /// A JUnit XML test suite.
+    JUnit,
+}
+
+impl ReportFormat {
+    /// Choose the format for a report file: JUnit for `.xml` files, JSON otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("xml") => ReportFormat::JUnit,
+            _ => ReportFormat::Json,
+        }
+    }
+}
+
+/// Write a report of `results` to `w`.
+///
+/// The results are the path, expected outcome, and result of each test file. `elapsed` is the
+/// duration of the whole run.
+pub fn write_report(
+    w: &mut Write,
+    format: ReportFormat,
+    results: &[(&Path, Expect, &TestResult)],
+    elapsed: Duration,
+) -> Result {
+    match format {
+        ReportFormat::Json => write_json(w, results, elapsed),
+        ReportFormat::JUnit => write_junit(w, results, elapsed),
+    }
+}
+
+fn write_json(
+    w: &mut Write,
+    results: &[(&Path, Expect, &TestResult)],
+    elapsed: Duration,
+) -> Result {
+    write!(
+        w,
+        concat!(
+            "{{\"tests\":{},\"failures\":{},\"skipped\":{},",
+            "\"duration\":{},\"results\":["
+        ),
+        results.len(),
+        count(results, "fail"),
+        count(results, "skip"),
+        seconds(elapsed)
+    )?;
+    for (idx, &(path, expect, result)) in results.iter().enumerate() {
+        if idx != 0 {
+            w.write_char(',')?;
+        }
+        w.write_str("\n{\"path\":")?;
+        write_json_string(w, &path.to_string_lossy())?;
+        write!(w, ",\"status\":\"{}\"", status(expect, result))?;
+        match *result {
+            Ok(dur) => write!(w, ",\"duration\":{},\"error\":null}}", seconds(dur))?,
+            Err(ref e) => {
+                w.write_str(",\"duration\":null,\"error\":")?;
+                write_json_string(w, e)?;
+                w.write_char('}')?;
+            }
+        }
+    }
+    w.write_str("\n]}\n")
+}
+
+fn write_junit(
+    w: &mut Write,
+    results: &[(&Path, Expect, &TestResult)],
+    elapsed: Duration,
+) -> Result {
+    w.write_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n")?;
+    writeln!(
+        w,
+        concat!(
+            "<testsuite name=\"filetests\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" ",
+            "time=\"{}\">"
+        ),
+        results.len(),
+        count(results, "fail"),
+        count(results, "skip") + count(results, "xfail"),
+        seconds(elapsed)
+    )?;
+    for &(path, expect, result) in results {
+        w.write_str("  <testcase classname=\"filetests\" name=\"")?;
+        write_xml_string(w, &path.to_string_lossy())?;
+        let status = status(expect, result);
+        match *result {
+            _ if status == "skip" => {
+                w.write_str("\" time=\"0\">\n    <skipped/>\n  </testcase>\n")?
+            }
+            Ok(dur) => writeln!(w, "\" time=\"{}\"/>", seconds(dur))?,
+            Err(ref e) => {
+                let element = if status == "xfail" { "skipped" } else { "failure" };
+                write!(w, "\" time=\"0\">\n    <{} message=\"", element)?;
+                write_xml_string(w, e.lines().next().unwrap_or(""))?;
+                w.write_str("\">")?;
+                write_xml_string(w, e)?;
+                writeln!(w, "</{}>\n  </testcase>", element)?;
+            }
+        }
+    }
+    w.write_str("</testsuite>\n")
+}
+
+/// Write the duration of each test in `results` to `w` as CSV.
+pub fn write_timings(w: &mut Write, results: &[(&Path, Expect, &TestResult)]) -> Result {
+    w.write_str("path,status,duration\n")?;
+    for &(path, expect, result) in results {
+        write_csv_field(w, &path.to_string_lossy())?;
+        write!(w, ",{},", status(expect, result))?;
+        if let Ok(dur) = *result {
+            w.write_str(&seconds(dur))?;
+        }
+        w.write_char('\n')?;
+    }
+    Ok(())
+}
+
+/// Get the status of a test as written in the report.
+fn status(expect: Expect, result: &TestResult) -> &'static str {
+    match (expect, result.is_ok()) {
+        (Expect::Skip, _) => "skip",
+        (_, true) => "pass",
+        (Expect::Fail, false) => "xfail",
+        (Expect::Pass, false) => "fail",
+    }
+}
+
+/// Count the tests with the given status.
+fn count(results: &[(&Path, Expect, &TestResult)], wanted: &str) -> usize {
+    results
+        .iter()
+        .filter(|&&(_, expect, result)| status(expect, result) == wanted)
+        .count()
+}
+
+/// Format a duration as seconds with millisecond precision.
+fn seconds(dur: Duration) -> String {
+    format!("{}.{:03}", dur.as_secs(), dur.subsec_nanos() / 1_000_000)
+}
+
+/// Write `s` as a JSON string, escaping the characters that need it.
+fn write_json_string(w: &mut Write, s: &str) -> Result {
+    w.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\t' => w.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char('"')
+}
+
+/// Write `s` as a CSV field, quoting it if needed.
+fn write_csv_field(w: &mut Write, s: &str) -> Result {
+    if !s.contains(&[',', '"', '\n'][..]) {
+        return w.write_str(s);
+    }
+    write!(w, "\"{}\"", s.replace('"', "\"\""))
+}
+
+/// Write `s` as XML character data or an attribute value.
+fn write_xml_string(w: &mut Write, s: &str) -> Result {
+    for c in s.chars() {
+        match c {
+            '<' => w.write_str("&lt;")?,
+            '>' => w.write_str("&gt;")?,
+            '&' => w.write_str("&amp;")?,
+            '"' => w.write_str("&quot;")?,
+            '\n' => w.write_str("&#10;")?,
+            // Other control characters are not allowed in XML 1.0 at all.
+            c if (c as u32) < 0x20 && c != '\t' => w.write_char('?')?,
+            c => w.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(format: Option<ReportFormat>) -> String {
+        let pass: TestResult = Ok(Duration::from_millis(1500));
+        let fail: TestResult = Err("run(%f): \"x\" < 1\nsecond line".to_string());
+        let skip: TestResult = Ok(Duration::default());
+        let xfail: TestResult = Err("bad".to_string());
+        let results = [
+            (Path::new("a/pass.cton"), Expect::Pass, &pass),
+            (Path::new("a/fail.cton"), Expect::Pass, &fail),
+            (Path::new("a/skip.cton"), Expect::Skip, &skip),
+            (Path::new("a/xfail,\"2\".cton"), Expect::Fail, &xfail),
+        ];
+        let mut s = String::new();
+        match format {
+            Some(format) => {
+                write_report(&mut s, format, &results, Duration::from_millis(2003)).unwrap()
+            }
+            None => write_timings(&mut s, &results).unwrap(),
+        }
+        s
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(ReportFormat::from_path(Path::new("out.json")), ReportFormat::Json);
+        assert_eq!(
+            report(Some(ReportFormat::Json)),
+            concat!(
+                "{\"tests\":4,\"failures\":1,\"skipped\":1,\"duration\":2.003,\"results\":[\n",
+                "{\"path\":\"a/pass.cton\",\"status\":\"pass\",\"duration\":1.500,",
+                "\"error\":null},\n",
+                "{\"path\":\"a/fail.cton\",\"status\":\"fail\",\"duration\":null,",
+                "\"error\":\"run(%f): \\\"x\\\" < 1\\nsecond line\"},\n",
+                "{\"path\":\"a/skip.cton\",\"status\":\"skip\",\"duration\":0.000,",
+                "\"error\":null},\n",
+                "{\"path\":\"a/xfail,\\\"2\\\".cton\",\"status\":\"xfail\",\"duration\":null,",
+                "\"error\":\"bad\"}\n",
+                "]}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn junit() {
+        assert_eq!(ReportFormat::from_path(Path::new("out.xml")), ReportFormat::JUnit);
+        assert_eq!(
+            report(Some(ReportFormat::JUnit)),
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<testsuite name=\"filetests\" tests=\"4\" failures=\"1\" skipped=\"2\" ",
+                "time=\"2.003\">\n",
+                "  <testcase classname=\"filetests\" name=\"a/pass.cton\" time=\"1.500\"/>\n",
+                "  <testcase classname=\"filetests\" name=\"a/fail.cton\" time=\"0\">\n",
+                "    <failure message=\"run(%f): &quot;x&quot; &lt; 1\">",
+                "run(%f): &quot;x&quot; &lt; 1&#10;second line</failure>\n",
+                "  </testcase>\n",
+                "  <testcase classname=\"filetests\" name=\"a/skip.cton\" time=\"0\">\n",
+                "    <skipped/>\n",
+                "  </testcase>\n",
+                "  <testcase classname=\"filetests\" name=\"a/xfail,&quot;2&quot;.cton\" ",
+                "time=\"0\">\n",
+                "    <skipped message=\"bad\">bad</skipped>\n",
+                "  </testcase>\n",
+                "</testsuite>\n"
+            )
+        );
+    }
+
+    #[test]
+    fn timings() {
+        assert_eq!(
+            report(None),
+            concat!(
+                "path,status,duration\n",
+                "a/pass.cton,pass,1.500\n",
+                "a/fail.cton,fail,\n",
+                "a/skip.cton,skip,0.000\n",
+                "\"a/xfail,\"\"2\"\".cton\",xfail,\n",
+            )
+        );
+    }
+}
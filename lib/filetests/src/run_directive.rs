@@ -0,0 +1,145 @@
+//! Parsing of `run:` directives.
+//!
+//! The `run` and `interpret` test commands call functions as directed by `run:` annotations:
+//!
+//! ```cton
+//!     ; run: %add(1, 2) == 3
+//!     ; run: %add(-1, 1) != 1
+//! ```
+//!
+//! The arguments are given as immediates of the parameter types. Without a comparison, the
+//! function must either return nothing or a single `b1`, which must be true.
+
+use cretonne::ir::immediates::{Ieee32, Ieee64, Imm64};
+use cretonne::ir::{types, AbiParam, ArgumentPurpose, Function, Type};
+use cton_interpreter::DataValue;
+use match_directive::match_directive;
+use std::str::FromStr;
+use subtest::{Context, Result};
+
+/// A call requested by a `run:` directive.
+pub struct Invocation {
+    /// The arguments to pass.
+    pub args: Vec<DataValue>,
+    /// The expected result, and whether it should compare equal.
+    pub expected: Option<(bool, DataValue)>,
+}
+
+impl Invocation {
+    /// Check the results of the call against the expectation.
+    ///
+    /// Floats compare like `fcmp`, so NaN is never equal to anything.
+    pub fn check(&self, results: &[DataValue]) -> bool {
+        match (results.first(), self.expected) {
+            (Some(result), Some((equal, expected))) => (*result == expected) == equal,
+            (Some(&DataValue::Bool(_, b)), None) => b,
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+}
+
+/// Parse all the `run:` directives for `func`, keeping the text of each.
+pub fn run_directives<'a>(
+    func: &Function,
+    context: &Context<'a>,
+) -> Result<Vec<(&'a str, Invocation)>> {
+    let mut invocations = Vec::new();
+    for comment in &context.details.comments {
+        if let Some(text) = match_directive(comment.text, "run:") {
+            invocations.push((text, parse_invocation(text, func)?));
+        }
+    }
+    if invocations.is_empty() {
+        return Err("no run: directives".to_string());
+    }
+    Ok(invocations)
+}
+
+/// Parse the text of a `run:` directive, like `%add(1, 2) == 3`.
+fn parse_invocation(text: &str, func: &Function) -> Result<Invocation> {
+    let (lpar, rpar) = match (text.find('('), text.rfind(')')) {
+        (Some(lpar), Some(rpar)) if lpar < rpar => (lpar, rpar),
+        _ => return Err(format!("run: {}: expected a call like %f(1, 2)", text)),
+    };
+    let name = text[0..lpar].trim();
+    if name != func.name.to_string() {
+        return Err(format!("run: {}: expected a call to {}", text, func.name));
+    }
+
+    let sig = &func.signature;
+    if let Some(param) = sig.params.iter().find(
+        |p| p.purpose != ArgumentPurpose::Normal,
+    )
+    {
+        return Err(format!("run: {}: can't pass a {} argument", text, param));
+    }
+    let args_text = text[lpar + 1..rpar].trim();
+    let args_text: Vec<&str> = if args_text.is_empty() {
+        Vec::new()
+    } else {
+        args_text.split(',').map(str::trim).collect()
+    };
+    if args_text.len() != sig.params.len() {
+        return Err(format!(
+            "run: {}: expected {} arguments",
+            text,
+            sig.params.len()
+        ));
+    }
+    let mut args = Vec::new();
+    for (arg, param) in args_text.iter().zip(&sig.params) {
+        args.push(parse_constant(arg, param.value_type).map_err(
+            |e| format!("run: {}: {}", text, e),
+        )?);
+    }
+
+    let comparison = text[rpar + 1..].trim();
+    let expected = if comparison.is_empty() {
+        if !sig.returns.is_empty() && sig.returns != [AbiParam::new(types::B1)] {
+            return Err(format!("run: {}: expected a comparison with the result", text));
+        }
+        None
+    } else {
+        let (equal, value) = if comparison.starts_with("==") {
+            (true, &comparison[2..])
+        } else if comparison.starts_with("!=") {
+            (false, &comparison[2..])
+        } else {
+            return Err(format!("run: {}: expected == or != after the call", text));
+        };
+        if sig.returns.len() != 1 {
+            return Err(format!("run: {}: can only compare a single result", text));
+        }
+        let value = parse_constant(value.trim(), sig.returns[0].value_type)
+            .map_err(|e| format!("run: {}: {}", text, e))?;
+        Some((equal, value))
+    };
+
+    Ok(Invocation { args, expected })
+}
+
+/// Parse a constant of type `ty`.
+pub fn parse_constant(text: &str, ty: Type) -> Result<DataValue> {
+    if ty.is_int() {
+        Imm64::from_str(text)
+            .map(|imm| DataValue::int(ty, imm.into()))
+            .map_err(|e| format!("{}: {}", text, e))
+    } else if ty == types::B1 {
+        match text {
+            "true" => Ok(DataValue::bool(true)),
+            "false" => Ok(DataValue::bool(false)),
+            _ => Err(format!("{}: expected true or false", text)),
+        }
+    } else if ty == types::F32 {
+        Ieee32::from_str(text)
+            .map(|x| DataValue::F32(f32::from_bits(x.bits())))
+            .map_err(|e| format!("{}: {}", text, e))
+    } else if ty == types::F64 {
+        Ieee64::from_str(text)
+            .map(|x| DataValue::F64(f64::from_bits(x.bits())))
+            .map_err(|e| format!("{}: {}", text, e))
+    } else {
+        Err(format!("{} values are not supported", ty))
+    }
+}
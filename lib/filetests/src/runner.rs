@@ -3,23 +3,57 @@
 //! This module implements the `TestRunner` struct which manages executing tests as well as
 //! scanning directories for tests.
 
+use std::collections::HashSet;
+use std::collections::hash_map::RandomState;
+use std::borrow::Cow;
+use std::cmp;
 use std::error::Error;
 use std::fmt::{self, Display};
 use std::ffi::OsStr;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::time;
 use {TestResult, runone};
+use cache::{self, ResultCache};
 use concurrent::{ConcurrentRunner, Reply};
+use console::{Console, Style};
+use random::Rng;
+use report::{write_report, write_timings, ReportFormat};
+use runone::Expect;
 
-// Timeout in seconds when we're not making progress.
-const TIMEOUT_PANIC: usize = 10;
+/// Default timeout in seconds for a single test.
+pub const DEFAULT_TIMEOUT: usize = 10;
 
-// Timeout for reporting slow tests without panicking.
+// Timeout for reporting slow tests without failing them.
 const TIMEOUT_SLOW: usize = 3;
 
+// Additional seconds without progress, beyond the longest timeout of the running tests, before we
+// decide that the worker threads are stalled and panic. This catches a deadlocked runner that
+// never gets around to timing out a test.
+const TIMEOUT_PANIC: usize = 10;
+
 struct QueueEntry {
     path: PathBuf,
     state: State,
+
+    // The ISA configuration to run, when the test file is split into a job per `isa` line.
+    config: Option<usize>,
+
+    // The expected outcome, known once the test has started.
+    expect: Expect,
+
+    // While the test is running: the worker thread running it, its timeout, and the number of
+    // ticks since it started.
+    thread_num: usize,
+    timeout: usize,
+    ticks: usize,
+
+    // The content hash of the test file when results are cached, and whether the result came
+    // from the cache.
+    hash: Option<u64>,
+    cached: bool,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -28,18 +62,47 @@ enum State {
     Queued,
     Running,
     Done(TestResult),
+    Cancelled,
 }
 
 impl QueueEntry {
+    pub fn new(path: PathBuf, config: Option<usize>) -> Self {
+        Self {
+            path,
+            state: State::New,
+            config,
+            expect: Expect::Pass,
+            thread_num: 0,
+            timeout: 0,
+            ticks: 0,
+            hash: None,
+            cached: false,
+        }
+    }
+
     pub fn path(&self) -> &Path {
         self.path.as_path()
     }
+
+    /// Get the name of the job: the path, followed by the ISA configuration number if the test
+    /// file is split.
+    pub fn name(&self) -> Cow<str> {
+        match self.config {
+            Some(config) => Cow::Owned(format!("{}[{}]", self.path.display(), config + 1)),
+            None => self.path.to_string_lossy(),
+        }
+    }
 }
 
 impl Display for QueueEntry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let p = self.path.to_string_lossy();
+        let p = self.name();
         match self.state {
+            State::Done(_) if self.expect == Expect::Skip => write!(f, "skip {}", p),
+            State::Done(Ok(_)) if self.cached => write!(f, "cached {}", p),
+            State::Done(Err(ref e)) if self.expect == Expect::Fail => {
+                write!(f, "xfail {}: {}", p, e)
+            }
             State::Done(Ok(dur)) => {
                 write!(
                     f,
@@ -58,6 +121,39 @@ impl Display for QueueEntry {
 pub struct TestRunner {
     verbose: bool,
 
+    // Only tests whose path matches this pattern are run.
+    filter: Option<String>,
+
+    // Only tests using this test command are run.
+    command: Option<String>,
+
+    // Only these tests are run, when rerunning the failures of the last run.
+    failed: Option<HashSet<PathBuf>>,
+
+    // Timeout in seconds for tests that don't specify their own.
+    timeout: usize,
+
+    // File to write a report of the results to.
+    report: Option<PathBuf>,
+
+    // File to write the durations of all the tests to, as CSV.
+    timings: Option<PathBuf>,
+
+    // Tests running longer than this are reported as slow, instead of the statistical outliers.
+    slow_threshold: Option<time::Duration>,
+
+    // Rewrite the filecheck directives of failing tests.
+    bless: bool,
+
+    // Seed for running the tests in a random order.
+    seed: Option<u64>,
+
+    // Stop running tests after the first failure.
+    fail_fast: bool,
+
+    // Results of the tests that passed in earlier runs.
+    cache: Option<ResultCache>,
+
     // Directories that have not yet been scanned.
     dir_stack: Vec<PathBuf>,
 
@@ -73,10 +169,17 @@ pub struct TestRunner {
     // Number of errors seen so far.
     errors: usize,
 
+    // Number of tests skipped, of expected failures, and of passes found in the cache.
+    skipped: usize,
+    xfailed: usize,
+    cached: usize,
+
     // Number of ticks received since we saw any progress.
     ticks_since_progress: usize,
 
     threads: Option<ConcurrentRunner>,
+
+    console: Console,
 }
 
 impl TestRunner {
@@ -84,13 +187,28 @@ impl TestRunner {
     pub fn new(verbose: bool) -> Self {
         Self {
             verbose,
+            filter: None,
+            command: None,
+            failed: None,
+            timeout: DEFAULT_TIMEOUT,
+            report: None,
+            timings: None,
+            slow_threshold: None,
+            bless: false,
+            seed: None,
+            fail_fast: false,
+            cache: None,
             dir_stack: Vec::new(),
             tests: Vec::new(),
             new_tests: 0,
             reported_tests: 0,
             errors: 0,
+            skipped: 0,
+            xfailed: 0,
+            cached: 0,
             ticks_since_progress: 0,
             threads: None,
+            console: Console::new(),
         }
     }
 
@@ -102,20 +220,123 @@ impl TestRunner {
         self.dir_stack.push(dir.into());
     }
 
+    /// Only run the tests whose path matches `pattern`.
+    ///
+    /// The pattern matches any part of the path. It can contain the wildcards `*`, matching any
+    /// sequence of characters, and `?`, matching any single character.
+    pub fn set_filter(&mut self, pattern: String) {
+        self.filter = Some(pattern);
+    }
+
+    /// Only run the test files with a `test` line for `command`, like `legalizer` or `binemit`.
+    ///
+    /// The header of each test file is read to decide.
+    pub fn set_command(&mut self, command: String) {
+        self.command = Some(command);
+    }
+
+    /// Only run the test files in `paths`, which didn't pass in the last run.
+    pub fn set_failed(&mut self, paths: HashSet<PathBuf>) {
+        self.failed = Some(paths);
+    }
+
+    /// Set the number of seconds a test may run before it fails.
+    ///
+    /// A test file can override this with a `timeout:` directive in its header. Timeouts only
+    /// apply to tests running on worker threads.
+    pub fn set_timeout(&mut self, secs: usize) {
+        self.timeout = secs;
+    }
+
+    /// Write a report of the results to `path` when the tests are done.
+    ///
+    /// The report is in JUnit XML format if `path` has an `xml` extension, and JSON otherwise.
+    pub fn set_report(&mut self, path: PathBuf) {
+        self.report = Some(path);
+    }
+
+    /// Write the duration of each test to `path` as CSV when the tests are done.
+    pub fn set_timings(&mut self, path: PathBuf) {
+        self.timings = Some(path);
+    }
+
+    /// Report the tests that pass in more than `threshold` as slow.
+    ///
+    /// By default, the tests that take much longer than the rest are reported as slow.
+    pub fn set_slow_threshold(&mut self, threshold: time::Duration) {
+        self.slow_threshold = Some(threshold);
+    }
+
+    /// Rewrite the filecheck directives of failing tests to match their actual output.
+    pub fn set_bless(&mut self) {
+        self.bless = true;
+    }
+
+    /// Skip the tests that passed in an earlier run according to `cache`, and update it with the
+    /// results of this run.
+    pub fn set_cache(&mut self, cache: ResultCache) {
+        self.cache = Some(cache);
+    }
+
+    /// Run the tests in a random order determined by `seed`.
+    ///
+    /// The tests are shuffled once all the directories have been scanned, so no tests start
+    /// before then. The seed is printed with the results, so the order can be reproduced.
+    pub fn set_shuffle(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    /// Stop running tests after the first failure.
+    ///
+    /// The tests that are already running are allowed to finish, and the rest are reported as not
+    /// run.
+    pub fn set_fail_fast(&mut self) {
+        self.fail_fast = true;
+    }
+
     /// Add a test to be executed later.
     ///
-    /// Any problems reading `file` as a test case file will be reported as a test failure.
+    /// Any problems reading `file` as a test case file will be reported as a test failure. The
+    /// test is ignored if it doesn't match the filter or the test command, or if only the failed
+    /// tests are run and it isn't one of them.
+    ///
+    /// A test file with several `isa` lines is split into a job for each of them, so the ISA
+    /// configurations run concurrently and are reported separately. The tests that don't need an
+    /// ISA run with the last configuration. In bless mode, the file is kept in one piece so its
+    /// directives are only rewritten once.
     pub fn push_test<P: Into<PathBuf>>(&mut self, file: P) {
-        self.tests.push(QueueEntry {
-            path: file.into(),
-            state: State::New,
-        });
+        let path = file.into();
+        if let Some(ref pattern) = self.filter {
+            if !matches_filter(&path.to_string_lossy(), pattern) {
+                return;
+            }
+        }
+        if let Some(ref failed) = self.failed {
+            if !failed.contains(&path) {
+                return;
+            }
+        }
+        let header = runone::header(&path);
+        if let Some(ref command) = self.command {
+            if !header.commands.contains(command) {
+                return;
+            }
+        }
+        if header.configs > 1 && !self.bless {
+            for config in 0..header.configs {
+                self.tests.push(QueueEntry::new(path.clone(), Some(config)));
+            }
+        } else {
+            self.tests.push(QueueEntry::new(path, None));
+        }
     }
 
-    /// Begin running tests concurrently.
-    pub fn start_threads(&mut self) {
+    /// Begin running tests concurrently on `num_threads` worker threads.
+    ///
+    /// Without calling this, tests are run one at a time on the current thread.
+    pub fn start_threads(&mut self, num_threads: usize) {
         assert!(self.threads.is_none());
-        self.threads = Some(ConcurrentRunner::new());
+        self.threads = Some(ConcurrentRunner::new(num_threads, self.bless));
     }
 
     /// Scan any directories pushed so far.
@@ -124,7 +345,8 @@ impl TestRunner {
         // This recursive search tries to minimize statting in a directory hierarchy containing
         // mostly test cases.
         //
-        // - Directory entries with a "cton" extension are presumed to be test case files.
+        // - Directory entries with a "cton", "wat", "wasm", or "wast" extension are presumed to be test
+        //   case files.
         // - Directory entries with no extension are presumed to be subdirectories.
         // - Anything else is ignored.
         //
@@ -157,7 +379,9 @@ impl TestRunner {
                                 // Recognize directories and tests by extension.
                                 // Yes, this means we ignore directories with '.' in their name.
                                 match path.extension().and_then(OsStr::to_str) {
-                                    Some("cton") => self.push_test(path),
+                                    Some("cton") | Some("wat") | Some("wasm") | Some("wast") => {
+                                        self.push_test(path)
+                                    }
                                     Some(_) => {}
                                     None => self.push_dir(path),
                                 }
@@ -166,23 +390,56 @@ impl TestRunner {
                     }
                 }
             }
-            // Get the new jobs running before moving on to the next directory.
-            self.schedule_jobs();
+            // Get the new jobs running before moving on to the next directory, unless they need to
+            // be shuffled first.
+            if self.seed.is_none() {
+                self.schedule_jobs();
+            }
         }
     }
 
     /// Report an error related to a path.
     fn path_error<E: Error>(&mut self, path: PathBuf, err: E) {
+        self.count_error();
+        let text = format!("{}: {}", path.to_string_lossy(), err);
+        self.console.println(Style::Fail, &text);
+    }
+
+    /// Count a failure, and cancel the queued tests if we're failing fast.
+    fn count_error(&mut self) {
         self.errors += 1;
-        println!("{}: {}", path.to_string_lossy(), err);
+        if self.fail_fast {
+            if let Some(ref mut conc) = self.threads {
+                conc.cancel();
+            }
+        }
+    }
+
+    /// Report on the finished jobs in order.
+    fn report_jobs(&mut self) {
+        while self.report_job() {
+            self.reported_tests += 1;
+        }
+        self.console.progress(self.reported_tests, self.tests.len());
     }
 
     /// Report on the next in-order job, if it's done.
-    fn report_job(&self) -> bool {
+    fn report_job(&mut self) -> bool {
         let jobid = self.reported_tests;
-        if let Some(&QueueEntry { state: State::Done(ref result), .. }) = self.tests.get(jobid) {
-            if self.verbose || result.is_err() {
-                println!("{}", self.tests[jobid]);
+        if let Some(&QueueEntry { state: State::Cancelled, .. }) = self.tests.get(jobid) {
+            return true;
+        }
+        if let Some(&QueueEntry { state: State::Done(ref result), expect, .. }) =
+            self.tests.get(jobid)
+        {
+            let style = match (expect, result.is_ok()) {
+                (Expect::Skip, _) | (Expect::Fail, false) => Style::Skip,
+                (_, false) => Style::Fail,
+                (_, true) => Style::Pass,
+            };
+            if self.verbose || style == Style::Fail {
+                let text = self.tests[jobid].to_string();
+                self.console.println(style, &text);
             }
             true
         } else {
@@ -191,20 +448,44 @@ impl TestRunner {
     }
 
     /// Schedule any new jobs to run.
+    ///
+    /// After a failure in fail-fast mode, the new jobs are cancelled instead.
     fn schedule_jobs(&mut self) {
+        let mut cancelled = false;
         for jobid in self.new_tests..self.tests.len() {
             assert_eq!(self.tests[jobid].state, State::New);
+            self.new_tests = jobid + 1;
+            if self.fail_fast && self.errors > 0 {
+                self.tests[jobid].state = State::Cancelled;
+                cancelled = true;
+                continue;
+            }
+            if let Some(dur) = self.lookup_cache(jobid) {
+                self.tests[jobid].state = State::Running;
+                self.tests[jobid].cached = true;
+                self.finish_job(jobid, Ok(dur));
+                continue;
+            }
             if let Some(ref mut conc) = self.threads {
                 // Queue test for concurrent execution.
                 self.tests[jobid].state = State::Queued;
-                conc.put(jobid, self.tests[jobid].path());
+                conc.put(jobid, self.tests[jobid].path(), self.tests[jobid].config);
             } else {
                 // Run test synchronously.
+                let expect = runone::header(self.tests[jobid].path()).expect;
                 self.tests[jobid].state = State::Running;
-                let result = runone::run(self.tests[jobid].path());
+                self.tests[jobid].expect = expect;
+                let result = if expect == Expect::Skip {
+                    Ok(time::Duration::default())
+                } else {
+                    let entry = &self.tests[jobid];
+                    runone::run(entry.path(), entry.config, self.bless)
+                };
                 self.finish_job(jobid, result);
             }
-            self.new_tests = jobid + 1;
+        }
+        if cancelled {
+            self.report_jobs();
         }
 
         // Check for any asynchronous replies without blocking.
@@ -213,77 +494,198 @@ impl TestRunner {
         }
     }
 
+    /// Hash the test file of a job, and look for a passed result for it in the cache.
+    fn lookup_cache(&mut self, jobid: usize) -> Option<time::Duration> {
+        let cache = self.cache.as_ref()?;
+        let entry = &mut self.tests[jobid];
+        entry.hash = cache::hash_file(entry.path()).ok();
+        cache.lookup(Path::new(entry.name().as_ref()), entry.hash?)
+    }
+
     /// Report the end of a job.
+    ///
+    /// Skipped tests and expected failures are counted separately. An expected failure that passes
+    /// is an error.
     fn finish_job(&mut self, jobid: usize, result: TestResult) {
         assert_eq!(self.tests[jobid].state, State::Running);
-        if result.is_err() {
-            self.errors += 1;
+        let expect = self.tests[jobid].expect;
+        let result = match (expect, result) {
+            (Expect::Skip, result) => {
+                self.skipped += 1;
+                result
+            }
+            (Expect::Fail, Ok(_)) => {
+                // Report the unexpected pass like any other failure.
+                self.count_error();
+                self.tests[jobid].expect = Expect::Pass;
+                Err("passed, but is expected to fail".to_string())
+            }
+            (Expect::Fail, Err(e)) => {
+                self.xfailed += 1;
+                Err(e)
+            }
+            (Expect::Pass, result) => {
+                if result.is_err() {
+                    self.count_error();
+                }
+                result
+            }
+        };
+        if let Some(ref mut cache) = self.cache {
+            let entry = &self.tests[jobid];
+            let name = entry.name();
+            match (entry.hash, &result) {
+                (Some(hash), &Ok(dur)) if expect == Expect::Pass => {
+                    cache.insert(Path::new(name.as_ref()), hash, dur)
+                }
+                _ => cache.remove(Path::new(name.as_ref())),
+            }
         }
-        self.tests[jobid].state = State::Done(result);
-
-        // Reports jobs in order.
-        while self.report_job() {
-            self.reported_tests += 1;
+        if self.tests[jobid].cached {
+            self.cached += 1;
         }
+        self.tests[jobid].state = State::Done(result);
+        self.report_jobs();
     }
 
     /// Handle a reply from the async threads.
     fn handle_reply(&mut self, reply: Reply) {
         match reply {
-            Reply::Starting { jobid, .. } => {
-                assert_eq!(self.tests[jobid].state, State::Queued);
-                self.tests[jobid].state = State::Running;
+            Reply::Starting {
+                jobid,
+                thread_num,
+                timeout,
+                expect,
+            } => {
+                let entry = &mut self.tests[jobid];
+                assert_eq!(entry.state, State::Queued);
+                entry.state = State::Running;
+                entry.expect = expect;
+                entry.thread_num = thread_num;
+                entry.timeout = timeout.unwrap_or(self.timeout);
             }
             Reply::Done { jobid, result } => {
                 self.ticks_since_progress = 0;
-                self.finish_job(jobid, result)
+                // A test that timed out has already been reported.
+                if self.tests[jobid].state == State::Running {
+                    self.finish_job(jobid, result)
+                }
+            }
+            Reply::Cancelled { jobid } => {
+                self.ticks_since_progress = 0;
+                assert_eq!(self.tests[jobid].state, State::Queued);
+                self.tests[jobid].state = State::Cancelled;
+                self.report_jobs();
             }
             Reply::Tick => {
                 self.ticks_since_progress += 1;
                 if self.ticks_since_progress == TIMEOUT_SLOW {
-                    println!(
+                    let text = format!(
                         "STALLED for {} seconds with {}/{} tests finished",
                         self.ticks_since_progress,
                         self.reported_tests,
                         self.tests.len()
                     );
+                    self.console.println(Style::Slow, &text);
                     for jobid in self.reported_tests..self.tests.len() {
                         if self.tests[jobid].state == State::Running {
-                            println!("slow: {}", self.tests[jobid]);
+                            let text = format!("slow: {}", self.tests[jobid]);
+                            self.console.println(Style::Slow, &text);
                         }
                     }
                 }
-                if self.ticks_since_progress >= TIMEOUT_PANIC {
+                self.check_timeouts();
+                let limit = self.stall_limit();
+                if self.ticks_since_progress >= limit {
                     panic!(
                         "worker threads stalled for {} seconds.",
                         self.ticks_since_progress
                     );
                 }
+                self.console.progress(self.reported_tests, self.tests.len());
+            }
+        }
+    }
+
+    /// Fail the running tests that have exceeded their timeout.
+    ///
+    /// The worker threads running them are abandoned, so the remaining tests can still run.
+    fn check_timeouts(&mut self) {
+        for jobid in self.reported_tests..self.tests.len() {
+            if self.tests[jobid].state != State::Running {
+                continue;
+            }
+            self.tests[jobid].ticks += 1;
+            let timeout = self.tests[jobid].timeout;
+            if self.tests[jobid].ticks >= timeout {
+                if let Some(ref mut conc) = self.threads {
+                    conc.abandon(self.tests[jobid].thread_num);
+                }
+                self.ticks_since_progress = 0;
+                self.finish_job(jobid, Err(format!("timed out after {} seconds", timeout)));
             }
         }
     }
 
+    /// Get the number of seconds without progress after which the worker threads are stalled.
+    ///
+    /// Running tests are allowed their full timeout, so the limit is relative to the longest one.
+    fn stall_limit(&self) -> usize {
+        let longest = self.tests[self.reported_tests..]
+            .iter()
+            .filter(|entry| entry.state == State::Running)
+            .map(|entry| entry.timeout)
+            .fold(self.timeout, cmp::max);
+        longest + TIMEOUT_PANIC
+    }
+
     /// Drain the async jobs and shut down the threads.
     fn drain_threads(&mut self) {
-        if let Some(mut conc) = self.threads.take() {
+        if let Some(ref mut conc) = self.threads {
             conc.shutdown();
-            while self.reported_tests < self.tests.len() {
-                match conc.get() {
-                    Some(reply) => self.handle_reply(reply),
-                    None => break,
-                }
+        }
+        // Keep the threads in place while draining so timed out workers can be replaced.
+        while self.reported_tests < self.tests.len() {
+            match self.threads.as_mut().and_then(ConcurrentRunner::get) {
+                Some(reply) => self.handle_reply(reply),
+                None => break,
             }
+        }
+        if let Some(mut conc) = self.threads.take() {
             conc.join();
         }
     }
 
     /// Print out a report of slow tests.
-    fn report_slow_tests(&self) {
+    fn report_slow_tests(&mut self) {
+        let cut = match self.slow_threshold.or_else(|| self.slow_cutoff()) {
+            Some(cut) => cut,
+            None => return,
+        };
+
+        let slow = self.tests
+            .iter()
+            .filter(|entry| match **entry {
+                QueueEntry { state: State::Done(Ok(dur)), .. } => dur > cut,
+                _ => false,
+            })
+            .map(|t| format!("slow: {}", t))
+            .collect::<Vec<_>>();
+        for text in slow {
+            self.console.println(Style::Slow, &text);
+        }
+    }
+
+    /// Compute the duration above which a test is considered slow from the durations of the
+    /// passed tests.
+    ///
+    /// Returns `None` if there aren't enough tests, or none of them are slow.
+    fn slow_cutoff(&self) -> Option<time::Duration> {
         // Collect runtimes of succeeded tests.
         let mut times = self.tests
             .iter()
             .filter_map(|entry| match *entry {
-                QueueEntry { state: State::Done(Ok(dur)), .. } => Some(dur),
+                QueueEntry { state: State::Done(Ok(dur)), expect: Expect::Pass, .. } => Some(dur),
                 _ => None,
             })
             .collect::<Vec<_>>();
@@ -291,7 +693,7 @@ impl TestRunner {
         // Get me some real data, kid.
         let len = times.len();
         if len < 4 {
-            return;
+            return None;
         }
 
         // Compute quartiles.
@@ -309,27 +711,120 @@ impl TestRunner {
         // but we have a wider distribution of test times, so double it to 3 IQR.
         let cut = q3 + iqr * 3;
         if cut > *times.last().unwrap() {
-            return;
+            return None;
         }
+        Some(cut)
+    }
 
-        for t in self.tests.iter().filter(|entry| match **entry {
-            QueueEntry { state: State::Done(Ok(dur)), .. } => dur > cut,
-            _ => false,
-        })
-        {
-            println!("slow: {}", t)
+    /// Write the results to the report file and the timings file, if any.
+    fn write_reports(&self, elapsed: time::Duration) -> Result<(), String> {
+        if self.report.is_none() && self.timings.is_none() {
+            return Ok(());
         }
+        let names = self.tests.iter().map(QueueEntry::name).collect::<Vec<_>>();
+        let results = self.tests
+            .iter()
+            .zip(&names)
+            .filter_map(|(entry, name)| match entry.state {
+                State::Done(ref result) => Some((Path::new(name.as_ref()), entry.expect, result)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if let Some(ref path) = self.report {
+            let mut text = String::new();
+            write_report(&mut text, ReportFormat::from_path(path), &results, elapsed)
+                .expect("writing to a String can't fail");
+            write_file(path, &text)?;
+        }
+        if let Some(ref path) = self.timings {
+            let mut text = String::new();
+            write_timings(&mut text, &results).expect("writing to a String can't fail");
+            write_file(path, &text)?;
+        }
+        Ok(())
+    }
 
+    /// Get the paths of the test files that failed, or weren't run because of an earlier failure.
+    fn failed_paths(&self) -> Vec<&Path> {
+        let mut paths = self.tests
+            .iter()
+            .filter(|entry| match entry.state {
+                State::Done(Err(_)) => entry.expect == Expect::Pass,
+                State::Cancelled => true,
+                _ => false,
+            })
+            .map(QueueEntry::path)
+            .collect::<Vec<_>>();
+        // A test file split into ISA configurations only needs to be listed once.
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// Get the updated list of tests that didn't pass, given the list `previous` from the last
+    /// run.
+    ///
+    /// The tests that ran replace their entries in `previous`. When only some of the tests were
+    /// selected with a filter, a test command, or the failed list, the failures of the tests that
+    /// weren't selected are kept. A full run replaces the whole list, so it forgets the tests that
+    /// no longer exist.
+    fn updated_failed_list(&self, previous: HashSet<PathBuf>) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if self.filter.is_some() || self.command.is_some() || self.failed.is_some() {
+            let queued = self.tests.iter().map(QueueEntry::path).collect::<HashSet<_>>();
+            paths.extend(previous.into_iter().filter(
+                |path| !queued.contains(path.as_path()),
+            ));
+        }
+        paths.extend(self.failed_paths().into_iter().map(Path::to_path_buf));
+        paths.sort();
+        paths.dedup();
+        paths
     }
 
     /// Scan pushed directories for tests and run them.
     pub fn run(&mut self) -> TestResult {
         let started = time::Instant::now();
         self.scan_dirs();
+        if let Some(seed) = self.seed {
+            shuffle(&mut self.tests[self.new_tests..], seed);
+        }
         self.schedule_jobs();
         self.drain_threads();
+        self.console.clear_progress();
         self.report_slow_tests();
-        println!("{} tests", self.tests.len());
+        print!("{} tests", self.tests.len());
+        if self.skipped > 0 {
+            print!(", {} skipped", self.skipped);
+        }
+        if self.xfailed > 0 {
+            print!(", {} expected failures", self.xfailed);
+        }
+        if self.cached > 0 {
+            print!(", {} cached", self.cached);
+        }
+        let cancelled = self.tests
+            .iter()
+            .filter(|entry| entry.state == State::Cancelled)
+            .count();
+        if cancelled > 0 {
+            print!(", {} not run", cancelled);
+        }
+        if let Some(seed) = self.seed {
+            print!(", shuffled with seed {}", seed);
+        }
+        println!();
+        self.write_reports(started.elapsed())?;
+        if let Some(ref cache) = self.cache {
+            // The cache only saves time, so failing to write it is not an error.
+            if let Err(e) = cache.save() {
+                println!("failed to save the test result cache: {}", e);
+            }
+        }
+        let previous = cache::load_failed().unwrap_or_default();
+        if let Err(e) = cache::save_failed(&self.updated_failed_list(previous)) {
+            println!("failed to save the list of failed tests: {}", e);
+        }
         match self.errors {
             0 => Ok(started.elapsed()),
             1 => Err("1 failure".to_string()),
@@ -337,3 +832,134 @@ impl TestRunner {
         }
     }
 }
+
+/// Write `text` to the file at `path`.
+fn write_file(path: &Path, text: &str) -> Result<(), String> {
+    File::create(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Get a random seed for shuffling the tests.
+pub fn random_seed() -> u64 {
+    // The standard hash maps are randomly keyed, which is good enough here.
+    RandomState::new().build_hasher().finish()
+}
+
+/// Shuffle `items` in an order determined by `seed`.
+///
+/// This is a Fisher-Yates shuffle driven by a xorshift64* generator, which doesn't need to be any
+/// better than that to vary the order of the tests.
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut rng = Rng::new(seed);
+    for i in (1..items.len()).rev() {
+        let j = rng.below(i + 1);
+        items.swap(i, j);
+    }
+}
+
+/// Does `pattern` match any part of `path`?
+///
+/// In the pattern, `*` matches any sequence of characters and `?` matches any single character.
+fn matches_filter(path: &str, pattern: &str) -> bool {
+    let path: Vec<char> = path.chars().collect();
+    // Matching any part of the path is the same as matching all of it with `*` on both sides.
+    let mut glob = vec!['*'];
+    glob.extend(pattern.chars());
+    glob.push('*');
+    matches_glob(&path, &glob)
+}
+
+/// Does `pattern` match all of `text`?
+///
+/// When a character doesn't match, only the last `*` seen needs to be extended to match one more
+/// character. Any earlier `*` could only match more by skipping text the last one can skip too,
+/// so this takes time proportional to the product of the lengths instead of exponential time.
+fn matches_glob(text: &[char], pattern: &[char]) -> bool {
+    let (mut t, mut p) = (0, 0);
+    // Position of the last `*` in the pattern, and of the text after what it matches.
+    let mut star = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter() {
+        assert!(matches_filter("filetests/isa/intel/abi64.cton", "intel"));
+        assert!(matches_filter("filetests/isa/intel/abi64.cton", "isa/*/abi"));
+        assert!(matches_filter("filetests/isa/intel/abi64.cton", "abi??.cton"));
+        assert!(matches_filter("filetests/isa/intel/abi64.cton", ""));
+        assert!(!matches_filter("filetests/isa/intel/abi64.cton", "riscv"));
+        assert!(!matches_filter("filetests/isa/intel/abi64.cton", "abi?.cton"));
+
+        // Many stars don't make the matching exponential.
+        let path = "a".repeat(10_000);
+        assert!(!matches_filter(&path, "a*a*a*a*a*a*a*a*a*a*b"));
+        assert!(matches_filter(&path, "a*a*a*a*a*a*a*a*a*a*a"));
+    }
+
+    #[test]
+    fn filtered_failed_list() {
+        let previous: HashSet<PathBuf> = ["a/foo.cton", "a/bar.cton", "b/foo.cton"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+
+        // Rerun the failed tests matching `foo`, one of which passes now.
+        let mut runner = TestRunner::new(false);
+        runner.set_failed(previous.clone());
+        runner.set_filter("foo".to_string());
+        let mut fixed = QueueEntry::new(PathBuf::from("a/foo.cton"), None);
+        fixed.state = State::Done(Ok(time::Duration::from_millis(1)));
+        let mut failing = QueueEntry::new(PathBuf::from("b/foo.cton"), None);
+        failing.state = State::Done(Err("boom".to_string()));
+        runner.tests.push(fixed);
+        runner.tests.push(failing);
+        assert_eq!(
+            runner.updated_failed_list(previous.clone()),
+            [PathBuf::from("a/bar.cton"), PathBuf::from("b/foo.cton")]
+        );
+
+        // A full run replaces the list.
+        runner.filter = None;
+        runner.failed = None;
+        assert_eq!(
+            runner.updated_failed_list(previous),
+            [PathBuf::from("b/foo.cton")]
+        );
+    }
+
+    #[test]
+    fn shuffle_order() {
+        let mut a: Vec<u32> = (0..20).collect();
+        let mut b = a.clone();
+        shuffle(&mut a, 42);
+        shuffle(&mut b, 42);
+        assert_eq!(a, b);
+        assert_ne!(a, (0..20).collect::<Vec<_>>());
+        b.sort();
+        assert_eq!(b, (0..20).collect::<Vec<_>>());
+
+        let mut c: Vec<u32> = (0..20).collect();
+        shuffle(&mut c, 43);
+        assert_ne!(a, c);
+    }
+}
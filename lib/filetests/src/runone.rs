@@ -1,9 +1,11 @@
 //! Run the tests in a single test file.
 
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::env;
 use std::path::Path;
 use std::time;
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use std::fs;
 use cretonne::ir::Function;
 use cretonne::isa::TargetIsa;
@@ -11,10 +13,15 @@ use cretonne::settings::Flags;
 use cretonne::timing;
 use cretonne::verify_function;
 use cretonne::print_errors::pretty_verifier_error;
-use cton_reader::parse_test;
+use cton_reader::{diagnostic, parse_test_with_recovery};
 use cton_reader::IsaSpec;
 use {TestResult, new_subtest};
+use bless::Bless;
+use captures::Captures;
+use match_directive::match_directive;
 use subtest::{SubTest, Context, Result};
+use test_wasm;
+use test_wast;
 
 /// Read an entire file into a string.
 fn read_to_string<P: AsRef<Path>>(path: P) -> io::Result<String> {
@@ -26,16 +33,49 @@ fn read_to_string<P: AsRef<Path>>(path: P) -> io::Result<String> {
 
 /// Load `path` and run the test in it.
 ///
+/// If `config` is given, only the tests for that ISA configuration are run: the ones using the
+/// ISA from the `config`th `isa` line, and, for the last configuration, the tests that don't need
+/// an ISA.
+///
+/// In `bless` mode, the filecheck directives of failing functions are rewritten to match the
+/// actual output, and the file is updated.
+///
+/// WebAssembly test files are translated by the `wasm` test command, and spec test scripts are run
+/// by the `wast` test command. Neither can be blessed.
+///
 /// If running this test causes a panic, it will propagate as normal.
-pub fn run(path: &Path) -> TestResult {
+pub fn run(path: &Path, config: Option<usize>, bless: bool) -> TestResult {
     let _tt = timing::process_file();
-    dbg!("---\nFile: {}", path.to_string_lossy());
+    debug!("---\nFile: {}", path.to_string_lossy());
+    if test_wasm::is_wasm_file(path) {
+        return test_wasm::run(path, config);
+    }
+    if test_wast::is_wast_file(path) {
+        return test_wast::run(path);
+    }
     let started = time::Instant::now();
     let buffer = read_to_string(path).map_err(|e| e.to_string())?;
-    let testfile = parse_test(&buffer).map_err(|e| e.to_string())?;
+    let filename = path.to_string_lossy();
+    let testfile = parse_test_with_recovery(&buffer).map_err(|errors| {
+        errors
+            .iter()
+            .map(|e| e.diagnostic(&filename, &buffer))
+            .collect::<Vec<_>>()
+            .join("\n")
+    })?;
     if testfile.functions.is_empty() {
         return Err("no functions found".to_string());
     }
+    for comment in &testfile.preamble_comments {
+        if let Some(text) = match_directive(comment.text, "timeout:") {
+            parse_timeout(text)?;
+        }
+        for directive in &["xfail:", "skip-if:"] {
+            if let Some(text) = match_directive(comment.text, directive) {
+                parse_condition(text).map_err(|e| format!("{} {}", directive, e))?;
+            }
+        }
+    }
 
     // Parse the test commands.
     let mut tests = testfile
@@ -56,15 +96,28 @@ pub fn run(path: &Path) -> TestResult {
     tests.sort_by_key(|st| (st.is_mutating(), st.needs_verifier()));
 
     // Expand the tests into (test, flags, isa) tuples.
-    let mut tuples = test_tuples(&tests, &testfile.isa_spec, flags)?;
+    let mut tuples = test_tuples(&tests, &testfile.isa_spec, flags, config)?;
 
     // Isolate the last test in the hope that this is the only mutating test.
     // If so, we can completely avoid cloning functions.
     let last_tuple = match tuples.pop() {
+        // A configuration may have nothing to run if none of the tests need an ISA.
+        None if config.is_some() => return Ok(started.elapsed()),
         None => return Err("no test commands found".to_string()),
         Some(t) => t,
     };
 
+    // Each test has its own filecheck variables, which carry over from one function to the next.
+    let captures: Vec<RefCell<Captures>> = (0..tuples.len() + 1)
+        .map(|_| RefCell::default())
+        .collect();
+
+    let blessed = if bless {
+        Some(RefCell::new(Bless::new(&buffer)))
+    } else {
+        None
+    };
+
     for (func, details) in testfile.functions {
         let mut context = Context {
             preamble_comments: &testfile.preamble_comments,
@@ -72,25 +125,176 @@ pub fn run(path: &Path) -> TestResult {
             verified: false,
             flags,
             isa: None,
+            captures: &captures[0],
+            bless: blessed.as_ref(),
         };
 
-        for tuple in &tuples {
-            run_one_test(*tuple, Cow::Borrowed(&func), &mut context)?;
+        for (tuple, captures) in tuples.iter().zip(&captures) {
+            context.captures = captures;
+            run_one_test(*tuple, Cow::Borrowed(&func), &mut context, &filename, &buffer)?;
         }
         // Run the last test with an owned function which means it won't need to clone it before
         // mutating.
-        run_one_test(last_tuple, Cow::Owned(func), &mut context)?;
+        context.captures = &captures[tuples.len()];
+        run_one_test(
+            last_tuple,
+            Cow::Owned(func),
+            &mut context,
+            &filename,
+            &buffer,
+        )?;
     }
 
+    if let Some((source, diff)) = blessed.as_ref().and_then(|b| b.borrow().apply()) {
+        fs::File::create(path)
+            .and_then(|mut file| file.write_all(source.as_bytes()))
+            .map_err(|e| format!("bless: {}", e))?;
+        println!("blessed {}:\n{}", filename, diff);
+    }
 
     Ok(started.elapsed())
 }
 
+/// How a test file is expected to behave on this host.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Expect {
+    /// The test should pass.
+    Pass,
+    /// The test is known to fail, as requested by an `xfail:` directive.
+    Fail,
+    /// The test should not be run, as requested by a `skip-if:` directive.
+    Skip,
+}
+
+/// The directives in the header of a test file that affect how the runner treats it.
+pub struct Header {
+    /// The timeout in seconds requested by a `timeout:` directive.
+    pub timeout: Option<usize>,
+    /// The expected outcome, from the `xfail:` and `skip-if:` directives.
+    pub expect: Expect,
+    /// The test commands in the `test` lines.
+    pub commands: Vec<String>,
+    /// The number of ISA configurations, which is the number of `isa` lines.
+    pub configs: usize,
+}
+
+/// Read the directives in the header of the test file at `path`.
+///
+/// This only scans the lines before the first function, so it is cheap enough to call before
+/// running the test. Malformed directives are ignored here and reported by `run`.
+///
+/// The `xfail:` and `skip-if:` directives take a condition, which is a comma-separated list of
+/// names that may be negated with `!`. The condition holds if any of them does. A name is true if
+/// it is one of the ISAs in the test header, or the architecture or operating system of the host,
+/// like `x86_64` or `linux`. The name `x86` matches both 32-bit and 64-bit x86 hosts. An empty
+/// condition always holds.
+pub fn header(path: &Path) -> Header {
+    let mut header = Header {
+        timeout: None,
+        expect: Expect::Pass,
+        commands: Vec::new(),
+        configs: 0,
+    };
+    let buffer = match read_to_string(path) {
+        Ok(buffer) => buffer,
+        Err(_) => return header,
+    };
+    let lines: Vec<&str> = buffer
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with("function"))
+        .collect();
+    // The header of a `.wat` or `.wast` file is in `;;` comments.
+    let wast = test_wast::is_wast_file(path);
+    let wat = test_wasm::is_wasm_file(path) || wast;
+    let mut isas = Vec::new();
+    for line in &lines {
+        let line = if wat { line.trim_left_matches(';') } else { line };
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("isa"), Some(isa)) => isas.push(isa),
+            (Some("test"), Some(command)) => header.commands.push(command.to_string()),
+            _ => {}
+        }
+    }
+    header.configs = isas.len();
+    // A WebAssembly test file without a header is a `test wasm` test, and a spec test script is
+    // always a `test wast` test.
+    if wast {
+        header.commands = vec!["wast".to_string()];
+    } else if wat && header.commands.is_empty() {
+        header.commands.push("wasm".to_string());
+    }
+    let holds = |text| parse_condition(text).map(|c| c.holds(&isas)).unwrap_or(false);
+
+    for line in lines.iter().filter(|line| line.starts_with(';')) {
+        if let Some(text) = match_directive(line, "timeout:") {
+            header.timeout = parse_timeout(text).ok().or(header.timeout);
+        } else if let Some(text) = match_directive(line, "skip-if:") {
+            if holds(text) {
+                header.expect = Expect::Skip;
+            }
+        } else if let Some(text) = match_directive(line, "xfail:") {
+            if holds(text) && header.expect == Expect::Pass {
+                header.expect = Expect::Fail;
+            }
+        }
+    }
+    header
+}
+
+/// Parse the text of a `timeout:` directive.
+fn parse_timeout(text: &str) -> Result<usize> {
+    match text.parse() {
+        Ok(secs) if secs > 0 => Ok(secs),
+        _ => Err(format!("timeout: {}: expected a positive number of seconds", text)),
+    }
+}
+
+/// A condition in an `xfail:` or `skip-if:` directive: a list of names, each possibly negated.
+struct Condition<'a>(Vec<(bool, &'a str)>);
+
+impl<'a> Condition<'a> {
+    /// Does the condition hold for a test file with `isas` in its header?
+    fn holds(&self, isas: &[&str]) -> bool {
+        self.0.is_empty() ||
+            self.0.iter().any(|&(negated, name)| {
+                let matches = isas.contains(&name) || name == env::consts::ARCH ||
+                    name == env::consts::OS ||
+                    (name == "x86" && (cfg!(target_arch = "x86") || cfg!(target_arch = "x86_64")));
+                matches != negated
+            })
+    }
+}
+
+/// Parse the text of an `xfail:` or `skip-if:` directive.
+fn parse_condition(text: &str) -> Result<Condition> {
+    let mut terms = Vec::new();
+    if text.is_empty() {
+        return Ok(Condition(terms));
+    }
+    for term in text.split(',').map(str::trim) {
+        let (negated, name) = if term.starts_with('!') {
+            (true, term[1..].trim())
+        } else {
+            (false, term)
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(format!("{}: expected a comma-separated list of names", text));
+        }
+        terms.push((negated, name));
+    }
+    Ok(Condition(terms))
+}
+
 // Given a slice of tests, generate a vector of (test, flags, isa) tuples.
+//
+// With a `config`, only generate the tuples for that ISA configuration.
 fn test_tuples<'a>(
     tests: &'a [Box<SubTest>],
     isa_spec: &'a IsaSpec,
     no_isa_flags: &'a Flags,
+    config: Option<usize>,
 ) -> Result<Vec<(&'a SubTest, &'a Flags, Option<&'a TargetIsa>)>> {
     let mut out = Vec::new();
     for test in tests {
@@ -101,11 +305,18 @@ fn test_tuples<'a>(
                     return Err(format!("test {} requires an ISA", test.name()));
                 }
                 IsaSpec::Some(ref isas) => {
-                    for isa in isas {
-                        out.push((&**test, isa.flags(), Some(&**isa)));
+                    for (idx, isa) in isas.iter().enumerate() {
+                        if config.map_or(true, |c| c == idx) {
+                            out.push((&**test, isa.flags(), Some(&**isa)));
+                        }
                     }
                 }
             }
+        } else if let (Some(config), &IsaSpec::Some(ref isas)) = (config, isa_spec) {
+            // Only run this test once, with the last configuration.
+            if config + 1 == isas.len() {
+                out.push((&**test, no_isa_flags, isa_spec.unique_isa()));
+            }
         } else {
             // This test doesn't require an ISA, and we only want to run one instance of it.
             // Still, give it an ISA ref if we happen to have a unique one.
@@ -120,10 +331,12 @@ fn run_one_test<'a>(
     tuple: (&'a SubTest, &'a Flags, Option<&'a TargetIsa>),
     func: Cow<Function>,
     context: &mut Context<'a>,
+    filename: &str,
+    source: &str,
 ) -> Result<()> {
     let (test, flags, isa) = tuple;
     let name = format!("{}({})", test.name(), func.name);
-    dbg!("Test: {} {}", name, isa.map(TargetIsa::name).unwrap_or("-"));
+    debug!("Test: {} {}", name, isa.map(TargetIsa::name).unwrap_or("-"));
 
     context.flags = flags;
     context.isa = isa;
@@ -132,7 +345,13 @@ fn run_one_test<'a>(
     if !context.verified && test.needs_verifier() {
         verify_function(&func, context.flags_or_isa()).map_err(
             |e| {
-                pretty_verifier_error(&func, isa, e)
+                // Point at the offending entity in the source when the parser saw it.
+                let mut msg = match context.details.map.location(e.location) {
+                    Some(loc) => diagnostic(filename, source, loc, &e.message),
+                    None => String::new(),
+                };
+                msg.push_str(&pretty_verifier_error(&func, isa, e));
+                msg
             },
         )?;
         context.verified = true;
@@ -142,3 +361,20 @@ fn run_one_test<'a>(
         |e| format!("{}: {}", name, e),
     )
 }
+
+#[test]
+fn test_conditions() {
+    let isas = ["riscv", "intel"];
+    let holds = |text| parse_condition(text).unwrap().holds(&isas);
+    assert!(holds(""));
+    assert!(holds("riscv"));
+    assert!(holds("arm32, intel"));
+    assert!(!holds("arm32"));
+    assert!(!holds("!riscv"));
+    assert!(holds("!arm32"));
+    assert!(holds(env::consts::OS));
+    assert_eq!(holds("x86"), cfg!(any(target_arch = "x86", target_arch = "x86_64")));
+    assert!(parse_condition("riscv,").is_err());
+    assert!(parse_condition("!").is_err());
+    assert!(parse_condition("a b").is_err());
+}
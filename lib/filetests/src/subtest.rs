@@ -2,11 +2,15 @@
 
 use std::result;
 use std::borrow::Cow;
+use std::cell::RefCell;
+use bless::Bless;
+use captures::Captures;
 use cretonne::ir::Function;
 use cretonne::isa::TargetIsa;
 use cretonne::settings::{Flags, FlagsOrIsa};
-use cton_reader::{Details, Comment};
-use filecheck::{CheckerBuilder, Checker, NO_VARIABLES};
+use cretonne::WriteOptions;
+use cton_reader::{Details, Comment, TestCommand, TestOption};
+use filecheck::{CheckerBuilder, Checker};
 
 pub type Result<T> = result::Result<T, String>;
 
@@ -27,6 +31,12 @@ pub struct Context<'a> {
     /// Target ISA to test against. Only guaranteed to be present for sub-tests whose `needs_isa`
     /// method returned `true`. For other sub-tests, this is set if the test file has a unique ISA.
     pub isa: Option<&'a TargetIsa>,
+
+    /// Filecheck variables captured by this test in the earlier functions of the test file.
+    pub captures: &'a RefCell<Captures>,
+
+    /// Changes to the test file in bless mode, where failing filecheck directives are rewritten.
+    pub bless: Option<&'a RefCell<Bless<'a>>>,
 }
 
 impl<'a> Context<'a> {
@@ -67,19 +77,51 @@ pub trait SubTest {
     fn run(&self, func: Cow<Function>, context: &Context) -> Result<()>;
 }
 
+/// Get the writer options selected by the options on a test command that prints functions.
+///
+/// The `no-encodings`, `no-reg-locations`, and `no-stack-locations` flags suppress the
+/// corresponding annotations. Any other option is an error.
+pub fn write_options(parsed: &TestCommand) -> Result<WriteOptions> {
+    let mut options = WriteOptions::default();
+    for option in &parsed.options {
+        match *option {
+            TestOption::Flag("no-encodings") => options.encodings = false,
+            TestOption::Flag("no-reg-locations") => options.reg_locations = false,
+            TestOption::Flag("no-stack-locations") => options.stack_locations = false,
+            _ => return Err(format!("Unknown option on {}", parsed)),
+        }
+    }
+    Ok(options)
+}
+
 /// Run filecheck on `text`, using directives extracted from `context`.
+///
+/// The variables captured by earlier functions in the file are available to the directives, and
+/// the variables captured here are recorded for the following functions when the check passes.
+///
+/// In bless mode, a failing check updates the directives instead of failing the test.
 pub fn run_filecheck(text: &str, context: &Context) -> Result<()> {
     let checker = build_filechecker(context)?;
-    if checker.check(text, NO_VARIABLES).map_err(|e| {
-        format!("filecheck: {}", e)
-    })?
-    {
+    let passed = checker
+        .check(text, &*context.captures.borrow())
+        .map_err(|e| format!("filecheck: {}", e))?;
+    if passed {
+        context.captures.borrow_mut().record(
+            filecheck_directives(context),
+            text,
+        );
         Ok(())
+    } else if let Some(bless) = context.bless {
+        bless.borrow_mut().update(
+            context.preamble_comments,
+            &context.details.comments,
+            text,
+        )
     } else {
         // Filecheck mismatch. Emit an explanation as output.
-        let (_, explain) = checker.explain(text, NO_VARIABLES).map_err(|e| {
-            format!("explain: {}", e)
-        })?;
+        let (_, explain) = checker
+            .explain(text, &*context.captures.borrow())
+            .map_err(|e| format!("explain: {}", e))?;
         Err(format!("filecheck failed:\n{}{}", checker, explain))
     }
 }
@@ -87,16 +129,23 @@ pub fn run_filecheck(text: &str, context: &Context) -> Result<()> {
 /// Build a filechecker using the directives in the file preamble and the function's comments.
 pub fn build_filechecker(context: &Context) -> Result<Checker> {
     let mut builder = CheckerBuilder::new();
-    // Preamble comments apply to all functions.
-    for comment in context.preamble_comments {
-        builder.directive(comment.text).map_err(|e| {
-            format!("filecheck: {}", e)
-        })?;
-    }
-    for comment in &context.details.comments {
-        builder.directive(comment.text).map_err(|e| {
-            format!("filecheck: {}", e)
-        })?;
+    for text in filecheck_directives(context) {
+        builder.directive(text).map_err(
+            |e| format!("filecheck: {}", e),
+        )?;
     }
     Ok(builder.finish())
 }
+
+/// Get the comments that may contain filecheck directives for the function in `context`.
+///
+/// Preamble comments apply to all functions, so they come first.
+fn filecheck_directives<'a>(context: &'a Context) -> Box<Iterator<Item = &'a str> + 'a> {
+    Box::new(
+        context
+            .preamble_comments
+            .iter()
+            .chain(&context.details.comments)
+            .map(|comment| comment.text),
+    )
+}
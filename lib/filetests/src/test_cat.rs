@@ -2,24 +2,26 @@
 
 use std::borrow::Cow;
 use cretonne::ir::Function;
+use cretonne::WriteOptions;
 use cton_reader::TestCommand;
 use subtest::{self, SubTest, Context, Result as STResult};
 
 /// Object implementing the `test cat` sub-test.
 ///
 /// This command is used for testing the parser and function printer. It simply parses a function
-/// and prints it out again.
+/// and prints it out again, in the format that reads back with the same entity numbers.
 ///
-/// The result is verified by filecheck.
-struct TestCat;
+/// The result is verified by filecheck. The options of `subtest::write_options()` select the
+/// annotations to print.
+struct TestCat {
+    options: WriteOptions,
+}
 
 pub fn subtest(parsed: &TestCommand) -> STResult<Box<SubTest>> {
     assert_eq!(parsed.command, "cat");
-    if !parsed.options.is_empty() {
-        Err(format!("No options allowed on {}", parsed))
-    } else {
-        Ok(Box::new(TestCat))
-    }
+    let mut options = subtest::write_options(parsed)?;
+    options.stable = true;
+    Ok(Box::new(TestCat { options }))
 }
 
 impl SubTest for TestCat {
@@ -32,6 +34,7 @@ impl SubTest for TestCat {
     }
 
     fn run(&self, func: Cow<Function>, context: &Context) -> STResult<()> {
-        subtest::run_filecheck(&func.display(context.isa).to_string(), context)
+        let text = func.display_with_options(context.isa, self.options).to_string();
+        subtest::run_filecheck(&text, context)
     }
 }
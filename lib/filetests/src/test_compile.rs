@@ -46,7 +46,7 @@ impl SubTest for TestCompile {
             pretty_error(&comp_ctx.func, context.isa, e)
         })?;
 
-        dbg!(
+        debug!(
             "Generated {} bytes of code:\n{}",
             code_size,
             comp_ctx.func.display(isa)
@@ -0,0 +1,206 @@
+//! Test command for checking the final machine code.
+//!
+//! The `disasm` test command compiles each function through the full code generator pipeline like
+//! `test compile`, emits the machine code, and runs filecheck on a disassembly listing of it. The
+//! listing has a label for each EBB, and one line per instruction with its offset, followed by the
+//! relocations within the instruction:
+//!
+//! ```text
+//! ebb0:
+//!    0: push rbp
+//!    1: mov rbp, rsp
+//!    4: call 0 ; reloc PCRel4(%g)
+//! ```
+//!
+//! This catches emission bugs that the IL-level directives of `test compile` can't see. The
+//! disassembler only supports the Intel and ARM ISAs.
+
+use capstone::prelude::*;
+use capstone::arch;
+use cretonne;
+use cretonne::binemit;
+use cretonne::ir;
+use cretonne::isa::TargetIsa;
+use cretonne::print_errors::pretty_error;
+use cton_reader::TestCommand;
+use std::borrow::Cow;
+use std::fmt::Write;
+use subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestDisasm;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "disasm");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestDisasm))
+    }
+}
+
+impl SubTest for TestDisasm {
+    fn name(&self) -> Cow<str> {
+        Cow::from("disasm")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<ir::Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("disasm needs an ISA");
+
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+        comp_ctx.compile(isa).map_err(|e| {
+            pretty_error(&comp_ctx.func, context.isa, e)
+        })?;
+
+        let mut sink = CodeRecorder::default();
+        binemit::emit_function(
+            &comp_ctx.func,
+            |func, inst, div, sink| isa.emit_inst(func, inst, div, sink),
+            &mut sink,
+        );
+
+        let text = disassemble(isa, &comp_ctx.func, &sink)?;
+        run_filecheck(&text, context)
+    }
+}
+
+/// Create a disassembler for the code generated by `isa`.
+fn disassembler(isa: &TargetIsa) -> Result<Capstone> {
+    let cs = match isa.name() {
+        "intel" => {
+            let mode = if isa.flags().is_64bit() {
+                arch::x86::ArchMode::Mode64
+            } else {
+                arch::x86::ArchMode::Mode32
+            };
+            Capstone::new().x86().mode(mode).build()
+        }
+        "arm32" => Capstone::new().arm().mode(arch::arm::ArchMode::Arm).build(),
+        "arm64" => Capstone::new().arm64().mode(arch::arm64::ArchMode::Arm).build(),
+        name => return Err(format!("disassembly is not supported for {}", name)),
+    };
+    cs.map_err(|e| format!("disassembler: {}", e))
+}
+
+/// Produce the disassembly listing of the machine code for `func` recorded in `code`.
+fn disassemble(isa: &TargetIsa, func: &ir::Function, code: &CodeRecorder) -> Result<String> {
+    let cs = disassembler(isa)?;
+    let insns = cs.disasm_all(&code.bytes, 0).map_err(
+        |e| format!("disassembler: {}", e),
+    )?;
+
+    let mut ebbs = func.layout
+        .ebbs()
+        .map(|ebb| (func.offsets[ebb], ebb))
+        .collect::<Vec<_>>();
+    ebbs.sort();
+    let mut ebbs = ebbs.into_iter().peekable();
+    let mut relocs = code.relocs.iter().peekable();
+    let mut text = String::new();
+    let mut end = 0;
+
+    for insn in insns.iter() {
+        let offset = insn.address() as binemit::CodeOffset;
+        end = offset + insn.bytes().len() as binemit::CodeOffset;
+        while let Some(&(ebb_offset, ebb)) = ebbs.peek() {
+            if ebb_offset > offset {
+                break;
+            }
+            writeln!(text, "{}:", ebb).unwrap();
+            ebbs.next();
+        }
+        write!(
+            text,
+            "{:4x}: {} {}",
+            offset,
+            insn.mnemonic().unwrap_or("?"),
+            insn.op_str().unwrap_or("")
+        ).unwrap();
+        while let Some(&&(reloc_offset, ref reloc)) = relocs.peek() {
+            if reloc_offset >= end {
+                break;
+            }
+            write!(text, " ; reloc {}", reloc).unwrap();
+            relocs.next();
+        }
+        text.push('\n');
+    }
+
+    // Capstone stops at the first byte sequence it can't decode.
+    if end as usize != code.bytes.len() {
+        return Err(format!(
+            "can't disassemble the code at offset {:#x}:\n{}",
+            end,
+            text
+        ));
+    }
+    Ok(text)
+}
+
+/// Code sink that records the machine code and a description of each relocation.
+#[derive(Default)]
+struct CodeRecorder {
+    bytes: Vec<u8>,
+    relocs: Vec<(binemit::CodeOffset, String)>,
+}
+
+impl binemit::CodeSink for CodeRecorder {
+    fn offset(&self) -> binemit::CodeOffset {
+        self.bytes.len() as binemit::CodeOffset
+    }
+
+    fn put1(&mut self, x: u8) {
+        self.bytes.push(x);
+    }
+
+    fn put2(&mut self, x: u16) {
+        for i in 0..2 {
+            self.put1((x >> (8 * i)) as u8);
+        }
+    }
+
+    fn put4(&mut self, x: u32) {
+        for i in 0..4 {
+            self.put1((x >> (8 * i)) as u8);
+        }
+    }
+
+    fn put8(&mut self, x: u64) {
+        for i in 0..8 {
+            self.put1((x >> (8 * i)) as u8);
+        }
+    }
+
+    fn reloc_ebb(&mut self, reloc: binemit::Reloc, ebb_offset: binemit::CodeOffset) {
+        let offset = self.offset();
+        self.relocs.push((offset, format!("{}({:#x})", reloc, ebb_offset)));
+    }
+
+    fn reloc_external(
+        &mut self,
+        reloc: binemit::Reloc,
+        name: &ir::ExternalName,
+        addend: binemit::Addend,
+    ) {
+        let offset = self.offset();
+        let text = if addend != 0 {
+            format!("{}({}{:+})", reloc, name, addend)
+        } else {
+            format!("{}({})", reloc, name)
+        };
+        self.relocs.push((offset, text));
+    }
+
+    fn reloc_jt(&mut self, reloc: binemit::Reloc, jt: ir::JumpTable) {
+        let offset = self.offset();
+        self.relocs.push((offset, format!("{}({})", reloc, jt)));
+    }
+}
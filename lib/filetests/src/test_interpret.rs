@@ -0,0 +1,52 @@
+//! Test command for evaluating functions with the IL interpreter.
+//!
+//! The `interpret` test command calls each function as directed by its `run:` annotations, like
+//! the `run` test command, but evaluates it with `cton_interpreter` instead of compiling it. The
+//! results don't depend on the host, and a test file with both `test run` and `test interpret`
+//! checks that the compiled code agrees with the interpreter.
+
+use cretonne::ir::Function;
+use cton_interpreter::Interpreter;
+use cton_reader::TestCommand;
+use run_directive::run_directives;
+use std::borrow::Cow;
+use subtest::{SubTest, Context, Result};
+
+/// The maximum number of instructions to execute for each directive, so that a function that
+/// doesn't terminate fails the test instead of hanging it.
+const FUEL: u64 = 10_000_000;
+
+struct TestInterpret;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "interpret");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestInterpret))
+    }
+}
+
+impl SubTest for TestInterpret {
+    fn name(&self) -> Cow<str> {
+        Cow::from("interpret")
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let invocations = run_directives(&func, context)?;
+        let mut interpreter = Interpreter::new();
+        interpreter.add_function(&func);
+
+        for (text, invocation) in invocations {
+            interpreter.set_fuel(Some(FUEL));
+            let results = interpreter.call(&func.name, &invocation.args).map_err(
+                |e| format!("run: {}: {}", text, e),
+            )?;
+            if !invocation.check(&results) {
+                let results: Vec<String> = results.iter().map(ToString::to_string).collect();
+                return Err(format!("run: {} failed, got {}", text, results.join(", ")));
+            }
+        }
+        Ok(())
+    }
+}
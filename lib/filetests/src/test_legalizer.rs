@@ -6,20 +6,19 @@
 use std::borrow::Cow;
 use cretonne;
 use cretonne::ir::Function;
+use cretonne::WriteOptions;
 use cretonne::print_errors::pretty_error;
 use cton_reader::TestCommand;
-use subtest::{SubTest, Context, Result, run_filecheck};
+use subtest::{self, SubTest, Context, Result, run_filecheck};
 use std::fmt::Write;
 
-struct TestLegalizer;
+struct TestLegalizer {
+    options: WriteOptions,
+}
 
 pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
     assert_eq!(parsed.command, "legalizer");
-    if !parsed.options.is_empty() {
-        Err(format!("No options allowed on {}", parsed))
-    } else {
-        Ok(Box::new(TestLegalizer))
-    }
+    Ok(Box::new(TestLegalizer { options: subtest::write_options(parsed)? }))
 }
 
 impl SubTest for TestLegalizer {
@@ -46,7 +45,7 @@ impl SubTest for TestLegalizer {
         })?;
 
         let mut text = String::new();
-        write!(&mut text, "{}", &comp_ctx.func.display(Some(isa)))
+        write!(&mut text, "{}", &comp_ctx.func.display_with_options(Some(isa), self.options))
             .map_err(|e| e.to_string())?;
         run_filecheck(&text, context)
     }
@@ -1,25 +1,44 @@
 //! The `print-cfg` sub-command.
 //!
 //! Read a series of Cretonne IL files and print their control flow graphs
-//! in graphviz format.
+//! in graphviz format, or as text.
 
 use std::borrow::Cow;
 
 use cretonne::ir::Function;
 use cretonne::cfg_printer::CFGPrinter;
-use cton_reader::TestCommand;
+use cton_reader::{TestCommand, TestOption};
 use subtest::{self, SubTest, Context, Result as STResult};
 
 /// Object implementing the `test print-cfg` sub-test.
-struct TestPrintCfg;
+///
+/// The `domtree`, `loops`, and `live` flags add the corresponding overlays to the graph, and the
+/// `text` flag prints the graph as text instead of graphviz.
+struct TestPrintCfg {
+    domtree: bool,
+    loops: bool,
+    live: bool,
+    text: bool,
+}
 
 pub fn subtest(parsed: &TestCommand) -> STResult<Box<SubTest>> {
     assert_eq!(parsed.command, "print-cfg");
-    if !parsed.options.is_empty() {
-        Err(format!("No options allowed on {}", parsed))
-    } else {
-        Ok(Box::new(TestPrintCfg))
+    let mut test = TestPrintCfg {
+        domtree: false,
+        loops: false,
+        live: false,
+        text: false,
+    };
+    for option in &parsed.options {
+        match *option {
+            TestOption::Flag("domtree") => test.domtree = true,
+            TestOption::Flag("loops") => test.loops = true,
+            TestOption::Flag("live") => test.live = true,
+            TestOption::Flag("text") => test.text = true,
+            _ => return Err(format!("Unknown option on {}", parsed)),
+        }
     }
+    Ok(Box::new(test))
 }
 
 impl SubTest for TestPrintCfg {
@@ -32,6 +51,22 @@ impl SubTest for TestPrintCfg {
     }
 
     fn run(&self, func: Cow<Function>, context: &Context) -> STResult<()> {
-        subtest::run_filecheck(&CFGPrinter::new(&func).to_string(), context)
+        let mut printer = CFGPrinter::new(&func);
+        if self.domtree {
+            printer.show_domtree();
+        }
+        if self.loops {
+            printer.show_loops();
+        }
+        if self.live {
+            printer.show_live_values();
+        }
+        let mut text = String::new();
+        if self.text {
+            printer.write_text(&mut text).unwrap();
+        } else {
+            printer.write(&mut text).unwrap();
+        }
+        subtest::run_filecheck(&text, context)
     }
 }
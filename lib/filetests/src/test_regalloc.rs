@@ -6,22 +6,21 @@
 //! The resulting function is sent to `filecheck`.
 
 use cretonne::ir::Function;
+use cretonne::WriteOptions;
 use cretonne;
 use cretonne::print_errors::pretty_error;
 use cton_reader::TestCommand;
-use subtest::{SubTest, Context, Result, run_filecheck};
+use subtest::{self, SubTest, Context, Result, run_filecheck};
 use std::borrow::Cow;
 use std::fmt::Write;
 
-struct TestRegalloc;
+struct TestRegalloc {
+    options: WriteOptions,
+}
 
 pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
     assert_eq!(parsed.command, "regalloc");
-    if !parsed.options.is_empty() {
-        Err(format!("No options allowed on {}", parsed))
-    } else {
-        Ok(Box::new(TestRegalloc))
-    }
+    Ok(Box::new(TestRegalloc { options: subtest::write_options(parsed)? }))
 }
 
 impl SubTest for TestRegalloc {
@@ -55,7 +54,7 @@ impl SubTest for TestRegalloc {
         })?;
 
         let mut text = String::new();
-        write!(&mut text, "{}", &comp_ctx.func.display(Some(isa)))
+        write!(&mut text, "{}", &comp_ctx.func.display_with_options(Some(isa), self.options))
             .map_err(|e| e.to_string())?;
         run_filecheck(&text, context)
     }
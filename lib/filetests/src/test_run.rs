@@ -0,0 +1,151 @@
+//! Test command for running functions natively.
+//!
+//! The `run` test command compiles each function for the host machine, JIT-compiles it into
+//! executable memory, and calls it as directed by `run:` annotations. See the `run_directive`
+//! module for their syntax.
+//!
+//! The call is made from a trampoline function that is compiled along with the tested function,
+//! so any signature with integer, boolean, and floating point parameters can be called.
+
+use cretonne::cursor::{Cursor, FuncCursor};
+use cretonne::ir::condcodes::{FloatCC, IntCC};
+use cretonne::ir::immediates::{Ieee32, Ieee64};
+use cretonne::ir::{types, AbiParam, CallConv, ExtFuncData, ExternalName, Function, InstBuilder,
+                   Signature, Value};
+use cretonne;
+use cton_interpreter::DataValue;
+use cton_reader::TestCommand;
+use cton_simplejit::SimpleJIT;
+use run_directive::{run_directives, Invocation};
+use std::borrow::Cow;
+use std::mem;
+use subtest::{SubTest, Context, Result};
+
+struct TestRun;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "run");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestRun))
+    }
+}
+
+impl SubTest for TestRun {
+    fn name(&self) -> Cow<str> {
+        Cow::from("run")
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let func = func.into_owned();
+        let invocations = run_directives(&func, context)?;
+
+        let mut jit = SimpleJIT::new().map_err(|e| e.to_string())?;
+        let mut comp_ctx = cretonne::Context::for_function(func.clone());
+        jit.compile_function(func.name.clone(), &mut comp_ctx)
+            .map_err(|e| format!("{}: {}", func.name, e))?;
+
+        let mut names = Vec::new();
+        for (idx, &(text, ref invocation)) in invocations.iter().enumerate() {
+            let name = ExternalName::testcase(format!("run{}", idx));
+            let tramp = trampoline(name.clone(), &func, invocation);
+            let mut comp_ctx = cretonne::Context::for_function(tramp);
+            jit.compile_function(name.clone(), &mut comp_ctx)
+                .map_err(|e| format!("trampoline for {}: {}", text, e))?;
+            names.push(name);
+        }
+        jit.finalize().map_err(|e| e.to_string())?;
+
+        for (name, &(text, _)) in names.iter().zip(&invocations) {
+            let code = jit.get_function(name).expect("trampoline was compiled");
+            let trampoline: extern "C" fn() -> bool = unsafe { mem::transmute(code) };
+            if !trampoline() {
+                return Err(format!("run: {} failed", text));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create a function named `name` that calls `func` as described by `invocation`.
+///
+/// The trampoline takes no arguments and returns true if the call produced the expected result.
+fn trampoline(name: ExternalName, func: &Function, invocation: &Invocation) -> Function {
+    let mut sig = Signature::new(CallConv::Native);
+    sig.returns.push(AbiParam::new(types::B1));
+    let mut tramp = Function::with_name_signature(name, sig);
+    let sigref = tramp.import_signature(func.signature.clone());
+    let callee = tramp.import_function(ExtFuncData {
+        name: func.name.clone(),
+        signature: sigref,
+    });
+
+    let ebb = tramp.dfg.make_ebb();
+    let mut pos = FuncCursor::new(&mut tramp);
+    pos.insert_ebb(ebb);
+    let args: Vec<Value> = invocation
+        .args
+        .iter()
+        .map(|&arg| constant(&mut pos, arg))
+        .collect();
+    let call = pos.ins().call(callee, &args);
+    let result = pos.func.dfg.inst_results(call).first().cloned();
+    let success = match (result, invocation.expected) {
+        (Some(result), Some((equal, DataValue::Bool(_, expected)))) => {
+            let cond = if equal {
+                IntCC::Equal
+            } else {
+                IntCC::NotEqual
+            };
+            let result = pos.ins().bint(types::I32, result);
+            pos.ins().icmp_imm(cond, result, expected as i64)
+        }
+        (Some(result), Some((equal, expected))) => {
+            let expected = constant(&mut pos, expected);
+            compare(&mut pos, equal, result, expected)
+        }
+        (Some(result), None) => result,
+        (None, _) => {
+            let zero = pos.ins().iconst(types::I32, 0);
+            pos.ins().icmp_imm(IntCC::Equal, zero, 0)
+        }
+    };
+    pos.ins().return_(&[success]);
+    tramp
+}
+
+/// Insert an instruction materializing `value`.
+///
+/// Booleans are produced by comparisons since `bconst` can't be encoded for all targets, so only
+/// `b1` constants are supported.
+pub fn constant(pos: &mut FuncCursor, value: DataValue) -> Value {
+    match value {
+        DataValue::Int(ty, _) => pos.ins().iconst(ty, value.to_i64().unwrap()),
+        DataValue::Bool(_, b) => {
+            let int = pos.ins().iconst(types::I32, b as i64);
+            pos.ins().icmp_imm(IntCC::NotEqual, int, 0)
+        }
+        DataValue::F32(x) => pos.ins().f32const(Ieee32::with_float(x)),
+        DataValue::F64(x) => pos.ins().f64const(Ieee64::with_float(x)),
+    }
+}
+
+/// Insert an instruction comparing `a` and `b` for equality, or inequality if `equal` is false.
+fn compare(pos: &mut FuncCursor, equal: bool, a: Value, b: Value) -> Value {
+    if pos.func.dfg.value_type(a).is_float() {
+        let cond = if equal {
+            FloatCC::Equal
+        } else {
+            FloatCC::NotEqual
+        };
+        pos.ins().fcmp(cond, a, b)
+    } else {
+        let cond = if equal {
+            IntCC::Equal
+        } else {
+            IntCC::NotEqual
+        };
+        pos.ins().icmp(cond, a, b)
+    }
+}
@@ -8,11 +8,23 @@
 //!
 //! This annotation means that the verifier is expected to given an error for the jump instruction
 //! containing the substring "jump to non-existent EBB".
+//!
+//! Errors on entities that can't be annotated, like values, are expected by naming the entity
+//! before the message:
+//!
+//! ```cton
+//!     v3 = iadd v1, v2 ; error: v3: value has no live range
+//! ```
+//!
+//! When the test has an ISA and the function has been register allocated, i.e., some values have
+//! locations, the value locations are verified too.
 
 use std::borrow::{Borrow, Cow};
 use cretonne::verify_function;
+use cretonne::verifier::{self, verify_allocation};
+use cretonne::ir::entities::AnyEntity;
 use cretonne::ir::Function;
-use cton_reader::TestCommand;
+use cton_reader::{SourceMap, TestCommand};
 use subtest::{SubTest, Context, Result};
 use match_directive::match_directive;
 
@@ -49,11 +61,11 @@ impl SubTest for TestVerifier {
                 if expected.is_some() {
                     return Err("cannot handle multiple error: directives".to_string());
                 }
-                expected = Some((comment.entity, tail));
+                expected = Some(parse_error(tail, comment.entity, &context.details.map)?);
             }
         }
 
-        match verify_function(func, context.flags_or_isa()) {
+        match verify(func, context) {
             Ok(_) => {
                 match expected {
                     None => Ok(()),
@@ -74,9 +86,50 @@ impl SubTest for TestVerifier {
                             ))
                         }
                     }
-                    Some(_) => Err(format!("mismatching error: {}", got)),
+                    Some((_, want_msg)) => {
+                        Err(format!("mismatching error: {}, expected: {}", got, want_msg))
+                    }
                 }
             }
         }
     }
 }
+
+/// Run the verifier on `func`, and check its value locations if it has been register allocated.
+fn verify(func: &Function, context: &Context) -> verifier::Result {
+    verify_function(func, context.flags_or_isa())?;
+    match context.isa {
+        Some(isa) if is_allocated(func) => verify_allocation(isa, func),
+        _ => Ok(()),
+    }
+}
+
+/// Have any values in `func` been assigned a location?
+fn is_allocated(func: &Function) -> bool {
+    func.locations.keys().any(|v| func.locations[v].is_assigned())
+}
+
+/// Parse the text of an `error:` directive annotating `entity`.
+///
+/// Returns the entity the error is expected on and the expected message.
+fn parse_error<'a>(
+    text: &'a str,
+    entity: AnyEntity,
+    map: &SourceMap,
+) -> Result<(AnyEntity, &'a str)> {
+    let (location, message) = match text.find(':') {
+        // A single word before the colon names the entity, like `v3: message`.
+        Some(colon) if !text[..colon].contains(char::is_whitespace) => {
+            let name = &text[..colon];
+            match map.lookup_str(name) {
+                Some(location) => (location, text[colon + 1..].trim()),
+                None => return Err(format!("error: directive on undefined entity {}", name)),
+            }
+        }
+        _ => (entity, text),
+    };
+    if message.is_empty() {
+        return Err(format!("error: directive without a message on {}", location));
+    }
+    Ok((location, message))
+}
@@ -0,0 +1,197 @@
+//! Test command for translating WebAssembly modules.
+//!
+//! The `wasm` test command applies to `.wat` and `.wasm` test files instead of Cretonne IL. The
+//! module is translated to Cretonne IL with the `DummyEnvironment` from `cton_wasm`, each function
+//! is verified, and the IL of all the functions is matched against the filecheck directives in the
+//! file. This covers the translator with the same harness as the rest of the compiler.
+//!
+//! In a `.wat` file, the test header is given in `;;` comments before the module, and filecheck
+//! directives can appear in `;;` comments anywhere:
+//!
+//! ```text
+//! ;; test wasm
+//! ;; isa intel
+//! (module
+//!   (func (param i32 i32) (result i32)
+//!     (i32.add (get_local 0) (get_local 1))))
+//! ;; check: iadd
+//! ```
+//!
+//! The directives are matched against the functions of the whole module, printed one after the
+//! other. Without a header, the file is tested with `test wasm` and no ISA. A `.wasm` file has no
+//! room for comments, so it is only translated and verified.
+//!
+//! Converting a `.wat` file to binary requires the `wat2wasm` tool from WABT. The test is disabled
+//! when it isn't installed.
+
+use cretonne::print_errors::pretty_verifier_error;
+use cretonne::settings::{Flags, FlagsOrIsa};
+use cretonne::isa::TargetIsa;
+use cretonne::verify_function;
+use cton_reader::{parse_test, IsaSpec, TestCommand};
+use cton_wasm::{translate_module, DummyEnvironment};
+use filecheck::{CheckerBuilder, Checker, NO_VARIABLES};
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::Command;
+use std::time;
+use subtest::{SubTest, Result};
+use tempdir::TempDir;
+use TestResult;
+
+/// The `wasm` test command can't appear in the header of a `.cton` file.
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "wasm");
+    Err(format!("{} only applies to .wat and .wasm files", parsed))
+}
+
+/// Is `path` a WebAssembly test file?
+pub fn is_wasm_file(path: &Path) -> bool {
+    match path.extension().and_then(OsStr::to_str) {
+        Some("wat") | Some("wasm") => true,
+        _ => false,
+    }
+}
+
+/// Run the test in the WebAssembly file at `path`.
+///
+/// If `config` is given, only check the translation for the ISA from that `isa` line.
+pub fn run(path: &Path, config: Option<usize>) -> TestResult {
+    let started = time::Instant::now();
+    let (text, data) = if path.extension() == Some(OsStr::new("wat")) {
+        let mut text = String::new();
+        fs::File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|e| e.to_string())?;
+        match wat2wasm(path)? {
+            Some(data) => (text, data),
+            None => {
+                println!("wat2wasm not found; disabled test {}", path.display());
+                return Ok(started.elapsed());
+            }
+        }
+    } else {
+        let mut data = Vec::new();
+        fs::File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|e| e.to_string())?;
+        (String::new(), data)
+    };
+
+    let header = header(&text);
+    let testfile = parse_test(&header).map_err(|e| e.to_string())?;
+    if let Some(command) = testfile.commands.iter().find(|c| {
+        c.command != "wasm" || !c.options.is_empty()
+    })
+    {
+        return Err(format!("{} is not supported for wasm files", command));
+    }
+
+    let mut builder = CheckerBuilder::new();
+    for line in text.lines().map(str::trim).filter(|l| l.starts_with(";;")) {
+        builder.directive(line).map_err(
+            |e| format!("filecheck: {}", e),
+        )?;
+    }
+    let checker = builder.finish();
+
+    match testfile.isa_spec {
+        IsaSpec::None(ref flags) => check_module(&data, flags, None, &checker)?,
+        IsaSpec::Some(ref isas) => {
+            for (idx, isa) in isas.iter().enumerate() {
+                if config.map_or(true, |c| c == idx) {
+                    check_module(&data, isa.flags(), Some(&**isa), &checker)
+                        .map_err(|e| format!("{}: {}", isa.name(), e))?;
+                }
+            }
+        }
+    }
+    Ok(started.elapsed())
+}
+
+/// Extract the test header from the leading `;;` comments of a `.wat` file.
+///
+/// Returns the `test`, `set`, and `isa` lines in the format of a `.cton` test file.
+fn header(text: &str) -> String {
+    let mut header = String::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if !line.starts_with(";;") {
+            break;
+        }
+        let line = line.trim_left_matches(';').trim();
+        if line.starts_with("test ") || line.starts_with("set ") || line.starts_with("isa ") {
+            header.push_str(line);
+            header.push('\n');
+        }
+    }
+    header
+}
+
+/// Translate the module in `data`, verify the functions, and check their IL.
+fn check_module(
+    data: &[u8],
+    flags: &Flags,
+    isa: Option<&TargetIsa>,
+    checker: &Checker,
+) -> Result<()> {
+    let mut environ = DummyEnvironment::with_flags(flags.clone());
+    translate_module(data, &mut environ).map_err(
+        |e| format!("translation failed: {}", e),
+    )?;
+
+    let mut text = String::new();
+    for func in &environ.info.function_bodies {
+        verify_function(func, FlagsOrIsa { flags, isa }).map_err(
+            |e| pretty_verifier_error(func, isa, e),
+        )?;
+        write!(text, "{}", func.display(isa)).map_err(|e| e.to_string())?;
+    }
+
+    if checker.check(&text, NO_VARIABLES).map_err(
+        |e| format!("filecheck: {}", e),
+    )?
+    {
+        Ok(())
+    } else {
+        let (_, explain) = checker.explain(&text, NO_VARIABLES).map_err(
+            |e| format!("explain: {}", e),
+        )?;
+        Err(format!("filecheck failed:\n{}{}", checker, explain))
+    }
+}
+
+/// Convert the `.wat` file at `path` to binary with `wat2wasm`.
+///
+/// Returns `None` if `wat2wasm` isn't installed.
+pub fn wat2wasm(path: &Path) -> Result<Option<Vec<u8>>> {
+    let tmp_dir = TempDir::new("cretonne-wasm").map_err(|e| e.to_string())?;
+    let file_path = tmp_dir.path().join("module.wasm");
+    match Command::new("wat2wasm").arg(path).arg("-o").arg(&file_path).output() {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("wat2wasm: {}", e)),
+        Ok(ref output) if !output.status.success() => {
+            return Err(format!(
+                "wat2wasm failed:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            ))
+        }
+        Ok(_) => {}
+    }
+    let mut data = Vec::new();
+    fs::File::open(&file_path)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .map_err(|e| e.to_string())?;
+    Ok(Some(data))
+}
+
+#[test]
+fn test_header() {
+    assert_eq!(
+        header(";; Comment\n\n;; test wasm\n;;isa intel\n(module)\n;; test cat\n"),
+        "test wasm\nisa intel\n"
+    );
+    assert_eq!(header("(module)\n"), "");
+}
@@ -0,0 +1,181 @@
+//! Test command for WebAssembly spec test scripts.
+//!
+//! The `wast` test command applies to the `.wast` scripts of the official WebAssembly test suite.
+//! Each module in a script is converted to binary with `wat2wasm`, translated to Cretonne IL,
+//! compiled for the host machine, and instantiated by the runtime in `wast_runtime`. Then the
+//! assertions of the script are checked by calling the compiled code. The expected results and
+//! traps in the scripts come from the reference interpreter, so a failed assertion means that the
+//! translator or the code generator got the semantics of WebAssembly wrong.
+//!
+//! Traps are detected without knowing their kind, so the messages of `assert_trap` aren't
+//! compared. The `assert_invalid` and `assert_malformed` commands exercise the validator and the
+//! text parser, which belong to `wasmparser` and `wat2wasm`, so they are skipped.
+//!
+//! The test is disabled when `wat2wasm` isn't installed.
+
+use cton_reader::TestCommand;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time;
+use subtest::{SubTest, Result};
+use tempdir::TempDir;
+use test_wasm::wat2wasm;
+use wast::{parse_script, Command, Module, ModuleSource, Value};
+use wast_runtime::{Failure, Runtime};
+use TestResult;
+
+/// The `wast` test command can't appear in the header of a `.cton` file.
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "wast");
+    Err(format!("{} only applies to .wast files", parsed))
+}
+
+/// Is `path` a WebAssembly spec test script?
+pub fn is_wast_file(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("wast"))
+}
+
+/// Why the commands of a script stopped being checked.
+enum Stop {
+    /// A failed assertion. The following commands are still checked.
+    Failed(String),
+    /// A failure that the following commands depend on.
+    Fatal(String),
+    /// `wat2wasm` isn't installed.
+    NoWat2Wasm,
+}
+
+/// Run the script at `path`, and report all the failed assertions.
+pub fn run(path: &Path) -> TestResult {
+    let started = time::Instant::now();
+    let mut text = String::new();
+    fs::File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|e| e.to_string())?;
+    let directives = parse_script(&text)?;
+
+    let tmp_dir = TempDir::new("cretonne-wast").map_err(|e| e.to_string())?;
+    let mut runtime = Runtime::new()?;
+    let mut failures = Vec::new();
+    for directive in &directives {
+        match check(&mut runtime, &directive.command, tmp_dir.path()) {
+            Ok(()) => {}
+            Err(Stop::Failed(msg)) => failures.push(format!("{}: {}", directive.line, msg)),
+            Err(Stop::Fatal(msg)) => {
+                failures.push(format!("{}: {}", directive.line, msg));
+                break;
+            }
+            Err(Stop::NoWat2Wasm) => {
+                println!("wat2wasm not found; disabled test {}", path.display());
+                return Ok(started.elapsed());
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(started.elapsed())
+    } else {
+        Err(failures.join("\n"))
+    }
+}
+
+/// Run a command of a script, and check its assertion.
+fn check(runtime: &mut Runtime, command: &Command, dir: &Path) -> ::std::result::Result<(), Stop> {
+    match *command {
+        Command::Module(ref module) => {
+            let data = wasm(module, dir)?;
+            runtime
+                .instantiate(&data, module.name.as_ref().map(String::as_str))
+                .map_err(|e| Stop::Fatal(format!("instantiating the module failed: {}", e)))
+        }
+        Command::Register {
+            ref name,
+            ref module,
+        } => {
+            runtime
+                .register(name, module.as_ref().map(String::as_str))
+                .map_err(Stop::Fatal)
+        }
+        Command::Action(ref action) => {
+            runtime.perform(action).map(|_| ()).map_err(|e| {
+                Stop::Failed(format!("{}: {}", action, e))
+            })
+        }
+        Command::AssertReturn(ref action, ref expected) => {
+            let results = runtime.perform(action).map_err(|e| {
+                Stop::Failed(format!("{}: {}", action, e))
+            })?;
+            if results.len() == expected.len() &&
+                expected.iter().zip(&results).all(|(e, &r)| e.matches(r))
+            {
+                Ok(())
+            } else {
+                let expected: Vec<String> = expected.iter().map(ToString::to_string).collect();
+                Err(Stop::Failed(format!(
+                    "{} returned {}, expected {}",
+                    action,
+                    list(&results),
+                    expected.join(" ")
+                )))
+            }
+        }
+        Command::AssertTrap(ref action, ref msg) |
+        Command::AssertExhaustion(ref action, ref msg) => {
+            match runtime.perform(action) {
+                Err(Failure::Trap(_)) => Ok(()),
+                Ok(results) => Err(Stop::Failed(format!(
+                    "{} returned {} instead of trapping with \"{}\"",
+                    action,
+                    list(&results),
+                    msg
+                ))),
+                Err(e) => Err(Stop::Failed(format!("{}: {}", action, e))),
+            }
+        }
+        Command::AssertModuleTrap(ref module, ref msg) => {
+            match runtime.instantiate(&wasm(module, dir)?, None) {
+                Err(Failure::Trap(_)) => Ok(()),
+                Ok(()) => Err(Stop::Failed(
+                    format!("the module was instantiated instead of trapping with \"{}\"", msg),
+                )),
+                Err(e) => Err(Stop::Failed(format!("instantiating the module failed: {}", e))),
+            }
+        }
+        Command::AssertUnlinkable(ref module, ref msg) => {
+            match runtime.instantiate(&wasm(module, dir)?, None) {
+                Err(Failure::Unlinkable(_)) => Ok(()),
+                Ok(()) => Err(Stop::Failed(
+                    format!("the module was instantiated instead of failing with \"{}\"", msg),
+                )),
+                Err(e) => Err(Stop::Failed(format!("instantiating the module failed: {}", e))),
+            }
+        }
+        Command::AssertInvalid(..) |
+        Command::AssertMalformed(..) => Ok(()),
+    }
+}
+
+/// Get the binary of `module`, converting it with `wat2wasm` if necessary.
+fn wasm(module: &Module, dir: &Path) -> ::std::result::Result<Vec<u8>, Stop> {
+    let text = match module.source {
+        ModuleSource::Binary(ref data) => return Ok(data.clone()),
+        ModuleSource::Text(ref text) |
+        ModuleSource::Quote(ref text) => text,
+    };
+    let path = dir.join("module.wat");
+    fs::File::create(&path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|e| Stop::Fatal(e.to_string()))?;
+    match wat2wasm(&path) {
+        Ok(Some(data)) => Ok(data),
+        Ok(None) => Err(Stop::NoWat2Wasm),
+        Err(e) => Err(Stop::Fatal(e)),
+    }
+}
+
+fn list(values: &[Value]) -> String {
+    let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+    values.join(" ")
+}
@@ -0,0 +1,838 @@
+//! Parser for WebAssembly spec test scripts.
+//!
+//! The official WebAssembly test suite consists of `.wast` scripts that define modules in the text
+//! format and make assertions about them, like:
+//!
+//! ```text
+//! (module
+//!   (func (export "div") (param i32 i32) (result i32)
+//!     (i32.div_s (get_local 0) (get_local 1))))
+//! (assert_return (invoke "div" (i32.const 7) (i32.const 2)) (i32.const 3))
+//! (assert_trap (invoke "div" (i32.const 1) (i32.const 0)) "integer divide by zero")
+//! ```
+//!
+//! This module only parses the script commands and the constants in them. The modules are kept as
+//! text to be converted by `wat2wasm`, or as the bytes of a `binary` module.
+
+use cretonne::ir::immediates::{Ieee32, Ieee64};
+use cretonne::ir::types;
+use cretonne::ir::Type;
+use std::cmp;
+use std::fmt;
+use std::str;
+
+/// A WebAssembly value. The floating point values are kept as bits, so NaNs can be compared.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Value {
+    I32(u32),
+    I64(u64),
+    F32(u32),
+    F64(u64),
+}
+
+impl Value {
+    /// Get the Cretonne type of the value.
+    pub fn ty(self) -> Type {
+        match self {
+            Value::I32(_) => types::I32,
+            Value::I64(_) => types::I64,
+            Value::F32(_) => types::F32,
+            Value::F64(_) => types::F64,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::I32(x) => write!(f, "(i32.const {})", x as i32),
+            Value::I64(x) => write!(f, "(i64.const {})", x as i64),
+            Value::F32(x) => write!(f, "(f32.const {})", Ieee32::with_bits(x)),
+            Value::F64(x) => write!(f, "(f64.const {})", Ieee64::with_bits(x)),
+        }
+    }
+}
+
+/// An expected result of an `assert_return` command.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Expected {
+    /// Exactly this value.
+    Value(Value),
+    /// A NaN of the given type with only the quiet bit set in the payload, and any sign.
+    CanonicalNan(Type),
+    /// A NaN of the given type with the quiet bit set, any other payload bits, and any sign.
+    ArithmeticNan(Type),
+}
+
+impl Expected {
+    /// Does the result `value` meet the expectation?
+    pub fn matches(self, value: Value) -> bool {
+        match (self, value) {
+            (Expected::Value(expected), _) => expected == value,
+            (Expected::CanonicalNan(ty), Value::F32(x)) if ty == types::F32 => {
+                x & 0x7fff_ffff == 0x7fc0_0000
+            }
+            (Expected::CanonicalNan(ty), Value::F64(x)) if ty == types::F64 => {
+                x & 0x7fff_ffff_ffff_ffff == 0x7ff8_0000_0000_0000
+            }
+            (Expected::ArithmeticNan(ty), Value::F32(x)) if ty == types::F32 => {
+                x & 0x7fc0_0000 == 0x7fc0_0000
+            }
+            (Expected::ArithmeticNan(ty), Value::F64(x)) if ty == types::F64 => {
+                x & 0x7ff8_0000_0000_0000 == 0x7ff8_0000_0000_0000
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expected::Value(value) => value.fmt(f),
+            Expected::CanonicalNan(ty) => write!(f, "({}.const nan:canonical)", ty),
+            Expected::ArithmeticNan(ty) => write!(f, "({}.const nan:arithmetic)", ty),
+        }
+    }
+}
+
+/// The source of a module in a script.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ModuleSource {
+    /// A module in the text format, like `(module (func))`.
+    Text(String),
+    /// The contents of a `binary` module.
+    Binary(Vec<u8>),
+    /// The text of a `quote` module, which is usually malformed.
+    Quote(String),
+}
+
+/// A module defined by a script.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Module {
+    /// The `$name` the script uses to refer to the module.
+    pub name: Option<String>,
+    pub source: ModuleSource,
+}
+
+/// An action performed on a module instance.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    /// Call the exported function `field` with `args`.
+    Invoke {
+        module: Option<String>,
+        field: String,
+        args: Vec<Value>,
+    },
+    /// Read the exported global `field`.
+    Get {
+        module: Option<String>,
+        field: String,
+    },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Action::Invoke {
+                ref field,
+                ref args,
+                ..
+            } => {
+                write!(f, "(invoke \"{}\"", field)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Action::Get { ref field, .. } => write!(f, "(get \"{}\")", field),
+        }
+    }
+}
+
+/// A command in a script.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Command {
+    /// Instantiate a module, which becomes the current module.
+    Module(Module),
+    /// Make the exports of a module instance available for import under `name`.
+    Register {
+        name: String,
+        module: Option<String>,
+    },
+    /// Perform an action and ignore its results, but not a trap.
+    Action(Action),
+    /// Perform an action that must return the expected results.
+    AssertReturn(Action, Vec<Expected>),
+    /// Perform an action that must trap.
+    AssertTrap(Action, String),
+    /// Instantiate a module, which must trap in its start function or its initializers.
+    AssertModuleTrap(Module, String),
+    /// Perform an action that must exhaust the call stack.
+    AssertExhaustion(Action, String),
+    /// A module that doesn't validate.
+    AssertInvalid(Module, String),
+    /// A module that can't be decoded or parsed.
+    AssertMalformed(Module, String),
+    /// A module that can't be instantiated because its imports don't match.
+    AssertUnlinkable(Module, String),
+}
+
+/// A command and the line number where it starts.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Directive {
+    pub line: usize,
+    pub command: Command,
+}
+
+/// Parse the script `text` into directives.
+pub fn parse_script(text: &str) -> Result<Vec<Directive>, String> {
+    let mut reader = Reader {
+        text,
+        pos: 0,
+        line: 1,
+    };
+    let mut directives = Vec::new();
+    while let Some(sexpr) = reader.next()? {
+        let line = match sexpr {
+            Sexpr::List { line, .. } => line,
+            _ => return Err(format!("{}: expected a command", reader.line)),
+        };
+        let command = command(&sexpr).map_err(|e| format!("{}: {}", line, e))?;
+        directives.push(Directive { line, command });
+    }
+    Ok(directives)
+}
+
+/// An S-expression.
+enum Sexpr<'a> {
+    Atom(&'a str),
+    Str(Vec<u8>),
+    List {
+        items: Vec<Sexpr<'a>>,
+        /// The source text of the whole list, including the parentheses.
+        text: &'a str,
+        line: usize,
+    },
+}
+
+impl<'a> Sexpr<'a> {
+    fn atom(&self) -> Option<&'a str> {
+        match *self {
+            Sexpr::Atom(atom) => Some(atom),
+            _ => None,
+        }
+    }
+
+    fn string(&self) -> Result<String, String> {
+        match *self {
+            Sexpr::Str(ref bytes) => {
+                String::from_utf8(bytes.clone()).map_err(|_| "invalid UTF-8 in name".to_string())
+            }
+            _ => Err("expected a string".to_string()),
+        }
+    }
+
+    fn items(&self) -> &[Sexpr<'a>] {
+        match *self {
+            Sexpr::List { ref items, .. } => items,
+            _ => &[],
+        }
+    }
+
+    /// Get the atom at the head of a list.
+    fn head(&self) -> Option<&'a str> {
+        self.items().first().and_then(Sexpr::atom)
+    }
+}
+
+/// A reader of S-expressions.
+struct Reader<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).cloned()
+    }
+
+    fn bump(&mut self) -> Option<u8> {
+        let c = self.peek();
+        if c == Some(b'\n') {
+            self.line += 1;
+        }
+        self.pos += 1;
+        c
+    }
+
+    /// Skip white space and comments.
+    fn skip(&mut self) -> Result<(), String> {
+        loop {
+            let rest = &self.text[self.pos..];
+            if rest.starts_with(";;") {
+                while self.peek().map_or(false, |c| c != b'\n') {
+                    self.bump();
+                }
+            } else if rest.starts_with("(;") {
+                let line = self.line;
+                let mut depth = 0;
+                loop {
+                    let rest = &self.text[self.pos..];
+                    if rest.starts_with("(;") {
+                        depth += 1;
+                        self.pos += 2;
+                    } else if rest.starts_with(";)") {
+                        depth -= 1;
+                        self.pos += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else if self.bump().is_none() {
+                        return Err(format!("{}: unterminated block comment", line));
+                    }
+                }
+            } else if self.peek().map_or(false, |c| c.is_ascii_whitespace()) {
+                self.bump();
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Read the next S-expression, or `None` at the end of the text.
+    fn next(&mut self) -> Result<Option<Sexpr<'a>>, String> {
+        self.skip()?;
+        let start = self.pos;
+        let line = self.line;
+        match self.peek() {
+            None => Ok(None),
+            Some(b'(') => {
+                self.bump();
+                let mut items = Vec::new();
+                loop {
+                    self.skip()?;
+                    match self.peek() {
+                        None => return Err(format!("{}: unterminated list", line)),
+                        Some(b')') => {
+                            self.bump();
+                            break;
+                        }
+                        _ => items.push(self.next()?.expect("not at the end")),
+                    }
+                }
+                Ok(Some(Sexpr::List {
+                    items,
+                    text: &self.text[start..self.pos],
+                    line,
+                }))
+            }
+            Some(b')') => Err(format!("{}: unexpected `)`", line)),
+            Some(b'"') => {
+                self.bump();
+                let mut bytes = Vec::new();
+                loop {
+                    match self.bump() {
+                        None => return Err(format!("{}: unterminated string", line)),
+                        Some(b'"') => break,
+                        Some(b'\\') => self.escape(&mut bytes)?,
+                        Some(c) => bytes.push(c),
+                    }
+                }
+                Ok(Some(Sexpr::Str(bytes)))
+            }
+            Some(_) => {
+                while self.peek().map_or(false, |c| {
+                    !c.is_ascii_whitespace() && c != b'(' && c != b')' && c != b'"' && c != b';'
+                })
+                {
+                    self.bump();
+                }
+                Ok(Some(Sexpr::Atom(&self.text[start..self.pos])))
+            }
+        }
+    }
+
+    /// Decode the escape sequence after a backslash in a string.
+    fn escape(&mut self, bytes: &mut Vec<u8>) -> Result<(), String> {
+        let line = self.line;
+        let bad = || format!("{}: invalid escape in string", line);
+        match self.bump() {
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(b'"') => bytes.push(b'"'),
+            Some(b'\'') => bytes.push(b'\''),
+            Some(b'\\') => bytes.push(b'\\'),
+            Some(b'u') => {
+                let rest = &self.text[self.pos..];
+                let end = rest.find('}').ok_or_else(&bad)?;
+                if !rest.starts_with('{') {
+                    return Err(bad());
+                }
+                let code = u32::from_str_radix(&rest[1..end], 16).map_err(|_| bad())?;
+                let c = ::std::char::from_u32(code).ok_or_else(&bad)?;
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                self.pos += end + 1;
+            }
+            Some(c) => {
+                let low = self.bump().ok_or_else(&bad)?;
+                let hex = [c, low];
+                let hex = str::from_utf8(&hex).map_err(|_| bad())?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| bad())?);
+            }
+            None => return Err(bad()),
+        }
+        Ok(())
+    }
+}
+
+/// Convert a top-level S-expression into a command.
+fn command(sexpr: &Sexpr) -> Result<Command, String> {
+    let items = sexpr.items();
+    let head = sexpr.head().ok_or("expected a command")?;
+    let message = || items.get(2).map_or(Ok(String::new()), Sexpr::string);
+    let arg = || items.get(1).ok_or(format!("missing argument to {}", head));
+    Ok(match head {
+        "module" => Command::Module(module(sexpr)?),
+        "register" => {
+            Command::Register {
+                name: arg()?.string()?,
+                module: items.get(2).and_then(Sexpr::atom).map(str::to_string),
+            }
+        }
+        "invoke" | "get" => Command::Action(action(sexpr)?),
+        "assert_return" => {
+            let expected = items[2..].iter().map(expected).collect::<Result<_, _>>()?;
+            Command::AssertReturn(action(arg()?)?, expected)
+        }
+        "assert_return_canonical_nan" | "assert_return_arithmetic_nan" => {
+            let action = action(arg()?)?;
+            let ty = action_type(&action)?;
+            let expected = if head == "assert_return_canonical_nan" {
+                Expected::CanonicalNan(ty)
+            } else {
+                Expected::ArithmeticNan(ty)
+            };
+            Command::AssertReturn(action, vec![expected])
+        }
+        "assert_trap" => {
+            if arg()?.head() == Some("module") {
+                Command::AssertModuleTrap(module(arg()?)?, message()?)
+            } else {
+                Command::AssertTrap(action(arg()?)?, message()?)
+            }
+        }
+        "assert_uninstantiable" => Command::AssertModuleTrap(module(arg()?)?, message()?),
+        "assert_exhaustion" => Command::AssertExhaustion(action(arg()?)?, message()?),
+        "assert_invalid" => Command::AssertInvalid(module(arg()?)?, message()?),
+        "assert_malformed" => Command::AssertMalformed(module(arg()?)?, message()?),
+        "assert_unlinkable" => Command::AssertUnlinkable(module(arg()?)?, message()?),
+        _ => return Err(format!("unknown command `{}`", head)),
+    })
+}
+
+/// Guess the type of the NaN expected from `action` by the old NaN assertions, which don't say.
+///
+/// The type is taken from the name of the function, or the type of its first argument.
+fn action_type(action: &Action) -> Result<Type, String> {
+    if let Action::Invoke {
+        ref field,
+        ref args,
+        ..
+    } = *action
+    {
+        if field.starts_with("f32") {
+            return Ok(types::F32);
+        } else if field.starts_with("f64") {
+            return Ok(types::F64);
+        } else if let Some(arg) = args.first() {
+            if arg.ty().is_float() {
+                return Ok(arg.ty());
+            }
+        }
+    }
+    Err(format!("can't tell the type of NaN returned by {}", action))
+}
+
+fn module(sexpr: &Sexpr) -> Result<Module, String> {
+    let (items, text) = match *sexpr {
+        Sexpr::List { ref items, text, .. } if sexpr.head() == Some("module") => (items, text),
+        _ => return Err("expected a module".to_string()),
+    };
+    let mut rest = &items[1..];
+    let mut name = None;
+    if let Some(id) = rest.first().and_then(Sexpr::atom) {
+        if id.starts_with('$') {
+            name = Some(id.to_string());
+            rest = &rest[1..];
+        }
+    }
+    let strings = |parts: &[Sexpr]| -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        for part in parts {
+            match *part {
+                Sexpr::Str(ref s) => bytes.extend_from_slice(s),
+                _ => return Err("expected a string in the module".to_string()),
+            }
+        }
+        Ok(bytes)
+    };
+    let source = match rest.first().and_then(Sexpr::atom) {
+        Some("binary") => ModuleSource::Binary(strings(&rest[1..])?),
+        Some("quote") => {
+            let quoted = String::from_utf8_lossy(&strings(&rest[1..])?).into_owned();
+            ModuleSource::Quote(format!("(module {})", quoted))
+        }
+        _ => ModuleSource::Text(text.to_string()),
+    };
+    Ok(Module { name, source })
+}
+
+fn action(sexpr: &Sexpr) -> Result<Action, String> {
+    let items = sexpr.items();
+    let mut rest = items.get(1..).unwrap_or(&[]);
+    let mut module = None;
+    if let Some(id) = rest.first().and_then(Sexpr::atom) {
+        module = Some(id.to_string());
+        rest = &rest[1..];
+    }
+    let field = rest.first().ok_or("missing export name")?.string()?;
+    match sexpr.head() {
+        Some("invoke") => {
+            Ok(Action::Invoke {
+                module,
+                field,
+                args: rest[1..].iter().map(constant).collect::<Result<_, _>>()?,
+            })
+        }
+        Some("get") => Ok(Action::Get { module, field }),
+        _ => Err("expected an action".to_string()),
+    }
+}
+
+fn constant(sexpr: &Sexpr) -> Result<Value, String> {
+    match expected(sexpr)? {
+        Expected::Value(value) => Ok(value),
+        _ => Err("expected a constant".to_string()),
+    }
+}
+
+fn expected(sexpr: &Sexpr) -> Result<Expected, String> {
+    let items = sexpr.items();
+    let literal = match items.get(1).and_then(Sexpr::atom) {
+        Some(literal) if items.len() == 2 => literal,
+        _ => return Err("expected a constant".to_string()),
+    };
+    let ty = match sexpr.head() {
+        Some("i32.const") => types::I32,
+        Some("i64.const") => types::I64,
+        Some("f32.const") => types::F32,
+        Some("f64.const") => types::F64,
+        _ => return Err("expected a constant".to_string()),
+    };
+    if literal == "nan:canonical" {
+        return Ok(Expected::CanonicalNan(ty));
+    } else if literal == "nan:arithmetic" {
+        return Ok(Expected::ArithmeticNan(ty));
+    }
+    let bad = |_| format!("invalid {} literal `{}`", ty, literal);
+    Ok(Expected::Value(match ty {
+        types::I32 => Value::I32(parse_int(literal, 32).map_err(&bad)? as u32),
+        types::I64 => Value::I64(parse_int(literal, 64).map_err(&bad)?),
+        types::F32 => Value::F32(parse_float(literal, 8, 23).map_err(&bad)? as u32),
+        _ => Value::F64(parse_float(literal, 11, 52).map_err(&bad)?),
+    }))
+}
+
+/// Parse an integer literal of `bits` bits. Both signed and unsigned literals are accepted.
+fn parse_int(text: &str, bits: u32) -> Result<u64, ()> {
+    let (negative, digits) = split_sign(text);
+    let digits = digits.replace('_', "");
+    let magnitude = if digits.starts_with("0x") {
+        u64::from_str_radix(&digits[2..], 16)
+    } else {
+        digits.parse()
+    }.map_err(|_| ())?;
+    let mask = !0u64 >> (64 - bits);
+    if negative {
+        if magnitude > 1 << (bits - 1) {
+            return Err(());
+        }
+        Ok(magnitude.wrapping_neg() & mask)
+    } else if magnitude > mask {
+        Err(())
+    } else {
+        Ok(magnitude)
+    }
+}
+
+/// Parse a floating point literal with `w` exponent bits and `t` trailing significand bits.
+///
+/// Unlike Cretonne IL, WebAssembly allows decimal and inexact hexadecimal literals, which are
+/// rounded to the nearest representable value.
+fn parse_float(text: &str, w: u32, t: u32) -> Result<u64, ()> {
+    let (negative, body) = split_sign(text);
+    let body = body.replace('_', "");
+    let sign = if negative { 1 << (w + t) } else { 0 };
+    let exponent_mask = ((1 << w) - 1) << t;
+    let quiet = 1 << (t - 1);
+    let magnitude = if body == "inf" {
+        exponent_mask
+    } else if body == "nan" {
+        exponent_mask | quiet
+    } else if body.starts_with("nan:0x") {
+        match u64::from_str_radix(&body[6..], 16) {
+            Ok(payload) if payload != 0 && payload >> t == 0 => exponent_mask | payload,
+            _ => return Err(()),
+        }
+    } else if body.starts_with("0x") {
+        parse_hex_float(&body[2..], w, t)?
+    } else if t == 23 {
+        body.parse::<f32>().map(|x| u64::from(x.to_bits())).map_err(
+            |_| (),
+        )?
+    } else {
+        body.parse::<f64>().map(f64::to_bits).map_err(|_| ())?
+    };
+    Ok(sign | magnitude)
+}
+
+/// Parse the digits of a positive hexadecimal floating point literal, rounding to nearest even.
+fn parse_hex_float(text: &str, w: u32, t: u32) -> Result<u64, ()> {
+    let (mantissa, mut exponent) = match text.find(|c| c == 'p' || c == 'P') {
+        Some(p) => (&text[..p], text[p + 1..].parse::<i32>().map_err(|_| ())?),
+        None => (text, 0),
+    };
+
+    // Collect the leading 60 or more bits of the significand, and remember if any of the digits
+    // that didn't fit were non-zero.
+    let mut significand = 0u64;
+    let mut sticky = false;
+    let mut point = false;
+    let mut digits = 0;
+    for c in mantissa.chars() {
+        if c == '.' && !point {
+            point = true;
+            continue;
+        }
+        let digit = u64::from(c.to_digit(16).ok_or(())?);
+        digits += 1;
+        if significand >> 60 == 0 {
+            significand = significand << 4 | digit;
+            if point {
+                exponent -= 4;
+            }
+        } else {
+            sticky |= digit != 0;
+            if !point {
+                exponent += 4;
+            }
+        }
+    }
+    if digits == 0 {
+        return Err(());
+    }
+    if significand == 0 {
+        return Ok(0);
+    }
+
+    // The value is `significand * 2^exponent`. Find the exponent of the least significant bit
+    // that can be represented, which is fixed for subnormal numbers.
+    let t = t as i32;
+    let bias = (1 << (w - 1)) - 1;
+    let width = 64 - significand.leading_zeros() as i32;
+    let mut lsb = cmp::max(exponent + width - 1 - t, 1 - bias - t);
+    let shift = lsb - exponent;
+    let mut m = if shift <= 0 {
+        significand << -shift
+    } else {
+        let kept = if shift >= 64 { 0 } else { significand >> shift };
+        let (half, rest) = if shift > 64 {
+            (false, true)
+        } else {
+            let half = 1u64 << (shift - 1);
+            let dropped = if shift == 64 {
+                significand
+            } else {
+                significand & ((1 << shift) - 1)
+            };
+            (dropped & half != 0, dropped & (half - 1) != 0 || sticky)
+        };
+        if half && (rest || kept & 1 != 0) {
+            kept + 1
+        } else {
+            kept
+        }
+    };
+
+    // Rounding up may carry into a new bit.
+    if m >> (t + 1) != 0 {
+        m >>= 1;
+        lsb += 1;
+    }
+    if m >> t == 0 {
+        // A subnormal number or zero.
+        return Ok(m);
+    }
+    let biased = lsb + t + bias;
+    if biased >= (1 << w) - 1 {
+        return Err(());
+    }
+    Ok((biased as u64) << t | (m & ((1 << t) - 1)))
+}
+
+fn split_sign(text: &str) -> (bool, &str) {
+    if text.starts_with('-') {
+        (true, &text[1..])
+    } else if text.starts_with('+') {
+        (false, &text[1..])
+    } else {
+        (false, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers() {
+        assert_eq!(parse_int("0", 32), Ok(0));
+        assert_eq!(parse_int("-1", 32), Ok(0xffff_ffff));
+        assert_eq!(parse_int("0xffff_ffff", 32), Ok(0xffff_ffff));
+        assert_eq!(parse_int("-0x8000_0000", 32), Ok(0x8000_0000));
+        assert_eq!(parse_int("0x1_0000_0000", 32), Err(()));
+        assert_eq!(parse_int("-0x8000_0001", 32), Err(()));
+        assert_eq!(parse_int("-9223372036854775808", 64), Ok(1 << 63));
+        assert_eq!(parse_int("18446744073709551615", 64), Ok(!0));
+        assert_eq!(parse_int("+7", 64), Ok(7));
+        assert_eq!(parse_int("", 64), Err(()));
+    }
+
+    #[test]
+    fn floats() {
+        let f32 = |text| parse_float(text, 8, 23).map(|x| x as u32);
+        let f64 = |text| parse_float(text, 11, 52);
+        assert_eq!(f32("1.5"), Ok(1.5f32.to_bits()));
+        assert_eq!(f32("-0x1.8p1"), Ok((-3.0f32).to_bits()));
+        assert_eq!(f32("0x1p-149"), Ok(1));
+        assert_eq!(f32("0x1p-150"), Ok(0));
+        assert_eq!(f32("0x1.8p-149"), Ok(2));
+        assert_eq!(f32("0x1.fffffep127"), Ok(0x7f7f_ffff));
+        assert_eq!(f32("0x1.ffffffp127"), Err(()));
+        assert_eq!(f32("0x1.000001p0"), Ok(0x3f80_0000));
+        assert_eq!(f32("0x1.000001000000000000001p0"), Ok(0x3f80_0001));
+        assert_eq!(f32("0x1.000003p0"), Ok(0x3f80_0002));
+        assert_eq!(f32("0x0.00000100000000000p-126"), Ok(0));
+        assert_eq!(f32("0x1P+2"), Ok(4.0f32.to_bits()));
+        assert_eq!(f32("inf"), Ok(0x7f80_0000));
+        assert_eq!(f32("-nan"), Ok(0xffc0_0000));
+        assert_eq!(f32("nan:0x200000"), Ok(0x7fa0_0000));
+        assert_eq!(f32("nan:0x0"), Err(()));
+        assert_eq!(f64("1e1_0"), Ok(1e10f64.to_bits()));
+        assert_eq!(f64("0x1.fffffffffffff8p0"), Ok(2.0f64.to_bits()));
+        assert_eq!(f64("0x1p-1074"), Ok(1));
+        assert_eq!(f64("0x123456789abcdef0123p0"), Ok(0x4472_3456_789a_bcdf));
+        assert_eq!(f64("0x0.0"), Ok(0));
+        assert_eq!(f64("-0x0p0"), Ok(1 << 63));
+    }
+
+    #[test]
+    fn nans() {
+        let canonical = Expected::CanonicalNan(types::F32);
+        assert!(canonical.matches(Value::F32(0xffc0_0000)));
+        assert!(!canonical.matches(Value::F32(0x7fc0_0001)));
+        assert!(Expected::ArithmeticNan(types::F32).matches(Value::F32(0x7fc0_0001)));
+        assert!(!canonical.matches(Value::F64(0x7ff8_0000_0000_0000)));
+    }
+
+    #[test]
+    fn script() {
+        let text = r#"
+            ;; A comment (; with a nested block comment ;)
+            (module $M (func (export "f") (param i32) (result i32) (get_local 0)))
+            (; a block
+               comment ;)
+            (register "m" $M)
+            (assert_return (invoke $M "f" (i32.const -1)) (i32.const 0xffff_ffff))
+            (assert_return (invoke "g") (f32.const nan:canonical))
+            (assert_return_arithmetic_nan (invoke "f64.add" (f64.const nan)))
+            (assert_trap (invoke "trap") "unreachable")
+            (assert_trap (module (func unreachable) (start 0)) "unreachable")
+            (assert_malformed (module binary "\00asm" "\01\00\00\00\u{41}") "bad")
+            (assert_invalid (module quote "(func)") "invalid")
+            (get "g\74")
+        "#;
+        let directives = parse_script(text).unwrap();
+        assert_eq!(directives.len(), 10);
+        assert_eq!(directives[0].line, 3);
+        assert_eq!(
+            directives[0].command,
+            Command::Module(Module {
+                name: Some("$M".to_string()),
+                source: ModuleSource::Text(
+                    r#"(module $M (func (export "f") (param i32) (result i32) (get_local 0)))"#
+                        .to_string(),
+                ),
+            })
+        );
+        assert_eq!(directives[1].line, 6);
+        assert_eq!(
+            directives[1].command,
+            Command::Register {
+                name: "m".to_string(),
+                module: Some("$M".to_string()),
+            }
+        );
+        assert_eq!(
+            directives[2].command,
+            Command::AssertReturn(
+                Action::Invoke {
+                    module: Some("$M".to_string()),
+                    field: "f".to_string(),
+                    args: vec![Value::I32(0xffff_ffff)],
+                },
+                vec![Expected::Value(Value::I32(0xffff_ffff))],
+            )
+        );
+        match directives[4].command {
+            Command::AssertReturn(_, ref expected) => {
+                assert_eq!(expected[..], [Expected::ArithmeticNan(types::F64)]);
+            }
+            ref command => panic!("unexpected {:?}", command),
+        }
+        match directives[6].command {
+            Command::AssertModuleTrap(..) => {}
+            ref command => panic!("unexpected {:?}", command),
+        }
+        match directives[7].command {
+            Command::AssertMalformed(ref module, _) => {
+                assert_eq!(module.source, ModuleSource::Binary(b"\0asm\x01\0\0\0A".to_vec()));
+            }
+            ref command => panic!("unexpected {:?}", command),
+        }
+        match directives[8].command {
+            Command::AssertInvalid(ref module, _) => {
+                assert_eq!(module.source, ModuleSource::Quote("(module (func))".to_string()));
+            }
+            ref command => panic!("unexpected {:?}", command),
+        }
+        assert_eq!(
+            directives[9].command,
+            Command::Action(Action::Get {
+                module: None,
+                field: "gt".to_string(),
+            })
+        );
+
+        assert_eq!(parse_script("(module").unwrap_err(), "1: unterminated list");
+        assert_eq!(
+            parse_script("\n(assert_return (invoke \"f\" (i32.const 1.5)))").unwrap_err(),
+            "2: invalid i32 literal `1.5`"
+        );
+        assert_eq!(parse_script("(frob)").unwrap_err(), "1: unknown command `frob`");
+    }
+}
@@ -0,0 +1,1322 @@
+//! Runtime for the modules of WebAssembly spec test scripts.
+//!
+//! The modules are translated with `cton_wasm`, compiled for the host machine with
+//! `cton_simplejit`, and instantiated in a minimal embedding of WebAssembly. The linear memories,
+//! tables, and globals are stored in memory described by the `vmctx` of each instance:
+//!
+//! - Word 0 points to the linear memory descriptor `[base, bound, maximum pages]`.
+//! - Word 1 points to the table descriptor `[base, length]`. Each table entry is three words:
+//!   the code address of the function, its `vmctx`, and the id of its signature, which
+//!   `call_indirect` compares with the expected one.
+//! - Then come pointers to the 8-byte cells of the globals, and the code address and `vmctx` of
+//!   each imported function.
+//!
+//! Every call into compiled code runs in a child process, so a trap or a stack overflow kills the
+//! child instead of the test runner, without installing any signal handlers. All the state of the
+//! instances is in shared memory mappings, so the effects of a call are visible afterwards.
+
+use cretonne::Context;
+use cretonne::cursor::{Cursor, FuncCursor};
+use cretonne::ir::{self, types, AbiParam, ArgumentPurpose, CallConv, ExtFuncData, ExternalName,
+                   Function, InstBuilder, MemFlags, Signature, TrapCode, Type};
+use cretonne::ir::condcodes::IntCC;
+use cretonne::settings::Flags;
+use cton_simplejit::SimpleJIT;
+use cton_wasm::{translate_module, FuncEnvironment, FuncTranslator, FunctionIndex, Global,
+                GlobalIndex, GlobalInit, GlobalValue, Memory, MemoryIndex, ModuleEnvironment,
+                SignatureIndex, Table, TableIndex};
+use libc;
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::os::unix::process::ExitStatusExt;
+use std::process;
+use std::ptr;
+use wast::{Action, Value};
+
+/// The size of a WebAssembly page.
+const PAGE_SIZE: usize = 0x1_0000;
+
+/// The maximum number of pages of a linear memory. The bound of the memory must fit in the `i32`
+/// offsets of the heap, and the whole memory is reserved up front.
+const MAX_PAGES: usize = 0x4000;
+
+/// The size of the inaccessible region after the bound of a linear memory. The translator only
+/// checks accesses against the bound to a multiple of this size, and relies on the guard region to
+/// catch the rest.
+const GUARD_SIZE: usize = PAGE_SIZE;
+
+/// The maximum number of elements of a table.
+const MAX_ELEMENTS: usize = 0x10_0000;
+
+/// The number of 8-byte slots for the arguments and results of a call.
+const SLOTS: usize = 256;
+
+/// The number of seconds a call may run before it is considered stuck.
+const CALL_TIMEOUT: u32 = 10;
+
+/// The size of a word in the `vmctx` and the descriptors.
+const WORD: usize = mem::size_of::<usize>();
+
+/// The `vmctx` words pointing to the descriptors of the linear memory and the table.
+const MEMORY_WORD: usize = 0;
+const TABLE_WORD: usize = 1;
+
+/// Why a module couldn't be instantiated, or an action failed.
+#[derive(Debug)]
+pub enum Failure {
+    /// The imports of the module don't match the exports they refer to, or a segment doesn't fit.
+    Unlinkable(String),
+    /// The compiled code trapped or overflowed the stack.
+    Trap(String),
+    /// Anything else, which is a problem with the test or with Cretonne.
+    Error(String),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Failure::Unlinkable(ref msg) => write!(f, "unlinkable: {}", msg),
+            Failure::Trap(ref msg) => write!(f, "trapped: {}", msg),
+            Failure::Error(ref msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(msg: String) -> Self {
+        Failure::Error(msg)
+    }
+}
+
+/// A mapping of anonymous memory shared with the child processes running the calls.
+pub struct Region {
+    pub ptr: *mut u8,
+    len: usize,
+}
+
+impl Region {
+    fn new(len: usize, prot: libc::c_int) -> Result<Self, String> {
+        let len = cmp::max(len, 1);
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                prot,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(format!("mmap failed: {}", io::Error::last_os_error()));
+        }
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    /// Allocate a readable and writable region of `count` words.
+    pub fn words(count: usize) -> Result<Self, String> {
+        Self::new(count * WORD, libc::PROT_READ | libc::PROT_WRITE)
+    }
+
+    fn word(&self, index: usize) -> *mut usize {
+        debug_assert!((index + 1) * WORD <= self.len);
+        unsafe { (self.ptr as *mut usize).offset(index as isize) }
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+/// Make `len` bytes at `ptr` readable and writable. This is async-signal-safe.
+fn unprotect(ptr: *mut u8, len: usize) -> bool {
+    let prot = libc::PROT_READ | libc::PROT_WRITE;
+    len == 0 || unsafe { libc::mprotect(ptr as *mut libc::c_void, len, prot) == 0 }
+}
+
+/// A linear memory. All of its maximum size is reserved, but only the pages below the bound are
+/// accessible.
+struct LinearMemory {
+    /// The descriptor `[base, bound, maximum pages]` read by the compiled code.
+    desc: Region,
+    data: Region,
+    /// The maximum number of pages declared by the module.
+    maximum: Option<usize>,
+    /// The number of bytes made accessible in this process.
+    accessible: usize,
+}
+
+impl LinearMemory {
+    fn new(pages: usize, maximum: Option<usize>) -> Result<Self, String> {
+        let limit = cmp::min(maximum.unwrap_or(MAX_PAGES), MAX_PAGES);
+        if pages > limit {
+            return Err(format!("a memory of {} pages is too large", pages));
+        }
+        let data = Region::new(limit * PAGE_SIZE + GUARD_SIZE, libc::PROT_NONE)?;
+        let desc = Region::words(3)?;
+        unsafe {
+            *desc.word(0) = data.ptr as usize;
+            *desc.word(1) = pages * PAGE_SIZE;
+            *desc.word(2) = limit;
+        }
+        let mut memory = Self {
+            desc,
+            data,
+            maximum,
+            accessible: 0,
+        };
+        memory.sync()?;
+        Ok(memory)
+    }
+
+    fn bound(&self) -> usize {
+        unsafe { *self.desc.word(1) }
+    }
+
+    /// Make the pages added by `grow_memory` in a child process accessible in this one too.
+    fn sync(&mut self) -> Result<(), String> {
+        let bound = self.bound();
+        if bound > self.accessible {
+            let start = unsafe { self.data.ptr.offset(self.accessible as isize) };
+            if !unprotect(start, bound - self.accessible) {
+                return Err(format!("mprotect failed: {}", io::Error::last_os_error()));
+            }
+            self.accessible = bound;
+        }
+        Ok(())
+    }
+}
+
+/// The implementation of `grow_memory`, called by the compiled code.
+///
+/// This runs in the child process, so it must only use async-signal-safe functions.
+extern "C" fn grow_memory(delta: u32, vmctx: *const usize) -> u32 {
+    unsafe {
+        let desc = *vmctx.offset(MEMORY_WORD as isize) as *mut usize;
+        let base = *desc as *mut u8;
+        let bound = *desc.offset(1);
+        let old = bound / PAGE_SIZE;
+        let delta = delta as usize;
+        if delta > *desc.offset(2) - old ||
+            !unprotect(base.offset(bound as isize), delta * PAGE_SIZE)
+        {
+            return !0;
+        }
+        *desc.offset(1) = bound + delta * PAGE_SIZE;
+        old as u32
+    }
+}
+
+/// The `print` functions of the `spectest` module, which do nothing.
+extern "C" fn print() {}
+
+// The `print` functions of the `spectest` module that print their arguments like the reference
+// interpreter. They allocate, so they are only used when the runner isn't multithreaded.
+
+extern "C" fn print_i32(x: i32) {
+    println!("{} : i32", x);
+}
+
+extern "C" fn print_i64(x: i64) {
+    println!("{} : i64", x);
+}
+
+extern "C" fn print_f32(x: f32) {
+    println!("{} : f32", x);
+}
+
+extern "C" fn print_f64(x: f64) {
+    println!("{} : f64", x);
+}
+
+extern "C" fn print_i32_f32(x: i32, y: f32) {
+    print_i32(x);
+    print_f32(y);
+}
+
+extern "C" fn print_f64_f64(x: f64, y: f64) {
+    print_f64(x);
+    print_f64(y);
+}
+
+/// The host functions printing a value of each type, by name.
+pub const PRINT_FUNCTIONS: [(&str, &[Type]); 7] = [
+    ("print", &[]),
+    ("print_i32", &[types::I32]),
+    ("print_i64", &[types::I64]),
+    ("print_f32", &[types::F32]),
+    ("print_f64", &[types::F64]),
+    ("print_i32_f32", &[types::I32, types::F32]),
+    ("print_f64_f64", &[types::F64, types::F64]),
+];
+
+/// Get the address of the host function printing its arguments for the `PRINT_FUNCTIONS` entry
+/// `name`.
+pub fn print_function(name: &str) -> *const u8 {
+    match name {
+        "print_i32" => print_i32 as *const u8,
+        "print_i64" => print_i64 as *const u8,
+        "print_f32" => print_f32 as *const u8,
+        "print_f64" => print_f64 as *const u8,
+        "print_i32_f32" => print_i32_f32 as *const u8,
+        "print_f64_f64" => print_f64_f64 as *const u8,
+        _ => print as *const u8,
+    }
+}
+
+/// A table of functions.
+struct TableData {
+    /// The descriptor `[base, length]` read by the compiled code.
+    desc: Region,
+    elements: Region,
+    /// The maximum number of elements declared by the module.
+    maximum: Option<usize>,
+}
+
+impl TableData {
+    fn new(size: usize, maximum: Option<usize>) -> Result<Self, String> {
+        if size > MAX_ELEMENTS {
+            return Err(format!("a table of {} elements is too large", size));
+        }
+        let elements = Region::words(3 * size)?;
+        let desc = Region::words(2)?;
+        unsafe {
+            *desc.word(0) = elements.ptr as usize;
+            *desc.word(1) = size;
+        }
+        Ok(Self {
+            desc,
+            elements,
+            maximum,
+        })
+    }
+
+    fn len(&self) -> usize {
+        unsafe { *self.desc.word(1) }
+    }
+
+    fn set(&self, index: usize, func: Func) {
+        assert!(index < self.len());
+        unsafe {
+            *self.elements.word(3 * index) = func.code as usize;
+            *self.elements.word(3 * index + 1) = func.vmctx as usize;
+            *self.elements.word(3 * index + 2) = func.sig;
+        }
+    }
+}
+
+/// A function that can be called, imported, or stored in a table.
+#[derive(Clone, Copy)]
+struct Func {
+    code: *const u8,
+    vmctx: *mut usize,
+    /// The id of the signature in `Runtime::signatures`.
+    sig: usize,
+}
+
+/// Something exported by an instance.
+#[derive(Clone, Copy)]
+enum Extern {
+    Func(Func),
+    Global {
+        cell: *mut u64,
+        ty: Type,
+        mutable: bool,
+    },
+    /// A linear memory in `Runtime::memories`.
+    Memory(usize),
+    /// A table in `Runtime::tables`.
+    Table(usize),
+}
+
+/// An index in one of the index spaces of a module.
+#[derive(Clone, Copy)]
+enum Index {
+    Func(FunctionIndex),
+    Global(GlobalIndex),
+    Memory(MemoryIndex),
+    Table(TableIndex),
+}
+
+/// An import of a module.
+struct Import<'data> {
+    module: &'data str,
+    field: &'data str,
+    index: Index,
+}
+
+/// An element segment.
+struct Elements {
+    table: TableIndex,
+    base: Option<GlobalIndex>,
+    offset: usize,
+    elements: Vec<FunctionIndex>,
+}
+
+/// A data segment.
+struct Data<'data> {
+    memory: MemoryIndex,
+    base: Option<GlobalIndex>,
+    offset: usize,
+    data: &'data [u8],
+}
+
+/// Everything declared by a module, as provided by `translate_module`.
+struct ModuleInfo<'data> {
+    flags: Flags,
+    /// The namespace of the names of the functions.
+    instance: u32,
+    signatures: Vec<Signature>,
+    /// The ids of the signatures in `Runtime::signatures`.
+    sig_ids: Vec<usize>,
+    imports: Vec<Import<'data>>,
+    functions: Vec<SignatureIndex>,
+    num_func_imports: usize,
+    bodies: Vec<&'data [u8]>,
+    globals: Vec<Global>,
+    tables: Vec<Table>,
+    memories: Vec<Memory>,
+    elements: Vec<Elements>,
+    data: Vec<Data<'data>>,
+    exports: Vec<(&'data str, Index)>,
+    start: Option<FunctionIndex>,
+}
+
+impl<'data> ModuleInfo<'data> {
+    fn new(flags: Flags, instance: u32) -> Self {
+        Self {
+            flags,
+            instance,
+            signatures: Vec::new(),
+            sig_ids: Vec::new(),
+            imports: Vec::new(),
+            functions: Vec::new(),
+            num_func_imports: 0,
+            bodies: Vec::new(),
+            globals: Vec::new(),
+            tables: Vec::new(),
+            memories: Vec::new(),
+            elements: Vec::new(),
+            data: Vec::new(),
+            exports: Vec::new(),
+            start: None,
+        }
+    }
+
+    fn pointer_type(&self) -> Type {
+        if self.flags.is_64bit() {
+            types::I64
+        } else {
+            types::I32
+        }
+    }
+
+    /// Get the signature `index` with the `vmctx` parameter added.
+    fn vmctx_sig(&self, index: SignatureIndex) -> Signature {
+        let mut sig = self.signatures[index].clone();
+        sig.params.push(AbiParam::special(
+            self.pointer_type(),
+            ArgumentPurpose::VMContext,
+        ));
+        sig
+    }
+
+    /// Get the `vmctx` word pointing to the cell of a global.
+    fn global_word(&self, index: GlobalIndex) -> usize {
+        2 + index
+    }
+
+    /// Get the first of the two `vmctx` words of an imported function.
+    fn func_word(&self, index: FunctionIndex) -> usize {
+        debug_assert!(index < self.num_func_imports);
+        2 + self.globals.len() + 2 * index
+    }
+
+    fn vmctx_words(&self) -> usize {
+        2 + self.globals.len() + 2 * self.num_func_imports
+    }
+
+    fn import(&mut self, module: &'data str, field: &'data str, index: Index) {
+        self.imports.push(Import {
+            module,
+            field,
+            index,
+        });
+    }
+}
+
+impl<'data> ModuleEnvironment<'data> for ModuleInfo<'data> {
+    fn get_func_name(&self, func_index: FunctionIndex) -> ExternalName {
+        ExternalName::user(self.instance, func_index as u32)
+    }
+
+    fn declare_signature(&mut self, sig: &Signature) {
+        self.signatures.push(sig.clone());
+    }
+
+    fn get_signature(&self, sig_index: SignatureIndex) -> &Signature {
+        &self.signatures[sig_index]
+    }
+
+    fn declare_func_import(
+        &mut self,
+        sig_index: SignatureIndex,
+        module: &'data str,
+        field: &'data str,
+    ) {
+        let index = Index::Func(self.functions.len());
+        self.import(module, field, index);
+        self.functions.push(sig_index);
+        self.num_func_imports += 1;
+    }
+
+    fn get_num_func_imports(&self) -> usize {
+        self.num_func_imports
+    }
+
+    fn declare_func_type(&mut self, sig_index: SignatureIndex) {
+        self.functions.push(sig_index);
+    }
+
+    fn get_func_type(&self, func_index: FunctionIndex) -> SignatureIndex {
+        self.functions[func_index]
+    }
+
+    fn declare_global(&mut self, global: Global) {
+        self.globals.push(global);
+    }
+
+    fn declare_global_import(&mut self, global: Global, module: &'data str, field: &'data str) {
+        let index = Index::Global(self.globals.len());
+        self.import(module, field, index);
+        self.globals.push(global);
+    }
+
+    fn get_global(&self, global_index: GlobalIndex) -> &Global {
+        &self.globals[global_index]
+    }
+
+    fn declare_table(&mut self, table: Table) {
+        self.tables.push(table);
+    }
+
+    fn declare_table_import(&mut self, table: Table, module: &'data str, field: &'data str) {
+        let index = Index::Table(self.tables.len());
+        self.import(module, field, index);
+        self.tables.push(table);
+    }
+
+    fn declare_table_elements(
+        &mut self,
+        table: TableIndex,
+        base: Option<GlobalIndex>,
+        offset: usize,
+        elements: Vec<FunctionIndex>,
+    ) {
+        self.elements.push(Elements {
+            table,
+            base,
+            offset,
+            elements,
+        });
+    }
+
+    fn declare_memory(&mut self, memory: Memory) {
+        self.memories.push(memory);
+    }
+
+    fn declare_memory_import(&mut self, memory: Memory, module: &'data str, field: &'data str) {
+        let index = Index::Memory(self.memories.len());
+        self.import(module, field, index);
+        self.memories.push(memory);
+    }
+
+    fn declare_data_initialization(
+        &mut self,
+        memory: MemoryIndex,
+        base: Option<GlobalIndex>,
+        offset: usize,
+        data: &'data [u8],
+    ) {
+        self.data.push(Data {
+            memory,
+            base,
+            offset,
+            data,
+        });
+    }
+
+    fn declare_func_export(&mut self, func_index: FunctionIndex, name: &'data str) {
+        self.exports.push((name, Index::Func(func_index)));
+    }
+
+    fn declare_table_export(&mut self, table_index: TableIndex, name: &'data str) {
+        self.exports.push((name, Index::Table(table_index)));
+    }
+
+    fn declare_memory_export(&mut self, memory_index: MemoryIndex, name: &'data str) {
+        self.exports.push((name, Index::Memory(memory_index)));
+    }
+
+    fn declare_global_export(&mut self, global_index: GlobalIndex, name: &'data str) {
+        self.exports.push((name, Index::Global(global_index)));
+    }
+
+    fn declare_start_func(&mut self, index: FunctionIndex) {
+        debug_assert!(self.start.is_none());
+        self.start = Some(index);
+    }
+
+    fn define_function_body(&mut self, body_bytes: &'data [u8]) -> Result<(), String> {
+        self.bodies.push(body_bytes);
+        Ok(())
+    }
+}
+
+/// The `FuncEnvironment` for translating the functions of a module.
+struct FuncEnv<'a, 'data: 'a> {
+    info: &'a ModuleInfo<'data>,
+}
+
+/// Get the byte offset of the word `index`.
+fn offset(index: usize) -> i32 {
+    (index * WORD) as i32
+}
+
+/// Get the `vmctx` parameter of the function at `pos`.
+fn vmctx(pos: &FuncCursor) -> ir::Value {
+    pos.func.special_param(ArgumentPurpose::VMContext).expect(
+        "Missing vmctx parameter",
+    )
+}
+
+impl<'a, 'data> FuncEnvironment for FuncEnv<'a, 'data> {
+    fn flags(&self) -> &Flags {
+        &self.info.flags
+    }
+
+    fn make_global(&mut self, func: &mut Function, index: GlobalIndex) -> GlobalValue {
+        let offset = offset(self.info.global_word(index)).into();
+        let cell = func.create_global_var(ir::GlobalVarData::VmCtx { offset });
+        let gv = func.create_global_var(ir::GlobalVarData::Deref {
+            base: cell,
+            offset: 0.into(),
+        });
+        GlobalValue::Memory {
+            gv,
+            ty: self.info.globals[index].ty,
+        }
+    }
+
+    fn make_heap(&mut self, func: &mut Function, _index: MemoryIndex) -> ir::Heap {
+        let desc = func.create_global_var(ir::GlobalVarData::VmCtx {
+            offset: offset(MEMORY_WORD).into(),
+        });
+        let base = func.create_global_var(ir::GlobalVarData::Deref {
+            base: desc,
+            offset: offset(0).into(),
+        });
+        let bound_gv = func.create_global_var(ir::GlobalVarData::Deref {
+            base: desc,
+            offset: offset(1).into(),
+        });
+        func.create_heap(ir::HeapData {
+            base: ir::HeapBase::GlobalVar(base),
+            min_size: 0.into(),
+            guard_size: (GUARD_SIZE as i64).into(),
+            style: ir::HeapStyle::Dynamic { bound_gv },
+        })
+    }
+
+    fn make_indirect_sig(&mut self, func: &mut Function, index: SignatureIndex) -> ir::SigRef {
+        func.import_signature(self.info.vmctx_sig(index))
+    }
+
+    fn make_direct_func(&mut self, func: &mut Function, index: FunctionIndex) -> ir::FuncRef {
+        let signature = func.import_signature(self.info.vmctx_sig(self.info.functions[index]));
+        let name = self.info.get_func_name(index);
+        func.import_function(ExtFuncData { name, signature })
+    }
+
+    fn translate_call_indirect(
+        &mut self,
+        mut pos: FuncCursor,
+        _table_index: TableIndex,
+        sig_index: SignatureIndex,
+        sig_ref: ir::SigRef,
+        callee: ir::Value,
+        call_args: &[ir::Value],
+    ) -> ir::Inst {
+        let ptr = self.native_pointer();
+        let flags = MemFlags::new();
+        let vmctx = vmctx(&pos);
+        let table = pos.ins().load(ptr, flags, vmctx, offset(TABLE_WORD));
+
+        let len = pos.ins().load(ptr, flags, table, offset(1));
+        let index = if ptr == types::I32 {
+            callee
+        } else {
+            pos.ins().uextend(ptr, callee)
+        };
+        let oob = pos.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, index, len);
+        pos.ins().trapnz(oob, TrapCode::OutOfBounds);
+
+        let base = pos.ins().load(ptr, flags, table, offset(0));
+        let entry_offset = pos.ins().imul_imm(index, 3 * WORD as i64);
+        let entry = pos.ins().iadd(base, entry_offset);
+        let code = pos.ins().load(ptr, flags, entry, offset(0));
+        pos.ins().trapz(code, TrapCode::IndirectCallToNull);
+        let sig = pos.ins().load(ptr, flags, entry, offset(2));
+        let sig_id = self.info.sig_ids[sig_index] as i64;
+        let bad_sig = pos.ins().icmp_imm(IntCC::NotEqual, sig, sig_id);
+        pos.ins().trapnz(bad_sig, TrapCode::BadSignature);
+
+        let callee_vmctx = pos.ins().load(ptr, flags, entry, offset(1));
+        let mut args = call_args.to_vec();
+        args.push(callee_vmctx);
+        pos.ins().call_indirect(sig_ref, code, &args)
+    }
+
+    fn translate_call(
+        &mut self,
+        mut pos: FuncCursor,
+        callee_index: FunctionIndex,
+        callee: ir::FuncRef,
+        call_args: &[ir::Value],
+    ) -> ir::Inst {
+        let vmctx = vmctx(&pos);
+        let mut args = call_args.to_vec();
+        if callee_index < self.info.num_func_imports {
+            // Imported functions are called through their `vmctx` words.
+            let ptr = self.native_pointer();
+            let word = self.info.func_word(callee_index);
+            let code = pos.ins().load(ptr, MemFlags::new(), vmctx, offset(word));
+            let callee_vmctx = pos.ins().load(ptr, MemFlags::new(), vmctx, offset(word + 1));
+            args.push(callee_vmctx);
+            let sig_ref = pos.func.dfg.ext_funcs[callee].signature;
+            pos.ins().call_indirect(sig_ref, code, &args)
+        } else {
+            args.push(vmctx);
+            pos.ins().call(callee, &args)
+        }
+    }
+
+    fn translate_grow_memory(
+        &mut self,
+        mut pos: FuncCursor,
+        _index: MemoryIndex,
+        _heap: ir::Heap,
+        val: ir::Value,
+    ) -> ir::Value {
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.params.push(AbiParam::special(
+            self.native_pointer(),
+            ArgumentPurpose::VMContext,
+        ));
+        sig.returns.push(AbiParam::new(types::I32));
+        let signature = pos.func.import_signature(sig);
+        let grow = pos.func.import_function(ExtFuncData {
+            name: ExternalName::testcase("grow_memory"),
+            signature,
+        });
+        let vmctx = vmctx(&pos);
+        let call = pos.ins().call(grow, &[val, vmctx]);
+        pos.func.dfg.first_result(call)
+    }
+
+    fn translate_current_memory(
+        &mut self,
+        mut pos: FuncCursor,
+        _index: MemoryIndex,
+        _heap: ir::Heap,
+    ) -> ir::Value {
+        let ptr = self.native_pointer();
+        let vmctx = vmctx(&pos);
+        let desc = pos.ins().load(ptr, MemFlags::new(), vmctx, offset(MEMORY_WORD));
+        let bound = pos.ins().load(ptr, MemFlags::new(), desc, offset(1));
+        let pages = pos.ins().ushr_imm(bound, PAGE_SIZE.trailing_zeros() as i64);
+        if ptr == types::I32 {
+            pages
+        } else {
+            pos.ins().ireduce(types::I32, pages)
+        }
+    }
+}
+
+/// An instance of a module.
+struct Instance {
+    exports: HashMap<String, Extern>,
+    /// Does the module have a start function?
+    has_start: bool,
+    /// The memory referenced by the compiled code and the exports.
+    _vmctx: Region,
+    _globals: Region,
+}
+
+/// The instances of the modules of a script, and the code compiled for them.
+pub struct Runtime {
+    jit: SimpleJIT,
+    flags: Flags,
+    /// The distinct signatures of all the modules. Their indices are the signature ids checked by
+    /// `call_indirect`.
+    signatures: Vec<Signature>,
+    memories: Vec<LinearMemory>,
+    tables: Vec<TableData>,
+    /// All the instances, including the ones that failed to instantiate: their functions may have
+    /// been stored in the tables of other instances.
+    instances: Vec<Instance>,
+    /// The instances with a `$name`.
+    names: HashMap<String, usize>,
+    /// The last instance, which is used by actions that don't name one.
+    current: Option<usize>,
+    /// The exports available for import, by module name.
+    registered: HashMap<String, HashMap<String, Extern>>,
+    /// The trampolines for calling each signature id.
+    trampolines: HashMap<usize, *const u8>,
+    /// The arguments and results of calls.
+    slots: Region,
+    /// The globals of the `spectest` module.
+    spectest_globals: Region,
+}
+
+impl Runtime {
+    /// Create a runtime with the `spectest` module registered.
+    pub fn new() -> Result<Self, String> {
+        Self::create(false)
+    }
+
+    /// Create a runtime whose `spectest` print functions print their arguments.
+    ///
+    /// The printing allocates in the child process running the call, so this is only safe in a
+    /// single-threaded program like `cton-util run`.
+    pub fn with_printing() -> Result<Self, String> {
+        Self::create(true)
+    }
+
+    fn create(printing: bool) -> Result<Self, String> {
+        let mut jit = SimpleJIT::new().map_err(|e| e.to_string())?;
+        jit.symbol(
+            ExternalName::testcase("grow_memory"),
+            grow_memory as *const u8,
+        );
+        let flags = jit.isa().flags().clone();
+        let mut runtime = Self {
+            jit,
+            flags,
+            signatures: Vec::new(),
+            memories: Vec::new(),
+            tables: Vec::new(),
+            instances: Vec::new(),
+            names: HashMap::new(),
+            current: None,
+            registered: HashMap::new(),
+            trampolines: HashMap::new(),
+            slots: Region::words(SLOTS * 8 / WORD)?,
+            spectest_globals: Region::words(4 * 8 / WORD)?,
+        };
+        runtime.spectest(printing)?;
+        Ok(runtime)
+    }
+
+    /// Register the `spectest` module provided to the scripts by the reference interpreter.
+    fn spectest(&mut self, printing: bool) -> Result<(), String> {
+        let mut exports = HashMap::new();
+        for &(name, params) in &PRINT_FUNCTIONS {
+            let mut sig = Signature::new(CallConv::Native);
+            sig.params = params.iter().map(|&ty| AbiParam::new(ty)).collect();
+            let func = Func {
+                code: if printing {
+                    print_function(name)
+                } else {
+                    print as *const u8
+                },
+                vmctx: ptr::null_mut(),
+                sig: self.intern(&sig),
+            };
+            exports.insert(name.to_string(), Extern::Func(func));
+        }
+
+        let cells = self.spectest_globals.ptr as *mut u64;
+        let globals = [
+            ("global_i32", types::I32, 666),
+            ("global_i64", types::I64, 666),
+            ("global_f32", types::F32, u64::from(666.6f32.to_bits())),
+            ("global_f64", types::F64, 666.6f64.to_bits()),
+        ];
+        for (i, &(name, ty, bits)) in globals.iter().enumerate() {
+            let cell = unsafe { cells.offset(i as isize) };
+            unsafe { *cell = bits };
+            let global = Extern::Global {
+                cell,
+                ty,
+                mutable: false,
+            };
+            exports.insert(name.to_string(), global);
+        }
+
+        self.tables.push(TableData::new(10, Some(20))?);
+        exports.insert("table".to_string(), Extern::Table(self.tables.len() - 1));
+        self.memories.push(LinearMemory::new(1, Some(2))?);
+        exports.insert("memory".to_string(), Extern::Memory(self.memories.len() - 1));
+        self.registered.insert("spectest".to_string(), exports);
+        Ok(())
+    }
+
+    /// Get the id of `sig`.
+    fn intern(&mut self, sig: &Signature) -> usize {
+        match self.signatures.iter().position(|s| s == sig) {
+            Some(id) => id,
+            None => {
+                self.signatures.push(sig.clone());
+                self.signatures.len() - 1
+            }
+        }
+    }
+
+    /// Instantiate the module in `data`, and make it the current module.
+    pub fn instantiate(&mut self, data: &[u8], name: Option<&str>) -> Result<(), Failure> {
+        let mut info = ModuleInfo::new(self.flags.clone(), self.instances.len() as u32);
+        translate_module(data, &mut info).map_err(|e| {
+            Failure::Error(format!("translation failed: {}", e))
+        })?;
+        info.sig_ids = info.signatures.iter().map(|sig| self.intern(sig)).collect();
+
+        // Resolve the imports.
+        let mut funcs = Vec::new();
+        let mut cells = Vec::new();
+        let mut memory = None;
+        let mut table = None;
+        for import in &info.imports {
+            let export = self.registered.get(import.module).and_then(|exports| {
+                exports.get(import.field).cloned()
+            });
+            let export = export.ok_or_else(|| {
+                Failure::Unlinkable(format!("unknown import {}.{}", import.module, import.field))
+            })?;
+            let compatible = match (import.index, export) {
+                (Index::Func(index), Extern::Func(func)) => {
+                    funcs.push(func);
+                    func.sig == info.sig_ids[info.functions[index]]
+                }
+                (Index::Global(index), Extern::Global { cell, ty, mutable }) => {
+                    cells.push(cell);
+                    let global = &info.globals[index];
+                    ty == global.ty && mutable == global.mutability
+                }
+                (Index::Memory(index), Extern::Memory(id)) => {
+                    memory = Some(id);
+                    let declared = &info.memories[index];
+                    let imported = &self.memories[id];
+                    imported.bound() / PAGE_SIZE >= declared.pages_count &&
+                        fits(imported.maximum, declared.maximum)
+                }
+                (Index::Table(index), Extern::Table(id)) => {
+                    table = Some(id);
+                    let declared = &info.tables[index];
+                    let imported = &self.tables[id];
+                    imported.len() >= declared.size && fits(imported.maximum, declared.maximum)
+                }
+                _ => false,
+            };
+            if !compatible {
+                return Err(Failure::Unlinkable(format!(
+                    "incompatible import type for {}.{}",
+                    import.module,
+                    import.field
+                )));
+            }
+        }
+
+        // Compile the functions.
+        let mut trans = FuncTranslator::new();
+        for (i, body) in info.bodies.iter().enumerate() {
+            let index = info.num_func_imports + i;
+            let name = info.get_func_name(index);
+            let sig = info.vmctx_sig(info.functions[index]);
+            let mut func = Function::with_name_signature(name.clone(), sig);
+            trans
+                .translate(body, &mut func, &mut FuncEnv { info: &info })
+                .map_err(|e| format!("translating function {}: {}", index, e))?;
+            let mut ctx = Context::for_function(func);
+            self.jit.compile_function(name, &mut ctx).map_err(|e| {
+                format!("compiling function {}: {}", index, e)
+            })?;
+        }
+        self.jit.finalize().map_err(|e| e.to_string())?;
+
+        // Allocate the memory, table, and globals defined by the module.
+        if memory.is_none() && !info.memories.is_empty() {
+            let declared = &info.memories[0];
+            self.memories.push(
+                LinearMemory::new(declared.pages_count, declared.maximum)?,
+            );
+            memory = Some(self.memories.len() - 1);
+        }
+        if table.is_none() && !info.tables.is_empty() {
+            let declared = &info.tables[0];
+            self.tables.push(TableData::new(declared.size, declared.maximum)?);
+            table = Some(self.tables.len() - 1);
+        }
+        let globals = Region::words(info.globals.len() * 8 / WORD)?;
+        for (index, global) in info.globals.iter().enumerate().skip(cells.len()) {
+            let bits = match global.initializer {
+                GlobalInit::I32Const(x) => u64::from(x as u32),
+                GlobalInit::I64Const(x) => x as u64,
+                GlobalInit::F32Const(x) => u64::from(x),
+                GlobalInit::F64Const(x) => x,
+                GlobalInit::GlobalRef(other) => unsafe { *cells[other] },
+                GlobalInit::Import() => panic!("global {} is imported", index),
+            };
+            let cell = unsafe { (globals.ptr as *mut u64).offset(index as isize) };
+            unsafe { *cell = bits };
+            cells.push(cell);
+        }
+
+        // Fill in the `vmctx`.
+        let vmctx = Region::words(info.vmctx_words())?;
+        unsafe {
+            *vmctx.word(MEMORY_WORD) = memory.map_or(0, |id| self.memories[id].desc.ptr as usize);
+            *vmctx.word(TABLE_WORD) = table.map_or(0, |id| self.tables[id].desc.ptr as usize);
+            for (index, &cell) in cells.iter().enumerate() {
+                *vmctx.word(info.global_word(index)) = cell as usize;
+            }
+            for (index, func) in funcs.iter().enumerate() {
+                *vmctx.word(info.func_word(index)) = func.code as usize;
+                *vmctx.word(info.func_word(index) + 1) = func.vmctx as usize;
+            }
+        }
+        for index in info.num_func_imports..info.functions.len() {
+            let name = info.get_func_name(index);
+            funcs.push(Func {
+                code: self.jit.get_function(&name).expect("function was compiled"),
+                vmctx: vmctx.word(0),
+                sig: info.sig_ids[info.functions[index]],
+            });
+        }
+
+        // Check that the segments fit before initializing any of them.
+        let start = |base: Option<GlobalIndex>, offset: usize| {
+            base.map_or(0, |global| unsafe { *cells[global] as u32 as usize }) + offset
+        };
+        for segment in &info.elements {
+            let end = start(segment.base, segment.offset) + segment.elements.len();
+            if table.map_or(0, |id| self.tables[id].len()) < end {
+                return Err(Failure::Unlinkable("elements segment does not fit".to_string()));
+            }
+        }
+        for segment in &info.data {
+            let end = start(segment.base, segment.offset) + segment.data.len();
+            if memory.map_or(0, |id| self.memories[id].bound()) < end {
+                return Err(Failure::Unlinkable("data segment does not fit".to_string()));
+            }
+        }
+        for segment in &info.elements {
+            debug_assert_eq!(segment.table, 0);
+            let table = &self.tables[table.expect("checked above")];
+            let start = start(segment.base, segment.offset);
+            for (i, &index) in segment.elements.iter().enumerate() {
+                table.set(start + i, funcs[index]);
+            }
+        }
+        for segment in &info.data {
+            debug_assert_eq!(segment.memory, 0);
+            let memory = &self.memories[memory.expect("checked above")];
+            let start = start(segment.base, segment.offset);
+            unsafe {
+                let dst = memory.data.ptr.offset(start as isize);
+                ptr::copy_nonoverlapping(segment.data.as_ptr(), dst, segment.data.len());
+            }
+        }
+
+        let mut exports = HashMap::new();
+        for &(field, index) in &info.exports {
+            let export = match index {
+                Index::Func(index) => Extern::Func(funcs[index]),
+                Index::Global(index) => Extern::Global {
+                    cell: cells[index],
+                    ty: info.globals[index].ty,
+                    mutable: info.globals[index].mutability,
+                },
+                Index::Memory(_) => Extern::Memory(memory.expect("exported memory")),
+                Index::Table(_) => Extern::Table(table.expect("exported table")),
+            };
+            exports.insert(field.to_string(), export);
+        }
+        self.instances.push(Instance {
+            exports,
+            has_start: info.start.is_some(),
+            _vmctx: vmctx,
+            _globals: globals,
+        });
+
+        if let Some(index) = info.start {
+            self.call(funcs[index], &[])?;
+        }
+        let id = self.instances.len() - 1;
+        if let Some(name) = name {
+            self.names.insert(name.to_string(), id);
+        }
+        self.current = Some(id);
+        Ok(())
+    }
+
+    /// Make the exports of the module `module`, or the current one, available for import under
+    /// `name`.
+    pub fn register(&mut self, name: &str, module: Option<&str>) -> Result<(), String> {
+        let id = self.instance(module)?;
+        let exports = self.instances[id].exports.clone();
+        self.registered.insert(name.to_string(), exports);
+        Ok(())
+    }
+
+    /// Perform `action`, and return its results.
+    pub fn perform(&mut self, action: &Action) -> Result<Vec<Value>, Failure> {
+        match *action {
+            Action::Invoke {
+                ref module,
+                ref field,
+                ref args,
+            } => {
+                match self.export(module, field)? {
+                    Extern::Func(func) => self.call(func, args),
+                    _ => Err(Failure::Error(format!("{} is not a function", field))),
+                }
+            }
+            Action::Get {
+                ref module,
+                ref field,
+            } => {
+                match self.export(module, field)? {
+                    Extern::Global { cell, ty, .. } => Ok(vec![value(ty, unsafe { *cell })]),
+                    _ => Err(Failure::Error(format!("{} is not a global", field))),
+                }
+            }
+        }
+    }
+
+    /// Get the signature of the function exported as `field` by the current module.
+    pub fn export_signature(&self, field: &str) -> Result<Signature, String> {
+        match self.export(&None, field)? {
+            Extern::Func(func) => Ok(self.signatures[func.sig].clone()),
+            _ => Err(format!("{} is not a function", field)),
+        }
+    }
+
+    /// Does the current module have a start function?
+    pub fn has_start(&self) -> bool {
+        self.current.map_or(false, |id| self.instances[id].has_start)
+    }
+
+    /// Get the names of the functions exported by the current module, sorted.
+    pub fn exported_functions(&self) -> Vec<String> {
+        let mut names: Vec<String> = match self.current {
+            Some(id) => {
+                self.instances[id]
+                    .exports
+                    .iter()
+                    .filter(|&(_, export)| match *export {
+                        Extern::Func(_) => true,
+                        _ => false,
+                    })
+                    .map(|(name, _)| name.clone())
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        names.sort();
+        names
+    }
+
+    fn instance(&self, name: Option<&str>) -> Result<usize, String> {
+        match name {
+            Some(name) => {
+                self.names.get(name).cloned().ok_or_else(
+                    || format!("unknown module {}", name),
+                )
+            }
+            None => self.current.ok_or_else(|| "no module".to_string()),
+        }
+    }
+
+    fn export(&self, module: &Option<String>, field: &str) -> Result<Extern, String> {
+        let id = self.instance(module.as_ref().map(String::as_str))?;
+        self.instances[id].exports.get(field).cloned().ok_or_else(
+            || {
+                format!("unknown export {}", field)
+            },
+        )
+    }
+
+    /// Call `func` with `args` in a child process.
+    fn call(&mut self, func: Func, args: &[Value]) -> Result<Vec<Value>, Failure> {
+        let sig = self.signatures[func.sig].clone();
+        if args.len() != sig.params.len() ||
+            args.iter().zip(&sig.params).any(
+                |(arg, param)| arg.ty() != param.value_type,
+            )
+        {
+            return Err(Failure::Error(format!("the arguments don't match {}", sig)));
+        }
+        if cmp::max(sig.params.len(), sig.returns.len()) > SLOTS {
+            return Err(Failure::Error(format!("too many values in {}", sig)));
+        }
+        let trampoline = self.trampoline(func.sig)?;
+        let trampoline: extern "C" fn(*const u8, *mut usize, *mut u64) =
+            unsafe { mem::transmute(trampoline) };
+
+        let slots = self.slots.ptr as *mut u64;
+        for (i, &arg) in args.iter().enumerate() {
+            unsafe { *slots.offset(i as isize) = bits(arg) };
+        }
+        let result = call_in_child(|| trampoline(func.code, func.vmctx, slots));
+        for memory in &mut self.memories {
+            memory.sync()?;
+        }
+        result?;
+        Ok(
+            sig.returns
+                .iter()
+                .enumerate()
+                .map(|(i, ret)| {
+                    value(ret.value_type, unsafe { *slots.offset(i as isize) })
+                })
+                .collect(),
+        )
+    }
+
+    /// Get a function that calls code with the signature `sig`, with the arguments loaded from the
+    /// 8-byte slots pointed to by its last parameter, and stores the results in the same slots.
+    fn trampoline(&mut self, sig: usize) -> Result<*const u8, String> {
+        if let Some(&code) = self.trampolines.get(&sig) {
+            return Ok(code);
+        }
+        let ptr = if self.flags.is_64bit() {
+            types::I64
+        } else {
+            types::I32
+        };
+        let mut callee_sig = self.signatures[sig].clone();
+        callee_sig.params.push(
+            AbiParam::special(ptr, ArgumentPurpose::VMContext),
+        );
+        let mut tramp_sig = Signature::new(CallConv::Native);
+        tramp_sig.params = vec![AbiParam::new(ptr); 3];
+        let name = ExternalName::testcase(format!("trampoline{}", sig));
+        let mut func = Function::with_name_signature(name.clone(), tramp_sig);
+        let sig_ref = func.import_signature(callee_sig.clone());
+
+        let ebb = func.dfg.make_ebb();
+        let code = func.dfg.append_ebb_param(ebb, ptr);
+        let vmctx = func.dfg.append_ebb_param(ebb, ptr);
+        let slots = func.dfg.append_ebb_param(ebb, ptr);
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb);
+        let mut args = Vec::new();
+        for (i, param) in self.signatures[sig].params.iter().enumerate() {
+            let arg = pos.ins().load(param.value_type, MemFlags::new(), slots, 8 * i as i32);
+            args.push(arg);
+        }
+        args.push(vmctx);
+        let call = pos.ins().call_indirect(sig_ref, code, &args);
+        let results = pos.func.dfg.inst_results(call).to_vec();
+        for (i, result) in results.into_iter().enumerate() {
+            pos.ins().store(MemFlags::new(), result, slots, 8 * i as i32);
+        }
+        pos.ins().return_(&[]);
+
+        let mut ctx = Context::for_function(func);
+        self.jit.compile_function(name.clone(), &mut ctx).map_err(
+            |e| {
+                format!("compiling the trampoline for {}: {}", callee_sig, e)
+            },
+        )?;
+        self.jit.finalize().map_err(|e| e.to_string())?;
+        let code = self.jit.get_function(&name).expect("trampoline was compiled");
+        self.trampolines.insert(sig, code);
+        Ok(code)
+    }
+}
+
+/// Does a memory or table with `maximum` fit the limit `declared` by an import?
+fn fits(maximum: Option<usize>, declared: Option<usize>) -> bool {
+    match (maximum, declared) {
+        (_, None) => true,
+        (Some(maximum), Some(declared)) => maximum <= declared,
+        (None, Some(_)) => false,
+    }
+}
+
+fn bits(value: Value) -> u64 {
+    match value {
+        Value::I32(x) | Value::F32(x) => u64::from(x),
+        Value::I64(x) | Value::F64(x) => x,
+    }
+}
+
+fn value(ty: Type, bits: u64) -> Value {
+    match ty {
+        types::I32 => Value::I32(bits as u32),
+        types::I64 => Value::I64(bits),
+        types::F32 => Value::F32(bits as u32),
+        _ => Value::F64(bits),
+    }
+}
+
+/// Run `f` in a child process, and report how it died if it didn't return.
+///
+/// The test runner is multithreaded, so only async-signal-safe functions may be called in the
+/// child, and `f` must not allocate.
+pub fn call_in_child<F: FnOnce()>(f: F) -> Result<(), Failure> {
+    let pid = unsafe { libc::fork() };
+    if pid == 0 {
+        unsafe {
+            // Die from the signals instead of running the handlers of the runner, and don't leave
+            // core dumps behind.
+            libc::signal(libc::SIGSEGV, libc::SIG_DFL);
+            libc::signal(libc::SIGBUS, libc::SIG_DFL);
+            let limit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            libc::setrlimit(libc::RLIMIT_CORE, &limit);
+            libc::alarm(CALL_TIMEOUT);
+            f();
+            libc::_exit(0);
+        }
+    } else if pid < 0 {
+        return Err(Failure::Error(
+            format!("fork failed: {}", io::Error::last_os_error()),
+        ));
+    }
+
+    let mut status = 0;
+    loop {
+        if unsafe { libc::waitpid(pid, &mut status, 0) } == pid {
+            break;
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(Failure::Error(format!("waitpid failed: {}", error)));
+        }
+    }
+    let status = process::ExitStatus::from_raw(status);
+    match status.signal() {
+        None if status.success() => Ok(()),
+        None => Err(Failure::Error(format!("the call failed with {}", status))),
+        Some(libc::SIGALRM) => Err(Failure::Error(
+            format!("the call timed out after {} seconds", CALL_TIMEOUT),
+        )),
+        Some(signal) => Err(Failure::Trap(format!("killed by signal {}", signal))),
+    }
+}
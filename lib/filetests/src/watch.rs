@@ -0,0 +1,136 @@
+//! Watch mode for the test runner.
+//!
+//! In watch mode, the whole test suite is run once, and then the test directories are polled for
+//! changes. Test files that are modified or added are run again as soon as they are saved.
+//!
+//! The running program can't pick up changes to the compiler itself, so when its executable is
+//! rebuilt, it restarts itself with the same arguments to run the whole suite with the new code.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use TestResult;
+use test_wasm;
+use test_wast;
+
+/// How often to check for changes, in milliseconds.
+const POLL_INTERVAL: u64 = 500;
+
+/// Run the tests in `paths`, and then rerun any test files that change.
+///
+/// The `run` callback runs the tests in a list of files and directories. This function only
+/// returns if restarting after a rebuild fails.
+pub fn watch<F>(paths: &[String], mut run: F) -> TestResult
+where
+    F: FnMut(Vec<String>) -> TestResult,
+{
+    let exe = env::current_exe().ok();
+    let exe_mtime = exe.as_ref().and_then(|exe| modified(exe));
+    // The new modification time of the executable, once it has been rebuilt.
+    let mut rebuilt = None;
+
+    // Failures have already been printed, so the results don't matter here.
+    let _ = run(paths.to_vec());
+    let mut files = scan(paths);
+    println!("Watching for changes...");
+
+    loop {
+        thread::sleep(Duration::from_millis(POLL_INTERVAL));
+
+        if let Some(ref exe) = exe {
+            let mtime = modified(exe);
+            // Wait for the modification time to settle before restarting, in case the linker is
+            // still writing the executable.
+            if mtime.is_some() && mtime != exe_mtime {
+                if mtime == rebuilt {
+                    println!("{} was rebuilt, restarting", exe.display());
+                    return restart(exe);
+                }
+                rebuilt = mtime;
+            }
+        }
+
+        let new_files = scan(paths);
+        let mut changed = new_files
+            .iter()
+            .filter(|&(path, mtime)| files.get(path) != Some(mtime))
+            .map(|(path, _)| path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        files = new_files;
+        if !changed.is_empty() {
+            changed.sort();
+            let _ = run(changed);
+            println!("Watching for changes...");
+        }
+    }
+}
+
+/// Find the test files in `paths` along with their modification times.
+fn scan(paths: &[String]) -> HashMap<PathBuf, SystemTime> {
+    let mut files = HashMap::new();
+    let mut dirs = Vec::new();
+    for path in paths.iter().map(PathBuf::from) {
+        if path.is_file() {
+            if let Some(mtime) = modified(&path) {
+                files.insert(path, mtime);
+            }
+        } else {
+            dirs.push(path);
+        }
+    }
+
+    // Errors are ignored here. They are reported when the tests are run.
+    while let Some(dir) = dirs.pop() {
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(ty) if ty.is_dir() => dirs.push(path),
+                Ok(_) if is_test_file(&path) => {
+                    if let Some(mtime) = modified(&path) {
+                        files.insert(path, mtime);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    files
+}
+
+/// Is `path` a test file, judging by its extension?
+fn is_test_file(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("cton")) || test_wasm::is_wasm_file(path) ||
+        test_wast::is_wast_file(path)
+}
+
+/// Get the modification time of the file at `path`.
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|md| md.modified()).ok()
+}
+
+/// Replace the current process with a new run of `exe` with the same arguments.
+#[cfg(unix)]
+fn restart(exe: &Path) -> TestResult {
+    use std::os::unix::process::CommandExt;
+    let err = Command::new(exe).args(env::args_os().skip(1)).exec();
+    Err(format!("{}: {}", exe.display(), err))
+}
+
+/// Run `exe` with the same arguments as the current process, and wait for it to exit.
+#[cfg(not(unix))]
+fn restart(exe: &Path) -> TestResult {
+    match Command::new(exe).args(env::args_os().skip(1)).status() {
+        Ok(status) if status.success() => Ok(Duration::default()),
+        Ok(status) => Err(format!("{}: {}", exe.display(), status)),
+        Err(err) => Err(format!("{}: {}", exe.display(), err)),
+    }
+}
@@ -0,0 +1,258 @@
+//! Structured control flow helpers.
+//!
+//! Frontends for structured languages repeatedly build the same shapes of control flow graphs.
+//! Building them by hand is error-prone, because each `Ebb` must be sealed exactly when all its
+//! predecessors are known: sealing a loop header before its back edges are inserted is a classic
+//! frontend bug. The helpers in this module create the `Ebb`s of a construct, seal them at the
+//! right time, and hand closures a builder positioned in the right block.
+
+use cretonne::entity::EntityRef;
+use cretonne::ir::{Ebb, InstBuilder, Type, Value};
+use frontend::FunctionBuilder;
+
+/// The `Ebb`s of a loop built with `FunctionBuilder::build_loop`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoopBlocks {
+    /// The loop header, which decides whether to run the body or leave the loop. Jumping here
+    /// from the body starts the next iteration.
+    pub header: Ebb,
+    /// The first block of the loop body.
+    pub body: Ebb,
+    /// The block following the loop. Jumping here from the body leaves the loop.
+    pub exit: Ebb,
+}
+
+impl<'a, Variable> FunctionBuilder<'a, Variable>
+where
+    Variable: EntityRef,
+{
+    /// Build a loop at the current position.
+    ///
+    /// The current block jumps to a new loop header, and `header` is called with the builder
+    /// positioned in it. It must fill the header with branches to `body` or `exit`. Then `body`
+    /// is called with the builder positioned in the body block. The body may jump to `header` to
+    /// start the next iteration, and to `exit` to leave the loop; if its last block isn't filled,
+    /// it jumps back to the header.
+    ///
+    /// The header is sealed once all the back edges are known, and the body and exit blocks as
+    /// soon as their predecessors are. The builder is left positioned in the exit block, whose
+    /// `Ebb` is also returned in the `LoopBlocks`.
+    pub fn build_loop<H, B>(&mut self, header: H, body: B) -> LoopBlocks
+    where
+        H: FnOnce(&mut Self, LoopBlocks),
+        B: FnOnce(&mut Self, LoopBlocks),
+    {
+        let blocks = LoopBlocks {
+            header: self.create_ebb(),
+            body: self.create_ebb(),
+            exit: self.create_ebb(),
+        };
+        self.ins().jump(blocks.header, &[]);
+
+        self.switch_to_block(blocks.header);
+        header(self, blocks);
+        debug_assert!(
+            self.is_filled(),
+            "the loop header must end with a branch to the body or the exit"
+        );
+
+        // Only the header branches to the body.
+        self.switch_to_block(blocks.body);
+        self.seal_block(blocks.body);
+        body(self, blocks);
+        if !self.is_filled() {
+            self.ins().jump(blocks.header, &[]);
+        }
+
+        // All the back edges and loop exits are known now.
+        self.seal_block(blocks.header);
+        self.switch_to_block(blocks.exit);
+        self.seal_block(blocks.exit);
+        blocks
+    }
+
+    /// Build a conditional at the current position and return the merged result values.
+    ///
+    /// The current block branches to a new then block if `cond` is true, and to a new else
+    /// block otherwise. `then` and `else_` are called with the builder positioned in those
+    /// blocks, and return the values of the branch, whose types must be `result_types`. Both
+    /// branches then jump to a merge block with one parameter per result, and the builder is left
+    /// positioned in the merge block. A branch that fills its last block, for example with a
+    /// `return`, doesn't jump to the merge block, and its values are ignored.
+    ///
+    /// Returns the parameters of the merge block, which hold the results of the branch taken.
+    pub fn build_if_else<T, E>(
+        &mut self,
+        cond: Value,
+        result_types: &[Type],
+        then: T,
+        else_: E,
+    ) -> Vec<Value>
+    where
+        T: FnOnce(&mut Self) -> Vec<Value>,
+        E: FnOnce(&mut Self) -> Vec<Value>,
+    {
+        let then_block = self.create_ebb();
+        let else_block = self.create_ebb();
+        let merge_block = self.create_ebb();
+        for &ty in result_types {
+            self.append_ebb_param(merge_block, ty);
+        }
+        self.ins().brz(cond, else_block, &[]);
+        self.ins().jump(then_block, &[]);
+
+        self.switch_to_block(then_block);
+        self.seal_block(then_block);
+        let results = then(self);
+        self.jump_to_merge(merge_block, &results);
+
+        self.switch_to_block(else_block);
+        self.seal_block(else_block);
+        let results = else_(self);
+        self.jump_to_merge(merge_block, &results);
+
+        self.switch_to_block(merge_block);
+        self.seal_block(merge_block);
+        self.ebb_params(merge_block).to_vec()
+    }
+
+    /// Jump from the end of a conditional branch to its merge block, unless the branch is filled.
+    fn jump_to_merge(&mut self, merge_block: Ebb, results: &[Value]) {
+        if self.is_filled() {
+            return;
+        }
+        debug_assert_eq!(
+            results.len(),
+            self.ebb_params(merge_block).len(),
+            "wrong number of conditional results"
+        );
+        self.ins().jump(merge_block, results);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cretonne::entity::EntityRef;
+    use cretonne::ir::condcodes::IntCC;
+    use cretonne::ir::types::{I32, I64};
+    use cretonne::ir::{AbiParam, CallConv, ExternalName, Function, InstBuilder, Signature};
+    use cretonne::settings;
+    use cretonne::verifier::verify_function;
+    use frontend::{FunctionBuilder, FunctionBuilderContext};
+    use Variable;
+
+    fn verify(func: &Function) {
+        let flags = settings::Flags::new(&settings::builder());
+        if let Err(err) = verify_function(func, &flags) {
+            panic!("{}{}", func.display(None), err);
+        }
+    }
+
+    #[test]
+    fn counting_loop() {
+        // Sum the integers below the argument, skipping 7 and stopping at 100.
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sum"), sig);
+        let blocks;
+        {
+            let mut bx = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+            let entry = bx.create_ebb();
+            bx.append_ebb_params_for_function_params(entry);
+            bx.switch_to_block(entry);
+            bx.seal_block(entry);
+            let i = Variable::new(0);
+            let sum = Variable::new(1);
+            bx.declare_var(i, I32);
+            bx.declare_var(sum, I32);
+            let n = bx.ebb_params(entry)[0];
+            let zero = bx.ins().iconst(I32, 0);
+            bx.def_var(i, zero);
+            bx.def_var(sum, zero);
+
+            blocks = bx.build_loop(
+                |bx, blocks| {
+                    let iv = bx.use_var(i);
+                    let done = bx.ins().icmp(IntCC::SignedGreaterThanOrEqual, iv, n);
+                    bx.ins().brnz(done, blocks.exit, &[]);
+                    bx.ins().jump(blocks.body, &[]);
+                },
+                |bx, blocks| {
+                    let iv = bx.use_var(i);
+                    let next = bx.ins().iadd_imm(iv, 1);
+                    bx.def_var(i, next);
+                    let skip = bx.ins().icmp_imm(IntCC::Equal, iv, 7);
+                    bx.ins().brnz(skip, blocks.header, &[]);
+                    let sv = bx.use_var(sum);
+                    let sv = bx.ins().iadd(sv, iv);
+                    bx.def_var(sum, sv);
+                    let stop = bx.ins().icmp_imm(IntCC::SignedGreaterThan, sv, 100);
+                    bx.ins().brnz(stop, blocks.exit, &[]);
+                },
+            );
+            let sv = bx.use_var(sum);
+            bx.ins().return_(&[sv]);
+            bx.finalize();
+        }
+        verify(&func);
+        // `i` and `sum` are carried around the loop, and `sum` is merged at the exit.
+        assert_eq!(func.dfg.num_ebb_params(blocks.header), 2);
+        assert_eq!(func.dfg.num_ebb_params(blocks.exit), 1);
+    }
+
+    #[test]
+    fn if_else() {
+        // Return `(x < 0 ? -x : x, x < 0)` as an i64 and i32, and 0 for 1000.
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(I64));
+        sig.returns.push(AbiParam::new(I64));
+        sig.returns.push(AbiParam::new(I32));
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("abs"), sig);
+        let results;
+        {
+            let mut bx = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+            let entry = bx.create_ebb();
+            bx.append_ebb_params_for_function_params(entry);
+            bx.switch_to_block(entry);
+            bx.seal_block(entry);
+            let x = bx.ebb_params(entry)[0];
+
+            let is_magic = bx.ins().icmp_imm(IntCC::Equal, x, 1000);
+            bx.build_if_else(
+                is_magic,
+                &[],
+                |bx| {
+                    let zero = bx.ins().iconst(I64, 0);
+                    let zero32 = bx.ins().iconst(I32, 0);
+                    bx.ins().return_(&[zero, zero32]);
+                    Vec::new()
+                },
+                |_| Vec::new(),
+            );
+
+            let is_neg = bx.ins().icmp_imm(IntCC::SignedLessThan, x, 0);
+            results = bx.build_if_else(
+                is_neg,
+                &[I64, I32],
+                |bx| {
+                    let neg = bx.ins().irsub_imm(x, 0);
+                    let one = bx.ins().iconst(I32, 1);
+                    vec![neg, one]
+                },
+                |bx| {
+                    let zero = bx.ins().iconst(I32, 0);
+                    vec![x, zero]
+                },
+            );
+            bx.ins().return_(&results);
+            bx.finalize();
+        }
+        verify(&func);
+        assert_eq!(results.len(), 2);
+        assert_eq!(func.dfg.value_type(results[0]), I64);
+        assert_eq!(func.dfg.value_type(results[1]), I32);
+    }
+}
@@ -2,13 +2,18 @@
 use cretonne::cursor::{Cursor, FuncCursor};
 use cretonne::ir;
 use cretonne::ir::{Ebb, Type, Value, Function, Inst, JumpTable, StackSlot, JumpTableData,
-                   StackSlotData, DataFlowGraph, InstructionData, ExtFuncData, FuncRef, SigRef,
-                   Signature, InstBuilderBase, GlobalVarData, GlobalVar, HeapData, Heap};
+                   StackSlotData, StackSlotKind, DataFlowGraph, InstructionData, ExtFuncData,
+                   FuncRef, SigRef, Signature, InstBuilder, InstBuilderBase, GlobalVarData,
+                   GlobalVar, HeapData, Heap, ValueLabel, ValueLabelData, ExternalName};
+use cretonne::ir::stackslot::StackSize;
 use cretonne::ir::function::DisplayFunction;
 use cretonne::isa::TargetIsa;
 use ssa::{SSABuilder, SideEffects, Block};
-use cretonne::entity::{EntityRef, EntityMap, EntitySet};
+use cretonne::entity::{EntityRef, EntityMap};
 use cretonne::packed_option::PackedOption;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
 
 /// Structure used for translating a series of functions into Cretonne IL.
 ///
@@ -19,6 +24,9 @@ use cretonne::packed_option::PackedOption;
 /// The `Variable` parameter can be any index-like type that can be made to
 /// implement `EntityRef`. For frontends that don't have an obvious type to
 /// use here, `variable::Variable` can be used.
+///
+/// Since the allocations grow to fit the largest function translated so far, a long-running
+/// process can bound the memory kept between functions with `set_memory_limit`.
 pub struct FunctionBuilderContext<Variable>
 where
     Variable: EntityRef,
@@ -26,6 +34,24 @@ where
     ssa: SSABuilder<Variable>,
     ebbs: EntityMap<Ebb, EbbData>,
     types: EntityMap<Variable, Type>,
+    labels: EntityMap<Variable, PackedOption<ValueLabel>>,
+    signatures: HashMap<Signature, SigRef>,
+    functions: HashMap<ExternalName, FuncRef>,
+    memory_limit: Option<usize>,
+    stats: MemoryStats,
+}
+
+/// Memory statistics of a `FunctionBuilderContext`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Number of functions translated with the context.
+    pub functions: usize,
+    /// Approximate number of bytes of heap memory currently reserved by the context.
+    pub reserved_bytes: usize,
+    /// Largest number of bytes reserved at the end of a function.
+    pub peak_reserved_bytes: usize,
+    /// Number of times the memory was released because it exceeded the memory limit.
+    pub shrinks: usize,
 }
 
 
@@ -50,6 +76,56 @@ struct EbbData {
     filled: bool,
     pristine: bool,
     user_param_count: usize,
+    /// The source location that was current when the `Ebb` was created.
+    srcloc: ir::SourceLoc,
+    /// A label set by the user with `set_ebb_label`, for diagnostics.
+    label: Option<String>,
+}
+
+/// An `Ebb` that was used but not sealed or not filled when the function was finalized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnfinishedBlock {
+    /// The unfinished `Ebb`.
+    pub ebb: Ebb,
+    /// The label set with `FunctionBuilder::set_ebb_label`, if any.
+    pub label: Option<String>,
+    /// The source location that was current when the `Ebb` was created.
+    pub srcloc: ir::SourceLoc,
+    /// Was the `Ebb` sealed?
+    pub sealed: bool,
+    /// Was the `Ebb` filled with a terminator?
+    pub filled: bool,
+}
+
+/// The blocks of a function that were left unsealed or unfilled, in creation order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnfinishedBlocks(pub Vec<UnfinishedBlock>);
+
+impl fmt::Display for UnfinishedBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.ebb)?;
+        if let Some(ref label) = self.label {
+            write!(f, " \"{}\"", label)?;
+        }
+        write!(f, " created at {}:", self.srcloc)?;
+        if !self.sealed {
+            write!(f, " not sealed")?;
+        }
+        if !self.filled {
+            write!(f, "{} not filled", if self.sealed { "" } else { "," })?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for UnfinishedBlocks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} unfinished blocks in FunctionBuilder:", self.0.len())?;
+        for block in &self.0 {
+            write!(f, "\n  {}", block)?;
+        }
+        Ok(())
+    }
 }
 
 struct Position {
@@ -88,6 +164,64 @@ where
             ssa: SSABuilder::new(),
             ebbs: EntityMap::new(),
             types: EntityMap::new(),
+            labels: EntityMap::new(),
+            signatures: HashMap::new(),
+            functions: HashMap::new(),
+            memory_limit: None,
+            stats: MemoryStats::default(),
+        }
+    }
+
+    /// Limit the memory kept between functions.
+    ///
+    /// When a function is finalized and the context reserves more than `limit` bytes, its memory
+    /// is released instead of being kept for the next function. With `None`, the default, the
+    /// memory is always kept.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    /// Get the memory statistics of the context.
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            reserved_bytes: self.memory_usage(),
+            ..self.stats
+        }
+    }
+
+    /// Release the memory reserved by the context.
+    ///
+    /// This can only be called between functions.
+    pub fn shrink_to_fit(&mut self) {
+        debug_assert!(self.is_empty(), "can't shrink while a function is being built");
+        self.ssa.shrink_to_fit();
+        self.ebbs.shrink_to_fit();
+        self.types.shrink_to_fit();
+        self.labels.shrink_to_fit();
+        self.signatures.shrink_to_fit();
+        self.functions.shrink_to_fit();
+    }
+
+    /// Get the approximate number of bytes of heap memory reserved by the context.
+    fn memory_usage(&self) -> usize {
+        self.ssa.memory_usage() + self.ebbs.capacity() * mem::size_of::<EbbData>() +
+            self.types.capacity() * mem::size_of::<Type>() +
+            self.labels.capacity() * mem::size_of::<PackedOption<ValueLabel>>() +
+            self.signatures.capacity() * mem::size_of::<(Signature, SigRef)>() +
+            self.functions.capacity() * mem::size_of::<(ExternalName, FuncRef)>()
+    }
+
+    /// Clear the context at the end of a function, and update the statistics.
+    fn finish_function(&mut self) {
+        let bytes = self.memory_usage();
+        self.stats.functions += 1;
+        self.stats.peak_reserved_bytes = self.stats.peak_reserved_bytes.max(bytes);
+        self.clear();
+        if let Some(limit) = self.memory_limit {
+            if bytes > limit {
+                self.shrink_to_fit();
+                self.stats.shrinks += 1;
+            }
         }
     }
 
@@ -95,10 +229,14 @@ where
         self.ssa.clear();
         self.ebbs.clear();
         self.types.clear();
+        self.labels.clear();
+        self.signatures.clear();
+        self.functions.clear();
     }
 
     fn is_empty(&self) -> bool {
-        self.ssa.is_empty() && self.ebbs.is_empty() && self.types.is_empty()
+        self.ssa.is_empty() && self.ebbs.is_empty() && self.types.is_empty() &&
+            self.labels.is_empty() && self.signatures.is_empty() && self.functions.is_empty()
     }
 }
 
@@ -162,16 +300,22 @@ impl<'short, 'long, Variable> InstBuilderBase<'short> for FuncInstBuilder<'short
                     if let InstructionData::BranchTable { table, .. } = data {
 // Unlike all other jumps/branches, jump tables are
 // capable of having the same successor appear
-// multiple times, so we must deduplicate.
-                        let mut unique = EntitySet::<Ebb>::new();
-                        for dest_ebb in self.builder
-                                    .func
-                                    .jump_tables
-                                    .get(table)
-                                    .expect("you are referencing an undeclared jump table")
-                                    .entries()
-                                    .map(|(_, ebb)| ebb)
-                                    .filter(|dest_ebb| unique.insert(*dest_ebb)) {
+// multiple times, so we must deduplicate. Sorting keeps
+// this proportional to the size of the table rather than
+// the number of EBBs in the function, and the first
+// occurrences are declared in table order.
+                        let mut dests: Vec<(Ebb, usize)> = self.builder
+                            .func
+                            .jump_tables
+                            .get(table)
+                            .expect("you are referencing an undeclared jump table")
+                            .entries()
+                            .map(|(idx, ebb)| (ebb, idx))
+                            .collect();
+                        dests.sort_unstable();
+                        dests.dedup_by_key(|&mut (ebb, _)| ebb);
+                        dests.sort_unstable_by_key(|&(_, idx)| idx);
+                        for (dest_ebb, _) in dests {
                             self.builder.func_ctx.ssa.declare_ebb_predecessor(
                                 dest_ebb,
                                 self.builder.position.basic_block.unwrap(),
@@ -256,10 +400,18 @@ where
             filled: false,
             pristine: true,
             user_param_count: 0,
+            srcloc: self.srcloc,
+            label: None,
         };
         ebb
     }
 
+    /// Attach a label to `ebb`, used to identify it when it is reported as unfinished by
+    /// `finalize` or `check_blocks`.
+    pub fn set_ebb_label(&mut self, ebb: Ebb, label: &str) {
+        self.func_ctx.ebbs[ebb].label = Some(label.to_string());
+    }
+
     /// After the call to this function, new instructions will be inserted into the designated
     /// block, in the order they are declared. You must declare the types of the Ebb arguments
     /// you will use here.
@@ -295,7 +447,7 @@ where
         self.handle_ssa_side_effects(side_effects);
     }
 
-    /// Effectively calls seal_block on all blocks in the function.
+    /// Effectively calls seal_block on all blocks in the function that aren't sealed yet.
     ///
     /// It's more efficient to seal `Ebb`s as soon as possible, during
     /// translation, but for frontends where this is impractical to do, this
@@ -307,16 +459,18 @@ where
     }
 
     /// In order to use a variable in a `use_var`, you need to declare its type with this method.
+    ///
+    /// Variables can have any value type, including the boolean and vector types. The EBB
+    /// parameters inserted by the SSA construction get the declared type.
     pub fn declare_var(&mut self, var: Variable, ty: Type) {
+        debug_assert!(!ty.is_void(), "variables can't be void");
         self.func_ctx.types[var] = ty;
     }
 
     /// Returns the Cretonne IL value corresponding to the utilization at the current program
     /// position of a previously defined user variable.
     pub fn use_var(&mut self, var: Variable) -> Value {
-        let ty = *self.func_ctx.types.get(var).expect(
-            "this variable is used but its type has not been declared",
-        );
+        let ty = self.var_type(var);
         let (val, side_effects) = self.func_ctx.ssa.use_var(
             self.func,
             var,
@@ -324,17 +478,49 @@ where
             self.position.basic_block.unwrap(),
         );
         self.handle_ssa_side_effects(side_effects);
+        self.label_var_value(var, val);
         val
     }
 
     /// Register a new definition of a user variable. Panics if the type of the value is not the
     /// same as the type registered for the variable.
     pub fn def_var(&mut self, var: Variable, val: Value) {
+        debug_assert_eq!(
+            self.func.dfg.value_type(val),
+            self.var_type(var),
+            "declared type of variable {} doesn't match type of value {}",
+            var.index(),
+            val
+        );
         self.func_ctx.ssa.def_var(
             var,
             val,
             self.position.basic_block.unwrap(),
         );
+        self.label_var_value(var, val);
+    }
+
+    /// Attach debug information to a variable.
+    ///
+    /// The values defined and used for `var` are labelled with the returned label, so the code
+    /// ranges where the variable lives can be found after compilation with
+    /// `Context::value_labels_ranges`. This should be called before the variable is defined.
+    pub fn set_var_debug_info(
+        &mut self,
+        var: Variable,
+        name: &str,
+        srcloc: ir::SourceLoc,
+    ) -> ValueLabel {
+        let label = self.func.create_value_label(ValueLabelData::new(name, srcloc));
+        self.func_ctx.labels[var] = label.into();
+        label
+    }
+
+    /// Label `val` with the debug label of `var`, if it has one.
+    fn label_var_value(&mut self, var: Variable, val: Value) {
+        if let Some(label) = self.func_ctx.labels.get(var).and_then(|l| l.expand()) {
+            self.func.label_value(val, label);
+        }
     }
 
     /// Creates a jump table in the function, to be used by `br_table` instructions.
@@ -353,6 +539,51 @@ where
         self.func.create_stack_slot(data)
     }
 
+    /// Creates an explicit stack slot for an object of `size` bytes aligned to `align` bytes.
+    ///
+    /// The alignment must be a power of two, and it is recorded on the stack slot. It can't be
+    /// larger than the alignment of the stack pointer on the target; compiling the function fails
+    /// with `CodegenError::ImplLimitExceeded` otherwise.
+    pub fn create_stack_object(&mut self, size: StackSize, align: StackSize) -> StackSlot {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        let mut data = StackSlotData::new(StackSlotKind::ExplicitSlot, size);
+        data.align = Some(align);
+        self.create_stack_slot(data)
+    }
+
+    /// Computes the address of the byte at `offset` in the stack object `ss`, as a value of
+    /// type `addr_ty`.
+    ///
+    /// This can be used to get the address of a field of an address-taken local.
+    ///
+    /// Panics if `offset` is outside the stack object.
+    pub fn stack_object_addr(&mut self, addr_ty: Type, ss: StackSlot, offset: StackSize) -> Value {
+        assert!(
+            offset < self.func.stack_slots[ss].size,
+            "offset {} is outside {}",
+            offset,
+            ss
+        );
+        self.ins().stack_addr(addr_ty, ss, offset as i32)
+    }
+
+    /// Loads a value of type `ty` from the field at `offset` in the stack object `ss`.
+    ///
+    /// Panics if the field is outside the stack object or isn't naturally aligned.
+    pub fn stack_object_load(&mut self, ty: Type, ss: StackSlot, offset: StackSize) -> Value {
+        self.check_stack_object_access(ty, ss, offset);
+        self.ins().stack_load(ty, ss, offset as i32)
+    }
+
+    /// Stores `val` to the field at `offset` in the stack object `ss`.
+    ///
+    /// Panics if the field is outside the stack object or isn't naturally aligned.
+    pub fn stack_object_store(&mut self, val: Value, ss: StackSlot, offset: StackSize) {
+        let ty = self.func.dfg.value_type(val);
+        self.check_stack_object_access(ty, ss, offset);
+        self.ins().stack_store(val, ss, offset as i32);
+    }
+
     /// Adds a signature which can later be used to declare an external function import.
     pub fn import_signature(&mut self, signature: Signature) -> SigRef {
         self.func.import_signature(signature)
@@ -363,6 +594,71 @@ where
         self.func.import_function(data)
     }
 
+    /// Get a reference to `signature`, importing it on first use.
+    ///
+    /// Unlike `import_signature`, importing the same signature again returns the existing
+    /// `SigRef`.
+    pub fn signature_ref(&mut self, signature: &Signature) -> SigRef {
+        if let Some(&sigref) = self.func_ctx.signatures.get(signature) {
+            return sigref;
+        }
+        let sigref = self.func.import_signature(signature.clone());
+        self.func_ctx.signatures.insert(signature.clone(), sigref);
+        sigref
+    }
+
+    /// Get a reference to the external function `name` with `signature`, declaring it on first
+    /// use.
+    ///
+    /// Unlike `import_function`, declaring the same function again returns the existing
+    /// `FuncRef`. All the declarations of a function must use the same signature.
+    pub fn func_ref(&mut self, name: ExternalName, signature: &Signature) -> FuncRef {
+        if let Some(&fref) = self.func_ctx.functions.get(&name) {
+            debug_assert_eq!(
+                self.func.dfg.signatures[self.func.dfg.ext_funcs[fref].signature],
+                *signature,
+                "function {} was declared with a different signature",
+                name
+            );
+            return fref;
+        }
+        let sigref = self.signature_ref(signature);
+        let fref = self.func.import_function(ExtFuncData {
+            name: name.clone(),
+            signature: sigref,
+        });
+        self.func_ctx.functions.insert(name, fref);
+        fref
+    }
+
+    /// Emit a direct call to the external function `name` with `signature`.
+    ///
+    /// The function and its signature are imported on first use. The results of the call are
+    /// available from `inst_results`.
+    pub fn call_external(
+        &mut self,
+        name: ExternalName,
+        signature: &Signature,
+        args: &[Value],
+    ) -> Inst {
+        let fref = self.func_ref(name, signature);
+        self.ins().call(fref, args)
+    }
+
+    /// Emit an indirect call to `callee` with `signature`.
+    ///
+    /// The signature is imported on first use. The results of the call are available from
+    /// `inst_results`.
+    pub fn call_indirect_with_signature(
+        &mut self,
+        signature: &Signature,
+        callee: Value,
+        args: &[Value],
+    ) -> Inst {
+        let sigref = self.signature_ref(signature);
+        self.ins().call_indirect(sigref, callee, args)
+    }
+
     /// Declares a global variable accessible to the function.
     pub fn create_global_var(&mut self, data: GlobalVarData) -> GlobalVar {
         self.func.create_global_var(data)
@@ -433,27 +729,52 @@ where
         }
     }
 
+    /// Check that all the `Ebb`s that were used are sealed and filled.
+    ///
+    /// The error lists each unfinished `Ebb` along with its label and the source location that
+    /// was current when it was created.
+    pub fn check_blocks(&self) -> Result<(), UnfinishedBlocks> {
+        let unfinished: Vec<UnfinishedBlock> = self.func_ctx
+            .ebbs
+            .keys()
+            .filter_map(|ebb| {
+                let data = &self.func_ctx.ebbs[ebb];
+                let sealed = self.func_ctx.ssa.is_sealed(ebb);
+                if data.pristine || (sealed && data.filled) {
+                    return None;
+                }
+                Some(UnfinishedBlock {
+                    ebb,
+                    label: data.label.clone(),
+                    srcloc: data.srcloc,
+                    sealed,
+                    filled: data.filled,
+                })
+            })
+            .collect();
+        if unfinished.is_empty() {
+            Ok(())
+        } else {
+            Err(UnfinishedBlocks(unfinished))
+        }
+    }
+
     /// Declare that translation of the current function is complete. This
     /// resets the state of the `FunctionBuilder` in preparation to be used
     /// for another function.
+    ///
+    /// In debug mode, this panics with the list of unfinished blocks if any `Ebb` was left
+    /// unsealed or unfilled, see `check_blocks`.
     pub fn finalize(&mut self) {
-        // Check that all the `Ebb`s are filled and sealed.
-        debug_assert!(
-            self.func_ctx.ebbs.keys().all(|ebb| {
-                self.func_ctx.ebbs[ebb].pristine || self.func_ctx.ssa.is_sealed(ebb)
-            }),
-            "all blocks should be sealed before dropping a FunctionBuilder"
-        );
-        debug_assert!(
-            self.func_ctx.ebbs.keys().all(|ebb| {
-                self.func_ctx.ebbs[ebb].pristine || self.func_ctx.ebbs[ebb].filled
-            }),
-            "all blocks should be filled before dropping a FunctionBuilder"
-        );
+        if cfg!(debug_assertions) {
+            if let Err(err) = self.check_blocks() {
+                panic!("{}", err);
+            }
+        }
 
-        // Clear the state (but preserve the allocated buffers) in preparation
-        // for translation another function.
-        self.func_ctx.clear();
+        // Clear the state (but preserve the allocated buffers, unless they exceed the memory
+        // limit) in preparation for translation another function.
+        self.func_ctx.finish_function();
 
         // Reset srcloc and position to initial states.
         self.srcloc = Default::default();
@@ -558,6 +879,13 @@ impl<'a, Variable> FunctionBuilder<'a, Variable>
 where
     Variable: EntityRef,
 {
+    fn var_type(&self, var: Variable) -> Type {
+        match self.func_ctx.types.get(var) {
+            Some(&ty) if !ty.is_void() => ty,
+            _ => panic!("this variable is used but its type has not been declared"),
+        }
+    }
+
     fn move_to_next_basic_block(&mut self) {
         self.position.basic_block = PackedOption::from(self.func_ctx.ssa.declare_ebb_body_block(
             self.position.basic_block.unwrap(),
@@ -576,6 +904,26 @@ where
         );
     }
 
+    /// Check that an access to a `ty` field at `offset` stays inside the stack object `ss` and
+    /// is naturally aligned.
+    fn check_stack_object_access(&self, ty: Type, ss: StackSlot, offset: StackSize) {
+        assert!(
+            u64::from(offset) + u64::from(ty.bytes()) <= u64::from(self.func.stack_slots[ss].size),
+            "{} access at offset {} is outside {}",
+            ty,
+            offset,
+            ss
+        );
+        assert_eq!(
+            offset % ty.bytes(),
+            0,
+            "{} access at offset {} in {} is misaligned",
+            ty,
+            offset,
+            ss
+        );
+    }
+
     fn handle_ssa_side_effects(&mut self, side_effects: SideEffects) {
         for split_ebb in side_effects.split_ebbs_created {
             self.func_ctx.ebbs[split_ebb].filled = true
@@ -590,11 +938,13 @@ where
 mod tests {
 
     use cretonne::entity::EntityRef;
-    use cretonne::ir::{ExternalName, Function, CallConv, Signature, AbiParam, InstBuilder};
+    use cretonne::ir::{ExternalName, Function, CallConv, Signature, AbiParam, InstBuilder,
+                       JumpTableData};
     use cretonne::ir::types::*;
-    use frontend::{FunctionBuilderContext, FunctionBuilder};
+    use frontend::{FunctionBuilderContext, FunctionBuilder, UnfinishedBlock, UnfinishedBlocks};
+    use cretonne::ir::SourceLoc;
     use cretonne::verifier::verify_function;
-    use cretonne::settings;
+    use cretonne::{isa, settings, Context};
     use Variable;
 
     fn sample_function(lazy_seal: bool) {
@@ -701,4 +1051,378 @@ mod tests {
     fn sample_with_lazy_seal() {
         sample_function(true)
     }
+
+    #[test]
+    fn non_scalar_variables() {
+        let sig = Signature::new(CallConv::Native);
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        let header;
+        {
+            let mut builder = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+            let block0 = builder.create_ebb();
+            let block1 = builder.create_ebb();
+            let block2 = builder.create_ebb();
+            header = block1;
+            let x = Variable::new(0);
+            let flag = Variable::new(1);
+            let d = Variable::new(2);
+            builder.declare_var(x, I32X4);
+            builder.declare_var(flag, B1);
+            builder.declare_var(d, F64X2);
+
+            builder.switch_to_block(block0);
+            builder.seal_block(block0);
+            let tmp = builder.ins().iconst(I32X4, 1);
+            builder.def_var(x, tmp);
+            let tmp = builder.ins().bconst(B1, true);
+            builder.def_var(flag, tmp);
+            builder.ins().jump(block1, &[]);
+
+            // `d` is never defined, so it is zero-initialized in `block0`.
+            builder.switch_to_block(block1);
+            let arg = builder.use_var(flag);
+            builder.ins().brz(arg, block2, &[]);
+            let arg = builder.use_var(x);
+            let tmp = builder.ins().iadd(arg, arg);
+            builder.def_var(x, tmp);
+            let tmp = builder.ins().bconst(B1, false);
+            builder.def_var(flag, tmp);
+            let arg = builder.use_var(d);
+            let tmp = builder.ins().fadd(arg, arg);
+            builder.def_var(d, tmp);
+            builder.ins().jump(block1, &[]);
+            builder.seal_block(block1);
+
+            builder.switch_to_block(block2);
+            builder.seal_block(block2);
+            builder.ins().return_(&[]);
+            builder.finalize();
+        }
+
+        let types: Vec<_> = func.dfg
+            .ebb_params(header)
+            .iter()
+            .map(|&v| func.dfg.value_type(v))
+            .collect();
+        assert_eq!(types, [B1, I32X4, F64X2]);
+        let flags = settings::Flags::new(&settings::builder());
+        if let Err(err) = verify_function(&func, &flags) {
+            panic!("{}{}", func.display(None), err);
+        }
+    }
+
+    #[test]
+    fn var_debug_info() {
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        let (label_x, label_y);
+        {
+            let mut builder = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+            let block0 = builder.create_ebb();
+            let block1 = builder.create_ebb();
+            let x = Variable::new(0);
+            let y = Variable::new(1);
+            builder.declare_var(x, I32);
+            builder.declare_var(y, I32);
+            label_x = builder.set_var_debug_info(x, "x", SourceLoc::new(1));
+            label_y = builder.set_var_debug_info(y, "y", SourceLoc::new(2));
+
+            builder.append_ebb_params_for_function_params(block0);
+            builder.switch_to_block(block0);
+            builder.seal_block(block0);
+            let arg = builder.ebb_params(block0)[0];
+            builder.def_var(x, arg);
+            let tmp = builder.ins().iconst(I32, 0);
+            builder.def_var(y, tmp);
+            builder.ins().jump(block1, &[]);
+
+            // `x` and `y` become parameters of the loop header.
+            builder.switch_to_block(block1);
+            let arg_x = builder.use_var(x);
+            let arg_y = builder.use_var(y);
+            let tmp = builder.ins().iadd(arg_x, arg_y);
+            builder.def_var(y, tmp);
+            let tmp = builder.ins().iadd_imm(arg_x, -1);
+            builder.def_var(x, tmp);
+            builder.ins().brnz(tmp, block1, &[]);
+            builder.seal_block(block1);
+            let arg = builder.use_var(y);
+            builder.ins().return_(&[arg]);
+            builder.finalize();
+        }
+        assert_eq!(func.value_labels[label_x].name, "x");
+        assert_eq!(func.value_labels[label_y].srcloc, SourceLoc::new(2));
+
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(
+            &settings::builder(),
+        ));
+        let mut ctx = Context::for_function(func);
+        let code_size = ctx.compile(&*isa).unwrap();
+        let ranges = ctx.value_labels_ranges(&*isa);
+        for label in &[label_x, label_y] {
+            assert!(!ranges[label].is_empty());
+            for range in &ranges[label] {
+                assert!(range.start < range.end && range.end <= code_size);
+            }
+        }
+    }
+
+    /// Build a function with a chain of `n` blocks, each using and defining `n` variables.
+    fn build_chain(fn_ctx: &mut FunctionBuilderContext<Variable>, n: usize) {
+        let sig = Signature::new(CallConv::Native);
+        let mut func = Function::with_name_signature(ExternalName::testcase("chain"), sig);
+        let mut builder = FunctionBuilder::<Variable>::new(&mut func, fn_ctx);
+        let mut block = builder.create_ebb();
+        builder.switch_to_block(block);
+        for v in 0..n {
+            builder.declare_var(Variable::new(v), I32);
+            let zero = builder.ins().iconst(I32, 0);
+            builder.def_var(Variable::new(v), zero);
+        }
+        for _ in 0..n {
+            let next = builder.create_ebb();
+            builder.ins().jump(next, &[]);
+            builder.seal_block(block);
+            builder.switch_to_block(next);
+            for v in 0..n {
+                let val = builder.use_var(Variable::new(v));
+                let val = builder.ins().iadd_imm(val, 1);
+                builder.def_var(Variable::new(v), val);
+            }
+            block = next;
+        }
+        builder.seal_block(block);
+        builder.ins().return_(&[]);
+        builder.finalize();
+    }
+
+    #[test]
+    fn memory_stats() {
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        assert_eq!(fn_ctx.memory_stats(), Default::default());
+
+        build_chain(&mut fn_ctx, 50);
+        let stats = fn_ctx.memory_stats();
+        assert_eq!(stats.functions, 1);
+        assert!(stats.reserved_bytes > 0);
+        assert!(stats.peak_reserved_bytes >= stats.reserved_bytes);
+
+        // The memory is kept for smaller functions.
+        build_chain(&mut fn_ctx, 2);
+        let kept = fn_ctx.memory_stats();
+        assert_eq!(kept.functions, 2);
+        assert_eq!(kept.reserved_bytes, stats.reserved_bytes);
+        assert_eq!(kept.peak_reserved_bytes, stats.peak_reserved_bytes);
+
+        // It is released after a function that exceeds the limit.
+        fn_ctx.set_memory_limit(Some(1000));
+        build_chain(&mut fn_ctx, 50);
+        let shrunk = fn_ctx.memory_stats();
+        assert_eq!(shrunk.shrinks, 1);
+        assert!(shrunk.reserved_bytes < 1000);
+        build_chain(&mut fn_ctx, 2);
+        assert_eq!(fn_ctx.memory_stats().shrinks, 1);
+    }
+
+    #[test]
+    fn call_helpers() {
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(I64));
+        sig.returns.push(AbiParam::new(I64));
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig.clone());
+        {
+            let mut builder = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+            let block0 = builder.create_ebb();
+            builder.append_ebb_params_for_function_params(block0);
+            builder.switch_to_block(block0);
+            builder.seal_block(block0);
+            let arg = builder.ebb_params(block0)[0];
+
+            let call = builder.call_external(ExternalName::testcase("foo"), &sig, &[arg]);
+            let ret = builder.inst_results(call)[0];
+            let call = builder.call_external(ExternalName::testcase("foo"), &sig, &[ret]);
+            let ret = builder.inst_results(call)[0];
+            let call = builder.call_external(ExternalName::testcase("bar"), &sig, &[ret]);
+            let ret = builder.inst_results(call)[0];
+            let call = builder.call_indirect_with_signature(&sig, ret, &[ret]);
+            let ret = builder.inst_results(call)[0];
+            builder.ins().return_(&[ret]);
+            builder.finalize();
+        }
+        assert_eq!(func.dfg.signatures.len(), 1);
+        assert_eq!(func.dfg.ext_funcs.len(), 2);
+        let flags = settings::Flags::new(&settings::builder());
+        if let Err(err) = verify_function(&func, &flags) {
+            panic!("{}{}", func.display(None), err);
+        }
+    }
+
+    #[test]
+    fn unfinished_blocks() {
+        let sig = Signature::new(CallConv::Native);
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        let mut builder = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+        let block0 = builder.create_ebb();
+        builder.set_srcloc(SourceLoc::new(0x12));
+        let block1 = builder.create_ebb();
+        builder.set_ebb_label(block1, "loop header");
+        let unused = builder.create_ebb();
+        builder.set_ebb_label(unused, "unused");
+
+        builder.switch_to_block(block0);
+        builder.seal_block(block0);
+        builder.ins().jump(block1, &[]);
+        builder.switch_to_block(block1);
+        builder.ins().iconst(I32, 0);
+        assert_eq!(
+            builder.check_blocks(),
+            Err(UnfinishedBlocks(vec![
+                UnfinishedBlock {
+                    ebb: block1,
+                    label: Some("loop header".to_string()),
+                    srcloc: SourceLoc::new(0x12),
+                    sealed: false,
+                    filled: false,
+                },
+            ]))
+        );
+        assert_eq!(
+            builder.check_blocks().unwrap_err().to_string(),
+            "1 unfinished blocks in FunctionBuilder:\n  ebb1 \"loop header\" created at @0012: \
+             not sealed, not filled"
+        );
+
+        builder.ins().jump(block1, &[]);
+        assert!(builder.check_blocks().is_err());
+        builder.seal_block(block1);
+        assert_eq!(builder.check_blocks(), Ok(()));
+        builder.finalize();
+    }
+
+    #[test]
+    #[should_panic(expected = "ebb0 created at @-: not sealed")]
+    fn finalize_unsealed() {
+        let sig = Signature::new(CallConv::Native);
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        let mut builder = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+        let block0 = builder.create_ebb();
+        builder.switch_to_block(block0);
+        builder.ins().return_(&[]);
+        builder.finalize();
+    }
+
+    #[test]
+    fn br_table_split_duplicate_entries() {
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        let (jt, target, other) = {
+            let mut builder = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+            let block0 = builder.create_ebb();
+            let block1 = builder.create_ebb();
+            let target = builder.create_ebb();
+            let other = builder.create_ebb();
+            let x = Variable::new(0);
+            builder.declare_var(x, I32);
+            builder.append_ebb_params_for_function_params(block0);
+
+            builder.switch_to_block(block0);
+            builder.seal_block(block0);
+            let arg = builder.ebb_params(block0)[0];
+            builder.def_var(x, arg);
+            let mut data = JumpTableData::new();
+            data.push_entry(target);
+            data.push_entry(other);
+            data.push_entry(target);
+            let jt = builder.create_jump_table(data);
+            builder.ins().br_table(arg, jt);
+            builder.ins().jump(block1, &[]);
+
+            builder.switch_to_block(block1);
+            builder.seal_block(block1);
+            let tmp = builder.ins().iconst(I32, 1);
+            builder.def_var(x, tmp);
+            builder.ins().jump(target, &[]);
+
+            // `x` differs between the predecessors, so the `br_table` edge must be split.
+            builder.switch_to_block(target);
+            builder.seal_block(target);
+            let arg = builder.use_var(x);
+            builder.ins().return_(&[arg]);
+
+            builder.switch_to_block(other);
+            builder.seal_block(other);
+            let arg = builder.use_var(x);
+            builder.ins().return_(&[arg]);
+            builder.finalize();
+            (jt, target, other)
+        };
+
+        let entries: Vec<_> = func.jump_tables[jt].entries().map(|(_, ebb)| ebb).collect();
+        assert_eq!(entries[0], entries[2]);
+        assert!(entries[0] != target);
+        assert_eq!(entries[1], other);
+        let flags = settings::Flags::new(&settings::builder());
+        if let Err(err) = verify_function(&func, &flags) {
+            panic!("{}{}", func.display(None), err);
+        }
+    }
+
+    #[test]
+    fn stack_objects() {
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I64));
+
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        let pair;
+        {
+            let mut builder = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+            let block0 = builder.create_ebb();
+            builder.append_ebb_params_for_function_params(block0);
+            builder.switch_to_block(block0);
+            builder.seal_block(block0);
+
+            // struct { a: i32, b: i64 }
+            pair = builder.create_stack_object(16, 8);
+            let a = builder.ebb_params(block0)[0];
+            builder.stack_object_store(a, pair, 0);
+            let b = builder.ins().iconst(I64, 7);
+            builder.stack_object_store(b, pair, 8);
+            let addr = builder.stack_object_addr(I64, pair, 8);
+            let loaded = builder.stack_object_load(I64, pair, 8);
+            let sum = builder.ins().iadd(addr, loaded);
+            builder.ins().return_(&[sum]);
+            builder.finalize();
+        }
+
+        assert_eq!(func.stack_slots[pair].size, 16);
+        assert_eq!(func.stack_slots[pair].align, Some(8));
+        let flags = settings::Flags::new(&settings::builder());
+        if let Err(err) = verify_function(&func, &flags) {
+            panic!("{}{}", func.display(None), err);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "outside ss0")]
+    fn stack_object_out_of_bounds() {
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        let mut func = Function::new();
+        let mut builder = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+        let block0 = builder.create_ebb();
+        builder.switch_to_block(block0);
+        let ss = builder.create_stack_object(4, 4);
+        builder.stack_object_load(I64, ss, 0);
+    }
 }
@@ -133,9 +133,14 @@
 
 extern crate cretonne;
 
-pub use frontend::{FunctionBuilderContext, FunctionBuilder};
+pub use control::LoopBlocks;
+pub use frontend::{FunctionBuilderContext, FunctionBuilder, MemoryStats, UnfinishedBlock,
+                   UnfinishedBlocks};
+pub use switch::Switch;
 pub use variable::Variable;
 
+mod control;
 mod frontend;
 mod ssa;
+mod switch;
 mod variable;
@@ -6,7 +6,7 @@
 //! Lecture Notes in Computer Science, vol 7791. Springer, Berlin, Heidelberg
 
 use cretonne::cursor::{Cursor, FuncCursor};
-use cretonne::ir::{Ebb, Value, Inst, Type, Function, InstBuilder};
+use cretonne::ir::{Ebb, Value, Inst, Type, Function, InstBuilder, JumpTable, JumpTableData};
 use cretonne::ir::instructions::BranchInfo;
 use cretonne::entity::{EntityRef, PrimaryMap, EntityMap};
 use cretonne::packed_option::PackedOption;
@@ -14,6 +14,7 @@ use cretonne::packed_option::ReservedValue;
 use std::u32;
 use cretonne::ir::types::{F32, F64};
 use cretonne::ir::immediates::{Ieee32, Ieee64};
+use std::collections::HashMap;
 use std::mem;
 
 /// Structure containing the data relevant the construction of SSA for a given function.
@@ -43,6 +44,9 @@ where
     blocks: PrimaryMap<Block, BlockData<Variable>>,
     // Records the basic blocks at the beginning of the `Ebb`s.
     ebb_headers: EntityMap<Ebb, PackedOption<Block>>,
+    // For the jump tables whose critical edges are being split, the positions of the entries
+    // branching to each `Ebb`. This avoids rescanning a large table for every destination.
+    jump_table_entries: HashMap<JumpTable, HashMap<Ebb, Vec<usize>>>,
 
     // Call and result stacks for use in the `use_var`/`predecessors_lookup` state machine.
     calls: Vec<Call>,
@@ -153,6 +157,7 @@ where
             variables: EntityMap::with_default(EntityMap::new()),
             blocks: PrimaryMap::new(),
             ebb_headers: EntityMap::new(),
+            jump_table_entries: HashMap::new(),
             calls: Vec::new(),
             results: Vec::new(),
             side_effects: SideEffects::new(),
@@ -162,18 +167,67 @@ where
     /// Clears a `SSABuilder` from all its data, letting it in a pristine state without
     /// deallocating memory.
     pub fn clear(&mut self) {
-        self.variables.clear();
+        // Keep the per-variable maps around so their memory can be reused by the next function.
+        for var in self.variables.keys() {
+            self.variables[var].clear();
+        }
         self.blocks.clear();
         self.ebb_headers.clear();
+        self.jump_table_entries.clear();
         debug_assert!(self.calls.is_empty());
         debug_assert!(self.results.is_empty());
         debug_assert!(self.side_effects.is_empty());
     }
 
+    /// Get the approximate number of bytes of heap memory reserved by the builder.
+    pub fn memory_usage(&self) -> usize {
+        let mut bytes = self.variables.capacity() *
+            mem::size_of::<EntityMap<Block, PackedOption<Value>>>();
+        for var in self.variables.keys() {
+            bytes += self.variables[var].capacity() * mem::size_of::<PackedOption<Value>>();
+        }
+        bytes += self.blocks.capacity() * mem::size_of::<BlockData<Variable>>();
+        for block in self.blocks.keys() {
+            if let BlockData::EbbHeader(ref data) = self.blocks[block] {
+                bytes += data.predecessors.capacity() * mem::size_of::<(Block, Inst)>();
+                bytes += data.undef_variables.capacity() * mem::size_of::<(Variable, Value)>();
+            }
+        }
+        bytes += self.ebb_headers.capacity() * mem::size_of::<PackedOption<Block>>();
+        bytes += self.jump_table_entries.capacity() *
+            mem::size_of::<(JumpTable, HashMap<Ebb, Vec<usize>>)>();
+        for entries in self.jump_table_entries.values() {
+            bytes += entries.capacity() * mem::size_of::<(Ebb, Vec<usize>)>();
+            for positions in entries.values() {
+                bytes += positions.capacity() * mem::size_of::<usize>();
+            }
+        }
+        bytes += self.calls.capacity() * mem::size_of::<Call>();
+        bytes += self.results.capacity() * mem::size_of::<Value>();
+        bytes += self.side_effects.split_ebbs_created.capacity() * mem::size_of::<Ebb>();
+        bytes += self.side_effects.instructions_added_to_ebbs.capacity() * mem::size_of::<Ebb>();
+        bytes
+    }
+
+    /// Release the memory of a cleared `SSABuilder`.
+    pub fn shrink_to_fit(&mut self) {
+        debug_assert!(self.is_empty());
+        self.variables.clear();
+        self.variables.shrink_to_fit();
+        self.blocks.shrink_to_fit();
+        self.ebb_headers.shrink_to_fit();
+        self.jump_table_entries.shrink_to_fit();
+        self.calls.shrink_to_fit();
+        self.results.shrink_to_fit();
+        self.side_effects.split_ebbs_created.shrink_to_fit();
+        self.side_effects.instructions_added_to_ebbs.shrink_to_fit();
+    }
+
     /// Tests whether an `SSABuilder` is in a cleared state.
     pub fn is_empty(&self) -> bool {
-        self.variables.is_empty() && self.blocks.is_empty() && self.ebb_headers.is_empty() &&
-            self.calls.is_empty() &&
+        self.variables.keys().all(|var| self.variables[var].is_empty()) &&
+            self.blocks.is_empty() && self.ebb_headers.is_empty() &&
+            self.jump_table_entries.is_empty() && self.calls.is_empty() &&
             self.results.is_empty() && self.side_effects.is_empty()
     }
 }
@@ -201,34 +255,39 @@ enum Call {
 }
 
 /// Emit instructions to produce a zero value in the given type.
+///
+/// Vectors are produced by splatting the zero value of their lane type, except for integer and
+/// boolean vectors, which have constant instructions of their own.
 fn emit_zero(ty: Type, mut cur: FuncCursor) -> Value {
-    if ty.is_int() {
+    let lane_ty = ty.lane_type();
+    if lane_ty.is_int() {
         cur.ins().iconst(ty, 0)
-    } else if ty.is_bool() {
+    } else if lane_ty.is_bool() {
         cur.ins().bconst(ty, false)
-    } else if ty == F32 {
-        cur.ins().f32const(Ieee32::with_bits(0))
-    } else if ty == F64 {
-        cur.ins().f64const(Ieee64::with_bits(0))
-    } else if ty.is_vector() {
-        let scalar_ty = ty.lane_type();
-        if scalar_ty.is_int() {
-            cur.ins().iconst(ty, 0)
-        } else if scalar_ty.is_bool() {
-            cur.ins().bconst(ty, false)
-        } else if scalar_ty == F32 {
-            let scalar = cur.ins().f32const(Ieee32::with_bits(0));
-            cur.ins().splat(ty, scalar)
-        } else if scalar_ty == F64 {
-            let scalar = cur.ins().f64const(Ieee64::with_bits(0));
+    } else {
+        let scalar = if lane_ty == F32 {
+            cur.ins().f32const(Ieee32::with_bits(0))
+        } else if lane_ty == F64 {
+            cur.ins().f64const(Ieee64::with_bits(0))
+        } else {
+            panic!("variables of type {} must be defined before they are used", ty)
+        };
+        if ty.is_vector() {
             cur.ins().splat(ty, scalar)
         } else {
-            panic!("unimplemented scalar type: {:?}", ty)
+            scalar
         }
-    } else {
-        panic!("unimplemented type: {:?}", ty)
     }
 }
+/// Get the positions of the entries of `jt` for each destination `Ebb`.
+fn index_jump_table(jt: &JumpTableData) -> HashMap<Ebb, Vec<usize>> {
+    let mut index = HashMap::new();
+    for (idx, ebb) in jt.entries() {
+        index.entry(ebb).or_insert_with(Vec::new).push(idx);
+    }
+    index
+}
+
 /// The following methods are the API of the SSA builder. Here is how it should be used when
 /// translating to Cretonne IL:
 ///
@@ -266,8 +325,8 @@ where
     /// are the results of critical edge splitting for `br_table` with arguments.
     ///
     /// If the variable has never been defined in this blocks or recursively in its predecessors,
-    /// this method will silently create an initializer with `iconst` or `fconst`. You are
-    /// responsible for making sure that you initialize your variables.
+    /// this method will silently create a zero initializer of type `ty`. You are responsible for
+    /// making sure that you initialize your variables.
     pub fn use_var(
         &mut self,
         func: &mut Function,
@@ -418,13 +477,15 @@ where
     /// It's more efficient to seal `Ebb`s as soon as possible, during
     /// translation, but for frontends where this is impractical to do, this
     /// function can be used at the end of translating all blocks to ensure
-    /// that everything is sealed.
+    /// that everything is sealed. `Ebb`s that are already sealed are skipped.
     pub fn seal_all_ebb_header_blocks(&mut self, func: &mut Function) -> SideEffects {
         // Seal all `Ebb`s currently in the function. This can entail splitting
         // and creation of new blocks, however such new blocks are sealed on
         // the fly, so we don't need to account for them here.
         for ebb in self.ebb_headers.keys() {
-            self.seal_one_ebb_header_block(ebb, func);
+            if !self.is_sealed(ebb) {
+                self.seal_one_ebb_header_block(ebb, func);
+            }
         }
         mem::replace(&mut self.side_effects, SideEffects::new())
     }
@@ -639,10 +700,14 @@ where
                 let middle_block = self.declare_ebb_header_block(middle_ebb);
                 self.blocks[middle_block].add_predecessor(jump_inst_block, jump_inst);
                 self.mark_ebb_header_block_sealed(middle_block);
-                for old_dest in func.jump_tables[jt].as_mut_slice() {
-                    if old_dest.unwrap() == dest_ebb {
-                        *old_dest = PackedOption::from(middle_ebb);
-                    }
+                let positions = self.jump_table_entries
+                    .entry(jt)
+                    .or_insert_with(|| index_jump_table(&func.jump_tables[jt]))
+                    .remove(&dest_ebb)
+                    .unwrap_or_default();
+                let entries = func.jump_tables[jt].as_mut_slice();
+                for idx in positions {
+                    entries[idx] = PackedOption::from(middle_ebb);
                 }
                 let mut cur = FuncCursor::new(func).at_bottom(middle_ebb);
                 let middle_jump_inst = cur.ins().jump(dest_ebb, &[val]);
@@ -1116,12 +1181,19 @@ mod tests {
         let f64_var = Variable::new(2);
         let b1_var = Variable::new(3);
         let f32x4_var = Variable::new(4);
+        let i16x8_var = Variable::new(5);
+        let b8x16_var = Variable::new(6);
         ssa.use_var(&mut func, i32_var, I32, block);
         ssa.use_var(&mut func, f32_var, F32, block);
         ssa.use_var(&mut func, f64_var, F64, block);
         ssa.use_var(&mut func, b1_var, B1, block);
         ssa.use_var(&mut func, f32x4_var, F32X4, block);
+        ssa.use_var(&mut func, i16x8_var, I16X8, block);
+        ssa.use_var(&mut func, b8x16_var, B8X16, block);
         assert_eq!(func.dfg.num_ebb_params(ebb0), 0);
+        FuncCursor::new(&mut func).at_bottom(ebb0).ins().return_(&[]);
+        let flags = settings::Flags::new(&settings::builder());
+        verify_function(&func, &flags).unwrap();
     }
 
     #[test]
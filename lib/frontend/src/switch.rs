@@ -0,0 +1,268 @@
+//! Lowering of `switch` statements.
+//!
+//! Most source languages have a multi-way branch on an integer value. The `Switch` helper collects
+//! the cases of such a branch and emits a dispatch sequence for them: runs of consecutive case
+//! values are dispatched through a jump table, isolated values are tested with compares, and a
+//! binary search tree over the runs keeps the number of tests logarithmic in the number of runs.
+
+use cretonne::entity::EntityRef;
+use cretonne::ir::condcodes::IntCC;
+use cretonne::ir::{Ebb, InstBuilder, JumpTableData, Value};
+use frontend::FunctionBuilder;
+use std::collections::BTreeMap;
+
+/// The value of a switch case.
+pub type EntryIndex = u64;
+
+/// Runs of consecutive case values at least this long are dispatched through a jump table.
+const MIN_JUMP_TABLE_SIZE: usize = 4;
+
+/// Runs longer than this are split into a binary search tree before they are tested linearly.
+const MAX_LINEAR_RANGES: usize = 3;
+
+/// A builder for a multi-way branch on an integer value.
+///
+/// # Example
+///
+/// ```rust
+/// # extern crate cretonne;
+/// # extern crate cton_frontend;
+/// # use cretonne::ir::{Function, InstBuilder};
+/// # use cretonne::ir::types::I32;
+/// # use cton_frontend::{FunctionBuilder, FunctionBuilderContext, Switch, Variable};
+/// # fn main() {
+/// # let mut func = Function::new();
+/// # let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+/// # let mut builder = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+/// # let entry = builder.create_ebb();
+/// # builder.switch_to_block(entry);
+/// # let value = builder.ins().iconst(I32, 1);
+/// let case0 = builder.create_ebb();
+/// let case1 = builder.create_ebb();
+/// let otherwise = builder.create_ebb();
+///
+/// let mut switch = Switch::new();
+/// switch.set_entry(0, case0);
+/// switch.set_entry(1, case1);
+/// switch.emit(&mut builder, value, otherwise);
+/// # }
+/// ```
+pub struct Switch {
+    cases: BTreeMap<EntryIndex, Ebb>,
+}
+
+/// A run of consecutive case values.
+struct CaseRange {
+    first: EntryIndex,
+    ebbs: Vec<Ebb>,
+}
+
+impl Switch {
+    /// Create a switch without any cases.
+    pub fn new() -> Self {
+        Self { cases: BTreeMap::new() }
+    }
+
+    /// Branch to `ebb` when the switch value is `index`.
+    ///
+    /// Panics if a case for `index` has already been set.
+    pub fn set_entry(&mut self, index: EntryIndex, ebb: Ebb) {
+        let prev = self.cases.insert(index, ebb);
+        assert!(prev.is_none(), "switch case {} was already set", index);
+    }
+
+    /// Emit the dispatch sequence at the end of the current block of `bx`.
+    ///
+    /// Control is transferred to the `Ebb` of the case equal to `val`, or to `otherwise` if there
+    /// is no such case. The comparisons are unsigned, and all case values must be representable
+    /// in the type of `val`. The current block is filled afterwards, and the blocks created for
+    /// the search tree are sealed. The case blocks must not have parameters.
+    pub fn emit<Variable>(self, bx: &mut FunctionBuilder<Variable>, val: Value, otherwise: Ebb)
+    where
+        Variable: EntityRef,
+    {
+        let ranges = self.ranges();
+        emit_search_tree(bx, val, otherwise, &ranges);
+    }
+
+    /// Group the cases into runs of consecutive values.
+    fn ranges(&self) -> Vec<CaseRange> {
+        let mut ranges: Vec<CaseRange> = Vec::new();
+        for (&index, &ebb) in &self.cases {
+            if let Some(last) = ranges.last_mut() {
+                if last.first + last.ebbs.len() as EntryIndex == index {
+                    last.ebbs.push(ebb);
+                    continue;
+                }
+            }
+            ranges.push(CaseRange {
+                first: index,
+                ebbs: vec![ebb],
+            });
+        }
+        ranges
+    }
+}
+
+/// Emit a binary search over `ranges`, testing the leaves linearly.
+fn emit_search_tree<Variable>(
+    bx: &mut FunctionBuilder<Variable>,
+    val: Value,
+    otherwise: Ebb,
+    ranges: &[CaseRange],
+) where
+    Variable: EntityRef,
+{
+    if ranges.len() <= MAX_LINEAR_RANGES {
+        for range in ranges {
+            emit_range(bx, val, range);
+        }
+        bx.ins().jump(otherwise, &[]);
+        return;
+    }
+
+    let (left, right) = ranges.split_at(ranges.len() / 2);
+    let left_ebb = bx.create_ebb();
+    let right_ebb = bx.create_ebb();
+    let is_right = bx.ins().icmp_imm(
+        IntCC::UnsignedGreaterThanOrEqual,
+        val,
+        right[0].first as i64,
+    );
+    bx.ins().brnz(is_right, right_ebb, &[]);
+    bx.ins().jump(left_ebb, &[]);
+
+    bx.switch_to_block(left_ebb);
+    bx.seal_block(left_ebb);
+    emit_search_tree(bx, val, otherwise, left);
+
+    bx.switch_to_block(right_ebb);
+    bx.seal_block(right_ebb);
+    emit_search_tree(bx, val, otherwise, right);
+}
+
+/// Branch to the case in `range` that matches `val`, or fall through if none does.
+fn emit_range<Variable>(bx: &mut FunctionBuilder<Variable>, val: Value, range: &CaseRange)
+where
+    Variable: EntityRef,
+{
+    if range.ebbs.len() < MIN_JUMP_TABLE_SIZE {
+        for (offset, &ebb) in range.ebbs.iter().enumerate() {
+            let index = range.first + offset as EntryIndex;
+            let is_equal = bx.ins().icmp_imm(IntCC::Equal, val, index as i64);
+            bx.ins().brnz(is_equal, ebb, &[]);
+        }
+        return;
+    }
+
+    let mut data = JumpTableData::with_capacity(range.ebbs.len());
+    for &ebb in &range.ebbs {
+        data.push_entry(ebb);
+    }
+    let jt = bx.create_jump_table(data);
+    // Values below the range wrap around to large unsigned indices that miss the table.
+    let index = if range.first == 0 {
+        val
+    } else {
+        bx.ins().iadd_imm(val, (range.first as i64).wrapping_neg())
+    };
+    bx.ins().br_table(index, jt);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cretonne::ir::types::I32;
+    use cretonne::ir::{AbiParam, CallConv, ExternalName, Function, Signature};
+    use cretonne::settings;
+    use cretonne::verifier::verify_function;
+    use frontend::FunctionBuilderContext;
+    use Variable;
+
+    /// Build a function that returns `i` for switch case `cases[i]` and -1 otherwise.
+    fn build(cases: &[EntryIndex]) -> Function {
+        let mut sig = Signature::new(CallConv::Native);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+        let mut func = Function::with_name_signature(ExternalName::testcase("switch"), sig);
+        let mut fn_ctx = FunctionBuilderContext::<Variable>::new();
+        {
+            let mut bx = FunctionBuilder::<Variable>::new(&mut func, &mut fn_ctx);
+            let entry = bx.create_ebb();
+            bx.append_ebb_params_for_function_params(entry);
+            bx.switch_to_block(entry);
+            let val = bx.ebb_params(entry)[0];
+
+            let otherwise = bx.create_ebb();
+            let case_ebbs: Vec<Ebb> = cases.iter().map(|_| bx.create_ebb()).collect();
+            let mut switch = Switch::new();
+            for (&case, &ebb) in cases.iter().zip(&case_ebbs) {
+                switch.set_entry(case, ebb);
+            }
+            switch.emit(&mut bx, val, otherwise);
+
+            for (i, &ebb) in case_ebbs.iter().enumerate() {
+                bx.switch_to_block(ebb);
+                let ret = bx.ins().iconst(I32, i as i64);
+                bx.ins().return_(&[ret]);
+            }
+            bx.switch_to_block(otherwise);
+            let ret = bx.ins().iconst(I32, -1);
+            bx.ins().return_(&[ret]);
+
+            bx.seal_all_blocks();
+            bx.finalize();
+        }
+        let flags = settings::Flags::new(&settings::builder());
+        if let Err(err) = verify_function(&func, &flags) {
+            panic!("{}{}", func.display(None), err);
+        }
+        func
+    }
+
+    fn count(func: &Function, pattern: &str) -> usize {
+        func.to_string().matches(pattern).count()
+    }
+
+    #[test]
+    fn empty() {
+        let func = build(&[]);
+        assert_eq!(count(&func, "jump ebb1"), 1);
+        assert_eq!(count(&func, "brnz"), 0);
+    }
+
+    #[test]
+    fn compares() {
+        let func = build(&[7, 3, 1]);
+        assert_eq!(count(&func, "eq v0"), 3);
+        assert_eq!(count(&func, "br_table"), 0);
+    }
+
+    #[test]
+    fn jump_table() {
+        let func = build(&[0, 1, 2, 3, 4]);
+        assert_eq!(count(&func, "br_table"), 1);
+        assert_eq!(count(&func, "iadd_imm"), 0);
+        assert_eq!(count(&func, "icmp_imm"), 0);
+
+        let func = build(&[10, 11, 12, 13]);
+        assert_eq!(count(&func, "br_table"), 1);
+        assert_eq!(count(&func, "iadd_imm v0, -10"), 1);
+    }
+
+    #[test]
+    fn search_tree() {
+        let func = build(&[1, 10, 20, 21, 22, 23, 24, 40, 50, 60, 70, 80]);
+        assert_eq!(count(&func, "br_table"), 1);
+        assert_eq!(count(&func, "uge v0"), 3);
+        assert_eq!(count(&func, "eq v0"), 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "switch case 3 was already set")]
+    fn duplicate() {
+        let mut switch = Switch::new();
+        switch.set_entry(3, Ebb::new(0));
+        switch.set_entry(3, Ebb::new(1));
+    }
+}
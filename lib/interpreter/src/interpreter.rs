@@ -0,0 +1,923 @@
+//! The interpreter.
+
+use cretonne::entity::EntityMap;
+use cretonne::ir::condcodes::{FloatCC, IntCC};
+use cretonne::ir::instructions::BranchInfo;
+use cretonne::ir::{types, Ebb, ExternalName, Function, Inst, InstructionData, Opcode, StackSlot,
+                   TrapCode, Type, Value};
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use value::{sign_extend, truncate, DataValue};
+
+/// The maximum depth of nested calls before the interpreter reports a stack overflow.
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// An error stopping the evaluation of a function.
+#[derive(Debug, PartialEq, Eq)]
+pub enum InterpreterError {
+    /// The function trapped, either explicitly or by an instruction like `udiv` with a zero
+    /// divisor.
+    Trap(TrapCode),
+
+    /// A called function hasn't been added to the interpreter.
+    UnknownFunction(ExternalName),
+
+    /// A function was called with arguments that don't match its signature.
+    BadArguments(ExternalName),
+
+    /// The function contains an instruction the interpreter doesn't support.
+    Unsupported(Opcode),
+
+    /// The function isn't valid IL, for example because it uses a value before defining it.
+    InvalidCode(String),
+
+    /// The interpreter executed more instructions than allowed by `set_fuel`.
+    OutOfFuel,
+}
+
+impl fmt::Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InterpreterError::Trap(code) => write!(f, "trap: {}", code),
+            InterpreterError::UnknownFunction(ref name) => write!(f, "unknown function {}", name),
+            InterpreterError::BadArguments(ref name) => write!(f, "bad arguments to {}", name),
+            InterpreterError::Unsupported(opcode) => {
+                write!(f, "unsupported instruction {}", opcode)
+            }
+            InterpreterError::InvalidCode(ref msg) => f.write_str(msg),
+            InterpreterError::OutOfFuel => f.write_str("out of fuel"),
+        }
+    }
+}
+
+impl StdError for InterpreterError {
+    fn description(&self) -> &str {
+        match *self {
+            InterpreterError::Trap(_) => "trap",
+            InterpreterError::UnknownFunction(_) => "unknown function",
+            InterpreterError::BadArguments(_) => "bad arguments",
+            InterpreterError::Unsupported(_) => "unsupported instruction",
+            InterpreterError::InvalidCode(_) => "invalid code",
+            InterpreterError::OutOfFuel => "out of fuel",
+        }
+    }
+}
+
+type Result<T> = ::std::result::Result<T, InterpreterError>;
+
+/// An interpreter for a set of functions that can call each other.
+pub struct Interpreter<'a> {
+    functions: HashMap<ExternalName, &'a Function>,
+    fuel: Option<u64>,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Create an interpreter without any functions.
+    pub fn new() -> Self {
+        Self {
+            functions: HashMap::new(),
+            fuel: None,
+        }
+    }
+
+    /// Add `func` to the functions that can be called, under its name.
+    ///
+    /// A function added earlier under the same name is replaced.
+    pub fn add_function(&mut self, func: &'a Function) {
+        self.functions.insert(func.name.clone(), func);
+    }
+
+    /// Limit the number of instructions executed by the following calls, or remove the limit.
+    ///
+    /// When the limit is reached, the call fails with `InterpreterError::OutOfFuel`. This is
+    /// useful for evaluating functions that may not terminate.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Call the function named `name` with `args`, and return its results.
+    pub fn call(&mut self, name: &ExternalName, args: &[DataValue]) -> Result<Vec<DataValue>> {
+        self.call_at_depth(name, args, 0)
+    }
+
+    fn call_at_depth(
+        &mut self,
+        name: &ExternalName,
+        args: &[DataValue],
+        depth: usize,
+    ) -> Result<Vec<DataValue>> {
+        if depth >= MAX_CALL_DEPTH {
+            return Err(InterpreterError::Trap(TrapCode::StackOverflow));
+        }
+        let func = match self.functions.get(name) {
+            Some(&func) => func,
+            None => return Err(InterpreterError::UnknownFunction(name.clone())),
+        };
+        let params = &func.signature.params;
+        if args.len() != params.len() ||
+            args.iter().zip(params).any(|(arg, p)| arg.ty() != p.value_type)
+        {
+            return Err(InterpreterError::BadArguments(name.clone()));
+        }
+        let entry = func.layout.entry_block().ok_or_else(|| {
+            InterpreterError::InvalidCode(format!("{} has no entry block", name))
+        })?;
+
+        let mut frame = Frame::new(func);
+        frame.set_params(entry, args)?;
+        let mut ebb = entry;
+        loop {
+            ebb = match self.run_ebb(&mut frame, ebb, depth)? {
+                Ok(dest) => dest,
+                Err(results) => return Ok(results),
+            };
+        }
+    }
+
+    /// Run the instructions in `ebb` and pass control on.
+    ///
+    /// Returns the EBB to continue in, or the results of a `return` instruction.
+    fn run_ebb(
+        &mut self,
+        frame: &mut Frame,
+        ebb: Ebb,
+        depth: usize,
+    ) -> Result<::std::result::Result<Ebb, Vec<DataValue>>> {
+        let func = frame.func;
+        for inst in func.layout.ebb_insts(ebb) {
+            if let Some(ref mut fuel) = self.fuel {
+                if *fuel == 0 {
+                    return Err(InterpreterError::OutOfFuel);
+                }
+                *fuel -= 1;
+            }
+            match self.step(frame, inst, depth)? {
+                Control::Continue => {}
+                Control::Jump(dest, args) => {
+                    frame.set_params(dest, &args)?;
+                    return Ok(Ok(dest));
+                }
+                Control::Return(results) => return Ok(Err(results)),
+            }
+        }
+        Err(InterpreterError::InvalidCode(
+            format!("{} doesn't end with a terminator", ebb),
+        ))
+    }
+
+    /// Execute a single instruction.
+    fn step(&mut self, frame: &mut Frame, inst: Inst, depth: usize) -> Result<Control> {
+        let func = frame.func;
+        let dfg = &func.dfg;
+        let data = &dfg[inst];
+        let fixed = frame.get_all(dfg.inst_fixed_args(inst))?;
+
+        // Branches pass their variable arguments on to the destination.
+        if let BranchInfo::SingleDest(dest, dest_args) = data.analyze_branch(&dfg.value_lists) {
+            let taken = match data.opcode() {
+                Opcode::Jump | Opcode::Fallthrough => true,
+                Opcode::Brz => !truthy(fixed[0]),
+                Opcode::Brnz => truthy(fixed[0]),
+                Opcode::BrIcmp => {
+                    let cond = match *data {
+                        InstructionData::BranchIcmp { cond, .. } => cond,
+                        _ => unreachable!(),
+                    };
+                    icmp(cond, fixed[0], fixed[1])
+                }
+                opcode => return Err(InterpreterError::Unsupported(opcode)),
+            };
+            return Ok(if taken {
+                Control::Jump(dest, frame.get_all(dest_args)?)
+            } else {
+                Control::Continue
+            });
+        }
+
+        match *data {
+            InstructionData::BranchTable { table, .. } => {
+                let idx = match fixed[0] {
+                    DataValue::Int(_, idx) => idx,
+                    _ => return Err(InterpreterError::Unsupported(data.opcode())),
+                };
+                let jt = &func.jump_tables[table];
+                return Ok(if idx < jt.len() as u64 {
+                    match jt.get_entry(idx as usize) {
+                        Some(dest) => Control::Jump(dest, Vec::new()),
+                        None => Control::Continue,
+                    }
+                } else {
+                    Control::Continue
+                });
+            }
+            InstructionData::MultiAry { opcode: Opcode::Return, .. } => {
+                return Ok(Control::Return(frame.get_all(dfg.inst_args(inst))?));
+            }
+            InstructionData::Call { func_ref, .. } => {
+                let args = frame.get_all(dfg.inst_args(inst))?;
+                let name = &dfg.ext_funcs[func_ref].name;
+                let results = self.call_at_depth(name, &args, depth + 1)?;
+                frame.set_all(dfg.inst_results(inst), &results)?;
+                return Ok(Control::Continue);
+            }
+            InstructionData::Trap { code, .. } => return Err(InterpreterError::Trap(code)),
+            InstructionData::CondTrap { opcode, code, .. } => {
+                if truthy(fixed[0]) == (opcode == Opcode::Trapnz) {
+                    return Err(InterpreterError::Trap(code));
+                }
+                return Ok(Control::Continue);
+            }
+            InstructionData::StackStore { stack_slot, offset, .. } => {
+                let bytes = frame.slot_bytes(stack_slot, offset.into(), fixed[0].ty())?;
+                store(bytes, fixed[0]);
+                return Ok(Control::Continue);
+            }
+            _ => {}
+        }
+
+        let results = dfg.inst_results(inst);
+        if results.len() != 1 {
+            return Err(InterpreterError::Unsupported(data.opcode()));
+        }
+        let result = results[0];
+        let ty = dfg.value_type(result);
+        let value = match *data {
+            InstructionData::StackLoad { stack_slot, offset, .. } => {
+                load(frame.slot_bytes(stack_slot, offset.into(), ty)?, ty)?
+            }
+            _ => evaluate(data, &fixed, ty)?,
+        };
+        frame.set(result, value);
+        Ok(Control::Continue)
+    }
+}
+
+/// How execution continues after an instruction.
+enum Control {
+    /// Continue with the next instruction.
+    Continue,
+    /// Continue in an EBB, passing it arguments.
+    Jump(Ebb, Vec<DataValue>),
+    /// Return from the function.
+    Return(Vec<DataValue>),
+}
+
+/// The state of a function being evaluated.
+struct Frame<'f> {
+    func: &'f Function,
+    values: EntityMap<Value, Option<DataValue>>,
+    slots: EntityMap<StackSlot, Vec<u8>>,
+}
+
+impl<'f> Frame<'f> {
+    fn new(func: &'f Function) -> Self {
+        let mut slots = EntityMap::new();
+        for ss in func.stack_slots.keys() {
+            slots[ss] = vec![0; func.stack_slots[ss].size as usize];
+        }
+        Self {
+            func,
+            values: EntityMap::new(),
+            slots,
+        }
+    }
+
+    fn get(&self, value: Value) -> Result<DataValue> {
+        let value = self.func.dfg.resolve_aliases(value);
+        self.values.get(value).cloned().and_then(|v| v).ok_or_else(|| {
+            InterpreterError::InvalidCode(format!("{} is used before it is defined", value))
+        })
+    }
+
+    fn get_all(&self, values: &[Value]) -> Result<Vec<DataValue>> {
+        values.iter().map(|&v| self.get(v)).collect()
+    }
+
+    fn set(&mut self, value: Value, data: DataValue) {
+        self.values[value] = Some(data);
+    }
+
+    fn set_all(&mut self, values: &[Value], data: &[DataValue]) -> Result<()> {
+        if values.len() != data.len() {
+            return Err(InterpreterError::InvalidCode(
+                format!("expected {} values, got {}", values.len(), data.len()),
+            ));
+        }
+        for (&value, &data) in values.iter().zip(data) {
+            self.set(value, data);
+        }
+        Ok(())
+    }
+
+    fn set_params(&mut self, ebb: Ebb, args: &[DataValue]) -> Result<()> {
+        let func = self.func;
+        self.set_all(func.dfg.ebb_params(ebb), args)
+    }
+
+    /// Get the bytes of a value of type `ty` at `offset` in a stack slot.
+    fn slot_bytes(&mut self, ss: StackSlot, offset: i64, ty: Type) -> Result<&mut [u8]> {
+        let slot = &mut self.slots[ss];
+        let end = offset + i64::from(ty.bytes());
+        if offset < 0 || end > slot.len() as i64 || ty.bytes() == 0 {
+            return Err(InterpreterError::Trap(TrapCode::OutOfBounds));
+        }
+        Ok(&mut slot[offset as usize..end as usize])
+    }
+}
+
+/// Store `value` in little-endian order.
+fn store(bytes: &mut [u8], value: DataValue) {
+    let bits = match value {
+        DataValue::Int(_, x) => x,
+        DataValue::Bool(ty, b) => if b { truncate(ty, !0) } else { 0 },
+        DataValue::F32(x) => u64::from(x.to_bits()),
+        DataValue::F64(x) => x.to_bits(),
+    };
+    for (idx, byte) in bytes.iter_mut().enumerate() {
+        *byte = (bits >> (8 * idx)) as u8;
+    }
+}
+
+/// Load a value of type `ty` stored in little-endian order.
+fn load(bytes: &[u8], ty: Type) -> Result<DataValue> {
+    let bits = bytes.iter().rev().fold(0, |bits, &byte| bits << 8 | u64::from(byte));
+    Ok(if ty.is_int() {
+        DataValue::Int(ty, bits)
+    } else if ty.is_bool() {
+        DataValue::Bool(ty, bits != 0)
+    } else if ty == types::F32 {
+        DataValue::F32(f32::from_bits(bits as u32))
+    } else if ty == types::F64 {
+        DataValue::F64(f64::from_bits(bits))
+    } else {
+        return Err(InterpreterError::Unsupported(Opcode::StackLoad));
+    })
+}
+
+/// Is `value` a true boolean or a non-zero integer?
+fn truthy(value: DataValue) -> bool {
+    match value {
+        DataValue::Int(_, x) => x != 0,
+        DataValue::Bool(_, b) => b,
+        DataValue::F32(x) => x != 0.0,
+        DataValue::F64(x) => x != 0.0,
+    }
+}
+
+/// Get the immediate operand of an instruction with an integer immediate.
+fn immediate(data: &InstructionData) -> Option<i64> {
+    match *data {
+        InstructionData::UnaryImm { imm, .. } |
+        InstructionData::BinaryImm { imm, .. } |
+        InstructionData::IntCompareImm { imm, .. } => Some(imm.into()),
+        _ => None,
+    }
+}
+
+/// Get the integer operands of an instruction.
+///
+/// The second operand is the immediate for instructions that have one.
+fn int_operands(data: &InstructionData, args: &[DataValue]) -> Option<(Type, u64, u64)> {
+    let (ty, a) = match args.first() {
+        Some(&DataValue::Int(ty, a)) => (ty, a),
+        _ => return None,
+    };
+    match (immediate(data), args.get(1)) {
+        (Some(imm), _) => Some((ty, a, truncate(ty, imm as u64))),
+        (None, Some(&DataValue::Int(_, b))) => Some((ty, a, b)),
+        _ => None,
+    }
+}
+
+/// Get the value of a float argument as an `f64`, which represents all `f32` values exactly.
+fn float(value: DataValue) -> Option<f64> {
+    match value {
+        DataValue::F32(x) => Some(f64::from(x)),
+        DataValue::F64(x) => Some(x),
+        _ => None,
+    }
+}
+
+/// Make a float of type `ty`, rounding `x` to `f32` if needed.
+///
+/// Rounding the result of an `f64` addition, subtraction, multiplication, division, or square root
+/// of `f32` values gives the correctly rounded `f32` result.
+fn float_value(ty: Type, x: f64) -> DataValue {
+    if ty == types::F32 {
+        DataValue::F32(x as f32)
+    } else {
+        DataValue::F64(x)
+    }
+}
+
+/// Evaluate an instruction that computes a single value from its arguments.
+fn evaluate(data: &InstructionData, args: &[DataValue], ty: Type) -> Result<DataValue> {
+    let opcode = data.opcode();
+    let unsupported = InterpreterError::Unsupported(opcode);
+    let value = match opcode {
+        Opcode::Iconst => DataValue::int(ty, immediate(data).ok_or(unsupported)?),
+        Opcode::Bconst => {
+            match *data {
+                InstructionData::UnaryBool { imm, .. } => DataValue::Bool(ty, imm),
+                _ => return Err(unsupported),
+            }
+        }
+        Opcode::F32const => {
+            match *data {
+                InstructionData::UnaryIeee32 { imm, .. } => {
+                    DataValue::F32(f32::from_bits(imm.bits()))
+                }
+                _ => return Err(unsupported),
+            }
+        }
+        Opcode::F64const => {
+            match *data {
+                InstructionData::UnaryIeee64 { imm, .. } => {
+                    DataValue::F64(f64::from_bits(imm.bits()))
+                }
+                _ => return Err(unsupported),
+            }
+        }
+        Opcode::Copy | Opcode::Spill | Opcode::Fill => args[0],
+        Opcode::Select => if truthy(args[0]) { args[1] } else { args[2] },
+
+        Opcode::Band | Opcode::Bor | Opcode::Bxor | Opcode::BandNot | Opcode::BorNot |
+        Opcode::BxorNot if args[0].ty().is_bool() => {
+            match (args[0], args[1]) {
+                (DataValue::Bool(_, a), DataValue::Bool(_, b)) => {
+                    DataValue::Bool(ty, bool_binary(opcode, a, b))
+                }
+                _ => return Err(unsupported),
+            }
+        }
+        Opcode::Bnot if args[0].ty().is_bool() => DataValue::Bool(ty, !truthy(args[0])),
+
+        Opcode::Iadd | Opcode::Isub | Opcode::Imul | Opcode::Udiv | Opcode::Sdiv |
+        Opcode::Urem | Opcode::Srem | Opcode::IaddImm | Opcode::ImulImm | Opcode::UdivImm |
+        Opcode::SdivImm | Opcode::UremImm | Opcode::SremImm | Opcode::IrsubImm | Opcode::Band |
+        Opcode::Bor | Opcode::Bxor | Opcode::BandNot | Opcode::BorNot | Opcode::BxorNot |
+        Opcode::BandImm | Opcode::BorImm | Opcode::BxorImm | Opcode::Rotl | Opcode::Rotr |
+        Opcode::RotlImm | Opcode::RotrImm | Opcode::Ishl | Opcode::Ushr | Opcode::Sshr |
+        Opcode::IshlImm | Opcode::UshrImm | Opcode::SshrImm => {
+            let (ty, a, b) = int_operands(data, args).ok_or(unsupported)?;
+            DataValue::Int(ty, truncate(ty, int_binary(opcode, ty, a, b)?))
+        }
+        Opcode::Bnot | Opcode::Clz | Opcode::Cls | Opcode::Ctz | Opcode::Popcnt => {
+            match args[0] {
+                DataValue::Int(ty, a) => DataValue::Int(ty, truncate(ty, int_unary(opcode, ty, a))),
+                _ => return Err(unsupported),
+            }
+        }
+        Opcode::Icmp | Opcode::IcmpImm => {
+            let cond = match *data {
+                InstructionData::IntCompare { cond, .. } |
+                InstructionData::IntCompareImm { cond, .. } => cond,
+                _ => return Err(unsupported),
+            };
+            let (ty, a, b) = int_operands(data, args).ok_or(unsupported)?;
+            DataValue::Bool(types::B1, icmp(cond, DataValue::Int(ty, a), DataValue::Int(ty, b)))
+        }
+
+        Opcode::Fcmp => {
+            let cond = match *data {
+                InstructionData::FloatCompare { cond, .. } => cond,
+                _ => return Err(unsupported),
+            };
+            match (float(args[0]), float(args[1])) {
+                (Some(a), Some(b)) => DataValue::Bool(types::B1, fcmp(cond, a, b)),
+                _ => return Err(unsupported),
+            }
+        }
+        Opcode::Fneg | Opcode::Fabs | Opcode::Fcopysign => float_sign(opcode, args)?,
+        Opcode::Fma => {
+            match (args[0], args[1], args[2]) {
+                (DataValue::F32(a), DataValue::F32(b), DataValue::F32(c)) => {
+                    DataValue::F32(a.mul_add(b, c))
+                }
+                (DataValue::F64(a), DataValue::F64(b), DataValue::F64(c)) => {
+                    DataValue::F64(a.mul_add(b, c))
+                }
+                _ => return Err(unsupported),
+            }
+        }
+        Opcode::Fadd | Opcode::Fsub | Opcode::Fmul | Opcode::Fdiv | Opcode::Fmin |
+        Opcode::Fmax => {
+            match (float(args[0]), float(args[1])) {
+                (Some(a), Some(b)) => float_value(ty, float_binary(opcode, a, b)),
+                _ => return Err(unsupported),
+            }
+        }
+        Opcode::Sqrt | Opcode::Ceil | Opcode::Floor | Opcode::Trunc | Opcode::Nearest => {
+            let x = float(args[0]).ok_or(unsupported)?;
+            float_value(ty, float_unary(opcode, x))
+        }
+
+        Opcode::Bint | Opcode::Bmask | Opcode::Breduce | Opcode::Bextend => {
+            let b = match args[0] {
+                DataValue::Bool(_, b) => b,
+                _ => return Err(unsupported),
+            };
+            match opcode {
+                Opcode::Bint => DataValue::Int(ty, b as u64),
+                Opcode::Bmask => DataValue::Int(ty, if b { truncate(ty, !0) } else { 0 }),
+                _ => DataValue::Bool(ty, b),
+            }
+        }
+        Opcode::Ireduce | Opcode::Uextend | Opcode::Sextend => {
+            match args[0] {
+                DataValue::Int(_, x) if opcode != Opcode::Sextend => {
+                    DataValue::Int(ty, truncate(ty, x))
+                }
+                DataValue::Int(from, x) => DataValue::int(ty, sign_extend(from, x)),
+                _ => return Err(unsupported),
+            }
+        }
+        Opcode::Fpromote | Opcode::Fdemote => {
+            float_value(ty, float(args[0]).ok_or(unsupported)?)
+        }
+        Opcode::FcvtFromSint | Opcode::FcvtFromUint => {
+            let (from, x) = match args[0] {
+                DataValue::Int(from, x) => (from, x),
+                _ => return Err(unsupported),
+            };
+            match (opcode == Opcode::FcvtFromSint, ty == types::F32) {
+                (true, true) => DataValue::F32(sign_extend(from, x) as f32),
+                (true, false) => DataValue::F64(sign_extend(from, x) as f64),
+                (false, true) => DataValue::F32(x as f32),
+                (false, false) => DataValue::F64(x as f64),
+            }
+        }
+        Opcode::FcvtToSint | Opcode::FcvtToUint => {
+            let x = float(args[0]).ok_or(unsupported)?;
+            if x.is_nan() {
+                return Err(InterpreterError::Trap(TrapCode::BadConversionToInteger));
+            }
+            let x = x.trunc();
+            let bits = i32::from(ty.lane_bits());
+            let (min, max) = if opcode == Opcode::FcvtToSint {
+                (-(2f64.powi(bits - 1)), 2f64.powi(bits - 1))
+            } else {
+                (0.0, 2f64.powi(bits))
+            };
+            if x < min || x >= max {
+                return Err(InterpreterError::Trap(TrapCode::IntegerOverflow));
+            }
+            if opcode == Opcode::FcvtToSint {
+                DataValue::int(ty, x as i64)
+            } else {
+                DataValue::Int(ty, x as u64)
+            }
+        }
+        Opcode::Bitcast => {
+            match (args[0], ty) {
+                (DataValue::Int(_, x), types::F32) => DataValue::F32(f32::from_bits(x as u32)),
+                (DataValue::Int(_, x), types::F64) => DataValue::F64(f64::from_bits(x)),
+                (DataValue::F32(x), types::I32) => DataValue::Int(ty, u64::from(x.to_bits())),
+                (DataValue::F64(x), types::I64) => DataValue::Int(ty, x.to_bits()),
+                _ => return Err(unsupported),
+            }
+        }
+        _ => return Err(unsupported),
+    };
+    Ok(value)
+}
+
+/// Evaluate a bitwise operation on booleans.
+fn bool_binary(opcode: Opcode, a: bool, b: bool) -> bool {
+    match opcode {
+        Opcode::Band => a & b,
+        Opcode::Bor => a | b,
+        Opcode::Bxor => a ^ b,
+        Opcode::BandNot => a & !b,
+        Opcode::BorNot => a | !b,
+        Opcode::BxorNot => a ^ !b,
+        _ => unreachable!(),
+    }
+}
+
+/// Evaluate a binary integer operation on operands of type `ty`.
+///
+/// The result may have bits set above the width of `ty`.
+fn int_binary(opcode: Opcode, ty: Type, a: u64, b: u64) -> Result<u64> {
+    let bits = u64::from(ty.lane_bits());
+    let (sa, sb) = (sign_extend(ty, a), sign_extend(ty, b));
+    // Shift amounts are taken modulo the width of the type.
+    let amt = (b & (bits - 1)) as u32;
+    let zero_check = || if b == 0 {
+        Err(InterpreterError::Trap(TrapCode::IntegerDivisionByZero))
+    } else {
+        Ok(())
+    };
+    Ok(match opcode {
+        Opcode::Iadd | Opcode::IaddImm => a.wrapping_add(b),
+        Opcode::Isub => a.wrapping_sub(b),
+        Opcode::IrsubImm => b.wrapping_sub(a),
+        Opcode::Imul | Opcode::ImulImm => a.wrapping_mul(b),
+        Opcode::Udiv | Opcode::UdivImm => {
+            zero_check()?;
+            a / b
+        }
+        Opcode::Urem | Opcode::UremImm => {
+            zero_check()?;
+            a % b
+        }
+        Opcode::Sdiv | Opcode::SdivImm => {
+            zero_check()?;
+            let q = sa.wrapping_div(sb);
+            // The quotient overflows when the smallest value is divided by -1.
+            if sb == -1 && sa == sign_extend(ty, 1 << (bits - 1)) {
+                return Err(InterpreterError::Trap(TrapCode::IntegerOverflow));
+            }
+            q as u64
+        }
+        Opcode::Srem | Opcode::SremImm => {
+            zero_check()?;
+            sa.wrapping_rem(sb) as u64
+        }
+        Opcode::Band | Opcode::BandImm => a & b,
+        Opcode::Bor | Opcode::BorImm => a | b,
+        Opcode::Bxor | Opcode::BxorImm => a ^ b,
+        Opcode::BandNot => a & !b,
+        Opcode::BorNot => a | !b,
+        Opcode::BxorNot => a ^ !b,
+        Opcode::Ishl | Opcode::IshlImm => a << amt,
+        Opcode::Ushr | Opcode::UshrImm => a >> amt,
+        Opcode::Sshr | Opcode::SshrImm => (sa >> amt) as u64,
+        Opcode::Rotl | Opcode::RotlImm if amt != 0 => a << amt | a >> (bits as u32 - amt),
+        Opcode::Rotr | Opcode::RotrImm if amt != 0 => a >> amt | a << (bits as u32 - amt),
+        Opcode::Rotl | Opcode::RotlImm | Opcode::Rotr | Opcode::RotrImm => a,
+        _ => unreachable!(),
+    })
+}
+
+/// Evaluate a unary integer operation on an operand of type `ty`.
+fn int_unary(opcode: Opcode, ty: Type, a: u64) -> u64 {
+    // The number of unused bits above the width of `ty`.
+    let unused = 64 - u32::from(ty.lane_bits());
+    match opcode {
+        Opcode::Bnot => !a,
+        Opcode::Clz => u64::from(a.leading_zeros() - unused),
+        Opcode::Cls => {
+            let sa = sign_extend(ty, a);
+            let magnitude = if sa < 0 { !sa } else { sa } as u64;
+            u64::from(magnitude.leading_zeros() - unused - 1)
+        }
+        Opcode::Ctz => u64::from(a.trailing_zeros().min(64 - unused)),
+        Opcode::Popcnt => u64::from(a.count_ones()),
+        _ => unreachable!(),
+    }
+}
+
+/// Evaluate an integer comparison.
+fn icmp(cond: IntCC, a: DataValue, b: DataValue) -> bool {
+    let (ty, a, b) = match (a, b) {
+        (DataValue::Int(ty, a), DataValue::Int(_, b)) => (ty, a, b),
+        _ => return false,
+    };
+    let (sa, sb) = (sign_extend(ty, a), sign_extend(ty, b));
+    match cond {
+        IntCC::Equal => a == b,
+        IntCC::NotEqual => a != b,
+        IntCC::SignedLessThan => sa < sb,
+        IntCC::SignedGreaterThanOrEqual => sa >= sb,
+        IntCC::SignedGreaterThan => sa > sb,
+        IntCC::SignedLessThanOrEqual => sa <= sb,
+        IntCC::UnsignedLessThan => a < b,
+        IntCC::UnsignedGreaterThanOrEqual => a >= b,
+        IntCC::UnsignedGreaterThan => a > b,
+        IntCC::UnsignedLessThanOrEqual => a <= b,
+    }
+}
+
+/// Evaluate a float comparison.
+fn fcmp(cond: FloatCC, a: f64, b: f64) -> bool {
+    let unordered = a.is_nan() || b.is_nan();
+    match cond {
+        FloatCC::Ordered => !unordered,
+        FloatCC::Unordered => unordered,
+        FloatCC::Equal => a == b,
+        FloatCC::NotEqual => a != b,
+        FloatCC::OrderedNotEqual => !unordered && a != b,
+        FloatCC::UnorderedOrEqual => unordered || a == b,
+        FloatCC::LessThan => a < b,
+        FloatCC::LessThanOrEqual => a <= b,
+        FloatCC::GreaterThan => a > b,
+        FloatCC::GreaterThanOrEqual => a >= b,
+        FloatCC::UnorderedOrLessThan => unordered || a < b,
+        FloatCC::UnorderedOrLessThanOrEqual => unordered || a <= b,
+        FloatCC::UnorderedOrGreaterThan => unordered || a > b,
+        FloatCC::UnorderedOrGreaterThanOrEqual => unordered || a >= b,
+    }
+}
+
+/// Evaluate a binary float operation.
+fn float_binary(opcode: Opcode, a: f64, b: f64) -> f64 {
+    match opcode {
+        Opcode::Fadd => a + b,
+        Opcode::Fsub => a - b,
+        Opcode::Fmul => a * b,
+        Opcode::Fdiv => a / b,
+        // `fmin` and `fmax` return NaN if either operand is NaN, and order -0.0 before 0.0.
+        Opcode::Fmin | Opcode::Fmax if a.is_nan() || b.is_nan() => a + b,
+        Opcode::Fmin if a == b => if a.is_sign_negative() { a } else { b },
+        Opcode::Fmax if a == b => if a.is_sign_negative() { b } else { a },
+        Opcode::Fmin => a.min(b),
+        Opcode::Fmax => a.max(b),
+        _ => unreachable!(),
+    }
+}
+
+/// Evaluate a unary float operation.
+fn float_unary(opcode: Opcode, x: f64) -> f64 {
+    match opcode {
+        Opcode::Sqrt => x.sqrt(),
+        Opcode::Ceil => x.ceil(),
+        Opcode::Floor => x.floor(),
+        Opcode::Trunc => x.trunc(),
+        // Round to nearest, with ties to even.
+        Opcode::Nearest if (x - x.trunc()).abs() == 0.5 => 2.0 * (x / 2.0).round(),
+        Opcode::Nearest => x.round(),
+        _ => unreachable!(),
+    }
+}
+
+/// Evaluate an operation on the sign bit of a float, which leaves the other bits alone.
+fn float_sign(opcode: Opcode, args: &[DataValue]) -> Result<DataValue> {
+    let sign_of = |value: DataValue| match value {
+        DataValue::F32(x) => x.is_sign_negative(),
+        DataValue::F64(x) => x.is_sign_negative(),
+        _ => false,
+    };
+    let negative = match opcode {
+        Opcode::Fneg => !sign_of(args[0]),
+        Opcode::Fabs => false,
+        _ => sign_of(args[1]),
+    };
+    match args[0] {
+        DataValue::F32(x) => {
+            let bits = x.to_bits() & !(1 << 31) | (negative as u32) << 31;
+            Ok(DataValue::F32(f32::from_bits(bits)))
+        }
+        DataValue::F64(x) => {
+            let bits = x.to_bits() & !(1 << 63) | (negative as u64) << 63;
+            Ok(DataValue::F64(f64::from_bits(bits)))
+        }
+        _ => Err(InterpreterError::Unsupported(opcode)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cton_reader::parse_functions;
+
+    /// Parse `text` and call its first function with `args`.
+    fn run(text: &str, args: &[DataValue]) -> Result<Vec<DataValue>> {
+        let functions = parse_functions(text).unwrap();
+        let mut interpreter = Interpreter::new();
+        for func in &functions {
+            interpreter.add_function(func);
+        }
+        interpreter.set_fuel(Some(10_000));
+        interpreter.call(&functions[0].name, args)
+    }
+
+    fn i32(x: i64) -> DataValue {
+        DataValue::int(types::I32, x)
+    }
+
+    #[test]
+    fn arithmetic() {
+        let text = "
+            function %f(i32, i32) -> i32 {
+            ebb0(v0: i32, v1: i32):
+                v2 = imul v0, v1
+                v3 = iadd_imm v2, -3
+                v4 = sdiv v3, v1
+                return v4
+            }";
+        assert_eq!(run(text, &[i32(7), i32(2)]), Ok(vec![i32(5)]));
+        assert_eq!(run(text, &[i32(-7), i32(2)]), Ok(vec![i32(-8)]));
+        assert_eq!(
+            run(text, &[i32(7), i32(0)]),
+            Err(InterpreterError::Trap(TrapCode::IntegerDivisionByZero))
+        );
+        assert_eq!(
+            run(text, &[i32(7)]),
+            Err(InterpreterError::BadArguments(ExternalName::testcase("f")))
+        );
+    }
+
+    #[test]
+    fn integers() {
+        let (a, b) = (0xf000_0001, 4);
+        let binary = |opcode| int_binary(opcode, types::I32, a, b).map(|x| truncate(types::I32, x));
+        assert_eq!(binary(Opcode::Rotl), Ok(0x0000_001f));
+        assert_eq!(binary(Opcode::Rotr), Ok(0x1f00_0000));
+        assert_eq!(binary(Opcode::Sshr), Ok(0xff00_0000));
+        assert_eq!(binary(Opcode::Ushr), Ok(0x0f00_0000));
+        assert_eq!(binary(Opcode::Srem), Ok(truncate(types::I32, -3i64 as u64)));
+        assert_eq!(
+            int_binary(Opcode::Sdiv, types::I8, 0x80, 0xff),
+            Err(InterpreterError::Trap(TrapCode::IntegerOverflow))
+        );
+        assert_eq!(int_unary(Opcode::Clz, types::I16, 1), 15);
+        assert_eq!(int_unary(Opcode::Cls, types::I16, 0xffff), 15);
+        assert_eq!(int_unary(Opcode::Ctz, types::I16, 0), 16);
+        assert_eq!(int_unary(Opcode::Popcnt, types::I64, !0), 64);
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(float_unary(Opcode::Nearest, 2.5), 2.0);
+        assert_eq!(float_unary(Opcode::Nearest, -3.5), -4.0);
+        assert_eq!(float_unary(Opcode::Nearest, 2.6), 3.0);
+        assert!(float_binary(Opcode::Fmin, 1.0, ::std::f64::NAN).is_nan());
+        assert!(float_binary(Opcode::Fmin, 0.0, -0.0).is_sign_negative());
+        assert!(!fcmp(FloatCC::Equal, ::std::f64::NAN, ::std::f64::NAN));
+        assert!(fcmp(FloatCC::UnorderedOrEqual, ::std::f64::NAN, 1.0));
+        assert_eq!(
+            float_sign(Opcode::Fcopysign, &[DataValue::F32(1.5), DataValue::F32(-0.0)]),
+            Ok(DataValue::F32(-1.5))
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        // Sum the numbers from 1 to n with a loop.
+        let text = "
+            function %sum(i64) -> i64 {
+            ebb0(v0: i64):
+                v1 = iconst.i64 0
+                jump ebb1(v0, v1)
+
+            ebb1(v2: i64, v3: i64):
+                brz v2, ebb2(v3)
+                v4 = iadd v3, v2
+                v5 = iadd_imm v2, -1
+                jump ebb1(v5, v4)
+
+            ebb2(v6: i64):
+                return v6
+            }";
+        let n = DataValue::int(types::I64, 100);
+        assert_eq!(run(text, &[n]), Ok(vec![DataValue::int(types::I64, 5050)]));
+        let n = DataValue::int(types::I64, 10_000);
+        assert_eq!(run(text, &[n]), Err(InterpreterError::OutOfFuel));
+    }
+
+    #[test]
+    fn calls_and_stack_slots() {
+        let text = "
+            function %double(i32) -> i32 {
+                ss0 = explicit_slot 8
+                fn0 = function %add(i32, i32) -> i32
+
+            ebb0(v0: i32):
+                stack_store v0, ss0+4
+                v1 = stack_load.i32 ss0+4
+                v2 = call fn0(v0, v1)
+                return v2
+            }
+
+            function %add(i32, i32) -> i32 {
+            ebb0(v0: i32, v1: i32):
+                v2 = iadd v0, v1
+                return v2
+            }";
+        assert_eq!(run(text, &[i32(21)]), Ok(vec![i32(42)]));
+    }
+
+    #[test]
+    fn traps() {
+        let text = "
+            function %f(i32) {
+                jt0 = jump_table ebb1, ebb2
+
+            ebb0(v0: i32):
+                br_table v0, jt0
+                trap user7
+
+            ebb1:
+                trapz v0, user1
+                return
+
+            ebb2:
+                trapnz v0, user2
+                return
+            }";
+        assert_eq!(
+            run(text, &[i32(0)]),
+            Err(InterpreterError::Trap(TrapCode::User(1)))
+        );
+        assert_eq!(
+            run(text, &[i32(1)]),
+            Err(InterpreterError::Trap(TrapCode::User(2)))
+        );
+        assert_eq!(
+            run(text, &[i32(2)]),
+            Err(InterpreterError::Trap(TrapCode::User(7)))
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! An interpreter for Cretonne IL.
+//!
+//! The [`Interpreter`](struct.Interpreter.html) evaluates functions directly from their IL,
+//! without compiling them. Its results don't depend on the host or on any target ISA, so it serves
+//! as a reference for the semantics of Cretonne instructions:
+//!
+//! ```
+//! # extern crate cretonne;
+//! # extern crate cton_interpreter;
+//! # extern crate cton_reader;
+//! # fn main() {
+//! use cretonne::ir::types;
+//! use cton_interpreter::{DataValue, Interpreter};
+//! use cton_reader::parse_functions;
+//!
+//! let functions = parse_functions(
+//!     "function %twice(i32) -> i32 {
+//!      ebb0(v0: i32):
+//!          v1 = imul_imm v0, 2
+//!          return v1
+//!      }",
+//! ).unwrap();
+//! let mut interpreter = Interpreter::new();
+//! interpreter.add_function(&functions[0]);
+//! let results = interpreter.call(&functions[0].name, &[DataValue::int(types::I32, 21)]);
+//! assert_eq!(results, Ok(vec![DataValue::int(types::I32, 42)]));
+//! # }
+//! ```
+//!
+//! Functions can call each other when they have all been added to the interpreter. Scalar integer,
+//! boolean, and floating point instructions, control flow, traps, and explicit stack slots are
+//! supported. Evaluating anything else, like heap accesses or SIMD instructions, fails with
+//! `InterpreterError::Unsupported`.
+
+#![deny(missing_docs,
+        trivial_numeric_casts,
+        unused_extern_crates)]
+
+extern crate cretonne;
+#[cfg(test)]
+extern crate cton_reader;
+
+mod interpreter;
+mod value;
+
+pub use interpreter::{Interpreter, InterpreterError};
+pub use value::DataValue;
@@ -0,0 +1,93 @@
+//! Values computed by the interpreter.
+
+use cretonne::ir::Type;
+use cretonne::ir::immediates::{Ieee32, Ieee64, Imm64};
+use cretonne::ir::types;
+use std::fmt;
+
+/// A scalar value of an IL type.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataValue {
+    /// An integer of the given type. The bits above the width of the type are always zero.
+    Int(Type, u64),
+    /// A boolean of the given type.
+    Bool(Type, bool),
+    /// A 32-bit float.
+    F32(f32),
+    /// A 64-bit float.
+    F64(f64),
+}
+
+impl DataValue {
+    /// Create an integer of type `ty`, truncating `x` to the width of the type.
+    pub fn int(ty: Type, x: i64) -> DataValue {
+        DataValue::Int(ty, truncate(ty, x as u64))
+    }
+
+    /// Create a `b1` boolean.
+    pub fn bool(b: bool) -> DataValue {
+        DataValue::Bool(types::B1, b)
+    }
+
+    /// Get the value of an integer, sign-extended from the width of its type.
+    pub fn to_i64(self) -> Option<i64> {
+        match self {
+            DataValue::Int(ty, x) => Some(sign_extend(ty, x)),
+            _ => None,
+        }
+    }
+
+    /// Get the IL type of this value.
+    pub fn ty(&self) -> Type {
+        match *self {
+            DataValue::Int(ty, _) |
+            DataValue::Bool(ty, _) => ty,
+            DataValue::F32(_) => types::F32,
+            DataValue::F64(_) => types::F64,
+        }
+    }
+}
+
+impl fmt::Display for DataValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DataValue::Int(ty, x) => write!(f, "{}", Imm64::new(sign_extend(ty, x))),
+            DataValue::Bool(_, b) => write!(f, "{}", b),
+            DataValue::F32(x) => write!(f, "{}", Ieee32::with_float(x)),
+            DataValue::F64(x) => write!(f, "{}", Ieee64::with_float(x)),
+        }
+    }
+}
+
+/// Clear the bits of `x` above the width of the integer type `ty`.
+pub fn truncate(ty: Type, x: u64) -> u64 {
+    let bits = ty.lane_bits();
+    if bits >= 64 {
+        x
+    } else {
+        x & ((1u64 << bits) - 1)
+    }
+}
+
+/// Interpret the low bits of `x` as a signed integer of type `ty`.
+pub fn sign_extend(ty: Type, x: u64) -> i64 {
+    let shift = 64 - ty.lane_bits().min(64);
+    ((x << shift) as i64) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ints() {
+        assert_eq!(DataValue::int(types::I8, -1), DataValue::Int(types::I8, 0xff));
+        assert_eq!(DataValue::int(types::I64, -1), DataValue::Int(types::I64, !0));
+        assert_eq!(sign_extend(types::I16, 0x8000), -0x8000);
+        assert_eq!(sign_extend(types::I16, 0x7fff), 0x7fff);
+        assert_eq!(DataValue::Int(types::I8, 0x80).to_i64(), Some(-0x80));
+        assert_eq!(DataValue::int(types::I32, -5).to_string(), "-5");
+        assert_eq!(DataValue::bool(true).to_string(), "true");
+        assert_eq!(DataValue::F32(1.5).to_string(), "0x1.800000p0");
+    }
+}
@@ -0,0 +1,84 @@
+//! Defines the `Backend` trait and helpers shared by backends.
+
+use cretonne::Context;
+use cretonne::binemit::{Addend, CodeOffset, Reloc};
+use cretonne::ir::LibCall;
+use cretonne::isa::TargetIsa;
+use DataContext;
+use Linkage;
+use ModuleNamespace;
+use ModuleResult;
+use {DataId, FuncId};
+
+/// A `Backend` implements the functionality needed to support a `Module`.
+///
+/// The `Module` takes care of declarations, external names, and compiling function bodies. The
+/// backend receives compiled functions and data objects and turns them into its `Product`, for
+/// example an object file or executable memory.
+pub trait Backend {
+    /// The final product of the backend, returned by `Module::finish`.
+    type Product;
+
+    /// Get the target ISA that functions should be compiled for.
+    fn isa(&self) -> &TargetIsa;
+
+    /// Define a function whose body has been compiled in `ctx`.
+    ///
+    /// The code size was returned by `Context::compile`. Other code refers to the function by
+    /// the `ExternalName` converted from `id`, and external names referenced by the function can
+    /// be looked up in `namespace`.
+    fn define_function(
+        &mut self,
+        id: FuncId,
+        name: &str,
+        linkage: Linkage,
+        ctx: &Context,
+        code_size: CodeOffset,
+        namespace: &ModuleNamespace,
+    ) -> ModuleResult<()>;
+
+    /// Define a data object.
+    fn define_data(
+        &mut self,
+        id: DataId,
+        name: &str,
+        linkage: Linkage,
+        writable: bool,
+        data: &DataContext,
+        namespace: &ModuleNamespace,
+    ) -> ModuleResult<()>;
+
+    /// Consume the backend and produce the final result.
+    ///
+    /// This is called after all declared functions and data objects with `Local` or `Export`
+    /// linkage have been defined.
+    fn finish(self, namespace: &ModuleNamespace) -> ModuleResult<Self::Product>;
+}
+
+/// Get the addend to use when resolving a relocation emitted by the code generator.
+///
+/// Object file formats compute PC-relative relocations as `S + A - P`, where `P` is the address
+/// of the relocated field. Cretonne emits `IntelPCRel4` relocations for direct calls with an
+/// addend relative to the end of the 4-byte field, where the processor measures the displacement
+/// from, so backends must add the -4 bias themselves. The other PC-relative relocations already
+/// include it.
+pub fn reloc_addend(reloc: Reloc, addend: Addend) -> Addend {
+    match reloc {
+        Reloc::IntelPCRel4 => addend - 4,
+        _ => addend,
+    }
+}
+
+/// Get the C library name of a library call.
+pub fn libcall_name(libcall: LibCall) -> &'static str {
+    match libcall {
+        LibCall::CeilF32 => "ceilf",
+        LibCall::CeilF64 => "ceil",
+        LibCall::FloorF32 => "floorf",
+        LibCall::FloorF64 => "floor",
+        LibCall::TruncF32 => "truncf",
+        LibCall::TruncF64 => "trunc",
+        LibCall::NearestF32 => "nearbyintf",
+        LibCall::NearestF64 => "nearbyint",
+    }
+}
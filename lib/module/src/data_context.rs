@@ -0,0 +1,116 @@
+//! Defines `DataContext`.
+
+use cretonne::binemit::{Addend, CodeOffset};
+use cretonne::ir::ExternalName;
+
+/// How a data object is initialized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DataDescription {
+    /// The data object hasn't been described yet.
+    Uninitialized,
+    /// The data object is `size` bytes of zeros.
+    Zeroinit(usize),
+    /// The data object has the given contents.
+    Initialized(Vec<u8>),
+}
+
+/// A pointer-sized relocation in a data object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataReloc {
+    /// Offset of the pointer in the data object.
+    pub offset: CodeOffset,
+    /// The function or data object being pointed to.
+    pub name: ExternalName,
+    /// Addend to add to the symbol address.
+    pub addend: Addend,
+}
+
+/// The contents and relocations of a data object, to be passed to `Module::define_data`.
+///
+/// Like `cretonne::Context`, a `DataContext` can be cleared and reused for multiple data objects
+/// to avoid reallocating.
+pub struct DataContext {
+    description: DataDescription,
+    relocs: Vec<DataReloc>,
+}
+
+impl DataContext {
+    /// Allocate a new context.
+    pub fn new() -> Self {
+        Self {
+            description: DataDescription::Uninitialized,
+            relocs: Vec::new(),
+        }
+    }
+
+    /// Clear all data structures in this context.
+    pub fn clear(&mut self) {
+        self.description = DataDescription::Uninitialized;
+        self.relocs.clear();
+    }
+
+    /// Define a zero-initialized object of `size` bytes.
+    pub fn define_zeroinit(&mut self, size: usize) {
+        debug_assert_eq!(self.description, DataDescription::Uninitialized);
+        self.description = DataDescription::Zeroinit(size);
+    }
+
+    /// Define an object initialized with `contents`.
+    pub fn define(&mut self, contents: Vec<u8>) {
+        debug_assert_eq!(self.description, DataDescription::Uninitialized);
+        self.description = DataDescription::Initialized(contents);
+    }
+
+    /// Store the address of `name` plus `addend` as a pointer at `offset`.
+    ///
+    /// Use `Module::function_name` or `Module::data_name` to get the name of a declared function
+    /// or data object.
+    pub fn write_pointer(&mut self, offset: CodeOffset, name: ExternalName, addend: Addend) {
+        self.relocs.push(DataReloc {
+            offset,
+            name,
+            addend,
+        });
+    }
+
+    /// Get the description of the contents.
+    pub fn description(&self) -> &DataDescription {
+        &self.description
+    }
+
+    /// Get the contents as bytes. A zero-initialized object is expanded.
+    pub fn contents(&self) -> Vec<u8> {
+        match self.description {
+            DataDescription::Uninitialized => Vec::new(),
+            DataDescription::Zeroinit(size) => vec![0; size],
+            DataDescription::Initialized(ref contents) => contents.clone(),
+        }
+    }
+
+    /// Get the pointer relocations.
+    pub fn relocs(&self) -> &[DataReloc] {
+        &self.relocs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DataContext, DataDescription};
+    use cretonne::ir::ExternalName;
+
+    #[test]
+    fn basic_data_context() {
+        let mut data = DataContext::new();
+        assert_eq!(*data.description(), DataDescription::Uninitialized);
+
+        data.define_zeroinit(16);
+        data.write_pointer(8, ExternalName::user(0, 1), 4);
+        assert_eq!(data.contents(), vec![0; 16]);
+        assert_eq!(data.relocs().len(), 1);
+
+        data.clear();
+        assert_eq!(data.relocs().len(), 0);
+        data.define(vec![1, 2, 3]);
+        assert_eq!(data.contents(), vec![1, 2, 3]);
+    }
+}
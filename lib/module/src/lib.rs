@@ -0,0 +1,22 @@
+//! Top-level lib.rs for `cton_module`.
+//!
+//! A [`Module`](struct.Module.html) manages the declarations of the functions and data objects in
+//! a multi-function program, assigns each of them an `ExternalName`, and drives the compilation of
+//! function bodies through a [`Backend`](trait.Backend.html). Backends turn the compiled code into
+//! an object file, executable memory, or anything else.
+
+#![deny(missing_docs,
+        trivial_numeric_casts,
+        unused_extern_crates)]
+
+#[macro_use]
+extern crate cretonne;
+
+mod backend;
+mod data_context;
+mod module;
+
+pub use backend::{Backend, libcall_name, reloc_addend};
+pub use data_context::{DataContext, DataDescription, DataReloc};
+pub use module::{DataId, FuncId, FuncOrDataId, Linkage, Module, ModuleError, ModuleResult,
+                 ModuleNamespace, FunctionDeclaration, DataDeclaration};
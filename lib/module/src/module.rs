@@ -0,0 +1,805 @@
+//! Defines `Module` and related types.
+
+use cretonne::Context;
+use cretonne::binemit::CodeOffset;
+use cretonne::cursor::{Cursor, FuncCursor};
+use cretonne::entity::{EntityRef, PrimaryMap};
+use cretonne::ir::{self, InstBuilder};
+use cretonne::isa::TargetIsa;
+use cretonne::result::CodegenError;
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use Backend;
+use DataContext;
+
+/// A function identifier for use in the `Module` interface.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FuncId(u32);
+entity_impl!(FuncId, "funcid");
+
+/// A data object identifier for use in the `Module` interface.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DataId(u32);
+entity_impl!(DataId, "dataid");
+
+impl From<FuncId> for ir::ExternalName {
+    fn from(id: FuncId) -> Self {
+        ir::ExternalName::user(FUNCTION_NAMESPACE, id.index() as u32)
+    }
+}
+
+impl From<DataId> for ir::ExternalName {
+    fn from(id: DataId) -> Self {
+        ir::ExternalName::user(DATA_NAMESPACE, id.index() as u32)
+    }
+}
+
+/// An identifier for either a function or a data object.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FuncOrDataId {
+    /// When it's a function.
+    Func(FuncId),
+    /// When it's a data object.
+    Data(DataId),
+}
+
+/// `ExternalName` namespace used for declared functions.
+const FUNCTION_NAMESPACE: u32 = 0;
+
+/// `ExternalName` namespace used for declared data objects.
+const DATA_NAMESPACE: u32 = 1;
+
+/// Size of an entry in the function table used in hot-swap mode.
+const FUNCTION_TABLE_ENTRY_SIZE: usize = 8;
+
+/// Linkage refers to where an entity is defined and who can see it.
+///
+/// The variants are ordered so that merging two declarations of the same entity yields the
+/// greater of the two linkages.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Linkage {
+    /// Defined outside of a module.
+    Import,
+    /// Defined inside the module, but not visible outside it.
+    Local,
+    /// Defined inside the module and visible outside it.
+    Export,
+}
+
+impl Linkage {
+    /// Can an entity with this linkage be defined in the module?
+    pub fn is_definable(self) -> bool {
+        self != Linkage::Import
+    }
+}
+
+/// Information about a function which can be called.
+#[derive(Clone, Debug)]
+pub struct FunctionDeclaration {
+    /// The symbol name of the function.
+    pub name: String,
+    /// Where the function is defined.
+    pub linkage: Linkage,
+    /// The function's signature.
+    pub signature: ir::Signature,
+}
+
+/// Information about a data object which can be accessed.
+#[derive(Clone, Debug)]
+pub struct DataDeclaration {
+    /// The symbol name of the data object.
+    pub name: String,
+    /// Where the data object is defined.
+    pub linkage: Linkage,
+    /// Can the data object be written at runtime?
+    pub writable: bool,
+}
+
+/// An error encountered by a `Module` or its `Backend`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ModuleError {
+    /// A name was used before it was declared.
+    Undeclared(String),
+
+    /// A name was redeclared with an incompatible signature or kind.
+    IncompatibleDeclaration(String),
+
+    /// A function or data object was defined twice.
+    DuplicateDefinition(String),
+
+    /// An imported function or data object was defined.
+    InvalidImportDefinition(String),
+
+    /// A function or data object with local or export linkage was never defined.
+    MissingDefinition(String),
+
+    /// Compiling a function failed.
+    Compilation(String, CodegenError),
+
+    /// Hot-swap mode was enabled for a target that isn't 64-bit.
+    HotSwapUnsupported,
+
+    /// Hot-swap mode was enabled after the named function was defined.
+    HotSwapAfterDefinition(String),
+
+    /// The backend failed.
+    Backend(String),
+}
+
+/// Result type for `Module` operations.
+pub type ModuleResult<T> = Result<T, ModuleError>;
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ModuleError::Undeclared(ref name) => write!(f, "undeclared identifier: {}", name),
+            ModuleError::IncompatibleDeclaration(ref name) => {
+                write!(f, "incompatible declaration of identifier: {}", name)
+            }
+            ModuleError::DuplicateDefinition(ref name) => {
+                write!(f, "duplicate definition of identifier: {}", name)
+            }
+            ModuleError::InvalidImportDefinition(ref name) => {
+                write!(f, "invalid to define identifier declared as an import: {}", name)
+            }
+            ModuleError::MissingDefinition(ref name) => {
+                write!(f, "identifier declared but never defined: {}", name)
+            }
+            ModuleError::Compilation(ref name, ref e) => {
+                write!(f, "compilation of {} failed: {}", name, e)
+            }
+            ModuleError::HotSwapUnsupported => {
+                f.write_str("hot-swap mode requires a 64-bit target")
+            }
+            ModuleError::HotSwapAfterDefinition(ref name) => {
+                write!(
+                    f,
+                    "hot-swap mode must be enabled before defining functions: {}",
+                    name
+                )
+            }
+            ModuleError::Backend(ref msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl StdError for ModuleError {
+    fn description(&self) -> &str {
+        match *self {
+            ModuleError::Undeclared(_) => "undeclared identifier",
+            ModuleError::IncompatibleDeclaration(_) => "incompatible declaration",
+            ModuleError::DuplicateDefinition(_) => "duplicate definition",
+            ModuleError::InvalidImportDefinition(_) => "invalid import definition",
+            ModuleError::MissingDefinition(_) => "missing definition",
+            ModuleError::Compilation(..) => "compilation failed",
+            ModuleError::HotSwapUnsupported => "hot-swap mode unsupported",
+            ModuleError::HotSwapAfterDefinition(_) => "hot-swap mode enabled too late",
+            ModuleError::Backend(ref msg) => msg,
+        }
+    }
+}
+
+/// A function belonging to a `Module`.
+struct ModuleFunction {
+    decl: FunctionDeclaration,
+    defined: bool,
+}
+
+/// A data object belonging to a `Module`.
+struct ModuleData {
+    decl: DataDeclaration,
+    defined: bool,
+}
+
+/// A view of the declarations in a `Module`, used by backends to map the `ExternalName`s in
+/// compiled code back to declarations.
+pub struct ModuleNamespace<'a> {
+    functions: &'a PrimaryMap<FuncId, ModuleFunction>,
+    data: &'a PrimaryMap<DataId, ModuleData>,
+}
+
+impl<'a> ModuleNamespace<'a> {
+    /// Get the function declaration for `name`, if it names a declared function.
+    pub fn get_function_decl(&self, name: &ir::ExternalName) -> Option<&FunctionDeclaration> {
+        match *name {
+            ir::ExternalName::User { namespace, index }
+                if namespace == FUNCTION_NAMESPACE && (index as usize) < self.functions.len() => {
+                Some(&self.functions[FuncId::new(index as usize)].decl)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the data declaration for `name`, if it names a declared data object.
+    pub fn get_data_decl(&self, name: &ir::ExternalName) -> Option<&DataDeclaration> {
+        match *name {
+            ir::ExternalName::User { namespace, index }
+                if namespace == DATA_NAMESPACE && (index as usize) < self.data.len() => {
+                Some(&self.data[DataId::new(index as usize)].decl)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the symbol name of the function or data object identified by `name`.
+    ///
+    /// Returns `None` for names that weren't assigned by the module, such as library calls.
+    pub fn get_name(&self, name: &ir::ExternalName) -> Option<&str> {
+        self.get_function_decl(name)
+            .map(|decl| decl.name.as_str())
+            .or_else(|| self.get_data_decl(name).map(|decl| decl.name.as_str()))
+    }
+
+    /// Get the external names and symbol names of all imported functions and data objects.
+    pub fn imports(&self) -> Vec<(ir::ExternalName, &str)> {
+        let mut imports = Vec::new();
+        for id in self.functions.keys() {
+            let decl = &self.functions[id].decl;
+            if decl.linkage == Linkage::Import {
+                imports.push((id.into(), decl.name.as_str()));
+            }
+        }
+        for id in self.data.keys() {
+            let decl = &self.data[id].decl;
+            if decl.linkage == Linkage::Import {
+                imports.push((id.into(), decl.name.as_str()));
+            }
+        }
+        imports
+    }
+}
+
+/// A `Module` is a utility for collecting functions and data objects, and linking them together.
+///
+/// Functions and data objects are first declared with a symbol name and a linkage, which assigns
+/// them an `ExternalName` that can be referenced from other functions. Declared functions are
+/// then defined by compiling a `Context`, and the compiled code is handed to the `Backend`.
+///
+/// # Hot-swap mode
+///
+/// After `enable_hot_swap()`, every direct call to a function declared in the module is compiled
+/// as a load from a function table followed by an indirect call. The table is a writable data
+/// object with one pointer per declared function, initialized to the function's address, so a
+/// function can be replaced at runtime by storing the address of new code at
+/// `function_table_offset()`, without patching any code.
+pub struct Module<B>
+where
+    B: Backend,
+{
+    names: HashMap<String, FuncOrDataId>,
+    functions: PrimaryMap<FuncId, ModuleFunction>,
+    data: PrimaryMap<DataId, ModuleData>,
+    function_table: Option<DataId>,
+    backend: B,
+}
+
+impl<B> Module<B>
+where
+    B: Backend,
+{
+    /// Create a new `Module` emitting through `backend`.
+    pub fn new(backend: B) -> Self {
+        Self {
+            names: HashMap::new(),
+            functions: PrimaryMap::new(),
+            data: PrimaryMap::new(),
+            function_table: None,
+            backend,
+        }
+    }
+
+    /// Get the target ISA functions are compiled for.
+    pub fn isa(&self) -> &TargetIsa {
+        self.backend.isa()
+    }
+
+    /// Get the backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Get the module identifier for a given name, if that name has been declared.
+    pub fn get_name(&self, name: &str) -> Option<FuncOrDataId> {
+        self.names.get(name).cloned()
+    }
+
+    /// Create a new empty signature with the native calling convention.
+    pub fn make_signature(&self) -> ir::Signature {
+        ir::Signature::new(ir::CallConv::Native)
+    }
+
+    /// Get the `ExternalName` assigned to a declared function.
+    pub fn function_name(func: FuncId) -> ir::ExternalName {
+        func.into()
+    }
+
+    /// Get the `ExternalName` assigned to a declared data object.
+    pub fn data_name(data: DataId) -> ir::ExternalName {
+        data.into()
+    }
+
+    /// Enable hot-swap mode, and declare the function table as an exported data object named
+    /// `table_name`.
+    ///
+    /// Hot-swap mode requires a 64-bit target, and it must be enabled before any function is
+    /// defined. Otherwise, `HotSwapUnsupported` or `HotSwapAfterDefinition` is returned.
+    pub fn enable_hot_swap(&mut self, table_name: &str) -> ModuleResult<DataId> {
+        if !self.isa().flags().is_64bit() {
+            return Err(ModuleError::HotSwapUnsupported);
+        }
+        if let Some(id) = self.functions.keys().find(|&id| self.functions[id].defined) {
+            return Err(ModuleError::HotSwapAfterDefinition(
+                self.functions[id].decl.name.clone(),
+            ));
+        }
+        let table = self.declare_data(table_name, Linkage::Export, true)?;
+        self.function_table = Some(table);
+        Ok(table)
+    }
+
+    /// Get the function table, if hot-swap mode is enabled.
+    pub fn function_table(&self) -> Option<DataId> {
+        self.function_table
+    }
+
+    /// Get the offset of the entry for `func` in the function table.
+    pub fn function_table_offset(func: FuncId) -> usize {
+        func.index() * FUNCTION_TABLE_ENTRY_SIZE
+    }
+
+    /// Rewrite direct calls to functions declared in this module into indirect calls through
+    /// the function table.
+    fn call_through_table(&self, table: DataId, func: &mut ir::Function) {
+        let mut table_gv = None;
+        let mut pos = FuncCursor::new(func);
+        while let Some(_ebb) = pos.next_ebb() {
+            while let Some(inst) = pos.next_inst() {
+                let fref = match pos.func.dfg[inst] {
+                    ir::InstructionData::Call { func_ref, .. } => func_ref,
+                    _ => continue,
+                };
+                let callee = match pos.func.dfg.ext_funcs[fref].name {
+                    ir::ExternalName::User { namespace, index }
+                        if namespace == FUNCTION_NAMESPACE &&
+                               (index as usize) < self.functions.len() => {
+                        FuncId::new(index as usize)
+                    }
+                    _ => continue,
+                };
+                let sigref = pos.func.dfg.ext_funcs[fref].signature;
+                let args = pos.func.dfg.inst_args(inst).to_vec();
+                let gv = match table_gv {
+                    Some(gv) => gv,
+                    None => {
+                        let gv = self.declare_data_in_func(table, pos.func);
+                        table_gv = Some(gv);
+                        gv
+                    }
+                };
+                let base = pos.ins().global_addr(ir::types::I64, gv);
+                let callee_ptr = pos.ins().load(
+                    ir::types::I64,
+                    ir::MemFlags::new(),
+                    base,
+                    Self::function_table_offset(callee) as i32,
+                );
+                pos.func.dfg.replace(inst).call_indirect(
+                    sigref,
+                    callee_ptr,
+                    &args,
+                );
+            }
+        }
+    }
+
+    /// Declare a function in this module.
+    ///
+    /// Declaring the same name again merges the linkages, but the signatures must match.
+    pub fn declare_function(
+        &mut self,
+        name: &str,
+        linkage: Linkage,
+        signature: &ir::Signature,
+    ) -> ModuleResult<FuncId> {
+        match self.names.get(name).cloned() {
+            Some(FuncOrDataId::Func(id)) => {
+                let existing = &mut self.functions[id];
+                if existing.decl.signature != *signature {
+                    return Err(ModuleError::IncompatibleDeclaration(name.to_string()));
+                }
+                existing.decl.linkage = ::std::cmp::max(existing.decl.linkage, linkage);
+                Ok(id)
+            }
+            Some(FuncOrDataId::Data(_)) => Err(
+                ModuleError::IncompatibleDeclaration(name.to_string()),
+            ),
+            None => {
+                let id = self.functions.push(ModuleFunction {
+                    decl: FunctionDeclaration {
+                        name: name.to_string(),
+                        linkage,
+                        signature: signature.clone(),
+                    },
+                    defined: false,
+                });
+                self.names.insert(name.to_string(), FuncOrDataId::Func(id));
+                Ok(id)
+            }
+        }
+    }
+
+    /// Declare a data object in this module.
+    ///
+    /// Declaring the same name again merges the linkages, and the object is writable if any
+    /// declaration says so.
+    pub fn declare_data(
+        &mut self,
+        name: &str,
+        linkage: Linkage,
+        writable: bool,
+    ) -> ModuleResult<DataId> {
+        match self.names.get(name).cloned() {
+            Some(FuncOrDataId::Data(id)) => {
+                let existing = &mut self.data[id];
+                existing.decl.linkage = ::std::cmp::max(existing.decl.linkage, linkage);
+                existing.decl.writable |= writable;
+                Ok(id)
+            }
+            Some(FuncOrDataId::Func(_)) => Err(
+                ModuleError::IncompatibleDeclaration(name.to_string()),
+            ),
+            None => {
+                let id = self.data.push(ModuleData {
+                    decl: DataDeclaration {
+                        name: name.to_string(),
+                        linkage,
+                        writable,
+                    },
+                    defined: false,
+                });
+                self.names.insert(name.to_string(), FuncOrDataId::Data(id));
+                Ok(id)
+            }
+        }
+    }
+
+    /// Use a declared function in `in_func` so it can be called.
+    ///
+    /// The signature and the function reference are only imported once per function; repeated
+    /// calls return the same `FuncRef`.
+    pub fn declare_func_in_func(&self, func: FuncId, in_func: &mut ir::Function) -> ir::FuncRef {
+        let name = Self::function_name(func);
+        if let Some(fref) = in_func.dfg.ext_funcs.keys().find(|&fref| {
+            in_func.dfg.ext_funcs[fref].name == name
+        })
+        {
+            return fref;
+        }
+
+        let signature = &self.functions[func].decl.signature;
+        let sigref = match in_func.dfg.signatures.keys().find(|&sigref| {
+            in_func.dfg.signatures[sigref] == *signature
+        }) {
+            Some(sigref) => sigref,
+            None => in_func.import_signature(signature.clone()),
+        };
+        in_func.import_function(ir::ExtFuncData {
+            name,
+            signature: sigref,
+        })
+    }
+
+    /// Use a declared data object in `in_func` so its address can be taken.
+    ///
+    /// Returns a `globalsym` global variable, which is only created once per function.
+    pub fn declare_data_in_func(&self, data: DataId, in_func: &mut ir::Function) -> ir::GlobalVar {
+        let name = Self::data_name(data);
+        if let Some(gv) = in_func.global_vars.keys().find(|&gv| match in_func.global_vars[gv] {
+            ir::GlobalVarData::Sym { name: ref n } => *n == name,
+            _ => false,
+        })
+        {
+            return gv;
+        }
+        in_func.create_global_var(ir::GlobalVarData::Sym { name })
+    }
+
+    /// Define a function, compiling the body in `ctx`.
+    ///
+    /// Returns the size of the function's code.
+    pub fn define_function(&mut self, func: FuncId, ctx: &mut Context) -> ModuleResult<CodeOffset> {
+        {
+            let info = &self.functions[func];
+            if info.defined {
+                return Err(ModuleError::DuplicateDefinition(info.decl.name.clone()));
+            }
+            if !info.decl.linkage.is_definable() {
+                return Err(ModuleError::InvalidImportDefinition(info.decl.name.clone()));
+            }
+            if ctx.func.signature != info.decl.signature {
+                return Err(ModuleError::IncompatibleDeclaration(info.decl.name.clone()));
+            }
+        }
+
+        if let Some(table) = self.function_table {
+            self.call_through_table(table, &mut ctx.func);
+        }
+        let code_size = ctx.compile(self.backend.isa()).map_err(|e| {
+            ModuleError::Compilation(self.functions[func].decl.name.clone(), e)
+        })?;
+
+        let namespace = ModuleNamespace {
+            functions: &self.functions,
+            data: &self.data,
+        };
+        let decl = &self.functions[func].decl;
+        self.backend.define_function(
+            func,
+            &decl.name,
+            decl.linkage,
+            ctx,
+            code_size,
+            &namespace,
+        )?;
+        self.functions[func].defined = true;
+        Ok(code_size)
+    }
+
+    /// Define a data object with the contents and relocations in `data_ctx`.
+    pub fn define_data(&mut self, data: DataId, data_ctx: &DataContext) -> ModuleResult<()> {
+        {
+            let info = &self.data[data];
+            if info.defined {
+                return Err(ModuleError::DuplicateDefinition(info.decl.name.clone()));
+            }
+            if !info.decl.linkage.is_definable() {
+                return Err(ModuleError::InvalidImportDefinition(info.decl.name.clone()));
+            }
+        }
+
+        let namespace = ModuleNamespace {
+            functions: &self.functions,
+            data: &self.data,
+        };
+        let decl = &self.data[data].decl;
+        self.backend.define_data(
+            data,
+            &decl.name,
+            decl.linkage,
+            decl.writable,
+            data_ctx,
+            &namespace,
+        )?;
+        self.data[data].defined = true;
+        Ok(())
+    }
+
+    /// Finish the module and return the backend's product.
+    ///
+    /// Every function and data object declared with `Local` or `Export` linkage must have been
+    /// defined. In hot-swap mode, the function table is defined here.
+    pub fn finish(mut self) -> ModuleResult<B::Product> {
+        if let Some(table) = self.function_table {
+            let mut table_ctx = DataContext::new();
+            table_ctx.define_zeroinit(self.functions.len() * FUNCTION_TABLE_ENTRY_SIZE);
+            for id in self.functions.keys() {
+                table_ctx.write_pointer(
+                    Self::function_table_offset(id) as CodeOffset,
+                    Self::function_name(id),
+                    0,
+                );
+            }
+            self.define_data(table, &table_ctx)?;
+        }
+        for id in self.functions.keys() {
+            let info = &self.functions[id];
+            if info.decl.linkage.is_definable() && !info.defined {
+                return Err(ModuleError::MissingDefinition(info.decl.name.clone()));
+            }
+        }
+        for id in self.data.keys() {
+            let info = &self.data[id];
+            if info.decl.linkage.is_definable() && !info.defined {
+                return Err(ModuleError::MissingDefinition(info.decl.name.clone()));
+            }
+        }
+        let namespace = ModuleNamespace {
+            functions: &self.functions,
+            data: &self.data,
+        };
+        self.backend.finish(&namespace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cretonne::ir::{AbiParam, types};
+    use cretonne::isa;
+    use cretonne::settings::{self, Configurable};
+
+    /// A backend that records the names of the definitions it receives.
+    struct Recorder {
+        isa: Box<TargetIsa>,
+        defined: Vec<String>,
+    }
+
+    impl Backend for Recorder {
+        type Product = Vec<String>;
+
+        fn isa(&self) -> &TargetIsa {
+            &*self.isa
+        }
+
+        fn define_function(
+            &mut self,
+            _id: FuncId,
+            name: &str,
+            _linkage: Linkage,
+            _ctx: &Context,
+            code_size: CodeOffset,
+            _namespace: &ModuleNamespace,
+        ) -> ModuleResult<()> {
+            assert!(code_size > 0);
+            self.defined.push(name.to_string());
+            Ok(())
+        }
+
+        fn define_data(
+            &mut self,
+            _id: DataId,
+            name: &str,
+            _linkage: Linkage,
+            _writable: bool,
+            _data: &DataContext,
+            _namespace: &ModuleNamespace,
+        ) -> ModuleResult<()> {
+            self.defined.push(name.to_string());
+            Ok(())
+        }
+
+        fn finish(self, namespace: &ModuleNamespace) -> ModuleResult<Vec<String>> {
+            let mut defined = self.defined;
+            for (_, name) in namespace.imports() {
+                defined.push(format!("import {}", name));
+            }
+            Ok(defined)
+        }
+    }
+
+    fn module() -> Module<Recorder> {
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(
+            &settings::builder(),
+        ));
+        Module::new(Recorder {
+            isa,
+            defined: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn declarations() {
+        let mut module = module();
+        let mut sig = module.make_signature();
+        sig.params.push(AbiParam::new(types::I32));
+
+        let f = module.declare_function("f", Linkage::Import, &sig).unwrap();
+        assert_eq!(module.declare_function("f", Linkage::Export, &sig), Ok(f));
+        assert_eq!(module.get_name("f"), Some(FuncOrDataId::Func(f)));
+        assert_eq!(
+            module.declare_function("f", Linkage::Export, &module.make_signature()),
+            Err(ModuleError::IncompatibleDeclaration("f".to_string()))
+        );
+        assert_eq!(
+            module.declare_data("f", Linkage::Local, false),
+            Err(ModuleError::IncompatibleDeclaration("f".to_string()))
+        );
+
+        let d = module.declare_data("d", Linkage::Import, false).unwrap();
+        assert_eq!(module.declare_data("d", Linkage::Local, true), Ok(d));
+        assert_eq!(Module::<Recorder>::data_name(d), ir::ExternalName::user(1, 0));
+
+        // Both `f` and `d` are now definable but undefined.
+        assert_eq!(
+            module.finish(),
+            Err(ModuleError::MissingDefinition("f".to_string()))
+        );
+    }
+
+    #[test]
+    fn define_and_reference() {
+        let mut module = module();
+        let sig = module.make_signature();
+        let callee = module.declare_function("callee", Linkage::Import, &sig).unwrap();
+        let caller = module.declare_function("caller", Linkage::Export, &sig).unwrap();
+        let data = module.declare_data("data", Linkage::Local, true).unwrap();
+
+        let mut ctx = Context::new();
+        ctx.func.signature = sig.clone();
+        let fref = module.declare_func_in_func(callee, &mut ctx.func);
+        assert_eq!(module.declare_func_in_func(callee, &mut ctx.func), fref);
+        assert_eq!(ctx.func.dfg.signatures.len(), 1);
+        let gv = module.declare_data_in_func(data, &mut ctx.func);
+        assert_eq!(module.declare_data_in_func(data, &mut ctx.func), gv);
+        {
+            let ebb = ctx.func.dfg.make_ebb();
+            ctx.func.layout.append_ebb(ebb);
+            let mut pos = FuncCursor::new(&mut ctx.func).at_bottom(ebb);
+            pos.ins().call(fref, &[]);
+            pos.ins().return_(&[]);
+        }
+        module.define_function(caller, &mut ctx).unwrap();
+        assert_eq!(
+            module.define_function(caller, &mut ctx),
+            Err(ModuleError::DuplicateDefinition("caller".to_string()))
+        );
+        assert_eq!(
+            module.define_function(callee, &mut ctx),
+            Err(ModuleError::InvalidImportDefinition("callee".to_string()))
+        );
+
+        let mut data_ctx = DataContext::new();
+        data_ctx.define_zeroinit(8);
+        data_ctx.write_pointer(0, Module::<Recorder>::function_name(caller), 0);
+        module.define_data(data, &data_ctx).unwrap();
+
+        assert_eq!(
+            module.finish().unwrap(),
+            vec![
+                "caller".to_string(),
+                "data".to_string(),
+                "import callee".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn hot_swap() {
+        assert_eq!(
+            module().enable_hot_swap("table"),
+            Err(ModuleError::HotSwapUnsupported)
+        );
+
+        let mut flags = settings::builder();
+        flags.enable("is_64bit").unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&flags));
+        let mut module = Module::new(Recorder {
+            isa,
+            defined: Vec::new(),
+        });
+        let sig = module.make_signature();
+        let callee = module.declare_function("callee", Linkage::Import, &sig).unwrap();
+        let caller = module.declare_function("caller", Linkage::Export, &sig).unwrap();
+        module.enable_hot_swap("table").unwrap();
+        assert_eq!(Module::<Recorder>::function_table_offset(caller), 8);
+
+        let mut ctx = Context::new();
+        ctx.func.signature = sig;
+        let fref = module.declare_func_in_func(callee, &mut ctx.func);
+        {
+            let ebb = ctx.func.dfg.make_ebb();
+            ctx.func.layout.append_ebb(ebb);
+            let mut pos = FuncCursor::new(&mut ctx.func).at_bottom(ebb);
+            pos.ins().call(fref, &[]);
+            pos.ins().return_(&[]);
+        }
+        module.define_function(caller, &mut ctx).unwrap();
+        let text = ctx.func.to_string();
+        assert!(text.contains("call_indirect"));
+        assert!(!text.contains(" call fn"));
+        assert_eq!(
+            module.enable_hot_swap("table2"),
+            Err(ModuleError::HotSwapAfterDefinition("caller".to_string()))
+        );
+
+        assert_eq!(
+            module.finish().unwrap(),
+            vec![
+                "caller".to_string(),
+                "table".to_string(),
+                "import callee".to_string(),
+            ]
+        );
+    }
+}
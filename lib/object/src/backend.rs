@@ -0,0 +1,185 @@
+//! A `cton_module::Backend` that writes an object file.
+
+use cretonne::Context;
+use cretonne::binemit::{CodeOffset, Reloc};
+use cretonne::ir::ExternalName;
+use cretonne::isa::TargetIsa;
+use cton_module::{self, Backend, DataContext, DataId, FuncId, ModuleError, ModuleNamespace,
+                  ModuleResult};
+use builder::{ObjectBuilder, RelocCollector, Relocation, default_symbol_name};
+use super::{DataKind, Format, Linkage, ObjectError, Result};
+
+/// A `Backend` that collects the definitions of a `Module` into an object file.
+///
+/// Declared functions and data objects use their declared names as symbol names. Other external
+/// names, such as library calls, are named with `default_symbol_name`.
+pub struct ObjectBackend {
+    isa: Box<TargetIsa>,
+    builder: ObjectBuilder,
+}
+
+impl ObjectBackend {
+    /// Create a backend that writes an object file of the given format for code compiled by
+    /// `isa`.
+    pub fn new(isa: Box<TargetIsa>, format: Format) -> Result<Self> {
+        let builder = ObjectBuilder::new(&*isa, format)?;
+        Ok(Self { isa, builder })
+    }
+}
+
+/// Get the symbol name of `name`.
+fn symbol_name(namespace: &ModuleNamespace, name: &ExternalName) -> String {
+    match namespace.get_name(name) {
+        Some(symbol) => symbol.to_string(),
+        None => default_symbol_name(name),
+    }
+}
+
+/// Translate the linkage of the definition of `name`.
+fn linkage(name: &str, linkage: cton_module::Linkage) -> ModuleResult<Linkage> {
+    match linkage {
+        cton_module::Linkage::Export => Ok(Linkage::Export),
+        cton_module::Linkage::Local => Ok(Linkage::Local),
+        cton_module::Linkage::Import => Err(ModuleError::InvalidImportDefinition(
+            name.to_string(),
+        )),
+    }
+}
+
+impl From<ObjectError> for ModuleError {
+    fn from(e: ObjectError) -> Self {
+        ModuleError::Backend(e.to_string())
+    }
+}
+
+impl Backend for ObjectBackend {
+    type Product = Vec<u8>;
+
+    fn isa(&self) -> &TargetIsa {
+        &*self.isa
+    }
+
+    fn define_function(
+        &mut self,
+        _id: FuncId,
+        name: &str,
+        link: cton_module::Linkage,
+        ctx: &Context,
+        code_size: CodeOffset,
+        namespace: &ModuleNamespace,
+    ) -> ModuleResult<()> {
+        let link = linkage(name, link)?;
+        let mut code = vec![0; code_size as usize];
+        let namer = |n: &ExternalName| symbol_name(namespace, n);
+        let mut collector = RelocCollector::new(&namer);
+        ctx.emit_to_memory(code.as_mut_ptr(), &mut collector, &*self.isa);
+        if collector.local_relocs != 0 {
+            return Err(ObjectError::UnresolvedLocalReloc(name.to_string()).into());
+        }
+        self.builder.define_function(
+            name,
+            link,
+            &code,
+            collector.relocs,
+        )?;
+        Ok(())
+    }
+
+    fn define_data(
+        &mut self,
+        _id: DataId,
+        name: &str,
+        link: cton_module::Linkage,
+        writable: bool,
+        data: &DataContext,
+        namespace: &ModuleNamespace,
+    ) -> ModuleResult<()> {
+        let link = linkage(name, link)?;
+        let relocs = data.relocs()
+            .iter()
+            .map(|r| {
+                Relocation {
+                    offset: r.offset,
+                    reloc: Reloc::IntelAbs8,
+                    target: symbol_name(namespace, &r.name),
+                    addend: r.addend,
+                }
+            })
+            .collect();
+        let kind = if writable {
+            DataKind::Writable
+        } else {
+            DataKind::ReadOnly
+        };
+        self.builder.define_data(
+            name,
+            link,
+            kind,
+            &data.contents(),
+            relocs,
+        )?;
+        Ok(())
+    }
+
+    fn finish(self, _namespace: &ModuleNamespace) -> ModuleResult<Vec<u8>> {
+        Ok(self.builder.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ObjectBackend, linkage};
+    use builder::tests::read_u16;
+    use cretonne::Context;
+    use cretonne::cursor::{Cursor, FuncCursor};
+    use cretonne::ir::InstBuilder;
+    use cretonne::isa;
+    use cretonne::settings::{self, Configurable};
+    use cton_module::{DataContext, Linkage, Module, ModuleError};
+    use Format;
+
+    #[test]
+    fn module() {
+        let mut flags = settings::builder();
+        flags.enable("is_64bit").unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(
+            settings::Flags::new(&flags),
+        );
+        let mut module = Module::new(ObjectBackend::new(isa, Format::Elf).unwrap());
+
+        let sig = module.make_signature();
+        let callee = module.declare_function("puts", Linkage::Import, &sig).unwrap();
+        let main = module.declare_function("main", Linkage::Export, &sig).unwrap();
+        let table = module.declare_data("table", Linkage::Local, false).unwrap();
+
+        let mut ctx = Context::new();
+        ctx.func.signature = sig;
+        let fref = module.declare_func_in_func(callee, &mut ctx.func);
+        {
+            let ebb = ctx.func.dfg.make_ebb();
+            ctx.func.layout.append_ebb(ebb);
+            let mut pos = FuncCursor::new(&mut ctx.func).at_bottom(ebb);
+            pos.ins().call(fref, &[]);
+            pos.ins().return_(&[]);
+        }
+        module.define_function(main, &mut ctx).unwrap();
+
+        let mut data = DataContext::new();
+        data.define_zeroinit(8);
+        data.write_pointer(0, Module::<ObjectBackend>::function_name(main), 0);
+        module.define_data(table, &data).unwrap();
+
+        let bytes = module.finish().unwrap();
+        assert_eq!(&bytes[0..4], b"\x7fELF");
+        // ET_REL
+        assert_eq!(read_u16(&bytes, 16), 1);
+    }
+
+    #[test]
+    fn import_linkage() {
+        assert_eq!(
+            linkage("puts", Linkage::Import),
+            Err(ModuleError::InvalidImportDefinition("puts".to_string()))
+        );
+    }
+}
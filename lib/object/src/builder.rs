@@ -0,0 +1,466 @@
+//! Collecting functions and data into an object file.
+
+use cretonne::Context;
+use cretonne::binemit::{Addend, CodeOffset, Reloc, RelocSink};
+use cretonne::ir::{ExternalName, JumpTable};
+use cretonne::isa::TargetIsa;
+use cton_module::{libcall_name, reloc_addend};
+use std::collections::HashMap;
+use {coff, elf, macho};
+use super::{DataKind, Format, Linkage, ObjectError, Result};
+
+/// A relocation against a named symbol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    /// Offset of the relocated field from the start of the function or data object.
+    pub offset: CodeOffset,
+    /// The kind of relocation.
+    pub reloc: Reloc,
+    /// Name of the symbol being referenced.
+    pub target: String,
+    /// Addend to add to the symbol value.
+    pub addend: Addend,
+}
+
+/// A `RelocSink` that collects relocations against external names.
+///
+/// External names are translated into symbol names with the `namer` function. Relocations that
+/// refer to EBBs or jump tables are not representable in an object file; they are counted in
+/// `local_relocs` so the builder can reject the function.
+pub struct RelocCollector<'a> {
+    namer: &'a Fn(&ExternalName) -> String,
+    /// The collected relocations.
+    pub relocs: Vec<Relocation>,
+    /// Number of EBB and jump table relocations seen.
+    pub local_relocs: usize,
+}
+
+impl<'a> RelocCollector<'a> {
+    /// Create a collector that names external symbols with `namer`.
+    pub fn new(namer: &'a Fn(&ExternalName) -> String) -> Self {
+        Self {
+            namer,
+            relocs: Vec::new(),
+            local_relocs: 0,
+        }
+    }
+}
+
+impl<'a> RelocSink for RelocCollector<'a> {
+    fn reloc_ebb(&mut self, _offset: CodeOffset, _reloc: Reloc, _ebb_offset: CodeOffset) {
+        self.local_relocs += 1;
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        self.relocs.push(Relocation {
+            offset,
+            reloc,
+            target: (self.namer)(name),
+            addend: reloc_addend(reloc, addend),
+        });
+    }
+
+    fn reloc_jt(&mut self, _offset: CodeOffset, _reloc: Reloc, _jt: JumpTable) {
+        self.local_relocs += 1;
+    }
+}
+
+/// The default mapping from external names to symbol names.
+///
+/// Test case names are used verbatim, user-defined names become `u<namespace>_<index>`, and
+/// library calls map to the corresponding C math library functions.
+pub fn default_symbol_name(name: &ExternalName) -> String {
+    match *name {
+        ExternalName::TestCase { length, ascii } => {
+            String::from_utf8_lossy(&ascii[0..length as usize]).into_owned()
+        }
+        ExternalName::User { namespace, index } => format!("u{}_{}", namespace, index),
+        ExternalName::LibCall(lc) => String::from(libcall_name(lc)),
+    }
+}
+
+/// Sections that definitions are placed in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SectionId {
+    Text,
+    Data,
+    ReadOnlyData,
+}
+
+/// All the sections, in the order they appear in the object file.
+pub const SECTIONS: [SectionId; 3] = [SectionId::Text, SectionId::Data, SectionId::ReadOnlyData];
+
+/// A symbol in the object file.
+pub struct Symbol {
+    pub name: String,
+    /// The section and offset of the definition, or `None` for an imported symbol.
+    pub def: Option<(SectionId, u64)>,
+    pub size: u64,
+    pub global: bool,
+    pub is_func: bool,
+}
+
+/// A relocation in a section, resolved to a symbol index.
+pub struct SectionReloc {
+    pub offset: u64,
+    pub reloc: Reloc,
+    pub symbol: usize,
+    pub addend: Addend,
+}
+
+/// The contents of a section.
+pub struct Section {
+    pub data: Vec<u8>,
+    pub align: u64,
+    pub relocs: Vec<SectionReloc>,
+}
+
+impl Section {
+    fn new(align: u64) -> Self {
+        Self {
+            data: Vec::new(),
+            align,
+            relocs: Vec::new(),
+        }
+    }
+}
+
+/// The complete, format-independent contents of an object file.
+///
+/// Symbols are ordered so that local symbols come first, followed by defined global symbols and
+/// finally imports. Every format writer relies on this ordering.
+pub struct Object {
+    pub text: Section,
+    pub data: Section,
+    pub rodata: Section,
+    pub symbols: Vec<Symbol>,
+}
+
+impl Object {
+    /// Get the contents of the section identified by `id`.
+    pub fn section(&self, id: SectionId) -> &Section {
+        match id {
+            SectionId::Text => &self.text,
+            SectionId::Data => &self.data,
+            SectionId::ReadOnlyData => &self.rodata,
+        }
+    }
+}
+
+/// A definition that has been added to the builder.
+struct Definition {
+    section: SectionId,
+    offset: u64,
+    size: u64,
+    linkage: Linkage,
+    relocs: Vec<Relocation>,
+}
+
+/// Collects compiled functions and data objects and writes them as an object file.
+pub struct ObjectBuilder {
+    format: Format,
+    text: Vec<u8>,
+    data: Vec<u8>,
+    rodata: Vec<u8>,
+    /// Definitions in the order they were added.
+    names: Vec<String>,
+    defs: HashMap<String, Definition>,
+}
+
+/// Alignment of functions in the text section.
+const FUNCTION_ALIGNMENT: usize = 16;
+
+/// Alignment of data objects.
+const DATA_ALIGNMENT: usize = 8;
+
+impl ObjectBuilder {
+    /// Create a new object builder for code compiled by `isa`.
+    ///
+    /// Only 64-bit Intel targets are currently supported.
+    pub fn new(isa: &TargetIsa, format: Format) -> Result<Self> {
+        if isa.name() != "intel" || !isa.flags().is_64bit() {
+            return Err(ObjectError::UnsupportedTarget(format!(
+                "{} ({}-bit)",
+                isa.name(),
+                if isa.flags().is_64bit() { 64 } else { 32 }
+            )));
+        }
+        Ok(Self {
+            format,
+            text: Vec::new(),
+            data: Vec::new(),
+            rodata: Vec::new(),
+            names: Vec::new(),
+            defs: HashMap::new(),
+        })
+    }
+
+    /// Get the object file format being written.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Is `name` defined in this object?
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.defs.contains_key(name)
+    }
+
+    /// Add the machine code for a function.
+    ///
+    /// Relocations are relative to the start of `code`. Any symbol referenced by a relocation
+    /// that isn't defined in the object is emitted as an import.
+    pub fn define_function(
+        &mut self,
+        name: &str,
+        linkage: Linkage,
+        code: &[u8],
+        relocs: Vec<Relocation>,
+    ) -> Result<()> {
+        self.define(name, SectionId::Text, linkage, code, relocs)
+    }
+
+    /// Add a data object.
+    ///
+    /// Relocations are relative to the start of `bytes`.
+    pub fn define_data(
+        &mut self,
+        name: &str,
+        linkage: Linkage,
+        kind: DataKind,
+        bytes: &[u8],
+        relocs: Vec<Relocation>,
+    ) -> Result<()> {
+        let section = match kind {
+            DataKind::Writable => SectionId::Data,
+            DataKind::ReadOnly => SectionId::ReadOnlyData,
+        };
+        self.define(name, section, linkage, bytes, relocs)
+    }
+
+    /// Compile the function in `ctx` and add it to the object.
+    ///
+    /// External names referenced by the function are translated to symbol names with `namer`.
+    pub fn compile_function(
+        &mut self,
+        name: &str,
+        linkage: Linkage,
+        ctx: &mut Context,
+        isa: &TargetIsa,
+        namer: &Fn(&ExternalName) -> String,
+    ) -> Result<()> {
+        let size = ctx.compile(isa).map_err(
+            |e| ObjectError::Compile(e.to_string()),
+        )?;
+        let mut code = vec![0; size as usize];
+        let mut collector = RelocCollector::new(namer);
+        ctx.emit_to_memory(code.as_mut_ptr(), &mut collector, isa);
+        if collector.local_relocs != 0 {
+            return Err(ObjectError::UnresolvedLocalReloc(name.to_string()));
+        }
+        self.define_function(name, linkage, &code, collector.relocs)
+    }
+
+    fn define(
+        &mut self,
+        name: &str,
+        section: SectionId,
+        linkage: Linkage,
+        bytes: &[u8],
+        relocs: Vec<Relocation>,
+    ) -> Result<()> {
+        if self.defs.contains_key(name) {
+            return Err(ObjectError::DuplicateDefinition(name.to_string()));
+        }
+        let (buf, align) = match section {
+            SectionId::Text => (&mut self.text, FUNCTION_ALIGNMENT),
+            SectionId::Data => (&mut self.data, DATA_ALIGNMENT),
+            SectionId::ReadOnlyData => (&mut self.rodata, DATA_ALIGNMENT),
+        };
+        let offset = ::bytes::align_to(buf.len(), align);
+        buf.resize(offset, 0);
+        buf.extend_from_slice(bytes);
+        self.names.push(name.to_string());
+        self.defs.insert(
+            name.to_string(),
+            Definition {
+                section,
+                offset: offset as u64,
+                size: bytes.len() as u64,
+                linkage,
+                relocs,
+            },
+        );
+        Ok(())
+    }
+
+    /// Lay out the symbol table and sections.
+    fn layout(self) -> Object {
+        let mut text = Section::new(FUNCTION_ALIGNMENT as u64);
+        let mut data = Section::new(DATA_ALIGNMENT as u64);
+        let mut rodata = Section::new(DATA_ALIGNMENT as u64);
+        text.data = self.text;
+        data.data = self.data;
+        rodata.data = self.rodata;
+
+        // Order the symbols: locals, then exports, then imports.
+        let mut symbols = Vec::new();
+        let mut index = HashMap::new();
+        for &linkage in &[Linkage::Local, Linkage::Export] {
+            for name in &self.names {
+                let def = &self.defs[name];
+                if def.linkage == linkage {
+                    index.insert(name.clone(), symbols.len());
+                    symbols.push(Symbol {
+                        name: name.clone(),
+                        def: Some((def.section, def.offset)),
+                        size: def.size,
+                        global: linkage == Linkage::Export,
+                        is_func: def.section == SectionId::Text,
+                    });
+                }
+            }
+        }
+        for name in &self.names {
+            for reloc in &self.defs[name].relocs {
+                if !index.contains_key(&reloc.target) {
+                    index.insert(reloc.target.clone(), symbols.len());
+                    symbols.push(Symbol {
+                        name: reloc.target.clone(),
+                        def: None,
+                        size: 0,
+                        global: true,
+                        is_func: false,
+                    });
+                }
+            }
+        }
+
+        // Resolve relocations to symbol indexes.
+        for name in &self.names {
+            let def = &self.defs[name];
+            let section = match def.section {
+                SectionId::Text => &mut text,
+                SectionId::Data => &mut data,
+                SectionId::ReadOnlyData => &mut rodata,
+            };
+            for reloc in &def.relocs {
+                section.relocs.push(SectionReloc {
+                    offset: def.offset + u64::from(reloc.offset),
+                    reloc: reloc.reloc,
+                    symbol: index[&reloc.target],
+                    addend: reloc.addend,
+                });
+            }
+        }
+
+        Object {
+            text,
+            data,
+            rodata,
+            symbols,
+        }
+    }
+
+    /// Write the object file.
+    pub fn finish(self) -> Result<Vec<u8>> {
+        let format = self.format;
+        let obj = self.layout();
+        match format {
+            Format::Elf => elf::write(&obj),
+            Format::MachO => macho::write(&obj),
+            Format::Coff => coff::write(&obj),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use cretonne::ir::LibCall;
+
+    /// Build an object with an exported function `foo` calling an imported `bar`, and a data
+    /// object `baz` pointing at `foo`.
+    pub fn sample() -> Object {
+        let mut builder = ObjectBuilder {
+            format: Format::Elf,
+            text: Vec::new(),
+            data: Vec::new(),
+            rodata: Vec::new(),
+            names: Vec::new(),
+            defs: HashMap::new(),
+        };
+        let call = Relocation {
+            offset: 17,
+            reloc: Reloc::IntelPLTRel4,
+            target: "bar".to_string(),
+            addend: -4,
+        };
+        builder
+            .define_function("foo", Linkage::Export, &[0x90; 32], vec![call])
+            .unwrap();
+        let ptr = Relocation {
+            offset: 0,
+            reloc: Reloc::IntelAbs8,
+            target: "foo".to_string(),
+            addend: 0,
+        };
+        builder
+            .define_data("baz", Linkage::Export, DataKind::Writable, &[0; 8], vec![ptr])
+            .unwrap();
+        assert_eq!(
+            builder.define_function("foo", Linkage::Local, &[], Vec::new()),
+            Err(ObjectError::DuplicateDefinition("foo".to_string()))
+        );
+        builder.layout()
+    }
+
+    pub fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+        u16::from(bytes[offset]) | (u16::from(bytes[offset + 1]) << 8)
+    }
+
+    pub fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from(read_u16(bytes, offset)) | (u32::from(read_u16(bytes, offset + 2)) << 16)
+    }
+
+    pub fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+        u64::from(read_u32(bytes, offset)) | (u64::from(read_u32(bytes, offset + 4)) << 32)
+    }
+
+    #[test]
+    fn layout() {
+        let obj = sample();
+        let names: Vec<&str> = obj.symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["foo", "baz", "bar"]);
+        assert_eq!(obj.symbols[1].def, Some((SectionId::Data, 0)));
+        assert_eq!(obj.symbols[2].def, None);
+        assert_eq!(obj.text.relocs[0].symbol, 2);
+        assert_eq!(obj.data.relocs[0].symbol, 0);
+    }
+
+    #[test]
+    fn call_bias() {
+        let namer = default_symbol_name;
+        let mut collector = RelocCollector::new(&namer);
+        let name = ExternalName::testcase("foo");
+        collector.reloc_external(1, Reloc::IntelPCRel4, &name, 0);
+        collector.reloc_external(8, Reloc::IntelPLTRel4, &name, -4);
+        let addends: Vec<Addend> = collector.relocs.iter().map(|r| r.addend).collect();
+        assert_eq!(addends, [-4, -4]);
+    }
+
+    #[test]
+    fn symbol_names() {
+        assert_eq!(default_symbol_name(&ExternalName::testcase("foo")), "foo");
+        assert_eq!(default_symbol_name(&ExternalName::user(1, 2)), "u1_2");
+        assert_eq!(
+            default_symbol_name(&ExternalName::LibCall(LibCall::FloorF32)),
+            "floorf"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Little-endian byte buffer used by the object file writers.
+
+/// A growable little-endian byte buffer.
+pub struct Bytes {
+    data: Vec<u8>,
+}
+
+impl Bytes {
+    pub fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    /// Current length of the buffer.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn u8(&mut self, x: u8) {
+        self.data.push(x);
+    }
+
+    pub fn u16(&mut self, x: u16) {
+        self.bytes(&[x as u8, (x >> 8) as u8]);
+    }
+
+    pub fn u32(&mut self, x: u32) {
+        self.u16(x as u16);
+        self.u16((x >> 16) as u16);
+    }
+
+    pub fn u64(&mut self, x: u64) {
+        self.u32(x as u32);
+        self.u32((x >> 32) as u32);
+    }
+
+    pub fn bytes(&mut self, b: &[u8]) {
+        self.data.extend_from_slice(b);
+    }
+
+    /// Write `b` into a fixed-size field of `size` bytes, padding with zeros.
+    pub fn fixed(&mut self, b: &[u8], size: usize) {
+        debug_assert!(b.len() <= size);
+        self.bytes(b);
+        self.zeros(size - b.len());
+    }
+
+    pub fn zeros(&mut self, n: usize) {
+        let len = self.data.len();
+        self.data.resize(len + n, 0);
+    }
+
+    /// Pad with zeros until the length is a multiple of `align`.
+    pub fn align(&mut self, align: usize) {
+        let len = self.data.len();
+        self.zeros(align_to(len, align) - len);
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Round `x` up to a multiple of `align`, which must be a power of two.
+pub fn align_to(x: usize, align: usize) -> usize {
+    debug_assert!(align.is_power_of_two());
+    (x + align - 1) & !(align - 1)
+}
+
+/// A string table where each string is stored once, NUL-terminated.
+pub struct StringTable {
+    data: Vec<u8>,
+}
+
+impl StringTable {
+    /// Create a string table. The initial contents are written verbatim before any strings.
+    pub fn new(prefix: &[u8]) -> Self {
+        Self { data: prefix.to_vec() }
+    }
+
+    /// Add `s` to the table and return its offset.
+    pub fn add(&mut self, s: &str) -> u32 {
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(s.as_bytes());
+        self.data.push(0);
+        offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Write `x` as a little-endian 32-bit value at `offset` in `code`.
+pub fn patch_u32(code: &mut [u8], offset: usize, x: u32) {
+    for i in 0..4 {
+        code[offset + i] = (x >> (8 * i)) as u8;
+    }
+}
+
+/// Write `x` as a little-endian 64-bit value at `offset` in `code`.
+pub fn patch_u64(code: &mut [u8], offset: usize, x: u64) {
+    patch_u32(code, offset, x as u32);
+    patch_u32(code, offset + 4, (x >> 32) as u32);
+}
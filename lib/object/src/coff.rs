@@ -0,0 +1,189 @@
+//! Writing COFF relocatable object files.
+//!
+//! Only x86-64 (AMD64) objects are supported. The file layout is:
+//!
+//! - COFF file header and section table.
+//! - For each of `.text`, `.data`, and `.rdata`: the section contents followed by its
+//!   relocations.
+//! - Symbol table and string table.
+//!
+//! Like Mach-O, COFF relocations store their addend in the relocated field.
+
+use cretonne::binemit::Reloc;
+use builder::{Object, SECTIONS, SectionId};
+use bytes::{self, Bytes, StringTable, align_to};
+use super::{Format, ObjectError, Result};
+
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+
+const HEADER_SIZE: usize = 20;
+const SECTION_SIZE: usize = 40;
+const RELOC_SIZE: usize = 10;
+
+const IMAGE_SCN_CNT_CODE: u32 = 0x0000_0020;
+const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x0000_0040;
+const IMAGE_SCN_ALIGN_8BYTES: u32 = 0x0040_0000;
+const IMAGE_SCN_ALIGN_16BYTES: u32 = 0x0050_0000;
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+const IMAGE_SYM_CLASS_EXTERNAL: u8 = 2;
+const IMAGE_SYM_CLASS_STATIC: u8 = 3;
+const IMAGE_SYM_DTYPE_FUNCTION: u16 = 0x20;
+
+// AMD64 relocation types.
+const IMAGE_REL_AMD64_ADDR64: u16 = 0x1;
+const IMAGE_REL_AMD64_ADDR32: u16 = 0x2;
+const IMAGE_REL_AMD64_REL32: u16 = 0x4;
+
+/// Get the COFF relocation type for `reloc` and whether it is pc-relative.
+fn reloc_info(reloc: Reloc) -> Result<(u16, bool)> {
+    Ok(match reloc {
+        Reloc::IntelAbs8 => (IMAGE_REL_AMD64_ADDR64, false),
+        Reloc::IntelAbs4 => (IMAGE_REL_AMD64_ADDR32, false),
+        // There is no PLT in COFF; calls are resolved directly by the linker.
+        Reloc::IntelPCRel4 | Reloc::IntelPLTRel4 => (IMAGE_REL_AMD64_REL32, true),
+        _ => return Err(ObjectError::UnsupportedReloc(reloc, Format::Coff)),
+    })
+}
+
+/// Write `obj` as a COFF relocatable object file.
+pub fn write(obj: &Object) -> Result<Vec<u8>> {
+    let nsects = SECTIONS.len();
+
+    // Compute the file offsets of section contents and relocations.
+    let mut offsets = Vec::new();
+    let mut reloffs = Vec::new();
+    let mut offset = HEADER_SIZE + nsects * SECTION_SIZE;
+    for &id in &SECTIONS {
+        let section = obj.section(id);
+        offset = align_to(offset, 4);
+        offsets.push(offset);
+        offset += section.data.len();
+        reloffs.push(offset);
+        offset += section.relocs.len() * RELOC_SIZE;
+    }
+    let symoff = offset;
+
+    let mut out = Bytes::new();
+    out.u16(IMAGE_FILE_MACHINE_AMD64);
+    out.u16(nsects as u16);
+    out.u32(0); // TimeDateStamp
+    out.u32(symoff as u32);
+    out.u32(obj.symbols.len() as u32);
+    out.u16(0); // SizeOfOptionalHeader
+    out.u16(0); // Characteristics
+
+    for (i, &id) in SECTIONS.iter().enumerate() {
+        let section = obj.section(id);
+        let (name, flags) = match id {
+            SectionId::Text => (
+                ".text",
+                IMAGE_SCN_CNT_CODE | IMAGE_SCN_ALIGN_16BYTES | IMAGE_SCN_MEM_EXECUTE |
+                    IMAGE_SCN_MEM_READ,
+            ),
+            SectionId::Data => (
+                ".data",
+                IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_ALIGN_8BYTES | IMAGE_SCN_MEM_READ |
+                    IMAGE_SCN_MEM_WRITE,
+            ),
+            SectionId::ReadOnlyData => (
+                ".rdata",
+                IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_ALIGN_8BYTES | IMAGE_SCN_MEM_READ,
+            ),
+        };
+        out.fixed(name.as_bytes(), 8);
+        out.u32(0); // VirtualSize
+        out.u32(0); // VirtualAddress
+        out.u32(section.data.len() as u32);
+        out.u32(offsets[i] as u32);
+        out.u32(reloffs[i] as u32);
+        out.u32(0); // PointerToLinenumbers
+        out.u16(section.relocs.len() as u16);
+        out.u16(0); // NumberOfLinenumbers
+        out.u32(flags);
+    }
+
+    for (i, &id) in SECTIONS.iter().enumerate() {
+        let section = obj.section(id);
+        let mut data = section.data.clone();
+        for r in &section.relocs {
+            let (kind, pcrel) = reloc_info(r.reloc)?;
+            let offset = r.offset as usize;
+            if kind == IMAGE_REL_AMD64_ADDR64 {
+                bytes::patch_u64(&mut data, offset, r.addend as u64);
+            } else if pcrel {
+                // COFF pc-relative relocations are relative to the end of the field.
+                bytes::patch_u32(&mut data, offset, (r.addend + 4) as u32);
+            } else {
+                bytes::patch_u32(&mut data, offset, r.addend as u32);
+            }
+        }
+        out.zeros(offsets[i] - out.len());
+        out.bytes(&data);
+        for r in &section.relocs {
+            out.u32(r.offset as u32);
+            out.u32(r.symbol as u32);
+            out.u16(reloc_info(r.reloc)?.0);
+        }
+    }
+
+    // Symbol names longer than 8 bytes go in the string table, which starts with its own size.
+    let mut strtab = StringTable::new(&[0; 4]);
+    for sym in &obj.symbols {
+        if sym.name.len() <= 8 {
+            out.fixed(sym.name.as_bytes(), 8);
+        } else {
+            out.u32(0);
+            out.u32(strtab.add(&sym.name));
+        }
+        match sym.def {
+            Some((section, offset)) => {
+                out.u32(offset as u32);
+                out.u16(SECTIONS.iter().position(|&s| s == section).unwrap() as u16 + 1);
+            }
+            None => {
+                out.u32(0);
+                out.u16(0);
+            }
+        }
+        out.u16(if sym.is_func { IMAGE_SYM_DTYPE_FUNCTION } else { 0 });
+        out.u8(if sym.global {
+            IMAGE_SYM_CLASS_EXTERNAL
+        } else {
+            IMAGE_SYM_CLASS_STATIC
+        });
+        out.u8(0); // NumberOfAuxSymbols
+    }
+    let mut strings = strtab.as_bytes().to_vec();
+    let len = strings.len() as u32;
+    bytes::patch_u32(&mut strings, 0, len);
+    out.bytes(&strings);
+    Ok(out.into_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use builder::tests::{sample, read_u16, read_u32};
+
+    #[test]
+    fn header() {
+        let bytes = super::write(&sample()).unwrap();
+        assert_eq!(read_u16(&bytes, 0), super::IMAGE_FILE_MACHINE_AMD64);
+        assert_eq!(read_u16(&bytes, 2), 3);
+
+        // The `.text` relocation table has one entry against `bar`.
+        let text = super::HEADER_SIZE;
+        assert_eq!(&bytes[text..text + 5], b".text");
+        let reloff = read_u32(&bytes, text + 24) as usize;
+        assert_eq!(read_u16(&bytes, text + 32), 1);
+        assert_eq!(read_u32(&bytes, reloff), 17);
+        assert_eq!(read_u32(&bytes, reloff + 4), 2);
+        assert_eq!(read_u16(&bytes, reloff + 8), super::IMAGE_REL_AMD64_REL32);
+
+        // The in-place addend is relative to the end of the field.
+        let offset = read_u32(&bytes, text + 20) as usize;
+        assert_eq!(read_u32(&bytes, offset + 17), 0);
+    }
+}
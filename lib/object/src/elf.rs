@@ -0,0 +1,289 @@
+//! Writing ELF relocatable object files.
+//!
+//! Only 64-bit little-endian x86-64 objects are supported. The file layout is:
+//!
+//! - ELF header.
+//! - Contents of `.text`, `.data`, and `.rodata`.
+//! - `.rela.*` relocation sections for each of the above.
+//! - `.symtab`, `.strtab`, and `.shstrtab`.
+//! - The section header table.
+
+use cretonne::binemit::Reloc;
+use builder::{Object, SECTIONS, SectionId};
+use bytes::{Bytes, StringTable};
+use super::{Format, ObjectError, Result};
+
+// Section header indexes.
+const SHN_TEXT: u16 = 1;
+const SHN_SYMTAB: u32 = 7;
+const SHN_STRTAB: u32 = 8;
+const SHN_SHSTRTAB: u16 = 9;
+const NUM_SECTIONS: u16 = 11;
+
+// Section types.
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+
+// Section flags.
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+// Symbol bindings and types.
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+const EM_X86_64: u16 = 62;
+
+const EHDR_SIZE: usize = 64;
+const SHDR_SIZE: usize = 64;
+const SYM_SIZE: u64 = 24;
+const RELA_SIZE: u64 = 24;
+
+/// Get the x86-64 ELF relocation type for `reloc`.
+fn reloc_type(reloc: Reloc) -> Result<u32> {
+    Ok(match reloc {
+        Reloc::IntelAbs8 => 1, // R_X86_64_64
+        Reloc::IntelPCRel4 => 2, // R_X86_64_PC32
+        Reloc::IntelPLTRel4 => 4, // R_X86_64_PLT32
+        Reloc::IntelGOTPCRel4 => 9, // R_X86_64_GOTPCREL
+        Reloc::IntelAbs4 => 10, // R_X86_64_32
+        _ => return Err(ObjectError::UnsupportedReloc(reloc, Format::Elf)),
+    })
+}
+
+/// Get the section header index of the section `id`.
+fn section_index(id: SectionId) -> u16 {
+    SHN_TEXT + SECTIONS.iter().position(|&s| s == id).unwrap() as u16
+}
+
+/// A section header.
+struct Header {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+impl Header {
+    fn write(&self, out: &mut Bytes) {
+        out.u32(self.name);
+        out.u32(self.kind);
+        out.u64(self.flags);
+        out.u64(0); // sh_addr
+        out.u64(self.offset);
+        out.u64(self.size);
+        out.u32(self.link);
+        out.u32(self.info);
+        out.u64(self.align);
+        out.u64(self.entsize);
+    }
+}
+
+/// Write `obj` as an ELF relocatable object file.
+pub fn write(obj: &Object) -> Result<Vec<u8>> {
+    let mut out = Bytes::new();
+    let mut shstrtab = StringTable::new(b"\0");
+    let mut headers = Vec::new();
+
+    // Leave room for the ELF header which is written last.
+    out.zeros(EHDR_SIZE);
+
+    // Section contents.
+    for &id in &SECTIONS {
+        let section = obj.section(id);
+        out.align(section.align as usize);
+        let (name, flags) = match id {
+            SectionId::Text => (".text", SHF_ALLOC | SHF_EXECINSTR),
+            SectionId::Data => (".data", SHF_ALLOC | SHF_WRITE),
+            SectionId::ReadOnlyData => (".rodata", SHF_ALLOC),
+        };
+        headers.push(Header {
+            name: shstrtab.add(name),
+            kind: SHT_PROGBITS,
+            flags,
+            offset: out.len() as u64,
+            size: section.data.len() as u64,
+            link: 0,
+            info: 0,
+            align: section.align,
+            entsize: 0,
+        });
+        out.bytes(&section.data);
+    }
+
+    // Relocations. ELF symbol indexes are offset by one for the null symbol.
+    for &id in &SECTIONS {
+        let section = obj.section(id);
+        out.align(8);
+        let name = match id {
+            SectionId::Text => ".rela.text",
+            SectionId::Data => ".rela.data",
+            SectionId::ReadOnlyData => ".rela.rodata",
+        };
+        headers.push(Header {
+            name: shstrtab.add(name),
+            kind: SHT_RELA,
+            flags: SHF_INFO_LINK,
+            offset: out.len() as u64,
+            size: section.relocs.len() as u64 * RELA_SIZE,
+            link: SHN_SYMTAB,
+            info: u32::from(section_index(id)),
+            align: 8,
+            entsize: RELA_SIZE,
+        });
+        for r in &section.relocs {
+            out.u64(r.offset);
+            out.u64(((r.symbol as u64 + 1) << 32) | u64::from(reloc_type(r.reloc)?));
+            out.u64(r.addend as u64);
+        }
+    }
+
+    // Symbol table.
+    let mut strtab = StringTable::new(b"\0");
+    out.align(8);
+    let symtab_offset = out.len() as u64;
+    out.zeros(SYM_SIZE as usize);
+    for sym in &obj.symbols {
+        out.u32(strtab.add(&sym.name));
+        let bind = if sym.global { STB_GLOBAL } else { STB_LOCAL };
+        let kind = match sym.def {
+            None => STT_NOTYPE,
+            Some(_) if sym.is_func => STT_FUNC,
+            Some(_) => STT_OBJECT,
+        };
+        out.u8((bind << 4) | kind);
+        out.u8(0);
+        match sym.def {
+            Some((section, offset)) => {
+                out.u16(section_index(section));
+                out.u64(offset);
+            }
+            None => {
+                out.u16(0);
+                out.u64(0);
+            }
+        }
+        out.u64(sym.size);
+    }
+    let first_global = 1 + obj.symbols.iter().take_while(|s| !s.global).count();
+    headers.push(Header {
+        name: shstrtab.add(".symtab"),
+        kind: SHT_SYMTAB,
+        flags: 0,
+        offset: symtab_offset,
+        size: (obj.symbols.len() as u64 + 1) * SYM_SIZE,
+        link: SHN_STRTAB,
+        info: first_global as u32,
+        align: 8,
+        entsize: SYM_SIZE,
+    });
+
+    headers.push(Header {
+        name: shstrtab.add(".strtab"),
+        kind: SHT_STRTAB,
+        flags: 0,
+        offset: out.len() as u64,
+        size: strtab.len() as u64,
+        link: 0,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    });
+    out.bytes(strtab.as_bytes());
+
+    // Mark the stack as non-executable.
+    let gnu_stack = shstrtab.add(".note.GNU-stack");
+    let shstrtab_name = shstrtab.add(".shstrtab");
+    headers.push(Header {
+        name: shstrtab_name,
+        kind: SHT_STRTAB,
+        flags: 0,
+        offset: out.len() as u64,
+        size: shstrtab.len() as u64,
+        link: 0,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    });
+    out.bytes(shstrtab.as_bytes());
+    headers.push(Header {
+        name: gnu_stack,
+        kind: SHT_PROGBITS,
+        flags: 0,
+        offset: out.len() as u64,
+        size: 0,
+        link: 0,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    });
+
+    // Section header table, starting with the null section.
+    out.align(8);
+    let shoff = out.len() as u64;
+    out.zeros(SHDR_SIZE);
+    for header in &headers {
+        header.write(&mut out);
+    }
+    debug_assert_eq!(headers.len() + 1, NUM_SECTIONS as usize);
+
+    let mut bytes = out.into_vec();
+    let mut ehdr = Bytes::new();
+    ehdr.bytes(&[0x7f, b'E', b'L', b'F']);
+    ehdr.u8(2); // ELFCLASS64
+    ehdr.u8(1); // ELFDATA2LSB
+    ehdr.u8(1); // EV_CURRENT
+    ehdr.zeros(9);
+    ehdr.u16(1); // ET_REL
+    ehdr.u16(EM_X86_64);
+    ehdr.u32(1); // EV_CURRENT
+    ehdr.u64(0); // e_entry
+    ehdr.u64(0); // e_phoff
+    ehdr.u64(shoff);
+    ehdr.u32(0); // e_flags
+    ehdr.u16(EHDR_SIZE as u16);
+    ehdr.u16(0); // e_phentsize
+    ehdr.u16(0); // e_phnum
+    ehdr.u16(SHDR_SIZE as u16);
+    ehdr.u16(NUM_SECTIONS);
+    ehdr.u16(SHN_SHSTRTAB);
+    bytes[0..EHDR_SIZE].copy_from_slice(&ehdr.into_vec());
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use builder::tests::{sample, read_u16, read_u32, read_u64};
+
+    #[test]
+    fn header() {
+        let bytes = super::write(&sample()).unwrap();
+        assert_eq!(&bytes[0..4], b"\x7fELF");
+        assert_eq!(read_u16(&bytes, 16), 1);
+        assert_eq!(read_u16(&bytes, 18), super::EM_X86_64);
+        assert_eq!(read_u16(&bytes, 60), super::NUM_SECTIONS);
+
+        // Find the `.rela.text` section header and check the relocation against `bar`.
+        let shoff = read_u64(&bytes, 40) as usize;
+        let rela = shoff + 4 * super::SHDR_SIZE;
+        assert_eq!(read_u32(&bytes, rela + 4), super::SHT_RELA);
+        let offset = read_u64(&bytes, rela + 24) as usize;
+        assert_eq!(read_u64(&bytes, rela + 32), super::RELA_SIZE);
+        assert_eq!(read_u64(&bytes, offset), 17);
+        // `bar` is the third symbol after the null symbol, `foo`, and `baz`.
+        assert_eq!(read_u64(&bytes, offset + 8), (3 << 32) | 4);
+        assert_eq!(read_u64(&bytes, offset + 16) as i64, -4);
+    }
+}
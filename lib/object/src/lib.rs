@@ -0,0 +1,144 @@
+//! Object file emission for Cretonne.
+//!
+//! This crate collects compiled functions and data objects into an
+//! [`ObjectBuilder`](struct.ObjectBuilder.html), lays them out in sections and writes a
+//! relocatable object file in one of the supported [`Format`](enum.Format.html)s. The resulting
+//! file can be handed to a system linker, so Cretonne can be used as an ahead-of-time compiler
+//! without going through an external assembler.
+//!
+//! When the functions are managed by a `cton_module::Module`, use
+//! [`ObjectBackend`](struct.ObjectBackend.html) as its backend instead of driving the builder
+//! directly.
+//!
+//! ```no_run
+//! # extern crate cretonne;
+//! # extern crate cton_object;
+//! # fn main() {
+//! use cretonne::Context;
+//! use cretonne::isa::TargetIsa;
+//! use cton_object::{ObjectBuilder, Format, Linkage, default_symbol_name};
+//!
+//! # let isa: Box<TargetIsa> = unimplemented!();
+//! let mut ctx = Context::new();
+//! // ... fill in `ctx.func` ...
+//! let mut obj = ObjectBuilder::new(&*isa, Format::Elf).unwrap();
+//! obj.compile_function("main", Linkage::Export, &mut ctx, &*isa, &default_symbol_name)
+//!     .unwrap();
+//! let bytes = obj.finish().unwrap();
+//! # }
+//! ```
+
+#![deny(missing_docs,
+        trivial_numeric_casts,
+        unused_extern_crates)]
+
+extern crate cretonne;
+extern crate cton_module;
+
+mod backend;
+mod builder;
+mod bytes;
+mod coff;
+mod elf;
+mod macho;
+
+pub use backend::ObjectBackend;
+pub use builder::{ObjectBuilder, Relocation, RelocCollector, default_symbol_name};
+
+use cretonne::binemit::Reloc;
+use std::error::Error as StdError;
+use std::fmt;
+
+/// Object file formats that can be written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// ELF, used on Linux and most other Unix systems.
+    Elf,
+    /// Mach-O, used on macOS.
+    MachO,
+    /// COFF, used on Windows.
+    Coff,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Format::Elf => "ELF",
+            Format::MachO => "Mach-O",
+            Format::Coff => "COFF",
+        })
+    }
+}
+
+/// The visibility of a symbol defined in the object file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Linkage {
+    /// Only visible inside the object file.
+    Local,
+    /// Visible to other object files being linked.
+    Export,
+}
+
+/// The kind of section a data object is placed in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataKind {
+    /// Data that may be written at runtime.
+    Writable,
+    /// Data that is never written at runtime.
+    ReadOnly,
+}
+
+/// An error encountered while collecting definitions or writing an object file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ObjectError {
+    /// The target ISA or pointer width can't be represented in object files yet.
+    UnsupportedTarget(String),
+
+    /// A symbol was defined more than once.
+    DuplicateDefinition(String),
+
+    /// A relocation kind can't be represented in the requested format.
+    UnsupportedReloc(Reloc, Format),
+
+    /// A relocation that refers to code inside the function being defined, such as an EBB or a
+    /// jump table, wasn't resolved before the function was added.
+    UnresolvedLocalReloc(String),
+
+    /// Compiling a function failed.
+    Compile(String),
+}
+
+/// Result type for object file operations.
+pub type Result<T> = ::std::result::Result<T, ObjectError>;
+
+impl fmt::Display for ObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjectError::UnsupportedTarget(ref isa) => {
+                write!(f, "object files are not supported for {}", isa)
+            }
+            ObjectError::DuplicateDefinition(ref name) => {
+                write!(f, "duplicate definition of '{}'", name)
+            }
+            ObjectError::UnsupportedReloc(reloc, format) => {
+                write!(f, "{:?} relocations are not supported in {} files", reloc, format)
+            }
+            ObjectError::UnresolvedLocalReloc(ref name) => {
+                write!(f, "'{}' contains an unresolved EBB or jump table relocation", name)
+            }
+            ObjectError::Compile(ref msg) => write!(f, "compilation failed: {}", msg),
+        }
+    }
+}
+
+impl StdError for ObjectError {
+    fn description(&self) -> &str {
+        match *self {
+            ObjectError::UnsupportedTarget(_) => "unsupported target",
+            ObjectError::DuplicateDefinition(_) => "duplicate definition",
+            ObjectError::UnsupportedReloc(..) => "unsupported relocation",
+            ObjectError::UnresolvedLocalReloc(_) => "unresolved local relocation",
+            ObjectError::Compile(_) => "compilation failed",
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Writing Mach-O relocatable object files.
+//!
+//! Only x86-64 objects are supported. All sections are placed in a single unnamed segment as is
+//! customary for `MH_OBJECT` files:
+//!
+//! - Mach-O header and load commands.
+//! - Contents of `__TEXT,__text`, `__DATA,__data`, and `__TEXT,__const`.
+//! - Relocations for each section.
+//! - Symbol table and string table.
+//!
+//! Mach-O relocations store their addend in the relocated field, so the section contents are
+//! patched while writing.
+
+use cretonne::binemit::Reloc;
+use builder::{Object, SECTIONS, SectionId};
+use bytes::{self, Bytes, StringTable, align_to};
+use super::{Format, ObjectError, Result};
+
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+const CPU_SUBTYPE_X86_64_ALL: u32 = 3;
+const MH_OBJECT: u32 = 1;
+
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_SYMTAB: u32 = 0x2;
+const LC_DYSYMTAB: u32 = 0xb;
+
+const HEADER_SIZE: usize = 32;
+const SEGMENT_SIZE: usize = 72;
+const SECTION_SIZE: usize = 80;
+const SYMTAB_SIZE: usize = 24;
+const DYSYMTAB_SIZE: usize = 80;
+const RELOC_SIZE: usize = 8;
+const NLIST_SIZE: usize = 16;
+
+const S_ATTR_PURE_INSTRUCTIONS: u32 = 0x8000_0000;
+const S_ATTR_SOME_INSTRUCTIONS: u32 = 0x0000_0400;
+
+const N_EXT: u8 = 0x01;
+const N_SECT: u8 = 0x0e;
+
+// x86-64 relocation types.
+const X86_64_RELOC_UNSIGNED: u32 = 0;
+const X86_64_RELOC_SIGNED: u32 = 1;
+const X86_64_RELOC_BRANCH: u32 = 2;
+const X86_64_RELOC_GOT_LOAD: u32 = 3;
+
+/// Get the Mach-O relocation type, pc-relative flag, and log2 of the field size for `reloc`.
+fn reloc_info(reloc: Reloc) -> Result<(u32, bool, u32)> {
+    Ok(match reloc {
+        Reloc::IntelAbs8 => (X86_64_RELOC_UNSIGNED, false, 3),
+        Reloc::IntelPCRel4 => (X86_64_RELOC_SIGNED, true, 2),
+        Reloc::IntelPLTRel4 => (X86_64_RELOC_BRANCH, true, 2),
+        Reloc::IntelGOTPCRel4 => (X86_64_RELOC_GOT_LOAD, true, 2),
+        _ => return Err(ObjectError::UnsupportedReloc(reloc, Format::MachO)),
+    })
+}
+
+/// Write `obj` as a Mach-O relocatable object file.
+pub fn write(obj: &Object) -> Result<Vec<u8>> {
+    let nsects = SECTIONS.len();
+    let sizeofcmds = SEGMENT_SIZE + nsects * SECTION_SIZE + SYMTAB_SIZE + DYSYMTAB_SIZE;
+
+    // Compute the addresses and file offsets of the section contents.
+    let mut addrs = Vec::new();
+    let mut offsets = Vec::new();
+    let mut addr = 0;
+    let mut offset = HEADER_SIZE + sizeofcmds;
+    for &id in &SECTIONS {
+        let section = obj.section(id);
+        let align = section.align as usize;
+        addr = align_to(addr, align);
+        offset = align_to(offset, align);
+        addrs.push(addr);
+        offsets.push(offset);
+        addr += section.data.len();
+        offset += section.data.len();
+    }
+    let segment_size = addr;
+    let segment_offset = HEADER_SIZE + sizeofcmds;
+    let segment_filesize = offset - segment_offset;
+
+    // Relocations come next.
+    let mut reloffs = Vec::new();
+    let mut reloff = align_to(offset, 4);
+    for &id in &SECTIONS {
+        reloffs.push(reloff);
+        reloff += obj.section(id).relocs.len() * RELOC_SIZE;
+    }
+    let symoff = align_to(reloff, 8);
+    let stroff = symoff + obj.symbols.len() * NLIST_SIZE;
+
+    let mut strtab = StringTable::new(b"\0");
+    let strx: Vec<u32> = obj.symbols
+        .iter()
+        .map(|sym| strtab.add(&format!("_{}", sym.name)))
+        .collect();
+
+    let mut out = Bytes::new();
+    out.u32(MH_MAGIC_64);
+    out.u32(CPU_TYPE_X86_64);
+    out.u32(CPU_SUBTYPE_X86_64_ALL);
+    out.u32(MH_OBJECT);
+    out.u32(3); // ncmds
+    out.u32(sizeofcmds as u32);
+    out.u32(0); // flags
+    out.u32(0); // reserved
+
+    out.u32(LC_SEGMENT_64);
+    out.u32((SEGMENT_SIZE + nsects * SECTION_SIZE) as u32);
+    out.zeros(16); // segname
+    out.u64(0); // vmaddr
+    out.u64(segment_size as u64);
+    out.u64(segment_offset as u64);
+    out.u64(segment_filesize as u64);
+    out.u32(7); // maxprot
+    out.u32(7); // initprot
+    out.u32(nsects as u32);
+    out.u32(0); // flags
+
+    for (i, &id) in SECTIONS.iter().enumerate() {
+        let section = obj.section(id);
+        let (sectname, segname, flags) = match id {
+            SectionId::Text => (
+                "__text",
+                "__TEXT",
+                S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS,
+            ),
+            SectionId::Data => ("__data", "__DATA", 0),
+            SectionId::ReadOnlyData => ("__const", "__TEXT", 0),
+        };
+        out.fixed(sectname.as_bytes(), 16);
+        out.fixed(segname.as_bytes(), 16);
+        out.u64(addrs[i] as u64);
+        out.u64(section.data.len() as u64);
+        out.u32(offsets[i] as u32);
+        out.u32(section.align.trailing_zeros());
+        out.u32(reloffs[i] as u32);
+        out.u32(section.relocs.len() as u32);
+        out.u32(flags);
+        out.zeros(12); // reserved1-3
+    }
+
+    out.u32(LC_SYMTAB);
+    out.u32(SYMTAB_SIZE as u32);
+    out.u32(symoff as u32);
+    out.u32(obj.symbols.len() as u32);
+    out.u32(stroff as u32);
+    out.u32(strtab.len() as u32);
+
+    let nlocal = obj.symbols.iter().take_while(|s| !s.global).count();
+    let nextdef = obj.symbols[nlocal..]
+        .iter()
+        .take_while(|s| s.def.is_some())
+        .count();
+    let nundef = obj.symbols.len() - nlocal - nextdef;
+    out.u32(LC_DYSYMTAB);
+    out.u32(DYSYMTAB_SIZE as u32);
+    out.u32(0); // ilocalsym
+    out.u32(nlocal as u32);
+    out.u32(nlocal as u32); // iextdefsym
+    out.u32(nextdef as u32);
+    out.u32((nlocal + nextdef) as u32); // iundefsym
+    out.u32(nundef as u32);
+    out.zeros(12 * 4);
+    debug_assert_eq!(out.len(), HEADER_SIZE + sizeofcmds);
+
+    // Section contents with in-place addends.
+    for (i, &id) in SECTIONS.iter().enumerate() {
+        let section = obj.section(id);
+        let mut data = section.data.clone();
+        for r in &section.relocs {
+            let (_, pcrel, length) = reloc_info(r.reloc)?;
+            let offset = r.offset as usize;
+            if length == 3 {
+                bytes::patch_u64(&mut data, offset, r.addend as u64);
+            } else if pcrel {
+                // Mach-O pc-relative relocations are relative to the end of the field.
+                bytes::patch_u32(&mut data, offset, (r.addend + 4) as u32);
+            } else {
+                bytes::patch_u32(&mut data, offset, r.addend as u32);
+            }
+        }
+        out.zeros(offsets[i] - out.len());
+        out.bytes(&data);
+    }
+
+    out.align(4);
+    for &id in &SECTIONS {
+        for r in &obj.section(id).relocs {
+            let (kind, pcrel, length) = reloc_info(r.reloc)?;
+            out.u32(r.offset as u32);
+            out.u32(
+                (r.symbol as u32) | (u32::from(pcrel) << 24) | (length << 25) | (1 << 27) |
+                    (kind << 28),
+            );
+        }
+    }
+
+    out.align(8);
+    debug_assert_eq!(out.len(), symoff);
+    for (sym, &strx) in obj.symbols.iter().zip(&strx) {
+        out.u32(strx);
+        let ext = if sym.global { N_EXT } else { 0 };
+        match sym.def {
+            Some((section, offset)) => {
+                let index = SECTIONS.iter().position(|&s| s == section).unwrap();
+                out.u8(N_SECT | ext);
+                out.u8(index as u8 + 1);
+                out.u16(0);
+                out.u64(addrs[index] as u64 + offset);
+            }
+            None => {
+                out.u8(ext);
+                out.u8(0);
+                out.u16(0);
+                out.u64(0);
+            }
+        }
+    }
+    out.bytes(strtab.as_bytes());
+    Ok(out.into_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use builder::tests::{sample, read_u32};
+
+    #[test]
+    fn header() {
+        let bytes = super::write(&sample()).unwrap();
+        assert_eq!(read_u32(&bytes, 0), super::MH_MAGIC_64);
+        assert_eq!(read_u32(&bytes, 12), super::MH_OBJECT);
+
+        // The call to `bar` has an in-place addend of 0.
+        let text_offset = read_u32(&bytes, 32 + 72 + 48) as usize;
+        assert_eq!(read_u32(&bytes, text_offset + 17), 0);
+
+        // First relocation of `__text`.
+        let reloff = read_u32(&bytes, 32 + 72 + 56) as usize;
+        assert_eq!(read_u32(&bytes, reloff), 17);
+        assert_eq!(
+            read_u32(&bytes, reloff + 4),
+            2 | (1 << 24) | (2 << 25) | (1 << 27) | (super::X86_64_RELOC_BRANCH << 28)
+        );
+    }
+}
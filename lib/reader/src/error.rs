@@ -11,6 +11,20 @@ pub struct Location {
     /// Line number. Command-line arguments are line 0 and source file
     /// lines start from 1.
     pub line_number: usize,
+    /// Column of the first character, counted in characters from 1. Command-line arguments are
+    /// column 0.
+    pub column: usize,
+    /// Byte span of the located text in the source.
+    pub span: Span,
+}
+
+/// A range of bytes `start..end` in the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    /// Offset of the first byte.
+    pub start: usize,
+    /// Offset of the byte following the last one.
+    pub end: usize,
 }
 
 /// A parse error is returned when the parse failed.
@@ -27,11 +41,69 @@ impl fmt::Display for Error {
         if self.location.line_number == 0 {
             write!(f, "command-line arguments: {}", self.message)
         } else {
-            write!(f, "{}: {}", self.location.line_number, self.message)
+            write!(
+                f,
+                "{}:{}: {}",
+                self.location.line_number,
+                self.location.column,
+                self.message
+            )
         }
     }
 }
 
+impl Error {
+    /// Render this error as a diagnostic quoting the offending text in `source`, the text that was
+    /// parsed from the file named `filename`.
+    pub fn diagnostic(&self, filename: &str, source: &str) -> String {
+        diagnostic(filename, source, self.location, &self.message)
+    }
+}
+
+/// Render a diagnostic for `message` at `location` in `source`, in the style of rustc:
+///
+/// ```text
+/// error: expected instruction opcode
+///  --> test.cton:3:10
+///   |
+/// 3 |     v1 = $ v0
+///   |          ^
+/// ```
+///
+/// The carets underline the whole span of `location` on its first line.
+pub fn diagnostic(filename: &str, source: &str, location: Location, message: &str) -> String {
+    if location.line_number == 0 {
+        return format!("error: {}\n --> {}: command-line arguments\n", message, filename);
+    }
+    let mut out = format!(
+        "error: {}\n --> {}:{}:{}\n",
+        message,
+        filename,
+        location.line_number,
+        location.column
+    );
+    let line = match source.lines().nth(location.line_number - 1) {
+        Some(line) => line,
+        None => return out,
+    };
+    let gutter = location.line_number.to_string();
+    let blank = " ".repeat(gutter.len());
+    out.push_str(&format!("{} |\n{} | {}\n{} | ", blank, gutter, line, blank));
+
+    // Keep the tabs in front of the carets so they line up with the quoted line.
+    let indent = location.column.saturating_sub(1);
+    for ch in line.chars().take(indent) {
+        out.push(if ch == '\t' { '\t' } else { ' ' });
+    }
+    let width = source
+        .get(location.span.start..location.span.end)
+        .and_then(|text| text.lines().next())
+        .map_or(0, |text| text.chars().count());
+    out.push_str(&"^".repeat(width.max(1)));
+    out.push('\n');
+    out
+}
+
 /// Result of a parser operation. The `Error` variant includes a location.
 pub type Result<T> = result::Result<T, Error>;
 
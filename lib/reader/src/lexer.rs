@@ -6,7 +6,7 @@ use std::u16;
 use std::ascii::AsciiExt;
 use cretonne::ir::types;
 use cretonne::ir::{Value, Ebb};
-use error::Location;
+use error::{Location, Span};
 
 /// A Token returned from the `Lexer`.
 ///
@@ -123,6 +123,9 @@ pub struct Lexer<'a> {
 
     // Current line number.
     line_number: usize,
+
+    // Index into `source` of the first character on the current line.
+    line_start: usize,
 }
 
 impl<'a> Lexer<'a> {
@@ -133,6 +136,7 @@ impl<'a> Lexer<'a> {
             lookahead: None,
             pos: 0,
             line_number: 1,
+            line_start: 0,
         };
         // Advance to the first char.
         lex.next_ch();
@@ -143,9 +147,7 @@ impl<'a> Lexer<'a> {
     // Return the next lookahead character, or None when the end is encountered.
     // Always update cur_ch to reflect
     fn next_ch(&mut self) -> Option<char> {
-        if self.lookahead == Some('\n') {
-            self.line_number += 1;
-        }
+        let new_line = self.lookahead == Some('\n');
         match self.chars.next() {
             Some((idx, ch)) => {
                 self.pos = idx;
@@ -156,12 +158,23 @@ impl<'a> Lexer<'a> {
                 self.lookahead = None;
             }
         }
+        if new_line {
+            self.line_number += 1;
+            self.line_start = self.pos;
+        }
         self.lookahead
     }
 
-    // Get the location corresponding to `lookahead`.
+    // Get the location corresponding to `lookahead`, with an empty span.
     fn loc(&self) -> Location {
-        Location { line_number: self.line_number }
+        Location {
+            line_number: self.line_number,
+            column: self.source[self.line_start..self.pos].chars().count() + 1,
+            span: Span {
+                start: self.pos,
+                end: self.pos,
+            },
+        }
     }
 
     // Starting from `lookahead`, are we looking at `prefix`?
@@ -413,7 +426,7 @@ impl<'a> Lexer<'a> {
     pub fn next(&mut self) -> Option<Result<LocatedToken<'a>, LocatedError>> {
         loop {
             let loc = self.loc();
-            return match self.lookahead {
+            let result = match self.lookahead {
                 None => None,
                 Some(';') => Some(self.scan_comment()),
                 Some('(') => Some(self.scan_char(Token::LPar)),
@@ -449,6 +462,18 @@ impl<'a> Lexer<'a> {
                     Some(error(Error::InvalidChar, loc))
                 }
             };
+            // The token ends where the lookahead character is now.
+            let end = self.pos;
+            return result.map(|res| match res {
+                Ok(mut tok) => {
+                    tok.location.span.end = end;
+                    Ok(tok)
+                }
+                Err(mut err) => {
+                    err.location.span.end = end;
+                    Err(err)
+                }
+            });
         }
     }
 }
@@ -486,11 +511,26 @@ mod tests {
     }
 
     fn token<'a>(token: Token<'a>, line: usize) -> Option<Result<LocatedToken<'a>, LocatedError>> {
-        Some(super::token(token, Location { line_number: line }))
+        Some(super::token(token, line_only(line)))
     }
 
     fn error<'a>(error: Error, line: usize) -> Option<Result<LocatedToken<'a>, LocatedError>> {
-        Some(super::error(error, Location { line_number: line }))
+        Some(super::error(error, line_only(line)))
+    }
+
+    fn line_only(line: usize) -> Location {
+        Location {
+            line_number: line,
+            ..Default::default()
+        }
+    }
+
+    // Get the next token from `lex`, keeping only the line number of its location.
+    fn next<'a>(lex: &mut Lexer<'a>) -> Option<Result<LocatedToken<'a>, LocatedError>> {
+        lex.next().map(|res| match res {
+            Ok(tok) => token(tok.token, tok.location.line_number).unwrap(),
+            Err(err) => error(err.error, err.location.line_number).unwrap(),
+        })
     }
 
     #[test]
@@ -499,57 +539,57 @@ mod tests {
         let mut l2 = Lexer::new(" ");
         let mut l3 = Lexer::new("\n ");
 
-        assert_eq!(l1.next(), None);
-        assert_eq!(l2.next(), None);
-        assert_eq!(l3.next(), None);
+        assert_eq!(next(&mut l1), None);
+        assert_eq!(next(&mut l2), None);
+        assert_eq!(next(&mut l3), None);
     }
 
     #[test]
     fn lex_comment() {
         let mut lex = Lexer::new("; hello");
-        assert_eq!(lex.next(), token(Token::Comment("; hello"), 1));
-        assert_eq!(lex.next(), None);
+        assert_eq!(next(&mut lex), token(Token::Comment("; hello"), 1));
+        assert_eq!(next(&mut lex), None);
 
         lex = Lexer::new("\n  ;hello\n;foo");
-        assert_eq!(lex.next(), token(Token::Comment(";hello"), 2));
-        assert_eq!(lex.next(), token(Token::Comment(";foo"), 3));
-        assert_eq!(lex.next(), None);
+        assert_eq!(next(&mut lex), token(Token::Comment(";hello"), 2));
+        assert_eq!(next(&mut lex), token(Token::Comment(";foo"), 3));
+        assert_eq!(next(&mut lex), None);
 
         // Scan a comment after an invalid char.
         let mut lex = Lexer::new("$; hello");
-        assert_eq!(lex.next(), error(Error::InvalidChar, 1));
-        assert_eq!(lex.next(), token(Token::Comment("; hello"), 1));
-        assert_eq!(lex.next(), None);
+        assert_eq!(next(&mut lex), error(Error::InvalidChar, 1));
+        assert_eq!(next(&mut lex), token(Token::Comment("; hello"), 1));
+        assert_eq!(next(&mut lex), None);
     }
 
     #[test]
     fn lex_chars() {
         let mut lex = Lexer::new("(); hello\n = :{, }.");
-        assert_eq!(lex.next(), token(Token::LPar, 1));
-        assert_eq!(lex.next(), token(Token::RPar, 1));
-        assert_eq!(lex.next(), token(Token::Comment("; hello"), 1));
-        assert_eq!(lex.next(), token(Token::Equal, 2));
-        assert_eq!(lex.next(), token(Token::Colon, 2));
-        assert_eq!(lex.next(), token(Token::LBrace, 2));
-        assert_eq!(lex.next(), token(Token::Comma, 2));
-        assert_eq!(lex.next(), token(Token::RBrace, 2));
-        assert_eq!(lex.next(), token(Token::Dot, 2));
-        assert_eq!(lex.next(), None);
+        assert_eq!(next(&mut lex), token(Token::LPar, 1));
+        assert_eq!(next(&mut lex), token(Token::RPar, 1));
+        assert_eq!(next(&mut lex), token(Token::Comment("; hello"), 1));
+        assert_eq!(next(&mut lex), token(Token::Equal, 2));
+        assert_eq!(next(&mut lex), token(Token::Colon, 2));
+        assert_eq!(next(&mut lex), token(Token::LBrace, 2));
+        assert_eq!(next(&mut lex), token(Token::Comma, 2));
+        assert_eq!(next(&mut lex), token(Token::RBrace, 2));
+        assert_eq!(next(&mut lex), token(Token::Dot, 2));
+        assert_eq!(next(&mut lex), None);
     }
 
     #[test]
     fn lex_numbers() {
         let mut lex = Lexer::new(" 0 2_000 -1,0xf -0x0 0.0 0x0.4p-34 +5");
-        assert_eq!(lex.next(), token(Token::Integer("0"), 1));
-        assert_eq!(lex.next(), token(Token::Integer("2_000"), 1));
-        assert_eq!(lex.next(), token(Token::Integer("-1"), 1));
-        assert_eq!(lex.next(), token(Token::Comma, 1));
-        assert_eq!(lex.next(), token(Token::Integer("0xf"), 1));
-        assert_eq!(lex.next(), token(Token::Integer("-0x0"), 1));
-        assert_eq!(lex.next(), token(Token::Float("0.0"), 1));
-        assert_eq!(lex.next(), token(Token::Float("0x0.4p-34"), 1));
-        assert_eq!(lex.next(), token(Token::Integer("+5"), 1));
-        assert_eq!(lex.next(), None);
+        assert_eq!(next(&mut lex), token(Token::Integer("0"), 1));
+        assert_eq!(next(&mut lex), token(Token::Integer("2_000"), 1));
+        assert_eq!(next(&mut lex), token(Token::Integer("-1"), 1));
+        assert_eq!(next(&mut lex), token(Token::Comma, 1));
+        assert_eq!(next(&mut lex), token(Token::Integer("0xf"), 1));
+        assert_eq!(next(&mut lex), token(Token::Integer("-0x0"), 1));
+        assert_eq!(next(&mut lex), token(Token::Float("0.0"), 1));
+        assert_eq!(next(&mut lex), token(Token::Float("0x0.4p-34"), 1));
+        assert_eq!(next(&mut lex), token(Token::Integer("+5"), 1));
+        assert_eq!(next(&mut lex), None);
     }
 
     #[test]
@@ -560,63 +600,88 @@ mod tests {
              iflags fflags iflagss",
         );
         assert_eq!(
-            lex.next(),
+            next(&mut lex),
             token(Token::Value(Value::with_number(0).unwrap()), 1)
         );
-        assert_eq!(lex.next(), token(Token::Identifier("v00"), 1));
-        assert_eq!(lex.next(), token(Token::Identifier("vx01"), 1));
+        assert_eq!(next(&mut lex), token(Token::Identifier("v00"), 1));
+        assert_eq!(next(&mut lex), token(Token::Identifier("vx01"), 1));
         assert_eq!(
-            lex.next(),
+            next(&mut lex),
             token(Token::Ebb(Ebb::with_number(1234567890).unwrap()), 1)
         );
-        assert_eq!(lex.next(), token(Token::Identifier("ebb5234567890"), 1));
-        assert_eq!(lex.next(), token(Token::Identifier("v1x"), 1));
-        assert_eq!(lex.next(), token(Token::Identifier("vx1"), 1));
-        assert_eq!(lex.next(), token(Token::Identifier("vxvx4"), 1));
-        assert_eq!(lex.next(), token(Token::Identifier("function0"), 1));
-        assert_eq!(lex.next(), token(Token::Identifier("function"), 1));
-        assert_eq!(lex.next(), token(Token::Type(types::B1), 1));
-        assert_eq!(lex.next(), token(Token::Type(types::I32X4), 1));
-        assert_eq!(lex.next(), token(Token::Identifier("f32x5"), 1));
-        assert_eq!(lex.next(), token(Token::Type(types::IFLAGS), 1));
-        assert_eq!(lex.next(), token(Token::Type(types::FFLAGS), 1));
-        assert_eq!(lex.next(), token(Token::Identifier("iflagss"), 1));
-        assert_eq!(lex.next(), None);
+        assert_eq!(next(&mut lex), token(Token::Identifier("ebb5234567890"), 1));
+        assert_eq!(next(&mut lex), token(Token::Identifier("v1x"), 1));
+        assert_eq!(next(&mut lex), token(Token::Identifier("vx1"), 1));
+        assert_eq!(next(&mut lex), token(Token::Identifier("vxvx4"), 1));
+        assert_eq!(next(&mut lex), token(Token::Identifier("function0"), 1));
+        assert_eq!(next(&mut lex), token(Token::Identifier("function"), 1));
+        assert_eq!(next(&mut lex), token(Token::Type(types::B1), 1));
+        assert_eq!(next(&mut lex), token(Token::Type(types::I32X4), 1));
+        assert_eq!(next(&mut lex), token(Token::Identifier("f32x5"), 1));
+        assert_eq!(next(&mut lex), token(Token::Type(types::IFLAGS), 1));
+        assert_eq!(next(&mut lex), token(Token::Type(types::FFLAGS), 1));
+        assert_eq!(next(&mut lex), token(Token::Identifier("iflagss"), 1));
+        assert_eq!(next(&mut lex), None);
     }
 
     #[test]
     fn lex_hex_sequences() {
         let mut lex = Lexer::new("#0 #DEADbeef123 #789");
 
-        assert_eq!(lex.next(), token(Token::HexSequence("0"), 1));
-        assert_eq!(lex.next(), token(Token::HexSequence("DEADbeef123"), 1));
-        assert_eq!(lex.next(), token(Token::HexSequence("789"), 1));
+        assert_eq!(next(&mut lex), token(Token::HexSequence("0"), 1));
+        assert_eq!(next(&mut lex), token(Token::HexSequence("DEADbeef123"), 1));
+        assert_eq!(next(&mut lex), token(Token::HexSequence("789"), 1));
     }
 
     #[test]
     fn lex_names() {
         let mut lex = Lexer::new("%0 %x3 %function %123_abc %ss0 %v3 %ebb11 %_");
 
-        assert_eq!(lex.next(), token(Token::Name("0"), 1));
-        assert_eq!(lex.next(), token(Token::Name("x3"), 1));
-        assert_eq!(lex.next(), token(Token::Name("function"), 1));
-        assert_eq!(lex.next(), token(Token::Name("123_abc"), 1));
-        assert_eq!(lex.next(), token(Token::Name("ss0"), 1));
-        assert_eq!(lex.next(), token(Token::Name("v3"), 1));
-        assert_eq!(lex.next(), token(Token::Name("ebb11"), 1));
-        assert_eq!(lex.next(), token(Token::Name("_"), 1));
+        assert_eq!(next(&mut lex), token(Token::Name("0"), 1));
+        assert_eq!(next(&mut lex), token(Token::Name("x3"), 1));
+        assert_eq!(next(&mut lex), token(Token::Name("function"), 1));
+        assert_eq!(next(&mut lex), token(Token::Name("123_abc"), 1));
+        assert_eq!(next(&mut lex), token(Token::Name("ss0"), 1));
+        assert_eq!(next(&mut lex), token(Token::Name("v3"), 1));
+        assert_eq!(next(&mut lex), token(Token::Name("ebb11"), 1));
+        assert_eq!(next(&mut lex), token(Token::Name("_"), 1));
     }
 
     #[test]
     fn lex_userrefs() {
         let mut lex = Lexer::new("u0 u1 u234567890 u9:8765");
 
-        assert_eq!(lex.next(), token(Token::UserRef(0), 1));
-        assert_eq!(lex.next(), token(Token::UserRef(1), 1));
-        assert_eq!(lex.next(), token(Token::UserRef(234567890), 1));
-        assert_eq!(lex.next(), token(Token::UserRef(9), 1));
-        assert_eq!(lex.next(), token(Token::Colon, 1));
-        assert_eq!(lex.next(), token(Token::Integer("8765"), 1));
-        assert_eq!(lex.next(), None);
+        assert_eq!(next(&mut lex), token(Token::UserRef(0), 1));
+        assert_eq!(next(&mut lex), token(Token::UserRef(1), 1));
+        assert_eq!(next(&mut lex), token(Token::UserRef(234567890), 1));
+        assert_eq!(next(&mut lex), token(Token::UserRef(9), 1));
+        assert_eq!(next(&mut lex), token(Token::Colon, 1));
+        assert_eq!(next(&mut lex), token(Token::Integer("8765"), 1));
+        assert_eq!(next(&mut lex), None);
+    }
+
+    #[test]
+    fn lex_spans() {
+        let mut lex = Lexer::new("v1 = iadd\n\t  -> %foo $ ; c");
+        let mut spans = Vec::new();
+        while let Some(res) = lex.next() {
+            let loc = match res {
+                Ok(tok) => tok.location,
+                Err(err) => err.location,
+            };
+            spans.push((loc.line_number, loc.column, loc.span.start, loc.span.end));
+        }
+        assert_eq!(
+            spans,
+            [
+                (1, 1, 0, 2),
+                (1, 4, 3, 4),
+                (1, 6, 5, 9),
+                (2, 4, 13, 15),
+                (2, 7, 16, 20),
+                (2, 12, 21, 22),
+                (2, 14, 23, 26),
+            ]
+        );
     }
 }
@@ -9,10 +9,10 @@
 
 extern crate cretonne;
 
-pub use error::{Location, Result, Error};
-pub use parser::{parse_functions, parse_test};
+pub use error::{Location, Span, Result, Error, diagnostic};
+pub use parser::{parse_functions, parse_test, parse_test_with_recovery, parse_instruction};
 pub use testcommand::{TestCommand, TestOption};
-pub use testfile::{TestFile, Details, Comment};
+pub use testfile::{TestFile, Details, Comment, Annotations, Annotation};
 pub use isaspec::{IsaSpec, parse_options};
 pub use sourcemap::SourceMap;
 
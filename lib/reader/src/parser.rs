@@ -1,10 +1,12 @@
 //! Parser for .cton files.
 
 use std::str::FromStr;
+use std::fmt;
 use std::{u16, u32};
 use std::mem;
-use cretonne::ir::{Function, Ebb, Opcode, Value, Type, ExternalName, CallConv, StackSlotData,
-                   StackSlotKind, JumpTable, JumpTableData, Signature, AbiParam,
+use std::result;
+use cretonne::ir::{Function, Ebb, Inst, Opcode, Value, Type, ExternalName, CallConv,
+                   StackSlotData, StackSlotKind, JumpTable, JumpTableData, Signature, AbiParam,
                    ArgumentExtension, ExtFuncData, SigRef, FuncRef, StackSlot, ValueLoc,
                    ArgumentLoc, MemFlags, GlobalVar, GlobalVarData, Heap, HeapData, HeapStyle,
                    HeapBase};
@@ -17,13 +19,19 @@ use cretonne::isa::{self, TargetIsa, Encoding, RegUnit};
 use cretonne::{settings, timing};
 use cretonne::entity::EntityRef;
 use cretonne::packed_option::ReservedValue;
-use testfile::{TestFile, Details, Comment};
+use testfile::{TestFile, Details, Comment, Annotations};
 use error::{Location, Error, Result};
 use lexer::{self, Lexer, Token};
 use testcommand::TestCommand;
 use isaspec;
 use sourcemap::SourceMap;
 
+/// The largest entity number accepted in a function, like the 1000000 in `v1000000`.
+///
+/// The parser allocates the entity tables up to the highest number used, so this keeps a typo or
+/// a fuzzed input from exhausting memory.
+const MAX_ENTITY_NUMBER: usize = 1_000_000;
+
 /// Parse the entire `text` into a list of functions.
 ///
 /// Any test commands or ISA declarations are ignored.
@@ -38,8 +46,67 @@ pub fn parse_functions(text: &str) -> Result<Vec<Function>> {
 ///
 /// The returned `TestFile` contains direct references to substrings of `text`.
 pub fn parse_test<'a>(text: &'a str) -> Result<TestFile<'a>> {
+    let _tt = timing::parse_text();
+    parse_test_file(&mut Parser::new(text))
+}
+
+/// Parse the entire `text` as a test case file, reporting all the errors in it.
+///
+/// Where `parse_test` stops at the first error, this function recovers from syntax errors by
+/// skipping to the next instruction or function, so a single run finds all the errors in a file.
+/// The errors are returned in the order they were found.
+pub fn parse_test_with_recovery<'a>(text: &'a str) -> result::Result<TestFile<'a>, Vec<Error>> {
+    let _tt = timing::parse_text();
+    let mut parser = Parser::new(text);
+    parser.recovering = true;
+    let file = parse_test_file(&mut parser);
+    let mut errors = parser.errors;
+    match file {
+        Ok(file) => {
+            if errors.is_empty() {
+                return Ok(file);
+            }
+        }
+        Err(err) => errors.push(err),
+    }
+    Err(errors)
+}
+
+/// Parse a single instruction from `text` and append it to `ebb` in `func`.
+///
+/// The instruction can refer to any entity already in `func` by its number, like `v3`, `ebb1`, or
+/// `ss0`. The result values are created with the numbers given in `text`, so they must not exist
+/// in `func` yet. Encodings and register locations are only accepted when `unique_isa` is given.
+///
+/// Returns the new instruction. The instruction is parsed into a copy of `func`, so `func` is left
+/// unchanged if there is an error.
+pub fn parse_instruction(
+    text: &str,
+    func: &mut Function,
+    ebb: Ebb,
+    unique_isa: Option<&TargetIsa>,
+) -> Result<Inst> {
     let _tt = timing::parse_text();
     let mut parser = Parser::new(text);
+    if !func.layout.is_ebb_inserted(ebb) {
+        return err!(parser.loc, "{} is not in the function layout", ebb);
+    }
+
+    let mut ctx = Context::new(func.clone(), unique_isa);
+    ctx.define_existing_entities()?;
+    let num_insts = ctx.function.dfg.num_insts();
+    parser.parse_instruction_line(&mut ctx, ebb)?;
+    if ctx.function.dfg.num_insts() == num_insts {
+        return err!(parser.loc, "expected an instruction, not a value alias");
+    }
+    if parser.token().is_some() {
+        return err!(parser.loc, "expected end of instruction");
+    }
+    *func = ctx.function;
+    Ok(Inst::new(num_insts))
+}
+
+fn parse_test_file<'a>(parser: &mut Parser<'a>) -> Result<TestFile<'a>> {
     // Gather the preamble comments.
     parser.start_gathering_comments();
 
@@ -60,6 +127,65 @@ pub fn parse_test<'a>(text: &'a str) -> Result<TestFile<'a>> {
     })
 }
 
+// Attach the comments of a function to the entities they describe.
+//
+// A comment on the line defining an entity is its trailing comment, and a comment on its own line
+// leads the next entity defined in the function. `function_line` and `end_line` are the lines of
+// the `function` keyword and the closing brace.
+fn annotate_comments<'a>(
+    comments: &[Comment<'a>],
+    locations: &[Location],
+    map: &SourceMap,
+    function_line: usize,
+    end_line: usize,
+) -> Annotations<'a> {
+    // The entities starting a line, in source order. Values are defined on instruction and EBB
+    // header lines, so they can't own comments.
+    let mut entities: Vec<(Location, AnyEntity)> = map.entity_locations()
+        .filter(|&(entity, _)| match *entity {
+            AnyEntity::Value(_) => false,
+            _ => true,
+        })
+        .map(|(&entity, &loc)| (loc, entity))
+        .collect();
+    entities.sort_by_key(|&(loc, _)| (loc.line_number, loc.column));
+
+    let mut annotations = Annotations::default();
+    for (comment, loc) in comments.iter().zip(locations) {
+        let line = loc.line_number;
+        if line == function_line {
+            annotations.entity(AnyEntity::Function).trailing = Some(comment.text);
+        } else if line >= end_line {
+            annotations.after.push(comment.text);
+        } else if let Some(&(def, entity)) = entities.iter().find(
+            |&&(def, _)| def.line_number >= line,
+        )
+        {
+            let annotation = annotations.entity(entity);
+            if def.line_number == line {
+                annotation.trailing = Some(comment.text);
+            } else {
+                annotation.leading.push(comment.text);
+            }
+        } else {
+            annotations.body_end.push(comment.text);
+        }
+    }
+    annotations
+}
+
+// Convert a lexer error into a parse error.
+fn lex_error(err: lexer::Error, location: Location) -> Error {
+    match err {
+        lexer::Error::InvalidChar => {
+            Error {
+                location,
+                message: "invalid character".to_string(),
+            }
+        }
+    }
+}
+
 pub struct Parser<'a> {
     lex: Lexer<'a>,
 
@@ -74,11 +200,20 @@ pub struct Parser<'a> {
     // Are we gathering any comments that we encounter?
     gathering_comments: bool,
 
-    // The gathered comments; claim them with `claim_gathered_comments`.
-    gathered_comments: Vec<&'a str>,
+    // The gathered comments and their locations; claim them with `claim_gathered_comments`.
+    gathered_comments: Vec<(&'a str, Location)>,
+
+    // Are syntax errors recorded in `errors` and skipped instead of aborting the parse?
+    recovering: bool,
+
+    // The errors recovered from so far.
+    errors: Vec<Error>,
 
     // Comments collected so far.
     comments: Vec<Comment<'a>>,
+
+    // Locations of the comments in `comments`.
+    comment_locations: Vec<Location>,
 }
 
 // Context for resolving references when parsing a single function.
@@ -102,6 +237,37 @@ impl<'a> Context<'a> {
         }
     }
 
+    // Define all the entities in the function at the default location, so references to them
+    // resolve when parsing a single instruction.
+    fn define_existing_entities(&mut self) -> Result<()> {
+        let loc = Location::default();
+        for v in 0..self.function.dfg.num_values() {
+            self.map.def_value(Value::new(v), &loc)?;
+        }
+        for ebb in 0..self.function.dfg.num_ebbs() {
+            self.map.def_ebb(Ebb::new(ebb), &loc)?;
+        }
+        for ss in self.function.stack_slots.keys() {
+            self.map.def_ss(ss, &loc)?;
+        }
+        for gv in self.function.global_vars.keys() {
+            self.map.def_gv(gv, &loc)?;
+        }
+        for heap in self.function.heaps.keys() {
+            self.map.def_heap(heap, &loc)?;
+        }
+        for sig in self.function.dfg.signatures.keys() {
+            self.map.def_sig(sig, &loc)?;
+        }
+        for fn_ in self.function.dfg.ext_funcs.keys() {
+            self.map.def_fn(fn_, &loc)?;
+        }
+        for jt in self.function.jump_tables.keys() {
+            self.map.def_jt(jt, &loc)?;
+        }
+        Ok(())
+    }
+
     // Get the index of a recipe name if it exists.
     fn find_recipe_index(&self, recipe_name: &str) -> Option<u16> {
         if let Some(unique_isa) = self.unique_isa {
@@ -116,8 +282,18 @@ impl<'a> Context<'a> {
         }
     }
 
+    // Reject entity numbers so large that allocating the tables up to them would exhaust memory.
+    fn check_number<E: EntityRef + fmt::Display>(&self, entity: E, loc: &Location) -> Result<()> {
+        if entity.index() > MAX_ENTITY_NUMBER {
+            err!(loc, "{} is numbered too high, the limit is {}", entity, MAX_ENTITY_NUMBER)
+        } else {
+            Ok(())
+        }
+    }
+
     // Allocate a new stack slot.
     fn add_ss(&mut self, ss: StackSlot, data: StackSlotData, loc: &Location) -> Result<()> {
+        self.check_number(ss, loc)?;
         while self.function.stack_slots.next_key().index() <= ss.index() {
             self.function.create_stack_slot(
                 StackSlotData::new(StackSlotKind::SpillSlot, 0),
@@ -138,6 +314,7 @@ impl<'a> Context<'a> {
 
     // Allocate a global variable slot.
     fn add_gv(&mut self, gv: GlobalVar, data: GlobalVarData, loc: &Location) -> Result<()> {
+        self.check_number(gv, loc)?;
         while self.function.global_vars.next_key().index() <= gv.index() {
             self.function.create_global_var(GlobalVarData::Sym {
                 name: ExternalName::testcase(""),
@@ -158,6 +335,7 @@ impl<'a> Context<'a> {
 
     // Allocate a heap slot.
     fn add_heap(&mut self, heap: Heap, data: HeapData, loc: &Location) -> Result<()> {
+        self.check_number(heap, loc)?;
         while self.function.heaps.next_key().index() <= heap.index() {
             self.function.create_heap(HeapData {
                 base: HeapBase::ReservedReg,
@@ -181,6 +359,7 @@ impl<'a> Context<'a> {
 
     // Allocate a new signature.
     fn add_sig(&mut self, sig: SigRef, data: Signature, loc: &Location) -> Result<()> {
+        self.check_number(sig, loc)?;
         while self.function.dfg.signatures.next_key().index() <= sig.index() {
             self.function.import_signature(
                 Signature::new(CallConv::Native),
@@ -201,6 +380,7 @@ impl<'a> Context<'a> {
 
     // Allocate a new external function.
     fn add_fn(&mut self, fn_: FuncRef, data: ExtFuncData, loc: &Location) -> Result<()> {
+        self.check_number(fn_, loc)?;
         while self.function.dfg.ext_funcs.next_key().index() <= fn_.index() {
             self.function.import_function(ExtFuncData {
                 name: ExternalName::testcase(""),
@@ -222,6 +402,7 @@ impl<'a> Context<'a> {
 
     // Allocate a new jump table.
     fn add_jt(&mut self, jt: JumpTable, data: JumpTableData, loc: &Location) -> Result<()> {
+        self.check_number(jt, loc)?;
         while self.function.jump_tables.next_key().index() <= jt.index() {
             self.function.create_jump_table(JumpTableData::new());
         }
@@ -240,11 +421,15 @@ impl<'a> Context<'a> {
 
     // Allocate a new EBB.
     fn add_ebb(&mut self, ebb: Ebb, loc: &Location) -> Result<Ebb> {
+        self.check_number(ebb, loc)?;
+        // Define the EBB first so a duplicate is rejected before it is appended to the layout
+        // a second time.
+        self.map.def_ebb(ebb, loc)?;
         while self.function.dfg.num_ebbs() <= ebb.index() {
             self.function.dfg.make_ebb();
         }
         self.function.layout.append_ebb(ebb);
-        self.map.def_ebb(ebb, loc).and(Ok(ebb))
+        Ok(ebb)
     }
 }
 
@@ -255,13 +440,28 @@ impl<'a> Parser<'a> {
             lex: Lexer::new(text),
             lex_error: None,
             lookahead: None,
-            loc: Location { line_number: 0 },
+            loc: Location::default(),
             gathering_comments: false,
             gathered_comments: Vec::new(),
+            recovering: false,
+            errors: Vec::new(),
             comments: Vec::new(),
+            comment_locations: Vec::new(),
         }
     }
 
+    // Record `err` and prepare to continue parsing after it, if we are recovering from errors.
+    // Otherwise, return it.
+    fn recover(&mut self, err: Error) -> Result<()> {
+        if !self.recovering {
+            return Err(err);
+        }
+        self.errors.push(err);
+        self.gathering_comments = false;
+        self.gathered_comments.clear();
+        Ok(())
+    }
+
     // Consume the current lookahead token and return it.
     fn consume(&mut self) -> Token<'a> {
         self.lookahead.take().expect("No token to consume")
@@ -283,7 +483,7 @@ impl<'a> Parser<'a> {
                     match token {
                         Token::Comment(text) => {
                             if self.gathering_comments {
-                                self.gathered_comments.push(text);
+                                self.gathered_comments.push((text, location));
                             }
                         }
                         _ => self.lookahead = Some(token),
@@ -291,8 +491,13 @@ impl<'a> Parser<'a> {
                     self.loc = location;
                 }
                 Some(Err(lexer::LocatedError { error, location })) => {
-                    self.lex_error = Some(error);
                     self.loc = location;
+                    if self.recovering {
+                        // Report the error and skip the invalid character.
+                        self.errors.push(lex_error(error, location));
+                        continue;
+                    }
+                    self.lex_error = Some(error);
                     break;
                 }
                 None => break,
@@ -313,17 +518,17 @@ impl<'a> Parser<'a> {
     fn claim_gathered_comments<E: Into<AnyEntity>>(&mut self, entity: E) {
         debug_assert!(self.gathering_comments);
         let entity = entity.into();
-        self.comments.extend(
-            self.gathered_comments.drain(..).map(|text| {
-                Comment { entity, text }
-            }),
-        );
+        for (text, location) in self.gathered_comments.drain(..) {
+            self.comments.push(Comment { entity, text });
+            self.comment_locations.push(location);
+        }
         self.gathering_comments = false;
     }
 
     // Get the comments collected so far, clearing out the internal list.
     fn take_comments(&mut self) -> Vec<Comment<'a>> {
         debug_assert!(!self.gathering_comments);
+        self.comment_locations.clear();
         mem::replace(&mut self.comments, Vec::new())
     }
 
@@ -741,12 +946,18 @@ impl<'a> Parser<'a> {
     ) -> Result<Vec<(Function, Details<'a>)>> {
         let mut list = Vec::new();
         while self.token().is_some() {
-            list.push(self.parse_function(unique_isa)?);
+            match self.parse_function(unique_isa) {
+                Ok(func) => list.push(func),
+                Err(err) => {
+                    self.recover(err)?;
+                    self.comments.clear();
+                    self.comment_locations.clear();
+                    self.skip_to_next_function();
+                }
+            }
         }
         if let Some(err) = self.lex_error {
-            return match err {
-                lexer::Error::InvalidChar => err!(self.loc, "invalid character"),
-            };
+            return Err(lex_error(err, self.loc));
         }
         Ok(list)
     }
@@ -786,15 +997,24 @@ impl<'a> Parser<'a> {
             Token::RBrace,
             "expected '}' after function body",
         )?;
+        let end_line = self.loc.line_number;
 
         // Collect any comments following the end of the function, then stop gathering comments.
         self.start_gathering_comments();
         self.token();
         self.claim_gathered_comments(AnyEntity::Function);
 
+        let annotations = annotate_comments(
+            &self.comments,
+            &self.comment_locations,
+            &ctx.map,
+            location.line_number,
+            end_line,
+        );
         let details = Details {
             location,
             comments: self.take_comments(),
+            annotations,
             map: ctx.map,
         };
 
@@ -1005,34 +1225,37 @@ impl<'a> Parser<'a> {
                 }
                 Some(Token::GlobalVar(..)) => {
                     self.start_gathering_comments();
+                    let loc = self.loc;
                     self.parse_global_var_decl().and_then(|(gv, dat)| {
-                        ctx.add_gv(gv, dat, &self.loc)
+                        ctx.add_gv(gv, dat, &loc)
                     })
                 }
                 Some(Token::Heap(..)) => {
                     self.start_gathering_comments();
+                    let loc = self.loc;
                     self.parse_heap_decl().and_then(|(heap, dat)| {
-                        ctx.add_heap(heap, dat, &self.loc)
+                        ctx.add_heap(heap, dat, &loc)
                     })
                 }
                 Some(Token::SigRef(..)) => {
                     self.start_gathering_comments();
+                    let loc = self.loc;
                     self.parse_signature_decl(ctx.unique_isa).and_then(
-                        |(sig, dat)| {
-                            ctx.add_sig(sig, dat, &self.loc)
-                        },
+                        |(sig, dat)| ctx.add_sig(sig, dat, &loc),
                     )
                 }
                 Some(Token::FuncRef(..)) => {
                     self.start_gathering_comments();
+                    let loc = self.loc;
                     self.parse_function_decl(ctx).and_then(|(fn_, dat)| {
-                        ctx.add_fn(fn_, dat, &self.loc)
+                        ctx.add_fn(fn_, dat, &loc)
                     })
                 }
                 Some(Token::JumpTable(..)) => {
                     self.start_gathering_comments();
+                    let loc = self.loc;
                     self.parse_jump_table_decl().and_then(|(jt, dat)| {
-                        ctx.add_jt(jt, dat, &self.loc)
+                        ctx.add_jt(jt, dat, &loc)
                     })
                 }
                 // More to come..
@@ -1057,6 +1280,7 @@ impl<'a> Parser<'a> {
         let kind = self.match_enum("expected stack slot kind")?;
 
         // stack-slot-decl ::= StackSlot(ss) "=" stack-slot-kind * Bytes {"," stack-slot-flag}
+        // stack-slot-flag ::= "offset" Imm32 | "align" "(" Uimm32 ")"
         let bytes: i64 = self.match_imm64("expected byte-size in stack_slot decl")?
             .into();
         if bytes < 0 {
@@ -1071,6 +1295,15 @@ impl<'a> Parser<'a> {
         while self.optional(Token::Comma) {
             match self.match_any_identifier("expected stack slot flags")? {
                 "offset" => data.offset = Some(self.match_imm32("expected byte offset")?),
+                "align" => {
+                    self.match_token(Token::LPar, "expected '(' after align")?;
+                    let align: u32 = self.match_uimm32("expected alignment in bytes")?.into();
+                    if !align.is_power_of_two() {
+                        return err!(self.loc, "stack slot alignment must be a power of two");
+                    }
+                    self.match_token(Token::RPar, "expected ')' after alignment")?;
+                    data.align = Some(align);
+                }
                 other => return err!(self.loc, "Unknown stack slot flag '{}'", other),
             }
         }
@@ -1282,7 +1515,10 @@ impl<'a> Parser<'a> {
 
     // Parse a jump table decl.
     //
-    // jump-table-decl ::= * JumpTable(jt) "=" "jump_table" jt-entry {"," jt-entry}
+    // jump-table-decl ::= * JumpTable(jt) "=" "jump_table" [jt-entry {"," jt-entry}]
+    //
+    // The entries must be on the same line as the `jump_table` keyword, so an empty jump table
+    // can't be confused with a following EBB header.
     fn parse_jump_table_decl(&mut self) -> Result<(JumpTable, JumpTableData)> {
         let jt = self.match_jt()?;
         self.match_token(
@@ -1290,24 +1526,33 @@ impl<'a> Parser<'a> {
             "expected '=' in jump_table decl",
         )?;
         self.match_identifier("jump_table", "expected 'jump_table'")?;
+        let line_number = self.loc.line_number;
 
         let mut data = JumpTableData::new();
 
-        // jump-table-decl ::= JumpTable(jt) "=" "jump_table" * jt-entry {"," jt-entry}
-        for idx in 0_usize.. {
-            if let Some(dest) = self.parse_jump_table_entry()? {
-                data.set_entry(idx, dest);
-            }
-            if !self.optional(Token::Comma) {
-                // Collect any trailing comments.
-                self.token();
-                self.claim_gathered_comments(jt);
-
-                return Ok((jt, data));
+        // jump-table-decl ::= JumpTable(jt) "=" "jump_table" * [jt-entry {"," jt-entry}]
+        let empty = match self.token() {
+            Some(Token::Integer(_)) |
+            Some(Token::Ebb(_)) => self.loc.line_number != line_number,
+            _ => true,
+        };
+        if !empty {
+            loop {
+                match self.parse_jump_table_entry()? {
+                    Some(dest) => data.push_entry(dest),
+                    None => data.push_hole(),
+                }
+                if !self.optional(Token::Comma) {
+                    break;
+                }
             }
         }
 
-        err!(self.loc, "jump_table too long")
+        // Collect any trailing comments.
+        self.token();
+        self.claim_gathered_comments(jt);
+
+        Ok((jt, data))
     }
 
     // jt-entry ::= * Ebb(dest) | "0"
@@ -1374,52 +1619,76 @@ impl<'a> Parser<'a> {
             _ => false,
         }
         {
-            let srcloc = self.optional_srcloc()?;
-            let (encoding, result_locations) = self.parse_instruction_encoding(ctx)?;
+            // Instructions don't span lines, so we can recover at the next line.
+            let line = self.loc.line_number;
+            if let Err(err) = self.parse_instruction_line(ctx, ebb) {
+                self.recover(err)?;
+                self.skip_lines(line);
+            }
+        }
 
-            // We need to parse instruction results here because they are shared
-            // between the parsing of value aliases and the parsing of instructions.
-            //
-            // inst-results ::= Value(v) { "," Value(v) }
-            let results = self.parse_inst_results()?;
+        Ok(())
+    }
 
-            for result in &results {
-                while ctx.function.dfg.num_values() <= result.index() {
-                    ctx.function.dfg.make_invalid_value_for_parser();
-                }
+    // Parse an instruction or a value alias, add contents to `ctx`.
+    //
+    // instruction-line ::= * [srcloc] [encoding] inst-results "->" Value(v)
+    // instruction-line ::= * [srcloc] [encoding] [inst-results "="] Opcode(opc) ...
+    //
+    fn parse_instruction_line(&mut self, ctx: &mut Context, ebb: Ebb) -> Result<()> {
+        let srcloc = self.optional_srcloc()?;
+        let (encoding, result_locations) = self.parse_instruction_encoding(ctx)?;
+
+        // We need to parse instruction results here because they are shared
+        // between the parsing of value aliases and the parsing of instructions.
+        //
+        // inst-results ::= Value(v) { "," Value(v) }
+        let results = self.parse_inst_results()?;
+
+        // Define the result values at their names.
+        for &(result, ref loc) in &results {
+            ctx.check_number(result, loc)?;
+            while ctx.function.dfg.num_values() <= result.index() {
+                ctx.function.dfg.make_invalid_value_for_parser();
             }
+            ctx.map.def_value(result, loc)?;
+        }
+        let results: Vec<Value> = results.into_iter().map(|(result, _)| result).collect();
 
-            match self.token() {
-                Some(Token::Arrow) => {
-                    self.consume();
-                    self.parse_value_alias(results, ctx)?;
-                }
-                Some(Token::Equal) => {
-                    self.consume();
-                    self.parse_instruction(
-                        results,
-                        srcloc,
-                        encoding,
-                        result_locations,
-                        ctx,
-                        ebb,
-                    )?;
-                }
-                _ if !results.is_empty() => return err!(self.loc, "expected -> or ="),
-                _ => {
-                    self.parse_instruction(
-                        results,
-                        srcloc,
-                        encoding,
-                        result_locations,
-                        ctx,
-                        ebb,
-                    )?
-                }
+        match self.token() {
+            Some(Token::Arrow) => {
+                self.consume();
+                self.parse_value_alias(results, ctx)
+            }
+            Some(Token::Equal) => {
+                self.consume();
+                self.parse_instruction(results, srcloc, encoding, result_locations, ctx, ebb)
             }
+            _ if !results.is_empty() => err!(self.loc, "expected -> or ="),
+            _ => self.parse_instruction(results, srcloc, encoding, result_locations, ctx, ebb),
         }
+    }
 
-        Ok(())
+    // Skip the remaining tokens up to and including line `line`, to recover from an error in an
+    // instruction.
+    fn skip_lines(&mut self, line: usize) {
+        while self.token().is_some() && self.loc.line_number <= line {
+            self.consume();
+        }
+    }
+
+    // Skip the remaining tokens of a function, to recover from an error in it. Only a `function`
+    // keyword following a `}` starts a new function, since the preamble of a function can also
+    // contain the keyword.
+    fn skip_to_next_function(&mut self) {
+        let mut after_rbrace = false;
+        while let Some(tok) = self.token() {
+            if after_rbrace && tok == Token::Identifier("function") {
+                break;
+            }
+            after_rbrace = tok == Token::RBrace;
+            self.consume();
+        }
     }
 
     // Parse parenthesized list of EBB parameters. Returns a vector of (u32, Type) pairs with the
@@ -1467,14 +1736,15 @@ impl<'a> Parser<'a> {
         )?;
         // ebb-param ::= Value(v) ":" * Type(t) arg-loc?
 
+        ctx.check_number(v, &v_location)?;
         while ctx.function.dfg.num_values() <= v.index() {
             ctx.function.dfg.make_invalid_value_for_parser();
         }
 
         let t = self.match_type("expected EBB argument type")?;
         // Allocate the EBB argument.
-        ctx.function.dfg.append_ebb_param_for_parser(ebb, t, v);
         ctx.map.def_value(v, &v_location)?;
+        ctx.function.dfg.append_ebb_param_for_parser(ebb, t, v);
 
         // ebb-param ::= Value(v) ":" Type(t) * arg-loc?
         if self.optional(Token::LBracket) {
@@ -1572,11 +1842,11 @@ impl<'a> Parser<'a> {
         Ok((encoding, result_locations))
     }
 
-    // Parse instruction results and return them.
+    // Parse instruction results and return them along with their locations.
     //
     // inst-results ::= Value(v) { "," Value(v) }
     //
-    fn parse_inst_results(&mut self) -> Result<Vec<Value>> {
+    fn parse_inst_results(&mut self) -> Result<Vec<(Value, Location)>> {
         // Result value numbers.
         let mut results = Vec::new();
 
@@ -1585,12 +1855,13 @@ impl<'a> Parser<'a> {
         if let Some(Token::Value(v)) = self.token() {
             self.consume();
 
-            results.push(v);
+            results.push((v, self.loc));
 
             // inst-results ::= Value(v) * { "," Value(v) }
             while self.optional(Token::Comma) {
                 // inst-results ::= Value(v) { "," * Value(v) }
-                results.push(self.match_value("expected result value")?);
+                let v = self.match_value("expected result value")?;
+                results.push((v, self.loc));
             }
         }
 
@@ -1611,7 +1882,6 @@ impl<'a> Parser<'a> {
             dest,
             results[0],
         );
-        ctx.map.def_value(results[0], &self.loc)?;
         Ok(())
     }
 
@@ -1628,11 +1898,6 @@ impl<'a> Parser<'a> {
         ctx: &mut Context,
         ebb: Ebb,
     ) -> Result<()> {
-        // Define the result values.
-        for val in &results {
-            ctx.map.def_value(*val, &self.loc)?;
-        }
-
         // Collect comments for the next instruction.
         self.start_gathering_comments();
 
@@ -1671,7 +1936,23 @@ impl<'a> Parser<'a> {
             &inst_data,
         )?;
         let inst = ctx.function.dfg.make_inst(inst_data);
-        let num_results = ctx.function.dfg.make_inst_results_for_parser(
+
+        // Check the number of results before creating them. Missing results would otherwise be
+        // created with the numbers of values defined further down.
+        let num_results = match ctx.function.dfg.call_signature(inst) {
+            Some(sig) => ctx.function.dfg.signatures[sig].returns.len(),
+            None => opcode.constraints().fixed_results(),
+        };
+        if results.len() != num_results {
+            return err!(
+                self.loc,
+                "instruction produces {} result values, {} given",
+                num_results,
+                results.len()
+            );
+        }
+
+        ctx.function.dfg.make_inst_results_for_parser(
             inst,
             ctrl_typevar,
             &results,
@@ -1689,15 +1970,6 @@ impl<'a> Parser<'a> {
             ctx.function.encodings[inst] = encoding;
         }
 
-        if results.len() != num_results {
-            return err!(
-                self.loc,
-                "instruction produces {} result values, {} given",
-                num_results,
-                results.len()
-            );
-        }
-
         if let Some(ref result_locations) = result_locations {
             if results.len() != result_locations.len() {
                 return err!(
@@ -1754,6 +2026,15 @@ impl<'a> Parser<'a> {
                     let ctrl_src_value = inst_data
                         .typevar_operand(&ctx.function.dfg.value_lists)
                         .expect("Constraints <-> Format inconsistency");
+                    if !ctx.map.contains_value(ctrl_src_value) {
+                        return err!(
+                            self.loc,
+                            "type of {} is not known yet, use a type variable, e.g. '{}.{}'",
+                            ctrl_src_value,
+                            opcode,
+                            constraints.ctrl_typeset().unwrap().example()
+                        );
+                    }
                     ctx.function.dfg.value_type(ctrl_src_value)
                 } else if constraints.is_polymorphic() {
                     // This opcode does not support type inference, so the explicit type
@@ -2426,7 +2707,7 @@ mod tests {
                 .parse_signature(None)
                 .unwrap_err()
                 .to_string(),
-            "1: unknown calling convention: notacc"
+            "1:4: unknown calling convention: notacc"
         );
 
         // `void` is not recognized as a type by the lexer. It should not appear in files.
@@ -2435,21 +2716,21 @@ mod tests {
                 .parse_signature(None)
                 .unwrap_err()
                 .to_string(),
-            "1: expected parameter type"
+            "1:7: expected parameter type"
         );
         assert_eq!(
             Parser::new("i8 -> i8")
                 .parse_signature(None)
                 .unwrap_err()
                 .to_string(),
-            "1: expected function signature: ( args... )"
+            "1:1: expected function signature: ( args... )"
         );
         assert_eq!(
             Parser::new("(i8 -> i8")
                 .parse_signature(None)
                 .unwrap_err()
                 .to_string(),
-            "1: expected ')' after function arguments"
+            "1:5: expected ')' after function arguments"
         );
     }
 
@@ -2459,6 +2740,7 @@ mod tests {
             "function %foo() native {
                                        ss3 = incoming_arg 13
                                        ss1 = spill_slot 1
+                                       ss2 = explicit_slot 12, align(8)
                                      }",
         ).parse_function(None)
             .unwrap();
@@ -2469,7 +2751,8 @@ mod tests {
         assert_eq!(ss1.to_string(), "ss1");
         assert_eq!(func.stack_slots[ss1].kind, StackSlotKind::SpillSlot);
         assert_eq!(func.stack_slots[ss1].size, 1);
-        let _ss2 = iter.next().unwrap();
+        let ss2 = iter.next().unwrap();
+        assert_eq!(func.stack_slots[ss2].align, Some(8));
         let ss3 = iter.next().unwrap();
         assert_eq!(ss3.to_string(), "ss3");
         assert_eq!(func.stack_slots[ss3].kind, StackSlotKind::IncomingArg);
@@ -2486,7 +2769,7 @@ mod tests {
             ).parse_function(None)
                 .unwrap_err()
                 .to_string(),
-            "3: duplicate entity: ss1"
+            "3:37: duplicate entity: ss1"
         );
     }
 
@@ -2551,6 +2834,92 @@ mod tests {
         assert_eq!(comments[7].entity, AnyEntity::Function);
     }
 
+    #[test]
+    fn annotations() {
+        let (_, Details { annotations, .. }) = Parser::new(
+            "function %comment() native { ; decl
+                ; Leading stackslot.
+                ss10 = outgoing_arg 13 ; Trailing stackslot.
+
+                ; Leading block.
+                ; More leading block.
+            ebb0: ; Trailing block.
+                v0 = iconst.i32 1
+                ; Leading trap.
+                trap user42 ; Trailing trap.
+                ; Body end.
+            } ; After.
+            ; More after.",
+        ).parse_function(None)
+            .unwrap();
+
+        let function = &annotations.entities[&AnyEntity::Function];
+        assert!(function.leading.is_empty());
+        assert_eq!(function.trailing, Some("; decl"));
+
+        let ss = &annotations.entities[&StackSlot::with_number(10).unwrap().into()];
+        assert_eq!(ss.leading, ["; Leading stackslot."]);
+        assert_eq!(ss.trailing, Some("; Trailing stackslot."));
+
+        let ebb = &annotations.entities[&Ebb::with_number(0).unwrap().into()];
+        assert_eq!(ebb.leading, ["; Leading block.", "; More leading block."]);
+        assert_eq!(ebb.trailing, Some("; Trailing block."));
+
+        let mut insts = annotations.entities.iter().filter_map(|(&entity, annotation)| {
+            match entity {
+                AnyEntity::Inst(_) => Some(annotation),
+                _ => None,
+            }
+        });
+        let trap = insts.next().unwrap();
+        assert!(insts.next().is_none());
+        assert_eq!(trap.leading, ["; Leading trap."]);
+        assert_eq!(trap.trailing, Some("; Trailing trap."));
+
+        assert_eq!(annotations.body_end, ["; Body end."]);
+        assert_eq!(annotations.after, ["; After.", "; More after."]);
+    }
+
+    #[test]
+    fn write_annotations() {
+        use cretonne::{write_function_annotated, WriteOptions};
+
+        let text = "function %comment() native { ; decl
+; Leading stackslot.
+  ss2 = outgoing_arg 13 ;   Trailing stackslot.
+        ; Leading block.
+ebb0:   ; Trailing block.
+    v0 = iconst.i32 1
+; Leading trap.
+        trap user42 ; Trailing trap.
+; Body end.
+}
+";
+        let (func, mut details) = Parser::new(text).parse_function(None).unwrap();
+        let options = WriteOptions {
+            stable: true,
+            ..WriteOptions::default()
+        };
+        let mut printed = String::new();
+        write_function_annotated(&mut printed, &func, None, &options, &mut details)
+            .unwrap();
+        assert_eq!(
+            printed,
+            "function %comment() native { ; decl
+    ; Leading stackslot.
+    ss2 = outgoing_arg 13 ;   Trailing stackslot.
+
+; Leading block.
+ebb0: ; Trailing block.
+    v0 = iconst.i32 1
+    ; Leading trap.
+    trap user42 ; Trailing trap.
+    ; Body end.
+}
+"
+        );
+    }
+
     #[test]
     fn test_file() {
         let tf = parse_test(
@@ -2610,6 +2979,189 @@ mod tests {
         }
     }
 
+    #[test]
+    fn srclocs() {
+        let text = "function %srclocs(i32) native {
+                                ebb0(v0: i32):
+@0010                               v1 = iadd_imm v0, 1
+                                    v2 = iadd_imm v1, 2
+@abcd                               return
+}
+";
+        let func = Parser::new(text).parse_function(None).unwrap().0;
+        let insts: Vec<_> = func.layout
+            .ebb_insts(func.layout.entry_block().unwrap())
+            .map(|inst| func.srclocs[inst])
+            .collect();
+        assert_eq!(
+            insts,
+            [
+                ir::SourceLoc::new(0x10),
+                ir::SourceLoc::default(),
+                ir::SourceLoc::new(0xabcd),
+            ]
+        );
+        assert_eq!(func.to_string(), text);
+
+        let mut parser = Parser::new(
+            "function %bad() native {
+                                           ebb0:
+                                             @1234567890 return
+                                           }",
+        );
+        assert!(parser.parse_function(None).is_err());
+    }
+
+    #[test]
+    fn error_recovery() {
+        let text = "test verifier
+
+function %a() native {
+    fn0 = function %f() native
+ebb0:
+    v0 = iconst.i32 1
+    v1 = iadd_imm v0, $
+    v2 = bogus v0
+    v3 = iadd_imm v0, 3
+    return
+}
+
+function %b(i32 native {
+ebb0:
+    return
+}
+
+function %c() native {
+ebb0:
+    v0 = iconst.i32 1
+    return
+}
+";
+        assert!(parse_test(text).is_err());
+        let errors = parse_test_with_recovery(text).err().unwrap();
+        let lines: Vec<usize> = errors.iter().map(|e| e.location.line_number).collect();
+        assert_eq!(lines, [7, 8, 8, 13]);
+        assert_eq!(errors[0].message, "invalid character");
+        assert_eq!(errors[2].message, "Unknown opcode: 'bogus'");
+
+        // A valid file parses the same way.
+        let file = parse_test_with_recovery("function %c() native {\nebb0:\n    return\n}")
+            .unwrap();
+        assert_eq!(file.functions.len(), 1);
+    }
+
+    #[test]
+    fn caret_diagnostics() {
+        let text = "function %a() native {\nebb0:\n    v0 = iconst.i32 1\n    v2 = bogus v0\n}";
+        let err = parse_functions(text).unwrap_err();
+        assert_eq!(err.to_string(), "4:10: Unknown opcode: 'bogus'");
+        assert_eq!(
+            err.diagnostic("a.cton", text),
+            "error: Unknown opcode: 'bogus'
+ --> a.cton:4:10
+  |
+4 |     v2 = bogus v0
+  |          ^^^^^
+"
+        );
+
+        // Entity definitions remember their source spans.
+        let text = text.replace("bogus v0", "iadd_imm v0, 1");
+        let (_, details) = Parser::new(&text).parse_function(None).unwrap();
+        let v2 = Value::with_number(2).unwrap();
+        let loc = details.map.location(v2.into()).unwrap();
+        assert_eq!((loc.line_number, loc.column), (4, 5));
+        assert_eq!(&text[loc.span.start..loc.span.end], "v2");
+    }
+
+    #[test]
+    fn stable_round_trip() {
+        let text = "function %f(i32) -> i32 native {
+ebb0(v0: i32):
+    v1 -> v0
+    v3 = iadd v1, v1
+    v10 -> v1
+    v4 = iadd v10, v3
+    jump ebb2(v10)
+
+ebb2(v5: i32):
+    v6 = iadd.i32 v10, v5
+    return v6
+}
+";
+        let (func, _) = Parser::new(text).parse_function(None).unwrap();
+        let printed = func.display_stable(None).to_string();
+        assert_eq!(printed, text);
+
+        let (func2, _) = Parser::new(&printed).parse_function(None).unwrap();
+        assert_eq!(func2.display_stable(None).to_string(), printed);
+        assert_eq!(func2.dfg.num_values(), func.dfg.num_values());
+        assert_eq!(func2.dfg.num_ebbs(), func.dfg.num_ebbs());
+
+        // The default output repeats aliases, so it doesn't read back.
+        assert!(Parser::new(&func.to_string()).parse_function(None).is_err());
+    }
+
+    #[test]
+    fn preamble_round_trip() {
+        use cretonne::ir::{ExternalName, Function, GlobalVarData, HeapBase, HeapData, HeapStyle,
+                           JumpTableData, StackSlotData};
+
+        let mut func = Function::with_name_signature(
+            ExternalName::testcase("f"),
+            Signature::new(CallConv::Native),
+        );
+        let mut slot = StackSlotData::new(StackSlotKind::IncomingArg, 8);
+        slot.offset = Some(-8);
+        func.create_stack_slot(slot);
+        let vmctx = func.create_global_var(GlobalVarData::VmCtx { offset: 16.into() });
+        let base = func.create_global_var(GlobalVarData::Deref {
+            base: vmctx,
+            offset: (-8).into(),
+        });
+        let bound = func.create_global_var(GlobalVarData::Deref {
+            base: vmctx,
+            offset: 0.into(),
+        });
+        func.create_global_var(GlobalVarData::Sym { name: ExternalName::user(1, 2) });
+        func.create_heap(HeapData {
+            base: HeapBase::GlobalVar(base),
+            min_size: 0x1000.into(),
+            guard_size: 0x8000.into(),
+            style: HeapStyle::Dynamic { bound_gv: bound },
+        });
+        func.create_heap(HeapData {
+            base: HeapBase::ReservedReg,
+            min_size: 0.into(),
+            guard_size: 0.into(),
+            style: HeapStyle::Static { bound: 0x1_0000_0000.into() },
+        });
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        func.layout.append_ebb(ebb1);
+        let mut jt = JumpTableData::new();
+        jt.push_hole();
+        jt.push_entry(ebb1);
+        jt.push_hole();
+        func.create_jump_table(jt);
+        func.create_jump_table(JumpTableData::new());
+        let mut jt = JumpTableData::new();
+        jt.push_hole();
+        func.create_jump_table(jt);
+
+        let text = func.display_stable(None).to_string();
+        let (func2, _) = Parser::new(&text).parse_function(None).unwrap();
+        assert_eq!(func2.display_stable(None).to_string(), text);
+        assert_eq!(func2.jump_tables.len(), 3);
+        let lens: Vec<usize> = func2
+            .jump_tables
+            .keys()
+            .map(|jt| func2.jump_tables[jt].len())
+            .collect();
+        assert_eq!(lens, [3, 0, 1]);
+    }
+
     #[test]
     fn user_function_name() {
         // Valid characters in the name:
@@ -2657,4 +3209,106 @@ mod tests {
         );
         assert!(parser.parse_function(None).is_err());
     }
+
+    #[test]
+    fn single_instruction() {
+        let mut func = parse_functions(
+            "function %f(i32) native {
+                 ss0 = explicit_slot 4
+             ebb0(v0: i32):
+                 v1 = iconst.i32 3
+                 return v0
+             ebb1:
+                 trap user0
+             }",
+        ).unwrap()
+            .remove(0);
+        let ebb0 = Ebb::new(0);
+        let ret = func.layout.last_inst(ebb0).unwrap();
+
+        let inst = parse_instruction("v2 = iadd v0, v1", &mut func, ebb0, None).unwrap();
+        assert_eq!(func.dfg.display_inst(inst, None).to_string(), "v2 = iadd.i32 v0, v1");
+        assert_eq!(func.layout.last_inst(ebb0), Some(inst));
+        assert_eq!(func.layout.prev_inst(inst), Some(ret));
+
+        let inst = parse_instruction("stack_store v2, ss0", &mut func, ebb0, None).unwrap();
+        assert_eq!(func.dfg.display_inst(inst, None).to_string(), "stack_store.i32 v2, ss0");
+        let inst = parse_instruction("brz v2, ebb1", &mut func, ebb0, None).unwrap();
+        assert_eq!(func.dfg.display_inst(inst, None).to_string(), "brz.i32 v2, ebb1");
+    }
+
+    #[test]
+    fn single_instruction_errors() {
+        let mut func = parse_functions(
+            "function %f(i32) native {
+             ebb0(v0: i32):
+                 return v0
+             }",
+        ).unwrap()
+            .remove(0);
+        let ebb0 = Ebb::new(0);
+        let before = func.display(None).to_string();
+        let error = |text, func: &mut Function| {
+            parse_instruction(text, func, ebb0, None).unwrap_err().message
+        };
+
+        assert_eq!(error("v0 = iconst.i32 1", &mut func), "duplicate entity: v0");
+        assert_eq!(error("v1 -> v0", &mut func), "expected an instruction, not a value alias");
+        assert_eq!(error("v1 = iconst.i32 1 v2", &mut func), "expected end of instruction");
+        assert_eq!(error("stack_store v0, ss0", &mut func), "undefined stack slot ss0");
+        assert_eq!(
+            parse_instruction("nop", &mut func, Ebb::new(1), None)
+                .unwrap_err()
+                .message,
+            "ebb1 is not in the function layout"
+        );
+        assert_eq!(func.layout.ebb_insts(ebb0).count(), 1);
+        assert_eq!(func.display(None).to_string(), before);
+    }
+
+    #[test]
+    fn malformed_functions() {
+        let error = |text| match parse_test(text) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.to_string(),
+        };
+
+        assert_eq!(error("function %f() {\nebb0:\nebb0:\n"), "3:1: duplicate entity: ebb0");
+        assert_eq!(
+            error("function %f() {\nebb0(v0: i32, v0: i32):\n"),
+            "2:15: duplicate entity: v0"
+        );
+        assert_eq!(
+            error("function %f() {\nebb4000000000:\n"),
+            "2:1: ebb4000000000 is numbered too high, the limit is 1000000"
+        );
+        assert_eq!(
+            error("function %f() {\nebb0:\n    v1000001 = iconst.i32 0\n"),
+            "3:5: v1000001 is numbered too high, the limit is 1000000"
+        );
+        assert_eq!(
+            error("function %f() {\n    ss2000000 = spill_slot 4\n"),
+            "2:5: ss2000000 is numbered too high, the limit is 1000000"
+        );
+        assert_eq!(
+            error("function %f() {\nebb0:\n    v1 = iadd_imm v0, 1\n"),
+            "3:23: type of v0 is not known yet, use a type variable, e.g. 'iadd_imm.i32'"
+        );
+
+        // The missing result of the first `iadd` must not take the number of `v1`.
+        let errors = match parse_test_with_recovery(
+            "function %f() {
+             ebb0:
+                 v0 = iconst.i32 1
+                 iadd v0, v0
+                 v1 = iadd v0, v0
+                 return
+             }",
+        ) {
+            Ok(_) => panic!("expected an error"),
+            Err(errors) => errors,
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "4:27: instruction produces 1 result values, 0 given");
+    }
 }
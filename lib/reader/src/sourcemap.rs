@@ -11,6 +11,7 @@ use cretonne::ir::{StackSlot, GlobalVar, Heap, JumpTable, Ebb, Value, SigRef, Fu
 use error::{Result, Location};
 use lexer::split_entity_name;
 use std::collections::HashMap;
+use std::collections::hash_map;
 
 /// Mapping from entity names to source locations.
 #[derive(Debug)]
@@ -129,6 +130,11 @@ impl SourceMap {
     pub fn location(&self, entity: AnyEntity) -> Option<Location> {
         self.locations.get(&entity).cloned()
     }
+
+    /// Get the locations of all the defined entities, in no particular order.
+    pub fn entity_locations(&self) -> hash_map::Iter<AnyEntity, Location> {
+        self.locations.iter()
+    }
 }
 
 impl SourceMap {
@@ -4,8 +4,11 @@
 //! file-based test case.
 //!
 
+use cretonne::Annotator;
 use cretonne::ir::Function;
 use cretonne::ir::entities::AnyEntity;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
 use testcommand::TestCommand;
 use isaspec::IsaSpec;
 use sourcemap::SourceMap;
@@ -36,6 +39,8 @@ pub struct Details<'a> {
     pub location: Location,
     /// Annotation comments that appeared inside or after the function.
     pub comments: Vec<Comment<'a>>,
+    /// The same comments, attached to the entities they describe.
+    pub annotations: Annotations<'a>,
     /// Mapping of entity numbers to source locations.
     pub map: SourceMap,
 }
@@ -55,3 +60,76 @@ pub struct Comment<'a> {
     /// Text of the comment, including the leading `;`.
     pub text: &'a str,
 }
+
+/// The comments around a function, attached to the entities they describe.
+///
+/// Unlike `Comment`, which always belongs to the preceding entity, this distinguishes the comment
+/// at the end of an entity's line from the comments on their own lines before it. Tools rewriting
+/// a file can use this to keep the comments next to the code they annotate.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Annotations<'a> {
+    /// Comments attached to entities. The trailing comment of `AnyEntity::Function` is on the
+    /// line of the `function` keyword.
+    pub entities: HashMap<AnyEntity, Annotation<'a>>,
+    /// Comments on their own lines after the last entity of the function body.
+    pub body_end: Vec<&'a str>,
+    /// Comments after the closing brace of the function, up to the next function.
+    pub after: Vec<&'a str>,
+}
+
+impl<'a> Annotations<'a> {
+    /// Get the annotation of `entity`, creating an empty one if needed.
+    pub fn entity(&mut self, entity: AnyEntity) -> &mut Annotation<'a> {
+        self.entities.entry(entity).or_insert_with(Annotation::default)
+    }
+}
+
+/// Write the comments back when printing the function with `cretonne::write_function_annotated`.
+///
+/// Comments before EBB headers start in the first column, and the other comments in the function
+/// body are indented by 4 spaces. Trailing comments are separated from the code by one space.
+/// The comments after the function are not written.
+///
+/// The placeholder declarations filling the gaps in the entity numbers of the source text are
+/// hidden, so the function is written like it appeared in the source.
+impl<'a> Annotator for Details<'a> {
+    fn write_leading(&mut self, w: &mut Write, entity: AnyEntity) -> fmt::Result {
+        let indent = match entity {
+            AnyEntity::Function | AnyEntity::Ebb(_) => 0,
+            _ => 4,
+        };
+        if let Some(annotation) = self.annotations.entities.get(&entity) {
+            for text in &annotation.leading {
+                writeln!(w, "{1:0$}{2}", indent, "", text)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_trailing(&mut self, w: &mut Write, entity: AnyEntity) -> fmt::Result {
+        match self.annotations.entities.get(&entity).and_then(|a| a.trailing) {
+            Some(text) => write!(w, " {}", text),
+            None => Ok(()),
+        }
+    }
+
+    fn write_body_end(&mut self, w: &mut Write) -> fmt::Result {
+        for text in &self.annotations.body_end {
+            writeln!(w, "    {}", text)?;
+        }
+        Ok(())
+    }
+
+    fn show_declaration(&mut self, entity: AnyEntity) -> bool {
+        self.map.location(entity).is_some()
+    }
+}
+
+/// The comments attached to an entity.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Annotation<'a> {
+    /// Comments on their own lines before the entity, in order.
+    pub leading: Vec<&'a str>,
+    /// Comment at the end of the line defining the entity.
+    pub trailing: Option<&'a str>,
+}
@@ -0,0 +1,245 @@
+//! A `cton_module::Backend` that compiles into executable memory.
+
+use cretonne::Context;
+use cretonne::binemit::CodeOffset;
+use cretonne::ir::ExternalName;
+use cretonne::isa::TargetIsa;
+use cton_module::{Backend, DataContext, DataId, FuncId, Linkage, ModuleError, ModuleNamespace,
+                  ModuleResult};
+use jit::{SimpleJIT, JitError, lookup_host_symbol_name};
+use std::collections::HashMap;
+
+/// A `Backend` that places the definitions of a `Module` in a `SimpleJIT`.
+///
+/// Imported functions and data objects are resolved by their declared names: first among the
+/// symbols registered with `symbol()`, then in the host process. All data objects are writable.
+pub struct SimpleJITBackend {
+    jit: SimpleJIT,
+    symbols: HashMap<String, *const u8>,
+    names: HashMap<String, ExternalName>,
+}
+
+impl SimpleJITBackend {
+    /// Create a backend for the host machine with default settings.
+    pub fn new() -> Result<Self, JitError> {
+        Ok(Self::with_jit(SimpleJIT::new()?))
+    }
+
+    /// Create a backend that places definitions in `jit`.
+    pub fn with_jit(jit: SimpleJIT) -> Self {
+        Self {
+            jit,
+            symbols: HashMap::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Register the address of a host symbol that can be imported by name.
+    pub fn symbol(&mut self, name: &str, ptr: *const u8) {
+        self.symbols.insert(name.to_string(), ptr);
+    }
+}
+
+impl From<JitError> for ModuleError {
+    fn from(e: JitError) -> Self {
+        ModuleError::Backend(e.to_string())
+    }
+}
+
+impl Backend for SimpleJITBackend {
+    type Product = SimpleJITProduct;
+
+    fn isa(&self) -> &TargetIsa {
+        self.jit.isa()
+    }
+
+    fn define_function(
+        &mut self,
+        id: FuncId,
+        name: &str,
+        _linkage: Linkage,
+        ctx: &Context,
+        code_size: CodeOffset,
+        _namespace: &ModuleNamespace,
+    ) -> ModuleResult<()> {
+        let ext_name = ExternalName::from(id);
+        self.jit.define_function(ext_name.clone(), ctx, code_size)?;
+        self.names.insert(name.to_string(), ext_name);
+        Ok(())
+    }
+
+    fn define_data(
+        &mut self,
+        id: DataId,
+        name: &str,
+        _linkage: Linkage,
+        _writable: bool,
+        data: &DataContext,
+        _namespace: &ModuleNamespace,
+    ) -> ModuleResult<()> {
+        let ext_name = ExternalName::from(id);
+        self.jit.define_data(ext_name.clone(), &data.contents())?;
+        for reloc in data.relocs() {
+            self.jit.write_pointer(
+                &ext_name,
+                reloc.offset,
+                reloc.name.clone(),
+                reloc.addend,
+            )?;
+        }
+        self.names.insert(name.to_string(), ext_name);
+        Ok(())
+    }
+
+    fn finish(mut self, namespace: &ModuleNamespace) -> ModuleResult<SimpleJITProduct> {
+        for (ext_name, name) in namespace.imports() {
+            let ptr = match self.symbols.get(name) {
+                Some(&ptr) => Some(ptr),
+                None => lookup_host_symbol_name(name),
+            };
+            // Unresolved imports are reported by `finalize()` if they are actually used.
+            if let Some(ptr) = ptr {
+                self.jit.symbol(ext_name, ptr);
+            }
+        }
+        self.jit.finalize()?;
+        Ok(SimpleJITProduct {
+            jit: self.jit,
+            names: self.names,
+        })
+    }
+}
+
+/// The functions and data objects of a finished `Module`, looked up by their declared names.
+///
+/// The addresses remain valid for the lifetime of the product.
+pub struct SimpleJITProduct {
+    jit: SimpleJIT,
+    names: HashMap<String, ExternalName>,
+}
+
+impl SimpleJITProduct {
+    /// Get the address of the function `name`.
+    pub fn get_function(&self, name: &str) -> Option<*const u8> {
+        self.names.get(name).and_then(
+            |ext_name| self.jit.get_function(ext_name),
+        )
+    }
+
+    /// Get the address and size of the data object `name`.
+    pub fn get_data(&self, name: &str) -> Option<(*mut u8, usize)> {
+        self.names.get(name).and_then(
+            |ext_name| self.jit.get_data(ext_name),
+        )
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use super::SimpleJITBackend;
+    use cretonne::Context;
+    use cretonne::cursor::{Cursor, FuncCursor};
+    use cretonne::ir::{AbiParam, InstBuilder, types};
+    use cton_module::{DataContext, Linkage, Module};
+    use std::mem;
+
+    extern "C" fn answer() -> i64 {
+        42
+    }
+
+    #[test]
+    fn module() {
+        let mut backend = SimpleJITBackend::new().unwrap();
+        backend.symbol("answer", answer as *const u8);
+        let mut module = Module::new(backend);
+
+        let mut sig = module.make_signature();
+        sig.returns.push(AbiParam::new(types::I64));
+        let answer_id = module.declare_function("answer", Linkage::Import, &sig).unwrap();
+        let wrapper = module.declare_function("wrapper", Linkage::Export, &sig).unwrap();
+        let table = module.declare_data("table", Linkage::Local, true).unwrap();
+
+        let mut ctx = Context::new();
+        ctx.func.signature = sig;
+        let fref = module.declare_func_in_func(answer_id, &mut ctx.func);
+        {
+            let ebb = ctx.func.dfg.make_ebb();
+            ctx.func.layout.append_ebb(ebb);
+            let mut pos = FuncCursor::new(&mut ctx.func).at_bottom(ebb);
+            let call = pos.ins().call(fref, &[]);
+            let result = pos.func.dfg.first_result(call);
+            pos.ins().return_(&[result]);
+        }
+        module.define_function(wrapper, &mut ctx).unwrap();
+
+        let mut data = DataContext::new();
+        data.define_zeroinit(8);
+        data.write_pointer(0, Module::<SimpleJITBackend>::function_name(wrapper), 0);
+        module.define_data(table, &data).unwrap();
+
+        let product = module.finish().unwrap();
+        let code = product.get_function("wrapper").unwrap();
+        let (table, size) = product.get_data("table").unwrap();
+        assert_eq!(size, 8);
+        assert_eq!(unsafe { *(table as *const *const u8) }, code);
+
+        let f: extern "C" fn() -> i64 = unsafe { mem::transmute(code) };
+        assert_eq!(f(), 42);
+    }
+
+    #[test]
+    fn hot_swap() {
+        let mut backend = SimpleJITBackend::new().unwrap();
+        backend.symbol("answer", answer as *const u8);
+        let mut module = Module::new(backend);
+        module.enable_hot_swap("functions").unwrap();
+
+        let mut sig = module.make_signature();
+        sig.returns.push(AbiParam::new(types::I64));
+        let answer_id = module.declare_function("answer", Linkage::Import, &sig).unwrap();
+        let callee = module.declare_function("callee", Linkage::Export, &sig).unwrap();
+        let caller = module.declare_function("caller", Linkage::Export, &sig).unwrap();
+
+        let mut ctx = Context::new();
+        ctx.func.signature = sig.clone();
+        {
+            let ebb = ctx.func.dfg.make_ebb();
+            ctx.func.layout.append_ebb(ebb);
+            let mut pos = FuncCursor::new(&mut ctx.func).at_bottom(ebb);
+            let one = pos.ins().iconst(types::I64, 1);
+            pos.ins().return_(&[one]);
+        }
+        module.define_function(callee, &mut ctx).unwrap();
+
+        ctx.clear();
+        ctx.func.signature = sig;
+        let fref = module.declare_func_in_func(callee, &mut ctx.func);
+        {
+            let ebb = ctx.func.dfg.make_ebb();
+            ctx.func.layout.append_ebb(ebb);
+            let mut pos = FuncCursor::new(&mut ctx.func).at_bottom(ebb);
+            let call = pos.ins().call(fref, &[]);
+            let result = pos.func.dfg.first_result(call);
+            pos.ins().return_(&[result]);
+        }
+        module.define_function(caller, &mut ctx).unwrap();
+
+        let product = module.finish().unwrap();
+        let f: extern "C" fn() -> i64 =
+            unsafe { mem::transmute(product.get_function("caller").unwrap()) };
+        assert_eq!(f(), 1);
+
+        // Replace `callee` with `answer` without touching the code of `caller`.
+        let (table, size) = product.get_data("functions").unwrap();
+        assert_eq!(size, 24);
+        let slot = |id| unsafe {
+            table.offset(Module::<SimpleJITBackend>::function_table_offset(id) as isize) as
+                *mut *const u8
+        };
+        unsafe {
+            assert_eq!(*slot(answer_id), answer as *const u8);
+            *slot(callee) = *slot(answer_id);
+        }
+        assert_eq!(f(), 42);
+    }
+}
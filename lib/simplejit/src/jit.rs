@@ -0,0 +1,713 @@
+//! The JIT compiler.
+
+use cretonne::Context;
+use cretonne::binemit::{Addend, CodeOffset, Reloc, RelocSink};
+use cretonne::ir::{ExternalName, JumpTable};
+use cretonne::isa::TargetIsa;
+use cretonne::result::CodegenError;
+use cretonne::settings;
+use cton_module::{libcall_name, reloc_addend};
+use cton_native;
+use libc;
+use memory::Memory;
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::ffi::CString;
+use std::fmt;
+use std::ptr::{self, write_unaligned};
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An error encountered while JIT-compiling.
+#[derive(Debug, PartialEq, Eq)]
+pub enum JitError {
+    /// The host machine isn't supported by Cretonne.
+    UnsupportedHost(String),
+
+    /// Compiling a function failed.
+    Compile(CodegenError),
+
+    /// A function or data object was defined twice.
+    DuplicateDefinition(ExternalName),
+
+    /// A relocation referenced a symbol that couldn't be resolved.
+    UndefinedSymbol(ExternalName),
+
+    /// A relocation kind isn't supported by the JIT.
+    UnsupportedReloc(Reloc),
+
+    /// A function contains an EBB or jump table relocation that wasn't resolved during emission.
+    UnresolvedLocalReloc(ExternalName),
+
+    /// A relocation target is too far away from the relocated code.
+    RelocOutOfRange(ExternalName),
+
+    /// Allocating or protecting memory failed.
+    Memory(String),
+
+    /// Calls to the function can't be redirected because they don't go through a trampoline.
+    NotRedirectable(ExternalName),
+
+    /// A pointer written into a data object at the given offset doesn't fit inside it.
+    PointerOutOfBounds(ExternalName, CodeOffset),
+}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JitError::UnsupportedHost(ref msg) => write!(f, "unsupported host: {}", msg),
+            JitError::Compile(ref e) => write!(f, "compilation failed: {}", e),
+            JitError::DuplicateDefinition(ref name) => {
+                write!(f, "duplicate definition of {}", name)
+            }
+            JitError::UndefinedSymbol(ref name) => write!(f, "undefined symbol {}", name),
+            JitError::UnsupportedReloc(reloc) => write!(f, "unsupported relocation {:?}", reloc),
+            JitError::UnresolvedLocalReloc(ref name) => {
+                write!(f, "{} contains an unresolved EBB or jump table relocation", name)
+            }
+            JitError::RelocOutOfRange(ref name) => {
+                write!(f, "relocation against {} is out of range", name)
+            }
+            JitError::Memory(ref msg) => f.write_str(msg),
+            JitError::NotRedirectable(ref name) => {
+                write!(f, "calls to {} can't be redirected", name)
+            }
+            JitError::PointerOutOfBounds(ref name, offset) => {
+                write!(f, "pointer at offset {} is outside of data object {}", offset, name)
+            }
+        }
+    }
+}
+
+impl StdError for JitError {
+    fn description(&self) -> &str {
+        match *self {
+            JitError::UnsupportedHost(_) => "unsupported host",
+            JitError::Compile(_) => "compilation failed",
+            JitError::DuplicateDefinition(_) => "duplicate definition",
+            JitError::UndefinedSymbol(_) => "undefined symbol",
+            JitError::UnsupportedReloc(_) => "unsupported relocation",
+            JitError::UnresolvedLocalReloc(_) => "unresolved local relocation",
+            JitError::RelocOutOfRange(_) => "relocation out of range",
+            JitError::Memory(_) => "memory error",
+            JitError::NotRedirectable(_) => "function not redirectable",
+            JitError::PointerOutOfBounds(..) => "pointer outside of data object",
+        }
+    }
+}
+
+impl From<CodegenError> for JitError {
+    fn from(e: CodegenError) -> JitError {
+        JitError::Compile(e)
+    }
+}
+
+/// A relocation that hasn't been applied yet.
+struct PendingReloc {
+    /// Address of the relocated field.
+    at: *mut u8,
+    reloc: Reloc,
+    name: ExternalName,
+    addend: Addend,
+}
+
+/// A `RelocSink` that records external relocations for a function emitted at `base`.
+struct RelocRecorder<'a> {
+    base: *mut u8,
+    pending: &'a mut Vec<PendingReloc>,
+    local_relocs: bool,
+}
+
+impl<'a> RelocSink for RelocRecorder<'a> {
+    fn reloc_ebb(&mut self, _offset: CodeOffset, _reloc: Reloc, _ebb_offset: CodeOffset) {
+        self.local_relocs = true;
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        self.pending.push(PendingReloc {
+            at: unsafe { self.base.offset(offset as isize) },
+            reloc,
+            name: name.clone(),
+            addend: reloc_addend(reloc, addend),
+        });
+    }
+
+    fn reloc_jt(&mut self, _offset: CodeOffset, _reloc: Reloc, _jt: JumpTable) {
+        self.local_relocs = true;
+    }
+}
+
+/// A symbol lookup function.
+pub type LookupFn = Box<Fn(&ExternalName) -> Option<*const u8>>;
+
+/// A simple JIT compiler for the host machine.
+///
+/// Functions and data objects are identified by `ExternalName`s, the same names that appear in
+/// the relocations of compiled code. When resolving a relocation, the JIT looks for:
+///
+/// 1. A function or data object defined in this JIT.
+/// 2. A symbol registered with `symbol()`.
+/// 3. The result of the lookup function registered with `set_lookup()`.
+/// 4. For test case names and library calls, a symbol of the same name in the host process.
+///
+/// # Redirecting calls
+///
+/// To support tiered compilation, the JIT records the PC-relative references to every symbol,
+/// which are usually direct calls; see `call_sites()`. When `set_redirectable_calls(true)` is
+/// used, direct calls to functions defined in the JIT are routed through a trampoline which jumps
+/// to an address stored in writable data memory. `redirect()` atomically replaces that address,
+/// so a newer version of a function can be installed while other threads are running the old
+/// code. Code pages are never made writable again.
+pub struct SimpleJIT {
+    isa: Box<TargetIsa>,
+    code: Memory,
+    data: Memory,
+    functions: HashMap<ExternalName, (*const u8, usize)>,
+    data_objects: HashMap<ExternalName, (*mut u8, usize)>,
+    symbols: HashMap<ExternalName, *const u8>,
+    lookup: Option<LookupFn>,
+    pending: Vec<PendingReloc>,
+    /// Jump stubs used for calls to targets out of range of a 32-bit displacement.
+    stubs: HashMap<ExternalName, *const u8>,
+    /// Slots holding the address of symbols referenced through GOT relocations.
+    got: HashMap<ExternalName, *const u8>,
+    /// Route direct calls to functions defined in the JIT through trampolines?
+    redirectable_calls: bool,
+    /// Trampolines and the data slots holding their targets.
+    trampolines: HashMap<ExternalName, (*const u8, *mut u8)>,
+    /// Addresses of the relocated fields of PC-relative references to each symbol.
+    call_sites: HashMap<ExternalName, Vec<*const u8>>,
+}
+
+impl SimpleJIT {
+    /// Create a JIT for the host machine with default settings.
+    pub fn new() -> Result<Self, JitError> {
+        let (flag_builder, isa_builder) = cton_native::builders().map_err(|msg| {
+            JitError::UnsupportedHost(msg.to_string())
+        })?;
+        Ok(Self::with_isa(
+            isa_builder.finish(settings::Flags::new(&flag_builder)),
+        ))
+    }
+
+    /// Create a JIT that compiles code with `isa`.
+    ///
+    /// The ISA must describe the host machine, and must not be configured for position-independent
+    /// code.
+    pub fn with_isa(isa: Box<TargetIsa>) -> Self {
+        Self {
+            isa,
+            code: Memory::new(),
+            data: Memory::new(),
+            functions: HashMap::new(),
+            data_objects: HashMap::new(),
+            symbols: HashMap::new(),
+            lookup: None,
+            pending: Vec::new(),
+            stubs: HashMap::new(),
+            got: HashMap::new(),
+            redirectable_calls: false,
+            trampolines: HashMap::new(),
+            call_sites: HashMap::new(),
+        }
+    }
+
+    /// Get the target ISA used for compiling functions.
+    pub fn isa(&self) -> &TargetIsa {
+        &*self.isa
+    }
+
+    /// Register the address of a host symbol.
+    pub fn symbol(&mut self, name: ExternalName, ptr: *const u8) {
+        self.symbols.insert(name, ptr);
+    }
+
+    /// Register a function used to resolve symbols that are neither defined in the JIT nor
+    /// registered with `symbol()`.
+    pub fn set_lookup(&mut self, lookup: LookupFn) {
+        self.lookup = Some(lookup);
+    }
+
+    /// Route direct calls to functions defined in this JIT through redirectable trampolines.
+    ///
+    /// This applies to relocations resolved by later calls to `finalize()`.
+    pub fn set_redirectable_calls(&mut self, enable: bool) {
+        self.redirectable_calls = enable;
+    }
+
+    /// Get the addresses of the relocated fields of all PC-relative references to `name` that
+    /// have been resolved so far. These are usually the displacements of direct calls.
+    pub fn call_sites(&self, name: &ExternalName) -> &[*const u8] {
+        self.call_sites.get(name).map_or(&[], |sites| &sites[..])
+    }
+
+    /// Get the address of the trampoline used by redirectable calls to `name`, if there is one.
+    ///
+    /// Calling the trampoline always calls the current target of `name`.
+    pub fn get_trampoline(&self, name: &ExternalName) -> Option<*const u8> {
+        self.trampolines.get(name).map(|&(code, _)| code)
+    }
+
+    /// Atomically redirect all calls through the trampoline of `name` to `target`.
+    ///
+    /// Calls that have already entered the old code complete normally. The old code is never
+    /// freed, so it remains safe to execute.
+    pub fn redirect(&self, name: &ExternalName, target: *const u8) -> Result<(), JitError> {
+        let &(_, slot) = self.trampolines.get(name).ok_or_else(|| {
+            JitError::NotRedirectable(name.clone())
+        })?;
+        let slot = unsafe { &*(slot as *const AtomicUsize) };
+        slot.store(target as usize, Ordering::Release);
+        Ok(())
+    }
+
+    fn check_undefined(&self, name: &ExternalName) -> Result<(), JitError> {
+        if self.functions.contains_key(name) || self.data_objects.contains_key(name) {
+            Err(JitError::DuplicateDefinition(name.clone()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Compile the function in `ctx` and copy it into memory under `name`.
+    ///
+    /// The function can't be called until `finalize()` has been called.
+    pub fn compile_function(
+        &mut self,
+        name: ExternalName,
+        ctx: &mut Context,
+    ) -> Result<(), JitError> {
+        self.check_undefined(&name)?;
+        let size = ctx.compile(&*self.isa)?;
+        self.define_function(name, ctx, size)
+    }
+
+    /// Copy the function already compiled in `ctx` into memory under `name`.
+    ///
+    /// The code size must be the one returned by `Context::compile`.
+    pub fn define_function(
+        &mut self,
+        name: ExternalName,
+        ctx: &Context,
+        code_size: CodeOffset,
+    ) -> Result<(), JitError> {
+        self.check_undefined(&name)?;
+        let size = code_size as usize;
+        let ptr = self.code.allocate(size, 16).map_err(JitError::Memory)?;
+        let mut recorder = RelocRecorder {
+            base: ptr,
+            pending: &mut self.pending,
+            local_relocs: false,
+        };
+        ctx.emit_to_memory(ptr, &mut recorder, &*self.isa);
+        if recorder.local_relocs {
+            return Err(JitError::UnresolvedLocalReloc(name));
+        }
+        self.functions.insert(name, (ptr, size));
+        Ok(())
+    }
+
+    /// Define a writable data object initialized with `bytes`, and return its address.
+    pub fn define_data(&mut self, name: ExternalName, bytes: &[u8]) -> Result<*mut u8, JitError> {
+        self.check_undefined(&name)?;
+        let ptr = self.data.allocate(bytes.len(), 16).map_err(
+            JitError::Memory,
+        )?;
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len());
+        }
+        self.data_objects.insert(name, (ptr, bytes.len()));
+        Ok(ptr)
+    }
+
+    /// Store the address of `target` plus `addend` as a pointer at `offset` in the data object
+    /// `name`.
+    ///
+    /// The pointer is written when `finalize()` is called. The 8-byte pointer must fit inside the
+    /// data object.
+    pub fn write_pointer(
+        &mut self,
+        name: &ExternalName,
+        offset: CodeOffset,
+        target: ExternalName,
+        addend: Addend,
+    ) -> Result<(), JitError> {
+        let (ptr, size) = self.get_data(name).ok_or_else(
+            || JitError::UndefinedSymbol(name.clone()),
+        )?;
+        if offset as usize + 8 > size {
+            return Err(JitError::PointerOutOfBounds(name.clone(), offset));
+        }
+        self.pending.push(PendingReloc {
+            at: unsafe { ptr.offset(offset as isize) },
+            reloc: Reloc::IntelAbs8,
+            name: target,
+            addend,
+        });
+        Ok(())
+    }
+
+    /// Find the address of the symbol `name`.
+    fn resolve(&self, name: &ExternalName) -> Result<*const u8, JitError> {
+        if let Some(&(ptr, _)) = self.functions.get(name) {
+            return Ok(ptr);
+        }
+        if let Some(&(ptr, _)) = self.data_objects.get(name) {
+            return Ok(ptr);
+        }
+        if let Some(&ptr) = self.symbols.get(name) {
+            return Ok(ptr);
+        }
+        if let Some(ref lookup) = self.lookup {
+            if let Some(ptr) = lookup(name) {
+                return Ok(ptr);
+            }
+        }
+        lookup_host_symbol(name).ok_or_else(|| JitError::UndefinedSymbol(name.clone()))
+    }
+
+    /// Get a jump stub for `name` which can be reached with a 32-bit displacement from `at`.
+    fn stub(&mut self, name: &ExternalName, target: *const u8) -> Result<*const u8, JitError> {
+        if let Some(&stub) = self.stubs.get(name) {
+            return Ok(stub);
+        }
+        // jmp *0(%rip); .quad target
+        let stub = self.code.allocate(14, 16).map_err(JitError::Memory)?;
+        unsafe {
+            ptr::copy_nonoverlapping([0xff, 0x25, 0, 0, 0, 0].as_ptr(), stub, 6);
+            write_unaligned(stub.offset(6) as *mut u64, target as u64);
+        }
+        self.stubs.insert(name.clone(), stub);
+        Ok(stub)
+    }
+
+    /// Get a trampoline that jumps to the address stored in a writable slot, initially `target`.
+    fn trampoline(
+        &mut self,
+        name: &ExternalName,
+        target: *const u8,
+    ) -> Result<*const u8, JitError> {
+        if let Some(&(code, _)) = self.trampolines.get(name) {
+            return Ok(code);
+        }
+        let slot = self.data.allocate(8, 8).map_err(JitError::Memory)?;
+        // movabs $slot, %r11; jmp *(%r11)
+        let code = self.code.allocate(13, 16).map_err(JitError::Memory)?;
+        unsafe {
+            write_unaligned(slot as *mut u64, target as u64);
+            ptr::copy_nonoverlapping([0x49, 0xbb].as_ptr(), code, 2);
+            write_unaligned(code.offset(2) as *mut u64, slot as u64);
+            ptr::copy_nonoverlapping([0x41, 0xff, 0x23].as_ptr(), code.offset(10), 3);
+        }
+        self.trampolines.insert(name.clone(), (code, slot));
+        Ok(code)
+    }
+
+    /// Get a slot holding the address of `name` for GOT-relative relocations.
+    fn got_entry(&mut self, name: &ExternalName, target: *const u8) -> Result<*const u8, JitError> {
+        if let Some(&slot) = self.got.get(name) {
+            return Ok(slot);
+        }
+        let slot = self.code.allocate(8, 8).map_err(JitError::Memory)?;
+        unsafe {
+            write_unaligned(slot as *mut u64, target as u64);
+        }
+        self.got.insert(name.clone(), slot);
+        Ok(slot)
+    }
+
+    /// Apply a single relocation.
+    fn apply(&mut self, r: &PendingReloc) -> Result<(), JitError> {
+        let target = self.resolve(&r.name)?;
+        let pcrel = |to: *const u8| (to as i64).wrapping_add(r.addend).wrapping_sub(r.at as i64);
+        let fits = |disp: i64| disp == i64::from(disp as i32);
+        match r.reloc {
+            Reloc::IntelAbs4 => {
+                let value = (target as u64).wrapping_add(r.addend as u64);
+                if value != u64::from(value as u32) {
+                    return Err(JitError::RelocOutOfRange(r.name.clone()));
+                }
+                unsafe { write_unaligned(r.at as *mut u32, value as u32) };
+            }
+            Reloc::IntelAbs8 => {
+                let value = (target as u64).wrapping_add(r.addend as u64);
+                unsafe { write_unaligned(r.at as *mut u64, value) };
+            }
+            Reloc::IntelPCRel4 |
+            Reloc::IntelPLTRel4 => {
+                let target = if self.redirectable_calls && self.functions.contains_key(&r.name) {
+                    self.trampoline(&r.name, target)?
+                } else {
+                    target
+                };
+                self.call_sites
+                    .entry(r.name.clone())
+                    .or_insert_with(Vec::new)
+                    .push(r.at);
+                let mut disp = pcrel(target);
+                if !fits(disp) {
+                    disp = pcrel(self.stub(&r.name, target)?);
+                }
+                if !fits(disp) {
+                    return Err(JitError::RelocOutOfRange(r.name.clone()));
+                }
+                unsafe { write_unaligned(r.at as *mut i32, disp as i32) };
+            }
+            Reloc::IntelGOTPCRel4 => {
+                let disp = pcrel(self.got_entry(&r.name, target)?);
+                if !fits(disp) {
+                    return Err(JitError::RelocOutOfRange(r.name.clone()));
+                }
+                unsafe { write_unaligned(r.at as *mut i32, disp as i32) };
+            }
+            reloc => return Err(JitError::UnsupportedReloc(reloc)),
+        }
+        Ok(())
+    }
+
+    /// Resolve all pending relocations and make the compiled code executable.
+    ///
+    /// After this, the functions compiled so far can be retrieved with `get_function()`. More
+    /// functions can be compiled after finalizing; they are placed in new pages.
+    pub fn finalize(&mut self) -> Result<(), JitError> {
+        let mut pending = ::std::mem::replace(&mut self.pending, Vec::new()).into_iter();
+        while let Some(r) = pending.next() {
+            if let Err(e) = self.apply(&r) {
+                // Keep the failed relocation and the ones after it, so they can be applied by a
+                // later `finalize()` once the problem is fixed, e.g. by defining a missing symbol.
+                self.pending.push(r);
+                self.pending.extend(pending);
+                return Err(e);
+            }
+        }
+        self.code.set_readable_and_executable().map_err(
+            JitError::Memory,
+        )
+    }
+
+    /// Get the address of the function `name`.
+    ///
+    /// The address may only be called after `finalize()`. It remains valid for the lifetime of
+    /// the `SimpleJIT`.
+    pub fn get_function(&self, name: &ExternalName) -> Option<*const u8> {
+        self.functions.get(name).map(|&(ptr, _)| ptr)
+    }
+
+    /// Get the names and the machine code of all the functions, sorted by address.
+    ///
+    /// This is the information needed by profilers, see the `perf` module.
+    pub fn functions(&self) -> Vec<(&ExternalName, &[u8])> {
+        let mut functions: Vec<(&ExternalName, &[u8])> = self.functions
+            .iter()
+            .map(|(name, &(ptr, size))| {
+                (name, unsafe { slice::from_raw_parts(ptr, size) })
+            })
+            .collect();
+        functions.sort_by_key(|&(_, code)| code.as_ptr());
+        functions
+    }
+
+    /// Get the address and size of the data object `name`.
+    pub fn get_data(&self, name: &ExternalName) -> Option<(*mut u8, usize)> {
+        self.data_objects.get(name).cloned()
+    }
+}
+
+/// Look up a test case name or library call in the host process.
+fn lookup_host_symbol(name: &ExternalName) -> Option<*const u8> {
+    let sym = match *name {
+        ExternalName::TestCase { length, ascii } => {
+            CString::new(&ascii[0..length as usize]).ok()?
+        }
+        ExternalName::LibCall(lc) => CString::new(libcall_name(lc)).unwrap(),
+        ExternalName::User { .. } => return None,
+    };
+    dlsym(&sym)
+}
+
+/// Look up a symbol by name in the host process.
+pub fn lookup_host_symbol_name(name: &str) -> Option<*const u8> {
+    dlsym(&CString::new(name).ok()?)
+}
+
+fn dlsym(sym: &CString) -> Option<*const u8> {
+    let ptr = unsafe { libc::dlsym(libc::RTLD_DEFAULT, sym.as_ptr()) };
+    if ptr.is_null() {
+        None
+    } else {
+        Some(ptr as *const u8)
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    extern crate cton_reader;
+
+    use self::cton_reader::parse_functions;
+    use cretonne::Context;
+    use cretonne::ir::ExternalName;
+    use std::mem;
+    use super::{SimpleJIT, JitError};
+
+    fn compile(jit: &mut SimpleJIT, text: &str) -> Result<(), JitError> {
+        for func in parse_functions(text).unwrap() {
+            let name = func.name.clone();
+            jit.compile_function(name, &mut Context::for_function(func))?;
+        }
+        Ok(())
+    }
+
+    extern "C" fn triple(x: i32) -> i32 {
+        x * 3
+    }
+
+    #[test]
+    fn calls() {
+        let mut jit = SimpleJIT::new().unwrap();
+        jit.symbol(ExternalName::testcase("triple"), triple as *const u8);
+        compile(
+            &mut jit,
+            "function %add(i32, i32) -> i32 native {
+                 ebb0(v0: i32, v1: i32):
+                     v2 = iadd v0, v1
+                     return v2
+             }
+
+             function %add_triple(i32, i32) -> i32 native {
+                 sig0 = (i32, i32) -> i32 native
+                 sig1 = (i32) -> i32 native
+                 fn0 = sig0 %add
+                 fn1 = sig1 %triple
+             ebb0(v0: i32, v1: i32):
+                 v2 = call fn0(v0, v1)
+                 v3 = call fn1(v2)
+                 return v3
+             }",
+        ).unwrap();
+        jit.finalize().unwrap();
+
+        let ptr = jit.get_function(&ExternalName::testcase("add_triple"))
+            .unwrap();
+        let f: extern "C" fn(i32, i32) -> i32 = unsafe { mem::transmute(ptr) };
+        assert_eq!(f(3, 4), 21);
+
+        let functions = jit.functions();
+        assert_eq!(functions.len(), 2);
+        assert!(functions[0].1.as_ptr() < functions[1].1.as_ptr());
+        for &(name, code) in &functions {
+            assert_eq!(jit.get_function(name), Some(code.as_ptr()));
+            assert!(!code.is_empty());
+        }
+    }
+
+    #[test]
+    fn redirect() {
+        let mut jit = SimpleJIT::new().unwrap();
+        jit.set_redirectable_calls(true);
+        compile(
+            &mut jit,
+            "function %callee() -> i32 native {
+             ebb0:
+                 v0 = iconst.i32 1
+                 return v0
+             }
+
+             function %caller() -> i32 native {
+                 sig0 = () -> i32 native
+                 fn0 = sig0 %callee
+             ebb0:
+                 v0 = call fn0()
+                 return v0
+             }",
+        ).unwrap();
+        jit.finalize().unwrap();
+
+        let callee = ExternalName::testcase("callee");
+        let ptr = jit.get_function(&ExternalName::testcase("caller")).unwrap();
+        let caller: extern "C" fn() -> i32 = unsafe { mem::transmute(ptr) };
+        assert_eq!(caller(), 1);
+        assert_eq!(jit.call_sites(&callee).len(), 1);
+        assert!(jit.get_trampoline(&callee).is_some());
+
+        compile(
+            &mut jit,
+            "function %callee_v2() -> i32 native {
+             ebb0:
+                 v0 = iconst.i32 2
+                 return v0
+             }",
+        ).unwrap();
+        jit.finalize().unwrap();
+        let v2 = jit.get_function(&ExternalName::testcase("callee_v2")).unwrap();
+        jit.redirect(&callee, v2).unwrap();
+        assert_eq!(caller(), 2);
+
+        assert_eq!(
+            jit.redirect(&ExternalName::testcase("caller"), v2),
+            Err(JitError::NotRedirectable(ExternalName::testcase("caller")))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut jit = SimpleJIT::new().unwrap();
+        let text = "function %f() native {
+                        sig0 = () native
+                        fn0 = sig0 %no_such_function_anywhere
+                    ebb0:
+                        call fn0()
+                        return
+                    }";
+        compile(&mut jit, text).unwrap();
+        assert_eq!(
+            compile(&mut jit, text),
+            Err(JitError::DuplicateDefinition(ExternalName::testcase("f")))
+        );
+        assert_eq!(
+            jit.finalize(),
+            Err(JitError::UndefinedSymbol(
+                ExternalName::testcase("no_such_function_anywhere"),
+            ))
+        );
+
+        let data = ExternalName::testcase("data");
+        jit.define_data(data.clone(), &[0; 12]).unwrap();
+        assert_eq!(
+            jit.write_pointer(&data, 8, ExternalName::testcase("f"), 0),
+            Err(JitError::PointerOutOfBounds(data.clone(), 8))
+        );
+    }
+
+    #[test]
+    fn define_after_failed_finalize() {
+        let mut jit = SimpleJIT::new().unwrap();
+        compile(
+            &mut jit,
+            "function %f(i32) -> i32 native {
+                 sig0 = (i32) -> i32 native
+                 fn0 = sig0 %defined_after_finalize
+             ebb0(v0: i32):
+                 v1 = call fn0(v0)
+                 return v1
+             }",
+        ).unwrap();
+        let callee = ExternalName::testcase("defined_after_finalize");
+        assert_eq!(
+            jit.finalize(),
+            Err(JitError::UndefinedSymbol(callee.clone()))
+        );
+
+        // The failed relocation is still pending, so it is applied once the symbol is defined.
+        jit.symbol(callee, triple as *const u8);
+        jit.finalize().unwrap();
+        let ptr = jit.get_function(&ExternalName::testcase("f")).unwrap();
+        let f: extern "C" fn(i32) -> i32 = unsafe { mem::transmute(ptr) };
+        assert_eq!(f(5), 15);
+    }
+}
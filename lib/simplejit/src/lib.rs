@@ -0,0 +1,55 @@
+//! A simple JIT library for Cretonne.
+//!
+//! The [`SimpleJIT`](struct.SimpleJIT.html) type compiles Cretonne IL functions for the host
+//! machine, copies the machine code into executable memory, resolves relocations against other
+//! JIT-compiled functions and host symbols, and hands back function pointers:
+//!
+//! ```no_run
+//! # extern crate cretonne;
+//! # extern crate cton_simplejit;
+//! # fn main() {
+//! use cretonne::Context;
+//! use cretonne::ir::ExternalName;
+//! use cton_simplejit::SimpleJIT;
+//! use std::mem;
+//!
+//! let mut jit = SimpleJIT::new().unwrap();
+//! let mut ctx = Context::new();
+//! // ... fill in `ctx.func` with a function of type `(i32) -> i32` ...
+//! let name = ExternalName::testcase("twice");
+//! jit.compile_function(name.clone(), &mut ctx).unwrap();
+//! jit.finalize().unwrap();
+//! let code = jit.get_function(&name).unwrap();
+//! let twice: extern "C" fn(i32) -> i32 = unsafe { mem::transmute(code) };
+//! assert_eq!(twice(21), 42);
+//! # }
+//! ```
+//!
+//! Memory is never writable and executable at the same time: code is written into fresh pages
+//! which are only made executable by `finalize()`.
+//!
+//! The [`perf`](perf/index.html) module describes the compiled functions to the Linux `perf`
+//! profiler.
+//!
+//! To JIT-compile a `cton_module::Module`, use [`SimpleJITBackend`](struct.SimpleJITBackend.html)
+//! as its backend.
+//!
+//! Only Unix-like hosts are currently supported.
+
+#![deny(missing_docs,
+        trivial_numeric_casts,
+        unused_extern_crates)]
+
+extern crate cretonne;
+extern crate cton_module;
+extern crate cton_native;
+extern crate libc;
+
+mod backend;
+mod jit;
+mod memory;
+pub mod perf;
+
+pub use backend::{SimpleJITBackend, SimpleJITProduct};
+pub use jit::{SimpleJIT, JitError, LookupFn};
+pub use perf::{JitDump, PerfMap};
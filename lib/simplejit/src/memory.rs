@@ -0,0 +1,161 @@
+//! Page-based memory allocation with W^X protection.
+
+use libc;
+use std::ptr;
+
+/// A contiguous range of pages obtained from `mmap`.
+struct PtrLen {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl PtrLen {
+    fn new() -> Self {
+        Self {
+            ptr: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    /// Map at least `size` bytes of fresh read-write memory.
+    fn with_size(size: usize) -> Result<Self, String> {
+        let page_size = page_size();
+        let len = (size + page_size - 1) & !(page_size - 1);
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANON,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            Err(format!("mmap of {} bytes failed", len))
+        } else {
+            Ok(Self {
+                ptr: ptr as *mut u8,
+                len,
+            })
+        }
+    }
+}
+
+/// Get the host page size.
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Minimum number of bytes to map at a time.
+const MIN_ALLOCATION: usize = 64 * 1024;
+
+/// A memory allocator that hands out chunks from mapped pages.
+///
+/// Memory is writable until it is protected with `set_readable_and_executable`. Once protected,
+/// pages are never written again: subsequent allocations come from freshly mapped pages. This
+/// way, no page is ever both writable and executable.
+pub struct Memory {
+    /// Pages that are full or have been protected.
+    allocations: Vec<PtrLen>,
+    /// Number of leading entries in `allocations` that have already been protected.
+    protected: usize,
+    /// The pages currently being allocated from.
+    current: PtrLen,
+    /// Offset of the first free byte in `current`.
+    position: usize,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self {
+            allocations: Vec::new(),
+            protected: 0,
+            current: PtrLen::new(),
+            position: 0,
+        }
+    }
+
+    /// Allocate `size` bytes of writable memory aligned to `align`, which must be a power of two
+    /// no larger than the page size.
+    pub fn allocate(&mut self, size: usize, align: usize) -> Result<*mut u8, String> {
+        debug_assert!(align.is_power_of_two());
+        let aligned = (self.position + align - 1) & !(align - 1);
+        if aligned + size <= self.current.len {
+            self.position = aligned + size;
+            return Ok(unsafe { self.current.ptr.offset(aligned as isize) });
+        }
+
+        self.finish_current();
+        self.current = PtrLen::with_size(if size > MIN_ALLOCATION {
+            size
+        } else {
+            MIN_ALLOCATION
+        })?;
+        self.position = size;
+        Ok(self.current.ptr)
+    }
+
+    /// Retire the current pages so they can be protected.
+    fn finish_current(&mut self) {
+        let current = ::std::mem::replace(&mut self.current, PtrLen::new());
+        if current.len != 0 {
+            self.allocations.push(current);
+        }
+        self.position = 0;
+    }
+
+    /// Make all memory allocated so far readable and executable, but no longer writable.
+    pub fn set_readable_and_executable(&mut self) -> Result<(), String> {
+        self.protect(libc::PROT_READ | libc::PROT_EXEC)
+    }
+
+    fn protect(&mut self, prot: libc::c_int) -> Result<(), String> {
+        self.finish_current();
+        for alloc in &self.allocations[self.protected..] {
+            if unsafe { libc::mprotect(alloc.ptr as *mut libc::c_void, alloc.len, prot) } != 0 {
+                return Err(format!("mprotect of {} bytes failed", alloc.len));
+            }
+        }
+        self.protected = self.allocations.len();
+        Ok(())
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        self.finish_current();
+        for alloc in &self.allocations {
+            unsafe {
+                libc::munmap(alloc.ptr as *mut libc::c_void, alloc.len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Memory;
+
+    #[test]
+    fn allocate() {
+        let mut mem = Memory::new();
+        let a = mem.allocate(10, 1).unwrap() as usize;
+        let b = mem.allocate(8, 16).unwrap() as usize;
+        assert_eq!(b % 16, 0);
+        assert!(b >= a + 10);
+
+        // Large allocations get their own pages.
+        let c = mem.allocate(1 << 20, 16).unwrap();
+        unsafe {
+            *c.offset((1 << 20) - 1) = 1;
+        }
+        mem.set_readable_and_executable().unwrap();
+
+        // New allocations after protection come from fresh pages.
+        let d = mem.allocate(10, 1).unwrap();
+        unsafe {
+            *d = 1;
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Describing JIT-compiled code to the Linux `perf` profiler.
+//!
+//! `perf` can't find symbols for code that doesn't come from a file on disk, so samples in
+//! JIT-compiled functions show up as raw addresses. Two formats let a JIT describe its code:
+//!
+//! - A perf map, `/tmp/perf-<pid>.map`, is a text file with the address, size, and name of each
+//!   function. `perf report` reads it directly. It is the simplest option, but the functions must
+//!   still be in memory when the report is made, and there is no disassembly.
+//! - A jitdump file, `jit-<pid>.dump`, also records the machine code of each function and when it
+//!   was loaded. It must be written while `perf record -k mono` is running, and converted with
+//!   `perf inject --jit` before running `perf report`.
+//!
+//! After `SimpleJIT::finalize()`, `SimpleJIT::functions()` lists the compiled functions with their
+//! machine code:
+//!
+//! ```no_run
+//! # extern crate cton_simplejit;
+//! # fn main() {
+//! use cton_simplejit::{PerfMap, SimpleJIT};
+//!
+//! let mut jit = SimpleJIT::new().unwrap();
+//! // ... compile functions ...
+//! jit.finalize().unwrap();
+//! let mut map = PerfMap::new().unwrap();
+//! for (name, code) in jit.functions() {
+//!     map.add_function(&name.to_string(), code).unwrap();
+//! }
+//! # }
+//! ```
+
+use libc;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+/// The path of the perf map of the current process.
+///
+/// `perf` always looks for the map in `/tmp`, regardless of `TMPDIR`.
+pub fn perf_map_path() -> PathBuf {
+    Path::new("/tmp").join(format!("perf-{}.map", process_id()))
+}
+
+/// A writer of the perf map of the current process.
+pub struct PerfMap {
+    file: BufWriter<File>,
+}
+
+impl PerfMap {
+    /// Create the perf map of the current process, or append to it if it exists.
+    pub fn new() -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(
+            perf_map_path(),
+        )?;
+        Ok(Self { file: BufWriter::new(file) })
+    }
+
+    /// Describe the function `name` whose machine code is `code`.
+    ///
+    /// The line is written immediately, so it is visible to `perf` even if the process crashes.
+    pub fn add_function(&mut self, name: &str, code: &[u8]) -> io::Result<()> {
+        write_map_entry(&mut self.file, name, code.as_ptr(), code.len())?;
+        self.file.flush()
+    }
+}
+
+/// Write a perf map line describing the function `name` at `addr`, with `size` bytes of code.
+fn write_map_entry<W: Write>(
+    w: &mut W,
+    name: &str,
+    addr: *const u8,
+    size: usize,
+) -> io::Result<()> {
+    writeln!(w, "{:x} {:x} {}", addr as usize, size, name)
+}
+
+/// The jitdump magic number, "JiTD" when read as a big-endian number.
+const JITDUMP_MAGIC: u32 = 0x4a69_5444;
+
+/// The version of the jitdump format.
+const JITDUMP_VERSION: u32 = 1;
+
+/// The size of the jitdump file header.
+const JITDUMP_HEADER_SIZE: u32 = 40;
+
+/// The size of a record header.
+const RECORD_HEADER_SIZE: u32 = 16;
+
+/// The record type describing a loaded function.
+const JIT_CODE_LOAD: u32 = 0;
+
+/// The record type marking the end of the file.
+const JIT_CODE_CLOSE: u32 = 3;
+
+/// A writer of a jitdump file.
+///
+/// The file is mapped into memory while it is open, which is how `perf record` notices it.
+pub struct JitDump {
+    file: File,
+    /// The executable mapping of the first page of the file.
+    marker: *mut libc::c_void,
+    /// The index of the next function.
+    index: u64,
+}
+
+impl JitDump {
+    /// Create the file `jit-<pid>.dump` in the directory `dir`.
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(format!("jit-{}.dump", process_id()));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        let mut header = Vec::with_capacity(JITDUMP_HEADER_SIZE as usize);
+        put_u32(&mut header, JITDUMP_MAGIC);
+        put_u32(&mut header, JITDUMP_VERSION);
+        put_u32(&mut header, JITDUMP_HEADER_SIZE);
+        put_u32(&mut header, elf_machine());
+        put_u32(&mut header, 0);
+        put_u32(&mut header, process_id());
+        put_u64(&mut header, timestamp());
+        put_u64(&mut header, 0);
+        file.write_all(&header)?;
+
+        let marker = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                page_size(),
+                libc::PROT_READ | libc::PROT_EXEC,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if marker == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            file,
+            marker,
+            index: 0,
+        })
+    }
+
+    /// Record that the function `name`, whose machine code is `code`, has been loaded.
+    pub fn add_function(&mut self, name: &str, code: &[u8]) -> io::Result<()> {
+        let addr = code.as_ptr() as u64;
+        let size = RECORD_HEADER_SIZE as usize + 40 + name.len() + 1 + code.len();
+        let mut record = Vec::with_capacity(size);
+        put_u32(&mut record, JIT_CODE_LOAD);
+        put_u32(&mut record, size as u32);
+        put_u64(&mut record, timestamp());
+        put_u32(&mut record, process_id());
+        put_u32(&mut record, thread_id());
+        put_u64(&mut record, addr);
+        put_u64(&mut record, addr);
+        put_u64(&mut record, code.len() as u64);
+        put_u64(&mut record, self.index);
+        record.extend_from_slice(name.as_bytes());
+        record.push(0);
+        record.extend_from_slice(code);
+        debug_assert_eq!(record.len(), size);
+        self.index += 1;
+        self.file.write_all(&record)
+    }
+}
+
+impl Drop for JitDump {
+    fn drop(&mut self) {
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE as usize);
+        put_u32(&mut record, JIT_CODE_CLOSE);
+        put_u32(&mut record, RECORD_HEADER_SIZE);
+        put_u64(&mut record, timestamp());
+        // There is nothing to do about an error while dropping.
+        self.file.write_all(&record).ok();
+        unsafe {
+            libc::munmap(self.marker, page_size());
+        }
+    }
+}
+
+fn put_u32(buf: &mut Vec<u8>, x: u32) {
+    for i in 0..4 {
+        buf.push((x >> (8 * i)) as u8);
+    }
+}
+
+fn put_u64(buf: &mut Vec<u8>, x: u64) {
+    put_u32(buf, x as u32);
+    put_u32(buf, (x >> 32) as u32);
+}
+
+fn process_id() -> u32 {
+    unsafe { libc::getpid() as u32 }
+}
+
+#[cfg(target_os = "linux")]
+fn thread_id() -> u32 {
+    unsafe { libc::syscall(libc::SYS_gettid) as u32 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn thread_id() -> u32 {
+    process_id()
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// The current time in nanoseconds of the monotonic clock, which `perf record -k mono` uses.
+fn timestamp() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// The ELF machine number of the host.
+fn elf_machine() -> u32 {
+    if cfg!(target_arch = "x86_64") {
+        62
+    } else if cfg!(target_arch = "x86") {
+        3
+    } else if cfg!(target_arch = "arm") {
+        40
+    } else if cfg!(target_arch = "aarch64") {
+        183
+    } else if cfg!(target_arch = "riscv64") || cfg!(target_arch = "riscv32") {
+        243
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Read;
+
+    #[test]
+    fn map_entry() {
+        let mut text = Vec::new();
+        write_map_entry(&mut text, "%add", 0x7f00_1234 as *const u8, 0x2a).unwrap();
+        assert_eq!(text, b"7f001234 2a %add\n");
+        assert_eq!(
+            perf_map_path(),
+            PathBuf::from(format!("/tmp/perf-{}.map", process_id()))
+        );
+    }
+
+    #[test]
+    fn jitdump() {
+        let dir = env::temp_dir().join(format!("cton-jitdump-{}", process_id()));
+        fs::create_dir_all(&dir).unwrap();
+        let code = [0xc3u8];
+        {
+            let mut dump = JitDump::new(&dir).unwrap();
+            dump.add_function("%ret", &code).unwrap();
+        }
+        let path = dir.join(format!("jit-{}.dump", process_id()));
+        let mut data = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut data).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let u32_at = |offset: usize| {
+            (0..4).fold(0, |x, i| x | u32::from(data[offset + i]) << (8 * i))
+        };
+        assert_eq!(u32_at(0), JITDUMP_MAGIC);
+        assert_eq!(u32_at(8), JITDUMP_HEADER_SIZE);
+        assert_eq!(u32_at(20), process_id());
+
+        let load = JITDUMP_HEADER_SIZE as usize;
+        let load_size = 16 + 40 + 5 + 1;
+        assert_eq!(u32_at(load), JIT_CODE_LOAD);
+        assert_eq!(u32_at(load + 4), load_size as u32);
+        assert_eq!(&data[load + 56..load + 61], b"%ret\0");
+        assert_eq!(data[load + 61], 0xc3);
+
+        let close = load + load_size;
+        assert_eq!(u32_at(close), JIT_CODE_CLOSE);
+        assert_eq!(data.len(), close + 16);
+    }
+}
@@ -95,6 +95,9 @@ pub struct DummyEnvironment {
 
     /// Vector of wasm bytecode size for each function.
     pub func_bytecode_sizes: Vec<usize>,
+
+    /// The index or export name of the only function whose body is translated, if any.
+    selected_function: Option<String>,
 }
 
 impl DummyEnvironment {
@@ -109,6 +112,28 @@ impl DummyEnvironment {
             info: DummyModuleInfo::with_flags(flags),
             trans: FuncTranslator::new(),
             func_bytecode_sizes: Vec::new(),
+            selected_function: None,
+        }
+    }
+
+    /// Only translate the body of the function whose index or export name is `selector`.
+    ///
+    /// The other functions are defined without a body, so `info.function_bodies` still has an
+    /// entry for every function body in the module.
+    pub fn select_function(&mut self, selector: &str) {
+        self.selected_function = Some(String::from(selector));
+    }
+
+    /// Is the body of the function `func_index` translated?
+    pub fn is_selected(&self, func_index: FunctionIndex) -> bool {
+        match self.selected_function {
+            None => true,
+            Some(ref selector) => {
+                *selector == func_index.to_string() ||
+                    self.info.functions[func_index].export_names.iter().any(
+                        |name| name == selector,
+                    )
+            }
         }
     }
 
@@ -376,16 +401,19 @@ impl<'data> ModuleEnvironment<'data> for DummyEnvironment {
     }
 
     fn define_function_body(&mut self, body_bytes: &'data [u8]) -> Result<(), String> {
+        let function_index = self.get_num_func_imports() + self.info.function_bodies.len();
+        let selected = self.is_selected(function_index);
         let func = {
             let mut func_environ = DummyFuncEnvironment::new(&self.info);
-            let function_index = self.get_num_func_imports() + self.info.function_bodies.len();
             let name = get_func_name(function_index);
             let sig = func_environ.vmctx_sig(self.get_func_type(function_index));
             let mut func = ir::Function::with_name_signature(name, sig);
-            let reader = wasmparser::BinaryReader::new(body_bytes);
-            self.trans
-                .translate_from_reader(reader, &mut func, &mut func_environ)
-                .map_err(|e| String::from(e.description()))?;
+            if selected {
+                let reader = wasmparser::BinaryReader::new(body_bytes);
+                self.trans
+                    .translate_from_reader(reader, &mut func, &mut func_environ)
+                    .map_err(|e| String::from(e.description()))?;
+            }
             func
         };
         self.func_bytecode_sizes.push(body_bytes.len());
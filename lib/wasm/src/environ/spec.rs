@@ -179,11 +179,25 @@ pub trait ModuleEnvironment<'data> {
     /// Declares a global to the environment.
     fn declare_global(&mut self, global: Global);
 
+    /// Declares a global import to the environment.
+    ///
+    /// The default implementation ignores the names and declares the global like
+    /// `declare_global`.
+    fn declare_global_import(&mut self, global: Global, _module: &'data str, _field: &'data str) {
+        self.declare_global(global)
+    }
+
     /// Return the global for the given global index.
     fn get_global(&self, global_index: GlobalIndex) -> &Global;
 
     /// Declares a table to the environment.
     fn declare_table(&mut self, table: Table);
+    /// Declares a table import to the environment.
+    ///
+    /// The default implementation ignores the names and declares the table like `declare_table`.
+    fn declare_table_import(&mut self, table: Table, _module: &'data str, _field: &'data str) {
+        self.declare_table(table)
+    }
     /// Fills a declared table with references to functions in the module.
     fn declare_table_elements(
         &mut self,
@@ -194,6 +208,13 @@ pub trait ModuleEnvironment<'data> {
     );
     /// Declares a memory to the environment
     fn declare_memory(&mut self, memory: Memory);
+    /// Declares a memory import to the environment.
+    ///
+    /// The default implementation ignores the names and declares the memory like
+    /// `declare_memory`.
+    fn declare_memory_import(&mut self, memory: Memory, _module: &'data str, _field: &'data str) {
+        self.declare_memory(memory)
+    }
     /// Fills a declared memory with bytes at module instantiation.
     fn declare_data_initialization(
         &mut self,
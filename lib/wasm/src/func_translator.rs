@@ -7,7 +7,7 @@
 use code_translator::translate_operator;
 use cretonne::entity::EntityRef;
 use cretonne::ir::{self, InstBuilder, Ebb};
-use cretonne::result::{CtonResult, CtonError};
+use cretonne::result::{CodegenResult, CodegenError};
 use cretonne::timing;
 use cton_frontend::{FunctionBuilderContext, FunctionBuilder, Variable};
 use environ::FuncEnvironment;
@@ -46,17 +46,22 @@ impl FuncTranslator {
     ///
     /// [wasm]: https://webassembly.github.io/spec/binary/modules.html#code-section
     ///
-    /// The Cretonne IR function `func` should be completely empty except for the `func.signature`
-    /// and `func.name` fields. The signature may contain special-purpose arguments which are not
-    /// regarded as WebAssembly local variables. Any signature arguments marked as
-    /// `ArgumentPurpose::Normal` are made accessible as WebAssembly local variables.
+    /// Only the `func.signature` and `func.name` fields of the Cretonne IR function `func` are
+    /// used; the rest of it is cleared before the translated body is built. The signature may
+    /// contain special-purpose arguments which are not regarded as WebAssembly local variables.
+    /// Any signature arguments marked as `ArgumentPurpose::Normal` are made accessible as
+    /// WebAssembly local variables.
+    ///
+    /// When translating and compiling many functions, keep translating into the same function,
+    /// e.g. the one in a `cretonne::Context`, and update its name and signature in between. That
+    /// way, the memory allocated for the previous function is reused.
     ///
     pub fn translate<FE: FuncEnvironment + ?Sized>(
         &mut self,
         code: &[u8],
         func: &mut ir::Function,
         environ: &mut FE,
-    ) -> CtonResult {
+    ) -> CodegenResult {
         self.translate_from_reader(BinaryReader::new(code), func, environ)
     }
 
@@ -66,16 +71,15 @@ impl FuncTranslator {
         mut reader: BinaryReader,
         func: &mut ir::Function,
         environ: &mut FE,
-    ) -> CtonResult {
+    ) -> CodegenResult {
         let _tt = timing::wasm_translate_function();
-        dbg!(
+        debug!(
             "translate({} bytes, {}{})",
             reader.bytes_remaining(),
             func.name,
             func.signature
         );
-        debug_assert_eq!(func.dfg.num_ebbs(), 0, "Function must be empty");
-        debug_assert_eq!(func.dfg.num_insts(), 0, "Function must be empty");
+        func.clear_body();
 
         // This clears the `FunctionBuilderContext`.
         let mut builder = FunctionBuilder::new(func, &mut self.func_ctx);
@@ -134,17 +138,17 @@ fn parse_local_decls(
     reader: &mut BinaryReader,
     builder: &mut FunctionBuilder<Variable>,
     num_params: usize,
-) -> CtonResult {
+) -> CodegenResult {
     let mut next_local = num_params;
     let local_count = reader.read_local_count().map_err(
-        |_| CtonError::InvalidInput,
+        |_| CodegenError::InvalidInput,
     )?;
 
     let mut locals_total = 0;
     for _ in 0..local_count {
         builder.set_srcloc(cur_srcloc(reader));
         let (count, ty) = reader.read_local_decl(&mut locals_total).map_err(|_| {
-            CtonError::InvalidInput
+            CodegenError::InvalidInput
         })?;
         declare_locals(builder, count, ty, &mut next_local);
     }
@@ -189,14 +193,14 @@ fn parse_function_body<FE: FuncEnvironment + ?Sized>(
     builder: &mut FunctionBuilder<Variable>,
     state: &mut TranslationState,
     environ: &mut FE,
-) -> CtonResult {
+) -> CodegenResult {
     // The control stack is initialized with a single block representing the whole function.
     debug_assert_eq!(state.control_stack.len(), 1, "State not initialized");
 
     // Keep going until the final `End` operator which pops the outermost block.
     while !state.control_stack.is_empty() {
         builder.set_srcloc(cur_srcloc(&reader));
-        let op = reader.read_operator().map_err(|_| CtonError::InvalidInput)?;
+        let op = reader.read_operator().map_err(|_| CodegenError::InvalidInput)?;
         translate_operator(op, builder, state, environ);
     }
 
@@ -263,7 +267,7 @@ mod tests {
         trans
             .translate(&BODY, &mut ctx.func, &mut runtime.func_env())
             .unwrap();
-        dbg!("{}", ctx.func.display(None));
+        debug!("{}", ctx.func.display(None));
         ctx.verify(runtime.func_env().flags()).unwrap();
     }
 
@@ -294,8 +298,15 @@ mod tests {
         trans
             .translate(&BODY, &mut ctx.func, &mut runtime.func_env())
             .unwrap();
-        dbg!("{}", ctx.func.display(None));
+        debug!("{}", ctx.func.display(None));
         ctx.verify(runtime.func_env().flags()).unwrap();
+
+        // Translate the function again into the same function, reusing its memory.
+        let first = ctx.func.to_string();
+        trans
+            .translate(&BODY, &mut ctx.func, &mut runtime.func_env())
+            .unwrap();
+        assert_eq!(ctx.func.to_string(), first);
     }
 
     #[test]
@@ -333,7 +344,7 @@ mod tests {
         trans
             .translate(&BODY, &mut ctx.func, &mut runtime.func_env())
             .unwrap();
-        dbg!("{}", ctx.func.display(None));
+        debug!("{}", ctx.func.display(None));
         ctx.verify(runtime.func_env().flags()).unwrap();
     }
 }
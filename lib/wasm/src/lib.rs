@@ -15,8 +15,9 @@
 
 extern crate wasmparser;
 extern crate cton_frontend;
-#[macro_use(dbg)]
 extern crate cretonne;
+#[macro_use]
+extern crate log;
 
 mod code_translator;
 mod func_translator;
@@ -27,7 +28,7 @@ mod state;
 mod translation_utils;
 
 pub use func_translator::FuncTranslator;
-pub use module_translator::translate_module;
+pub use module_translator::{translate_module, translate_module_with_observer};
 pub use environ::{FuncEnvironment, ModuleEnvironment, DummyEnvironment, GlobalValue};
 pub use translation_utils::{FunctionIndex, GlobalIndex, TableIndex, MemoryIndex, SignatureIndex,
                             Global, GlobalInit, Table, Memory};
@@ -1,6 +1,8 @@
 //! Translation skeleton that traverses the whole WebAssembly module and call helper functions
 //! to deal with each part of it.
+use cretonne::observer::{CompileObserver, Event, Pass};
 use cretonne::timing;
+use std::time::Instant;
 use wasmparser::{ParserState, SectionCode, ParserInput, Parser, WasmDecoder, BinaryReaderError};
 use sections_translator::{SectionParsingError, parse_function_signatures, parse_import_section,
                           parse_function_section, parse_export_section, parse_start_section,
@@ -15,6 +17,33 @@ use environ::ModuleEnvironment;
 pub fn translate_module<'data>(
     data: &'data [u8],
     environ: &mut ModuleEnvironment<'data>,
+) -> Result<(), String> {
+    translate(data, environ, None)
+}
+
+/// Translate a Wasm binary like `translate_module`, and report the progress to `observer`.
+///
+/// The observer receives the start and end of the `WasmTranslateModule` pass, and an event for
+/// every function body handed to the environment.
+pub fn translate_module_with_observer<'data>(
+    data: &'data [u8],
+    environ: &mut ModuleEnvironment<'data>,
+    observer: &mut CompileObserver,
+) -> Result<(), String> {
+    observer.event(&Event::PassStarted(Pass::WasmTranslateModule));
+    let started = Instant::now();
+    let result = translate(data, environ, Some(&mut *observer));
+    observer.event(&Event::PassFinished {
+        pass: Pass::WasmTranslateModule,
+        duration: started.elapsed(),
+    });
+    result
+}
+
+fn translate<'data>(
+    data: &'data [u8],
+    environ: &mut ModuleEnvironment<'data>,
+    mut observer: Option<&mut CompileObserver>,
 ) -> Result<(), String> {
     let _tt = timing::wasm_translate_module();
     let mut parser = Parser::new(data);
@@ -132,6 +161,7 @@ pub fn translate_module<'data>(
         };
     }
     // At this point we've entered the code section
+    let mut index = 0;
     loop {
         match *parser.read() {
             ParserState::BeginFunctionBody { .. } => {}
@@ -140,11 +170,19 @@ pub fn translate_module<'data>(
         }
         let mut reader = parser.create_binary_reader();
         let size = reader.bytes_remaining();
-        environ.define_function_body(
-            reader.read_bytes(size).map_err(|e| {
-                format!("at offset {}: {}", e.offset, e.message)
-            })?,
-        )?;
+        let body = reader.read_bytes(size).map_err(|e| {
+            format!("at offset {}: {}", e.offset, e.message)
+        })?;
+        let started = Instant::now();
+        environ.define_function_body(body)?;
+        if let Some(ref mut observer) = observer {
+            observer.event(&Event::WasmFunctionBody {
+                index,
+                body_size: size,
+                duration: started.elapsed(),
+            });
+        }
+        index += 1;
     }
     loop {
         match *parser.read() {
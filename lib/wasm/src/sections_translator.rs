@@ -79,34 +79,51 @@ pub fn parse_import_section<'data>(
                                                    limits: ref memlimits,
                                                    shared,
                                                }),
-                ..
+                module,
+                field,
             } => {
-                environ.declare_memory(Memory {
-                    pages_count: memlimits.initial as usize,
-                    maximum: memlimits.maximum.map(|x| x as usize),
-                    shared,
-                });
+                environ.declare_memory_import(
+                    Memory {
+                        pages_count: memlimits.initial as usize,
+                        maximum: memlimits.maximum.map(|x| x as usize),
+                        shared,
+                    },
+                    from_utf8(module).unwrap(),
+                    from_utf8(field).unwrap(),
+                );
             }
             ParserState::ImportSectionEntry {
-                ty: ImportSectionEntryType::Global(ref ty), ..
+                ty: ImportSectionEntryType::Global(ref ty),
+                module,
+                field,
             } => {
-                environ.declare_global(Global {
-                    ty: type_to_type(&ty.content_type).unwrap(),
-                    mutability: ty.mutable,
-                    initializer: GlobalInit::Import(),
-                });
+                environ.declare_global_import(
+                    Global {
+                        ty: type_to_type(&ty.content_type).unwrap(),
+                        mutability: ty.mutable,
+                        initializer: GlobalInit::Import(),
+                    },
+                    from_utf8(module).unwrap(),
+                    from_utf8(field).unwrap(),
+                );
             }
             ParserState::ImportSectionEntry {
-                ty: ImportSectionEntryType::Table(ref tab), ..
+                ty: ImportSectionEntryType::Table(ref tab),
+                module,
+                field,
             } => {
-                environ.declare_table(Table {
-                    ty: match type_to_type(&tab.element_type) {
-                        Ok(t) => TableElementType::Val(t),
-                        Err(()) => TableElementType::Func(),
+                environ.declare_table_import(
+                    Table {
+                        ty: match type_to_type(&tab.element_type) {
+                            Ok(t) => TableElementType::Val(t),
+                            Err(()) => TableElementType::Func(),
+                        },
+                        size: tab.limits.initial as usize,
+                        maximum: tab.limits.maximum.map(|x| x as usize),
                     },
-                    size: tab.limits.initial as usize,
-                    maximum: tab.limits.maximum.map(|x| x as usize),
-                })
+                    from_utf8(module).unwrap(),
+                    from_utf8(field).unwrap(),
+                )
             }
             ParserState::EndSection => break,
             ref s => return Err(SectionParsingError::WrongSectionContent(format!("{:?}", s))),
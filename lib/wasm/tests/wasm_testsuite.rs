@@ -2,7 +2,7 @@ extern crate cton_wasm;
 extern crate cretonne;
 extern crate tempdir;
 
-use cton_wasm::{translate_module, DummyEnvironment};
+use cton_wasm::{translate_module, translate_module_with_observer, DummyEnvironment};
 use std::path::PathBuf;
 use std::fs::File;
 use std::error::Error;
@@ -11,6 +11,7 @@ use std::str;
 use std::io::prelude::*;
 use std::process::Command;
 use std::fs;
+use cretonne::observer::{CompileObserver, Event, Pass};
 use cretonne::settings::{self, Configurable, Flags};
 use cretonne::verifier;
 use cretonne::print_errors::pretty_verifier_error;
@@ -107,3 +108,66 @@ fn handle_module(path: PathBuf, flags: &Flags) {
             .unwrap();
     }
 }
+
+#[test]
+fn select_function() {
+    // A module with two functions returning 1 and 2, where the second one is exported as
+    // "second".
+    let data = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        // Type section: () -> i32.
+        0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f,
+        // Function section.
+        0x03, 0x03, 0x02, 0x00, 0x00,
+        // Export section.
+        0x07, 0x0a, 0x01, 0x06, b's', b'e', b'c', b'o', b'n', b'd', 0x00, 0x01,
+        // Code section.
+        0x0a, 0x0b, 0x02, 0x04, 0x00, 0x41, 0x01, 0x0b, 0x04, 0x00, 0x41, 0x02, 0x0b,
+    ];
+    let flags = Flags::new(&settings::builder());
+    for selector in &["1", "second"] {
+        let mut dummy_environ = DummyEnvironment::with_flags(flags.clone());
+        dummy_environ.select_function(selector);
+        translate_module(&data, &mut dummy_environ).unwrap();
+        assert!(!dummy_environ.is_selected(0));
+        assert!(dummy_environ.is_selected(1));
+        let bodies = &dummy_environ.info.function_bodies;
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0].layout.entry_block(), None);
+        assert!(bodies[1].layout.entry_block().is_some());
+    }
+}
+
+/// An observer that records the function body events.
+struct Bodies(Vec<(usize, usize)>, usize);
+
+impl CompileObserver for Bodies {
+    fn event(&mut self, event: &Event) {
+        match *event {
+            Event::WasmFunctionBody { index, body_size, .. } => self.0.push((index, body_size)),
+            Event::PassStarted(Pass::WasmTranslateModule) |
+            Event::PassFinished { pass: Pass::WasmTranslateModule, .. } => self.1 += 1,
+            _ => panic!("unexpected event {:?}", event),
+        }
+    }
+}
+
+#[test]
+fn observe_translation() {
+    // A module with two functions returning 1 and 2.
+    let data = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        // Type section: () -> i32.
+        0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f,
+        // Function section.
+        0x03, 0x03, 0x02, 0x00, 0x00,
+        // Code section.
+        0x0a, 0x0b, 0x02, 0x04, 0x00, 0x41, 0x01, 0x0b, 0x04, 0x00, 0x41, 0x02, 0x0b,
+    ];
+    let mut dummy_environ = DummyEnvironment::with_flags(Flags::new(&settings::builder()));
+    let mut observer = Bodies(Vec::new(), 0);
+    translate_module_with_observer(&data, &mut dummy_environ, &mut observer).unwrap();
+    assert_eq!(observer.0, [(0, 4), (1, 4)]);
+    assert_eq!(observer.1, 2);
+    assert_eq!(dummy_environ.info.function_bodies.len(), 2);
+}
@@ -27,7 +27,7 @@ cargo update
 
 echo git commit -a -m "\"Bump version to $version"\"
 echo git push
-for crate in cretonne frontend native reader wasm; do
+for crate in cretonne frontend native reader wasm module object simplejit interpreter; do
     echo cargo publish --manifest-path "lib/$crate/Cargo.toml"
 done
 echo
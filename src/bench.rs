@@ -0,0 +1,304 @@
+//! CLI tool to measure how fast a corpus of files compiles.
+//!
+//! Compiles all the `.cton`, `.wasm`, and `.wat` files in the given files and directories over
+//! and over, and reports the time per iteration, the compilation throughput, and the time spent in
+//! each pass. The first iterations warm up the caches and the allocator, and aren't measured.
+//!
+//! The files are read and parsed once. The WebAssembly modules are translated in every iteration,
+//! so the translation is included in the measurements. The verifier is disabled unless it is
+//! enabled with `--set enable_verifier=true`.
+
+use cretonne::Context;
+use cretonne::ir::Function;
+use cretonne::isa::TargetIsa;
+use cretonne::print_errors::pretty_error;
+use cretonne::settings::FlagsOrIsa;
+use cretonne::timing;
+use cton_reader::{parse_test, IsaSpec};
+use cton_wasm::{translate_module, DummyEnvironment};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use utils::{self, parse_sets_and_isa, read_input};
+
+/// The code to compile in each iteration.
+enum Source {
+    /// The functions of a `.cton` file.
+    Functions(Vec<Function>),
+    /// A WebAssembly module in binary form.
+    Wasm(Vec<u8>),
+}
+
+/// A file of the corpus.
+struct Input {
+    name: String,
+    /// The ISA of a `.cton` file with a single `isa` command.
+    isa: Option<Box<TargetIsa>>,
+    source: Source,
+}
+
+/// The amount of code compiled in one iteration.
+#[derive(Default)]
+struct Totals {
+    functions: u64,
+    insts: u64,
+    code_bytes: u64,
+}
+
+/// Summary statistics of a sample of measurements.
+struct Summary {
+    mean: f64,
+    median: f64,
+    min: f64,
+    max: f64,
+    std_dev: f64,
+}
+
+impl Summary {
+    fn new(samples: &[f64]) -> Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let n = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let median = if n % 2 == 0 {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+        } else {
+            sorted[n / 2]
+        };
+        // The sample standard deviation, which is zero for a single sample.
+        let variance = if n > 1 {
+            sorted.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1) as f64
+        } else {
+            0.0
+        };
+        Self {
+            mean,
+            median,
+            min: sorted[0],
+            max: sorted[n - 1],
+            std_dev: variance.sqrt(),
+        }
+    }
+}
+
+/// The time samples of one pass, in milliseconds.
+struct PassSamples {
+    desc: &'static str,
+    total: Vec<f64>,
+    self_time: Vec<f64>,
+}
+
+pub fn run(
+    files: Vec<String>,
+    flag_set: Vec<String>,
+    flag_isa: String,
+    flag_iterations: usize,
+    flag_warmup: usize,
+    flag_verbose: bool,
+) -> Result<(), String> {
+    if flag_iterations == 0 {
+        return Err(String::from("at least one iteration is required"));
+    }
+    let mut flag_set = flag_set;
+    flag_set.insert(0, String::from("enable_verifier=false"));
+    let parsed = parse_sets_and_isa(flag_set, flag_isa)?;
+    let fisa = parsed.as_fisa();
+
+    let mut paths = Vec::new();
+    for file in files {
+        collect(Path::new(&file), &mut paths)?;
+    }
+    if paths.is_empty() {
+        return Err(String::from("no .cton, .wasm, or .wat files to compile"));
+    }
+    let mut inputs = Vec::new();
+    for path in &paths {
+        if flag_verbose {
+            println!("Reading {}", path.display());
+        }
+        inputs.push(load(path)?);
+    }
+
+    for _ in 0..flag_warmup {
+        compile_corpus(&inputs, &fisa)?;
+    }
+    timing::take_current();
+
+    let mut totals = Totals::default();
+    let mut times = Vec::new();
+    let mut passes: Vec<PassSamples> = Vec::new();
+    for iteration in 0..flag_iterations {
+        let started = Instant::now();
+        totals = compile_corpus(&inputs, &fisa)?;
+        let elapsed = millis(started.elapsed());
+        if flag_verbose {
+            println!("Iteration {}: {:.3} ms", iteration + 1, elapsed);
+        }
+        times.push(elapsed);
+
+        for (desc, total, self_time) in timing::take_current().passes() {
+            let index = match passes.iter().position(|pass| pass.desc == desc) {
+                Some(index) => index,
+                None => {
+                    passes.push(PassSamples {
+                        desc,
+                        total: Vec::new(),
+                        self_time: Vec::new(),
+                    });
+                    passes.len() - 1
+                }
+            };
+            passes[index].total.push(millis(total));
+            passes[index].self_time.push(millis(self_time));
+        }
+    }
+
+    println!(
+        "Compiled {} files: {} functions, {} instructions, {} bytes of code",
+        inputs.len(),
+        totals.functions,
+        totals.insts,
+        totals.code_bytes
+    );
+    println!(
+        "{} iterations after {} warmup iterations",
+        flag_iterations,
+        flag_warmup
+    );
+
+    let time = Summary::new(&times);
+    println!(
+        "Time per iteration: mean {:.3} ms, median {:.3} ms, min {:.3} ms, max {:.3} ms, \
+         std dev {:.3} ms ({:.1}%)",
+        time.mean,
+        time.median,
+        time.min,
+        time.max,
+        time.std_dev,
+        100.0 * time.std_dev / time.mean
+    );
+    let per_second = 1000.0 / time.mean;
+    println!(
+        "Throughput: {:.0} functions/s, {:.0} instructions/s, {:.1} KB/s of code",
+        totals.functions as f64 * per_second,
+        totals.insts as f64 * per_second,
+        totals.code_bytes as f64 * per_second / 1024.0
+    );
+
+    println!();
+    println!("======== ======== ======== ========  ==================================");
+    println!("    Mean   Median  Std dev     Self  Pass (mean ms per iteration)");
+    println!("-------- -------- -------- --------  ----------------------------------");
+    for pass in &passes {
+        let total = Summary::new(&pass.total);
+        let self_time = Summary::new(&pass.self_time);
+        println!(
+            "{:8.3} {:8.3} {:8.3} {:8.3}  {}",
+            total.mean,
+            total.median,
+            total.std_dev,
+            self_time.mean,
+            pass.desc
+        );
+    }
+    println!("======== ======== ======== ========  ==================================");
+    Ok(())
+}
+
+/// Convert a duration to milliseconds.
+fn millis(dur: Duration) -> f64 {
+    dur.as_secs() as f64 * 1000.0 + f64::from(dur.subsec_nanos()) / 1_000_000.0
+}
+
+/// Does `path` name a file that can be compiled?
+fn is_input(path: &Path) -> bool {
+    match path.extension().and_then(OsStr::to_str) {
+        Some("cton") | Some("wasm") | Some("wat") => true,
+        _ => false,
+    }
+}
+
+/// Add the file `path`, or the files to compile in the directory `path` and its subdirectories,
+/// to `paths`.
+fn collect(path: &Path, paths: &mut Vec<PathBuf>) -> Result<(), String> {
+    if !path.is_dir() {
+        paths.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(path).map_err(
+        |e| format!("{}: {}", path.display(), e),
+    )?
+    {
+        entries.push(entry.map_err(|e| format!("{}: {}", path.display(), e))?.path());
+    }
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() || is_input(&entry) {
+            collect(&entry, paths)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read and parse the file at `path`.
+fn load(path: &Path) -> Result<Input, String> {
+    let name = path.display().to_string();
+    let (isa, source) = match read_input(path)? {
+        utils::Input::Wasm(data) => (None, Source::Wasm(data)),
+        utils::Input::Cton(text) => {
+            let test_file = parse_test(&text).map_err(|e| e.diagnostic(&name, &text))?;
+            let isa = match test_file.isa_spec {
+                IsaSpec::Some(mut isas) => if isas.len() == 1 { isas.pop() } else { None },
+                IsaSpec::None(_) => None,
+            };
+            let funcs = test_file.functions.into_iter().map(|(func, _)| func).collect();
+            (isa, Source::Functions(funcs))
+        }
+    };
+    Ok(Input { name, isa, source })
+}
+
+/// Compile all the inputs once.
+fn compile_corpus(inputs: &[Input], fisa: &FlagsOrIsa) -> Result<Totals, String> {
+    let mut totals = Totals::default();
+    let mut context = Context::new();
+    for input in inputs {
+        // An ISA given on the command line takes precedence over the ISA of the file.
+        let isa = match fisa.isa.or_else(|| input.isa.as_ref().map(|isa| &**isa)) {
+            Some(isa) => isa,
+            None => return Err(format!("{}: compilation requires a target isa", input.name)),
+        };
+        let funcs = match input.source {
+            Source::Functions(ref funcs) => funcs.clone(),
+            Source::Wasm(ref data) => {
+                let mut environ = DummyEnvironment::with_flags(isa.flags().clone());
+                translate_module(data, &mut environ).map_err(|e| {
+                    format!("{}: {}", input.name, e)
+                })?;
+                environ.info.function_bodies
+            }
+        };
+        for func in funcs {
+            totals.functions += 1;
+            for ebb in func.layout.ebbs() {
+                totals.insts += func.layout.ebb_insts(ebb).count() as u64;
+            }
+            context.reset(func);
+            let code_size = context.compile(isa).map_err(|err| {
+                format!(
+                    "{}: {}",
+                    input.name,
+                    pretty_error(&context.func, Some(isa), err)
+                )
+            })?;
+            let mut code = vec![0; code_size as usize];
+            context.emit_to_slice(&mut code, isa).map_err(|err| {
+                format!("{}: {}", input.name, err)
+            })?;
+            totals.code_bytes += u64::from(code_size);
+        }
+    }
+    Ok(totals)
+}
@@ -2,34 +2,44 @@
 //!
 //! Read a sequence of Cretonne IL files and print them again to stdout. This has the effect of
 //! normalizing formatting and removing comments.
+//!
+//! With `--json`, the functions are printed in the JSON format of `cretonne::json` instead, one
+//! function per line, for consumption by external tools.
 
+use cretonne::json::write_function as write_json;
 use cton_reader::parse_functions;
 use CommandResult;
-use utils::read_to_string;
+use utils::read_cton;
 
-pub fn run(files: Vec<String>) -> CommandResult {
+pub fn run(files: Vec<String>, json: bool) -> CommandResult {
     for (i, f) in files.into_iter().enumerate() {
-        if i != 0 {
+        if i != 0 && !json {
             println!();
         }
-        cat_one(f)?
+        cat_one(f, json)?
     }
     Ok(())
 }
 
-fn cat_one(filename: String) -> CommandResult {
-    let buffer = read_to_string(&filename).map_err(
-        |e| format!("{}: {}", filename, e),
-    )?;
+fn cat_one(filename: String, json: bool) -> CommandResult {
+    let buffer = read_cton(&filename)?;
     let items = parse_functions(&buffer).map_err(
-        |e| format!("{}: {}", filename, e),
+        |e| e.diagnostic(&filename, &buffer),
     )?;
 
     for (idx, func) in items.into_iter().enumerate() {
+        if json {
+            let mut text = String::new();
+            write_json(&mut text, &func, None).map_err(
+                |e| format!("{}: {}", filename, e),
+            )?;
+            println!("{}", text);
+            continue;
+        }
         if idx != 0 {
             println!();
         }
-        print!("{}", func);
+        print!("{}", func.display_stable(None));
     }
 
     Ok(())
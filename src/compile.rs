@@ -1,30 +1,41 @@
 //! CLI tool to compile cretonne IL into native code.
 //!
-//! Reads IR files into Cretonne IL and compiles it.
+//! Reads IR files into Cretonne IL and compiles it. The machine code of the compiled functions
+//! can be written as a hexdump, as raw bytes, or as a relocatable object file.
+//!
+//! WebAssembly modules are compiled too, after translating them with the dummy environment of
+//! `cton_wasm`.
 
 use cton_reader::parse_test;
 use std::path::PathBuf;
 use cretonne::Context;
+use cretonne::isa::TargetIsa;
 use cretonne::settings::FlagsOrIsa;
 use cretonne::{binemit, ir};
 use cretonne::print_errors::pretty_error;
+use cton_object::{default_symbol_name, Format, Linkage, ObjectBuilder, RelocCollector};
+use cton_wasm::{translate_module, DummyEnvironment};
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, Write};
 use std::path::Path;
-use utils::{read_to_string, parse_sets_and_isa};
+use utils::{parse_sets_and_isa, read_input, Input};
 
-struct PrintRelocs {
-    flag_print: bool,
+/// A `RelocSink` that describes the relocations in text.
+struct RelocList {
+    relocs: Vec<String>,
 }
 
-impl binemit::RelocSink for PrintRelocs {
+impl binemit::RelocSink for RelocList {
     fn reloc_ebb(
         &mut self,
         where_: binemit::CodeOffset,
         r: binemit::Reloc,
         offset: binemit::CodeOffset,
     ) {
-        if self.flag_print {
-            println!("reloc_ebb: {} {} at {}", r, offset, where_);
-        }
+        self.relocs.push(
+            format!("reloc_ebb: {} {} at {}", r, offset, where_),
+        );
     }
 
     fn reloc_external(
@@ -34,14 +45,144 @@ impl binemit::RelocSink for PrintRelocs {
         name: &ir::ExternalName,
         addend: binemit::Addend,
     ) {
-        if self.flag_print {
-            println!("reloc_ebb: {} {} {} at {}", r, name, addend, where_);
-        }
+        self.relocs.push(format!(
+            "reloc_external: {} {} {} at {}",
+            r,
+            name,
+            addend,
+            where_
+        ));
     }
 
     fn reloc_jt(&mut self, where_: binemit::CodeOffset, r: binemit::Reloc, jt: ir::JumpTable) {
-        if self.flag_print {
-            println!("reloc_ebb: {} {} at {}", r, jt, where_);
+        self.relocs.push(
+            format!("reloc_jt: {} {} at {}", r, jt, where_),
+        );
+    }
+}
+
+/// How the machine code of the compiled functions is written.
+enum Emit {
+    /// The machine code isn't written.
+    Nothing,
+    /// A hexdump of each function, followed by its relocations.
+    Hex,
+    /// The machine code of all the functions, one after the other. Relocations are not applied.
+    Raw,
+    /// A relocatable object file with an exported symbol per function.
+    Object(Format),
+}
+
+impl Emit {
+    fn parse(emit: Option<String>, object_format: Option<String>) -> Result<Emit, String> {
+        let format = match object_format.as_ref().map(String::as_str) {
+            None | Some("elf") => Format::Elf,
+            Some("macho") => Format::MachO,
+            Some("coff") => Format::Coff,
+            Some(other) => return Err(format!("unknown object format '{}'", other)),
+        };
+        match emit.as_ref().map(String::as_str) {
+            None => Ok(Emit::Nothing),
+            Some("hex") => Ok(Emit::Hex),
+            Some("raw") => Ok(Emit::Raw),
+            Some("obj") => Ok(Emit::Object(format)),
+            Some(other) => Err(format!("unknown output kind '{}'", other)),
+        }
+    }
+}
+
+/// The machine code of the functions compiled so far, in the form selected by `Emit`.
+struct Output {
+    emit: Emit,
+    bytes: Vec<u8>,
+    object: Option<ObjectBuilder>,
+}
+
+impl Output {
+    fn new(emit: Emit) -> Self {
+        Self {
+            emit,
+            bytes: Vec::new(),
+            object: None,
+        }
+    }
+
+    /// Add the function in `context`, whose machine code is `code`.
+    fn add_function(
+        &mut self,
+        context: &Context,
+        isa: &TargetIsa,
+        code: &[u8],
+        relocs: &RelocList,
+    ) -> Result<(), String> {
+        match self.emit {
+            Emit::Nothing => {}
+            Emit::Hex => {
+                let mut text = String::new();
+                writeln!(text, "{}: {} bytes", context.func.name, code.len()).unwrap();
+                for (line, chunk) in code.chunks(16).enumerate() {
+                    write!(text, "{:08x}:", line * 16).unwrap();
+                    for byte in chunk {
+                        write!(text, " {:02x}", byte).unwrap();
+                    }
+                    writeln!(text).unwrap();
+                }
+                for reloc in &relocs.relocs {
+                    writeln!(text, "{}", reloc).unwrap();
+                }
+                self.bytes.extend_from_slice(text.as_bytes());
+            }
+            Emit::Raw => self.bytes.extend_from_slice(code),
+            Emit::Object(format) => {
+                if self.object.is_none() {
+                    self.object = Some(ObjectBuilder::new(isa, format).map_err(
+                        |e| e.to_string(),
+                    )?);
+                }
+                let name = default_symbol_name(&context.func.name);
+                let mut code = vec![0; code.len()];
+                let mut collector = RelocCollector::new(&default_symbol_name);
+                context.emit_to_memory(code.as_mut_ptr(), &mut collector, isa);
+                if collector.local_relocs != 0 {
+                    return Err(format!(
+                        "{} contains EBB or jump table relocations, which can't be written to \
+                         an object file",
+                        context.func.name
+                    ));
+                }
+                if let Some(ref mut object) = self.object {
+                    object
+                        .define_function(&name, Linkage::Export, &code, collector.relocs)
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the output to the file `path`, or to stdout.
+    fn write(self, path: Option<String>) -> Result<(), String> {
+        let bytes = match self.emit {
+            Emit::Nothing => return Ok(()),
+            Emit::Object(_) => {
+                match self.object {
+                    Some(object) => object.finish().map_err(|e| e.to_string())?,
+                    None => return Err(String::from("no functions to write")),
+                }
+            }
+            Emit::Hex | Emit::Raw => self.bytes,
+        };
+        match path {
+            Some(path) => {
+                File::create(&path)
+                    .and_then(|mut file| file.write_all(&bytes))
+                    .map_err(|e| format!("{}: {}", path, e))
+            }
+            None => {
+                let stdout = io::stdout();
+                let mut handle = stdout.lock();
+                handle.write_all(&bytes).map_err(|e| e.to_string())
+            }
         }
     }
 }
@@ -49,29 +190,59 @@ impl binemit::RelocSink for PrintRelocs {
 pub fn run(
     files: Vec<String>,
     flag_print: bool,
+    flag_stats: bool,
     flag_set: Vec<String>,
     flag_isa: String,
+    flag_emit: Option<String>,
+    flag_object_format: Option<String>,
+    flag_output: Option<String>,
 ) -> Result<(), String> {
     let parsed = parse_sets_and_isa(flag_set, flag_isa)?;
+    let mut output = Output::new(Emit::parse(flag_emit, flag_object_format)?);
 
     for filename in files {
         let path = Path::new(&filename);
         let name = String::from(path.as_os_str().to_string_lossy());
-        handle_module(flag_print, path.to_path_buf(), name, parsed.as_fisa())?;
+        handle_module(
+            flag_print,
+            flag_stats,
+            path.to_path_buf(),
+            name,
+            parsed.as_fisa(),
+            &mut output,
+        )?;
     }
-    Ok(())
+    output.write(flag_output)
 }
 
 fn handle_module(
     flag_print: bool,
+    flag_stats: bool,
     path: PathBuf,
     name: String,
     fisa: FlagsOrIsa,
+    output: &mut Output,
 ) -> Result<(), String> {
-    let buffer = read_to_string(&path).map_err(
-        |e| format!("{}: {}", name, e),
-    )?;
-    let test_file = parse_test(&buffer).map_err(|e| format!("{}: {}", name, e))?;
+    let buffer = match read_input(&path)? {
+        Input::Cton(text) => text,
+        Input::Wasm(data) => {
+            let isa = fisa.isa.ok_or_else(
+                || String::from("compilation requires a target isa"),
+            )?;
+            let mut environ = DummyEnvironment::with_flags(isa.flags().clone());
+            translate_module(&data, &mut environ).map_err(
+                |e| format!("{}: {}", name, e),
+            )?;
+            return compile_functions(
+                flag_print,
+                flag_stats,
+                environ.info.function_bodies,
+                isa,
+                output,
+            );
+        }
+    };
+    let test_file = parse_test(&buffer).map_err(|e| e.diagnostic(&name, &buffer))?;
 
     // If we have an isa from the command-line, use that. Otherwise if the
     // file contains a unique isa, use that.
@@ -82,10 +253,24 @@ fn handle_module(
     } else {
         return Err(String::from("compilation requires a target isa"));
     };
+    let funcs = test_file.functions.into_iter().map(|(func, _)| func).collect();
+    compile_functions(flag_print, flag_stats, funcs, isa, output)
+}
 
-    for (func, _) in test_file.functions {
-        let mut context = Context::new();
-        context.func = func;
+/// Compile `funcs` for `isa`, and add their machine code to `output`.
+fn compile_functions(
+    flag_print: bool,
+    flag_stats: bool,
+    funcs: Vec<ir::Function>,
+    isa: &TargetIsa,
+    output: &mut Output,
+) -> Result<(), String> {
+    let mut context = Context::new();
+    if flag_stats {
+        context.enable_stats();
+    }
+    for func in funcs {
+        context.reset(func);
         let size = context.compile(isa).map_err(|err| {
             pretty_error(&context.func, Some(isa), err)
         })?;
@@ -95,11 +280,14 @@ fn handle_module(
 
         // Encode the result as machine code.
         let mut mem = Vec::new();
-        let mut relocs = PrintRelocs { flag_print };
+        let mut relocs = RelocList { relocs: Vec::new() };
         mem.resize(size as usize, 0);
         context.emit_to_memory(mem.as_mut_ptr(), &mut relocs, &*isa);
 
         if flag_print {
+            for reloc in &relocs.relocs {
+                println!("{}", reloc);
+            }
             print!(".byte ");
             let mut first = true;
             for byte in &mem {
@@ -112,7 +300,12 @@ fn handle_module(
             }
             println!();
         }
+
+        output.add_function(&context, isa, &mem, &relocs)?;
     }
 
+    if let Some(stats) = context.stats() {
+        print!("{}", stats);
+    }
     Ok(())
 }
@@ -2,9 +2,11 @@ extern crate cretonne;
 extern crate cton_reader;
 extern crate cton_wasm;
 extern crate cton_filetests;
+extern crate cton_object;
 extern crate docopt;
 #[macro_use]
 extern crate serde_derive;
+extern crate file_per_thread_logger;
 extern crate filecheck;
 extern crate tempdir;
 extern crate term;
@@ -16,21 +18,46 @@ use std::process;
 
 mod utils;
 mod cat;
+mod diff;
+mod fmt;
 mod print_cfg;
 mod rsfilecheck;
 mod wasm;
 mod compile;
+mod bench;
+mod generate;
 
 const USAGE: &str = "
 Cretonne code generator utility
 
+Input files can be Cretonne IL, or WebAssembly modules in binary or text format, which are told
+apart by their contents and extension. The file - reads stdin.
+
+The RUST_LOG environment variable enables the compiler traces, which are written to a
+cretonne.dbg.* file per thread. RUST_LOG=cretonne::regalloc=trace traces the register allocator.
+
 Usage:
-    cton-util test [-vT] <file>...
-    cton-util cat <file>...
+    cton-util test [-vTwb] [-j <n>] [--filter <pat>] [--command <cmd>] [--timeout <s>]
+                   [--report <file>] [--timings <file>] [--slow-threshold <s>]
+                   [--shuffle] [--seed <n>] [--no-cache] [--fail-fast] [--failed] <file>...
+    cton-util mutate [-v] [--mutants <n>] [--seed <n>] <file>...
+    cton-util generate [-v] [--functions <n>] [--seed <n>] [--types <types>] [--branches <n>]
+                       [--calls <n>] [--set <set>]... <isa>...
+    cton-util differential [-v] [--functions <n>] [--inputs <n>] [--seed <n>] [--types <types>]
+                           [--branches <n>] [--calls <n>]
+    cton-util run [--invoke <name>] <file> [--] [<arg>...]
+    cton-util interpret [--invoke <name>] [--fuel <n>] <file> [--] [<arg>...]
+    cton-util cat [--json] <file>...
+    cton-util fmt [--check] <file>...
     cton-util filecheck [-v] <file>
-    cton-util print-cfg <file>...
-    cton-util compile [-vpT] [--set <set>]... [--isa <isa>] <file>...
-    cton-util wasm [-ctvpTs] [--set <set>]... [--isa <isa>] <file>...
+    cton-util diff <file>...
+    cton-util print-cfg [--domtree] [--loops] [--live] [--text] <file>...
+    cton-util compile [-vpT] [--stats] [--set <set>]... [--isa <isa> | --target <isa>]
+                      [--emit <kind>] [--object-format <fmt>] [-o <file>] <file>...
+    cton-util wasm [-ctvpTsO] [--stats] [--set <set>]... [--isa <isa> | --target <isa>]
+                   [--function <func>] [-o <file>] <file>...
+    cton-util bench [-v] [--iterations <n>] [--warmup <n>] [--set <set>]...
+                    [--isa <isa> | --target <isa>] <file>...
     cton-util --help | --version
 
 Options:
@@ -44,9 +71,62 @@ Options:
     -c, --check-translation
                     just checks the correctness of Cretonne IL translated from WebAssembly
     -p, --print     print the resulting Cretonne IL
+    -O, --optimize  enable the optimization passes, like --set opt_level=best
+    --stats         print IR statistics of the compilation stages
+    --domtree       show the dominator tree in the CFG
+    --loops         show the loop nesting in the CFG
+    --live          show the number of live values per EBB in the CFG
+    --text          print the CFG, dominator tree, and loops as text instead of graphviz
+    --check         report unformatted files instead of rewriting them
+    --json          print the functions as JSON, one object per line
+    -j, --jobs=<n>  run <n> tests in parallel, or one at a time with -j 1
+    --filter=<pat>  only run the tests whose path matches <pat>
+    --command=<cmd>
+                    only run the tests using the test command <cmd>, like legalizer
+    --timeout=<s>   fail tests that run for more than <s> seconds
+    --report=<file>
+                    write the test results to a JSON file, or JUnit XML for .xml
+    --timings=<file>
+                    write the duration of each test to a CSV file
+    --slow-threshold=<s>
+                    report the tests that run for more than <s> seconds as slow
+    -w, --watch     rerun tests when they change, and everything when cton-util is rebuilt
+    -b, --bless     rewrite the filecheck directives of failing tests to match their output
+    --shuffle       run the tests in a random order
+    --seed=<n>      seed the random order of the tests, the mutations, or the generated
+                    functions with <n>
+    --mutants=<n>   number of mutants of each function, 100 by default
+    --functions=<n>
+                    number of functions to generate, 1000 by default
+    --inputs=<n>    number of random arguments to call each function with, 10 by default
+    --types=<types>
+                    comma separated types of the generated values, like i32,i64,f64
+    --branches=<n>  percentage of generated statements that are conditionals, switches, or loops
+    --calls=<n>     percentage of generated statements that are calls
+    --iterations=<n>
+                    number of measured iterations, 10 by default
+    --warmup=<n>    number of iterations to run before measuring, 3 by default
+    --function=<func>
+                    only translate and compile the WebAssembly function with the index or
+                    export name <func>
+    --invoke=<name>
+                    name of the function to run, main by default
+    --fuel=<n>      stop interpreting after <n> instructions
+    --no-cache      also run the tests that passed last time and haven't changed since
+    --fail-fast     stop running tests after the first failure
+    --failed        only run the tests that failed last time
     -h, --help      print this help message
     --set=<set>     configure Cretonne settings
     --isa=<isa>     specify the Cretonne ISA
+    --target=<isa>  specify the Cretonne ISA, like --isa
+    --emit=<kind>   write the machine code as a hexdump (hex), as raw bytes (raw), or as an
+                    object file (obj)
+    --object-format=<fmt>
+                    format of the object file written by --emit obj: elf (the default),
+                    macho, or coff
+    -o, --output=<file>
+                    write the machine code to <file> instead of stdout, or the Cretonne IL
+                    translated from WebAssembly to <file>
     --version       print the Cretonne version
 
 ";
@@ -54,20 +134,67 @@ Options:
 #[derive(Deserialize, Debug)]
 struct Args {
     cmd_test: bool,
+    cmd_mutate: bool,
+    cmd_generate: bool,
+    cmd_differential: bool,
+    cmd_run: bool,
+    cmd_interpret: bool,
     cmd_cat: bool,
+    cmd_fmt: bool,
     cmd_filecheck: bool,
+    cmd_diff: bool,
     cmd_print_cfg: bool,
     cmd_compile: bool,
     cmd_wasm: bool,
+    cmd_bench: bool,
     arg_file: Vec<String>,
+    arg_isa: Vec<String>,
+    arg_arg: Vec<String>,
     flag_just_decode: bool,
     flag_check_translation: bool,
     flag_print: bool,
     flag_verbose: bool,
     flag_set: Vec<String>,
     flag_isa: String,
+    flag_target: Option<String>,
+    flag_emit: Option<String>,
+    flag_object_format: Option<String>,
+    flag_output: Option<String>,
     flag_time_passes: bool,
     flag_print_size: bool,
+    flag_stats: bool,
+    flag_optimize: bool,
+    flag_domtree: bool,
+    flag_loops: bool,
+    flag_live: bool,
+    flag_text: bool,
+    flag_check: bool,
+    flag_json: bool,
+    flag_jobs: Option<usize>,
+    flag_filter: Option<String>,
+    flag_command: Option<String>,
+    flag_timeout: Option<usize>,
+    flag_report: Option<String>,
+    flag_timings: Option<String>,
+    flag_slow_threshold: Option<f64>,
+    flag_watch: bool,
+    flag_bless: bool,
+    flag_shuffle: bool,
+    flag_seed: Option<u64>,
+    flag_no_cache: bool,
+    flag_fail_fast: bool,
+    flag_failed: bool,
+    flag_mutants: Option<usize>,
+    flag_functions: Option<usize>,
+    flag_inputs: Option<usize>,
+    flag_types: Option<String>,
+    flag_branches: Option<usize>,
+    flag_calls: Option<usize>,
+    flag_iterations: Option<usize>,
+    flag_warmup: Option<usize>,
+    flag_function: Option<String>,
+    flag_invoke: Option<String>,
+    flag_fuel: Option<u64>,
 }
 
 /// A command either succeeds or fails with an error message.
@@ -86,15 +213,98 @@ fn cton_util() -> CommandResult {
 
     // Find the sub-command to execute.
     let result = if args.cmd_test {
-        cton_filetests::run(args.flag_verbose, args.arg_file).map(|_time| ())
+        let options = cton_filetests::Options {
+            verbose: args.flag_verbose,
+            jobs: args.flag_jobs,
+            filter: args.flag_filter,
+            command: args.flag_command,
+            timeout: args.flag_timeout,
+            report: args.flag_report,
+            timings: args.flag_timings,
+            slow_threshold: args.flag_slow_threshold,
+            bless: args.flag_bless,
+            shuffle: args.flag_shuffle,
+            seed: args.flag_seed,
+            no_cache: args.flag_no_cache,
+            fail_fast: args.flag_fail_fast,
+            failed: args.flag_failed,
+        };
+        let result = if args.flag_watch {
+            cton_filetests::watch(&options, args.arg_file)
+        } else {
+            cton_filetests::run(&options, args.arg_file)
+        };
+        result.map(|_time| ())
+    } else if args.cmd_mutate {
+        cton_filetests::mutate(
+            args.arg_file,
+            args.flag_mutants.unwrap_or(100),
+            args.flag_seed,
+            args.flag_verbose,
+        ).map(|_time| ())
+    } else if args.cmd_generate {
+        generate::run(
+            args.arg_isa,
+            args.flag_set,
+            args.flag_functions.unwrap_or(1000),
+            args.flag_seed,
+            args.flag_types,
+            args.flag_branches,
+            args.flag_calls,
+            args.flag_verbose,
+        )
+    } else if args.cmd_differential {
+        generate::run_differential(
+            args.flag_functions.unwrap_or(1000),
+            args.flag_inputs.unwrap_or(10),
+            args.flag_seed,
+            args.flag_types,
+            args.flag_branches,
+            args.flag_calls,
+            args.flag_verbose,
+        )
+    } else if args.cmd_run {
+        let invoke = args.flag_invoke.as_ref().map(String::as_str);
+        utils::read_input(&args.arg_file[0]).and_then(|input| match input {
+            utils::Input::Cton(text) => cton_filetests::execute_cton(&text, invoke, &args.arg_arg),
+            utils::Input::Wasm(data) => cton_filetests::execute_wasm(&data, invoke, &args.arg_arg),
+        })
+    } else if args.cmd_interpret {
+        utils::read_cton(&args.arg_file[0]).and_then(|text| {
+            cton_filetests::interpret(
+                &text,
+                args.flag_invoke.as_ref().map(String::as_str),
+                &args.arg_arg,
+                args.flag_fuel,
+            )
+        })
     } else if args.cmd_cat {
-        cat::run(args.arg_file)
+        cat::run(args.arg_file, args.flag_json)
+    } else if args.cmd_fmt {
+        fmt::run(args.arg_file, args.flag_check)
     } else if args.cmd_filecheck {
         rsfilecheck::run(args.arg_file, args.flag_verbose)
+    } else if args.cmd_diff {
+        diff::run(args.arg_file)
     } else if args.cmd_print_cfg {
-        print_cfg::run(args.arg_file)
+        print_cfg::run(
+            args.arg_file,
+            args.flag_domtree,
+            args.flag_loops,
+            args.flag_live,
+            args.flag_text,
+        )
     } else if args.cmd_compile {
-        compile::run(args.arg_file, args.flag_print, args.flag_set, args.flag_isa)
+        compile::run(
+            args.arg_file,
+            args.flag_print,
+            args.flag_stats,
+            args.flag_set,
+            args.flag_target.unwrap_or(args.flag_isa),
+            args.flag_emit,
+            args.flag_object_format,
+            args.flag_output,
+        )
     } else if args.cmd_wasm {
         wasm::run(
             args.arg_file,
@@ -103,8 +313,22 @@ fn cton_util() -> CommandResult {
             args.flag_check_translation,
             args.flag_print,
             args.flag_set,
-            args.flag_isa,
+            args.flag_target.unwrap_or(args.flag_isa),
             args.flag_print_size,
+            args.flag_stats,
+            args.flag_optimize,
+            args.flag_time_passes,
+            args.flag_output,
+            args.flag_function,
+        )
+    } else if args.cmd_bench {
+        bench::run(
+            args.arg_file,
+            args.flag_set,
+            args.flag_target.unwrap_or(args.flag_isa),
+            args.flag_iterations.unwrap_or(10),
+            args.flag_warmup.unwrap_or(3),
+            args.flag_verbose,
         )
     } else {
         // Debugging / shouldn't happen with proper command line handling above.
@@ -119,6 +343,7 @@ fn cton_util() -> CommandResult {
 }
 
 fn main() {
+    file_per_thread_logger::initialize(cton_filetests::LOG_FILENAME_PREFIX);
     if let Err(mut msg) = cton_util() {
         if !msg.ends_with('\n') {
             msg.push('\n');
@@ -0,0 +1,59 @@
+//! The `diff` sub-command.
+//!
+//! Print the changes between versions of Cretonne IL functions. With two files, the functions in
+//! the first file are compared with the functions at the same positions in the second file. With a
+//! single file, each function is compared with the next one, which is useful for a file holding
+//! snapshots of a function after each compiler pass.
+//!
+//! See `cretonne::diff` for the output format.
+
+use cretonne::diff::write_function_diff;
+use cretonne::ir::Function;
+use cton_reader::parse_test;
+use CommandResult;
+use utils::read_cton;
+
+pub fn run(files: Vec<String>) -> CommandResult {
+    let buffers = files.iter().map(read_cton).collect::<Result<Vec<_>, _>>()?;
+    let mut tests = Vec::new();
+    for (filename, buffer) in files.iter().zip(&buffers) {
+        tests.push(parse_test(buffer).map_err(
+            |e| e.diagnostic(filename, buffer),
+        )?);
+    }
+    let isa = tests[0].isa_spec.unique_isa();
+
+    let functions: Vec<Vec<&Function>> = tests
+        .iter()
+        .map(|test| test.functions.iter().map(|&(ref func, _)| func).collect())
+        .collect();
+    let pairs: Vec<(&Function, &Function)> = match functions.len() {
+        1 => functions[0].windows(2).map(|w| (w[0], w[1])).collect(),
+        2 => {
+            if functions[0].len() != functions[1].len() {
+                return Err(format!(
+                    "{} has {} functions, but {} has {}",
+                    files[0],
+                    functions[0].len(),
+                    files[1],
+                    functions[1].len()
+                ));
+            }
+            functions[0].iter().cloned().zip(functions[1].iter().cloned()).collect()
+        }
+        _ => return Err("diff needs one or two files".to_string()),
+    };
+
+    for (old, new) in pairs {
+        let mut text = String::new();
+        write_function_diff(&mut text, old, new, isa).map_err(
+            |e| e.to_string(),
+        )?;
+        if !text.is_empty() {
+            println!("--- {}", old.name);
+            println!("+++ {}", new.name);
+            print!("{}", text);
+        }
+    }
+    Ok(())
+}
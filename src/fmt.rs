@@ -0,0 +1,161 @@
+//! The `fmt` sub-command.
+//!
+//! Reformat Cretonne IL test files in place, or print the reformatted text read from stdin.
+//! Functions are printed with canonical spacing and entity ordering, and the comments in them,
+//! including filecheck directives, stay next to the entities they annotate. Trailing comments on
+//! consecutive lines are aligned.
+//!
+//! The test commands and ISA specification before the first function are kept as they are, except
+//! for trailing whitespace and repeated blank lines.
+
+use cretonne::{write_function_annotated, WriteOptions};
+use cton_reader::{parse_test, TestFile};
+use std::fmt::Write;
+use std::fs::File;
+use std::io;
+use CommandResult;
+use utils::read_cton;
+
+pub fn run(files: Vec<String>, check: bool) -> CommandResult {
+    let mut unformatted = Vec::new();
+    for filename in files {
+        let buffer = read_cton(&filename)?;
+        let formatted = format_file(&filename, &buffer)?;
+        // Text read from stdin can't be rewritten in place, so it is printed instead.
+        if filename == "-" && !check {
+            print!("{}", formatted);
+            continue;
+        }
+        if formatted == buffer {
+            continue;
+        }
+        if check {
+            unformatted.push(filename);
+        } else {
+            write_file(&filename, &formatted).map_err(
+                |e| format!("{}: {}", filename, e),
+            )?;
+        }
+    }
+
+    if unformatted.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} file(s) need formatting:\n    {}",
+            unformatted.len(),
+            unformatted.join("\n    ")
+        ))
+    }
+}
+
+fn write_file(filename: &str, text: &str) -> io::Result<()> {
+    use std::io::Write;
+    File::create(filename)?.write_all(text.as_bytes())
+}
+
+/// Get the formatted text of the test file `text`.
+fn format_file(filename: &str, text: &str) -> Result<String, String> {
+    let TestFile { isa_spec, functions, .. } = parse_test(text).map_err(
+        |e| e.diagnostic(filename, text),
+    )?;
+    let lines: Vec<&str> = text.lines().collect();
+    let isa = isa_spec.unique_isa();
+    let options = WriteOptions {
+        stable: true,
+        ..WriteOptions::default()
+    };
+    let mut out = String::new();
+
+    // The header is everything before the first function.
+    let header_end = functions.first().map_or(lines.len(), |&(_, ref details)| {
+        details.location.line_number - 1
+    });
+    write_header(&mut out, &lines[0..header_end]);
+
+    let mut functions = functions.into_iter().peekable();
+    while let Some((func, mut details)) = functions.next() {
+        let mut text = String::new();
+        write_function_annotated(&mut text, &func, isa, &options, &mut details)
+            .map_err(|e| format!("{}: {}", filename, e))?;
+        align_comments(&mut out, &text);
+
+        // The comments after the function. The ones immediately above the next function, without
+        // any blank lines, describe that function.
+        let after = &details.annotations.after;
+        let described = match functions.peek() {
+            Some(&(_, ref next)) => {
+                let next_line = next.location.line_number - 1;
+                lines[0..next_line]
+                    .iter()
+                    .rev()
+                    .take_while(|line| line.trim_left().starts_with(';'))
+                    .count()
+            }
+            None => 0,
+        };
+        let (own, next) = after.split_at(after.len() - described.min(after.len()));
+        for comment in own {
+            writeln!(out, "{}", comment).unwrap();
+        }
+        if functions.peek().is_some() {
+            out.push('\n');
+        }
+        for comment in next {
+            writeln!(out, "{}", comment).unwrap();
+        }
+    }
+    Ok(out)
+}
+
+/// Write the header lines without trailing whitespace and repeated blank lines.
+fn write_header(out: &mut String, lines: &[&str]) {
+    let mut blank = true;
+    for line in lines {
+        let line = line.trim_right();
+        if line.is_empty() {
+            if !blank {
+                out.push('\n');
+            }
+            blank = true;
+        } else {
+            writeln!(out, "{}", line).unwrap();
+            blank = false;
+        }
+    }
+}
+
+/// Split `line` into the code and the trailing comment, if there is any code before the comment.
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    match line.find(';') {
+        Some(pos) if !line[0..pos].trim().is_empty() => {
+            (line[0..pos].trim_right(), Some(&line[pos..]))
+        }
+        _ => (line, None),
+    }
+}
+
+/// Write the lines of `text` to `out`, aligning the trailing comments in each paragraph.
+fn align_comments(out: &mut String, text: &str) {
+    let lines: Vec<_> = text.lines().map(split_comment).collect();
+    for (idx, paragraph) in lines
+        .split(|&(code, _)| code.trim().is_empty())
+        .enumerate()
+    {
+        if idx != 0 {
+            out.push('\n');
+        }
+        let width = paragraph
+            .iter()
+            .filter(|&&(_, comment)| comment.is_some())
+            .map(|&(code, _)| code.len())
+            .max()
+            .unwrap_or(0);
+        for &(code, comment) in paragraph {
+            match comment {
+                Some(comment) => writeln!(out, "{:2$} {}", code, comment, width).unwrap(),
+                None => writeln!(out, "{}", code).unwrap(),
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! CLI tool to stress test the code generator with random functions.
+//!
+//! Generates random functions and compiles them for each of the given ISAs, or runs them natively
+//! and in the interpreter. See the `generate` and `differential` modules of `cton_filetests` for
+//! details.
+
+use cton_filetests::{self, Shape};
+use utils::{parse_sets_and_isa, OwnedFlagsOrIsa};
+use CommandResult;
+
+pub fn run(
+    isa_names: Vec<String>,
+    flag_set: Vec<String>,
+    count: usize,
+    seed: Option<u64>,
+    flag_types: Option<String>,
+    flag_branches: Option<usize>,
+    flag_calls: Option<usize>,
+    flag_verbose: bool,
+) -> CommandResult {
+    let shape = make_shape(flag_types, flag_branches, flag_calls)?;
+
+    let mut isas = Vec::new();
+    for name in isa_names {
+        match parse_sets_and_isa(flag_set.clone(), name)? {
+            OwnedFlagsOrIsa::Isa(isa) => isas.push(isa),
+            OwnedFlagsOrIsa::Flags(_) => return Err("empty ISA name".to_string()),
+        }
+    }
+
+    cton_filetests::generate(&shape, &isas, count, seed, flag_verbose).map(|_time| ())
+}
+
+pub fn run_differential(
+    count: usize,
+    inputs: usize,
+    seed: Option<u64>,
+    flag_types: Option<String>,
+    flag_branches: Option<usize>,
+    flag_calls: Option<usize>,
+    flag_verbose: bool,
+) -> CommandResult {
+    let shape = make_shape(flag_types, flag_branches, flag_calls)?;
+    cton_filetests::differential(&shape, count, inputs, seed, flag_verbose).map(|_time| ())
+}
+
+fn make_shape(
+    flag_types: Option<String>,
+    flag_branches: Option<usize>,
+    flag_calls: Option<usize>,
+) -> Result<Shape, String> {
+    let mut shape = Shape::default();
+    if let Some(types) = flag_types {
+        shape.set_types(&types)?;
+    }
+    if let Some(branches) = flag_branches {
+        shape.branches = branches;
+    }
+    if let Some(calls) = flag_calls {
+        shape.calls = calls;
+    }
+    Ok(shape)
+}
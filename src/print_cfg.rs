@@ -1,36 +1,63 @@
 //! The `print-cfg` sub-command.
 //!
 //! Read a series of Cretonne IL files and print their control flow graphs
-//! in graphviz format.
+//! in graphviz format or as text, optionally annotated with the dominator tree,
+//! the loop nesting, and live value counts.
 
 use CommandResult;
 use cretonne::cfg_printer::CFGPrinter;
 use cton_reader::parse_functions;
-use utils::read_to_string;
+use utils::read_cton;
 
-pub fn run(files: Vec<String>) -> CommandResult {
+pub fn run(
+    files: Vec<String>,
+    domtree: bool,
+    loops: bool,
+    live: bool,
+    text: bool,
+) -> CommandResult {
     for (i, f) in files.into_iter().enumerate() {
         if i != 0 {
             println!();
         }
-        print_cfg(f)?
+        print_cfg(f, domtree, loops, live, text)?
     }
     Ok(())
 }
 
-fn print_cfg(filename: String) -> CommandResult {
-    let buffer = read_to_string(&filename).map_err(
-        |e| format!("{}: {}", filename, e),
-    )?;
+fn print_cfg(
+    filename: String,
+    domtree: bool,
+    loops: bool,
+    live: bool,
+    text: bool,
+) -> CommandResult {
+    let buffer = read_cton(&filename)?;
     let items = parse_functions(&buffer).map_err(
-        |e| format!("{}: {}", filename, e),
+        |e| e.diagnostic(&filename, &buffer),
     )?;
 
     for (idx, func) in items.into_iter().enumerate() {
         if idx != 0 {
             println!();
         }
-        print!("{}", CFGPrinter::new(&func));
+        let mut printer = CFGPrinter::new(&func);
+        if domtree {
+            printer.show_domtree();
+        }
+        if loops {
+            printer.show_loops();
+        }
+        if live {
+            printer.show_live_values();
+        }
+        if text {
+            let mut output = String::new();
+            printer.write_text(&mut output).map_err(|e| e.to_string())?;
+            print!("{}", output);
+        } else {
+            print!("{}", printer);
+        }
     }
 
     Ok(())
@@ -3,27 +3,114 @@
 use cretonne::isa::TargetIsa;
 use cretonne::settings::{self, FlagsOrIsa};
 use cretonne::isa;
+use cton_filetests::wat2wasm;
 use cton_reader::{parse_options, Location};
+use std::ffi::OsStr;
 use std::fs::File;
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use std::path::Path;
+use tempdir::TempDir;
 
-/// Read an entire file into a string.
+/// The magic number at the start of a WebAssembly binary.
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// Open a file for reading, or stdin if `path` is `-`.
+fn open<P: AsRef<Path>>(path: P) -> io::Result<Box<Read>> {
+    if path.as_ref() == Path::new("-") {
+        Ok(Box::new(io::stdin()))
+    } else {
+        Ok(Box::new(File::open(path)?))
+    }
+}
+
+/// Read an entire file into a string. The path `-` reads stdin.
 pub fn read_to_string<P: AsRef<Path>>(path: P) -> io::Result<String> {
-    let mut file = File::open(path)?;
+    let mut file = open(path)?;
     let mut buffer = String::new();
     file.read_to_string(&mut buffer)?;
     Ok(buffer)
 }
 
-/// Read an entire file into a vector of bytes.
+/// Read an entire file into a vector of bytes. The path `-` reads stdin.
 pub fn read_to_end<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
-    let mut file = File::open(path)?;
+    let mut file = open(path)?;
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)?;
     Ok(buffer)
 }
 
+/// The contents of an input file.
+pub enum Input {
+    /// Cretonne IL.
+    Cton(String),
+    /// A WebAssembly module in binary form.
+    Wasm(Vec<u8>),
+}
+
+/// Read an input file, or stdin if `path` is `-`, and detect its format.
+///
+/// WebAssembly binaries are recognized by their magic number. Text is in the WebAssembly text
+/// format if the file has the extension `.wat` or `.wast`, or if it has neither that nor `.cton`
+/// and its first line that isn't a comment starts with `(`. WebAssembly text is converted to binary
+/// with `wat2wasm`. Any other text is taken to be Cretonne IL.
+pub fn read_input<P: AsRef<Path>>(path: P) -> Result<Input, String> {
+    let path = path.as_ref();
+    let data = read_to_end(path).map_err(
+        |e| format!("{}: {}", path.display(), e),
+    )?;
+    if data.starts_with(WASM_MAGIC) {
+        return Ok(Input::Wasm(data));
+    }
+    let text = String::from_utf8(data).map_err(|_| {
+        format!(
+            "{}: not a WebAssembly binary, and not text either",
+            path.display()
+        )
+    })?;
+    let wat = match path.extension().and_then(OsStr::to_str) {
+        Some("wat") | Some("wast") => true,
+        Some("cton") => false,
+        _ => {
+            text.lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty() && !line.starts_with(';'))
+                .map_or(false, |line| line.starts_with('('))
+        }
+    };
+    if wat {
+        let data = wat_to_wasm(&text).map_err(
+            |e| format!("{}: {}", path.display(), e),
+        )?;
+        Ok(Input::Wasm(data))
+    } else {
+        Ok(Input::Cton(text))
+    }
+}
+
+/// Read a file of Cretonne IL, or stdin if `path` is `-`.
+pub fn read_cton<P: AsRef<Path>>(path: P) -> Result<String, String> {
+    let path = path.as_ref();
+    match read_input(path)? {
+        Input::Cton(text) => Ok(text),
+        Input::Wasm(_) => Err(format!(
+            "{}: expected Cretonne IL, found a WebAssembly module; translate it with \
+             `cton-util wasm -o <file>`",
+            path.display()
+        )),
+    }
+}
+
+/// Convert a module in the WebAssembly text format to binary.
+fn wat_to_wasm(text: &str) -> Result<Vec<u8>, String> {
+    // `wat2wasm` reads a file, which may not exist when the text comes from stdin.
+    let tmp_dir = TempDir::new("cretonne-wat").map_err(|e| e.to_string())?;
+    let path = tmp_dir.path().join("module.wat");
+    File::create(&path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|e| e.to_string())?;
+    wat2wasm(&path)?.ok_or_else(|| String::from("wat2wasm not found"))
+}
+
 /// Like `FlagsOrIsa`, but holds ownership.
 pub enum OwnedFlagsOrIsa {
     Flags(settings::Flags),
@@ -49,7 +136,7 @@ pub fn parse_sets_and_isa(
     parse_options(
         flag_set.iter().map(|x| x.as_str()),
         &mut flag_builder,
-        &Location { line_number: 0 },
+        &Location::default(),
     ).map_err(|err| err.to_string())?;
 
     let mut words = flag_isa.trim().split_whitespace();
@@ -60,7 +147,7 @@ pub fn parse_sets_and_isa(
             isa::LookupError::Unsupported => format!("support for ISA '{}' not enabled", isa_name),
         })?;
         // Apply the ISA-specific settings to `isa_builder`.
-        parse_options(words, &mut isa_builder, &Location { line_number: 0 })
+        parse_options(words, &mut isa_builder, &Location::default())
             .map_err(|err| err.to_string())?;
 
         Ok(OwnedFlagsOrIsa::Isa(
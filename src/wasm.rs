@@ -1,6 +1,6 @@
 //! CLI tool to use the functions provided by the [cretonne-wasm](../cton_wasm/index.html) crate.
 //!
-//! Reads Wasm binary files, translates the functions' code to Cretonne IL.
+//! Reads Wasm binary or text files, or stdin, translates the functions' code to Cretonne IL.
 
 use cton_wasm::{translate_module, DummyEnvironment, ModuleEnvironment};
 use std::path::PathBuf;
@@ -8,13 +8,12 @@ use cretonne::Context;
 use cretonne::settings::FlagsOrIsa;
 use cretonne::print_errors::{pretty_error, pretty_verifier_error};
 use std::fs::File;
-use std::error::Error;
-use std::io;
+use std::io::Write;
+use std::mem;
 use std::path::Path;
-use std::process::Command;
-use tempdir::TempDir;
+use std::time::{Duration, Instant};
 use term;
-use utils::{parse_sets_and_isa, read_to_end};
+use utils::{parse_sets_and_isa, read_input, Input};
 
 macro_rules! vprintln {
     ($x: expr, $($tts:tt)*) => {
@@ -38,12 +37,22 @@ pub fn run(
     flag_just_decode: bool,
     flag_check_translation: bool,
     flag_print: bool,
-    flag_set: Vec<String>,
+    mut flag_set: Vec<String>,
     flag_isa: String,
     flag_print_size: bool,
+    flag_stats: bool,
+    flag_optimize: bool,
+    flag_time_passes: bool,
+    flag_output: Option<String>,
+    flag_function: Option<String>,
 ) -> Result<(), String> {
+    if flag_optimize {
+        // Later settings override earlier ones, so an explicit `--set opt_level` still wins.
+        flag_set.insert(0, String::from("opt_level=best"));
+    }
     let parsed = parse_sets_and_isa(flag_set, flag_isa)?;
 
+    let mut il = String::new();
     for filename in files {
         let path = Path::new(&filename);
         let name = String::from(path.as_os_str().to_string_lossy());
@@ -53,23 +62,50 @@ pub fn run(
             flag_check_translation,
             flag_print,
             flag_print_size,
+            flag_stats,
+            flag_time_passes,
+            flag_function.as_ref().map(String::as_str),
             path.to_path_buf(),
             name,
             parsed.as_fisa(),
+            &mut il,
         )?;
     }
+
+    if let Some(path) = flag_output {
+        File::create(&path)
+            .and_then(|mut file| file.write_all(il.as_bytes()))
+            .map_err(|e| format!("{}: {}", path, e))?;
+    }
     Ok(())
 }
 
+/// Print the time spent in each phase of handling the module `name`.
+fn print_phases(name: &str, phases: &[(&str, Duration)]) {
+    println!("======== ==================================");
+    println!("    Time  Phase ({})", name);
+    println!("-------- ----------------------------------");
+    for &(phase, mut dur) in phases {
+        // Round to nearest ms by adding 500us.
+        dur += Duration::new(0, 500_000);
+        println!("{:4}.{:03}  {}", dur.as_secs(), dur.subsec_nanos() / 1_000_000, phase);
+    }
+    println!("======== ==================================");
+}
+
 fn handle_module(
     flag_verbose: bool,
     flag_just_decode: bool,
     flag_check_translation: bool,
     flag_print: bool,
     flag_print_size: bool,
+    flag_stats: bool,
+    flag_time_passes: bool,
+    flag_function: Option<&str>,
     path: PathBuf,
     name: String,
     fisa: FlagsOrIsa,
+    il: &mut String,
 ) -> Result<(), String> {
     let mut terminal = term::stdout().unwrap();
     terminal.fg(term::color::YELLOW).unwrap();
@@ -80,30 +116,44 @@ fn handle_module(
     vprint!(flag_verbose, "Translating... ");
     terminal.reset().unwrap();
 
-    let mut data = read_to_end(path.clone()).map_err(|err| {
-        String::from(err.description())
-    })?;
-    if !data.starts_with(&[b'\0', b'a', b's', b'm']) {
-        let tmp_dir = TempDir::new("cretonne-wasm").unwrap();
-        let file_path = tmp_dir.path().join("module.wasm");
-        File::create(file_path.clone()).unwrap();
-        Command::new("wat2wasm")
-            .arg(path.clone())
-            .arg("-o")
-            .arg(file_path.to_str().unwrap())
-            .output()
-            .or_else(|e| if let io::ErrorKind::NotFound = e.kind() {
-                return Err(String::from("wat2wasm not found"));
-            } else {
-                return Err(String::from(e.description()));
-            })?;
-        data = read_to_end(file_path).map_err(
-            |err| String::from(err.description()),
-        )?;
-    }
+    let mut phases = Vec::new();
+    let started = Instant::now();
+    let data = match read_input(&path)? {
+        Input::Wasm(data) => data,
+        Input::Cton(_) => {
+            return Err(format!(
+                "{}: expected a WebAssembly module, found Cretonne IL; compile it with \
+                 `cton-util compile`",
+                name
+            ))
+        }
+    };
 
+    phases.push(("Decoding", started.elapsed()));
+
+    let started = Instant::now();
     let mut dummy_environ = DummyEnvironment::with_flags(fisa.flags.clone());
+    if let Some(selector) = flag_function {
+        dummy_environ.select_function(selector);
+    }
     translate_module(&data, &mut dummy_environ)?;
+    phases.push(("Translation", started.elapsed()));
+
+    let num_func_imports = dummy_environ.get_num_func_imports();
+    let num_funcs = num_func_imports + dummy_environ.info.function_bodies.len();
+    if let Some(selector) = flag_function {
+        if !(num_func_imports..num_funcs).any(|func_index| dummy_environ.is_selected(func_index)) {
+            return Err(format!("{}: no function with index or export name {}", name, selector));
+        }
+    }
+
+    // Write the translated functions, which can be compiled again with `cton-util compile`.
+    il.push_str(&format!("; Translated from {}\n", name));
+    for (def_index, func) in dummy_environ.info.function_bodies.iter().enumerate() {
+        if dummy_environ.is_selected(num_func_imports + def_index) {
+            il.push_str(&format!("\n{}", func.display(None)));
+        }
+    }
 
     terminal.fg(term::color::GREEN).unwrap();
     vprintln!(flag_verbose, "ok");
@@ -111,11 +161,11 @@ fn handle_module(
 
     if flag_just_decode {
         if flag_print {
-            let num_func_imports = dummy_environ.get_num_func_imports();
             for (def_index, func) in dummy_environ.info.function_bodies.iter().enumerate() {
                 let func_index = num_func_imports + def_index;
-                let mut context = Context::new();
-                context.func = func.clone();
+                if !dummy_environ.is_selected(func_index) {
+                    continue;
+                }
                 if let Some(start_func) = dummy_environ.info.start_func {
                     if func_index == start_func {
                         println!("; Selected as wasm start function");
@@ -125,11 +175,14 @@ fn handle_module(
                 for export_name in &dummy_environ.info.functions[func_index].export_names {
                     println!("; Exported as \"{}\"", export_name);
                 }
-                println!("{}", context.func.display(None));
+                println!("{}", func.display(None));
                 vprintln!(flag_verbose, "");
             }
             terminal.reset().unwrap();
         }
+        if flag_time_passes {
+            print_phases(&name, &phases);
+        }
         return Ok(());
     }
 
@@ -145,12 +198,19 @@ fn handle_module(
         vprintln!(flag_verbose, "");
     }
 
-    let num_func_imports = dummy_environ.get_num_func_imports();
+    let started = Instant::now();
     let mut total_module_code_size = 0;
-    for (def_index, func) in dummy_environ.info.function_bodies.iter().enumerate() {
+    let mut context = Context::new();
+    if flag_stats {
+        context.enable_stats();
+    }
+    let function_bodies = mem::replace(&mut dummy_environ.info.function_bodies, Vec::new());
+    for (def_index, func) in function_bodies.into_iter().enumerate() {
         let func_index = num_func_imports + def_index;
-        let mut context = Context::new();
-        context.func = func.clone();
+        if !dummy_environ.is_selected(func_index) {
+            continue;
+        }
+        context.reset(func);
         if flag_check_translation {
             context.verify(fisa).map_err(|err| {
                 pretty_verifier_error(&context.func, fisa.isa, err)
@@ -191,6 +251,11 @@ fn handle_module(
             vprintln!(flag_verbose, "");
         }
     }
+    if flag_check_translation {
+        phases.push(("Verification", started.elapsed()));
+    } else {
+        phases.push(("Compilation", started.elapsed()));
+    }
 
     if !flag_check_translation && flag_print_size {
         println!("Total module code size: {} bytes", total_module_code_size);
@@ -201,6 +266,13 @@ fn handle_module(
         println!("Total module bytecode size: {} bytes", total_bytecode_size);
     }
 
+    if let Some(stats) = context.stats() {
+        print!("{}", stats);
+    }
+    if flag_time_passes {
+        print_phases(&name, &phases);
+    }
+
     terminal.fg(term::color::GREEN).unwrap();
     vprintln!(flag_verbose, "ok");
     terminal.reset().unwrap();
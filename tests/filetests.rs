@@ -2,6 +2,13 @@ extern crate cton_filetests;
 
 #[test]
 fn filetests() {
-    // Run all the filetests in the following directories.
-    cton_filetests::run(false, vec!["filetests".into(), "docs".into()]).expect("test harness");
+    // Run all the filetests in the following directories, without skipping the ones that passed
+    // in an earlier run.
+    cton_filetests::run(
+        &cton_filetests::Options {
+            no_cache: true,
+            ..Default::default()
+        },
+        vec!["filetests".into(), "docs".into()],
+    ).expect("test harness");
 }